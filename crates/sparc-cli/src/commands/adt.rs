@@ -0,0 +1,95 @@
+//! Normalize antibody capture (ADT) counts and summarize isotype background
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::adt::{clr_normalize, dsb_normalize, find_isotypes, read_adt_matrix, AdtMetrics};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct AdtArgs {
+    /// Matrix Market directory; only `Antibody Capture` rows are used when
+    /// features.tsv has a type column
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Normalization: clr, or dsb (needs empty droplets via --background-max-umis)
+    #[arg(long, default_value = "clr")]
+    method: String,
+
+    /// Isotype control feature names (features containing "isotype" are always included)
+    #[arg(long)]
+    isotype: Vec<String>,
+
+    /// Barcodes with at most this many ADT UMIs are empty droplets (dsb background)
+    #[arg(long)]
+    background_max_umis: Option<u64>,
+
+    /// Cells above this isotype UMI fraction count as high-background
+    #[arg(long, default_value = "0.1")]
+    max_isotype_fraction: f64,
+}
+
+pub fn run(args: AdtArgs) -> Result<()> {
+    let matrix = read_adt_matrix(&args.input)
+        .with_context(|| format!("Failed to read ADT matrix {:?}", args.input))?;
+    if matrix.n_rows == 0 {
+        anyhow::bail!("No antibody features in {:?}", args.input);
+    }
+    let isotypes = find_isotypes(&matrix.genes, &args.isotype);
+    log::info!(
+        "{} antibody features ({} isotype controls), {} barcodes",
+        matrix.n_rows,
+        isotypes.len(),
+        matrix.n_cols
+    );
+
+    let totals = matrix.counts_per_cell();
+    let (cells, normalized): (Vec<usize>, _) = match args.method.as_str() {
+        "clr" => ((0..matrix.n_cols).collect(), clr_normalize(&matrix)),
+        "dsb" => {
+            let max_umis = args
+                .background_max_umis
+                .context("--method dsb needs --background-max-umis to pick empty droplets")?;
+            let (background, cells): (Vec<usize>, Vec<usize>) =
+                (0..matrix.n_cols).partition(|&c| totals[c] <= max_umis);
+            log::info!("{} empty droplets as background", background.len());
+            let normalized = dsb_normalize(&matrix, &cells, &background, &isotypes)?;
+            (cells, normalized)
+        }
+        other => anyhow::bail!("Unknown normalization method: {} (expected clr or dsb)", other),
+    };
+
+    std::fs::create_dir_all(&args.output)?;
+    let csv_path = args.output.join("adt_normalized.csv");
+    let mut out = BufWriter::new(std::fs::File::create(&csv_path)?);
+    writeln!(out, "barcode,{}", matrix.genes.join(","))?;
+    for (&c, values) in cells.iter().zip(&normalized) {
+        let values: Vec<String> = values.iter().map(|v| format!("{:.4}", v)).collect();
+        writeln!(out, "{},{}", matrix.barcodes[c], values.join(","))?;
+    }
+    out.flush()?;
+
+    let cell_matrix = matrix.subset_cols(&cells);
+    let metrics = AdtMetrics::from_matrix(&cell_matrix, &isotypes, args.max_isotype_fraction);
+    let metrics_path = args.output.join("adt_metrics.json");
+    sparc_core::atomic::write(&metrics_path, serde_json::to_string_pretty(&metrics)?)?;
+
+    println!("\n=== ADT Summary ===");
+    println!("Features:              {}", metrics.num_features);
+    println!("Isotype controls:      {}", metrics.num_isotypes);
+    println!("Cells:                 {}", cells.len());
+    println!("Median ADT UMIs/cell:  {:.0}", metrics.median_umis_per_cell);
+    println!("Median isotype frac:   {:.3}", metrics.median_isotype_fraction);
+    println!(
+        "High-isotype cells:    {:.1}%",
+        metrics.high_isotype_cell_fraction * 100.0
+    );
+    println!("\nOutput: {:?}, {:?}", csv_path, metrics_path);
+
+    Ok(())
+}
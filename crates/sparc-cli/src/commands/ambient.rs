@@ -0,0 +1,156 @@
+//! Estimate ambient RNA contamination and write a corrected count matrix
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::ambient::{estimate_contamination, soup_profile, AmbientConfig, AmbientEstimate};
+use sparc_core::count::CountMatrix;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct AmbientArgs {
+    /// Raw (unfiltered) Matrix Market directory including empty droplets
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Cell barcodes, one per line (default: barcodes with --min-cell-umis)
+    #[arg(long)]
+    cells: Option<PathBuf>,
+
+    /// Minimum UMIs for a barcode to be a cell when --cells is not given
+    #[arg(long, default_value = "500")]
+    min_cell_umis: u64,
+
+    /// Barcodes with 1 to this many UMIs are empty droplets (the ambient profile)
+    #[arg(long, default_value = "100")]
+    empty_max_umis: u64,
+
+    /// Cluster labels (barcode<TAB>cluster, e.g. clusters.tsv from `sparc analyze`)
+    #[arg(long)]
+    clusters: Option<PathBuf>,
+
+    /// Only estimate contamination; skip writing the corrected matrix
+    #[arg(long)]
+    no_correct: bool,
+}
+
+pub fn run(args: AmbientArgs) -> Result<()> {
+    let matrix = CountMatrix::read_mtx(&args.input)
+        .with_context(|| format!("Failed to read matrix {:?}", args.input))?;
+    let totals = matrix.counts_per_cell();
+
+    let cells: Vec<usize> = match &args.cells {
+        Some(path) => {
+            let wanted = read_barcodes(path)?;
+            let index: HashMap<&str, usize> =
+                matrix.barcodes.iter().enumerate().map(|(i, b)| (b.as_str(), i)).collect();
+            wanted.iter().filter_map(|b| index.get(b.as_str()).copied()).collect()
+        }
+        None => (0..matrix.n_cols).filter(|&c| totals[c] >= args.min_cell_umis).collect(),
+    };
+    let is_cell: HashSet<usize> = cells.iter().copied().collect();
+    let empty: Vec<usize> = (0..matrix.n_cols)
+        .filter(|&c| !is_cell.contains(&c) && totals[c] > 0 && totals[c] <= args.empty_max_umis)
+        .collect();
+    if cells.is_empty() {
+        anyhow::bail!("No cells selected from {:?}", args.input);
+    }
+
+    let clusters = match &args.clusters {
+        Some(path) => Some(read_clusters(path, &matrix, &cells)?),
+        None => None,
+    };
+
+    let estimate = estimate_ambient(
+        &matrix,
+        &cells,
+        &empty,
+        clusters.as_deref(),
+        !args.no_correct,
+        &args.output,
+    )?;
+
+    println!("\n=== Ambient RNA Summary ===");
+    println!("Cells:                 {}", cells.len());
+    println!("Empty droplets:        {}", empty.len());
+    println!("Mean contamination:    {:.1}%", estimate.mean_contamination() * 100.0);
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
+
+/// Estimate contamination for `cells` from the `empty` droplets and write
+/// `ambient_profile.tsv`, `contamination.csv`, and (with `correct`) a
+/// `corrected/` Matrix Market directory under `output`
+pub(crate) fn estimate_ambient(
+    matrix: &CountMatrix,
+    cells: &[usize],
+    empty: &[usize],
+    clusters: Option<&[usize]>,
+    correct: bool,
+    output: &Path,
+) -> Result<AmbientEstimate> {
+    log::info!("Estimating ambient profile from {} empty droplets", empty.len());
+    let profile = soup_profile(matrix, empty)?;
+    let estimate =
+        estimate_contamination(matrix, cells, clusters, &profile, &AmbientConfig::default())?;
+
+    std::fs::create_dir_all(output)?;
+    estimate.write_profile(matrix, output.join("ambient_profile.tsv"))?;
+    estimate.write_contamination(matrix, output.join("contamination.csv"))?;
+    if correct {
+        let corrected_dir = output.join("corrected");
+        std::fs::create_dir_all(&corrected_dir)?;
+        let corrected = estimate.correct(matrix);
+        corrected.write_mtx(corrected_dir.join("matrix.mtx"))?;
+        corrected.write_barcodes(corrected_dir.join("barcodes.tsv"))?;
+        corrected.write_genes(corrected_dir.join("genes.tsv"))?;
+    }
+    Ok(estimate)
+}
+
+/// First tab-separated column of each non-blank line
+pub(crate) fn read_barcodes(path: &Path) -> Result<Vec<String>> {
+    let reader =
+        BufReader::new(File::open(path).with_context(|| format!("Cannot open {:?}", path))?);
+    let mut barcodes = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(barcode) = line.split('\t').next().filter(|b| !b.is_empty()) {
+            barcodes.push(barcode.to_string());
+        }
+    }
+    Ok(barcodes)
+}
+
+/// Cluster index of each cell from a barcode<TAB>cluster file
+fn read_clusters(path: &Path, matrix: &CountMatrix, cells: &[usize]) -> Result<Vec<usize>> {
+    let reader =
+        BufReader::new(File::open(path).with_context(|| format!("Cannot open {:?}", path))?);
+    let mut by_barcode: HashMap<String, String> = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        if let Some((barcode, label)) = line.split_once('\t') {
+            by_barcode.insert(barcode.to_string(), label.trim().to_string());
+        }
+    }
+
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    cells
+        .iter()
+        .map(|&c| -> Result<usize> {
+            let barcode = &matrix.barcodes[c];
+            let label = by_barcode
+                .get(barcode)
+                .with_context(|| format!("Cell {} has no cluster in {:?}", barcode, path))?;
+            let next = ids.len();
+            Ok(*ids.entry(label.as_str()).or_insert(next))
+        })
+        .collect()
+}
@@ -0,0 +1,89 @@
+//! Annotate aligned reads with gene (GX/GN) and region (RE) tags from a GTF
+
+use anyhow::{Context, Result};
+use clap::Args;
+use indicatif::{ProgressBar, ProgressStyle};
+use sparc_core::annotation::{
+    annotate_bam, GeneAnnotation, GeneAssigner, OverlapMode, Strandedness,
+};
+use sparc_core::metrics;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct AnnotateArgs {
+    /// Input aligned BAM file
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Gene annotation GTF file (.gz supported)
+    #[arg(short, long)]
+    gtf: PathBuf,
+
+    /// Output tagged BAM file
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Also assign intronic reads to their gene (GX/GN)
+    #[arg(long)]
+    include_introns: bool,
+
+    /// Multi-gene overlap policy: unique, union, or intersection-strict
+    #[arg(long, default_value = "unique")]
+    overlap_mode: OverlapMode,
+
+    /// Library strandedness: forward, reverse, or unstranded
+    #[arg(long, default_value = "unstranded")]
+    strandedness: Strandedness,
+
+    /// Write region and splicing read counts as JSON (input for `sparc qc --region-stats`)
+    #[arg(long)]
+    stats: Option<PathBuf>,
+}
+
+pub fn run(args: AnnotateArgs) -> Result<()> {
+    let annotation = GeneAnnotation::from_gtf(&args.gtf).context("Failed to load GTF")?;
+    if annotation.is_empty() {
+        anyhow::bail!("No genes found in {:?}", args.gtf);
+    }
+
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .expect("valid progress template"),
+    );
+    progress.set_message(format!("Annotating {:?}", args.input));
+
+    let assigner = GeneAssigner::new(&annotation)
+        .overlap_mode(args.overlap_mode)
+        .strandedness(args.strandedness)
+        .include_introns(args.include_introns);
+    let stats =
+        annotate_bam(&args.input, &args.output, &assigner).context("Failed to annotate BAM")?;
+    stats.add_to_metrics(metrics::global());
+
+    progress.finish_with_message(format!("Done! Annotated {} reads", stats.total_reads));
+
+    let total = stats.total_reads.max(1) as f64;
+    let pct = |n: u64| n as f64 / total * 100.0;
+    println!("\n=== Annotation Summary ===");
+    println!("Genes:           {}", annotation.len());
+    println!("Total reads:     {}", stats.total_reads);
+    println!("Unmapped:        {} ({:.1}%)", stats.unmapped_reads, pct(stats.unmapped_reads));
+    println!("Exonic:          {} ({:.1}%)", stats.exonic_reads, pct(stats.exonic_reads));
+    println!("Intronic:        {} ({:.1}%)", stats.intronic_reads, pct(stats.intronic_reads));
+    println!("Intergenic:      {} ({:.1}%)", stats.intergenic_reads, pct(stats.intergenic_reads));
+    println!("Multi-gene:      {} ({:.1}%)", stats.ambiguous_reads, pct(stats.ambiguous_reads));
+    if args.strandedness != Strandedness::Unstranded {
+        println!("Antisense:       {} ({:.1}%)", stats.antisense_reads, pct(stats.antisense_reads));
+    }
+    println!("Assigned (GX):   {} ({:.1}%)", stats.assigned_reads, pct(stats.assigned_reads));
+    println!("  Spliced:       {} ({:.1}%)", stats.spliced_reads, pct(stats.spliced_reads));
+    println!("  Unspliced:     {} ({:.1}%)", stats.unspliced_reads, pct(stats.unspliced_reads));
+    if let Some(path) = &args.stats {
+        sparc_core::atomic::write(path, serde_json::to_string_pretty(&stats)?)?;
+    }
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
@@ -146,14 +146,15 @@ fn process_sample(sample: &SampleConfig, args: &BatchArgs) -> Result<()> {
     let sample_output = args.output.join(&sample.name);
 
     let pipeline_args = super::pipeline::PipelineArgs {
-        r1: sample.r1.clone(),
-        r2: sample.r2.clone(),
+        r1: Some(sample.r1.clone()),
+        r2: Some(sample.r2.clone()),
         reference: args.reference.clone(),
         output: sample_output,
-        whitelist: sample.whitelist.clone(),
+        whitelist: Some(sample.whitelist.clone()),
         protocol: args.protocol.clone(),
         sample: sample.name.clone(),
         aligner: args.aligner.clone(),
+        align_retries: 1,
         max_mismatch: args.max_mismatch,
         min_barcode_qual: 10,
         min_mapq: 30,
@@ -163,6 +164,10 @@ fn process_sample(sample: &SampleConfig, args: &BatchArgs) -> Result<()> {
         bam: None,
         min_genes: 200,
         max_genes: 10000,
+        samples: None,
+        parallel_samples: 1,
+        dry_run: false,
+        ambient: false,
     };
 
     super::pipeline::run(pipeline_args)
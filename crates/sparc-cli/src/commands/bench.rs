@@ -0,0 +1,148 @@
+//! Micro-benchmark the pipeline stages on this machine
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+use sparc_core::benchmark::{Stage, Workload};
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// A thread count within this fraction of the best throughput is good enough
+const NEAR_BEST: f64 = 0.9;
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Simulated cells (about 2,000 read pairs each)
+    #[arg(long, default_value = "100")]
+    cells: usize,
+
+    /// Thread counts to try for parallel stages, comma-separated
+    /// [default: 1, 2, 4, ... up to all cores]
+    #[arg(long, value_delimiter = ',')]
+    thread_counts: Vec<usize>,
+
+    /// Timed repetitions per measurement; the fastest is reported
+    #[arg(long, default_value = "3")]
+    repeat: usize,
+
+    /// Also write the measurements as JSON
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct Measurement {
+    stage: Stage,
+    threads: usize,
+    seconds: f64,
+    reads_per_sec: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    reads: usize,
+    cores: usize,
+    measurements: Vec<Measurement>,
+    /// Fewest threads reaching 90% of the best parallel throughput
+    suggested_threads: usize,
+}
+
+pub fn run(args: BenchArgs) -> Result<()> {
+    anyhow::ensure!(args.cells > 0, "--cells must be positive");
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let thread_counts = if args.thread_counts.is_empty() {
+        default_thread_counts(cores)
+    } else {
+        args.thread_counts.clone()
+    };
+    anyhow::ensure!(
+        thread_counts.iter().all(|&n| n > 0),
+        "--thread-counts must be positive"
+    );
+
+    println!("Simulating {} cells...", args.cells);
+    let workload = Workload::generate(args.cells, sparc_core::seed::global())?;
+    println!("{} read pairs, {} cores\n", workload.reads(), cores);
+
+    println!("{:<20} {:>8} {:>10} {:>14}", "Stage", "Threads", "Seconds", "Reads/s");
+    let single = [1];
+    let mut measurements = Vec::new();
+    for stage in Stage::ALL {
+        let counts: &[usize] = if stage.is_parallel() { &thread_counts } else { &single };
+        for &threads in counts {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .context("Failed to build thread pool")?;
+            let mut best = f64::INFINITY;
+            let mut reads = 0;
+            for _ in 0..args.repeat.max(1) {
+                let start = Instant::now();
+                reads = pool.install(|| workload.run(stage))?;
+                best = best.min(start.elapsed().as_secs_f64());
+            }
+            let m = Measurement {
+                stage,
+                threads,
+                seconds: best,
+                reads_per_sec: reads as f64 / best.max(1e-9),
+            };
+            println!(
+                "{:<20} {:>8} {:>10.3} {:>14.0}",
+                stage.name(),
+                m.threads,
+                m.seconds,
+                m.reads_per_sec
+            );
+            measurements.push(m);
+        }
+    }
+
+    let suggested_threads = suggest_threads(&measurements, &thread_counts);
+    println!("\nSuggested thread count: -j {}", suggested_threads);
+
+    if let Some(path) = &args.json {
+        let report = BenchReport {
+            reads: workload.reads(),
+            cores,
+            measurements,
+            suggested_threads,
+        };
+        sparc_core::atomic::write(path, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("Failed to write {:?}", path))?;
+        println!("Results: {:?}", path);
+    }
+    Ok(())
+}
+
+/// Powers of two below `cores`, then `cores` itself
+fn default_thread_counts(cores: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = std::iter::successors(Some(1usize), |n| Some(n * 2))
+        .take_while(|&n| n < cores)
+        .collect();
+    counts.push(cores);
+    counts
+}
+
+/// Fewest threads at which every parallel stage reaches `NEAR_BEST` of its
+/// best throughput
+fn suggest_threads(measurements: &[Measurement], thread_counts: &[usize]) -> usize {
+    let mut sorted = thread_counts.to_vec();
+    sorted.sort_unstable();
+    let parallel = || measurements.iter().filter(|m| m.stage.is_parallel());
+    let best = |stage: Stage| {
+        parallel()
+            .filter(|m| m.stage == stage)
+            .map(|m| m.reads_per_sec)
+            .fold(0.0, f64::max)
+    };
+    sorted
+        .iter()
+        .copied()
+        .find(|&threads| {
+            parallel()
+                .filter(|m| m.threads == threads)
+                .all(|m| m.reads_per_sec >= NEAR_BEST * best(m.stage))
+        })
+        .unwrap_or(1)
+}
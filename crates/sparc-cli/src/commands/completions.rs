@@ -0,0 +1,17 @@
+//! Shell completion scripts
+
+use anyhow::Result;
+use clap::{Args, Command};
+use clap_complete::Shell;
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate completions for (bash, zsh, fish, powershell, elvish)
+    shell: Shell,
+}
+
+pub fn run(args: CompletionsArgs, mut cmd: Command) -> Result<()> {
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(args.shell, &mut cmd, name, &mut std::io::stdout());
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! `sparc config` - Inspect run configuration
+
+use anyhow::Result;
+use clap::{Args, Command, Subcommand};
+use std::ffi::OsString;
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the fully-resolved parameters (defaults + config + flags) of a command as TOML
+    Dump(DumpArgs),
+}
+
+#[derive(Args)]
+struct DumpArgs {
+    /// Command line to resolve, e.g. `pipeline -1 R1.fq.gz -2 R2.fq.gz ...`
+    #[arg(
+        required = true,
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_name = "COMMAND"
+    )]
+    command: Vec<OsString>,
+}
+
+pub fn run(args: ConfigArgs, argv: &[OsString], cmd: &Command) -> Result<()> {
+    match args.command {
+        ConfigCommand::Dump(dump) => {
+            print!("{}", crate::config::dump(argv, &dump.command, cmd)?);
+            Ok(())
+        }
+    }
+}
@@ -2,20 +2,52 @@
 
 use anyhow::{Context, Result};
 use clap::Args;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::ProgressBar;
 use sparc_core::{
-    bam::BamParser,
-    count::GeneCounter,
+    annotation::{GeneAnnotation, RegionType, Strandedness, TranscriptAnnotation},
+    bam::{BamParser, BamRecord},
+    barcode::{BarcodeCorrector, CorrectionReport, Whitelist},
+    count::{
+        BiotypeFilter, CellStats, CountMatrix, DedupStats, DuplicateMarker, EmRounding,
+        GeneBiotypes, GeneCounter, MoleculeCounter, MultiGeneCounter, GENE_EXPRESSION,
+    },
+    fastq::{FastqParser, HeaderTags, ParsePolicy},
+    metrics::{self, Counter, Metrics},
+    perf,
+    pseudoalign::KmerIndex,
+    umi::{UmiDeduplicator, UmiNPolicy},
 };
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-#[derive(Args)]
+use super::dry_run::{counter_memory, mtx_bytes, DryRunPlan};
+use super::samples::{parse_sample_sheet, run_samples, SampleEntry, SampleMetrics};
+
+#[derive(Args, Clone)]
 pub struct CountArgs {
     /// Input BAM file (with CB, UB, GN/GX tags)
-    #[arg(short, long)]
-    input: PathBuf,
+    #[arg(short, long, required_unless_present_any = ["samples", "fastq"])]
+    input: Option<PathBuf>,
+
+    /// Barcode-tagged cDNA FASTQ from `sparc extract`, pseudoaligned instead of a BAM
+    #[arg(long, conflicts_with = "input", requires = "index")]
+    fastq: Option<PathBuf>,
+
+    /// K-mer index from `sparc index` (with --fastq)
+    #[arg(long, requires = "fastq")]
+    index: Option<PathBuf>,
+
+    /// Malformed --fastq records: strict (fail) or lenient (skip and count)
+    #[arg(long, default_value = "strict", requires = "fastq")]
+    parse_policy: ParsePolicy,
 
-    /// Output directory for matrix files
+    /// Regex with named groups `cb` and `umi` locating the barcode and UMI in read
+    /// headers: --fastq headers, or BAM read names lacking a CB tag
+    #[arg(long)]
+    header_regex: Option<String>,
+
+    /// Output directory for matrix files (one subdirectory per sample with --samples)
     #[arg(short, long)]
     output: PathBuf,
 
@@ -26,62 +58,252 @@ pub struct CountArgs {
     /// Output format (mtx, h5ad)
     #[arg(long, default_value = "mtx")]
     format: String,
+
+    /// Only count reads from these cell barcodes (one per line, e.g. a filtered barcodes.tsv)
+    #[arg(long)]
+    barcodes: Option<PathBuf>,
+
+    /// Library strandedness: forward, reverse, or unstranded (stranded needs --gtf)
+    #[arg(long, default_value = "unstranded")]
+    strandedness: Strandedness,
+
+    /// Gene annotation GTF giving gene strands and biotypes (.gz supported)
+    #[arg(long)]
+    gtf: Option<PathBuf>,
+
+    /// Only count genes of these biotypes, e.g. protein_coding,lncRNA (needs --gtf)
+    #[arg(long, value_delimiter = ',', requires = "gtf")]
+    include_biotypes: Vec<String>,
+
+    /// Leave genes of these biotypes out of the matrix, e.g. rRNA,Mt_rRNA (needs --gtf)
+    #[arg(long, value_delimiter = ',', requires = "gtf")]
+    exclude_biotypes: Vec<String>,
+
+    /// Count antisense reads into a separate matrix under <output>/antisense/
+    #[arg(long)]
+    antisense_matrix: bool,
+
+    /// Count transcripts instead of genes: BAM reads are assigned to the --gtf
+    /// transcripts whose exons and splice junctions they match
+    #[arg(
+        long,
+        requires = "gtf",
+        conflicts_with_all = ["fastq", "include_biotypes", "exclude_biotypes", "antisense_matrix"]
+    )]
+    transcripts: bool,
+
+    /// With --transcripts, also write gene counts summed over each gene's
+    /// transcripts under <output>/genes/
+    #[arg(long, requires = "transcripts")]
+    rollup: bool,
+
+    /// Share reads compatible with several genes among them by EM instead of discarding them
+    #[arg(long)]
+    em: bool,
+
+    /// Rounding of fractional EM counts: nearest or floor
+    #[arg(long, default_value = "nearest", requires = "em")]
+    em_rounding: EmRounding,
+
+    /// Correct raw CR barcodes against this whitelist for reads without a CB tag;
+    /// their UR tag stands in for a missing UB
+    #[arg(long, conflicts_with = "fastq")]
+    whitelist: Option<PathBuf>,
+
+    /// Maximum Hamming distance for CR barcode correction (with --whitelist)
+    #[arg(long, default_value = "1", requires = "whitelist")]
+    max_mismatch: u32,
+
+    /// Count reads instead of unique UMIs (PCR duplicates count every time)
+    #[arg(long)]
+    no_dedup: bool,
+
+    /// UMIs containing N: keep (as a mismatch), drop, or correct (single N, to a
+    /// UMI of the same cell and gene)
+    #[arg(long, default_value = "keep", conflicts_with_all = ["no_dedup", "no_umi"])]
+    umi_n: UmiNPolicy,
+
+    /// Protocols without UMIs (e.g. smart-seq2): count uniquely mapped reads and
+    /// mark duplicates by alignment position instead of collapsing UMIs
+    #[arg(long, conflicts_with_all = ["fastq", "no_dedup"])]
+    no_umi: bool,
+
+    /// Deduplicate UMIs separately within bins of this many bp of 5' alignment
+    /// position, so the same UMI at distinct priming sites of a gene is kept apart
+    #[arg(long, value_name = "BP", conflicts_with_all = ["fastq", "no_dedup", "no_umi"])]
+    umi_bin: Option<u32>,
+
+    /// Discard molecules supported by fewer than this many reads after UMI
+    /// correction (e.g. 2 for noisy, ambient-heavy libraries)
+    #[arg(long, default_value = "1", conflicts_with_all = ["no_dedup", "no_umi"])]
+    min_umi_reads: u32,
+
+    /// Maximum Hamming distance between UMIs collapsed into one molecule (0-2 are
+    /// fast; larger distances compare every pair of UMIs)
+    #[arg(long, default_value = "1", conflicts_with_all = ["no_dedup", "no_umi"])]
+    umi_distance: u32,
+
+    /// Write the Cell Ranger v3 layout (matrix.mtx.gz, barcodes.tsv.gz, features.tsv.gz)
+    #[arg(long)]
+    gzip: bool,
+
+    /// CSV sample sheet (sample,bam) to count many samples
+    #[arg(long, conflicts_with_all = ["input", "fastq"])]
+    samples: Option<PathBuf>,
+
+    /// Samples to process in parallel with --samples
+    #[arg(long, default_value = "1")]
+    parallel_samples: usize,
+
+    /// Estimate reads, memory, and disk without counting
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl CountArgs {
+    /// Arguments for one sample-sheet row, writing under `output`
+    fn for_sample(&self, sample: &SampleEntry, output: PathBuf) -> Self {
+        let mut args = self.clone();
+        args.samples = None;
+        args.input = sample.bam.clone();
+        args.output = output;
+        args
+    }
 }
 
 pub fn run(args: CountArgs) -> Result<()> {
-    log::info!("Opening BAM file: {:?}", args.input);
-    let mut parser = BamParser::open(&args.input)
-        .context("Failed to open BAM file")?;
+    let Some(sheet) = &args.samples else {
+        if args.dry_run {
+            return dry_run(&args);
+        }
+        return run_sample(&args).map(|_| ());
+    };
+    let samples = parse_sample_sheet(sheet)?;
+    if args.dry_run {
+        for sample in &samples {
+            println!("--- Sample: {} ---", sample.name);
+            dry_run(&args.for_sample(sample, args.output.join(&sample.name)))?;
+            println!();
+        }
+        return Ok(());
+    }
+    run_samples(&samples, args.parallel_samples, &args.output, |sample, output| {
+        run_sample(&args.for_sample(sample, output))
+    })
+}
 
-    // Create output directory
-    std::fs::create_dir_all(&args.output)?;
+/// Inspect the BAM and print estimated resources
+fn dry_run(args: &CountArgs) -> Result<()> {
+    let input = args
+        .input
+        .as_ref()
+        .or(args.fastq.as_ref())
+        .context("No BAM given (--input or sample sheet bam column)")?;
+    let mut plan = DryRunPlan::default();
+    let records = plan.add_input(input)?;
 
-    let progress = ProgressBar::new_spinner();
-    progress.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed_precise}] {msg}")
-            .unwrap(),
-    );
+    // Every assigned read could be a distinct (cell, gene) entry
+    plan.memory("Counting (count table, upper bound)", counter_memory(records));
+    plan.disk("Count matrix (upper bound)", mtx_bytes(records));
 
-    let mut counter = GeneCounter::new();
-    let mut total_reads = 0u64;
-    let mut assigned_reads = 0u64;
+    plan.print("count");
+    Ok(())
+}
 
-    // Process BAM records
-    for result in &mut parser {
-        let record = result?;
-        total_reads += 1;
+fn run_sample(args: &CountArgs) -> Result<SampleMetrics> {
+    // Create output directory
+    std::fs::create_dir_all(&args.output)?;
 
-        if total_reads % 100000 == 0 {
-            progress.set_message(format!(
-                "Processed {} reads, {} assigned ({:.1}%)",
-                total_reads,
-                assigned_reads,
-                assigned_reads as f64 / total_reads as f64 * 100.0
-            ));
-        }
+    let input = args.input.as_deref().or(args.fastq.as_deref());
+    let progress = super::progress::input_progress(input, 1);
 
-        // Skip unmapped or low quality
-        if !record.is_mapped || record.mapq < args.min_mapq {
-            continue;
+    let mut cells = CellFilter::load(args.barcodes.as_deref())?;
+    let mut raw_barcodes = match &args.whitelist {
+        Some(whitelist) => Some(RawBarcodes {
+            corrector: super::extract::load_corrector(whitelist, None, args.max_mismatch)?,
+            report: CorrectionReport::new(),
+        }),
+        None => None,
+    };
+    let annotation = load_annotation(args)?;
+    let biotypes = annotation.as_ref().and_then(|a| biotype_filter(args, a));
+    let mut strand = StrandFilter::load(args, annotation.as_ref())?;
+    let counters = Metrics::new();
+    let transcripts = TranscriptReads::load(args, &counters)?;
+    let stage = perf::stage("count");
+    let mut tally = Tally::new(args, &counters);
+    let mut stats = if args.no_umi { CellStats::by_position() } else { CellStats::new() };
+    let mut multi = args.em.then(MultiGeneCounter::new);
+    let mut sinks = ReadSinks {
+        tally: &mut tally,
+        cells: &mut cells,
+        stats: &mut stats,
+        multi: multi.as_mut(),
+        dedup: !args.no_dedup && !args.no_umi,
+        biotypes: biotypes.as_ref(),
+        gene_names: HashMap::new(),
+        counts: CountCounters::new(&counters),
+        positions: args.no_umi.then(|| PositionDuplicates::new(&counters)),
+    };
+    let header_tags = match &args.header_regex {
+        Some(pattern) => Some(HeaderTags::regex(pattern)?),
+        None => None,
+    };
+    match (&args.fastq, &args.index) {
+        (Some(fastq), Some(index)) => {
+            let header_tags = header_tags.unwrap_or_default();
+            let parser = FastqParser::open(fastq)
+                .context("Failed to open FASTQ file")?
+                .with_policy(args.parse_policy);
+            count_pseudoaligned(parser, index, &header_tags, &mut sinks, &progress)?
         }
+        _ => {
+            let tags = BamTags {
+                header: header_tags.as_ref(),
+                raw: raw_barcodes.as_mut(),
+            };
+            let features = Features {
+                strand: strand.as_mut(),
+                transcripts: transcripts.as_ref(),
+            };
+            count_bam(args, tags, &mut sinks, features, &progress)?
+        }
+    }
+    let (total_reads, assigned_reads) = (sinks.counts.reads.get(), sinks.counts.assigned.get());
+    if sinks.biotypes.is_some() {
+        log::info!(
+            "{} reads of filtered biotypes were skipped",
+            sinks.counts.biotype_filtered.get()
+        );
+    }
+    let gene_names = sinks.gene_names;
+    let positions = sinks.positions;
+    if let Some(excluded) = cells.excluded() {
+        log::info!("{} reads from barcodes outside --barcodes were skipped", excluded);
+    }
+    stage.finish();
 
-        // Need cell barcode and gene
-        let (barcode, gene) = match (&record.cell_barcode, &record.gene_name) {
-            (Some(bc), Some(gn)) => (bc, gn),
-            (Some(bc), None) => {
-                // Try gene_id if gene_name not available
-                if let Some(gx) = &record.gene_id {
-                    (bc, gx)
-                } else {
-                    continue;
-                }
-            }
-            _ => continue,
-        };
-
-        counter.increment(barcode, gene);
-        assigned_reads += 1;
+    let stage = perf::stage("umi_dedup");
+    let (mut counter, dedup_stats) = tally.finish();
+    stage.finish();
+    if let Some(stats) = &dedup_stats {
+        counters.counter("umi_dedup", "reads").add(stats.reads);
+        counters.counter("umi_dedup", "molecules").add(stats.molecules);
+        counters.counter("umi_dedup", "n_umi_reads").add(stats.n_umi_reads);
+        counters.counter("umi_dedup", "n_umi_corrected").add(stats.n_umi_corrected);
+        counters.counter("umi_dedup", "n_umi_dropped").add(stats.n_umi_dropped);
+        if args.min_umi_reads > 1 {
+            counters
+                .counter("umi_dedup", "low_evidence_molecules")
+                .add(stats.low_evidence_molecules);
+        }
+    }
+    if let Some(positions) = &positions {
+        positions.finish(&counters);
+    }
+    metrics::global().merge(&counters);
+    for (id, name) in &gene_names {
+        counter.set_gene_name(id, name);
     }
 
     progress.finish_with_message(format!(
@@ -89,25 +311,185 @@ pub fn run(args: CountArgs) -> Result<()> {
         total_reads
     ));
 
-    // Build matrix
+    let mut matrix = build_matrix(counter)?;
+    let em_stats = match multi {
+        Some(multi) => {
+            log::info!("Resolving {} multi-gene classes by EM...", multi.len());
+            let stage = perf::stage("em");
+            let (resolved, em_stats) = multi.resolve(matrix, args.em_rounding);
+            stage.finish();
+            matrix = resolved;
+            Some(em_stats)
+        }
+        None => None,
+    };
+    let mut rollup = None;
+    match (&transcripts, &annotation) {
+        (Some(transcripts), annotation) => {
+            let annotated = matrix.annotate_transcripts(&transcripts.annotation);
+            log::info!("{} of {} transcripts matched the GTF annotation", annotated, matrix.n_rows);
+            if args.rollup {
+                let mut genes = matrix.rollup_to_genes()?;
+                if let Some(annotation) = annotation {
+                    genes.annotate_genes(annotation);
+                }
+                rollup = Some(genes);
+            }
+        }
+        (None, Some(annotation)) => {
+            let annotated = matrix.annotate_genes(annotation);
+            log::info!("{} of {} genes matched the GTF annotation", annotated, matrix.n_rows);
+        }
+        (None, None) => {}
+    }
+    write_matrix(args, &matrix, &args.output)?;
+    if let Some(genes) = &rollup {
+        log::info!("Writing gene-level rollup...");
+        write_matrix(args, genes, &args.output.join("genes"))?;
+    }
+    stats.add_matrix(&matrix);
+    let stats_path = args.output.join("cell_stats.tsv");
+    stats.write_tsv(&stats_path)?;
+    println!("  {:?}", stats_path);
+    let raw_summary = match &raw_barcodes {
+        Some(raw) => {
+            let corrections_path = args.output.join("barcode_corrections.tsv");
+            raw.report.write_tsv(&corrections_path)?;
+            println!("  {:?}", corrections_path);
+            Some(raw.report.summary())
+        }
+        None => None,
+    };
+
+    let antisense_reads = strand.as_ref().map_or(0, |s| s.antisense_reads);
+    if let Some(antisense) = strand.and_then(|s| s.antisense) {
+        log::info!("Writing antisense matrix...");
+        let (mut counter, _) = antisense.finish();
+        for (id, name) in &gene_names {
+            counter.set_gene_name(id, name);
+        }
+        write_matrix(args, &build_matrix(counter)?, &args.output.join("antisense"))?;
+    }
+    let antisense_pct =
+        antisense_reads as f64 / (assigned_reads + antisense_reads).max(1) as f64 * 100.0;
+
+    // Print summary
+    println!("\n=== Count Summary ===");
+    println!("Total reads:    {}", total_reads);
+    println!("Assigned reads: {} ({:.1}%)",
+        assigned_reads,
+        assigned_reads as f64 / total_reads.max(1) as f64 * 100.0
+    );
+    if args.strandedness != Strandedness::Unstranded {
+        println!("Antisense:      {} ({:.1}%)", antisense_reads, antisense_pct);
+    }
+    if let Some(stats) = &dedup_stats {
+        println!("Molecules:      {}", stats.molecules);
+        println!("Duplication:    {:.1}%", stats.duplication_rate() * 100.0);
+        if stats.n_umi_reads > 0 {
+            println!(
+                "UMIs with N:    {} reads ({} corrected, {} dropped)",
+                stats.n_umi_reads, stats.n_umi_corrected, stats.n_umi_dropped
+            );
+        }
+        if args.min_umi_reads > 1 {
+            println!(
+                "Low-evidence:   {} molecules with < {} reads discarded",
+                stats.low_evidence_molecules, args.min_umi_reads
+            );
+        }
+    }
+    if let Some(positions) = &positions {
+        println!(
+            "Duplicates:     {} ({:.1}%, by position)",
+            positions.marker.duplicates(),
+            positions.marker.duplication_rate() * 100.0
+        );
+        println!("Multi-mapped:   {} (not counted)", positions.multimapped.get());
+    }
+    if let Some(em) = &em_stats {
+        println!("EM rescued:     {} (+{} counts)", em.units, em.added);
+    }
+    if let Some(raw) = &raw_summary {
+        println!(
+            "CR barcodes:    {} exact, {} corrected, {} unmatched",
+            raw.exact, raw.corrected, raw.no_match
+        );
+    }
+    println!("Cells:          {}", matrix.n_cols);
+    println!("Genes:          {}", matrix.n_rows);
+
+    let mut metrics = vec![
+        ("total_reads", total_reads as f64),
+        ("assigned_pct", assigned_reads as f64 / total_reads.max(1) as f64 * 100.0),
+        ("cells", matrix.n_cols as f64),
+        ("genes", matrix.n_rows as f64),
+    ];
+    if args.strandedness != Strandedness::Unstranded {
+        metrics.push(("antisense_pct", antisense_pct));
+    }
+    if let Some(stats) = &dedup_stats {
+        metrics.push(("molecules", stats.molecules as f64));
+        metrics.push(("duplication_pct", stats.duplication_rate() * 100.0));
+        metrics.push(("n_umi_reads", stats.n_umi_reads as f64));
+        metrics.push(("n_umi_dropped", stats.n_umi_dropped as f64));
+        if args.min_umi_reads > 1 {
+            metrics.push(("low_evidence_molecules", stats.low_evidence_molecules as f64));
+        }
+    }
+    if let Some(positions) = &positions {
+        metrics.push(("duplication_pct", positions.marker.duplication_rate() * 100.0));
+        metrics.push(("position_duplicates", positions.marker.duplicates() as f64));
+        metrics.push(("multimapped", positions.multimapped.get() as f64));
+    }
+    if let Some(em) = &em_stats {
+        metrics.push(("em_rescued", em.units as f64));
+    }
+    if let Some(raw) = &raw_summary {
+        metrics.push(("cr_corrected", raw.corrected as f64));
+        metrics.push(("cr_unmatched", raw.no_match as f64));
+    }
+    Ok(metrics)
+}
+
+/// Build the matrix from `counter`
+fn build_matrix(counter: GeneCounter) -> Result<CountMatrix> {
     log::info!("Building count matrix...");
-    let matrix = counter.build();
+    let stage = perf::stage("build_matrix");
+    let matrix = counter.try_build()?;
+    stage.finish();
+    Ok(matrix)
+}
 
+/// Write `matrix` to `dir` in `--format`
+fn write_matrix(args: &CountArgs, matrix: &CountMatrix, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
     log::info!("Matrix dimensions: {} genes x {} cells",
         matrix.n_rows, matrix.n_cols);
     log::info!("Non-zero entries: {}", matrix.values.len());
 
     // Write output
+    let stage = perf::stage("write_matrix");
     match args.format.as_str() {
         "mtx" => {
-            let mtx_path = args.output.join("matrix.mtx");
-            let barcodes_path = args.output.join("barcodes.tsv");
-            let genes_path = args.output.join("genes.tsv");
+            // Cell Ranger v3 layout when gzipped, v2 otherwise
+            let ext = if args.gzip { ".gz" } else { "" };
+            let mtx_path = dir.join(format!("matrix.mtx{}", ext));
+            let barcodes_path = dir.join(format!("barcodes.tsv{}", ext));
+            let genes_path = if args.gzip {
+                dir.join("features.tsv.gz")
+            } else {
+                dir.join("genes.tsv")
+            };
 
             log::info!("Writing Matrix Market files...");
             matrix.write_mtx(&mtx_path)?;
             matrix.write_barcodes(&barcodes_path)?;
-            matrix.write_genes(&genes_path)?;
+            if args.gzip {
+                matrix.write_features(&genes_path, GENE_EXPRESSION)?;
+            } else {
+                matrix.write_genes(&genes_path)?;
+            }
 
             println!("\nOutput files:");
             println!("  {:?}", mtx_path);
@@ -119,16 +501,648 @@ pub fn run(args: CountArgs) -> Result<()> {
         }
         _ => anyhow::bail!("Unknown format: {}", args.format),
     }
+    stage.finish();
 
-    // Print summary
-    println!("\n=== Count Summary ===");
-    println!("Total reads:    {}", total_reads);
-    println!("Assigned reads: {} ({:.1}%)",
-        assigned_reads,
-        assigned_reads as f64 / total_reads as f64 * 100.0
-    );
-    println!("Cells:          {}", matrix.n_cols);
-    println!("Genes:          {}", matrix.n_rows);
+    Ok(())
+}
+
+/// Assigned reads, counted per read or per unique UMI
+enum Tally {
+    Reads(GeneCounter),
+    Molecules {
+        molecules: MoleculeCounter,
+        /// Max Hamming distance within one molecule (--umi-distance)
+        max_distance: u32,
+        /// Assigned reads dropped for lacking a UMI
+        missing_umi: Arc<Counter>,
+    },
+}
+
+impl Tally {
+    fn new(args: &CountArgs, counters: &Metrics) -> Self {
+        if args.no_dedup || args.no_umi {
+            Tally::Reads(GeneCounter::with_resources(sparc_core::resources::global()))
+        } else {
+            Tally::Molecules {
+                molecules: MoleculeCounter::new()
+                    .with_n_policy(args.umi_n)
+                    .with_position_bins(args.umi_bin.unwrap_or(0))
+                    .with_min_reads(args.min_umi_reads),
+                max_distance: args.umi_distance,
+                missing_umi: counters.counter("count", "missing_umi"),
+            }
+        }
+    }
+
+    /// Count an assigned read whose 5' end aligns at `position` (for
+    /// --umi-bin); false if it was dropped for lacking a usable UMI
+    fn add(&mut self, barcode: &str, gene: &str, umi: Option<&str>, position: Option<i64>) -> bool {
+        match (self, umi) {
+            (Tally::Reads(counter), _) => counter.increment(barcode, gene),
+            (Tally::Molecules { molecules, .. }, Some(umi)) => {
+                return molecules.add_read_at(barcode, gene, umi, position);
+            }
+            (Tally::Molecules { missing_umi, .. }, None) => {
+                missing_umi.inc();
+                return false;
+            }
+        }
+        true
+    }
 
+    /// The counts to build the matrix from, deduplicated unless counting reads
+    fn finish(self) -> (GeneCounter, Option<DedupStats>) {
+        match self {
+            Tally::Reads(counter) => (counter, None),
+            Tally::Molecules {
+                molecules,
+                max_distance,
+                missing_umi,
+            } => {
+                if missing_umi.get() > 0 {
+                    log::warn!(
+                        "{} assigned reads had no UMI and were not counted",
+                        missing_umi.get()
+                    );
+                }
+                log::info!("Deduplicating UMIs of {} reads...", molecules.reads());
+                let counter = GeneCounter::with_resources(sparc_core::resources::global());
+                let dedup = UmiDeduplicator::new(max_distance);
+                let (counter, stats) = molecules.finish(&dedup, counter);
+                log::info!(
+                    "{} molecules from {} reads ({:.1}% duplicates)",
+                    stats.molecules,
+                    stats.reads,
+                    stats.duplication_rate() * 100.0
+                );
+                (counter, Some(stats))
+            }
+        }
+    }
+}
+
+/// Optional `--barcodes` list restricting which cells are counted
+struct CellFilter {
+    cells: Option<Whitelist>,
+    /// Reads skipped for a barcode outside the list
+    excluded: u64,
+}
+
+impl CellFilter {
+    fn load(path: Option<&Path>) -> Result<Self> {
+        let cells = match path {
+            Some(path) => {
+                let cells = Whitelist::from_file(path)
+                    .with_context(|| format!("Failed to load cell barcodes {:?}", path))?;
+                anyhow::ensure!(!cells.is_empty(), "No cell barcodes in {:?}", path);
+                log::info!("Counting {} listed cell barcodes", cells.len());
+                Some(cells)
+            }
+            None => None,
+        };
+        Ok(Self { cells, excluded: 0 })
+    }
+
+    /// Whether reads from `barcode` are counted
+    fn allows(&mut self, barcode: &str) -> bool {
+        match &self.cells {
+            Some(cells) if !cells.contains(barcode) => {
+                self.excluded += 1;
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Reads skipped so far, when a list was given
+    fn excluded(&self) -> Option<u64> {
+        self.cells.as_ref().map(|_| self.excluded)
+    }
+}
+
+/// Whitelist correction of raw CR barcodes, for BAMs without CB tags
+struct RawBarcodes {
+    corrector: BarcodeCorrector,
+    report: CorrectionReport,
+}
+
+impl RawBarcodes {
+    /// Corrected barcode for `raw`, if it matches the whitelist
+    fn correct(&mut self, raw: &str) -> Option<String> {
+        let barcode_match = self.corrector.match_barcode(raw);
+        self.report.record(&barcode_match);
+        barcode_match.barcode().map(str::to_string)
+    }
+}
+
+/// Fallbacks for BAM records without a CB tag
+struct BamTags<'a> {
+    /// Barcode and UMI parsed from the read name (--header-regex)
+    header: Option<&'a HeaderTags>,
+    /// CR/UR tags corrected against --whitelist
+    raw: Option<&'a mut RawBarcodes>,
+}
+
+/// Per-read checks against the `--gtf` features
+struct Features<'s, 'a> {
+    strand: Option<&'s mut StrandFilter<'a>>,
+    transcripts: Option<&'s TranscriptReads>,
+}
+
+/// Assigns reads to transcripts from their alignment with --transcripts
+struct TranscriptReads {
+    annotation: TranscriptAnnotation,
+    strandedness: Strandedness,
+    /// Mapped reads compatible with no transcript
+    unassigned: Arc<Counter>,
+}
+
+impl TranscriptReads {
+    fn load(args: &CountArgs, metrics: &Metrics) -> Result<Option<Self>> {
+        if !args.transcripts {
+            return Ok(None);
+        }
+        let gtf = args.gtf.as_ref().context("--transcripts needs --gtf")?;
+        let annotation = TranscriptAnnotation::from_gtf(gtf).context("Failed to load GTF")?;
+        anyhow::ensure!(!annotation.is_empty(), "No transcripts (exon transcript_id) in {:?}", gtf);
+        Ok(Some(Self {
+            annotation,
+            strandedness: args.strandedness,
+            unassigned: metrics.counter("count", "no_transcript"),
+        }))
+    }
+
+    /// IDs of the transcripts the read best matches on its sense strand
+    fn assign(&self, chrom: &str, record: &BamRecord) -> Vec<&str> {
+        self.annotation
+            .compatible(chrom, &record.aligned_blocks())
+            .into_iter()
+            .map(|i| self.annotation.transcript(i))
+            .filter(|t| self.strandedness.is_sense_to(t.strand, record.is_reverse))
+            .map(|t| t.id.as_str())
+            .collect()
+    }
+}
+
+/// Checks tagged reads against the strand of their gene
+struct StrandFilter<'a> {
+    strandedness: Strandedness,
+    annotation: &'a GeneAnnotation,
+    /// Gene ID or symbol -> gene index in `annotation`
+    genes: HashMap<String, usize>,
+    /// Assigned reads antisense to their gene
+    antisense_reads: u64,
+    /// Counts of antisense reads with --antisense-matrix
+    antisense: Option<Tally>,
+}
+
+impl<'a> StrandFilter<'a> {
+    /// Gene strands from the `--gtf` annotation; `None` for unstranded libraries
+    /// and with --transcripts, which checks transcript strands instead
+    fn load(args: &CountArgs, annotation: Option<&'a GeneAnnotation>) -> Result<Option<Self>> {
+        if args.strandedness == Strandedness::Unstranded || args.transcripts {
+            return Ok(None);
+        }
+        anyhow::ensure!(
+            args.fastq.is_none(),
+            "--strandedness needs aligned reads (-i BAM), not --fastq"
+        );
+        let annotation = annotation.context("--strandedness needs gene strands from --gtf")?;
+
+        // Symbols can repeat across genes; IDs are inserted last so they win
+        let mut genes = HashMap::new();
+        for (i, gene) in annotation.genes().iter().enumerate() {
+            genes.entry(gene.name.clone()).or_insert(i);
+        }
+        for (i, gene) in annotation.genes().iter().enumerate() {
+            genes.insert(gene.id.clone(), i);
+        }
+        Ok(Some(Self {
+            strandedness: args.strandedness,
+            annotation,
+            genes,
+            antisense_reads: 0,
+            antisense: args.antisense_matrix.then(|| Tally::new(args, &Metrics::new())),
+        }))
+    }
+
+    /// Whether the read is sense for `gene` (or the gene is not in the GTF).
+    /// Antisense reads are counted here instead.
+    fn keep(&mut self, barcode: &str, gene: &str, umi: Option<&str>, record: &BamRecord) -> bool {
+        if self.is_sense(gene, record) {
+            return true;
+        }
+        self.record_antisense(barcode, &[gene], umi, record);
+        false
+    }
+
+    /// The candidate genes of a multi-gene read it is sense for. A read sense
+    /// to none of them is counted as antisense, toward each of its genes.
+    fn retain<'g>(
+        &mut self,
+        barcode: &str,
+        genes: &[&'g str],
+        umi: Option<&str>,
+        record: &BamRecord,
+    ) -> Vec<&'g str> {
+        let sense: Vec<&str> = genes.iter().copied().filter(|g| self.is_sense(g, record)).collect();
+        if sense.is_empty() {
+            self.record_antisense(barcode, genes, umi, record);
+        }
+        sense
+    }
+
+    fn is_sense(&self, gene: &str, record: &BamRecord) -> bool {
+        match self.genes.get(gene) {
+            Some(&idx) => self.strandedness.is_sense(self.annotation.gene(idx), record.is_reverse),
+            None => true,
+        }
+    }
+
+    fn record_antisense(
+        &mut self,
+        barcode: &str,
+        genes: &[&str],
+        umi: Option<&str>,
+        record: &BamRecord,
+    ) {
+        self.antisense_reads += 1;
+        if let Some(antisense) = &mut self.antisense {
+            for gene in genes {
+                antisense.add(barcode, gene, umi, Some(record.five_prime()));
+            }
+        }
+    }
+}
+
+/// The `--gtf` annotation, if given
+fn load_annotation(args: &CountArgs) -> Result<Option<GeneAnnotation>> {
+    let Some(gtf) = &args.gtf else {
+        return Ok(None);
+    };
+    let annotation = GeneAnnotation::from_gtf(gtf).context("Failed to load GTF")?;
+    if annotation.is_empty() {
+        anyhow::bail!("No genes found in {:?}", gtf);
+    }
+    Ok(Some(annotation))
+}
+
+/// The `--include-biotypes`/`--exclude-biotypes` filter, if either was given
+fn biotype_filter(args: &CountArgs, annotation: &GeneAnnotation) -> Option<BiotypeFilter> {
+    if args.include_biotypes.is_empty() && args.exclude_biotypes.is_empty() {
+        return None;
+    }
+    let biotypes = GeneBiotypes::from_annotation(annotation);
+    if biotypes.is_empty() {
+        log::warn!("The GTF has no gene_type or gene_biotype attributes; all genes are unknown");
+    }
+    Some(BiotypeFilter::new(biotypes, &args.include_biotypes, &args.exclude_biotypes))
+}
+
+/// Advance the bar every 10k reads and report counts every 100k
+fn report_progress(progress: &ProgressBar, counts: &CountCounters) {
+    let (total_reads, assigned_reads) = (counts.reads.get(), counts.assigned.get());
+    if total_reads % 10_000 == 0 {
+        super::progress::set_position(progress, total_reads);
+    }
+    if total_reads % 100000 == 0 {
+        progress.set_message(format!(
+            "Processed {} reads, {} assigned ({:.1}%)",
+            total_reads,
+            assigned_reads,
+            assigned_reads as f64 / total_reads.max(1) as f64 * 100.0
+        ));
+    }
+}
+
+/// Where each tagged read is recorded
+struct ReadSinks<'a> {
+    tally: &'a mut Tally,
+    cells: &'a mut CellFilter,
+    stats: &'a mut CellStats,
+    /// Multi-gene reads kept for EM with --em
+    multi: Option<&'a mut MultiGeneCounter>,
+    /// Whether multi-gene reads collapse by UMI
+    dedup: bool,
+    /// Genes kept by --include-biotypes/--exclude-biotypes
+    biotypes: Option<&'a BiotypeFilter>,
+    /// Gene ID -> symbol, from GX/GN tags or the k-mer index
+    gene_names: HashMap<String, String>,
+    counts: CountCounters,
+    /// Duplicate marking of uniquely mapped reads with --no-umi
+    positions: Option<PositionDuplicates>,
+}
+
+/// Read tallies of the counting stage
+struct CountCounters {
+    /// Reads seen
+    reads: Arc<Counter>,
+    /// Reads counted toward a gene (or kept for EM)
+    assigned: Arc<Counter>,
+    /// Reads dropped because every gene had a filtered biotype
+    biotype_filtered: Arc<Counter>,
+    /// FASTQ reads without a barcode/UMI in the header
+    untagged: Arc<Counter>,
+    /// Reads compatible with more than one gene
+    multi_gene: Arc<Counter>,
+}
+
+impl CountCounters {
+    fn new(metrics: &Metrics) -> Self {
+        Self {
+            reads: metrics.counter("count", "reads"),
+            assigned: metrics.counter("count", "assigned"),
+            biotype_filtered: metrics.counter("count", "biotype_filtered"),
+            untagged: metrics.counter("count", "untagged"),
+            multi_gene: metrics.counter("count", "multi_gene"),
+        }
+    }
+}
+
+/// Reads of one cell sharing a 5' position and strand, marked as duplicates
+/// when counting without UMIs
+struct PositionDuplicates {
+    marker: DuplicateMarker,
+    /// Reads skipped for aligning more than once (NH > 1)
+    multimapped: Arc<Counter>,
+}
+
+impl PositionDuplicates {
+    fn new(metrics: &Metrics) -> Self {
+        Self {
+            marker: DuplicateMarker::new(),
+            multimapped: metrics.counter("count", "multimapped"),
+        }
+    }
+
+    /// Log the duplicate totals and add them to `metrics`
+    fn finish(&self, metrics: &Metrics) {
+        log::info!(
+            "{} of {} assigned reads are position duplicates ({:.1}%)",
+            self.marker.duplicates(),
+            self.marker.reads(),
+            self.marker.duplication_rate() * 100.0
+        );
+        metrics.counter("count", "position_duplicates").add(self.marker.duplicates());
+    }
+}
+
+impl ReadSinks<'_> {
+    /// Remember the symbol of gene `id`; `;`-separated lists pair up in order
+    fn name_genes(&mut self, ids: &str, names: &str) {
+        if ids.matches(';').count() != names.matches(';').count() {
+            return;
+        }
+        for (id, name) in ids.split(';').zip(names.split(';')) {
+            if id != name && !self.gene_names.contains_key(id) {
+                self.gene_names.insert(id.to_string(), name.to_string());
+            }
+        }
+    }
+
+    /// Mark an assigned read as a duplicate if an earlier read of the cell
+    /// started at the same position and strand (--no-umi)
+    fn mark_duplicate(&mut self, barcode: &str, record: &BamRecord) {
+        let Some(positions) = &mut self.positions else {
+            return;
+        };
+        let five_prime = record.five_prime();
+        if positions.marker.mark(barcode, record.tid, five_prime, record.is_reverse) {
+            self.stats.record_duplicate(barcode);
+        }
+    }
+
+    /// Count a read assigned to one gene; false if it was dropped
+    fn add_unique(
+        &mut self,
+        barcode: &str,
+        gene: &str,
+        umi: Option<&str>,
+        position: Option<i64>,
+    ) -> bool {
+        if self.biotypes.map_or(false, |b| !b.allows(gene)) {
+            self.counts.biotype_filtered.inc();
+            return false;
+        }
+        let added = self.tally.add(barcode, gene, umi, position);
+        if added {
+            self.stats.record_assigned(barcode);
+        }
+        added
+    }
+
+    /// Keep a read compatible with several genes for EM; false without --em
+    /// or, when deduplicating, without a UMI. Genes of filtered biotypes are
+    /// dropped first, so a read left with one gene counts as unique.
+    fn add_multi(
+        &mut self,
+        barcode: &str,
+        genes: &[&str],
+        umi: Option<&str>,
+        position: Option<i64>,
+    ) -> bool {
+        let genes: Vec<&str> = match self.biotypes {
+            Some(filter) => genes.iter().copied().filter(|g| filter.allows(g)).collect(),
+            None => genes.to_vec(),
+        };
+        match genes.as_slice() {
+            [] => {
+                self.counts.biotype_filtered.inc();
+                return false;
+            }
+            [gene] => return self.add_unique(barcode, gene, umi, position),
+            _ => {}
+        }
+        let Some(multi) = self.multi.as_deref_mut() else {
+            return false;
+        };
+        match (self.dedup, umi) {
+            (true, None) => return false,
+            (true, umi) => multi.add(barcode, &genes, umi),
+            (false, _) => multi.add(barcode, &genes, None),
+        }
+        self.stats.record_assigned(barcode);
+        true
+    }
+}
+
+/// Count reads carrying CB and gene tags in an aligned BAM. A gene tag listing
+/// several `;`-separated genes marks a multi-gene read. Reads without a CB tag
+/// fall back to their corrected CR tag, then to the read name. With
+/// --transcripts, reads are counted toward the transcripts their alignment
+/// matches instead of their gene tag.
+fn count_bam(
+    args: &CountArgs,
+    mut tags: BamTags,
+    sinks: &mut ReadSinks,
+    features: Features<'_, '_>,
+    progress: &ProgressBar,
+) -> Result<()> {
+    let input = args.input.as_ref().context("No BAM given (--input or sample sheet bam column)")?;
+    log::info!("Opening BAM file: {:?}", input);
+    let mut parser = BamParser::open(input)
+        .context("Failed to open BAM file")?;
+    let Features {
+        mut strand,
+        transcripts,
+    } = features;
+    let chroms = parser.reference_names();
+
+    // Process BAM records
+    for result in &mut parser {
+        let record = result?;
+        sinks.counts.reads.inc();
+        report_progress(progress, &sinks.counts);
+
+        // Skip unmapped or low quality
+        if !record.is_mapped || record.mapq < args.min_mapq {
+            continue;
+        }
+        if let Some(positions) = &sinks.positions {
+            if !record.is_unique() {
+                positions.multimapped.inc();
+                continue;
+            }
+        }
+
+        // Need cell barcode and gene, keyed by gene ID (GX) when there is one
+        let corrected = match (&record.cell_barcode, &record.raw_barcode, tags.raw.as_mut()) {
+            (None, Some(raw), Some(raw_barcodes)) => raw_barcodes.correct(raw),
+            _ => None,
+        };
+        let (barcode, umi) = match (&record.cell_barcode, &corrected, tags.header) {
+            (Some(barcode), _, _) => (barcode.as_str(), record.umi.as_deref()),
+            (None, Some(barcode), _) => {
+                let umi = record.umi.as_deref().or(record.raw_umi.as_deref());
+                (barcode.as_str(), umi)
+            }
+            (None, None, Some(header)) => match header.parse(&record.name) {
+                Some((barcode, umi)) => (barcode, Some(umi)),
+                None => continue,
+            },
+            (None, None, None) => continue,
+        };
+        if !sinks.cells.allows(barcode) {
+            continue;
+        }
+        sinks.stats.record_read(barcode, record.region.and_then(RegionType::from_tag));
+        let position = args.umi_bin.is_some().then(|| record.five_prime());
+        if let Some(transcripts) = transcripts {
+            let chrom = chroms.get(record.tid as usize).map_or("", String::as_str);
+            let added = match transcripts.assign(chrom, &record).as_slice() {
+                [] => {
+                    transcripts.unassigned.inc();
+                    false
+                }
+                [transcript] => sinks.add_unique(barcode, transcript, umi, position),
+                ids => {
+                    sinks.counts.multi_gene.inc();
+                    sinks.add_multi(barcode, ids, umi, position)
+                }
+            };
+            if added {
+                sinks.counts.assigned.inc();
+                sinks.mark_duplicate(barcode, &record);
+            }
+            continue;
+        }
+        let Some(gene) = record.gene_id.as_ref().or(record.gene_name.as_ref()) else {
+            continue;
+        };
+        if let (Some(id), Some(name)) = (&record.gene_id, &record.gene_name) {
+            sinks.name_genes(id, name);
+        }
+
+        if gene.contains(';') {
+            let mut genes: Vec<&str> = gene.split(';').filter(|g| !g.is_empty()).collect();
+            if let Some(strand) = strand.as_deref_mut() {
+                genes = strand.retain(barcode, &genes, umi, &record);
+                if genes.is_empty() {
+                    continue;
+                }
+            }
+            if sinks.add_multi(barcode, &genes, umi, position) {
+                sinks.counts.assigned.inc();
+                sinks.mark_duplicate(barcode, &record);
+            }
+            continue;
+        }
+        if let Some(strand) = strand.as_deref_mut() {
+            if !strand.keep(barcode, gene, umi, &record) {
+                continue;
+            }
+        }
+        if sinks.add_unique(barcode, gene, umi, position) {
+            sinks.counts.assigned.inc();
+            sinks.mark_duplicate(barcode, &record);
+        }
+    }
+
+    if let Some(transcripts) = transcripts {
+        log::info!(
+            "{} reads matched no transcript; {} matched more than one",
+            transcripts.unassigned.get(),
+            sinks.counts.multi_gene.get()
+        );
+    }
+    Ok(())
+}
+
+/// Count barcode-tagged reads assigned to genes by k-mer pseudoalignment
+fn count_pseudoaligned(
+    mut parser: FastqParser,
+    index: &Path,
+    header_tags: &HeaderTags,
+    sinks: &mut ReadSinks,
+    progress: &ProgressBar,
+) -> Result<()> {
+    let index = KmerIndex::read(index)
+        .with_context(|| format!("Failed to load k-mer index {:?}", index))?;
+
+    for result in parser.by_ref() {
+        let record = result?;
+        sinks.counts.reads.inc();
+        report_progress(progress, &sinks.counts);
+
+        let Some((barcode, umi)) = header_tags.parse(&record.id) else {
+            sinks.counts.untagged.inc();
+            continue;
+        };
+        if !sinks.cells.allows(barcode) {
+            continue;
+        }
+        sinks.stats.record_read(barcode, None);
+        let genes = index.compatible_genes(&record.seq).unwrap_or_default();
+        for &g in &genes {
+            sinks.name_genes(index.gene_id(g as usize), index.gene_name(g as usize));
+        }
+        let added = match genes.as_slice() {
+            [] => false,
+            [gene] => sinks.add_unique(barcode, index.gene_id(*gene as usize), Some(umi), None),
+            genes => {
+                sinks.counts.multi_gene.inc();
+                let ids: Vec<&str> = genes.iter().map(|&g| index.gene_id(g as usize)).collect();
+                sinks.add_multi(barcode, &ids, Some(umi), None)
+            }
+        };
+        if added {
+            sinks.counts.assigned.inc();
+        }
+    }
+
+    let untagged = sinks.counts.untagged.get();
+    if untagged > 0 {
+        log::warn!(
+            "{} reads had no barcode/UMI in the header (run `sparc extract` or set --header-regex)",
+            untagged
+        );
+    }
+    if parser.skipped() > 0 {
+        log::warn!("Skipped {} malformed FASTQ records", parser.skipped());
+    }
+    log::info!(
+        "{} reads were compatible with more than one gene",
+        sinks.counts.multi_gene.get()
+    );
     Ok(())
 }
@@ -0,0 +1,63 @@
+//! Call CRISPR guides per cell from a guide count matrix
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::count::CountMatrix;
+use sparc_core::crispr::{call_guides, GuideCallType, GuideCallerConfig};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct CrisprArgs {
+    /// Guide count matrix: Matrix Market directory or matrix.mtx[.gz] (guides x cells)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Never call a guide with fewer UMIs than this
+    #[arg(long, default_value = "3")]
+    min_umis: u32,
+
+    /// Posterior probability of expression required to call a guide
+    #[arg(long, default_value = "0.5")]
+    min_posterior: f64,
+}
+
+pub fn run(args: CrisprArgs) -> Result<()> {
+    let matrix = CountMatrix::read_mtx(&args.input)
+        .with_context(|| format!("Failed to read guide matrix {:?}", args.input))?;
+    if matrix.n_rows == 0 {
+        anyhow::bail!("Guide matrix {:?} has no features", args.input);
+    }
+    log::info!("Calling {} guides across {} cells", matrix.n_rows, matrix.n_cols);
+
+    let config = GuideCallerConfig {
+        min_umis: args.min_umis,
+        min_posterior: args.min_posterior,
+        ..Default::default()
+    };
+    let calls = call_guides(&matrix, &config);
+
+    std::fs::create_dir_all(&args.output)?;
+    calls.write_per_cell(args.output.join("protospacer_calls_per_cell.csv"))?;
+    calls.write_summary(args.output.join("protospacer_calls_summary.csv"))?;
+    calls.write_thresholds(args.output.join("protospacer_umi_thresholds.csv"))?;
+
+    let total = calls.cells.len().max(1) as f64;
+    let line = |label: &str, call_type: GuideCallType| {
+        let n = calls.count(call_type);
+        println!("{:<22} {:>10} ({:.1}%)", label, n, n as f64 / total * 100.0);
+    };
+    println!("\n=== Guide Calling Summary ===");
+    println!("{:<22} {:>10}", "Guides", matrix.n_rows);
+    println!("{:<22} {:>10}", "Cells", calls.cells.len());
+    line("No guide molecules", GuideCallType::NoMolecules);
+    line("No confident call", GuideCallType::NoCall);
+    line("1 guide", GuideCallType::Single);
+    line(">1 guide", GuideCallType::Multiple);
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+//! Demultiplex read pairs into per-sample FASTQs using index reads
+
+use anyhow::{Context, Result};
+use clap::Args;
+use indicatif::{ProgressBar, ProgressStyle};
+use sparc_core::{
+    demux::{DemuxMatch, SampleDemuxer, SampleSheet},
+    fastq::{check_mates, FastqParser, FastqRecord, FastqWriter, PairedFastqParser},
+    Error,
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct DemuxArgs {
+    /// Input R1 FASTQ file
+    #[arg(short = '1', long)]
+    r1: PathBuf,
+
+    /// Input R2 FASTQ file
+    #[arg(short = '2', long)]
+    r2: PathBuf,
+
+    /// Index read 1 (i7) FASTQ file
+    #[arg(long)]
+    i1: PathBuf,
+
+    /// Index read 2 (i5) FASTQ file
+    #[arg(long)]
+    i2: Option<PathBuf>,
+
+    /// Sample sheet CSV (columns: sample,index[,index2])
+    #[arg(short, long)]
+    sample_sheet: PathBuf,
+
+    /// Output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Maximum total index mismatches
+    #[arg(long, default_value = "1")]
+    max_mismatch: u32,
+}
+
+struct SampleOutput {
+    r1: FastqWriter,
+    r2: FastqWriter,
+    reads: u64,
+}
+
+impl SampleOutput {
+    fn create(dir: &Path, name: &str) -> Result<Self> {
+        Ok(Self {
+            r1: FastqWriter::new(dir.join(format!("{}_R1.fastq.gz", name)))?,
+            r2: FastqWriter::new(dir.join(format!("{}_R2.fastq.gz", name)))?,
+            reads: 0,
+        })
+    }
+
+    /// Move both FASTQs into place and return the sample's read count
+    fn finish(self) -> Result<u64> {
+        self.r1.finish()?;
+        self.r2.finish()?;
+        Ok(self.reads)
+    }
+}
+
+/// The index read of pair `pair_num`, which must exist and be R1's mate
+fn next_index(
+    parser: &mut FastqParser,
+    r1: &FastqRecord,
+    name: &str,
+    pair_num: u64,
+) -> Result<FastqRecord> {
+    let index = parser.next().ok_or_else(|| {
+        Error::FastqParse(format!("{} ended before R1/R2 at pair {}", name, pair_num))
+    })??;
+    check_mates(r1, &index, ("R1", name), pair_num)?;
+    Ok(index)
+}
+
+pub fn run(args: DemuxArgs) -> Result<()> {
+    let sheet = SampleSheet::from_csv(&args.sample_sheet).context("Failed to load sample sheet")?;
+    if sheet.samples.is_empty() {
+        anyhow::bail!("Sample sheet {:?} lists no samples", args.sample_sheet);
+    }
+    if sheet.is_dual_index() && args.i2.is_none() {
+        anyhow::bail!("Sample sheet has index2 values but --i2 was not given");
+    }
+    log::info!(
+        "Loaded {} samples ({} index rows) from sample sheet",
+        sheet.sample_names().len(),
+        sheet.samples.len()
+    );
+
+    std::fs::create_dir_all(&args.output)?;
+
+    // Rows of a multi-index sample share one writer pair
+    let names: Vec<String> = sheet.sample_names().into_iter().map(String::from).collect();
+    let row_output: Vec<usize> = sheet
+        .samples
+        .iter()
+        .map(|s| names.iter().position(|n| *n == s.sample).expect("name listed"))
+        .collect();
+    let demuxer = SampleDemuxer::new(sheet, args.max_mismatch);
+    let mut outputs = names
+        .iter()
+        .map(|name| SampleOutput::create(&args.output, name))
+        .collect::<Result<Vec<_>>>()?;
+    let mut undetermined = SampleOutput::create(&args.output, "Undetermined")?;
+    let mut ambiguous = 0u64;
+
+    let mut pairs = PairedFastqParser::open(&args.r1, &args.r2).context("Failed to open R1/R2 FASTQ")?;
+    let mut i1_parser = FastqParser::open(&args.i1).context("Failed to open I1 FASTQ")?;
+    let mut i2_parser = match &args.i2 {
+        Some(p) => Some(FastqParser::open(p).context("Failed to open I2 FASTQ")?),
+        None => None,
+    };
+
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .expect("valid progress template"),
+    );
+
+    let mut total_reads = 0u64;
+    for result in &mut pairs {
+        let (r1, r2) = result?;
+        total_reads += 1;
+        let i1 = next_index(&mut i1_parser, &r1, "I1", total_reads)?;
+        let i2 = match i2_parser.as_mut() {
+            Some(p) => Some(next_index(p, &r1, "I2", total_reads)?),
+            None => None,
+        };
+
+        if total_reads % 100000 == 0 {
+            progress.set_message(format!("Demultiplexed {} reads", total_reads));
+        }
+
+        let out = match demuxer.assign(&i1.seq, i2.as_ref().map(|r| r.seq.as_slice())) {
+            DemuxMatch::Sample(row, _) => &mut outputs[row_output[row]],
+            DemuxMatch::Ambiguous => {
+                ambiguous += 1;
+                &mut undetermined
+            }
+            DemuxMatch::Undetermined => &mut undetermined,
+        };
+        out.r1.write_record(&r1)?;
+        out.r2.write_record(&r2)?;
+        out.reads += 1;
+    }
+
+    // Index reads left over after R1/R2 end are out of sync too
+    let leftover = [("I1", Some(&mut i1_parser)), ("I2", i2_parser.as_mut())];
+    for (name, parser) in leftover {
+        if parser.is_some_and(|p| p.next().is_some()) {
+            return Err(Error::FastqParse(format!(
+                "{} has more records than R1/R2 ({} pairs)",
+                name, total_reads
+            ))
+            .into());
+        }
+    }
+
+    let sample_reads = outputs
+        .into_iter()
+        .map(SampleOutput::finish)
+        .collect::<Result<Vec<_>>>()?;
+    let undetermined_reads = undetermined.finish()?;
+
+    progress.finish_with_message(format!("Done! Demultiplexed {} reads", total_reads));
+
+    let mut summary = serde_json::Map::new();
+    println!("\n=== Demultiplexing Summary ===");
+    for (name, &reads) in names.iter().zip(&sample_reads) {
+        println!("{:<20} {:>12} ({:.1}%)",
+            name,
+            reads,
+            reads as f64 / total_reads.max(1) as f64 * 100.0
+        );
+        summary.insert(name.clone(), reads.into());
+    }
+    println!("{:<20} {:>12} ({:.1}%, {} ambiguous)",
+        "Undetermined",
+        undetermined_reads,
+        undetermined_reads as f64 / total_reads.max(1) as f64 * 100.0,
+        ambiguous
+    );
+    summary.insert("Undetermined".to_string(), undetermined_reads.into());
+
+    let json = serde_json::json!({
+        "total_reads": total_reads,
+        "ambiguous_reads": ambiguous,
+        "reads_per_sample": summary,
+    });
+    sparc_core::atomic::write(
+        args.output.join("demux_summary.json"),
+        serde_json::to_string_pretty(&json)?,
+    )?;
+
+    Ok(())
+}
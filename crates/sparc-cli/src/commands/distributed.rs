@@ -99,18 +99,14 @@ fn process_shard(args: &DistributedArgs, shard_index: usize) -> Result<()> {
     let shard_output = args.output.join(format!("shard_{}", shard_index));
     std::fs::create_dir_all(&shard_output)?;
 
-    use sparc_core::{
-        barcode::{BarcodeCorrector, Whitelist},
-        count::GeneCounter,
-        fastq::FastqParser,
-    };
-
-    let whitelist = Whitelist::from_file(&args.whitelist)?;
-    let corrector = BarcodeCorrector::new(whitelist, 1);
+    use sparc_core::{count::GeneCounter, fastq::FastqParser};
+
     let protocol = super::pipeline::get_protocol(&args.protocol)?;
+    let corrector =
+        super::extract::Corrector::load(&*protocol, Some(args.whitelist.as_path()), None, 1)?;
 
     let mut parser = FastqParser::open(&args.r1)?;
-    let mut counter = GeneCounter::new();
+    let mut counter = GeneCounter::with_resources(sparc_core::resources::global());
     let mut record_num = 0u64;
     let mut processed = 0u64;
 
@@ -133,7 +129,7 @@ fn process_shard(args: &DistributedArgs, shard_index: usize) -> Result<()> {
         record_num += 1;
     }
 
-    let matrix = counter.build();
+    let matrix = counter.try_build()?;
     matrix.write_mtx(shard_output.join("matrix.mtx"))?;
     matrix.write_barcodes(shard_output.join("barcodes.tsv"))?;
     matrix.write_genes(shard_output.join("genes.tsv"))?;
@@ -152,7 +148,7 @@ fn merge_shards(args: &DistributedArgs) -> Result<()> {
 
     println!("=== Merging {} shards ===\n", args.shards);
 
-    let mut counter = GeneCounter::new();
+    let mut counter = GeneCounter::with_resources(sparc_core::resources::global());
     let mut shards_found = 0usize;
     let mut shards_missing = Vec::new();
 
@@ -266,7 +262,7 @@ fn merge_shards(args: &DistributedArgs) -> Result<()> {
     let merged_dir = args.output.join("merged");
     std::fs::create_dir_all(&merged_dir)?;
 
-    let matrix = counter.build();
+    let matrix = counter.try_build()?;
     matrix.write_mtx(merged_dir.join("matrix.mtx"))?;
     matrix.write_barcodes(merged_dir.join("barcodes.tsv"))?;
     matrix.write_genes(merged_dir.join("genes.tsv"))?;
@@ -0,0 +1,152 @@
+//! Resource estimation for `--dry-run`
+//!
+//! Read counts are extrapolated from a sample of each input; memory and disk
+//! figures are heuristics meant for sizing a job request, not guarantees.
+
+use anyhow::{Context, Result};
+use sparc_core::count::COUNT_ENTRY_BYTES;
+use sparc_core::qc::stats::{file_stats, FileStats};
+use sparc_core::remote;
+use std::path::Path;
+
+/// Records sampled per input to extrapolate read counts
+const SAMPLE_RECORDS: u64 = 100_000;
+
+/// Approximate bytes per non-zero entry in `matrix.mtx`
+const MTX_ENTRY_BYTES: u64 = 20;
+
+/// What a run would read, use, and execute
+#[derive(Default)]
+pub(crate) struct DryRunPlan {
+    inputs: Vec<FileStats>,
+    remote_inputs: Vec<String>,
+    /// Peak memory per sequential stage
+    memory: Vec<(String, u64)>,
+    /// Bytes written per output
+    disk: Vec<(String, u64)>,
+    commands: Vec<String>,
+    notes: Vec<String>,
+}
+
+impl DryRunPlan {
+    /// Sample an input and return its estimated record count (0 for remote inputs)
+    pub fn add_input(&mut self, path: &Path) -> Result<u64> {
+        if remote::is_remote(path) {
+            self.remote_inputs.push(path.display().to_string());
+            return Ok(0);
+        }
+        let stats = file_stats(path, SAMPLE_RECORDS)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        let records = stats.estimated_records;
+        self.inputs.push(stats);
+        Ok(records)
+    }
+
+    /// Total size of the local inputs
+    pub fn input_bytes(&self) -> u64 {
+        self.inputs.iter().map(|s| s.file_size).sum()
+    }
+
+    pub fn memory(&mut self, stage: &str, bytes: u64) {
+        self.memory.push((stage.to_string(), bytes));
+    }
+
+    pub fn disk(&mut self, output: &str, bytes: u64) {
+        self.disk.push((output.to_string(), bytes));
+    }
+
+    pub fn commands(&mut self, commands: Vec<String>) {
+        self.commands.extend(commands);
+    }
+
+    pub fn note(&mut self, note: impl Into<String>) {
+        self.notes.push(note.into());
+    }
+
+    /// Stages run one after another, so the peak is the largest stage
+    pub fn peak_memory(&self) -> u64 {
+        self.memory.iter().map(|(_, b)| *b).max().unwrap_or(0)
+    }
+
+    pub fn total_disk(&self) -> u64 {
+        self.disk.iter().map(|(_, b)| *b).sum()
+    }
+
+    pub fn print(&self, command: &str) {
+        println!("=== Dry Run: sparc {} ===\n", command);
+
+        println!("Inputs:");
+        for stats in &self.inputs {
+            println!(
+                "  {}  {}  {:?}  {} records{}",
+                stats.path,
+                format_bytes(stats.file_size),
+                stats.compression,
+                stats.estimated_records,
+                if stats.complete { "" } else { " (estimated)" }
+            );
+        }
+        for uri in &self.remote_inputs {
+            println!("  {}  (remote, not sampled)", uri);
+        }
+
+        println!("\nEstimated peak memory: {}", format_bytes(self.peak_memory()));
+        for (stage, bytes) in &self.memory {
+            println!("  {:<36} {}", stage, format_bytes(*bytes));
+        }
+
+        println!("\nEstimated disk usage: {}", format_bytes(self.total_disk()));
+        for (output, bytes) in &self.disk {
+            println!("  {:<36} {}", output, format_bytes(*bytes));
+        }
+
+        println!("\nExternal commands:");
+        if self.commands.is_empty() {
+            println!("  (none)");
+        }
+        for command in &self.commands {
+            println!("  {}", command);
+        }
+
+        for note in &self.notes {
+            println!("\nNote: {}", note);
+        }
+        println!("\nDry run only; no files were written.");
+    }
+}
+
+/// Count table memory for `entries` non-zero entries, capped by `--max-memory`
+/// since the counter spills to disk beyond it
+pub(crate) fn counter_memory(entries: u64) -> u64 {
+    let bytes = entries.saturating_mul(COUNT_ENTRY_BYTES);
+    match sparc_core::resources::global().max_memory {
+        Some(budget) => bytes.min(budget),
+        None => bytes,
+    }
+}
+
+/// Size of `matrix.mtx` with `entries` non-zero entries
+pub(crate) fn mtx_bytes(entries: u64) -> u64 {
+    entries.saturating_mul(MTX_ENTRY_BYTES)
+}
+
+/// Total size of the files under a directory (or of a single file)
+pub(crate) fn path_size(path: &Path) -> u64 {
+    if path.is_file() {
+        return std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| path_size(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+    let b = bytes as f64;
+    if b >= GB {
+        format!("{:.1} GB", b / GB)
+    } else {
+        format!("{:.1} MB", b / MB)
+    }
+}
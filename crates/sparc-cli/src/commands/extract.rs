@@ -2,33 +2,59 @@
 
 use anyhow::{Context, Result};
 use clap::Args;
-use indicatif::{ProgressBar, ProgressStyle};
 use sparc_core::{
-    barcode::{BarcodeCorrector, BarcodeMatch, Whitelist},
-    fastq::FastqParser,
-    protocols::{DropSeq, InDrop, Protocol, SciRNA, SmartSeq2, TenX3Prime, TenX5Prime},
+    atomic::AtomicFile,
+    bam::{unaligned_record, AtomicBamWriter, BamWriter, RawBamRecord, UnalignedPairReader},
+    barcode::{BarcodeCorrector, BarcodeMatch, CorrectionReport, TieredCorrector, Whitelist},
+    fastq::{
+        encode_block, AnnotationStyle, FastqRecord, FastqRouter, PairedFastqParser, ParsePolicy,
+        RouterConfig, Trimmer,
+    },
+    metrics::{self, Counter, Metrics},
+    perf,
+    protocols::Protocol,
+    qc::{complexity::DEFAULT_MIN_ENTROPY, ComplexityFilter, ComplexityStats},
+    streaming::ChunkPipeline,
 };
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-#[derive(Args)]
+use super::samples::{parse_sample_sheet, run_samples, SampleMetrics};
+
+#[derive(Args, Clone)]
 pub struct ExtractArgs {
     /// Input R1 FASTQ file (barcode/UMI read)
-    #[arg(short = '1', long)]
-    r1: PathBuf,
+    #[arg(short = '1', long, required_unless_present_any = ["samples", "bam"])]
+    r1: Option<PathBuf>,
 
     /// Input R2 FASTQ file (cDNA read)
-    #[arg(short = '2', long)]
-    r2: PathBuf,
+    #[arg(short = '2', long, required_unless_present_any = ["samples", "bam"])]
+    r2: Option<PathBuf>,
+
+    /// Unaligned BAM/CRAM of paired raw reads, instead of -1/-2
+    #[arg(long, conflicts_with_all = ["r1", "r2", "samples"])]
+    bam: Option<PathBuf>,
 
-    /// Output directory
+    /// Malformed FASTQ records: strict (fail) or lenient (skip the pair and count it)
+    #[arg(long, default_value = "strict")]
+    parse_policy: ParsePolicy,
+
+    /// Output directory (one subdirectory per sample with --samples)
     #[arg(short, long)]
     output: PathBuf,
 
-    /// Barcode whitelist file
+    /// Barcode whitelist file (optional for hive-v1)
     #[arg(short = 'w', long)]
-    whitelist: PathBuf,
+    whitelist: Option<PathBuf>,
+
+    /// Binary whitelist index cache: loaded when newer than the whitelist,
+    /// otherwise built and written here for the next run
+    #[arg(long)]
+    whitelist_index: Option<PathBuf>,
 
-    /// Protocol (10x-3prime-v3, 10x-3prime-v2, 10x-5prime-v2, drop-seq, indrop, sci-rna-seq, smart-seq2)
+    /// Protocol (10x-3prime-v3, 10x-3prime-v2, 10x-5prime-v2, drop-seq, hive-v1, indrop,
+    /// pipseq-v3, sci-rna-seq, smart-seq2)
     #[arg(short, long, default_value = "10x-3prime-v3")]
     protocol: String,
 
@@ -36,88 +62,520 @@ pub struct ExtractArgs {
     #[arg(long, default_value = "1")]
     max_mismatch: u32,
 
+    /// Break ties between equally distant whitelist barcodes by base quality,
+    /// preferring the candidate whose mismatch sits at a low-quality cycle
+    #[arg(long)]
+    quality_prior: bool,
+
     /// Minimum barcode quality score
     #[arg(long, default_value = "10")]
     min_barcode_qual: u8,
+
+    /// Base entropy (bits) below which a barcode or UMI is flagged as
+    /// low-complexity; homopolymers are always flagged
+    #[arg(long, default_value_t = DEFAULT_MIN_ENTROPY)]
+    min_entropy: f64,
+
+    /// Drop reads whose barcode or UMI is low-complexity instead of only counting them
+    #[arg(long)]
+    drop_low_complexity: bool,
+
+    /// How to attach CB/UMI to output reads (name: read_CB_UMI, comment: SAM-style tags)
+    #[arg(long, default_value = "name")]
+    annotate: String,
+
+    /// Trim TSO, adapters, and polyA tails from R2 before writing
+    #[arg(long)]
+    trim: bool,
+
+    /// Write an unaligned BAM (annotated_R2.bam) with CR/CY/CB/UR/UY/UB tags
+    /// instead of FASTQ
+    #[arg(long)]
+    ubam: bool,
+
+    /// Also write one R2 FASTQ per cell barcode under <output>/cells/
+    #[arg(long)]
+    split_cells: bool,
+
+    /// Maximum open per-cell files with --split-cells
+    #[arg(long, default_value = "256")]
+    max_open_files: usize,
+
+    /// CSV sample sheet (sample,r1,r2[,whitelist,protocol]) to extract many samples
+    #[arg(long, conflicts_with_all = ["r1", "r2"])]
+    samples: Option<PathBuf>,
+
+    /// Samples to process in parallel with --samples
+    #[arg(long, default_value = "1")]
+    parallel_samples: usize,
+}
+
+/// Barcode and complexity reports, kept per worker and merged after the run
+#[derive(Default)]
+struct ExtractStats {
+    /// Barcode match outcomes, with every observed correction
+    barcodes: CorrectionReport,
+    /// Homopolymer and low-entropy barcodes and UMIs
+    complexity: ComplexityStats,
+}
+
+impl ExtractStats {
+    fn merge(&mut self, other: &Self) {
+        self.barcodes.merge(&other.barcodes);
+        self.complexity.merge(&other.complexity);
+    }
+}
+
+/// One chunk of extracted reads, ready to write
+struct ExtractedChunk {
+    /// Read pairs in the chunk
+    pairs: u64,
+    /// Annotated R2 reads as a gzip member (FASTQ output)
+    block: Vec<u8>,
+    /// Unaligned records (--ubam)
+    records: Vec<RawBamRecord>,
+    /// (barcode, read) for --split-cells
+    reads: Vec<(String, FastqRecord)>,
+}
+
+/// Where annotated R2 reads go
+enum ExtractOutput {
+    /// Concatenated gzip members
+    Fastq(AtomicFile),
+    /// Unaligned BAM
+    Ubam(AtomicBamWriter),
+}
+
+/// Per-read extraction settings shared by the workers
+struct Extractor<'a> {
+    protocol: &'a dyn Protocol,
+    corrector: &'a Corrector,
+    trimmer: Option<&'a Trimmer>,
+    style: AnnotationStyle,
+    min_barcode_qual: u8,
+    quality_prior: bool,
+    complexity: ComplexityFilter,
+    drop_low_complexity: bool,
+    split_cells: bool,
+    ubam: bool,
+    /// Read pairs seen
+    reads: Arc<Counter>,
+    /// Reads dropped for being too short after trimming
+    too_short: Arc<Counter>,
+}
+
+impl Extractor<'_> {
+    /// Extract, correct, and annotate one chunk of read pairs
+    fn extract(
+        &self,
+        stats: &mut ExtractStats,
+        chunk: Vec<(FastqRecord, FastqRecord)>,
+    ) -> sparc_core::Result<ExtractedChunk> {
+        let pairs = chunk.len() as u64;
+        self.reads.add(pairs);
+        let mut reads = Vec::with_capacity(chunk.len());
+        let mut records = Vec::new();
+        let mut barcodes = Vec::new();
+        for (record, mut r2) in chunk {
+            // Extract barcode and UMI
+            let components = match self.protocol.extract_r1(&record.seq, &record.qual) {
+                Ok(c) => c,
+                Err(_) => continue,
+            };
+
+            // Check barcode quality
+            if !components.barcode_quality_ok(self.min_barcode_qual) {
+                continue;
+            }
+
+            // Flag (and optionally drop) homopolymer or low-entropy barcodes and UMIs
+            let low_barcode = self.complexity.is_low_complexity(&components.barcode);
+            let low_umi = self.complexity.is_low_complexity(&components.umi);
+            stats.complexity.record(low_barcode, low_umi);
+            if self.drop_low_complexity && (low_barcode || low_umi) {
+                stats.complexity.dropped += 1;
+                continue;
+            }
+
+            // Match barcode
+            let barcode_str = components.barcode_str();
+            let barcode_match = if self.quality_prior {
+                self.corrector
+                    .match_barcode_with_quality(&barcode_str, &components.barcode_qual)
+            } else {
+                self.corrector.match_barcode(&barcode_str)
+            };
+            stats.barcodes.record(&barcode_match);
+
+            // Tag the cDNA read with its corrected barcode and UMI
+            if let Some(barcode) = barcode_match.barcode() {
+                if let Some(trimmer) = self.trimmer {
+                    if trimmer.trim(&mut r2).length < trimmer.config().min_length {
+                        self.too_short.inc();
+                        continue;
+                    }
+                }
+                let umi = components.umi_str();
+                if self.ubam {
+                    let barcode_qual = String::from_utf8_lossy(&components.barcode_qual);
+                    let umi_qual = String::from_utf8_lossy(&components.umi_qual);
+                    records.push(unaligned_record(
+                        &r2,
+                        &[
+                            (b"CR", barcode_str.as_str()),
+                            (b"CY", &*barcode_qual),
+                            (b"CB", barcode),
+                            (b"UR", umi.as_str()),
+                            (b"UY", &*umi_qual),
+                            (b"UB", umi.as_str()),
+                        ],
+                    )?);
+                    if !self.split_cells {
+                        continue;
+                    }
+                }
+                r2.annotate(&barcode_str, barcode, &umi, self.style);
+                if self.split_cells {
+                    barcodes.push(barcode.to_string());
+                }
+                reads.push(r2);
+            }
+        }
+
+        Ok(ExtractedChunk {
+            pairs,
+            block: if self.ubam {
+                Vec::new()
+            } else {
+                encode_block(&reads, true)?
+            },
+            records,
+            reads: if self.split_cells {
+                barcodes.into_iter().zip(reads).collect()
+            } else {
+                Vec::new()
+            },
+        })
+    }
 }
 
 pub fn run(args: ExtractArgs) -> Result<()> {
-    log::info!("Loading barcode whitelist from {:?}", args.whitelist);
-    let whitelist = Whitelist::from_file(&args.whitelist)
+    let Some(sheet) = &args.samples else {
+        return run_sample(&args).map(|_| ());
+    };
+    let samples = parse_sample_sheet(sheet)?;
+    run_samples(&samples, args.parallel_samples, &args.output, |sample, output| {
+        let mut sample_args = args.clone();
+        sample_args.samples = None;
+        sample_args.r1 = sample.r1.clone();
+        sample_args.r2 = sample.r2.clone();
+        sample_args.whitelist = sample.whitelist.clone().or(sample_args.whitelist);
+        sample_args.protocol = sample.protocol.clone().unwrap_or(sample_args.protocol);
+        sample_args.output = output;
+        run_sample(&sample_args)
+    })
+}
+
+/// Whitelist matching for whole barcodes, or tier by tier for protocols
+/// with combinatorial barcodes (PIPseq)
+pub(crate) enum Corrector {
+    Whole(BarcodeCorrector),
+    Tiered(TieredCorrector),
+    /// No whitelist: any barcode without an `N` is accepted as is
+    Observed,
+}
+
+impl Corrector {
+    /// Load the corrector `protocol` needs; tiered protocols take a
+    /// `<tier> <barcode>` whitelist, and protocols without a fixed barcode
+    /// list may go without one
+    pub(crate) fn load(
+        protocol: &dyn Protocol,
+        whitelist_path: Option<&Path>,
+        index_path: Option<&PathBuf>,
+        max_mismatch: u32,
+    ) -> Result<Self> {
+        let Some(whitelist_path) = whitelist_path else {
+            anyhow::ensure!(
+                !protocol.requires_whitelist(),
+                "No whitelist given (--whitelist or sample sheet whitelist column)"
+            );
+            log::info!("No whitelist given; keeping {} barcodes as sequenced", protocol.name());
+            return Ok(Corrector::Observed);
+        };
+        let Some(tiers) = protocol.barcode_tiers() else {
+            return load_corrector(whitelist_path, index_path, max_mismatch).map(Corrector::Whole);
+        };
+        if index_path.is_some() {
+            log::warn!("--whitelist-index is not used for tiered barcodes");
+        }
+        log::info!("Loading tiered barcode whitelist from {:?}", whitelist_path);
+        let corrector = TieredCorrector::from_file(whitelist_path, max_mismatch)
+            .context("Failed to load tiered barcode whitelist")?;
+        anyhow::ensure!(
+            corrector.tier_lens() == tiers,
+            "{} expects barcode tiers of length {:?}, but the whitelist has {:?}",
+            protocol.name(),
+            tiers,
+            corrector.tier_lens()
+        );
+        Ok(Corrector::Tiered(corrector))
+    }
+
+    pub(crate) fn match_barcode(&self, barcode: &str) -> BarcodeMatch {
+        match self {
+            Corrector::Whole(corrector) => corrector.match_barcode(barcode),
+            Corrector::Tiered(corrector) => corrector.match_barcode(barcode),
+            Corrector::Observed => observed(barcode),
+        }
+    }
+
+    fn match_barcode_with_quality(&self, barcode: &str, qual: &[u8]) -> BarcodeMatch {
+        match self {
+            Corrector::Whole(corrector) => corrector.match_barcode_with_quality(barcode, qual),
+            Corrector::Tiered(corrector) => corrector.match_barcode_with_quality(barcode, qual),
+            Corrector::Observed => observed(barcode),
+        }
+    }
+}
+
+fn observed(barcode: &str) -> BarcodeMatch {
+    if barcode.bytes().all(|b| matches!(b, b'A' | b'C' | b'G' | b'T')) {
+        BarcodeMatch::Exact(barcode.to_string())
+    } else {
+        BarcodeMatch::NoMatch(barcode.to_string())
+    }
+}
+
+/// Build the barcode corrector, going through the whitelist index cache when
+/// one is given
+pub(crate) fn load_corrector(
+    whitelist_path: &Path,
+    index_path: Option<&PathBuf>,
+    max_mismatch: u32,
+) -> Result<BarcodeCorrector> {
+    if let Some(index_path) = index_path {
+        if is_newer(index_path, whitelist_path) {
+            log::info!("Loading whitelist index from {:?}", index_path);
+            let whitelist = Whitelist::load_index(index_path)
+                .with_context(|| format!("Failed to load whitelist index {:?}", index_path))?;
+            return Ok(BarcodeCorrector::new(whitelist, max_mismatch));
+        }
+    }
+
+    log::info!("Loading barcode whitelist from {:?}", whitelist_path);
+    let whitelist = Whitelist::from_file(whitelist_path)
         .context("Failed to load barcode whitelist")?;
     log::info!("Loaded {} barcodes", whitelist.len());
+    let corrector = BarcodeCorrector::new(whitelist, max_mismatch);
 
-    let corrector = BarcodeCorrector::new(whitelist, args.max_mismatch);
-
-    let protocol: Box<dyn Protocol> = match args.protocol.as_str() {
-        "10x-3prime-v3" => Box::new(TenX3Prime::v3()),
-        "10x-3prime-v2" => Box::new(TenX3Prime::v2()),
-        "10x-5prime-v2" => Box::new(TenX5Prime::v2()),
-        "drop-seq" => Box::new(DropSeq::new()),
-        "indrop" => Box::new(InDrop::new()),
-        "sci-rna-seq" => Box::new(SciRNA::new()),
-        "smart-seq2" => Box::new(SmartSeq2::new("sample".to_string())),
-        _ => anyhow::bail!("Unknown protocol: {}", args.protocol),
-    };
+    if let Some(index_path) = index_path {
+        // Write beside the target and rename so a concurrent run never sees
+        // a partial index
+        let tmp = index_path.with_extension("tmp");
+        match corrector.whitelist().save_index(&tmp) {
+            Ok(()) => {
+                std::fs::rename(&tmp, index_path)
+                    .with_context(|| format!("Failed to write {:?}", index_path))?;
+                log::info!("Saved whitelist index to {:?}", index_path);
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&tmp);
+                log::warn!("Not caching whitelist index: {}", e);
+            }
+        }
+    }
+    Ok(corrector)
+}
+
+/// Whether `path` exists and was modified after `than`
+fn is_newer(path: &Path, than: &Path) -> bool {
+    let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(path), modified(than)) {
+        (Some(a), Some(b)) => a >= b,
+        _ => false,
+    }
+}
+
+/// Read pairs to extract, from --bam or R1/R2 FASTQ
+enum ReadPairs {
+    Fastq(PairedFastqParser),
+    Bam(UnalignedPairReader),
+}
+
+impl ReadPairs {
+    /// Malformed records skipped, and well-formed mates dropped with them
+    fn skipped(&self) -> (u64, u64) {
+        match self {
+            ReadPairs::Fastq(pairs) => (pairs.skipped(), pairs.orphans()),
+            ReadPairs::Bam(_) => (0, 0),
+        }
+    }
+}
+
+impl Iterator for ReadPairs {
+    type Item = sparc_core::Result<(FastqRecord, FastqRecord)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            ReadPairs::Fastq(pairs) => pairs.next(),
+            ReadPairs::Bam(pairs) => pairs.next(),
+        }
+    }
+}
+
+fn open_pairs(args: &ExtractArgs) -> Result<ReadPairs> {
+    if let Some(bam) = &args.bam {
+        let pairs = UnalignedPairReader::open(bam)
+            .with_context(|| format!("Failed to open unaligned reads {:?}", bam))?;
+        return Ok(ReadPairs::Bam(pairs));
+    }
+    let r1 = args.r1.as_ref().context("No R1 FASTQ given (--r1 or sample sheet r1 column)")?;
+    let r2 = args.r2.as_ref().context("No R2 FASTQ given (--r2 or sample sheet r2 column)")?;
+    // Mates must share a read name
+    let pairs = PairedFastqParser::open(r1, r2)
+        .context("Failed to open R1/R2 FASTQ")?
+        .check_names(true)
+        .with_policy(args.parse_policy);
+    Ok(ReadPairs::Fastq(pairs))
+}
+
+fn run_sample(args: &ExtractArgs) -> Result<SampleMetrics> {
+    let protocol = super::pipeline::get_protocol(&args.protocol)?;
 
     log::info!("Using protocol: {} {}", protocol.name(), protocol.version());
 
+    let stage = perf::stage("load_whitelist");
+    let corrector = Corrector::load(
+        &*protocol,
+        args.whitelist.as_deref(),
+        args.whitelist_index.as_ref(),
+        args.max_mismatch,
+    )?;
+    stage.finish();
+
+    let style = AnnotationStyle::from_name(&args.annotate)
+        .with_context(|| format!("Unknown annotation style: {}", args.annotate))?;
+
     // Create output directory
     std::fs::create_dir_all(&args.output)?;
 
-    // Open input files
-    let mut r1_parser = FastqParser::open(&args.r1)
-        .context("Failed to open R1 FASTQ")?;
+    let mut pairs = open_pairs(args)?;
 
-    let progress = ProgressBar::new_spinner();
-    progress.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed_precise}] {msg}")
-            .expect("valid progress template"),
-    );
+    let (output_path, mut output) = if args.ubam {
+        let path = args.output.join("annotated_R2.bam");
+        let mut writer = AtomicBamWriter::new(&path, &BamWriter::create_default_header())
+            .context("Failed to create annotated R2 BAM")?;
+        let threads = sparc_core::resources::global().threads;
+        writer.set_threads(threads.compress_or(threads.workers()))?;
+        (path, ExtractOutput::Ubam(writer))
+    } else {
+        let path = args.output.join("annotated_R2.fastq.gz");
+        let file = AtomicFile::create(&path).context("Failed to create annotated R2 output")?;
+        (path, ExtractOutput::Fastq(file))
+    };
 
-    let mut total_reads = 0u64;
-    let mut valid_barcode = 0u64;
-    let mut corrected_barcode = 0u64;
-
-    // Process reads
-    for result in &mut r1_parser {
-        let record = result?;
-        total_reads += 1;
-
-        if total_reads % 100000 == 0 {
-            progress.set_message(format!(
-                "Processed {} reads, {} valid barcodes ({:.1}%)",
-                total_reads,
-                valid_barcode,
-                valid_barcode as f64 / total_reads as f64 * 100.0
-            ));
-        }
+    // An unaligned BAM holds both mates of a pair as separate records
+    let progress = match &args.bam {
+        Some(bam) => super::progress::input_progress(Some(bam), 2),
+        None => super::progress::input_progress(args.r1.as_deref(), 1),
+    };
+
+    let trimmer = args.trim.then(Trimmer::default);
 
-        // Extract barcode and UMI
-        let components = match protocol.extract_r1(&record.seq, &record.qual) {
-            Ok(c) => c,
-            Err(_) => continue,
+    let mut router = if args.split_cells {
+        let config = RouterConfig {
+            max_open: args.max_open_files,
+            ..Default::default()
         };
+        Some(FastqRouter::new(args.output.join("cells"), config)?)
+    } else {
+        None
+    };
 
-        // Check barcode quality
-        if !components.barcode_quality_ok(args.min_barcode_qual) {
-            continue;
-        }
+    // Workers extract, correct, and gzip their chunks; this thread writes the
+    // compressed blocks (and per-cell reads) in input order
+    let threads = sparc_core::resources::global().threads.workers();
+    log::info!("Extracting with {} worker threads", threads);
+    let counters = Metrics::new();
+    let extractor = Extractor {
+        protocol: &*protocol,
+        corrector: &corrector,
+        trimmer: trimmer.as_ref(),
+        style,
+        min_barcode_qual: args.min_barcode_qual,
+        quality_prior: args.quality_prior,
+        complexity: ComplexityFilter::new(args.min_entropy),
+        drop_low_complexity: args.drop_low_complexity,
+        split_cells: args.split_cells,
+        ubam: args.ubam,
+        reads: counters.counter("extract", "reads"),
+        too_short: counters.counter("extract", "too_short"),
+    };
 
-        // Match barcode
-        let barcode_str = components.barcode_str();
-        match corrector.match_barcode(&barcode_str) {
-            BarcodeMatch::Exact(_) => {
-                valid_barcode += 1;
-            }
-            BarcodeMatch::Corrected(_, _, _) => {
-                valid_barcode += 1;
-                corrected_barcode += 1;
-            }
-            BarcodeMatch::NoMatch(_) => {}
-        }
+    let stage = perf::stage("extract");
+    let mut processed = 0u64;
+    let (_, worker_stats) = ChunkPipeline::new(threads)
+        .run(
+            &mut pairs,
+            ExtractStats::default,
+            |stats, chunk| extractor.extract(stats, chunk),
+            |chunk| {
+                match &mut output {
+                    ExtractOutput::Fastq(writer) => writer.write_all(&chunk.block)?,
+                    ExtractOutput::Ubam(writer) => {
+                        for record in &chunk.records {
+                            writer.write(record)?;
+                        }
+                    }
+                }
+                if let Some(router) = router.as_mut() {
+                    for (barcode, read) in &chunk.reads {
+                        router.write(barcode, read)?;
+                    }
+                }
+                let before = processed;
+                processed += chunk.pairs;
+                super::progress::set_position(&progress, processed);
+                if processed / 100_000 != before / 100_000 {
+                    progress.set_message(format!("Processed {} reads", processed));
+                }
+                Ok(())
+            },
+        )
+        .context("Extraction failed")?;
+    match output {
+        ExtractOutput::Fastq(file) => file.commit()?,
+        ExtractOutput::Ubam(writer) => writer.commit()?,
     }
+    stage.finish();
+
+    let stats = worker_stats.iter().fold(ExtractStats::default(), |mut acc, s| {
+        acc.merge(s);
+        acc
+    });
+    let ExtractStats { barcodes, complexity } = stats;
+    let (skipped, orphans) = pairs.skipped();
+    let summary = barcodes.summary();
+    let (valid_barcode, corrected_barcode) = (summary.exact + summary.corrected, summary.corrected);
+    counters.counter("extract", "valid_barcodes").add(valid_barcode);
+    counters.counter("extract", "corrected_barcodes").add(corrected_barcode);
+    counters.counter("extract", "unmatched_barcodes").add(summary.no_match);
+    counters.counter("extract", "low_complexity_dropped").add(complexity.dropped);
+    counters.counter("extract", "malformed_skipped").add(skipped);
+    metrics::global().merge(&counters);
+    let (total_reads, too_short) = (extractor.reads.get(), extractor.too_short.get());
+
+    let corrections_path = args.output.join("barcode_corrections.tsv");
+    barcodes.write_tsv(&corrections_path)?;
+    let summary_path = args.output.join("barcode_summary.json");
+    sparc_core::atomic::write(&summary_path, serde_json::to_string_pretty(&summary)?)?;
+    let complexity_path = args.output.join("low_complexity.json");
+    sparc_core::atomic::write(&complexity_path, serde_json::to_string_pretty(&complexity)?)?;
+    let cells_written = match router {
+        Some(router) => Some(router.finish()?.len()),
+        None => None,
+    };
 
     progress.finish_with_message(format!(
         "Done! Processed {} reads",
@@ -135,6 +593,38 @@ pub fn run(args: ExtractArgs) -> Result<()> {
         corrected_barcode,
         corrected_barcode as f64 / total_reads.max(1) as f64 * 100.0
     );
+    if trimmer.is_some() {
+        println!("Too short after trim: {}", too_short);
+    }
+    println!("Unmatched barcodes: {}", summary.no_match);
+    if args.parse_policy == ParsePolicy::Lenient {
+        println!("Malformed records:  {} skipped ({} mates dropped)", skipped, orphans);
+    }
+    println!(
+        "Low-complexity:     {} barcodes, {} UMIs{}",
+        complexity.low_complexity_barcodes,
+        complexity.low_complexity_umis,
+        if args.drop_low_complexity {
+            format!(" ({} reads dropped)", complexity.dropped)
+        } else {
+            String::new()
+        }
+    );
+    println!("\nAnnotated R2 written to {:?}", output_path);
+    println!("Barcode corrections: {:?}, {:?}", corrections_path, summary_path);
+    println!("Low-complexity stats: {:?}", complexity_path);
+    if let Some(n) = cells_written {
+        println!("Per-cell FASTQs:   {} files in {:?}", n, args.output.join("cells"));
+    }
 
-    Ok(())
+    Ok(vec![
+        ("total_reads", total_reads as f64),
+        ("valid_barcode_pct", valid_barcode as f64 / total_reads.max(1) as f64 * 100.0),
+        ("corrected_barcode_pct", corrected_barcode as f64 / total_reads.max(1) as f64 * 100.0),
+        (
+            "low_complexity_barcode_pct",
+            complexity.low_complexity_barcodes as f64 / total_reads.max(1) as f64 * 100.0,
+        ),
+        ("malformed_records_skipped", skipped as f64),
+    ])
 }
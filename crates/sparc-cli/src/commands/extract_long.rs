@@ -0,0 +1,114 @@
+//! Extract barcodes and UMIs from long reads (PacBio, ONT, MAS-Seq arrays)
+
+use anyhow::{Context, Result};
+use clap::Args;
+use indicatif::{ProgressBar, ProgressStyle};
+use sparc_core::{
+    barcode::{EditCorrector, Whitelist},
+    fastq::{AnnotationStyle, FastqParser, FastqWriter},
+    longread::{LongReadExtractor, LongReadLayout, LongReadStats, TENX_READ1},
+};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct ExtractLongArgs {
+    /// Input long-read FASTQ (CCS/HiFi or basecalled ONT reads)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output FASTQ of annotated cDNA inserts (.gz for gzip compression)
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Barcode whitelist file (cells called from short reads work best)
+    #[arg(short = 'w', long)]
+    whitelist: PathBuf,
+
+    /// Adapter immediately 5' of the barcode [default: 10x Read 1]
+    #[arg(long)]
+    adapter: Option<String>,
+
+    /// Maximum edits when locating the adapter
+    #[arg(long, default_value = "4")]
+    max_adapter_errors: u32,
+
+    /// UMI length
+    #[arg(long, default_value = "12")]
+    umi_len: usize,
+
+    /// MAS-Seq segmentation adapter to split arrays at (repeatable)
+    #[arg(long)]
+    array_adapter: Vec<String>,
+
+    /// Maximum edits when locating an array adapter
+    #[arg(long, default_value = "2")]
+    max_array_errors: u32,
+
+    /// Drop molecules whose cDNA insert is shorter than this
+    #[arg(long, default_value = "50")]
+    min_insert_len: usize,
+
+    /// How to attach CB/UMI to output reads (name: read_CB_UMI, comment: SAM-style tags)
+    #[arg(long, default_value = "name")]
+    annotate: String,
+}
+
+pub fn run(args: ExtractLongArgs) -> Result<()> {
+    let style = AnnotationStyle::from_name(&args.annotate)
+        .with_context(|| format!("Unknown annotation style '{}'", args.annotate))?;
+    let whitelist = Whitelist::from_file(&args.whitelist)
+        .with_context(|| format!("Failed to load whitelist {:?}", args.whitelist))?;
+    let corrector = EditCorrector::new(&whitelist)?;
+    let layout = LongReadLayout {
+        adapter: args
+            .adapter
+            .as_ref()
+            .map_or_else(|| TENX_READ1.to_vec(), |a| a.to_ascii_uppercase().into_bytes()),
+        max_adapter_errors: args.max_adapter_errors,
+        umi_len: args.umi_len,
+        array_adapters: args
+            .array_adapter
+            .iter()
+            .map(|a| a.to_ascii_uppercase().into_bytes())
+            .collect(),
+        max_array_errors: args.max_array_errors,
+        min_insert_len: args.min_insert_len,
+    };
+    let extractor = LongReadExtractor::new(layout, corrector);
+
+    let parser = FastqParser::open(&args.input).context("Failed to open input FASTQ")?;
+    let mut writer = FastqWriter::new(&args.output).context("Failed to create output FASTQ")?;
+
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .expect("valid progress template"),
+    );
+
+    let mut stats = LongReadStats::default();
+    for result in parser {
+        let read = result?;
+        writer.write_records(&extractor.extract(&read, style, &mut stats))?;
+        if stats.reads % 10000 == 0 {
+            progress.set_message(format!("Processed {} reads", stats.reads));
+        }
+    }
+    writer.finish()?;
+    progress.finish_with_message(format!("Done! Processed {} reads", stats.reads));
+
+    let segments = stats.segments.max(1) as f64;
+    let pct = |n: u64| n as f64 / segments * 100.0;
+    println!("\n=== Long-Read Extraction Summary ===");
+    println!("Reads:                {}", stats.reads);
+    println!("Molecules:            {}", stats.segments);
+    println!("Extracted:            {} ({:.1}%)", stats.extracted, pct(stats.extracted));
+    println!("  Barcode corrected:  {}", stats.corrected);
+    println!("  Reverse strand:     {}", stats.reverse);
+    println!("No adapter:           {} ({:.1}%)", stats.no_adapter, pct(stats.no_adapter));
+    println!("No barcode match:     {} ({:.1}%)", stats.no_barcode, pct(stats.no_barcode));
+    println!("Too short:            {} ({:.1}%)", stats.too_short, pct(stats.too_short));
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! Call cells and apply QC thresholds to write a filtered count matrix
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::count::CountMatrix;
+use sparc_core::qc::{CellCalling, CellFilter, FilterReason};
+use std::path::PathBuf;
+
+use super::ambient::read_barcodes;
+
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Input matrix directory (raw, or already cell-called)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output directory for the filtered matrix and filter_status.tsv
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Minimum genes per cell
+    #[arg(long, default_value = "200")]
+    min_genes: u64,
+
+    /// Maximum genes per cell
+    #[arg(long, default_value = "10000")]
+    max_genes: u64,
+
+    /// Maximum mitochondrial percentage
+    #[arg(long, default_value = "20.0")]
+    max_mito: f64,
+
+    /// Call about this many cells by UMI count (Cell Ranger order-of-magnitude rule)
+    #[arg(long, conflicts_with_all = ["force_cells", "min_umis"])]
+    expect_cells: Option<usize>,
+
+    /// Call exactly this many cells, those with the most UMIs
+    #[arg(long, conflicts_with = "min_umis")]
+    force_cells: Option<usize>,
+
+    /// Call barcodes with at least this many UMIs as cells
+    #[arg(long)]
+    min_umis: Option<u64>,
+
+    /// Doublet barcodes, one per line, to remove from the called cells
+    #[arg(long)]
+    doublets: Option<PathBuf>,
+}
+
+pub fn run(args: FilterArgs) -> Result<()> {
+    let matrix = CountMatrix::read_mtx(&args.input)
+        .with_context(|| format!("Failed to read matrix {:?}", args.input))?;
+
+    let calling = match (args.expect_cells, args.force_cells, args.min_umis) {
+        (Some(n), _, _) => CellCalling::Expected(n),
+        (_, Some(n), _) => CellCalling::Forced(n),
+        (_, _, Some(min)) => CellCalling::MinUmis(min),
+        _ => CellCalling::All,
+    };
+    let filter = CellFilter {
+        calling,
+        min_umis: 0,
+        min_genes: args.min_genes,
+        max_genes: args.max_genes,
+        max_mito_percent: args.max_mito,
+    };
+    let doublets = match &args.doublets {
+        Some(path) => read_barcodes(path)?,
+        None => Vec::new(),
+    };
+    let result = filter.apply(&matrix, &doublets);
+    let kept = result.kept();
+    if kept.is_empty() {
+        log::warn!("No barcodes pass the filters; the filtered matrix is empty");
+    }
+
+    std::fs::create_dir_all(&args.output)?;
+    let filtered = matrix.subset_cols(&kept);
+    filtered.write_mtx(args.output.join("matrix.mtx"))?;
+    filtered.write_barcodes(args.output.join("barcodes.tsv"))?;
+    // Feature IDs, symbols, types, and gene metadata carry over unchanged
+    let feature_types = CountMatrix::read_feature_types(&args.input)?;
+    filtered.write_features_typed(args.output.join("features.tsv"), &feature_types)?;
+    result.write_status(&matrix, args.output.join("filter_status.tsv"))?;
+
+    let total = matrix.n_cols.max(1) as f64;
+    let pct = |n: usize| n as f64 / total * 100.0;
+    println!("\n=== Cell Filter Summary ===");
+    println!("Barcodes:            {}", matrix.n_cols);
+    for (label, reason) in [
+        ("Not called:          ", FilterReason::NotCalled),
+        ("Too few genes:       ", FilterReason::LowGenes),
+        ("Too many genes:      ", FilterReason::HighGenes),
+        ("High mito:           ", FilterReason::HighMito),
+        ("Doublets:            ", FilterReason::Doublet),
+    ] {
+        let n = result.count(reason);
+        println!("{}{} ({:.1}%)", label, n, pct(n));
+    }
+    println!("Cells kept:          {} ({:.1}%)", kept.len(), pct(kept.len()));
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! Assign cells to pooled donors from SNP alleles in an aligned BAM
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::genotype::{
+    demux_donors, donor_name, pileup, DonorDemuxConfig, PileupConfig, SnpSet,
+};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct GenotypeDemuxArgs {
+    /// Aligned BAM with CB (and UB) tags
+    #[arg(short, long)]
+    bam: PathBuf,
+
+    /// VCF of common SNPs (plain or .gz); only biallelic SNVs are used
+    #[arg(long)]
+    vcf: PathBuf,
+
+    /// Output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Number of pooled donors
+    #[arg(short = 'n', long)]
+    donors: usize,
+
+    /// Cell barcodes to assign, one per line (e.g. filtered barcodes.tsv)
+    #[arg(long)]
+    cells: Option<PathBuf>,
+
+    /// Minimum mapping quality
+    #[arg(long, default_value = "20")]
+    min_mapq: u8,
+
+    /// Minimum base quality at the SNP
+    #[arg(long, default_value = "13")]
+    min_base_qual: u8,
+
+    /// Prior probability that a droplet holds two donors
+    #[arg(long, default_value = "0.05")]
+    doublet_prior: f64,
+
+    /// Posterior probability required for a singlet or doublet call
+    #[arg(long, default_value = "0.9")]
+    min_posterior: f64,
+
+    /// Cells covering fewer SNPs are left unassigned
+    #[arg(long, default_value = "5")]
+    min_snps: usize,
+}
+
+pub fn run(args: GenotypeDemuxArgs) -> Result<()> {
+    let snps = SnpSet::from_vcf(&args.vcf)
+        .with_context(|| format!("Failed to load VCF {:?}", args.vcf))?;
+    if snps.is_empty() {
+        anyhow::bail!("No biallelic SNPs in {:?}", args.vcf);
+    }
+    let cells = match &args.cells {
+        Some(path) => Some(super::ambient::read_barcodes(path)?),
+        None => None,
+    };
+
+    let pileup_config = PileupConfig {
+        min_mapq: args.min_mapq,
+        min_base_qual: args.min_base_qual,
+        ..Default::default()
+    };
+    log::info!("Piling up reads at {} SNPs", snps.len());
+    let counts = pileup(&args.bam, &snps, cells.as_deref(), &pileup_config)
+        .with_context(|| format!("Failed to pile up {:?}", args.bam))?;
+
+    let config = DonorDemuxConfig {
+        n_donors: args.donors,
+        doublet_prior: args.doublet_prior,
+        min_posterior: args.min_posterior,
+        min_snps: args.min_snps,
+        ..Default::default()
+    };
+    let demux = demux_donors(&counts, snps.len(), &config)?;
+
+    std::fs::create_dir_all(&args.output)?;
+    demux.write_assignments(args.output.join("donor_assignments.csv"))?;
+    demux.write_genotypes(&snps, args.output.join("donor_genotypes.tsv"))?;
+
+    let total = demux.cells.len().max(1) as f64;
+    let line = |label: &str, n: usize| {
+        println!("{:<22} {:>10} ({:.1}%)", label, n, n as f64 / total * 100.0);
+    };
+    println!("\n=== Genotype Demultiplexing Summary ===");
+    println!("{:<22} {:>10}", "SNPs", snps.len());
+    println!("{:<22} {:>10}", "Cells", demux.cells.len());
+    for (d, n) in demux.singlets_per_donor().into_iter().enumerate() {
+        line(&donor_name(d), n);
+    }
+    line("Doublets", demux.count("doublet"));
+    line("Unassigned", demux.count("unassigned"));
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+//! Build a transcriptome k-mer index for alignment-free counting
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::pseudoalign::KmerIndex;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct IndexArgs {
+    /// Transcriptome FASTA (cDNA sequences, .gz supported)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output index file
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Transcript-to-gene TSV (transcript, gene_id[, gene_name]); default: FASTA headers
+    #[arg(long)]
+    t2g: Option<PathBuf>,
+
+    /// K-mer length (at most 31)
+    #[arg(short, long, default_value = "31")]
+    kmer: usize,
+}
+
+pub fn run(args: IndexArgs) -> Result<()> {
+    let index = KmerIndex::from_fasta(&args.input, args.t2g.as_deref(), args.kmer)
+        .with_context(|| format!("Failed to index {:?}", args.input))?;
+    if index.n_genes() == 0 {
+        anyhow::bail!("No transcripts found in {:?}", args.input);
+    }
+    index.write(&args.output)?;
+
+    println!("\n=== Index Summary ===");
+    println!("K-mer length:         {}", index.k());
+    println!("Genes:                {}", index.n_genes());
+    println!("K-mers:               {}", index.n_kmers());
+    println!("Equivalence classes:  {}", index.n_classes());
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
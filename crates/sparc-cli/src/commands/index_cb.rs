@@ -0,0 +1,45 @@
+//! Build a cell barcode index for fetching one cell's reads from a BAM
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::bam::CellIndex;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct IndexCbArgs {
+    /// Input BAM file with CB tags (CB-sorted for the smallest index)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output index file [default: <input>.cbi, where fetch_cell looks]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub fn run(args: IndexCbArgs) -> Result<()> {
+    let output = args.output.clone().unwrap_or_else(|| CellIndex::sidecar_path(&args.input));
+    log::info!("Indexing cell barcodes of {:?}", args.input);
+    let index = CellIndex::build(&args.input)
+        .with_context(|| format!("Failed to index {:?}", args.input))?;
+    if index.is_empty() {
+        anyhow::bail!("No records with a CB tag in {:?}", args.input);
+    }
+    index.save(&output)?;
+
+    if index.n_runs() > index.len() * 2 {
+        log::warn!(
+            "Cells are scattered over {} runs; `samtools sort -t CB` first for a smaller index \
+             and faster fetches",
+            index.n_runs()
+        );
+    }
+
+    println!("\n=== Cell Index Summary ===");
+    println!("Cell barcodes:  {}", index.len());
+    println!("Records:        {}", index.n_records());
+    println!("Runs:           {}", index.n_runs());
+    println!("Untagged:       {}", index.untagged());
+    println!("\nOutput: {:?}", output);
+
+    Ok(())
+}
@@ -1,10 +1,33 @@
 //! CLI command implementations
 
+pub mod adt;
+pub mod ambient;
+pub mod annotate;
 pub mod batch;
+pub mod bench;
+pub mod completions;
+pub mod config;
 pub mod count;
+pub mod crispr;
+pub mod demux;
 pub mod distributed;
+pub mod dry_run;
 pub mod extract;
+pub mod extract_long;
+pub mod filter;
+pub mod genotype_demux;
+pub mod index;
+pub mod index_cb;
+pub mod peaks;
 pub mod pipeline;
+pub mod progress;
 pub mod analyze;
 pub mod qc;
+pub mod samples;
+pub mod simulate;
+pub mod spatial;
+pub mod stats;
+pub mod subsample;
+pub mod trim;
 pub mod validate;
+pub mod velocity;
@@ -0,0 +1,80 @@
+//! Count scATAC fragments in peaks to a cell x peak matrix
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::atac::{count_peaks, fragments_from_bam, FragmentReader, PeakCountMode, PeakSet};
+use std::path::PathBuf;
+
+use super::ambient::read_barcodes;
+
+#[derive(Args)]
+pub struct PeaksArgs {
+    /// Fragments file (fragments.tsv[.gz]) or a paired-end BAM with CB tags
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Peaks BED file (.gz supported)
+    #[arg(short, long)]
+    peaks: PathBuf,
+
+    /// Output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Count only these cell barcodes (one per line); default: every barcode
+    #[arg(long)]
+    cells: Option<PathBuf>,
+
+    /// What to count per peak: fragments or cut-sites (Tn5 insertions)
+    #[arg(long, default_value = "fragments")]
+    mode: PeakCountMode,
+
+    /// Minimum mapping quality of both mates (BAM input only)
+    #[arg(long, default_value = "30")]
+    min_mapq: u8,
+}
+
+pub fn run(args: PeaksArgs) -> Result<()> {
+    let peaks = PeakSet::from_bed(&args.peaks)
+        .with_context(|| format!("Failed to load peaks {:?}", args.peaks))?;
+    if peaks.is_empty() {
+        anyhow::bail!("No peaks found in {:?}", args.peaks);
+    }
+    let cells = args.cells.as_deref().map(read_barcodes).transpose()?;
+
+    let is_bam = args.input.extension().is_some_and(|ext| ext == "bam");
+    let (matrix, stats) = if is_bam {
+        let fragments = fragments_from_bam(&args.input, args.min_mapq)
+            .with_context(|| format!("Failed to read fragments from {:?}", args.input))?;
+        count_peaks(fragments.into_iter().map(Ok), &peaks, cells.as_deref(), args.mode)?
+    } else {
+        let fragments = FragmentReader::open(&args.input)
+            .with_context(|| format!("Failed to open {:?}", args.input))?;
+        count_peaks(fragments, &peaks, cells.as_deref(), args.mode)?
+    };
+
+    std::fs::create_dir_all(&args.output)?;
+    matrix.write_mtx(args.output.join("matrix.mtx"))?;
+    matrix.write_barcodes(args.output.join("barcodes.tsv"))?;
+    peaks.write_features(args.output.join("features.tsv"))?;
+    peaks.write_bed(args.output.join("peaks.bed"))?;
+    sparc_core::atomic::write(
+        args.output.join("peak_stats.json"),
+        serde_json::to_string_pretty(&stats)?,
+    )?;
+
+    println!("\n=== Peak Count Summary ===");
+    println!("Peaks:                {}", peaks.len());
+    println!("Cells:                {}", matrix.n_cols);
+    println!("Fragments:            {}", stats.total_fragments);
+    println!("Fragments in cells:   {}", stats.fragments_in_cells);
+    println!(
+        "Fragments in peaks:   {} ({:.1}% FRiP)",
+        stats.fragments_in_peaks,
+        stats.frip() * 100.0
+    );
+    println!("Mode:                 {}", args.mode);
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
@@ -4,39 +4,46 @@ use anyhow::{Context, Result};
 use clap::Args;
 use indicatif::{ProgressBar, ProgressStyle};
 use sparc_core::{
-    aligner::{Aligner, AlignerConfig},
+    aligner::AlignerConfig,
     bam::BamParser,
-    barcode::{BarcodeCorrector, BarcodeMatch, Whitelist},
+    barcode::BarcodeMatch,
     count::GeneCounter,
     fastq::FastqParser,
-    protocols::{DropSeq, InDrop, Protocol, SciRNA, SmartSeq2, TenX3Prime, TenX5Prime},
-    qc::{CellMetrics, QcMetrics, QcReport},
+    metrics::{self, Metrics},
+    perf,
+    protocols::Protocol,
+    qc::{CellFilter, CellMetrics, QcMetrics, QcReport},
 };
 use std::path::PathBuf;
 
-#[derive(Args)]
+use super::dry_run::{counter_memory, mtx_bytes, path_size, DryRunPlan};
+use super::extract::Corrector;
+use super::samples::{parse_sample_sheet, run_samples, SampleEntry, SampleMetrics};
+
+#[derive(Args, Clone)]
 pub struct PipelineArgs {
     /// Input R1 FASTQ file
-    #[arg(short = '1', long)]
-    pub(crate) r1: PathBuf,
+    #[arg(short = '1', long, required_unless_present = "samples")]
+    pub(crate) r1: Option<PathBuf>,
 
     /// Input R2 FASTQ file
-    #[arg(short = '2', long)]
-    pub(crate) r2: PathBuf,
+    #[arg(short = '2', long, required_unless_present = "samples")]
+    pub(crate) r2: Option<PathBuf>,
 
     /// Reference genome directory
     #[arg(short = 'r', long)]
     pub(crate) reference: PathBuf,
 
-    /// Output directory
+    /// Output directory (one subdirectory per sample with --samples)
     #[arg(short, long)]
     pub(crate) output: PathBuf,
 
-    /// Barcode whitelist file
+    /// Barcode whitelist file (optional for hive-v1)
     #[arg(short = 'w', long)]
-    pub(crate) whitelist: PathBuf,
+    pub(crate) whitelist: Option<PathBuf>,
 
-    /// Protocol (10x-3prime-v3, 10x-3prime-v2, 10x-5prime-v2, drop-seq, indrop, sci-rna-seq, smart-seq2)
+    /// Protocol (10x-3prime-v3, 10x-3prime-v2, 10x-5prime-v2, drop-seq, hive-v1, indrop,
+    /// pipseq-v3, sci-rna-seq, smart-seq2)
     #[arg(short, long, default_value = "10x-3prime-v3")]
     pub(crate) protocol: String,
 
@@ -48,6 +55,10 @@ pub struct PipelineArgs {
     #[arg(long, default_value = "star")]
     pub(crate) aligner: String,
 
+    /// Times to re-run a failed alignment (logs are kept in <output>/alignment)
+    #[arg(long, default_value = "1")]
+    pub(crate) align_retries: usize,
+
     /// Maximum Hamming distance for barcode correction
     #[arg(long, default_value = "1")]
     pub(crate) max_mismatch: u32,
@@ -83,24 +94,145 @@ pub struct PipelineArgs {
     /// Maximum genes per cell for QC
     #[arg(long, default_value = "10000")]
     pub(crate) max_genes: u64,
+
+    /// Estimate ambient RNA from empty droplets after counting (writes <output>/ambient)
+    #[arg(long)]
+    pub(crate) ambient: bool,
+
+    /// CSV sample sheet (sample,r1,r2[,whitelist,protocol,expect_cells,bam]) to run many samples
+    #[arg(long, conflicts_with_all = ["r1", "r2", "sample"])]
+    pub(crate) samples: Option<PathBuf>,
+
+    /// Samples to process in parallel with --samples
+    #[arg(long, default_value = "1")]
+    pub(crate) parallel_samples: usize,
+
+    /// Estimate reads, memory, and disk and print the commands to run, without running them
+    #[arg(long)]
+    pub(crate) dry_run: bool,
 }
 
-pub(crate) fn get_protocol(name: &str) -> Result<Box<dyn Protocol>> {
-    match name {
-        "10x-3prime-v3" => Ok(Box::new(TenX3Prime::v3())),
-        "10x-3prime-v2" => Ok(Box::new(TenX3Prime::v2())),
-        "10x-5prime-v2" => Ok(Box::new(TenX5Prime::v2())),
-        "drop-seq" => Ok(Box::new(DropSeq::new())),
-        "indrop" => Ok(Box::new(InDrop::new())),
-        "sci-rna-seq" => Ok(Box::new(SciRNA::new())),
-        "smart-seq2" => Ok(Box::new(SmartSeq2::new("sample".to_string()))),
-        _ => anyhow::bail!("Unknown protocol: {}", name),
+impl PipelineArgs {
+    /// Arguments for one sample-sheet row, writing under `output`
+    fn for_sample(&self, sample: &SampleEntry, output: PathBuf) -> Self {
+        let mut args = self.clone();
+        args.samples = None;
+        args.r1 = sample.r1.clone();
+        args.r2 = sample.r2.clone();
+        args.whitelist = sample.whitelist.clone().or(args.whitelist);
+        args.protocol = sample.protocol.clone().unwrap_or(args.protocol);
+        args.expect_cells = sample.expect_cells.or(args.expect_cells);
+        args.bam = sample.bam.clone().or(args.bam);
+        args.sample = sample.name.clone();
+        args.output = output;
+        args
     }
 }
 
+pub(crate) fn get_protocol(name: &str) -> Result<Box<dyn Protocol>> {
+    Ok(sparc_core::protocols::from_name(name)?)
+}
+
 pub fn run(args: PipelineArgs) -> Result<()> {
+    let Some(sheet) = &args.samples else {
+        if args.dry_run {
+            return dry_run(&args);
+        }
+        return run_sample(&args).map(|_| ());
+    };
+    let samples = parse_sample_sheet(sheet)?;
+    if args.dry_run {
+        for sample in &samples {
+            println!("--- Sample: {} ---", sample.name);
+            dry_run(&args.for_sample(sample, args.output.join(&sample.name)))?;
+            println!();
+        }
+        return Ok(());
+    }
+    run_samples(&samples, args.parallel_samples, &args.output, |sample, output| {
+        run_sample(&args.for_sample(sample, output))
+    })
+}
+
+fn aligner_config(args: &PipelineArgs) -> Result<AlignerConfig> {
+    let config = match args.aligner.as_str() {
+        "star" => AlignerConfig::star(args.reference.clone(), rayon::current_num_threads()),
+        "minimap2" => {
+            AlignerConfig::minimap2(args.reference.clone(), rayon::current_num_threads())
+        }
+        _ => anyhow::bail!("Unknown aligner: {}", args.aligner),
+    };
+    Ok(config.retries(args.align_retries))
+}
+
+/// Inspect inputs and print estimated resources and the external commands
+fn dry_run(args: &PipelineArgs) -> Result<()> {
+    let r1 = args.r1.as_ref().context("No R1 FASTQ given (--r1 or sample sheet r1 column)")?;
+    let r2 = args.r2.as_ref().context("No R2 FASTQ given (--r2 or sample sheet r2 column)")?;
+    let protocol = get_protocol(&args.protocol)?;
+    anyhow::ensure!(
+        args.whitelist.is_some() || !protocol.requires_whitelist(),
+        "No whitelist given (--whitelist or sample sheet whitelist column)"
+    );
+
+    let mut plan = DryRunPlan::default();
+    let reads = plan.add_input(r1)?;
+    plan.add_input(r2)?;
+
+    // Whitelist index: string, allocation, and hash-table overhead is ~5x the file size
+    if let Some(whitelist_path) = &args.whitelist {
+        plan.memory("Barcode extraction (whitelist index)", path_size(whitelist_path) * 5);
+    }
+
+    if args.skip_align {
+        let bam = args.bam.clone().unwrap_or_else(|| args.output.join("aligned.bam"));
+        if bam.exists() {
+            plan.add_input(&bam)?;
+        } else {
+            plan.note(format!("--skip-align: BAM {:?} does not exist yet", bam));
+        }
+    } else {
+        let aligner = aligner_config(args)?.build();
+        let reference_size = path_size(&args.reference);
+        let sort_memory = sparc_core::resources::global()
+            .max_memory
+            .unwrap_or(reference_size);
+        let index_memory = match args.aligner.as_str() {
+            // STAR loads the whole genome index, then sorts the BAM in memory
+            "star" => reference_size + sort_memory,
+            // minimap2 builds an index of roughly 4x the FASTA size
+            _ => reference_size * 4,
+        };
+        plan.memory(&format!("Alignment ({})", aligner.binary_name()), index_memory);
+        // Aligned BAM and sort temporaries are each about the size of the compressed reads
+        plan.disk("Aligned BAM", plan.input_bytes());
+        plan.disk("Sort temporaries (--tmp-dir)", plan.input_bytes());
+        plan.commands(aligner.planned_commands(
+            r2,
+            Some(r1.as_path()),
+            &args.output.join("alignment"),
+        ));
+        if !aligner.is_available() {
+            plan.note(format!("{} not found in PATH", aligner.binary_name()));
+        }
+    }
+
+    // Non-zero entries are bounded by reads and by cells x max genes per cell
+    let cells = args.force_cells.or(args.expect_cells).unwrap_or(10_000) as u64;
+    let entries = reads.min(cells * args.max_genes);
+    plan.memory("Counting (count table, upper bound)", counter_memory(entries));
+    plan.disk("Count matrix (upper bound)", mtx_bytes(entries));
+
+    plan.print("pipeline");
+    Ok(())
+}
+
+fn run_sample(args: &PipelineArgs) -> Result<SampleMetrics> {
     println!("=== SPARC Pipeline ===\n");
 
+    let r1 = args.r1.as_ref().context("No R1 FASTQ given (--r1 or sample sheet r1 column)")?;
+    let r2 = args.r2.as_ref().context("No R2 FASTQ given (--r2 or sample sheet r2 column)")?;
+
     // Create output directories
     let extract_dir = args.output.join("extraction");
     let align_dir = args.output.join("alignment");
@@ -111,16 +243,15 @@ pub fn run(args: PipelineArgs) -> Result<()> {
     std::fs::create_dir_all(&align_dir)?;
     std::fs::create_dir_all(&count_dir)?;
     std::fs::create_dir_all(&qc_dir)?;
+    let counters = Metrics::new();
 
     // ===== Step 1: Extract barcodes =====
     println!("--- Step 1/4: Extracting barcodes and UMIs ---");
+    let stage = perf::stage("extract");
 
-    let whitelist =
-        Whitelist::from_file(&args.whitelist).context("Failed to load barcode whitelist")?;
-    log::info!("Loaded {} barcodes", whitelist.len());
-
-    let corrector = BarcodeCorrector::new(whitelist, args.max_mismatch);
     let protocol = get_protocol(&args.protocol)?;
+    let corrector =
+        Corrector::load(&*protocol, args.whitelist.as_deref(), None, args.max_mismatch)?;
 
     let pb = ProgressBar::new_spinner();
     pb.set_style(
@@ -130,22 +261,22 @@ pub fn run(args: PipelineArgs) -> Result<()> {
     );
 
     let mut r1_parser =
-        FastqParser::open(&args.r1).context("Failed to open R1 FASTQ")?;
+        FastqParser::open(r1).context("Failed to open R1 FASTQ")?;
 
-    let mut total_reads = 0u64;
-    let mut valid_barcode = 0u64;
-    let mut corrected_barcode = 0u64;
+    let reads = counters.counter("extract", "reads");
+    let valid = counters.counter("extract", "valid_barcodes");
+    let corrected = counters.counter("extract", "corrected_barcodes");
 
     for result in &mut r1_parser {
         let record = result?;
-        total_reads += 1;
+        reads.inc();
 
-        if total_reads % 100000 == 0 {
+        if reads.get() % 100000 == 0 {
             pb.set_message(format!(
                 "Processed {} reads, {} valid barcodes ({:.1}%)",
-                total_reads,
-                valid_barcode,
-                valid_barcode as f64 / total_reads as f64 * 100.0
+                reads.get(),
+                valid.get(),
+                valid.get() as f64 / reads.get() as f64 * 100.0
             ));
         }
 
@@ -161,16 +292,18 @@ pub fn run(args: PipelineArgs) -> Result<()> {
         let barcode_str = components.barcode_str();
         match corrector.match_barcode(&barcode_str) {
             BarcodeMatch::Exact(_) => {
-                valid_barcode += 1;
+                valid.inc();
             }
             BarcodeMatch::Corrected(_, _, _) => {
-                valid_barcode += 1;
-                corrected_barcode += 1;
+                valid.inc();
+                corrected.inc();
             }
             BarcodeMatch::NoMatch(_) => {}
         }
     }
 
+    let (total_reads, valid_barcode, corrected_barcode) =
+        (reads.get(), valid.get(), corrected.get());
     pb.finish_with_message(format!(
         "Extraction complete: {} valid from {} reads",
         valid_barcode, total_reads
@@ -188,22 +321,22 @@ pub fn run(args: PipelineArgs) -> Result<()> {
         corrected_barcode as f64 / total_reads.max(1) as f64 * 100.0
     );
 
+    stage.finish();
+
+    let mut metrics: SampleMetrics = vec![
+        ("total_reads", total_reads as f64),
+        ("valid_barcode_pct", valid_barcode as f64 / total_reads.max(1) as f64 * 100.0),
+    ];
+
     // ===== Step 2: Alignment =====
+    let stage = perf::stage("align");
     let bam_path = if args.skip_align {
         println!("\n--- Step 2/4: Alignment (skipped) ---");
         args.bam.clone().unwrap_or_else(|| args.output.join("aligned.bam"))
     } else {
         println!("\n--- Step 2/4: Aligning reads ---");
 
-        let aligner_config = match args.aligner.as_str() {
-            "star" => AlignerConfig::star(args.reference.clone(), rayon::current_num_threads()),
-            "minimap2" => {
-                AlignerConfig::minimap2(args.reference.clone(), rayon::current_num_threads())
-            }
-            _ => anyhow::bail!("Unknown aligner: {}", args.aligner),
-        };
-
-        let aligner = Aligner::new(aligner_config);
+        let aligner = aligner_config(args)?.build();
 
         if !aligner.is_available() {
             println!("  WARNING: {} not found in PATH", aligner.binary_name());
@@ -211,15 +344,19 @@ pub fn run(args: PipelineArgs) -> Result<()> {
             args.output.join("aligned.bam")
         } else {
             let bam = aligner
-                .align(&args.r2, Some(&args.r1), &align_dir)
+                .align(r2, Some(r1.as_path()), &align_dir)
                 .context("Alignment failed")?;
             println!("  Alignment complete: {:?}", bam);
+            println!("  Aligner log: {:?}", aligner.log_path(&align_dir));
             bam
         }
     };
 
+    stage.finish();
+
     // ===== Step 3: Count matrix =====
     println!("\n--- Step 3/4: Generating count matrix ---");
+    let stage = perf::stage("count");
 
     if bam_path.exists() {
         let pb3 = ProgressBar::new_spinner();
@@ -232,44 +369,53 @@ pub fn run(args: PipelineArgs) -> Result<()> {
         let mut bam_parser =
             BamParser::open(&bam_path).context("Failed to open BAM file")?;
 
-        let mut counter = GeneCounter::new();
-        let mut bam_total = 0u64;
-        let mut assigned = 0u64;
+        let mut counter = GeneCounter::with_resources(sparc_core::resources::global());
+        let bam_reads = counters.counter("count", "reads");
+        let assigned_reads = counters.counter("count", "assigned");
 
         for result in &mut bam_parser {
             let record = result?;
-            bam_total += 1;
-
-            if bam_total % 100000 == 0 {
-                pb3.set_message(format!("Processed {} reads, {} assigned", bam_total, assigned));
+            bam_reads.inc();
+
+            if bam_reads.get() % 100000 == 0 {
+                pb3.set_message(format!(
+                    "Processed {} reads, {} assigned",
+                    bam_reads.get(),
+                    assigned_reads.get()
+                ));
             }
 
             if !record.is_mapped || record.mapq < args.min_mapq {
                 continue;
             }
 
-            let (barcode, gene) = match (&record.cell_barcode, &record.gene_name) {
-                (Some(bc), Some(gn)) => (bc, gn),
+            // Rows are keyed by gene ID (GX) when there is one
+            let (barcode, gene) = match (&record.cell_barcode, &record.gene_id) {
+                (Some(bc), Some(gx)) => (bc, gx),
                 (Some(bc), None) => {
-                    if let Some(gx) = &record.gene_id {
-                        (bc, gx)
+                    if let Some(gn) = &record.gene_name {
+                        (bc, gn)
                     } else {
                         continue;
                     }
                 }
                 _ => continue,
             };
+            if let (Some(gx), Some(gn)) = (&record.gene_id, &record.gene_name) {
+                counter.set_gene_name(gx, gn);
+            }
 
             counter.increment(barcode, gene);
-            assigned += 1;
+            assigned_reads.inc();
         }
+        let (bam_total, assigned) = (bam_reads.get(), assigned_reads.get());
 
         pb3.finish_with_message(format!(
             "Count matrix: {} assigned from {} reads",
             assigned, bam_total
         ));
 
-        let matrix = counter.build();
+        let matrix = counter.try_build()?;
 
         matrix.write_mtx(count_dir.join("matrix.mtx"))?;
         matrix.write_barcodes(count_dir.join("barcodes.tsv"))?;
@@ -281,44 +427,80 @@ pub fn run(args: PipelineArgs) -> Result<()> {
         );
         println!("  Non-zero entries: {}", matrix.values.len());
 
+        if args.ambient {
+            let totals = matrix.counts_per_cell();
+            let genes_per_cell = matrix.genes_per_cell();
+            let cells: Vec<usize> =
+                (0..matrix.n_cols).filter(|&c| genes_per_cell[c] >= args.min_genes).collect();
+            let empty: Vec<usize> = (0..matrix.n_cols)
+                .filter(|&c| genes_per_cell[c] < args.min_genes)
+                .filter(|&c| totals[c] > 0 && totals[c] <= 100)
+                .collect();
+            match super::ambient::estimate_ambient(
+                &matrix,
+                &cells,
+                &empty,
+                None,
+                true,
+                &args.output.join("ambient"),
+            ) {
+                Ok(estimate) => {
+                    println!(
+                        "  Ambient contamination: {:.1}% mean over {} cells",
+                        estimate.mean_contamination() * 100.0,
+                        cells.len()
+                    );
+                    metrics.push(("ambient_contamination", estimate.mean_contamination()));
+                }
+                Err(e) => log::warn!("Skipping ambient RNA estimation: {:#}", e),
+            }
+        }
+
+        stage.finish();
+
         // ===== Step 4: QC =====
         println!("\n--- Step 4/4: Quality control ---");
+        let _stage = perf::stage("qc");
 
         let counts_per_cell = matrix.counts_per_cell();
         let genes_per_cell = matrix.genes_per_cell();
 
-        let mut metrics = QcMetrics::new();
-        metrics.total_reads = total_reads;
-        metrics.valid_barcode_reads = valid_barcode;
-        metrics.mapped_reads = bam_total;
-        metrics.assigned_reads = assigned;
-        metrics.num_cells = matrix.n_cols as u64;
-        metrics.total_genes = matrix.n_rows as u64;
-        metrics.update_from_cells(&counts_per_cell, &genes_per_cell, &counts_per_cell);
+        let mut qc_metrics = QcMetrics::new();
+        qc_metrics.total_reads = total_reads;
+        qc_metrics.valid_barcode_reads = valid_barcode;
+        qc_metrics.mapped_reads = bam_total;
+        qc_metrics.assigned_reads = assigned;
+        qc_metrics.num_cells = matrix.n_cols as u64;
+        qc_metrics.total_genes = matrix.n_rows as u64;
+        qc_metrics.update_from_cells(&counts_per_cell, &genes_per_cell, &counts_per_cell);
 
         let mut report = QcReport::new(args.sample.clone());
-        report.metrics = metrics;
+        report.metrics = qc_metrics;
 
+        let filter = CellFilter {
+            min_genes: args.min_genes,
+            max_genes: args.max_genes,
+            ..CellFilter::default()
+        };
+        let status = filter.apply(&matrix, &[]);
         for (i, barcode) in matrix.barcodes.iter().enumerate() {
-            report.per_cell_metrics.push(CellMetrics {
-                barcode: barcode.clone(),
-                reads: counts_per_cell.get(i).copied().unwrap_or(0),
-                genes: genes_per_cell.get(i).copied().unwrap_or(0),
-                umis: counts_per_cell.get(i).copied().unwrap_or(0),
-                mito_percent: 0.0,
-            });
+            let mut cell_metrics = CellMetrics::new(
+                barcode.clone(),
+                counts_per_cell[i],
+                genes_per_cell[i],
+                counts_per_cell[i],
+                status.mito_percent[i],
+            );
+            cell_metrics.set_qc_flags(status.reasons[i].clone());
+            report.per_cell_metrics.push(cell_metrics);
         }
 
         report.generate_warnings();
 
-        let filtered_cells = report
-            .per_cell_metrics
-            .iter()
-            .filter(|c| c.genes >= args.min_genes && c.genes <= args.max_genes)
-            .count();
+        let filtered_cells = report.cells_passing();
 
         let json = report.to_json()?;
-        std::fs::write(qc_dir.join("qc_report.json"), &json)?;
+        sparc_core::atomic::write(qc_dir.join("qc_report.json"), &json)?;
 
         println!(
             "  Median genes/cell: {:.0}",
@@ -334,6 +516,11 @@ pub fn run(args: PipelineArgs) -> Result<()> {
             filtered_cells as f64 / matrix.n_cols.max(1) as f64 * 100.0
         );
 
+        metrics.push(("cells", matrix.n_cols as f64));
+        metrics.push(("cells_passing_qc", filtered_cells as f64));
+        metrics.push(("median_genes_per_cell", report.metrics.median_genes_per_cell));
+        metrics.push(("median_umi_per_cell", report.metrics.median_umi_per_cell));
+
         if !report.warnings.is_empty() {
             println!("\n  Warnings:");
             for w in &report.warnings {
@@ -347,8 +534,10 @@ pub fn run(args: PipelineArgs) -> Result<()> {
         );
     }
 
+    metrics::global().merge(&counters);
+
     println!("\n=== Pipeline Complete ===");
     println!("Output directory: {:?}", args.output);
 
-    Ok(())
+    Ok(metrics)
 }
@@ -0,0 +1,66 @@
+//! Progress bars sized from an estimate of the input's read count
+//!
+//! The total is extrapolated from the first records of the input and the
+//! (compressed) bytes they take up, so percentages and ETAs are approximate.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use sparc_core::qc::stats::estimate_records;
+use sparc_core::remote;
+use std::path::Path;
+
+/// Records sampled to estimate the input's total
+const SAMPLE_RECORDS: u64 = 20_000;
+
+/// A progress bar over the reads of `input`, where each read spans
+/// `records_per_read` records of the file. Falls back to a spinner when the
+/// input can't be sized (remote, unreadable, or empty).
+pub fn input_progress(input: Option<&Path>, records_per_read: u64) -> ProgressBar {
+    let Some(path) = input.filter(|path| !remote::is_remote(path)) else {
+        return spinner();
+    };
+    let total = match estimate_records(path, SAMPLE_RECORDS) {
+        Ok(estimate) => estimate.estimated / records_per_read.max(1),
+        Err(e) => {
+            log::debug!("Could not estimate reads in {:?}: {}", path, e);
+            0
+        }
+    };
+    if total == 0 {
+        return spinner();
+    }
+    log::info!("Estimated ~{} reads in {:?}", total, path);
+
+    let progress = ProgressBar::new(total);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:30.cyan/blue}] {percent}% \
+                 (ETA {eta}) {msg}",
+            )
+            .expect("valid progress template")
+            .progress_chars("=> "),
+    );
+    progress
+}
+
+/// A spinner for inputs of unknown size
+pub fn spinner() -> ProgressBar {
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .expect("valid progress template"),
+    );
+    progress
+}
+
+/// Move `progress` to `position` reads, raising its length by 10% whenever
+/// the estimate runs short
+pub fn set_position(progress: &ProgressBar, position: u64) {
+    if let Some(length) = progress.length() {
+        if position > length {
+            progress.set_length(position + position / 10);
+        }
+    }
+    progress.set_position(position);
+}
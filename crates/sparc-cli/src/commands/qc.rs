@@ -2,11 +2,18 @@
 
 use anyhow::{Context, Result};
 use clap::Args;
-use sparc_core::qc::{CellMetrics, QcMetrics, QcReport};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use sparc_core::adt::{find_isotypes, read_adt_matrix, AdtMetrics};
+use sparc_core::annotation::{AnnotateStats, GeneAnnotation, RegionMetrics};
+use sparc_core::atac::{fragments_from_bam, FragmentReader, PeakSet};
+use sparc_core::count::{CountMatrix, GeneBiotypes};
+use sparc_core::qc::{
+    atac_qc, tss_from_annotation, AtacQcConfig, BarnyardConfig, BarnyardMetrics, CellCalling,
+    CellFilter, CellMetrics, ComplexityMetrics, ComplexityStats, QcMetrics, QcReport,
+};
 use std::path::PathBuf;
 
+use super::ambient::read_barcodes;
+
 #[derive(Args)]
 pub struct QcArgs {
     /// Input matrix directory (with matrix.mtx, barcodes.tsv, genes.tsv)
@@ -32,96 +39,198 @@ pub struct QcArgs {
     /// Maximum mitochondrial percentage
     #[arg(long, default_value = "20.0")]
     max_mito: f64,
+
+    /// Minimum UMIs per cell (0 disables)
+    #[arg(long, default_value = "0")]
+    min_umis: u64,
+
+    /// Doublet barcodes, one per line, to flag as failing QC
+    #[arg(long)]
+    doublets: Option<PathBuf>,
+
+    /// Antibody capture matrix directory to add ADT/isotype metrics
+    #[arg(long)]
+    adt: Option<PathBuf>,
+
+    /// Isotype control feature names for --adt (names containing "isotype" are always included)
+    #[arg(long, requires = "adt")]
+    isotype: Vec<String>,
+
+    /// Read region stats JSON (from `sparc annotate --stats` or `sparc velocity`) to add
+    /// exonic/intronic and spliced/unspliced fractions
+    #[arg(long)]
+    region_stats: Option<PathBuf>,
+
+    /// Low-complexity barcode/UMI stats from `sparc extract` (low_complexity.json)
+    #[arg(long)]
+    low_complexity: Option<PathBuf>,
+
+    /// Add species-mixing (barnyard) metrics for a mixed-species reference
+    #[arg(long)]
+    barnyard: bool,
+
+    /// Gene name prefixes of the two species, e.g. GRCh38,mm10 (default: detect)
+    #[arg(long, requires = "barnyard", value_delimiter = ',')]
+    species: Vec<String>,
+
+    /// Fraction of a cell's UMIs from one species needed for a singlet call
+    #[arg(long, default_value = "0.9")]
+    species_fraction: f64,
+
+    /// Include per-cell species UMI counts for a scatter plot in the report
+    #[arg(long, requires = "barnyard")]
+    barnyard_scatter: bool,
+
+    /// Gene annotation GTF to add the fraction of UMIs per gene biotype (.gz supported)
+    #[arg(long)]
+    gtf: Option<PathBuf>,
+
+    /// ATAC fragments (fragments.tsv[.gz] or a paired-end BAM with CB tags) to add
+    /// TSS enrichment, FRiP, and nucleosome signal; TSSs come from --gtf
+    #[arg(long, requires = "gtf")]
+    fragments: Option<PathBuf>,
+
+    /// Peaks BED file for the fraction of fragments in peaks (.gz supported)
+    #[arg(long, requires = "fragments")]
+    peaks: Option<PathBuf>,
+
+    /// Minimum mapping quality of both mates (BAM fragments only)
+    #[arg(long, default_value = "30")]
+    min_mapq: u8,
 }
 
 pub fn run(args: QcArgs) -> Result<()> {
     log::info!("Reading count matrix from {:?}", args.input);
+    let matrix = CountMatrix::read_mtx(&args.input)
+        .with_context(|| format!("Failed to read matrix from {:?}", args.input))?;
+    let (n_rows, n_cols) = (matrix.n_rows, matrix.n_cols);
+    let counts_per_cell = matrix.counts_per_cell();
+    let genes_per_cell_count = matrix.genes_per_cell();
+
+    // Calculate metrics
+    let mut metrics = QcMetrics::new();
+    metrics.num_cells = n_cols as u64;
+    metrics.total_genes = n_rows as u64;
+    metrics.update_from_cells(&counts_per_cell, &genes_per_cell_count, &counts_per_cell);
 
-    // Read barcodes
-    let barcodes_path = args.input.join("barcodes.tsv");
-    let barcodes: Vec<String> = BufReader::new(File::open(&barcodes_path)?)
-        .lines()
-        .collect::<std::io::Result<Vec<_>>>()?;
-
-    // Read genes
-    let genes_path = args.input.join("genes.tsv");
-    let genes: Vec<String> = BufReader::new(File::open(&genes_path)?)
-        .lines()
-        .map(|l| l.map(|s| s.split('\t').next().unwrap_or("").to_string()))
-        .collect::<std::io::Result<Vec<_>>>()?;
-
-    // Read matrix
-    let mtx_path = args.input.join("matrix.mtx");
-    let mtx_file = BufReader::new(File::open(&mtx_path)?);
-
-    let mut n_rows = 0;
-    let mut n_cols = 0;
-    let mut counts_per_cell: Vec<u64> = Vec::new();
-    let mut genes_per_cell: Vec<std::collections::HashSet<usize>> = Vec::new();
-
-    for (i, line) in mtx_file.lines().enumerate() {
-        let line = line?;
-        if line.starts_with('%') {
-            continue;
+    if let Some(adt_path) = &args.adt {
+        let adt = read_adt_matrix(adt_path)
+            .with_context(|| format!("Failed to read ADT matrix {:?}", adt_path))?;
+        let isotypes = find_isotypes(&adt.genes, &args.isotype);
+        if isotypes.is_empty() {
+            log::warn!("No isotype controls found in {:?}; isotype metrics will be zero", adt_path);
         }
+        metrics.adt = Some(AdtMetrics::from_matrix(&adt, &isotypes, 0.1));
+    }
+
+    if let Some(stats_path) = &args.region_stats {
+        let json = std::fs::read_to_string(stats_path)
+            .with_context(|| format!("Failed to read region stats {:?}", stats_path))?;
+        let stats: AnnotateStats = serde_json::from_str(&json)
+            .with_context(|| format!("Invalid region stats {:?}", stats_path))?;
+        metrics.regions = Some(RegionMetrics::from_stats(&stats));
+    }
 
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() == 3 && i <= 2 {
-            // Header line
-            n_rows = parts[0].parse().unwrap_or(0);
-            n_cols = parts[1].parse().unwrap_or(0);
-            counts_per_cell = vec![0; n_cols];
-            genes_per_cell = (0..n_cols).map(|_| std::collections::HashSet::new()).collect();
-        } else if parts.len() == 3 {
-            // Data line (1-indexed)
-            let row: usize = parts[0].parse().context("Invalid row index")?;
-            let col: usize = parts[1].parse().context("Invalid col index")?;
-            let val: u64 = parts[2].parse().context("Invalid value")?;
-
-            if col > 0 && col <= n_cols && row > 0 && row <= n_rows {
-                counts_per_cell[col - 1] += val;
-                genes_per_cell[col - 1].insert(row - 1);
-            }
+    if let Some(stats_path) = &args.low_complexity {
+        let json = std::fs::read_to_string(stats_path)
+            .with_context(|| format!("Failed to read low-complexity stats {:?}", stats_path))?;
+        let stats: ComplexityStats = serde_json::from_str(&json)
+            .with_context(|| format!("Invalid low-complexity stats {:?}", stats_path))?;
+        metrics.low_complexity = Some(ComplexityMetrics::from_stats(&stats));
+    }
+
+    let annotation = match &args.gtf {
+        Some(gtf) => Some(GeneAnnotation::from_gtf(gtf).context("Failed to load GTF")?),
+        None => None,
+    };
+    if let (Some(gtf), Some(annotation)) = (&args.gtf, &annotation) {
+        let biotypes = GeneBiotypes::from_annotation(annotation);
+        if biotypes.is_empty() {
+            log::warn!("No gene_type or gene_biotype attributes in {:?}", gtf);
         }
+        metrics.biotypes = Some(biotypes.fractions(&matrix));
     }
 
-    let genes_per_cell_count: Vec<u64> = genes_per_cell.iter().map(|s| s.len() as u64).collect();
+    // ATAC metrics for the matrix's cells
+    let atac_cells = match (&args.fragments, &annotation) {
+        (Some(path), Some(annotation)) => {
+            let tss = tss_from_annotation(annotation);
+            let peaks = match &args.peaks {
+                Some(bed) => Some(
+                    PeakSet::from_bed(bed)
+                        .with_context(|| format!("Failed to load peaks {:?}", bed))?,
+                ),
+                None => None,
+            };
+            let config = AtacQcConfig::default();
+            let cells = Some(matrix.barcodes.as_slice());
+            let (atac, per_cell) = if path.extension().is_some_and(|ext| ext == "bam") {
+                let fragments = fragments_from_bam(path, args.min_mapq)
+                    .with_context(|| format!("Failed to read fragments from {:?}", path))?;
+                atac_qc(fragments.into_iter().map(Ok), &tss, peaks.as_ref(), cells, &config)?
+            } else {
+                let fragments = FragmentReader::open(path)
+                    .with_context(|| format!("Failed to open {:?}", path))?;
+                atac_qc(fragments, &tss, peaks.as_ref(), cells, &config)?
+            };
+            metrics.atac = Some(atac);
+            per_cell
+        }
+        _ => Vec::new(),
+    };
 
-    // Calculate metrics
-    let mut metrics = QcMetrics::new();
-    metrics.num_cells = n_cols as u64;
-    metrics.total_genes = n_rows as u64;
-    metrics.update_from_cells(&counts_per_cell, &genes_per_cell_count, &counts_per_cell);
+    if args.barnyard {
+        let config = BarnyardConfig {
+            species: match args.species.as_slice() {
+                [a, b] => Some([a.clone(), b.clone()]),
+                [] => None,
+                _ => anyhow::bail!("--species takes exactly two prefixes, e.g. GRCh38,mm10"),
+            },
+            min_fraction: args.species_fraction,
+            scatter: args.barnyard_scatter,
+            ..Default::default()
+        };
+        metrics.barnyard = Some(BarnyardMetrics::from_matrix(&matrix, &config)?);
+    }
 
     // Build report
     let mut report = QcReport::new(args.sample.clone());
     report.metrics = metrics;
 
-    // Per-cell metrics
-    for (i, barcode) in barcodes.iter().enumerate() {
-        let cell_metrics = CellMetrics {
-            barcode: barcode.clone(),
-            reads: counts_per_cell.get(i).copied().unwrap_or(0),
-            genes: genes_per_cell_count.get(i).copied().unwrap_or(0),
-            umis: counts_per_cell.get(i).copied().unwrap_or(0),
-            mito_percent: 0.0, // Would need gene annotations
-        };
+    // Per-cell metrics, flagged with every threshold they fail
+    let doublets = match &args.doublets {
+        Some(path) => read_barcodes(path)?,
+        None => Vec::new(),
+    };
+    let filter = CellFilter {
+        calling: CellCalling::All,
+        min_umis: args.min_umis,
+        min_genes: args.min_genes,
+        max_genes: args.max_genes,
+        max_mito_percent: args.max_mito,
+    };
+    let status = filter.apply(&matrix, &doublets);
+    for (i, barcode) in matrix.barcodes.iter().enumerate() {
+        let mut cell_metrics = CellMetrics::new(
+            barcode.clone(),
+            counts_per_cell[i],
+            genes_per_cell_count[i],
+            counts_per_cell[i],
+            status.mito_percent[i],
+        );
+        cell_metrics.set_qc_flags(status.reasons[i].clone());
+        cell_metrics.atac = atac_cells.get(i).cloned();
         report.per_cell_metrics.push(cell_metrics);
     }
 
     // Generate warnings
     report.generate_warnings();
 
-    // Apply filters
-    let filtered_cells = report
-        .per_cell_metrics
-        .iter()
-        .filter(|c| c.genes >= args.min_genes && c.genes <= args.max_genes)
-        .count();
+    let filtered_cells = report.cells_passing();
 
     // Write report
     let json = report.to_json()?;
-    std::fs::write(&args.output, &json)?;
+    sparc_core::atomic::write(&args.output, &json)?;
 
     // Print summary
     println!("\n=== QC Summary ===");
@@ -135,6 +244,78 @@ pub fn run(args: QcArgs) -> Result<()> {
         filtered_cells as f64 / n_cols.max(1) as f64 * 100.0
     );
 
+    if let Some(adt) = &report.metrics.adt {
+        println!("ADT features:        {} ({} isotype)", adt.num_features, adt.num_isotypes);
+        println!("Median ADT UMIs:     {:.0}", adt.median_umis_per_cell);
+        println!("High-isotype cells:  {:.1}%", adt.high_isotype_cell_fraction * 100.0);
+    }
+
+    if let Some(regions) = &report.metrics.regions {
+        println!(
+            "Exonic/intronic/intergenic: {:.1}% / {:.1}% / {:.1}%",
+            regions.fraction_exonic * 100.0,
+            regions.fraction_intronic * 100.0,
+            regions.fraction_intergenic * 100.0
+        );
+        println!(
+            "Spliced/unspliced:   {:.1}% / {:.1}%",
+            regions.fraction_spliced * 100.0,
+            regions.fraction_unspliced * 100.0
+        );
+    }
+
+    if let Some(low) = &report.metrics.low_complexity {
+        println!(
+            "Low-complexity CB/UMI: {:.2}% / {:.2}% ({:.2}% dropped)",
+            low.fraction_low_complexity_barcodes * 100.0,
+            low.fraction_low_complexity_umis * 100.0,
+            low.fraction_dropped * 100.0
+        );
+    }
+
+    if let Some(biotypes) = &report.metrics.biotypes {
+        let mut by_fraction: Vec<_> = biotypes.iter().collect();
+        by_fraction.sort_by(|a, b| b.1.total_cmp(a.1));
+        let top: Vec<String> = by_fraction
+            .iter()
+            .take(4)
+            .map(|(biotype, f)| format!("{} {:.1}%", biotype, *f * 100.0))
+            .collect();
+        println!("UMIs by biotype:     {}", top.join(", "));
+    }
+
+    if let Some(atac) = &report.metrics.atac {
+        println!("Median fragments:    {:.0}", atac.median_fragments_per_cell);
+        println!(
+            "TSS enrichment:      {:.2} (median cell {:.2})",
+            atac.tss_enrichment, atac.median_tss_enrichment
+        );
+        if args.peaks.is_some() {
+            println!("Fragments in peaks:  {:.1}%", atac.frip * 100.0);
+        }
+        println!("Nucleosome signal:   {:.2}", atac.nucleosome_signal);
+    }
+
+    if let Some(barnyard) = &report.metrics.barnyard {
+        let [a, b] = &barnyard.species;
+        println!(
+            "Species cells:       {} {} / {} {} / {} multiplets",
+            barnyard.singlets[0], a, barnyard.singlets[1], b, barnyard.multiplets
+        );
+        println!(
+            "Multiplet rate:      {:.1}% observed, {:.1}% inferred",
+            barnyard.observed_multiplet_rate * 100.0,
+            barnyard.inferred_multiplet_rate * 100.0
+        );
+        println!(
+            "Cross-species UMIs:  {:.2}% in {} cells, {:.2}% in {} cells",
+            barnyard.cross_contamination[0] * 100.0,
+            a,
+            barnyard.cross_contamination[1] * 100.0,
+            b
+        );
+    }
+
     if !report.warnings.is_empty() {
         println!("\nWarnings:");
         for warning in &report.warnings {
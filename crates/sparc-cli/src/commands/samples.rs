@@ -0,0 +1,183 @@
+//! CSV sample sheets for running `extract`, `count`, and `pipeline` over many samples
+//!
+//! The first non-comment line is a header naming the columns. `sample` is
+//! required; `r1`, `r2`, `bam`, `whitelist`, `protocol`, and `expect_cells` are
+//! optional and override the corresponding command-line option per sample.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Headline metrics reported by one sample run, in display order
+pub(crate) type SampleMetrics = Vec<(&'static str, f64)>;
+
+/// One row of a sample sheet
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SampleEntry {
+    pub name: String,
+    pub r1: Option<PathBuf>,
+    pub r2: Option<PathBuf>,
+    pub bam: Option<PathBuf>,
+    pub whitelist: Option<PathBuf>,
+    pub protocol: Option<String>,
+    pub expect_cells: Option<u32>,
+}
+
+const COLUMNS: [&str; 7] = ["sample", "r1", "r2", "bam", "whitelist", "protocol", "expect_cells"];
+
+/// Parse a sample sheet CSV
+pub(crate) fn parse_sample_sheet(path: &Path) -> Result<Vec<SampleEntry>> {
+    let file = File::open(path).with_context(|| format!("Failed to open sample sheet {:?}", path))?;
+    let mut header: Option<Vec<String>> = None;
+    let mut samples = Vec::new();
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+
+        if header.is_none() {
+            let columns: Vec<String> = fields.iter().map(|f| f.to_lowercase()).collect();
+            if let Some(unknown) = columns.iter().find(|c| !COLUMNS.contains(&c.as_str())) {
+                anyhow::bail!(
+                    "Sample sheet {:?}: unknown column '{}' (expected {})",
+                    path,
+                    unknown,
+                    COLUMNS.join(",")
+                );
+            }
+            if !columns.iter().any(|c| c == "sample") {
+                anyhow::bail!("Sample sheet {:?}: header must include a 'sample' column", path);
+            }
+            header = Some(columns);
+            continue;
+        }
+        let columns = header.as_ref().expect("header parsed above");
+
+        if fields.len() != columns.len() {
+            anyhow::bail!(
+                "Sample sheet line {}: expected {} columns, got {}",
+                i + 1,
+                columns.len(),
+                fields.len()
+            );
+        }
+
+        let mut entry = SampleEntry::default();
+        for (column, value) in columns.iter().zip(&fields) {
+            if value.is_empty() {
+                continue;
+            }
+            match column.as_str() {
+                "sample" => entry.name = value.to_string(),
+                "r1" => entry.r1 = Some(PathBuf::from(value)),
+                "r2" => entry.r2 = Some(PathBuf::from(value)),
+                "bam" => entry.bam = Some(PathBuf::from(value)),
+                "whitelist" => entry.whitelist = Some(PathBuf::from(value)),
+                "protocol" => entry.protocol = Some(value.to_string()),
+                "expect_cells" => {
+                    entry.expect_cells = Some(value.parse().with_context(|| {
+                        format!("Sample sheet line {}: invalid expect_cells '{}'", i + 1, value)
+                    })?)
+                }
+                _ => unreachable!("columns validated against header"),
+            }
+        }
+        if entry.name.is_empty() {
+            anyhow::bail!("Sample sheet line {}: empty sample name", i + 1);
+        }
+        samples.push(entry);
+    }
+
+    if samples.is_empty() {
+        anyhow::bail!("Sample sheet {:?} lists no samples", path);
+    }
+    Ok(samples)
+}
+
+/// Run `f` for every sample (up to `parallel` at a time), then print and write
+/// an aggregate summary to `<output>/samples_summary.json`
+pub(crate) fn run_samples<F>(
+    samples: &[SampleEntry],
+    parallel: usize,
+    output: &Path,
+    f: F,
+) -> Result<()>
+where
+    F: Fn(&SampleEntry, PathBuf) -> Result<SampleMetrics> + Sync,
+{
+    println!("Found {} samples in sample sheet\n", samples.len());
+    std::fs::create_dir_all(output)?;
+
+    let run_one = |sample: &SampleEntry| {
+        println!("Processing sample: {}", sample.name);
+        (sample.name.clone(), f(sample, output.join(&sample.name)))
+    };
+    let results: Vec<(String, Result<SampleMetrics>)> = if parallel > 1 {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(parallel)
+            .build()
+            .context("Failed to build thread pool")?;
+        pool.install(|| samples.par_iter().map(run_one).collect())
+    } else {
+        samples.iter().map(run_one).collect()
+    };
+
+    println!("\n=== Sample Summary ===");
+    let mut summary = Vec::new();
+    let mut failed = 0;
+    for (name, result) in &results {
+        match result {
+            Ok(metrics) => {
+                let line: Vec<String> = metrics
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, format_metric(*v)))
+                    .collect();
+                println!("  [OK]   {:<20} {}", name, line.join("  "));
+                let mut entry = serde_json::Map::new();
+                entry.insert("sample".into(), name.clone().into());
+                entry.insert("status".into(), "ok".into());
+                for (k, v) in metrics {
+                    entry.insert((*k).into(), serde_json::json!(v));
+                }
+                summary.push(serde_json::Value::Object(entry));
+            }
+            Err(e) => {
+                println!("  [FAIL] {:<20} {:#}", name, e);
+                summary.push(serde_json::json!({
+                    "sample": name,
+                    "status": "failed",
+                    "error": format!("{:#}", e),
+                }));
+                failed += 1;
+            }
+        }
+    }
+
+    let summary_path = output.join("samples_summary.json");
+    sparc_core::atomic::write(&summary_path, serde_json::to_string_pretty(&summary)?)?;
+    println!(
+        "\nTotal: {} succeeded, {} failed",
+        results.len() - failed,
+        failed
+    );
+    println!("Summary written to {:?}", summary_path);
+
+    if failed > 0 {
+        anyhow::bail!("{} samples failed", failed);
+    }
+    Ok(())
+}
+
+fn format_metric(v: f64) -> String {
+    if v.fract() == 0.0 {
+        format!("{}", v as i64)
+    } else {
+        format!("{:.2}", v)
+    }
+}
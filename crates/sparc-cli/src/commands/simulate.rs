@@ -0,0 +1,125 @@
+//! Simulate single-cell reads with a known ground truth
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::fastq::FastqWriter;
+use sparc_core::sim::{simulate, SimConfig};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct SimulateArgs {
+    /// Output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Number of cells
+    #[arg(long, default_value = "100")]
+    cells: usize,
+
+    /// Number of empty droplets (ambient RNA only)
+    #[arg(long, default_value = "500")]
+    empty: usize,
+
+    /// Number of genes
+    #[arg(long, default_value = "500")]
+    genes: usize,
+
+    /// Number of cell types
+    #[arg(long, default_value = "3")]
+    cell_types: usize,
+
+    /// Mean UMIs per cell (log-normally distributed)
+    #[arg(long, default_value = "1000")]
+    umis_per_cell: f64,
+
+    /// Log-normal sigma of UMIs per cell
+    #[arg(long, default_value = "0.5")]
+    umi_sigma: f64,
+
+    /// Mean UMIs per empty droplet
+    #[arg(long, default_value = "5")]
+    empty_umis: f64,
+
+    /// Fraction of each cell's molecules from ambient RNA
+    #[arg(long, default_value = "0.05")]
+    ambient: f64,
+
+    /// Mean reads per molecule (PCR duplicates)
+    #[arg(long, default_value = "2")]
+    reads_per_umi: f64,
+
+    /// Per-base barcode substitution rate
+    #[arg(long, default_value = "0.005")]
+    barcode_error: f64,
+
+    /// Per-base UMI substitution rate
+    #[arg(long, default_value = "0.005")]
+    umi_error: f64,
+
+    /// Per-base cDNA substitution rate
+    #[arg(long, default_value = "0.002")]
+    cdna_error: f64,
+
+    /// Barcode length
+    #[arg(long, default_value = "16")]
+    barcode_len: usize,
+
+    /// UMI length
+    #[arg(long, default_value = "12")]
+    umi_len: usize,
+
+    /// R2 read length
+    #[arg(long, default_value = "90")]
+    read_len: usize,
+}
+
+pub fn run(args: SimulateArgs) -> Result<()> {
+    let config = SimConfig {
+        n_cells: args.cells,
+        n_empty: args.empty,
+        n_genes: args.genes,
+        n_cell_types: args.cell_types,
+        mean_umis: args.umis_per_cell,
+        umi_sigma: args.umi_sigma,
+        empty_umis: args.empty_umis,
+        ambient_fraction: args.ambient,
+        reads_per_umi: args.reads_per_umi,
+        barcode_error_rate: args.barcode_error,
+        umi_error_rate: args.umi_error,
+        cdna_error_rate: args.cdna_error,
+        barcode_len: args.barcode_len,
+        umi_len: args.umi_len,
+        read_len: args.read_len,
+        seed: sparc_core::seed::global(),
+        ..SimConfig::default()
+    };
+
+    std::fs::create_dir_all(&args.output)?;
+    let r1_path = args.output.join("sim_R1.fastq.gz");
+    let r2_path = args.output.join("sim_R2.fastq.gz");
+    let mut w1 = FastqWriter::new(&r1_path).context("Failed to create R1 output")?;
+    let mut w2 = FastqWriter::new(&r2_path).context("Failed to create R2 output")?;
+    let truth = simulate(&config, |r1, r2| {
+        w1.write_record(&r1)?;
+        w2.write_record(&r2)
+    })?;
+    w1.finish()?;
+    w2.finish()?;
+    truth.write(&args.output)?;
+
+    let stats = &truth.stats;
+    println!("\n=== Simulation Summary ===");
+    println!("Cells:                {}", stats.cells);
+    println!("Empty droplets:       {}", stats.empty_droplets);
+    println!("Genes:                {}", truth.genes.len());
+    println!("Molecules:            {}", stats.molecules);
+    println!("Ambient molecules:    {}", stats.ambient_molecules);
+    println!("Read pairs:           {}", stats.reads);
+    println!("Barcode errors:       {} reads", stats.barcode_error_reads);
+    println!("UMI errors:           {} reads", stats.umi_error_reads);
+    println!("\nOutput: {:?}", args.output);
+    println!("  Reads: {:?}, {:?}", r1_path, r2_path);
+    println!("  Truth: {:?}", args.output.join("truth"));
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! Attach Visium spot coordinates to a count matrix
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::count::CountMatrix;
+use sparc_core::spatial::{ScaleFactors, SpotTable};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct SpatialArgs {
+    /// Count matrix directory (matrix.mtx, barcodes.tsv, features.tsv)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Spot positions: tissue_positions.csv or a Visium coordinates whitelist
+    #[arg(long)]
+    positions: PathBuf,
+
+    /// Image scale factors (scalefactors_json.json)
+    #[arg(long)]
+    scalefactors: PathBuf,
+
+    /// Output directory (writes <OUTPUT>/spatial/)
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// High-resolution tissue image, copied to tissue_hires_image.png
+    #[arg(long)]
+    hires_image: Option<PathBuf>,
+
+    /// Low-resolution tissue image, copied to tissue_lowres_image.png
+    #[arg(long)]
+    lowres_image: Option<PathBuf>,
+}
+
+pub fn run(args: SpatialArgs) -> Result<()> {
+    let matrix = CountMatrix::read_mtx(&args.input)
+        .with_context(|| format!("Failed to read matrix from {:?}", args.input))?;
+    let spots = SpotTable::from_file(&args.positions)
+        .with_context(|| format!("Failed to load spot positions {:?}", args.positions))?;
+    if spots.is_empty() {
+        anyhow::bail!("No spots found in {:?}", args.positions);
+    }
+    let scale = ScaleFactors::from_json(&args.scalefactors)?;
+
+    let dir = args.output.join("spatial");
+    let placed = spots.write_spatial_dir(&dir, &matrix.barcodes, &scale)?;
+    for (image, name) in [
+        (&args.hires_image, "tissue_hires_image.png"),
+        (&args.lowres_image, "tissue_lowres_image.png"),
+    ] {
+        if let Some(image) = image {
+            std::fs::copy(image, dir.join(name))
+                .with_context(|| format!("Failed to copy image {:?}", image))?;
+        }
+    }
+
+    let unplaced = matrix.barcodes.len() - placed;
+    println!("\n=== Spatial Summary ===");
+    println!("Spots:            {}", spots.len());
+    println!("Matrix barcodes:  {}", matrix.barcodes.len());
+    println!("On slide:         {}", placed);
+    if unplaced > 0 {
+        log::warn!(
+            "{} matrix barcodes are not in {:?}; check the slide serial/whitelist",
+            unplaced,
+            args.positions
+        );
+    }
+    println!("\nOutput: {:?}", dir);
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! Quick inspection of FASTQ and BAM files from a sample of records
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::qc::stats::{file_stats, FileContent, FileStats};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct StatsArgs {
+    /// FASTQ (.fastq[.gz|.zst]) or BAM files to inspect
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Records to sample per file (0 = read entire file)
+    #[arg(short = 'n', long, default_value = "100000")]
+    max_records: u64,
+
+    /// Write statistics as JSON
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+pub fn run(args: StatsArgs) -> Result<()> {
+    let mut all = Vec::new();
+    for path in &args.files {
+        let stats = file_stats(path, args.max_records)
+            .with_context(|| format!("Failed to read {:?}", path))?;
+        print_stats(&stats);
+        all.push(stats);
+    }
+
+    if let Some(path) = &args.json {
+        sparc_core::atomic::write(path, serde_json::to_string_pretty(&all)?)?;
+        println!("Statistics written to {:?}", path);
+    }
+
+    Ok(())
+}
+
+fn print_stats(stats: &FileStats) {
+    println!("=== {} ===", stats.path);
+    println!(
+        "File size:      {:.1} MB ({:?})",
+        stats.file_size as f64 / 1_048_576.0,
+        stats.compression
+    );
+    let count_label = if stats.complete { "" } else { " (estimated)" };
+
+    match &stats.content {
+        FileContent::Fastq { encoding, stats: fq } => {
+            println!("Reads:          {}{}", stats.estimated_records, count_label);
+            if !stats.complete {
+                println!("Sampled reads:  {}", fq.reads);
+            }
+            println!(
+                "Read length:    min {}, mean {:.1}, max {}",
+                fq.min_length,
+                fq.mean_length(),
+                fq.max_length
+            );
+            let mut lengths: Vec<(&usize, &u64)> = fq.length_distribution.iter().collect();
+            lengths.sort_by(|a, b| b.1.cmp(a.1));
+            let top: Vec<String> = lengths
+                .iter()
+                .take(3)
+                .map(|(len, n)| {
+                    format!("{} bp ({:.1}%)", len, **n as f64 / fq.reads.max(1) as f64 * 100.0)
+                })
+                .collect();
+            println!("Common lengths: {}", top.join(", "));
+            println!("Encoding:       {}", encoding.name());
+            println!(
+                "Mean quality:   {:.1} (Q30 bases: {:.1}%)",
+                fq.mean_quality(),
+                fq.q30_fraction() * 100.0
+            );
+            println!(
+                "GC content:     {:.1}% (N bases: {})",
+                fq.gc_fraction() * 100.0,
+                fq.n_bases
+            );
+        }
+        FileContent::Bam(bam) => {
+            println!("Records:        {}{}", stats.estimated_records, count_label);
+            if !stats.complete {
+                println!("Sampled:        {}", bam.records);
+            }
+            println!(
+                "Mapped:         {} ({:.1}%)",
+                bam.mapped,
+                bam.fraction(bam.mapped) * 100.0
+            );
+            println!(
+                "Sort order:     header {}, observed {}",
+                bam.header_sort_order.as_deref().unwrap_or("none"),
+                if bam.coordinate_sorted { "coordinate" } else { "unsorted" }
+            );
+            println!("CB tag:         {:.1}%", bam.fraction(bam.with_cb) * 100.0);
+            println!("UB tag:         {:.1}%", bam.fraction(bam.with_ub) * 100.0);
+            println!("GX/GN tag:      {:.1}%", bam.fraction(bam.with_gene) * 100.0);
+        }
+    }
+    println!();
+}
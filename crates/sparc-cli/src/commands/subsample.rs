@@ -0,0 +1,153 @@
+//! Reproducible FASTQ subsampling with synchronized R1/R2 pairs
+
+use anyhow::{Context, Result};
+use clap::Args;
+use sparc_core::fastq::{
+    subsample::{keep_by_hash, PairReservoir, SampleMode},
+    FastqParser, FastqRecord, FastqWriter, PairedFastqParser,
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct SubsampleArgs {
+    /// Input R1 FASTQ file
+    #[arg(short = '1', long)]
+    r1: PathBuf,
+
+    /// Input R2 FASTQ file (optional, kept in sync with R1)
+    #[arg(short = '2', long)]
+    r2: Option<PathBuf>,
+
+    /// Output directory (files keep their input names, prefixed with R1_/R2_
+    /// when both inputs share a name)
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Fraction of reads to keep (hash-based, streaming)
+    #[arg(long, conflicts_with = "reads", required_unless_present = "reads")]
+    fraction: Option<f64>,
+
+    /// Exact number of reads to keep (seeded reservoir, holds the sample in memory)
+    #[arg(long)]
+    reads: Option<usize>,
+}
+
+/// Output file in `dir` for `input`, prefixed with `mate` when given.
+/// Refuses a path that is one of the `inputs`.
+fn output_path(dir: &Path, input: &Path, mate: Option<&str>, inputs: &[&Path]) -> Result<PathBuf> {
+    let name = input
+        .file_name()
+        .with_context(|| format!("Invalid input path: {:?}", input))?;
+    let name = match mate {
+        Some(mate) => format!("{}_{}", mate, name.to_string_lossy()).into(),
+        None => name.to_os_string(),
+    };
+    let path = dir.join(&name);
+    let resolved = dir.canonicalize()?.join(&name);
+    if inputs.iter().any(|input| input.canonicalize().is_ok_and(|p| p == resolved)) {
+        anyhow::bail!("Output {:?} would overwrite an input; choose another --output", path);
+    }
+    Ok(path)
+}
+
+pub fn run(args: SubsampleArgs) -> Result<()> {
+    let mode = match (args.fraction, args.reads) {
+        (Some(f), _) => {
+            if !(0.0..=1.0).contains(&f) {
+                anyhow::bail!("--fraction must be between 0 and 1, got {}", f);
+            }
+            SampleMode::Fraction(f)
+        }
+        (None, Some(n)) => SampleMode::Count(n),
+        (None, None) => anyhow::bail!("One of --fraction or --reads is required"),
+    };
+
+    std::fs::create_dir_all(&args.output)?;
+
+    let mut inputs = vec![args.r1.as_path()];
+    inputs.extend(args.r2.as_deref());
+    // R1 and R2 from different directories may share a file name
+    let same_name = args.r2.as_ref().is_some_and(|r2| r2.file_name() == args.r1.file_name());
+    let (mate1, mate2) = if same_name { (Some("R1"), Some("R2")) } else { (None, None) };
+
+    let out1 = output_path(&args.output, &args.r1, mate1, &inputs)?;
+    let mut w1 = FastqWriter::new(&out1).context("Failed to create R1 output")?;
+    let (out2, mut w2) = match &args.r2 {
+        Some(r2) => {
+            let p = output_path(&args.output, r2, mate2, &inputs)?;
+            let w = FastqWriter::new(&p).context("Failed to create R2 output")?;
+            (Some(p), Some(w))
+        }
+        None => (None, None),
+    };
+
+    let pairs: Box<dyn Iterator<Item = sparc_core::Result<(FastqRecord, Option<FastqRecord>)>>> =
+        match &args.r2 {
+            Some(r2) => Box::new(
+                PairedFastqParser::open(&args.r1, r2)
+                    .context("Failed to open R1/R2 FASTQ")?
+                    .map(|r| r.map(|(a, b)| (a, Some(b)))),
+            ),
+            None => Box::new(
+                FastqParser::open(&args.r1)
+                    .context("Failed to open R1 FASTQ")?
+                    .map(|r| r.map(|a| (a, None))),
+            ),
+        };
+
+    let seed = sparc_core::seed::global();
+    let mut total = 0u64;
+    let mut kept = 0u64;
+
+    let mut write_pair = |r1: &FastqRecord, r2: Option<&FastqRecord>| -> Result<()> {
+        w1.write_record(r1)?;
+        if let (Some(w), Some(r)) = (w2.as_mut(), r2) {
+            w.write_record(r)?;
+        }
+        Ok(())
+    };
+
+    match mode {
+        SampleMode::Fraction(fraction) => {
+            for result in pairs {
+                let (r1, r2) = result?;
+                total += 1;
+                if keep_by_hash(r1.name(), seed, fraction) {
+                    write_pair(&r1, r2.as_ref())?;
+                    kept += 1;
+                }
+            }
+        }
+        SampleMode::Count(n) => {
+            let mut reservoir = PairReservoir::new(n, seed);
+            for result in pairs {
+                let (r1, r2) = result?;
+                reservoir.offer(r1, r2);
+            }
+            total = reservoir.seen();
+            for (r1, r2) in reservoir.into_sorted() {
+                write_pair(&r1, r2.as_ref())?;
+                kept += 1;
+            }
+        }
+    }
+
+    w1.finish()?;
+    if let Some(w) = w2 {
+        w.finish()?;
+    }
+
+    println!("\n=== Subsampling Summary ===");
+    println!("Input reads:  {}", total);
+    println!("Kept reads:   {} ({:.1}%)",
+        kept,
+        kept as f64 / total.max(1) as f64 * 100.0
+    );
+    println!("Seed:         {}", seed);
+    println!("Output:       {:?}", out1);
+    if let Some(p) = out2 {
+        println!("              {:?}", p);
+    }
+
+    Ok(())
+}
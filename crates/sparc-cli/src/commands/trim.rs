@@ -0,0 +1,121 @@
+//! Trim adapters, polyA tails, and template-switch oligos from FASTQ reads
+
+use anyhow::{Context, Result};
+use clap::Args;
+use indicatif::{ProgressBar, ProgressStyle};
+use sparc_core::fastq::{
+    trim::{NEXTERA_ADAPTER, TRUSEQ_ADAPTER},
+    FastqParser, FastqWriter, TrimConfig, TrimStats, Trimmer,
+};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct TrimArgs {
+    /// Input FASTQ file (typically R2 / cDNA read)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output FASTQ file (.gz for gzip compression)
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// 3' adapter sequence (repeatable; defaults to TruSeq and Nextera)
+    #[arg(long)]
+    adapter: Vec<String>,
+
+    /// 5' template-switch oligo sequence
+    #[arg(long, default_value = "AAGCAGTGGTATCAACGCAGAGTACATGGG")]
+    tso: String,
+
+    /// Disable TSO trimming
+    #[arg(long)]
+    no_tso: bool,
+
+    /// Minimum polyA tail length to trim (0 disables polyA trimming)
+    #[arg(long, default_value = "10")]
+    min_polya: usize,
+
+    /// Minimum adapter overlap at the read end
+    #[arg(long, default_value = "5")]
+    min_overlap: usize,
+
+    /// Maximum mismatch rate within an adapter/TSO match
+    #[arg(long, default_value = "0.1")]
+    max_error_rate: f64,
+
+    /// Discard reads shorter than this after trimming
+    #[arg(long, default_value = "20")]
+    min_length: usize,
+}
+
+pub fn run(args: TrimArgs) -> Result<()> {
+    let adapters = if args.adapter.is_empty() {
+        vec![TRUSEQ_ADAPTER.to_vec(), NEXTERA_ADAPTER.to_vec()]
+    } else {
+        args.adapter.iter().map(|a| a.as_bytes().to_vec()).collect()
+    };
+
+    let config = TrimConfig {
+        adapters,
+        tso: if args.no_tso {
+            None
+        } else {
+            Some(args.tso.as_bytes().to_vec())
+        },
+        min_polya_len: args.min_polya,
+        min_overlap: args.min_overlap,
+        max_error_rate: args.max_error_rate,
+        min_length: args.min_length,
+    };
+    let trimmer = Trimmer::new(config);
+
+    let mut parser = FastqParser::open(&args.input).context("Failed to open input FASTQ")?;
+    let mut writer = FastqWriter::new(&args.output).context("Failed to create output FASTQ")?;
+
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .expect("valid progress template"),
+    );
+
+    let mut stats = TrimStats::default();
+    while let Some(result) = parser.next_ref() {
+        let (record, trim) = trimmer.trim_ref(result?);
+        stats.update(&trim, args.min_length);
+
+        if stats.total_reads % 100000 == 0 {
+            progress.set_message(format!("Trimmed {} reads", stats.total_reads));
+        }
+
+        if trim.length >= args.min_length {
+            writer.write_record_ref(record)?;
+        }
+    }
+    writer.finish()?;
+
+    progress.finish_with_message(format!("Done! Trimmed {} reads", stats.total_reads));
+
+    let total = stats.total_reads.max(1) as f64;
+    println!("\n=== Trimming Summary ===");
+    println!("Total reads:          {}", stats.total_reads);
+    println!("TSO trimmed:          {} ({:.1}%)",
+        stats.tso_trimmed_reads,
+        stats.tso_trimmed_reads as f64 / total * 100.0
+    );
+    println!("Adapter trimmed:      {} ({:.1}%)",
+        stats.adapter_trimmed_reads,
+        stats.adapter_trimmed_reads as f64 / total * 100.0
+    );
+    println!("PolyA trimmed:        {} ({:.1}%)",
+        stats.polya_trimmed_reads,
+        stats.polya_trimmed_reads as f64 / total * 100.0
+    );
+    println!("Too short (dropped):  {} ({:.1}%)",
+        stats.too_short_reads,
+        stats.too_short_reads as f64 / total * 100.0
+    );
+    println!("Bases removed:        {}", stats.bases_removed);
+
+    Ok(())
+}
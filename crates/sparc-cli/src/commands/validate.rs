@@ -1,15 +1,22 @@
-//! `sparc validate` - Run truthset validation against synthetic ground-truth data
+//! `sparc validate` - Run truthset validation against synthetic ground-truth data,
+//! or check real inputs before a run with `sparc validate inputs`
 
 use anyhow::Result;
-use clap::Args;
+use clap::{Args, Subcommand};
 use std::path::PathBuf;
 
+use super::pipeline::get_protocol;
+use sparc_core::validation::inputs::{self, CheckStatus, InputReport};
 use sparc_core::validation::report::{ValidationReport, ValidationThresholds};
 use sparc_core::validation::stages;
 use sparc_core::validation::synthetic::{SyntheticConfig, SyntheticDataset};
 
 #[derive(Args)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct ValidateArgs {
+    #[command(subcommand)]
+    command: Option<ValidateCommand>,
+
     /// Output directory for validation report
     #[arg(short, long, default_value = "validation_output")]
     output: PathBuf,
@@ -34,10 +41,6 @@ pub struct ValidateArgs {
     #[arg(long, default_value = "all")]
     stages: String,
 
-    /// Random seed for reproducibility
-    #[arg(long, default_value = "42")]
-    seed: u64,
-
     /// Minimum barcode F1 score for pass
     #[arg(long, default_value = "0.95")]
     min_barcode_f1: f64,
@@ -51,7 +54,114 @@ pub struct ValidateArgs {
     min_clustering_ari: f64,
 }
 
+#[derive(Subcommand)]
+enum ValidateCommand {
+    /// Check input integrity (pairing, whitelist, GTF, BAM tags, encoding) before a run
+    Inputs(InputsArgs),
+}
+
+#[derive(Args)]
+struct InputsArgs {
+    /// Input R1 FASTQ file
+    #[arg(short = '1', long, requires = "r2")]
+    r1: Option<PathBuf>,
+
+    /// Input R2 FASTQ file
+    #[arg(short = '2', long, requires = "r1")]
+    r2: Option<PathBuf>,
+
+    /// Barcode whitelist file
+    #[arg(short = 'w', long)]
+    whitelist: Option<PathBuf>,
+
+    /// Protocol the inputs are expected to match
+    #[arg(short, long, default_value = "10x-3prime-v3")]
+    protocol: String,
+
+    /// Gene annotation GTF file
+    #[arg(short, long)]
+    gtf: Option<PathBuf>,
+
+    /// Aligned BAM file to check for CB/UB/GX tags
+    #[arg(short, long)]
+    bam: Option<PathBuf>,
+
+    /// Read pairs to check (0 = entire files)
+    #[arg(long, default_value = "100000")]
+    max_records: u64,
+
+    /// Write the check results as JSON
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+fn run_inputs(args: InputsArgs) -> Result<()> {
+    if args.r1.is_none() && args.whitelist.is_none() && args.gtf.is_none() && args.bam.is_none() {
+        anyhow::bail!("Nothing to check: pass --r1/--r2, --whitelist, --gtf, and/or --bam");
+    }
+
+    let protocol = get_protocol(&args.protocol)?;
+    let rs = protocol.read_structure();
+    let min_r1_len = (rs.barcode_start + rs.barcode_len).max(rs.umi_start + rs.umi_len);
+
+    let mut report = InputReport::default();
+    if let (Some(r1), Some(r2)) = (&args.r1, &args.r2) {
+        println!("Checking FASTQ pair...");
+        report
+            .checks
+            .extend(inputs::check_fastq_pair(r1, r2, args.max_records, min_r1_len));
+    }
+    if let Some(whitelist) = &args.whitelist {
+        println!("Checking whitelist...");
+        let check = match protocol.barcode_tiers() {
+            Some(tiers) => inputs::check_tiered_whitelist(whitelist, tiers),
+            None => inputs::check_whitelist(whitelist, rs.barcode_len),
+        };
+        report.checks.push(check);
+    }
+    if let Some(gtf) = &args.gtf {
+        println!("Checking GTF...");
+        report.checks.push(inputs::check_gtf(gtf));
+    }
+    if let Some(bam) = &args.bam {
+        println!("Checking BAM tags...");
+        report.checks.extend(inputs::check_bam_tags(bam, 10_000));
+    }
+
+    println!("\n=== Input Validation ===");
+    for check in &report.checks {
+        let status = match check.status {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        };
+        println!("[{}] {:<18} {}", status, check.name, check.message);
+    }
+
+    if let Some(path) = &args.json {
+        sparc_core::atomic::write(path, serde_json::to_string_pretty(&report)?)?;
+        println!("\nResults written to: {}", path.display());
+    }
+
+    if report.passed() {
+        println!(
+            "\nAll checks passed ({} warning(s))",
+            report.count(CheckStatus::Warn)
+        );
+        Ok(())
+    } else {
+        anyhow::bail!(
+            "{} input check(s) failed — fix the issues above before running",
+            report.count(CheckStatus::Fail)
+        )
+    }
+}
+
 pub fn run(args: ValidateArgs) -> Result<()> {
+    if let Some(ValidateCommand::Inputs(inputs_args)) = args.command {
+        return run_inputs(inputs_args);
+    }
+
     let stages_to_run: Vec<&str> = if args.stages == "all" {
         vec!["extract", "count", "analysis"]
     } else {
@@ -62,7 +172,7 @@ pub fn run(args: ValidateArgs) -> Result<()> {
         n_cells: args.n_cells,
         n_genes: args.n_genes,
         n_cell_types: args.n_cell_types,
-        seed: args.seed,
+        seed: sparc_core::seed::global(),
         protocol: args.protocol.clone(),
         ..Default::default()
     };
@@ -0,0 +1,75 @@
+//! Count spliced, unspliced, and ambiguous molecules for RNA velocity
+
+use anyhow::{Context, Result};
+use clap::Args;
+use indicatif::{ProgressBar, ProgressStyle};
+use sparc_core::annotation::{GeneAnnotation, GeneAssigner, OverlapMode, Strandedness};
+use sparc_core::velocity::count_velocity;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct VelocityArgs {
+    /// Input aligned BAM file (with CB and UB tags)
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Gene annotation GTF file (.gz supported)
+    #[arg(short, long)]
+    gtf: PathBuf,
+
+    /// Output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Minimum mapping quality
+    #[arg(long, default_value = "30")]
+    min_mapq: u8,
+
+    /// Multi-gene overlap policy: unique, union, or intersection-strict
+    #[arg(long, default_value = "unique")]
+    overlap_mode: OverlapMode,
+
+    /// Library strandedness: forward, reverse, or unstranded
+    #[arg(long, default_value = "unstranded")]
+    strandedness: Strandedness,
+}
+
+pub fn run(args: VelocityArgs) -> Result<()> {
+    let annotation = GeneAnnotation::from_gtf(&args.gtf).context("Failed to load GTF")?;
+    if annotation.is_empty() {
+        anyhow::bail!("No genes found in {:?}", args.gtf);
+    }
+
+    let progress = ProgressBar::new_spinner();
+    progress.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {msg}")
+            .expect("valid progress template"),
+    );
+    progress.set_message(format!("Classifying reads in {:?}", args.input));
+
+    let assigner = GeneAssigner::new(&annotation)
+        .overlap_mode(args.overlap_mode)
+        .strandedness(args.strandedness);
+    let (matrices, stats) = count_velocity(&args.input, &assigner, args.min_mapq)
+        .context("Failed to count velocity matrices")?;
+    progress.finish_with_message(format!("Done! Classified {} reads", stats.total_reads));
+
+    matrices.write(&args.output)?;
+    sparc_core::atomic::write(
+        args.output.join("region_stats.json"),
+        serde_json::to_string_pretty(&stats)?,
+    )?;
+
+    let [spliced, unspliced, ambiguous] = matrices.totals();
+    let total = (spliced + unspliced + ambiguous).max(1) as f64;
+    let pct = |n: u64| n as f64 / total * 100.0;
+    println!("\n=== Velocity Summary ===");
+    println!("Cells:           {}", matrices.spliced.n_cols);
+    println!("Spliced:         {} ({:.1}%)", spliced, pct(spliced));
+    println!("Unspliced:       {} ({:.1}%)", unspliced, pct(unspliced));
+    println!("Ambiguous:       {} ({:.1}%)", ambiguous, pct(ambiguous));
+    println!("\nOutput: {:?}", args.output);
+
+    Ok(())
+}
@@ -0,0 +1,301 @@
+//! `--config run.toml` support
+//!
+//! Config values are turned into command-line arguments for the invoked
+//! subcommand, skipping any option already given on the command line so that
+//! explicit flags always win. Top-level keys apply to every subcommand that
+//! accepts them; a `[<subcommand>]` table overrides them for that command
+//! (nested subcommands use nested tables, e.g. `[validate.inputs]`):
+//!
+//! ```toml
+//! protocol = "10x-3prime-v3"
+//! threads = 8
+//!
+//! [extract]
+//! decompress_threads = 2
+//! compress_threads = 6
+//!
+//! [pipeline]
+//! r1 = "data/R1.fastq.gz"
+//! r2 = "data/R2.fastq.gz"
+//! whitelist = "ref/3M-february-2018.txt"
+//! output = "results/"
+//! expect_cells = 5000
+//! ```
+
+use anyhow::{bail, Context, Result};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Expand `--config <FILE>` into explicit arguments for the invoked subcommand
+pub fn expand_args(args: Vec<OsString>, cmd: &Command) -> Result<Vec<OsString>> {
+    let Some(config_path) = find_config(&args) else {
+        return Ok(args);
+    };
+    let path = find_subcommand(&args, cmd);
+    let Some(&(sub_pos, sub)) = path.last() else {
+        return Ok(args);
+    };
+
+    let table = load(&config_path)?;
+    let names: Vec<&str> = path.iter().map(|(_, c)| c.get_name()).collect();
+    let sub_name = names.join(".");
+
+    let mut entries: Vec<(String, toml::Value, bool)> = table
+        .iter()
+        .filter(|(_, v)| !v.is_table())
+        .map(|(k, v)| (k.replace('_', "-"), v.clone(), false))
+        .collect();
+    let mut section = Some(&table);
+    for name in &names {
+        section = match section.and_then(|t| t.get(*name)) {
+            Some(value) => Some(
+                value
+                    .as_table()
+                    .with_context(|| format!("Config key '{}' must be a table", sub_name))?,
+            ),
+            None => None,
+        };
+    }
+    if let Some(section) = section {
+        for (k, v) in section.iter().filter(|(_, v)| !v.is_table()) {
+            let key = k.replace('_', "-");
+            entries.retain(|(existing, _, _)| *existing != key);
+            entries.push((key, v.clone(), true));
+        }
+    }
+
+    let options: Vec<&Arg> = sub.get_arguments().chain(cmd.get_arguments()).collect();
+    let mut extra: Vec<OsString> = Vec::new();
+    for (key, value, scoped) in entries {
+        let arg = sub
+            .get_arguments()
+            .chain(cmd.get_arguments())
+            .find(|a| a.get_long() == Some(key.as_str()));
+        let Some(arg) = arg else {
+            if scoped {
+                bail!(
+                    "Unknown option '{}' in [{}] section of {:?}",
+                    key,
+                    sub_name,
+                    config_path
+                );
+            }
+            continue;
+        };
+        if key == "config" || given_on_command_line(&args, arg, &options) {
+            continue;
+        }
+        push_value(&mut extra, &key, &value)
+            .with_context(|| format!("Invalid value for '{}' in {:?}", key, config_path))?;
+    }
+
+    let mut expanded = args[..=sub_pos].to_vec();
+    expanded.extend(extra);
+    expanded.extend_from_slice(&args[sub_pos + 1..]);
+    Ok(expanded)
+}
+
+/// Resolve `sparc [GLOBALS] <command...>` (with `--config` applied) and render
+/// every parameter, defaults included, as a TOML run configuration.
+///
+/// `argv` is the full invocation of `sparc config dump`; global options given
+/// before `config` are forwarded to the dumped command.
+pub fn dump(argv: &[OsString], command: &[OsString], cmd: &Command) -> Result<String> {
+    let config_pos = find_subcommand(argv, cmd)
+        .first()
+        .map_or(argv.len(), |(pos, _)| *pos);
+    let mut args = argv[..config_pos].to_vec();
+    args.extend_from_slice(command);
+
+    let args = expand_args(args, cmd)?;
+    let matches = cmd.clone().try_get_matches_from(&args)?;
+    let table = resolved_table(cmd, &matches);
+
+    let names = find_subcommand(&args, cmd)
+        .iter()
+        .map(|(_, c)| c.get_name())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Ok(format!(
+        "# sparc {} resolved parameters for `sparc {}`\n{}",
+        env!("CARGO_PKG_VERSION"),
+        names,
+        toml::to_string(&table)?
+    ))
+}
+
+/// All options of the invoked command path as a TOML table: global options at
+/// the top level, command options under `[<command>]` (nested for subcommands)
+pub fn resolved_table(cmd: &Command, matches: &ArgMatches) -> toml::Table {
+    let mut root = toml::Table::new();
+    insert_args(cmd, matches, &mut root, true);
+
+    let mut table = &mut root;
+    let (mut cmd, mut matches) = (cmd, matches);
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        let Some(sub) = cmd.find_subcommand(name) else {
+            break;
+        };
+        table = table
+            .entry(name.to_string())
+            .or_insert(toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .expect("subcommand entry is a table");
+        insert_args(sub, sub_matches, table, false);
+        (cmd, matches) = (sub, sub_matches);
+    }
+    root
+}
+
+fn insert_args(cmd: &Command, matches: &ArgMatches, table: &mut toml::Table, root: bool) {
+    for arg in cmd.get_arguments() {
+        // Positionals cannot be set from a config file; globals live at the top level
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        if long == "config" || (!root && arg.is_global_set()) {
+            continue;
+        }
+        let Ok(Some(raw)) = matches.try_get_raw(arg.get_id().as_str()) else {
+            continue;
+        };
+        let values: Vec<String> = raw.map(|v| v.to_string_lossy().to_string()).collect();
+        let value = match arg.get_action() {
+            ArgAction::SetTrue | ArgAction::SetFalse => {
+                toml::Value::Boolean(values.first().map_or(false, |v| v == "true"))
+            }
+            ArgAction::Append => toml::Value::Array(values.iter().map(|v| scalar(v)).collect()),
+            _ if values.len() == 1 => scalar(&values[0]),
+            _ => toml::Value::Array(values.iter().map(|v| scalar(v)).collect()),
+        };
+        table.insert(long.replace('-', "_"), value);
+    }
+}
+
+fn scalar(value: &str) -> toml::Value {
+    if let Ok(n) = value.parse::<i64>() {
+        toml::Value::Integer(n)
+    } else if let Some(f) = value.parse::<f64>().ok().filter(|f| f.is_finite()) {
+        toml::Value::Float(f)
+    } else {
+        toml::Value::String(value.to_string())
+    }
+}
+
+/// Read and parse a TOML config file
+pub fn load(path: &Path) -> Result<toml::Table> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config {:?}", path))?;
+    toml::from_str(&text).with_context(|| format!("Failed to parse config {:?}", path))
+}
+
+fn find_config(args: &[OsString]) -> Option<std::path::PathBuf> {
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--config" {
+            return iter.next().map(|p| p.into());
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.into());
+        }
+    }
+    None
+}
+
+/// Positions and definitions of the (possibly nested) subcommand tokens
+fn find_subcommand<'a>(args: &[OsString], cmd: &'a Command) -> Vec<(usize, &'a Command)> {
+    let mut path = Vec::new();
+    let mut current = cmd;
+    let mut i = 1;
+    while i < args.len() {
+        let token = args[i].to_string_lossy();
+        if token.starts_with('-') {
+            let takes_value = current.get_arguments().chain(cmd.get_arguments()).any(|a| {
+                a.get_action().takes_values()
+                    && (a.get_long().map_or(false, |l| token == format!("--{}", l))
+                        || a.get_short().map_or(false, |s| token == format!("-{}", s)))
+            });
+            i += if takes_value { 2 } else { 1 };
+            continue;
+        }
+        match current.find_subcommand(&*token) {
+            Some(sub) => {
+                path.push((i, sub));
+                current = sub;
+                i += 1;
+            }
+            None => break,
+        }
+    }
+    path
+}
+
+/// Whether `arg` was given in `args`. The values of `options` that take one
+/// are skipped, so the `-j` in `-o -j` is not mistaken for the `-j` flag.
+fn given_on_command_line(args: &[OsString], arg: &Arg, options: &[&Arg]) -> bool {
+    let takes_value = |is: &dyn Fn(&Arg) -> bool| {
+        options.iter().any(|a| a.get_action().takes_values() && is(a))
+    };
+    let mut tokens = args.iter().skip(1).map(|t| t.to_string_lossy());
+    while let Some(token) = tokens.next() {
+        if token == "--" {
+            break;
+        }
+        if let Some(long) = token.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, _)) => (name, true),
+                None => (long, false),
+            };
+            if arg.get_long() == Some(name) {
+                return true;
+            }
+            if !inline && takes_value(&|a| a.get_long() == Some(name)) {
+                tokens.next();
+            }
+        } else if let Some(shorts) = token.strip_prefix('-') {
+            // A cluster like `-vj4`: flags up to the first short taking a
+            // value, which is the rest of the cluster or the next token
+            for (i, c) in shorts.char_indices() {
+                if arg.get_short() == Some(c) {
+                    return true;
+                }
+                if takes_value(&|a| a.get_short() == Some(c)) {
+                    if i + c.len_utf8() == shorts.len() {
+                        tokens.next();
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    false
+}
+
+fn push_value(out: &mut Vec<OsString>, key: &str, value: &toml::Value) -> Result<()> {
+    let flag = format!("--{}", key);
+    match value {
+        toml::Value::Boolean(true) => out.push(flag.into()),
+        toml::Value::Boolean(false) => {}
+        toml::Value::String(s) => {
+            out.push(flag.into());
+            out.push(s.into());
+        }
+        toml::Value::Integer(n) => {
+            out.push(flag.into());
+            out.push(n.to_string().into());
+        }
+        toml::Value::Float(f) => {
+            out.push(flag.into());
+            out.push(f.to_string().into());
+        }
+        toml::Value::Array(items) => {
+            for item in items {
+                push_value(out, key, item)?;
+            }
+        }
+        other => bail!("unsupported value type {}", other.type_str()),
+    }
+    Ok(())
+}
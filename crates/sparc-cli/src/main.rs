@@ -1,9 +1,13 @@
 //! SPARC CLI - Single-cell Pipeline Accelerated in Rust Core
 
 mod commands;
+mod config;
+mod manifest;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use sparc_core::resources::{ResourceConfig, StageThreads};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "sparc")]
@@ -17,21 +21,111 @@ struct Cli {
     #[arg(short = 'j', long, global = true, default_value = "0")]
     threads: usize,
 
+    /// Threads decompressing input: BGZF blocks of BAMs, or a read-ahead
+    /// thread for gzip/zstd FASTQ when above 0 [default: per command]
+    #[arg(long, global = true, value_name = "N")]
+    decompress_threads: Option<usize>,
+
+    /// Threads processing records [default: -j]
+    #[arg(long, global = true, value_name = "N")]
+    worker_threads: Option<usize>,
+
+    /// Threads compressing BAM and FASTQ output [default: per command]
+    #[arg(long, global = true, value_name = "N")]
+    compress_threads: Option<usize>,
+
+    /// TOML run configuration; command-line flags override its values
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Directory for spill files and sort temporaries [default: $TMPDIR]
+    #[arg(long, global = true, value_name = "DIR")]
+    tmp_dir: Option<PathBuf>,
+
+    /// Approximate memory budget (e.g. 8G, 512M); counting and sorting spill to --tmp-dir beyond it
+    #[arg(long, global = true, value_name = "SIZE", value_parser = parse_memory)]
+    max_memory: Option<u64>,
+
+    /// Skip input and output checksums (run_manifest.json and checksums.txt)
+    #[arg(long, global = true)]
+    no_checksums: bool,
+
+    /// Random seed for subsampling and simulation (recorded in run_manifest.json)
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        default_value_t = sparc_core::seed::DEFAULT_SEED
+    )]
+    seed: u64,
+
+    /// FASTQ quality encoding (phred33 or phred64) [default: detect per file]
+    #[arg(long, global = true, value_name = "ENCODING")]
+    quality_encoding: Option<sparc_core::fastq::QualityEncoding>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Demultiplex samples by index reads (I1/I2)
+    Demux(commands::demux::DemuxArgs),
+
+    /// Assign cells to pooled donors from SNP genotypes in a BAM
+    GenotypeDemux(commands::genotype_demux::GenotypeDemuxArgs),
+
     /// Extract barcodes and UMIs from FASTQ files
     Extract(commands::extract::ExtractArgs),
 
+    /// Extract barcodes and UMIs from long reads (PacBio, ONT, MAS-Seq arrays)
+    ExtractLong(commands::extract_long::ExtractLongArgs),
+
+    /// Trim adapters, polyA tails, and TSO from FASTQ reads
+    Trim(commands::trim::TrimArgs),
+
+    /// Reproducibly subsample paired FASTQ files
+    SubsampleFastq(commands::subsample::SubsampleArgs),
+
+    /// Tag aligned reads with gene and region from a GTF
+    Annotate(commands::annotate::AnnotateArgs),
+
+    /// Count spliced/unspliced/ambiguous molecules for RNA velocity
+    Velocity(commands::velocity::VelocityArgs),
+
+    /// Build a transcriptome k-mer index for alignment-free counting
+    Index(commands::index::IndexArgs),
+
+    /// Index a BAM by cell barcode for fetching one cell's reads
+    IndexCb(commands::index_cb::IndexCbArgs),
+
     /// Generate gene count matrix
     Count(commands::count::CountArgs),
 
+    /// Count scATAC fragments in peaks to a cell x peak matrix
+    CountPeaks(commands::peaks::PeaksArgs),
+
+    /// Call CRISPR guides per cell from a guide count matrix
+    Crispr(commands::crispr::CrisprArgs),
+
+    /// Normalize antibody capture (ADT) counts (CLR or DSB-style)
+    Adt(commands::adt::AdtArgs),
+
+    /// Estimate ambient RNA contamination and correct counts
+    Ambient(commands::ambient::AmbientArgs),
+
+    /// Attach Visium spot coordinates and write a spatial/ folder
+    Spatial(commands::spatial::SpatialArgs),
+
+    /// Quick statistics for FASTQ/BAM files (sampled)
+    Stats(commands::stats::StatsArgs),
+
     /// Generate QC report
     Qc(commands::qc::QcArgs),
 
+    /// Call cells and apply QC thresholds to write a filtered matrix
+    Filter(commands::filter::FilterArgs),
+
     /// Run full analysis pipeline
     Pipeline(commands::pipeline::PipelineArgs),
 
@@ -46,10 +140,29 @@ enum Commands {
 
     /// Run truthset validation against synthetic ground-truth data
     Validate(commands::validate::ValidateArgs),
+
+    /// Simulate paired FASTQs with known cells, UMIs, and errors, plus the true matrix
+    Simulate(commands::simulate::SimulateArgs),
+
+    /// Benchmark pipeline stages on simulated reads to pick a thread count
+    Bench(commands::bench::BenchArgs),
+
+    /// Inspect run configuration (`config dump` prints resolved parameters as TOML)
+    Config(commands::config::ConfigArgs),
+
+    /// Generate shell completion scripts
+    Completions(commands::completions::CompletionsArgs),
+}
+
+fn parse_memory(s: &str) -> std::result::Result<u64, String> {
+    sparc_core::resources::parse_memory_size(s).map_err(|e| e.to_string())
 }
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let args = config::expand_args(std::env::args_os().collect(), &Cli::command())?;
+    let command = Cli::command();
+    let matches = command.clone().get_matches_from(&args);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
 
     // Initialize logger
     if cli.verbose {
@@ -58,22 +171,80 @@ fn main() -> Result<()> {
         env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     }
 
-    // Set thread count
-    if cli.threads > 0 {
+    if let Some(path) = &cli.config {
+        log::info!("Using run configuration: {:?}", path);
+    }
+
+    // Set thread count; --worker-threads overrides -j for the worker pool
+    let workers = cli.worker_threads.filter(|&n| n > 0).unwrap_or(cli.threads);
+    if workers > 0 {
         rayon::ThreadPoolBuilder::new()
-            .num_threads(cli.threads)
+            .num_threads(workers)
             .build_global()
             .ok();
     }
 
-    match cli.command {
+    let mut resources = ResourceConfig::default();
+    if let Some(dir) = &cli.tmp_dir {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Cannot create temporary directory {:?}", dir))?;
+        resources.tmp_dir = dir.clone();
+    }
+    resources.max_memory = cli.max_memory;
+    resources.threads = StageThreads {
+        decompress: cli.decompress_threads,
+        workers: cli.worker_threads.filter(|&n| n > 0),
+        compress: cli.compress_threads,
+    };
+    log::debug!("Resource limits: {:?}", resources);
+    sparc_core::resources::set_global(resources);
+    sparc_core::seed::set_global(cli.seed);
+    if let Some(encoding) = cli.quality_encoding {
+        sparc_core::fastq::set_global_encoding(encoding);
+    }
+
+    let recorder = manifest::RunRecorder::start(&args, &command, &matches, !cli.no_checksums);
+
+    let result = match cli.command {
+        Commands::Demux(args) => commands::demux::run(args),
+        Commands::GenotypeDemux(args) => commands::genotype_demux::run(args),
         Commands::Extract(args) => commands::extract::run(args),
+        Commands::ExtractLong(args) => commands::extract_long::run(args),
+        Commands::Trim(args) => commands::trim::run(args),
+        Commands::SubsampleFastq(args) => commands::subsample::run(args),
+        Commands::Annotate(args) => commands::annotate::run(args),
+        Commands::Velocity(args) => commands::velocity::run(args),
+        Commands::Index(args) => commands::index::run(args),
+        Commands::IndexCb(args) => commands::index_cb::run(args),
         Commands::Count(args) => commands::count::run(args),
+        Commands::CountPeaks(args) => commands::peaks::run(args),
+        Commands::Crispr(args) => commands::crispr::run(args),
+        Commands::Adt(args) => commands::adt::run(args),
+        Commands::Ambient(args) => commands::ambient::run(args),
+        Commands::Spatial(args) => commands::spatial::run(args),
+        Commands::Stats(args) => commands::stats::run(args),
         Commands::Qc(args) => commands::qc::run(args),
+        Commands::Filter(args) => commands::filter::run(args),
         Commands::Pipeline(args) => commands::pipeline::run(args),
         Commands::Batch(args) => commands::batch::run(args),
         Commands::Distributed(args) => commands::distributed::run(args),
         Commands::Analyze(args) => commands::analyze::run(args),
         Commands::Validate(args) => commands::validate::run(args),
+        Commands::Simulate(args) => commands::simulate::run(args),
+        Commands::Bench(args) => commands::bench::run(args),
+        Commands::Config(args) => {
+            commands::config::run(args, &std::env::args_os().collect::<Vec<_>>(), &command)
+        }
+        Commands::Completions(args) => commands::completions::run(args, command.clone()),
+    };
+
+    let counters = sparc_core::metrics::global().snapshot();
+    if !counters.is_empty() {
+        log::debug!("Run counters:\n{}", counters.summary().trim_end());
+    }
+    if let Some(bytes) = sparc_core::perf::peak_rss_bytes() {
+        log::debug!("Peak RSS: {:.1} MB", bytes as f64 / (1024.0 * 1024.0));
     }
+    recorder.finish(&result);
+    result
 }
@@ -0,0 +1,306 @@
+//! `run_manifest.json` provenance output
+//!
+//! Records the SPARC version, the fully-resolved parameters (defaults, config,
+//! and flags), input files with SHA-256 checksums, timings (overall and per
+//! stage, with peak RSS), the run's counters, and the files the command
+//! produced with their SHA-256 and MD5 checksums. Directory outputs get
+//! `<output>/run_manifest.json`; file outputs get `<output>.run_manifest.json`
+//! alongside the file.
+//!
+//! Output checksums are also written in `sha256sum` format to `checksums.txt`
+//! (or `<output>.checksums.txt`) so transferred results can be verified with
+//! `sha256sum -c`.
+
+use anyhow::Result;
+use clap::{ArgMatches, Command};
+use serde::Serialize;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use sparc_core::metrics::{self, MetricsSnapshot};
+use sparc_core::perf::{self, StageTiming};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Parameters naming output locations rather than inputs
+const OUTPUT_PARAMS: [&str; 2] = ["output", "json"];
+
+/// A file referenced by a run
+#[derive(Debug, Clone, Serialize)]
+pub struct FileEntry {
+    pub path: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+}
+
+/// Provenance record for one command invocation
+#[derive(Debug, Clone, Serialize)]
+pub struct RunManifest {
+    pub sparc_version: String,
+    pub command: String,
+    pub argv: Vec<String>,
+    pub parameters: BTreeMap<String, serde_json::Value>,
+    pub inputs: Vec<FileEntry>,
+    pub outputs: Vec<FileEntry>,
+    pub started_at: String,
+    pub finished_at: String,
+    pub wall_time_secs: f64,
+    /// Stages timed through `sparc_core::perf`, in completion order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<StageTiming>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_rss_bytes: Option<u64>,
+    /// Counters fed by the command's stages, by stage and name
+    #[serde(skip_serializing_if = "MetricsSnapshot::is_empty")]
+    pub metrics: MetricsSnapshot,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Captures run state at start and writes the manifest when the command ends
+pub struct RunRecorder {
+    manifest: RunManifest,
+    output: Option<PathBuf>,
+    /// Input files, checksummed only when the manifest is written
+    inputs: Vec<PathBuf>,
+    checksums: bool,
+    started: Instant,
+    started_system: SystemTime,
+}
+
+impl RunRecorder {
+    /// Snapshot parameters and input paths before the command runs
+    pub fn start(
+        argv: &[std::ffi::OsString],
+        cmd: &Command,
+        matches: &ArgMatches,
+        checksums: bool,
+    ) -> Self {
+        let started_system = SystemTime::now();
+        let mut parameters = BTreeMap::new();
+        collect_params(cmd, matches, &mut parameters);
+
+        let mut command = Vec::new();
+        let (mut cmd, mut matches) = (cmd, matches);
+        while let Some((name, sub_matches)) = matches.subcommand() {
+            command.push(name.to_string());
+            let Some(sub_cmd) = cmd.find_subcommand(name) else {
+                break;
+            };
+            collect_params(sub_cmd, sub_matches, &mut parameters);
+            (cmd, matches) = (sub_cmd, sub_matches);
+        }
+
+        // A dry run writes nothing, not even into an existing --output
+        let dry_run = parameters.get("dry_run").and_then(|v| v.as_str()) == Some("true");
+        let output = parameters
+            .get("output")
+            .and_then(|v| v.as_str())
+            .filter(|_| !dry_run)
+            .map(PathBuf::from);
+
+        let inputs = parameters
+            .iter()
+            .filter(|(name, _)| !OUTPUT_PARAMS.contains(&name.as_str()))
+            .flat_map(|(_, value)| match value {
+                serde_json::Value::Array(items) => items.clone(),
+                other => vec![other.clone()],
+            })
+            .filter_map(|v| v.as_str().map(PathBuf::from))
+            .filter(|p| p.is_file())
+            .collect();
+
+        Self {
+            manifest: RunManifest {
+                sparc_version: env!("CARGO_PKG_VERSION").to_string(),
+                command: command.join(" "),
+                argv: argv.iter().map(|a| a.to_string_lossy().to_string()).collect(),
+                parameters,
+                inputs: Vec::new(),
+                outputs: Vec::new(),
+                started_at: chrono::Utc::now().to_rfc3339(),
+                finished_at: String::new(),
+                wall_time_secs: 0.0,
+                stages: Vec::new(),
+                peak_rss_bytes: None,
+                metrics: MetricsSnapshot::default(),
+                status: String::new(),
+                error: None,
+            },
+            output,
+            inputs,
+            checksums,
+            started: Instant::now(),
+            started_system,
+        }
+    }
+
+    /// Record the outcome and write the manifest (no-op for commands without
+    /// `--output` and for dry runs)
+    pub fn finish(mut self, result: &Result<()>) {
+        let Some(output) = self.output.take() else {
+            return;
+        };
+        let sidecar = |suffix: &str| {
+            let mut name = output.file_name().unwrap_or_default().to_os_string();
+            name.push(suffix);
+            output.with_file_name(name)
+        };
+        let (manifest_path, checksums_path) = if output.is_dir() {
+            (output.join("run_manifest.json"), output.join("checksums.txt"))
+        } else if output.exists() {
+            (sidecar(".run_manifest.json"), sidecar(".checksums.txt"))
+        } else {
+            return;
+        };
+
+        let m = &mut self.manifest;
+        m.inputs = self
+            .inputs
+            .iter()
+            .map(|p| file_entry(p, self.checksums, false))
+            .collect();
+        m.finished_at = chrono::Utc::now().to_rfc3339();
+        m.wall_time_secs = self.started.elapsed().as_secs_f64();
+        m.stages = perf::timings();
+        m.peak_rss_bytes = perf::peak_rss_bytes();
+        m.metrics = metrics::global().snapshot();
+        match result {
+            Ok(()) => m.status = "success".to_string(),
+            Err(e) => {
+                m.status = "failed".to_string();
+                m.error = Some(format!("{:#}", e));
+            }
+        }
+
+        // Files written during this run (1s slack for coarse filesystem timestamps)
+        let since = self.started_system - Duration::from_secs(1);
+        let mut candidates = Vec::new();
+        if output.is_dir() {
+            walk_files(&output, &mut candidates);
+        } else {
+            candidates.push(output.clone());
+        }
+        if let Some(json) = m.parameters.get("json").and_then(|v| v.as_str()) {
+            candidates.push(PathBuf::from(json));
+        }
+        m.outputs = candidates
+            .iter()
+            .filter(|p| *p != &manifest_path && *p != &checksums_path)
+            .filter(|p| {
+                std::fs::metadata(p)
+                    .and_then(|md| md.modified())
+                    .map_or(false, |t| t >= since)
+            })
+            .map(|p| file_entry(p, self.checksums, true))
+            .collect();
+        m.outputs.sort_by(|a, b| a.path.cmp(&b.path));
+
+        if self.checksums && !m.outputs.is_empty() {
+            let base = checksums_path.parent().unwrap_or(Path::new(""));
+            match write_checksums(&checksums_path, base, &m.outputs) {
+                Ok(()) => log::info!("Output checksums written to {:?}", checksums_path),
+                Err(e) => log::warn!("Failed to write checksums {:?}: {}", checksums_path, e),
+            }
+        }
+
+        let written = serde_json::to_string_pretty(&self.manifest)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| {
+                sparc_core::atomic::write(&manifest_path, json).map_err(anyhow::Error::from)
+            });
+        match written {
+            Ok(()) => log::info!("Run manifest written to {:?}", manifest_path),
+            Err(e) => log::warn!("Failed to write run manifest {:?}: {}", manifest_path, e),
+        }
+    }
+}
+
+/// Add every argument of `cmd` present in `matches` (including defaults) as a JSON value
+fn collect_params(cmd: &Command, matches: &ArgMatches, out: &mut BTreeMap<String, serde_json::Value>) {
+    for arg in cmd.get_arguments() {
+        let name = arg.get_id().as_str();
+        let Ok(Some(raw)) = matches.try_get_raw(name) else {
+            continue;
+        };
+        let values: Vec<serde_json::Value> = raw
+            .map(|v| serde_json::Value::String(v.to_string_lossy().to_string()))
+            .collect();
+        let value = match values.len() {
+            1 => values.into_iter().next().expect("one value"),
+            _ => serde_json::Value::Array(values),
+        };
+        out.insert(name.to_string(), value);
+    }
+}
+
+fn walk_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk_files(&path, out);
+        } else {
+            out.push(path);
+        }
+    }
+}
+
+fn file_entry(path: &Path, checksum: bool, md5: bool) -> FileEntry {
+    let digests = if checksum { digest_file(path, md5).ok() } else { None };
+    let (sha256, md5) = match digests {
+        Some((sha256, md5)) => (Some(sha256), md5),
+        None => (None, None),
+    };
+    FileEntry {
+        path: path.display().to_string(),
+        size: std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        sha256,
+        md5,
+    }
+}
+
+/// Write `sha256sum`-compatible lines for `entries`, with paths relative to `base`
+fn write_checksums(path: &Path, base: &Path, entries: &[FileEntry]) -> Result<()> {
+    let mut text = String::new();
+    for entry in entries {
+        let Some(sha256) = &entry.sha256 else {
+            continue;
+        };
+        let file = Path::new(&entry.path);
+        let relative = file.strip_prefix(base).unwrap_or(file);
+        text.push_str(&format!("{}  {}\n", sha256, relative.display()));
+    }
+    sparc_core::atomic::write(path, text)?;
+    Ok(())
+}
+
+/// Hex SHA-256 (and optionally MD5) of a file's contents, in one pass
+fn digest_file(path: &Path, md5: bool) -> std::io::Result<(String, Option<String>)> {
+    let mut file = File::open(path)?;
+    let mut sha256 = Sha256::new();
+    let mut md5 = md5.then(Md5::new);
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        sha256.update(&buf[..n]);
+        if let Some(md5) = md5.as_mut() {
+            md5.update(&buf[..n]);
+        }
+    }
+    Ok((
+        format!("{:x}", sha256.finalize()),
+        md5.map(|md5| format!("{:x}", md5.finalize())),
+    ))
+}
@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::process::Command;
+
+fn write_fastq(path: &Path, seqs: &[&str]) {
+    let mut text = String::new();
+    for (i, seq) in seqs.iter().enumerate() {
+        text.push_str(&format!("@read{}\n{}\n+\n{}\n", i, seq, "I".repeat(seq.len())));
+    }
+    std::fs::write(path, text).unwrap();
+}
+
+#[test]
+fn demux_merges_rows_of_multi_index_sample() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = |name: &str| dir.path().join(name);
+    let indices = ["AAAAAAAA", "CCCCCCCC", "GGGGGGGG", "TTTTTTTT"];
+    write_fastq(&p("R1.fastq"), &["ACGTACGTACGT"; 4]);
+    write_fastq(&p("R2.fastq"), &["TTTTGGGGCCCCAAAA"; 4]);
+    write_fastq(&p("I1.fastq"), &indices);
+    std::fs::write(
+        p("sheet.csv"),
+        "sample,index\nA,AAAAAAAA\nA,CCCCCCCC\nB,GGGGGGGG\nA,TTTTTTTT\n",
+    )
+    .unwrap();
+
+    let out = p("out");
+    let status = Command::new(env!("CARGO_BIN_EXE_sparc"))
+        .arg("demux")
+        .arg("-1")
+        .arg(p("R1.fastq"))
+        .arg("-2")
+        .arg(p("R2.fastq"))
+        .arg("--i1")
+        .arg(p("I1.fastq"))
+        .arg("--sample-sheet")
+        .arg(p("sheet.csv"))
+        .arg("--output")
+        .arg(&out)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(out.join("demux_summary.json")).unwrap())
+            .unwrap();
+    assert_eq!(summary["reads_per_sample"]["A"], 3);
+    assert_eq!(summary["reads_per_sample"]["B"], 1);
+    assert_eq!(summary["reads_per_sample"]["Undetermined"], 0);
+    for name in ["A_R1.fastq.gz", "A_R2.fastq.gz", "B_R1.fastq.gz", "B_R2.fastq.gz"] {
+        assert!(out.join(name).exists(), "missing {}", name);
+    }
+}
+
+#[test]
+fn demux_rejects_reserved_sample_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let p = |name: &str| dir.path().join(name);
+    write_fastq(&p("R1.fastq"), &["ACGT"]);
+    write_fastq(&p("R2.fastq"), &["ACGT"]);
+    write_fastq(&p("I1.fastq"), &["AAAAAAAA"]);
+    std::fs::write(p("sheet.csv"), "Undetermined,AAAAAAAA\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sparc"))
+        .arg("demux")
+        .arg("-1")
+        .arg(p("R1.fastq"))
+        .arg("-2")
+        .arg(p("R2.fastq"))
+        .arg("--i1")
+        .arg(p("I1.fastq"))
+        .arg("--sample-sheet")
+        .arg(p("sheet.csv"))
+        .arg("--output")
+        .arg(p("out"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("reserved"));
+}
@@ -0,0 +1,25 @@
+use std::process::Command;
+
+#[test]
+fn simulate_writes_fastq_pair() {
+    let dir = tempfile::tempdir().unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_sparc"))
+        .args(["simulate", "--cells", "5", "--empty", "5", "--genes", "20"])
+        .args(["--umis-per-cell", "20", "--output"])
+        .arg(dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    for name in ["sim_R1.fastq.gz", "sim_R2.fastq.gz"] {
+        let meta = std::fs::metadata(dir.path().join(name)).unwrap();
+        assert!(meta.len() > 0, "{} is empty", name);
+    }
+    // No atomic temporaries left behind
+    let leftovers: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty());
+}
@@ -0,0 +1,80 @@
+//! Hamming distance kernels against the char-by-char loop they replaced
+//!
+//! Run with `cargo bench -p sparc-core --bench hamming`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sparc_core::barcode::{BarcodeCorrector, Whitelist};
+use sparc_core::seq_util::{hamming, hamming_2bit, pack_2bit};
+use sparc_core::umi::{Umi, UmiDeduplicator};
+
+fn random_seqs(rng: &mut StdRng, n: usize, len: usize) -> Vec<String> {
+    (0..n)
+        .map(|_| (0..len).map(|_| b"ACGT"[rng.gen_range(0..4)] as char).collect())
+        .collect()
+}
+
+fn naive(a: &str, b: &str) -> u32 {
+    if a.len() != b.len() {
+        return u32::MAX;
+    }
+    a.chars().zip(b.chars()).filter(|(a, b)| a != b).count() as u32
+}
+
+fn bench_kernels(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut group = c.benchmark_group("hamming");
+    for len in [12usize, 16, 28] {
+        let seqs = random_seqs(&mut rng, 1024, len);
+        let packed: Vec<u64> = seqs.iter().map(|s| pack_2bit(s.as_bytes()).unwrap()).collect();
+        let pairs = || seqs.iter().zip(seqs.iter().skip(1));
+        group.bench_with_input(BenchmarkId::new("chars", len), &len, |b, _| {
+            b.iter(|| pairs().map(|(x, y)| naive(black_box(x), black_box(y))).sum::<u32>())
+        });
+        group.bench_with_input(BenchmarkId::new("bytes", len), &len, |b, _| {
+            b.iter(|| {
+                pairs()
+                    .map(|(x, y)| hamming(black_box(x.as_bytes()), black_box(y.as_bytes())))
+                    .sum::<u32>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("packed", len), &len, |b, _| {
+            b.iter(|| {
+                packed
+                    .windows(2)
+                    .map(|w| hamming_2bit(black_box(w[0]), black_box(w[1])))
+                    .sum::<u32>()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_barcode_correction(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(11);
+    let whitelist = Whitelist::from_vec(random_seqs(&mut rng, 10_000, 16)).unwrap();
+    let corrector = BarcodeCorrector::new(whitelist, 2);
+    // Queries two mismatches away miss the 1-mismatch index and take the scan
+    let queries: Vec<String> = random_seqs(&mut rng, 64, 16);
+    c.bench_function("barcode_correct_d2", |b| {
+        b.iter(|| {
+            for q in &queries {
+                black_box(corrector.match_barcode(q));
+            }
+        })
+    });
+}
+
+fn bench_umi_graph(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(13);
+    let umis: Vec<Umi> = random_seqs(&mut rng, 500, 12)
+        .into_iter()
+        .map(|s| Umi::with_count(s, rng.gen_range(1..20)))
+        .collect();
+    let dedup = UmiDeduplicator::new(1);
+    c.bench_function("umi_dedup_500", |b| b.iter(|| dedup.deduplicate(black_box(&umis))));
+}
+
+criterion_group!(benches, bench_kernels, bench_barcode_correction, bench_umi_graph);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+//! Pipeline stages over simulated reads
+//!
+//! Run with `cargo bench -p sparc-core --bench pipeline`. Parallel stages use
+//! the global rayon pool (set `RAYON_NUM_THREADS` to compare thread counts);
+//! `sparc bench` sweeps thread counts over the same workload.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use sparc_core::benchmark::{Stage, Workload};
+
+fn bench_stages(c: &mut Criterion) {
+    let workload = Workload::generate(20, 42).expect("simulated workload");
+    let mut group = c.benchmark_group("pipeline");
+    group.throughput(Throughput::Elements(workload.reads() as u64));
+    group.sample_size(20);
+    for stage in Stage::ALL {
+        group.bench_function(stage.name(), |b| b.iter(|| workload.run(stage).unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_stages);
+criterion_main!(benches);
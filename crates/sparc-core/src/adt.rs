@@ -0,0 +1,238 @@
+//! Antibody capture (ADT) normalization and background metrics
+//!
+//! Works on features x cells count matrices restricted to `Antibody Capture`
+//! rows. Normalized output is cells x features, like [`crate::analysis`].
+
+use crate::count::CountMatrix;
+use crate::{Error, Result};
+use std::path::Path;
+
+/// Cell Ranger feature type for antibody-derived tags
+pub const ANTIBODY_CAPTURE: &str = "Antibody Capture";
+
+/// Read a Matrix Market matrix and keep its `Antibody Capture` rows. A matrix
+/// without feature types is assumed to be ADT-only and returned whole.
+pub fn read_adt_matrix<P: AsRef<Path>>(path: P) -> Result<CountMatrix> {
+    let matrix = CountMatrix::read_mtx(&path)?;
+    let types = CountMatrix::read_feature_types(&path)?;
+    let adt_rows: Vec<usize> = types
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| *t == ANTIBODY_CAPTURE)
+        .map(|(i, _)| i)
+        .collect();
+    if adt_rows.is_empty() {
+        return Ok(matrix);
+    }
+    Ok(matrix.subset_rows(&adt_rows))
+}
+
+/// Centered log-ratio per cell: `ln(1 + x) - mean(ln(1 + x))` over features
+pub fn clr_normalize(matrix: &CountMatrix) -> Vec<Vec<f64>> {
+    let mut data = log1p_dense(matrix);
+    for cell in data.iter_mut() {
+        let mean = cell.iter().sum::<f64>() / cell.len().max(1) as f64;
+        for v in cell.iter_mut() {
+            *v -= mean;
+        }
+    }
+    data
+}
+
+/// DSB-style normalization against empty droplets.
+///
+/// Each feature's `ln(1 + x)` is standardized by its mean and standard
+/// deviation in the `background` columns (empty droplets). When `isotypes` are
+/// given, each cell's mean standardized isotype signal is then subtracted as a
+/// per-cell technical offset. Returns one row per column of `cells`.
+pub fn dsb_normalize(
+    matrix: &CountMatrix,
+    cells: &[usize],
+    background: &[usize],
+    isotypes: &[usize],
+) -> Result<Vec<Vec<f64>>> {
+    if background.len() < 2 {
+        return Err(Error::Matrix(format!(
+            "DSB normalization needs at least 2 background droplets, got {}",
+            background.len()
+        )));
+    }
+    let data = log1p_dense(matrix);
+    let n_features = matrix.n_rows;
+    let (mean, sd): (Vec<f64>, Vec<f64>) = (0..n_features)
+        .map(|f| {
+            let values: Vec<f64> = background.iter().map(|&c| data[c][f]).collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>()
+                / (values.len() - 1) as f64;
+            (mean, var.sqrt().max(1e-3))
+        })
+        .unzip();
+
+    Ok(cells
+        .iter()
+        .map(|&c| {
+            let mut cell: Vec<f64> =
+                (0..n_features).map(|f| (data[c][f] - mean[f]) / sd[f]).collect();
+            if !isotypes.is_empty() {
+                let offset =
+                    isotypes.iter().map(|&f| cell[f]).sum::<f64>() / isotypes.len() as f64;
+                for v in cell.iter_mut() {
+                    *v -= offset;
+                }
+            }
+            cell
+        })
+        .collect())
+}
+
+/// Dense cells x features matrix of `ln(1 + count)`
+fn log1p_dense(matrix: &CountMatrix) -> Vec<Vec<f64>> {
+    let mut data = vec![vec![0.0; matrix.n_rows]; matrix.n_cols];
+    for ((&r, &c), &v) in matrix.rows.iter().zip(&matrix.cols).zip(&matrix.values) {
+        data[c][r] = (v as f64).ln_1p();
+    }
+    data
+}
+
+/// Features whose name marks them as isotype controls (contains "isotype",
+/// case-insensitive, or matches one of `names` exactly)
+pub fn find_isotypes(features: &[String], names: &[String]) -> Vec<usize> {
+    features
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| names.contains(f) || f.to_lowercase().contains("isotype"))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// ADT summary for the QC report
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AdtMetrics {
+    /// Antibody features
+    pub num_features: u64,
+    /// Features treated as isotype controls
+    pub num_isotypes: u64,
+    /// Median ADT UMIs per cell
+    pub median_umis_per_cell: f64,
+    /// Mean fraction of a cell's ADT UMIs from isotype controls
+    pub mean_isotype_fraction: f64,
+    /// Median fraction of a cell's ADT UMIs from isotype controls
+    pub median_isotype_fraction: f64,
+    /// Fraction of cells whose isotype fraction exceeds the threshold
+    pub high_isotype_cell_fraction: f64,
+}
+
+impl AdtMetrics {
+    /// Compute metrics over all cells. Cells with an isotype fraction above
+    /// `max_isotype_fraction` count as high-background (e.g. dead cells or
+    /// aggregates binding antibodies non-specifically).
+    pub fn from_matrix(
+        matrix: &CountMatrix,
+        isotypes: &[usize],
+        max_isotype_fraction: f64,
+    ) -> Self {
+        let totals = matrix.counts_per_cell();
+        let mut isotype_umis = vec![0u64; matrix.n_cols];
+        let mut is_isotype = vec![false; matrix.n_rows];
+        for &f in isotypes {
+            is_isotype[f] = true;
+        }
+        for ((&r, &c), &v) in matrix.rows.iter().zip(&matrix.cols).zip(&matrix.values) {
+            if is_isotype[r] {
+                isotype_umis[c] += v as u64;
+            }
+        }
+
+        let mut fractions: Vec<f64> = totals
+            .iter()
+            .zip(&isotype_umis)
+            .map(|(&t, &i)| if t == 0 { 0.0 } else { i as f64 / t as f64 })
+            .collect();
+        fractions.sort_by(f64::total_cmp);
+        let mut sorted_totals = totals;
+        sorted_totals.sort_unstable();
+        let median_umis = sorted_totals.get(sorted_totals.len() / 2).copied().unwrap_or(0);
+        let high_isotype = fractions.iter().filter(|&&f| f > max_isotype_fraction).count();
+        let n = fractions.len().max(1) as f64;
+
+        Self {
+            num_features: matrix.n_rows as u64,
+            num_isotypes: isotypes.len() as u64,
+            median_umis_per_cell: median_umis as f64,
+            mean_isotype_fraction: fractions.iter().sum::<f64>() / n,
+            median_isotype_fraction: fractions.get(fractions.len() / 2).copied().unwrap_or(0.0),
+            high_isotype_cell_fraction: high_isotype as f64 / n,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adt_matrix() -> CountMatrix {
+        CountMatrix::from_dense(
+            (0..4).map(|i| format!("C{}", i)).collect(),
+            vec!["CD3".to_string(), "CD19".to_string(), "IgG1_Isotype".to_string()],
+            // Cells 0-1 real, 2-3 empty droplets
+            vec![vec![100, 5, 1, 0], vec![3, 80, 0, 1], vec![2, 30, 1, 3]],
+        )
+    }
+
+    #[test]
+    fn test_clr_centers_each_cell() {
+        let clr = clr_normalize(&adt_matrix());
+        assert_eq!(clr.len(), 4);
+        for cell in &clr {
+            assert!(cell.iter().sum::<f64>().abs() < 1e-9);
+        }
+        assert!(clr[0][0] > clr[0][1]);
+        assert!(clr[1][1] > clr[1][0]);
+    }
+
+    #[test]
+    fn test_dsb_with_isotype_offset() {
+        let matrix = adt_matrix();
+        let isotypes = find_isotypes(&matrix.genes, &[]);
+        assert_eq!(isotypes, vec![2]);
+
+        let dsb = dsb_normalize(&matrix, &[0, 1], &[2, 3], &isotypes).unwrap();
+        assert_eq!(dsb.len(), 2);
+        assert!(dsb[0][0] > 5.0, "{:?}", dsb[0]);
+        assert!(dsb[0][2].abs() < 1e-9, "isotype offset removed");
+        assert!(dsb_normalize(&matrix, &[0], &[2], &[]).is_err());
+    }
+
+    #[test]
+    fn test_read_adt_rows_from_combined_matrix() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("matrix.mtx"),
+            "%%MatrixMarket matrix coordinate integer general\n3 1 3\n1 1 7\n2 1 4\n3 1 2\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("barcodes.tsv"), "C0\n").unwrap();
+        std::fs::write(
+            dir.path().join("features.tsv"),
+            "ENSG1\tACTB\tGene Expression\nCD3\tCD3\tAntibody Capture\n\
+             IgG\tIgG\tAntibody Capture\n",
+        )
+        .unwrap();
+
+        let adt = read_adt_matrix(dir.path()).unwrap();
+        assert_eq!(adt.genes, vec!["CD3", "IgG"]);
+        assert_eq!((adt.n_rows, adt.n_cols), (2, 1));
+        assert_eq!(adt.values, vec![4, 2]);
+    }
+
+    #[test]
+    fn test_isotype_metrics() {
+        let metrics = AdtMetrics::from_matrix(&adt_matrix(), &[2], 0.25);
+        assert_eq!(metrics.num_features, 3);
+        assert_eq!(metrics.num_isotypes, 1);
+        // Isotype fractions: 2/105, 30/115, 1/2, 3/4
+        assert!((metrics.high_isotype_cell_fraction - 0.75).abs() < 1e-9);
+        assert!((metrics.median_isotype_fraction - 0.5).abs() < 1e-9);
+    }
+}
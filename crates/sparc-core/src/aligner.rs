@@ -1,9 +1,21 @@
-//! Built-in aligner integration (STAR and minimap2)
-
+//! Built-in aligner integration (STAR/STARsolo and minimap2)
+//!
+//! The [`Aligner`] trait describes how to drive an external aligner: build its
+//! command line, spawn it with stdout/stderr captured to a log in the output
+//! directory, optionally stream reads to it over stdin, and collect the BAM it
+//! writes. [`Star`] and [`Minimap2`] implement it; [`AlignerConfig::build`]
+//! picks one from a config.
+
+use crate::fastq::FastqRecord;
 use crate::{Error, Result};
 use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+
+/// Log lines quoted in the error when an aligner fails
+const FAILURE_LOG_LINES: usize = 20;
 
 /// Result of BAM file validation
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +39,9 @@ pub struct AlignerConfig {
     pub genome_dir: PathBuf,
     pub threads: usize,
     pub extra_args: Vec<String>,
+    /// Times to re-run a failed alignment from files before giving up
+    #[serde(default)]
+    pub retries: usize,
 }
 
 impl AlignerConfig {
@@ -53,6 +68,7 @@ impl AlignerConfig {
                 "--soloType".into(),
                 "CB_UMI_Simple".into(),
             ],
+            retries: 0,
         }
     }
 
@@ -62,121 +78,344 @@ impl AlignerConfig {
             genome_dir: genome_ref,
             threads,
             extra_args: vec!["-a".into(), "--secondary=no".into()],
+            retries: 0,
+        }
+    }
+
+    /// Set the number of retries after a failed alignment
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Aligner implementation for this config
+    pub fn build(self) -> Box<dyn Aligner> {
+        match self.aligner_type {
+            AlignerType::Star => Box::new(Star::new(self)),
+            AlignerType::Minimap2 => Box::new(Minimap2::new(self)),
         }
     }
 }
 
-/// Aligner wrapper
-pub struct Aligner {
-    config: AlignerConfig,
+/// Where an aligner reads its input from
+#[derive(Debug, Clone, Copy)]
+pub enum Reads<'a> {
+    /// cDNA read file and optional barcode read file
+    Files(&'a Path, Option<&'a Path>),
+    /// Single-end FASTQ streamed over stdin
+    Stdin,
 }
 
-impl Aligner {
-    pub fn new(config: AlignerConfig) -> Self {
-        Self { config }
+/// An external aligner driven as a child process
+pub trait Aligner: Send + Sync {
+    /// Configuration the aligner was built from
+    fn config(&self) -> &AlignerConfig;
+
+    /// Aligner binary name
+    fn binary_name(&self) -> &str;
+
+    /// Aligner command line for `reads`, writing into `output_dir`
+    fn command(&self, reads: Reads<'_>, output_dir: &Path) -> Command;
+
+    /// Locate (and post-process) the alignments written into `output_dir`
+    fn collect_output(&self, output_dir: &Path) -> Result<PathBuf>;
+
+    /// Remove partial outputs of a failed run before retrying
+    fn clean_partial(&self, _output_dir: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    /// Log file capturing the aligner's stdout and stderr
+    fn log_path(&self, output_dir: &Path) -> PathBuf {
+        output_dir.join(format!("{}.log", self.binary_name()))
     }
 
     /// Check if the aligner is available in PATH
-    pub fn is_available(&self) -> bool {
-        let result = Command::new("which")
-            .arg(self.binary_name())
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false);
+    fn is_available(&self) -> bool {
+        let result = is_tool_available(self.binary_name());
         log::info!("Aligner '{}' available: {}", self.binary_name(), result);
         result
     }
 
-    /// Get the aligner binary name
-    pub fn binary_name(&self) -> &str {
-        match self.config.aligner_type {
-            AlignerType::Star => "STAR",
-            AlignerType::Minimap2 => "minimap2",
+    /// External commands `align` would run, rendered for display (`--dry-run`)
+    fn planned_commands(&self, r1: &Path, r2: Option<&Path>, output_dir: &Path) -> Vec<String> {
+        vec![render_command(&self.command(Reads::Files(r1, r2), output_dir))]
+    }
+
+    /// Align read files, retrying up to `config().retries` times on failure
+    fn align(&self, r1: &Path, r2: Option<&Path>, output_dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(output_dir)?;
+        let attempts = self.config().retries + 1;
+        for attempt in 1.. {
+            let cmd = self.command(Reads::Files(r1, r2), output_dir);
+            match run_logged(cmd, &self.log_path(output_dir), None) {
+                Ok(()) => break,
+                Err(e) if attempt < attempts => {
+                    log::warn!(
+                        "{} failed (attempt {}/{}), retrying: {}",
+                        self.binary_name(),
+                        attempt,
+                        attempts,
+                        e
+                    );
+                    self.clean_partial(output_dir)?;
+                }
+                Err(e) => return Err(e),
+            }
         }
+        self.collect_output(output_dir)
     }
 
-    /// Run alignment
-    pub fn align<P: AsRef<Path>>(
+    /// Align reads streamed over stdin. Streams cannot be replayed, so
+    /// failures are not retried.
+    fn align_stream(
         &self,
-        r1: P,
-        r2: Option<P>,
-        output_dir: P,
+        reads: &mut dyn Iterator<Item = Result<FastqRecord>>,
+        output_dir: &Path,
     ) -> Result<PathBuf> {
-        match self.config.aligner_type {
-            AlignerType::Star => self.run_star(r1, r2, output_dir),
-            AlignerType::Minimap2 => self.run_minimap2(r1, r2, output_dir),
-        }
+        std::fs::create_dir_all(output_dir)?;
+        let cmd = self.command(Reads::Stdin, output_dir);
+        run_logged(cmd, &self.log_path(output_dir), Some(reads))?;
+        self.collect_output(output_dir)
     }
+}
 
-    fn run_star<P: AsRef<Path>>(
-        &self,
-        r1: P,
-        r2: Option<P>,
+/// Spawn `cmd` with stdout and stderr written to `log_path`, feeding `reads`
+/// to its stdin when given, and fail with the end of the log if it exits non-zero
+fn run_logged(
+    mut cmd: Command,
+    log_path: &Path,
+    reads: Option<&mut dyn Iterator<Item = Result<FastqRecord>>>,
+) -> Result<()> {
+    let name = cmd.get_program().to_string_lossy().to_string();
+    let log = File::create(log_path)?;
+    cmd.stdout(log.try_clone()?).stderr(log);
+    cmd.stdin(if reads.is_some() { Stdio::piped() } else { Stdio::null() });
+
+    log::info!("Running {} (log: {:?})", render_command(&cmd), log_path);
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| Error::BamParse(format!("Failed to start {}: {}", name, e)))?;
+    // stdin is closed when the writer drops, so the aligner sees EOF even on error
+    let streamed = match (reads, child.stdin.take()) {
+        (Some(reads), Some(stdin)) => write_fastq(BufWriter::new(stdin), reads),
+        _ => Ok(()),
+    };
+    let status = child.wait()?;
+
+    if !status.success() {
+        return Err(Error::BamParse(format!(
+            "{} failed ({}), see {:?}:\n{}",
+            name,
+            status,
+            log_path,
+            log_tail(log_path, FAILURE_LOG_LINES)
+        )));
+    }
+    streamed
+}
+
+fn write_fastq<W: Write>(
+    mut writer: W,
+    reads: &mut dyn Iterator<Item = Result<FastqRecord>>,
+) -> Result<()> {
+    for record in reads {
+        let record = record?;
+        writeln!(writer, "@{}", record.id)?;
+        writer.write_all(&record.seq)?;
+        writer.write_all(b"\n+\n")?;
+        writer.write_all(&record.qual)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Last `n` lines of a log file
+fn log_tail(path: &Path, n: usize) -> String {
+    let text = std::fs::read(path).unwrap_or_default();
+    let text = String::from_utf8_lossy(&text);
+    let lines: Vec<&str> = text.lines().collect();
+    lines[lines.len().saturating_sub(n)..].join("\n")
+}
+
+fn samtools_view_command(sam_path: &Path, bam_path: &Path) -> Command {
+    let mut cmd = Command::new("samtools");
+    cmd.args(["view", "-bS", "-o"]).arg(bam_path).arg(sam_path);
+    cmd
+}
+
+/// Coordinate sort, with temporaries and memory bounded by the global resource config
+fn samtools_sort_command(bam_path: &Path, sorted_path: &Path) -> Command {
+    let resources = crate::resources::global();
+    let mut cmd = Command::new("samtools");
+    cmd.arg("sort").arg("-T").arg(resources.unique_path("sort"));
+    if let Some(bytes) = resources.max_memory {
+        cmd.arg("-m").arg(bytes.to_string());
+    }
+    cmd.arg("-o").arg(sorted_path).arg(bam_path);
+    cmd
+}
+
+/// Render a command as a shell line, quoting arguments that need it
+fn render_command(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+            {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// STAR (and STARsolo, via `--solo*` extra arguments)
+pub struct Star {
+    config: AlignerConfig,
+}
+
+/// STAR output BAMs, in order of preference
+const STAR_BAMS: [&str; 2] = ["star_Aligned.sortedByCoord.out.bam", "star_Aligned.out.bam"];
+
+impl Star {
+    pub fn new(config: AlignerConfig) -> Self {
+        Self { config }
+    }
+
+    /// Generate STAR genome index
+    pub fn generate_index<P: AsRef<Path>>(
+        genome_fasta: P,
+        gtf: P,
         output_dir: P,
-    ) -> Result<PathBuf> {
+        threads: usize,
+    ) -> Result<()> {
         let output_dir = output_dir.as_ref();
         std::fs::create_dir_all(output_dir)?;
 
-        let output_prefix = output_dir.join("star_");
+        let status = Command::new("STAR")
+            .arg("--runMode")
+            .arg("genomeGenerate")
+            .arg("--genomeDir")
+            .arg(output_dir)
+            .arg("--genomeFastaFiles")
+            .arg(genome_fasta.as_ref())
+            .arg("--sjdbGTFfile")
+            .arg(gtf.as_ref())
+            .arg("--runThreadN")
+            .arg(threads.to_string())
+            .status()
+            .map_err(Error::Io)?;
 
+        if !status.success() {
+            return Err(Error::BamParse(
+                "STAR genome generation failed".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Aligner for Star {
+    fn config(&self) -> &AlignerConfig {
+        &self.config
+    }
+
+    fn binary_name(&self) -> &str {
+        "STAR"
+    }
+
+    fn command(&self, reads: Reads<'_>, output_dir: &Path) -> Command {
         let mut cmd = Command::new("STAR");
         cmd.arg("--genomeDir")
             .arg(&self.config.genome_dir)
             .arg("--readFilesIn");
 
-        if let Some(r2) = r2 {
-            cmd.arg(r2.as_ref()).arg(r1.as_ref());
-        } else {
-            cmd.arg(r1.as_ref());
+        match reads {
+            Reads::Files(r1, r2) => {
+                if let Some(r2) = r2 {
+                    cmd.arg(r2).arg(r1);
+                } else {
+                    cmd.arg(r1);
+                }
+            }
+            Reads::Stdin => {
+                cmd.arg("/dev/stdin");
+            }
         }
 
         cmd.arg("--runThreadN")
             .arg(self.config.threads.to_string())
             .arg("--outFileNamePrefix")
-            .arg(&output_prefix);
+            .arg(output_dir.join("star_"));
 
-        let r1_path = r1.as_ref();
-        if r1_path.extension().map_or(false, |e| e == "gz") {
-            cmd.arg("--readFilesCommand").arg("zcat");
+        if let Reads::Files(r1, _) = reads {
+            if r1.extension().map_or(false, |e| e == "gz") {
+                cmd.arg("--readFilesCommand").arg("zcat");
+            }
+        }
+
+        // STAR creates (and removes) its temp directory itself; it must not exist yet
+        let resources = crate::resources::global();
+        cmd.arg("--outTmpDir").arg(resources.unique_path("star"));
+        if let Some(bytes) = resources.max_memory {
+            cmd.arg("--limitBAMsortRAM").arg(bytes.to_string());
         }
 
         for arg in &self.config.extra_args {
             cmd.arg(arg);
         }
+        cmd
+    }
 
-        log::info!("Running STAR alignment...");
-        let output = cmd.output().map_err(Error::Io)?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::BamParse(format!("STAR failed: {}", stderr)));
-        }
+    fn collect_output(&self, output_dir: &Path) -> Result<PathBuf> {
+        STAR_BAMS
+            .iter()
+            .map(|name| output_dir.join(name))
+            .find(|path| path.exists())
+            .ok_or_else(|| Error::BamParse("STAR output BAM not found".into()))
+    }
 
-        let bam_path = output_dir.join("star_Aligned.sortedByCoord.out.bam");
-        if bam_path.exists() {
-            Ok(bam_path)
-        } else {
-            let bam_path2 = output_dir.join("star_Aligned.out.bam");
-            if bam_path2.exists() {
-                Ok(bam_path2)
-            } else {
-                Err(Error::BamParse("STAR output BAM not found".into()))
+    fn clean_partial(&self, output_dir: &Path) -> Result<()> {
+        for name in STAR_BAMS {
+            let path = output_dir.join(name);
+            if path.exists() {
+                std::fs::remove_file(path)?;
             }
         }
+        Ok(())
     }
+}
 
-    fn run_minimap2<P: AsRef<Path>>(
-        &self,
-        r1: P,
-        r2: Option<P>,
-        output_dir: P,
-    ) -> Result<PathBuf> {
-        let output_dir = output_dir.as_ref();
-        std::fs::create_dir_all(output_dir)?;
+/// minimap2, with SAM converted to a sorted BAM by samtools when available
+pub struct Minimap2 {
+    config: AlignerConfig,
+}
 
-        let bam_path = output_dir.join("aligned.bam");
-        let sam_path = output_dir.join("aligned.sam");
+impl Minimap2 {
+    pub fn new(config: AlignerConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Aligner for Minimap2 {
+    fn config(&self) -> &AlignerConfig {
+        &self.config
+    }
+
+    fn binary_name(&self) -> &str {
+        "minimap2"
+    }
 
+    fn command(&self, reads: Reads<'_>, output_dir: &Path) -> Command {
         let mut cmd = Command::new("minimap2");
         cmd.arg("-t").arg(self.config.threads.to_string());
 
@@ -186,34 +425,45 @@ impl Aligner {
 
         cmd.arg(&self.config.genome_dir);
 
-        if let Some(r2) = r2 {
-            cmd.arg(r2.as_ref()).arg(r1.as_ref());
-        } else {
-            cmd.arg(r1.as_ref());
+        match reads {
+            Reads::Files(r1, Some(r2)) => {
+                cmd.arg(r2).arg(r1);
+            }
+            Reads::Files(r1, None) => {
+                cmd.arg(r1);
+            }
+            Reads::Stdin => {
+                cmd.arg("-");
+            }
         }
 
-        cmd.arg("-o").arg(&sam_path);
+        cmd.arg("-o").arg(output_dir.join("aligned.sam"));
+        cmd
+    }
 
-        log::info!("Running minimap2 alignment...");
-        let output = cmd.output().map_err(Error::Io)?;
+    fn planned_commands(&self, r1: &Path, r2: Option<&Path>, output_dir: &Path) -> Vec<String> {
+        let sam_path = output_dir.join("aligned.sam");
+        let bam_path = output_dir.join("aligned.bam");
+        [
+            self.command(Reads::Files(r1, r2), output_dir),
+            samtools_view_command(&sam_path, &bam_path),
+            samtools_sort_command(&bam_path, &output_dir.join("aligned.sorted.bam")),
+        ]
+        .iter()
+        .map(render_command)
+        .collect()
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::BamParse(format!("minimap2 failed: {}", stderr)));
+    fn collect_output(&self, output_dir: &Path) -> Result<PathBuf> {
+        let bam_path = output_dir.join("aligned.bam");
+        let sam_path = output_dir.join("aligned.sam");
+        if !sam_path.exists() {
+            return Err(Error::BamParse("minimap2 output SAM not found".into()));
         }
 
         // Convert SAM to BAM using samtools if available
-        let samtools_available = Command::new("which")
-            .arg("samtools")
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false);
-
-        if samtools_available {
-            let status = Command::new("samtools")
-                .args(["view", "-bS", "-o"])
-                .arg(&bam_path)
-                .arg(&sam_path)
+        if is_tool_available("samtools") {
+            let status = samtools_view_command(&sam_path, &bam_path)
                 .status()
                 .map_err(Error::Io)?;
 
@@ -221,10 +471,7 @@ impl Aligner {
                 let _ = std::fs::remove_file(&sam_path);
 
                 let sorted_path = output_dir.join("aligned.sorted.bam");
-                let sort_ok = Command::new("samtools")
-                    .args(["sort", "-o"])
-                    .arg(&sorted_path)
-                    .arg(&bam_path)
+                let sort_ok = samtools_sort_command(&bam_path, &sorted_path)
                     .status()
                     .map(|s| s.success())
                     .unwrap_or(false);
@@ -240,106 +487,173 @@ impl Aligner {
         Ok(sam_path)
     }
 
-    /// Validate a BAM file by checking it can be read and has aligned records
-    pub fn validate_bam<P: AsRef<Path>>(bam_path: P) -> Result<BamValidation> {
-        let path = bam_path.as_ref();
-        if !path.exists() {
-            return Err(Error::BamParse(format!("BAM file not found: {:?}", path)));
+    fn clean_partial(&self, output_dir: &Path) -> Result<()> {
+        let sam_path = output_dir.join("aligned.sam");
+        if sam_path.exists() {
+            std::fs::remove_file(sam_path)?;
         }
+        Ok(())
+    }
+}
 
-        let file_size = std::fs::metadata(path)
-            .map(|m| m.len())
-            .unwrap_or(0);
+/// Validate a BAM file by checking it can be read and has aligned records
+pub fn validate_bam<P: AsRef<Path>>(bam_path: P) -> Result<BamValidation> {
+    let path = bam_path.as_ref();
+    if !path.exists() {
+        return Err(Error::BamParse(format!("BAM file not found: {:?}", path)));
+    }
 
-        if file_size == 0 {
-            return Err(Error::BamParse("BAM file is empty".into()));
-        }
+    let file_size = std::fs::metadata(path)
+        .map(|m| m.len())
+        .unwrap_or(0);
 
-        // Try to read header bytes to validate format
-        let mut file = std::fs::File::open(path)?;
-        let mut magic = [0u8; 4];
-        use std::io::Read;
-        file.read_exact(&mut magic)?;
+    if file_size == 0 {
+        return Err(Error::BamParse("BAM file is empty".into()));
+    }
 
-        let is_bam = &magic == b"BAM\x01";
-        let is_sam = magic[0] == b'@';
+    // Try to read header bytes to validate format
+    let mut file = std::fs::File::open(path)?;
+    let mut magic = [0u8; 4];
+    use std::io::Read;
+    file.read_exact(&mut magic)?;
 
-        if !is_bam && !is_sam {
-            return Err(Error::BamParse(
-                "File does not appear to be a valid BAM or SAM file".into(),
-            ));
-        }
+    let is_bam = &magic == b"BAM\x01";
+    let is_sam = magic[0] == b'@';
 
-        Ok(BamValidation {
-            path: path.to_path_buf(),
-            file_size,
-            format: if is_bam { "BAM".into() } else { "SAM".into() },
-        })
+    if !is_bam && !is_sam {
+        return Err(Error::BamParse(
+            "File does not appear to be a valid BAM or SAM file".into(),
+        ));
     }
 
-    /// Index a BAM file using samtools
-    pub fn index_bam<P: AsRef<Path>>(bam_path: P) -> Result<PathBuf> {
-        let bam_path = bam_path.as_ref();
-        let bai_path = bam_path.with_extension("bam.bai");
+    Ok(BamValidation {
+        path: path.to_path_buf(),
+        file_size,
+        format: if is_bam { "BAM".into() } else { "SAM".into() },
+    })
+}
 
-        if !Self::is_tool_available("samtools") {
-            return Err(Error::BamParse(
-                "samtools not found in PATH — cannot index BAM".into(),
-            ));
-        }
+/// Index a BAM file using samtools
+pub fn index_bam<P: AsRef<Path>>(bam_path: P) -> Result<PathBuf> {
+    let bam_path = bam_path.as_ref();
+    let bai_path = bam_path.with_extension("bam.bai");
 
-        let status = Command::new("samtools")
-            .args(["index", "-@", "4"])
-            .arg(bam_path)
-            .status()
-            .map_err(Error::Io)?;
+    if !is_tool_available("samtools") {
+        return Err(Error::BamParse(
+            "samtools not found in PATH — cannot index BAM".into(),
+        ));
+    }
 
-        if !status.success() {
-            return Err(Error::BamParse("samtools index failed".into()));
-        }
+    let status = Command::new("samtools")
+        .args(["index", "-@", "4"])
+        .arg(bam_path)
+        .status()
+        .map_err(Error::Io)?;
 
-        Ok(bai_path)
+    if !status.success() {
+        return Err(Error::BamParse("samtools index failed".into()));
     }
 
-    /// Check if a tool is available in PATH
-    fn is_tool_available(name: &str) -> bool {
-        Command::new("which")
-            .arg(name)
-            .output()
-            .map(|o| o.status.success())
-            .unwrap_or(false)
+    Ok(bai_path)
+}
+
+/// Check if a tool is available in PATH
+fn is_tool_available(name: &str) -> bool {
+    Command::new("which")
+        .arg(name)
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shell script standing in for an aligner; writes `out.fq` on success
+    struct Script {
+        config: AlignerConfig,
+        script: &'static str,
     }
 
-    /// Generate STAR genome index
-    pub fn generate_star_index<P: AsRef<Path>>(
-        genome_fasta: P,
-        gtf: P,
-        output_dir: P,
-        threads: usize,
-    ) -> Result<()> {
-        let output_dir = output_dir.as_ref();
-        std::fs::create_dir_all(output_dir)?;
+    impl Script {
+        fn new(script: &'static str, retries: usize) -> Self {
+            let config = AlignerConfig::minimap2(PathBuf::new(), 1).retries(retries);
+            Self { config, script }
+        }
+    }
 
-        let status = Command::new("STAR")
-            .arg("--runMode")
-            .arg("genomeGenerate")
-            .arg("--genomeDir")
-            .arg(output_dir)
-            .arg("--genomeFastaFiles")
-            .arg(genome_fasta.as_ref())
-            .arg("--sjdbGTFfile")
-            .arg(gtf.as_ref())
-            .arg("--runThreadN")
-            .arg(threads.to_string())
-            .status()
-            .map_err(Error::Io)?;
+    impl Aligner for Script {
+        fn config(&self) -> &AlignerConfig {
+            &self.config
+        }
 
-        if !status.success() {
-            return Err(Error::BamParse(
-                "STAR genome generation failed".to_string(),
-            ));
+        fn binary_name(&self) -> &str {
+            "sh"
         }
 
-        Ok(())
+        fn command(&self, _reads: Reads<'_>, output_dir: &Path) -> Command {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(self.script).current_dir(output_dir);
+            cmd
+        }
+
+        fn collect_output(&self, output_dir: &Path) -> Result<PathBuf> {
+            Ok(output_dir.join("out.fq"))
+        }
+    }
+
+    #[test]
+    fn test_align_stream_feeds_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let aligner = Script::new("cat > out.fq; echo aligned", 0);
+        let reads: Vec<Result<FastqRecord>> = vec![
+            Ok(FastqRecord::new("r1".into(), b"ACGT".to_vec(), b"IIII".to_vec())),
+            Ok(FastqRecord::new("r2".into(), b"GG".to_vec(), b"II".to_vec())),
+        ];
+        let out = aligner.align_stream(&mut reads.into_iter(), dir.path()).unwrap();
+        let written = std::fs::read_to_string(out).unwrap();
+        assert_eq!(written, "@r1\nACGT\n+\nIIII\n@r2\nGG\n+\nII\n");
+        let log = std::fs::read_to_string(dir.path().join("sh.log")).unwrap();
+        assert_eq!(log.trim(), "aligned");
+    }
+
+    #[test]
+    fn test_failure_reports_log_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let aligner = Script::new("echo 'index not found' >&2; exit 3", 0);
+        let err = aligner.align(Path::new("r1.fq"), None, dir.path()).unwrap_err();
+        assert!(err.to_string().contains("index not found"));
+    }
+
+    #[test]
+    fn test_retry_after_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = "if [ -e tried ]; then touch out.fq; else touch tried; exit 1; fi";
+        assert!(Script::new(script, 0).align(Path::new("r1.fq"), None, dir.path()).is_err());
+        std::fs::remove_file(dir.path().join("tried")).unwrap();
+        let out = Script::new(script, 1).align(Path::new("r1.fq"), None, dir.path()).unwrap();
+        assert!(out.exists());
+    }
+
+    #[test]
+    fn test_planned_commands() {
+        let out = Path::new("/out");
+        let star = AlignerConfig::star(PathBuf::from("/ref"), 4).build();
+        let (r1, r2) = (Path::new("r2.fq.gz"), Path::new("r1.fq.gz"));
+        let planned = star.planned_commands(r1, Some(r2), out);
+        assert_eq!(planned.len(), 1);
+        assert!(planned[0].starts_with("STAR --genomeDir /ref --readFilesIn r1.fq.gz r2.fq.gz"));
+        assert!(planned[0].contains("--readFilesCommand zcat"));
+        let stdin = star.command(Reads::Stdin, out);
+        assert!(render_command(&stdin).contains("--readFilesIn /dev/stdin --runThreadN"));
+
+        let minimap2 = AlignerConfig::minimap2(PathBuf::from("ref.fa"), 2).build();
+        let planned = minimap2.planned_commands(Path::new("reads.fq"), None, out);
+        assert_eq!(planned.len(), 3);
+        assert_eq!(
+            planned[0],
+            "minimap2 -t 2 -a --secondary=no ref.fa reads.fq -o /out/aligned.sam"
+        );
     }
 }
@@ -0,0 +1,319 @@
+//! Ambient RNA estimation and correction
+//!
+//! The ambient ("soup") profile is the pooled expression of empty droplets.
+//! Each cluster's contamination is bounded by the soup genes it expresses
+//! least (SoupX-style: every cluster is assumed to lack some soup genes), which
+//! fixes the cluster's native profile. Per-cell contamination fractions are
+//! then fit by EM against that native profile and the soup (decontX-style), and
+//! the soup-attributed share of each count can be removed.
+
+use crate::count::CountMatrix;
+use crate::atomic::AtomicFile;
+use crate::{Error, Result};
+use std::io::Write;
+use std::path::Path;
+
+/// Ambient estimation parameters
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AmbientConfig {
+    /// Genes need at least this many expected soup counts in a cluster to
+    /// bound its contamination
+    pub min_soup_counts: f64,
+    /// Maximum EM iterations per cell
+    pub max_iter: usize,
+    /// Stop when the contamination fraction changes by less than this
+    pub tolerance: f64,
+}
+
+impl Default for AmbientConfig {
+    fn default() -> Self {
+        Self {
+            min_soup_counts: 20.0,
+            max_iter: 200,
+            tolerance: 1e-6,
+        }
+    }
+}
+
+/// Soup profile: per-gene fraction of all counts in the `empty` droplets
+pub fn soup_profile(matrix: &CountMatrix, empty: &[usize]) -> Result<Vec<f64>> {
+    let mut is_empty = vec![false; matrix.n_cols];
+    for &c in empty {
+        is_empty[c] = true;
+    }
+    let mut profile = vec![0.0; matrix.n_rows];
+    for ((&r, &c), &v) in matrix.rows.iter().zip(&matrix.cols).zip(&matrix.values) {
+        if is_empty[c] {
+            profile[r] += v as f64;
+        }
+    }
+    let total: f64 = profile.iter().sum();
+    if total == 0.0 {
+        return Err(Error::Matrix(format!(
+            "{} empty droplets contain no counts; cannot estimate the ambient profile",
+            empty.len()
+        )));
+    }
+    profile.iter_mut().for_each(|p| *p /= total);
+    Ok(profile)
+}
+
+/// Ambient contamination estimates for a set of cells
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AmbientEstimate {
+    /// Soup profile over genes
+    pub profile: Vec<f64>,
+    /// Cell columns in the matrix
+    pub cells: Vec<usize>,
+    /// Estimated contamination fraction of each cell
+    pub contamination: Vec<f64>,
+    /// Contamination bound of each cluster
+    pub cluster_contamination: Vec<f64>,
+    /// Cluster of each cell
+    clusters: Vec<usize>,
+    /// Native expression profile of each cluster
+    native: Vec<Vec<f64>>,
+}
+
+/// Per-cell sparse (gene, count) lists for the given columns
+fn cell_entries(matrix: &CountMatrix, cells: &[usize]) -> Vec<Vec<(usize, u32)>> {
+    let mut slot = vec![None; matrix.n_cols];
+    for (i, &c) in cells.iter().enumerate() {
+        slot[c] = Some(i);
+    }
+    let mut entries = vec![Vec::new(); cells.len()];
+    for ((&r, &c), &v) in matrix.rows.iter().zip(&matrix.cols).zip(&matrix.values) {
+        if let Some(i) = slot[c] {
+            entries[i].push((r, v));
+        }
+    }
+    entries
+}
+
+/// Share of a count attributed to the soup
+fn soup_share(rho: f64, soup: f64, native: f64) -> f64 {
+    let ambient = rho * soup;
+    let total = ambient + (1.0 - rho) * native;
+    if total > 0.0 {
+        ambient / total
+    } else {
+        0.0
+    }
+}
+
+/// Estimate contamination for `cells` given the soup `profile`.
+///
+/// `clusters` labels each cell (in `cells` order); without labels all cells
+/// share one native profile, which underestimates contamination in
+/// heterogeneous samples.
+pub fn estimate_contamination(
+    matrix: &CountMatrix,
+    cells: &[usize],
+    clusters: Option<&[usize]>,
+    profile: &[f64],
+    config: &AmbientConfig,
+) -> Result<AmbientEstimate> {
+    let clusters: Vec<usize> = match clusters {
+        Some(labels) if labels.len() != cells.len() => {
+            return Err(Error::Matrix(format!(
+                "{} cluster labels for {} cells",
+                labels.len(),
+                cells.len()
+            )))
+        }
+        Some(labels) => labels.to_vec(),
+        None => vec![0; cells.len()],
+    };
+    let n_clusters = clusters.iter().max().map_or(0, |&k| k + 1);
+    let entries = cell_entries(matrix, cells);
+
+    // Cluster totals and their contamination bound
+    let mut pooled = vec![vec![0.0; matrix.n_rows]; n_clusters];
+    for (cell, &k) in entries.iter().zip(&clusters) {
+        for &(g, v) in cell {
+            pooled[k][g] += v as f64;
+        }
+    }
+    let mut cluster_contamination = vec![0.0; n_clusters];
+    let mut native = Vec::with_capacity(n_clusters);
+    for (k, counts) in pooled.iter().enumerate() {
+        let total: f64 = counts.iter().sum();
+        let rho = profile
+            .iter()
+            .zip(counts)
+            .filter(|&(&s, _)| s * total >= config.min_soup_counts)
+            .map(|(&s, &n)| n / total / s)
+            .reduce(f64::min)
+            .unwrap_or(0.0)
+            .clamp(0.0, 0.99);
+        let mut phi: Vec<f64> = counts
+            .iter()
+            .zip(profile)
+            .map(|(&n, &s)| (n / total.max(1.0) - rho * s).max(0.0))
+            .collect();
+        let phi_total: f64 = phi.iter().sum();
+        if phi_total > 0.0 {
+            phi.iter_mut().for_each(|p| *p /= phi_total);
+        }
+        cluster_contamination[k] = rho;
+        native.push(phi);
+    }
+
+    // Per-cell EM on the contamination fraction with profiles fixed
+    let contamination = entries
+        .iter()
+        .zip(&clusters)
+        .map(|(cell, &k)| {
+            let n: f64 = cell.iter().map(|&(_, v)| v as f64).sum();
+            if n == 0.0 {
+                return 0.0;
+            }
+            let mut rho = cluster_contamination[k].max(0.01);
+            for _ in 0..config.max_iter {
+                let ambient: f64 = cell
+                    .iter()
+                    .map(|&(g, v)| v as f64 * soup_share(rho, profile[g], native[k][g]))
+                    .sum();
+                let next = ambient / n;
+                let done = (next - rho).abs() < config.tolerance;
+                rho = next;
+                if done {
+                    break;
+                }
+            }
+            rho
+        })
+        .collect();
+
+    Ok(AmbientEstimate {
+        profile: profile.to_vec(),
+        cells: cells.to_vec(),
+        contamination,
+        cluster_contamination,
+        clusters,
+        native,
+    })
+}
+
+impl AmbientEstimate {
+    /// Cells-only matrix with each count reduced by its estimated soup share,
+    /// rounded to the nearest integer
+    pub fn correct(&self, matrix: &CountMatrix) -> CountMatrix {
+        let entries = cell_entries(matrix, &self.cells);
+        let mut corrected = CountMatrix {
+            barcodes: self.cells.iter().map(|&c| matrix.barcodes[c].clone()).collect(),
+            genes: matrix.genes.clone(),
+            gene_names: matrix.gene_names.clone(),
+            n_rows: matrix.n_rows,
+            n_cols: self.cells.len(),
+            gene_metadata: matrix.gene_metadata.clone(),
+            cell_metadata: matrix.cell_metadata.select(&self.cells),
+            ..CountMatrix::new()
+        };
+        for (i, cell) in entries.iter().enumerate() {
+            let (rho, native) = (self.contamination[i], &self.native[self.clusters[i]]);
+            for &(g, v) in cell {
+                let kept = v as f64 * (1.0 - soup_share(rho, self.profile[g], native[g]));
+                let kept = kept.round() as u32;
+                if kept > 0 {
+                    corrected.rows.push(g);
+                    corrected.cols.push(i);
+                    corrected.values.push(kept);
+                }
+            }
+        }
+        corrected
+    }
+
+    /// Mean contamination fraction across cells
+    pub fn mean_contamination(&self) -> f64 {
+        self.contamination.iter().sum::<f64>() / self.contamination.len().max(1) as f64
+    }
+
+    /// Write `barcode,contamination` for every cell
+    pub fn write_contamination<P: AsRef<Path>>(&self, matrix: &CountMatrix, path: P) -> Result<()> {
+        let mut out = AtomicFile::create(path)?;
+        writeln!(out, "barcode,contamination")?;
+        for (&c, rho) in self.cells.iter().zip(&self.contamination) {
+            writeln!(out, "{},{:.6}", matrix.barcodes[c], rho)?;
+        }
+        out.commit()
+    }
+
+    /// Write `gene\tfraction` for the soup profile, most abundant first
+    pub fn write_profile<P: AsRef<Path>>(&self, matrix: &CountMatrix, path: P) -> Result<()> {
+        let mut order: Vec<usize> = (0..self.profile.len()).collect();
+        order.sort_by(|&a, &b| self.profile[b].total_cmp(&self.profile[a]));
+        let mut out = AtomicFile::create(path)?;
+        writeln!(out, "gene\tfraction")?;
+        for g in order.into_iter().filter(|&g| self.profile[g] > 0.0) {
+            writeln!(out, "{}\t{:.6e}", matrix.genes[g], self.profile[g])?;
+        }
+        out.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cell types A (G0-G2) and B (G3-G5) with a 50/50 soup; cells 0-3 have
+    /// contamination 0.2, 0.1, 0.3, 0.05, and cells 4-5 are empty droplets
+    fn soupy_matrix() -> CountMatrix {
+        let cells: [[u32; 6]; 6] = [
+            [180, 180, 180, 20, 20, 20],
+            [190, 190, 190, 10, 10, 10],
+            [30, 30, 30, 170, 170, 170],
+            [5, 5, 5, 195, 195, 195],
+            [2, 2, 2, 2, 2, 2],
+            [1, 1, 1, 1, 1, 1],
+        ];
+        let data = (0..6).map(|g| cells.iter().map(|c| c[g]).collect()).collect();
+        CountMatrix::from_dense(
+            (0..6).map(|i| format!("BC{}", i)).collect(),
+            (0..6).map(|g| format!("G{}", g)).collect(),
+            data,
+        )
+    }
+
+    #[test]
+    fn test_soup_profile() {
+        let profile = soup_profile(&soupy_matrix(), &[4, 5]).unwrap();
+        for p in &profile {
+            assert!((p - 1.0 / 6.0).abs() < 1e-9);
+        }
+        assert!(soup_profile(&soupy_matrix(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_estimate_with_clusters() {
+        let matrix = soupy_matrix();
+        let profile = soup_profile(&matrix, &[4, 5]).unwrap();
+        let estimate = estimate_contamination(
+            &matrix,
+            &[0, 1, 2, 3],
+            Some(&[0, 0, 1, 1]),
+            &profile,
+            &AmbientConfig::default(),
+        )
+        .unwrap();
+
+        for (rho, truth) in estimate.contamination.iter().zip([0.2, 0.1, 0.3, 0.05]) {
+            assert!((rho - truth).abs() < 0.01, "{} vs {}", rho, truth);
+        }
+
+        let corrected = estimate.correct(&matrix);
+        assert_eq!(corrected.n_cols, 4);
+        assert_eq!(corrected.get(0, 0), 160);
+        assert_eq!(corrected.get(3, 0), 0);
+        assert_eq!(corrected.get(3, 2), 140);
+    }
+
+    #[test]
+    fn test_cluster_label_mismatch() {
+        let matrix = soupy_matrix();
+        let profile = soup_profile(&matrix, &[4, 5]).unwrap();
+        let config = AmbientConfig::default();
+        assert!(estimate_contamination(&matrix, &[0, 1], Some(&[0]), &profile, &config).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! Read-to-gene assignment with strand and multi-overlap policies
+
+use super::{
+    classify_splicing, Gene, GeneAnnotation, RegionType, SpliceClass, Strand, EXONIC_FRACTION,
+};
+use crate::{Error, Result};
+#[cfg(feature = "native")]
+use {super::aligned_blocks, rust_htslib::bam::record::Cigar};
+use std::fmt;
+use std::str::FromStr;
+
+/// How exon overlap with several genes (or partial overlap) is resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlapMode {
+    /// At least [`EXONIC_FRACTION`] of aligned bases in one gene's exons
+    #[default]
+    Unique,
+    /// Any aligned base in a gene's exons
+    Union,
+    /// Every aligned base in the gene's exons
+    IntersectionStrict,
+}
+
+impl OverlapMode {
+    fn is_exonic(self, overlap: i64, aligned: i64) -> bool {
+        match self {
+            OverlapMode::Unique => {
+                aligned > 0 && overlap as f64 / aligned as f64 >= EXONIC_FRACTION
+            }
+            OverlapMode::Union => overlap > 0,
+            OverlapMode::IntersectionStrict => aligned > 0 && overlap == aligned,
+        }
+    }
+}
+
+impl FromStr for OverlapMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "unique" => Ok(OverlapMode::Unique),
+            "union" => Ok(OverlapMode::Union),
+            "intersection-strict" => Ok(OverlapMode::IntersectionStrict),
+            _ => Err(Error::Config(format!(
+                "unknown overlap mode '{}' (expected unique, union, or intersection-strict)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for OverlapMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OverlapMode::Unique => "unique",
+            OverlapMode::Union => "union",
+            OverlapMode::IntersectionStrict => "intersection-strict",
+        })
+    }
+}
+
+/// Library strandedness: which read orientation counts as sense
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strandedness {
+    /// Reads align to the gene's strand (e.g. 10x 3')
+    Forward,
+    /// Reads align opposite the gene's strand
+    Reverse,
+    /// Orientation is ignored
+    #[default]
+    Unstranded,
+}
+
+impl Strandedness {
+    /// Whether a read on the reverse strand (or not) is sense for `gene`
+    pub fn is_sense(self, gene: &Gene, is_reverse: bool) -> bool {
+        self.is_sense_to(gene.strand, is_reverse)
+    }
+
+    /// Whether a read on the reverse strand (or not) is sense for a feature
+    /// on `strand`
+    pub fn is_sense_to(self, strand: Strand, is_reverse: bool) -> bool {
+        let read = if is_reverse { Strand::Reverse } else { Strand::Forward };
+        match (self, strand) {
+            (Strandedness::Unstranded, _) | (_, Strand::Unknown) => true,
+            (Strandedness::Forward, strand) => strand == read,
+            (Strandedness::Reverse, strand) => strand != read,
+        }
+    }
+}
+
+impl FromStr for Strandedness {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "forward" => Ok(Strandedness::Forward),
+            "reverse" => Ok(Strandedness::Reverse),
+            "unstranded" => Ok(Strandedness::Unstranded),
+            _ => Err(Error::Config(format!(
+                "unknown strandedness '{}' (expected forward, reverse, or unstranded)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Strandedness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Strandedness::Forward => "forward",
+            Strandedness::Reverse => "reverse",
+            Strandedness::Unstranded => "unstranded",
+        })
+    }
+}
+
+/// Outcome of assigning one alignment
+#[derive(Debug, Clone)]
+pub struct GeneAssignment<'a> {
+    /// Region class among sense genes
+    pub region: RegionType,
+    /// The assigned gene, when the region resolves to exactly one
+    pub gene: Option<&'a Gene>,
+    /// Sense genes compatible with the region
+    pub n_candidates: usize,
+    /// The read overlaps genes only on the antisense strand
+    pub antisense: bool,
+    /// Splicing state relative to the assigned gene
+    pub splice: Option<SpliceClass>,
+}
+
+impl<'a> GeneAssignment<'a> {
+    /// Gene ID (`GX`) of the assigned gene
+    pub fn gene_id(&self) -> Option<&'a str> {
+        self.gene.map(|g| g.id.as_str())
+    }
+
+    /// Gene symbol (`GN`) of the assigned gene
+    pub fn gene_name(&self) -> Option<&'a str> {
+        self.gene.map(|g| g.name.as_str())
+    }
+
+    /// More than one gene matched, so the read is left unassigned
+    pub fn is_ambiguous(&self) -> bool {
+        self.n_candidates > 1
+    }
+}
+
+/// Assigns alignments to genes using a [`GeneAnnotation`]
+#[derive(Debug, Clone)]
+pub struct GeneAssigner<'a> {
+    annotation: &'a GeneAnnotation,
+    overlap_mode: OverlapMode,
+    strandedness: Strandedness,
+    include_introns: bool,
+}
+
+impl<'a> GeneAssigner<'a> {
+    /// Unique overlap, unstranded, exonic reads only
+    pub fn new(annotation: &'a GeneAnnotation) -> Self {
+        Self {
+            annotation,
+            overlap_mode: OverlapMode::default(),
+            strandedness: Strandedness::default(),
+            include_introns: false,
+        }
+    }
+
+    /// Set the multi-overlap policy
+    pub fn overlap_mode(mut self, mode: OverlapMode) -> Self {
+        self.overlap_mode = mode;
+        self
+    }
+
+    /// Set the library strandedness
+    pub fn strandedness(mut self, strandedness: Strandedness) -> Self {
+        self.strandedness = strandedness;
+        self
+    }
+
+    /// Also assign intronic reads that fall within exactly one gene
+    pub fn include_introns(mut self, include: bool) -> Self {
+        self.include_introns = include;
+        self
+    }
+
+    /// The annotation genes are drawn from
+    pub fn annotation(&self) -> &'a GeneAnnotation {
+        self.annotation
+    }
+
+    /// Assign an alignment from its 0-based position and CIGAR
+    #[cfg(feature = "native")]
+    pub fn assign<'c, I>(
+        &self,
+        chrom: &str,
+        pos: i64,
+        cigar: I,
+        is_reverse: bool,
+    ) -> GeneAssignment<'a>
+    where
+        I: IntoIterator<Item = &'c Cigar>,
+    {
+        self.assign_blocks(chrom, &aligned_blocks(pos, cigar), is_reverse)
+    }
+
+    /// Assign an alignment from its aligned reference blocks
+    pub fn assign_blocks(
+        &self,
+        chrom: &str,
+        blocks: &[(i64, i64)],
+        is_reverse: bool,
+    ) -> GeneAssignment<'a> {
+        let unassigned = |region, antisense| GeneAssignment {
+            region,
+            gene: None,
+            n_candidates: 0,
+            antisense,
+            splice: None,
+        };
+        let (Some(start), Some(end)) = (
+            blocks.iter().map(|b| b.0).min(),
+            blocks.iter().map(|b| b.1).max(),
+        ) else {
+            return unassigned(RegionType::Intergenic, false);
+        };
+
+        let overlapping = self.annotation.overlapping(chrom, start, end);
+        let candidates: Vec<&'a Gene> = overlapping
+            .iter()
+            .map(|&i| self.annotation.gene(i))
+            .filter(|g| self.strandedness.is_sense(g, is_reverse))
+            .collect();
+        if candidates.is_empty() {
+            return unassigned(RegionType::Intergenic, !overlapping.is_empty());
+        }
+
+        let aligned: i64 = blocks.iter().map(|(s, e)| e - s).sum();
+        let exonic: Vec<&'a Gene> = candidates
+            .iter()
+            .copied()
+            .filter(|g| {
+                let overlap: i64 = blocks.iter().map(|&(s, e)| g.exonic_overlap(s, e)).sum();
+                self.overlap_mode.is_exonic(overlap, aligned)
+            })
+            .collect();
+
+        let (region, genes) = if exonic.is_empty() {
+            (RegionType::Intronic, candidates)
+        } else {
+            (RegionType::Exonic, exonic)
+        };
+        let assignable = region == RegionType::Exonic || self.include_introns;
+        let gene = match genes.as_slice() {
+            [g] if assignable => Some(*g),
+            _ => None,
+        };
+        GeneAssignment {
+            region,
+            gene,
+            n_candidates: genes.len(),
+            antisense: false,
+            splice: gene.map(|g| classify_splicing(g, blocks)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GTF: &str = "\
+chr1\tsrc\texon\t101\t200\t.\t+\t.\tgene_id \"G1\"; gene_name \"Alpha\";
+chr1\tsrc\texon\t401\t500\t.\t+\t.\tgene_id \"G1\"; gene_name \"Alpha\";
+chr1\tsrc\texon\t181\t300\t.\t-\t.\tgene_id \"G2\"; gene_name \"Beta\";
+";
+
+    fn annotation() -> GeneAnnotation {
+        GeneAnnotation::from_reader(GTF.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_overlap_modes() {
+        let ann = annotation();
+        // 150..190 (40 bases): all in G1's exon, last 10 also in G2's exon
+        let blocks = [(150, 190)];
+
+        let unique = GeneAssigner::new(&ann).assign_blocks("chr1", &blocks, false);
+        assert_eq!(unique.region, RegionType::Exonic);
+        assert_eq!(unique.gene_id(), Some("G1"));
+
+        let union = GeneAssigner::new(&ann)
+            .overlap_mode(OverlapMode::Union)
+            .assign_blocks("chr1", &blocks, false);
+        assert!(union.is_ambiguous());
+        assert_eq!(union.gene_id(), None);
+
+        // 190..230 leaves G1's exon, so only G2 covers every base
+        let strict = GeneAssigner::new(&ann)
+            .overlap_mode(OverlapMode::IntersectionStrict)
+            .assign_blocks("chr1", &[(190, 230)], false);
+        assert_eq!(strict.gene_name(), Some("Beta"));
+    }
+
+    #[test]
+    fn test_strandedness() {
+        let ann = annotation();
+        let forward = GeneAssigner::new(&ann)
+            .overlap_mode(OverlapMode::Union)
+            .strandedness(Strandedness::Forward);
+
+        let sense = forward.assign_blocks("chr1", &[(150, 190)], false);
+        assert_eq!(sense.gene_id(), Some("G1"));
+        let reversed = forward.assign_blocks("chr1", &[(150, 190)], true);
+        assert_eq!(reversed.gene_id(), Some("G2"));
+
+        let reverse = forward.clone().strandedness(Strandedness::Reverse);
+        let antisense = reverse.assign_blocks("chr1", &[(120, 140)], false);
+        assert_eq!(antisense.region, RegionType::Intergenic);
+        assert!(antisense.antisense);
+    }
+
+    #[test]
+    fn test_introns_and_cigar() {
+        let ann = annotation();
+        let assigner = GeneAssigner::new(&ann);
+        let intronic = assigner.assign_blocks("chr1", &[(320, 360)], false);
+        assert_eq!(intronic.region, RegionType::Intronic);
+        assert_eq!(intronic.gene, None);
+
+        let with_introns = assigner.clone().include_introns(true);
+        let assigned = with_introns.assign_blocks("chr1", &[(320, 360)], false);
+        assert_eq!(assigned.gene_id(), Some("G1"));
+
+        // Spliced read: 20M skipping the intron to 20M in the second exon
+        let cigar = [Cigar::Match(20), Cigar::RefSkip(220), Cigar::Match(20)];
+        let spliced = assigner.assign("chr1", 160, cigar.iter(), false);
+        assert_eq!(spliced.region, RegionType::Exonic);
+        assert_eq!(spliced.splice, Some(SpliceClass::Spliced));
+        assert_eq!(assigned.splice, Some(SpliceClass::Unspliced));
+
+        let mode: OverlapMode = "intersection-strict".parse().unwrap();
+        assert_eq!(mode, OverlapMode::IntersectionStrict);
+        assert!("sense".parse::<Strandedness>().is_err());
+    }
+}
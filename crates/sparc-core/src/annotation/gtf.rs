@@ -0,0 +1,97 @@
+//! Minimal GTF (GFF2) line parser
+
+use crate::{Error, Result};
+use ahash::AHashMap;
+
+/// A single GTF feature line
+#[derive(Debug, Clone)]
+pub struct GtfRecord {
+    /// Sequence (chromosome) name
+    pub seqname: String,
+    /// Feature type (gene, transcript, exon, ...)
+    pub feature: String,
+    /// Start position (0-based, inclusive)
+    pub start: i64,
+    /// End position (0-based, exclusive)
+    pub end: i64,
+    /// Strand character (`+`, `-`, or `.`)
+    pub strand: char,
+    /// Attribute key/value pairs
+    pub attributes: AHashMap<String, String>,
+}
+
+impl GtfRecord {
+    /// Parse one GTF line; `line_num` is used in error messages
+    pub fn parse(line: &str, line_num: usize) -> Result<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 9 {
+            return Err(Error::Annotation(format!(
+                "GTF line {}: expected 9 tab-separated fields, found {}",
+                line_num,
+                fields.len()
+            )));
+        }
+
+        let coord = |s: &str, what: &str| -> Result<i64> {
+            s.parse::<i64>().map_err(|_| {
+                Error::Annotation(format!("GTF line {}: invalid {} '{}'", line_num, what, s))
+            })
+        };
+        let start = coord(fields[3], "start")?;
+        let end = coord(fields[4], "end")?;
+        if start < 1 || end < start {
+            return Err(Error::Annotation(format!(
+                "GTF line {}: invalid interval {}-{}",
+                line_num, start, end
+            )));
+        }
+
+        Ok(Self {
+            seqname: fields[0].to_string(),
+            feature: fields[2].to_string(),
+            start: start - 1,
+            end,
+            strand: fields[6].chars().next().unwrap_or('.'),
+            attributes: parse_attributes(fields[8]),
+        })
+    }
+
+    /// Look up an attribute value
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(|s| s.as_str())
+    }
+}
+
+/// Parse the GTF attribute column (`key "value"; key "value";`)
+pub fn parse_attributes(column: &str) -> AHashMap<String, String> {
+    column
+        .split(';')
+        .filter_map(|attr| {
+            let attr = attr.trim();
+            let (key, value) = attr.split_once(char::is_whitespace)?;
+            Some((key.to_string(), value.trim().trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exon_line() {
+        let line = "chr1\tHAVANA\texon\t11869\t12227\t.\t+\t.\tgene_id \"ENSG00000223972\"; gene_name \"DDX11L1\";";
+        let rec = GtfRecord::parse(line, 1).unwrap();
+        assert_eq!(rec.seqname, "chr1");
+        assert_eq!(rec.feature, "exon");
+        assert_eq!(rec.start, 11868);
+        assert_eq!(rec.end, 12227);
+        assert_eq!(rec.strand, '+');
+        assert_eq!(rec.attribute("gene_name"), Some("DDX11L1"));
+    }
+
+    #[test]
+    fn test_parse_rejects_short_line() {
+        assert!(GtfRecord::parse("chr1\tsrc\texon", 3).is_err());
+    }
+}
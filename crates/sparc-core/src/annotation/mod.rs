@@ -0,0 +1,539 @@
+//! Gene annotation (GTF) and read-to-gene region assignment
+
+mod assign;
+mod gtf;
+mod splice;
+mod transcript;
+
+pub use assign::{GeneAssigner, GeneAssignment, OverlapMode, Strandedness};
+pub use gtf::{parse_attributes, GtfRecord};
+pub use splice::{classify_splicing, SpliceClass};
+pub use transcript::{Transcript, TranscriptAnnotation};
+
+pub use crate::intervals::Strand;
+
+#[cfg(feature = "native")]
+use crate::bam::BamWriter;
+use crate::intervals::{merge_intervals, IntervalIndex};
+use crate::{Error, Result};
+use ahash::AHashMap;
+use flate2::read::MultiGzDecoder;
+#[cfg(feature = "native")]
+use rust_htslib::bam::{self, record::Aux, record::Cigar, Read};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Minimum fraction of aligned bases inside exons for a read to be exonic
+pub const EXONIC_FRACTION: f64 = 0.5;
+
+/// A gene with its merged exon intervals
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Gene {
+    /// Gene ID (GTF `gene_id`)
+    pub id: String,
+    /// Gene symbol (GTF `gene_name`, falling back to the ID)
+    pub name: String,
+    /// Biotype (GTF `gene_type` or `gene_biotype`)
+    pub biotype: Option<String>,
+    /// Chromosome
+    pub chrom: String,
+    /// Gene start (0-based, inclusive)
+    pub start: i64,
+    /// Gene end (0-based, exclusive)
+    pub end: i64,
+    /// Strand
+    pub strand: Strand,
+    /// Sorted, non-overlapping exon intervals (0-based, half-open)
+    pub exons: Vec<(i64, i64)>,
+}
+
+impl Gene {
+    /// Number of bases of `[start, end)` covered by this gene's exons
+    pub fn exonic_overlap(&self, start: i64, end: i64) -> i64 {
+        self.exons
+            .iter()
+            .map(|&(s, e)| (end.min(e) - start.max(s)).max(0))
+            .sum()
+    }
+}
+
+/// Genomic region class of a read (10x `RE` tag values)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RegionType {
+    Exonic,
+    Intronic,
+    Intergenic,
+}
+
+impl RegionType {
+    /// Single-character `RE` tag value
+    pub fn tag(&self) -> u8 {
+        match self {
+            RegionType::Exonic => b'E',
+            RegionType::Intronic => b'N',
+            RegionType::Intergenic => b'I',
+        }
+    }
+
+    /// Parse an `RE` tag value
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            b'E' => Some(RegionType::Exonic),
+            b'N' => Some(RegionType::Intronic),
+            b'I' => Some(RegionType::Intergenic),
+            _ => None,
+        }
+    }
+}
+
+/// Region and candidate genes for one alignment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadAnnotation {
+    /// Region class
+    pub region: RegionType,
+    /// Indices of genes compatible with the region (empty if intergenic)
+    pub genes: Vec<usize>,
+}
+
+impl ReadAnnotation {
+    /// The gene index if the read is assigned to exactly one gene
+    pub fn gene(&self) -> Option<usize> {
+        match self.genes.as_slice() {
+            [g] => Some(*g),
+            _ => None,
+        }
+    }
+}
+
+/// Gene models indexed by chromosome for overlap queries
+#[derive(Debug, Clone, Default)]
+pub struct GeneAnnotation {
+    genes: Vec<Gene>,
+    /// Gene spans by chromosome
+    index: IntervalIndex,
+}
+
+impl GeneAnnotation {
+    /// Load gene models from a GTF file (plain or gzip)
+    pub fn from_gtf<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        log::info!("Loading GTF: {:?}", path);
+        let annotation = Self::from_reader(gtf_reader(path)?)?;
+        log::info!("Loaded {} genes", annotation.len());
+        Ok(annotation)
+    }
+
+    /// Load gene models from GTF text
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
+        let mut genes: Vec<Gene> = Vec::new();
+        let mut index: AHashMap<String, usize> = AHashMap::new();
+        let mut has_gene_line: Vec<bool> = Vec::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rec = GtfRecord::parse(&line, i + 1)?;
+            if rec.feature != "gene" && rec.feature != "exon" {
+                continue;
+            }
+            let id = rec.attribute("gene_id").ok_or_else(|| {
+                Error::Annotation(format!("GTF line {}: missing gene_id attribute", i + 1))
+            })?;
+
+            let idx = *index.entry(id.to_string()).or_insert_with(|| {
+                genes.push(Gene {
+                    id: id.to_string(),
+                    name: rec.attribute("gene_name").unwrap_or(id).to_string(),
+                    biotype: rec
+                        .attribute("gene_type")
+                        .or_else(|| rec.attribute("gene_biotype"))
+                        .map(|s| s.to_string()),
+                    chrom: rec.seqname.clone(),
+                    start: rec.start,
+                    end: rec.end,
+                    strand: Strand::from_char(rec.strand),
+                    exons: Vec::new(),
+                });
+                has_gene_line.push(false);
+                genes.len() - 1
+            });
+
+            let gene = &mut genes[idx];
+            if rec.feature == "gene" {
+                gene.start = rec.start;
+                gene.end = rec.end;
+                has_gene_line[idx] = true;
+            } else {
+                gene.exons.push((rec.start, rec.end));
+                if !has_gene_line[idx] {
+                    gene.start = gene.start.min(rec.start);
+                    gene.end = gene.end.max(rec.end);
+                }
+            }
+        }
+
+        for gene in &mut genes {
+            gene.exons = merge_intervals(std::mem::take(&mut gene.exons));
+        }
+        Ok(Self::from_genes(genes))
+    }
+
+    /// Build an index over already-constructed genes
+    pub fn from_genes(genes: Vec<Gene>) -> Self {
+        let index = IntervalIndex::new(genes.iter().map(|g| (g.chrom.as_str(), g.start, g.end)));
+        Self { genes, index }
+    }
+
+    /// All genes in GTF order
+    pub fn genes(&self) -> &[Gene] {
+        &self.genes
+    }
+
+    /// Gene by index
+    pub fn gene(&self, idx: usize) -> &Gene {
+        &self.genes[idx]
+    }
+
+    /// Number of genes
+    pub fn len(&self) -> usize {
+        self.genes.len()
+    }
+
+    /// Whether no genes were loaded
+    pub fn is_empty(&self) -> bool {
+        self.genes.is_empty()
+    }
+
+    /// Indices of genes whose span overlaps `[start, end)` on `chrom`
+    pub fn overlapping(&self, chrom: &str, start: i64, end: i64) -> Vec<usize> {
+        self.index.overlapping(chrom, start, end)
+    }
+
+    /// Classify an alignment given its aligned reference blocks.
+    ///
+    /// A read is exonic for a gene when at least [`EXONIC_FRACTION`] of its
+    /// aligned bases fall in that gene's exons, intronic when it overlaps a gene
+    /// without being exonic for any, and intergenic otherwise.
+    pub fn annotate(&self, chrom: &str, blocks: &[(i64, i64)]) -> ReadAnnotation {
+        let (Some(start), Some(end)) = (
+            blocks.iter().map(|b| b.0).min(),
+            blocks.iter().map(|b| b.1).max(),
+        ) else {
+            return ReadAnnotation {
+                region: RegionType::Intergenic,
+                genes: Vec::new(),
+            };
+        };
+
+        let candidates = self.overlapping(chrom, start, end);
+        let aligned: i64 = blocks.iter().map(|(s, e)| e - s).sum();
+        let exonic: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|&i| {
+                let gene = &self.genes[i];
+                let overlap: i64 = blocks.iter().map(|&(s, e)| gene.exonic_overlap(s, e)).sum();
+                aligned > 0 && overlap as f64 / aligned as f64 >= EXONIC_FRACTION
+            })
+            .collect();
+
+        if !exonic.is_empty() {
+            ReadAnnotation {
+                region: RegionType::Exonic,
+                genes: exonic,
+            }
+        } else if !candidates.is_empty() {
+            ReadAnnotation {
+                region: RegionType::Intronic,
+                genes: candidates,
+            }
+        } else {
+            ReadAnnotation {
+                region: RegionType::Intergenic,
+                genes: Vec::new(),
+            }
+        }
+    }
+}
+
+/// Buffered GTF text, decompressed when the path ends in `.gz`
+fn gtf_reader(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    Ok(if path.extension().map_or(false, |ext| ext == "gz") {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    })
+}
+
+/// Reference blocks covered by an alignment (split at `N` skips)
+#[cfg(feature = "native")]
+pub fn aligned_blocks<'a, I>(pos: i64, cigar: I) -> Vec<(i64, i64)>
+where
+    I: IntoIterator<Item = &'a Cigar>,
+{
+    let mut blocks = Vec::new();
+    let mut ref_pos = pos;
+    let mut block_start = pos;
+    for op in cigar {
+        match *op {
+            Cigar::Match(len) | Cigar::Equal(len) | Cigar::Diff(len) | Cigar::Del(len) => {
+                ref_pos += len as i64;
+            }
+            Cigar::RefSkip(len) => {
+                if ref_pos > block_start {
+                    blocks.push((block_start, ref_pos));
+                }
+                ref_pos += len as i64;
+                block_start = ref_pos;
+            }
+            Cigar::Ins(_) | Cigar::SoftClip(_) | Cigar::HardClip(_) | Cigar::Pad(_) => {}
+        }
+    }
+    if ref_pos > block_start {
+        blocks.push((block_start, ref_pos));
+    }
+    blocks
+}
+
+/// Summary of a BAM annotation run
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct AnnotateStats {
+    pub total_reads: u64,
+    pub unmapped_reads: u64,
+    pub exonic_reads: u64,
+    pub intronic_reads: u64,
+    pub intergenic_reads: u64,
+    /// Reads overlapping more than one gene (left without GX/GN)
+    pub ambiguous_reads: u64,
+    /// Reads overlapping genes only on the antisense strand (stranded mode)
+    #[serde(default)]
+    pub antisense_reads: u64,
+    /// Reads tagged with GX/GN
+    pub assigned_reads: u64,
+    /// Assigned reads with only exonic bases
+    #[serde(default)]
+    pub spliced_reads: u64,
+    /// Assigned reads with intronic bases and no splice junction
+    #[serde(default)]
+    pub unspliced_reads: u64,
+    /// Assigned reads with conflicting splicing evidence
+    #[serde(default)]
+    pub splice_ambiguous_reads: u64,
+}
+
+impl AnnotateStats {
+    /// Count one mapped read's assignment
+    pub fn record(&mut self, ann: &GeneAssignment) {
+        match ann.region {
+            RegionType::Exonic => self.exonic_reads += 1,
+            RegionType::Intronic => self.intronic_reads += 1,
+            RegionType::Intergenic => self.intergenic_reads += 1,
+        }
+        if ann.is_ambiguous() {
+            self.ambiguous_reads += 1;
+        }
+        if ann.antisense {
+            self.antisense_reads += 1;
+        }
+        if ann.gene.is_some() {
+            self.assigned_reads += 1;
+        }
+        match ann.splice {
+            Some(SpliceClass::Spliced) => self.spliced_reads += 1,
+            Some(SpliceClass::Unspliced) => self.unspliced_reads += 1,
+            Some(SpliceClass::Ambiguous) => self.splice_ambiguous_reads += 1,
+            None => {}
+        }
+    }
+
+    /// Add these totals to the `annotate` counters of `metrics`
+    pub fn add_to_metrics(&self, metrics: &crate::metrics::Metrics) {
+        let counters = [
+            ("reads", self.total_reads),
+            ("unmapped", self.unmapped_reads),
+            ("exonic", self.exonic_reads),
+            ("intronic", self.intronic_reads),
+            ("intergenic", self.intergenic_reads),
+            ("ambiguous", self.ambiguous_reads),
+            ("antisense", self.antisense_reads),
+            ("assigned", self.assigned_reads),
+            ("spliced", self.spliced_reads),
+            ("unspliced", self.unspliced_reads),
+            ("splice_ambiguous", self.splice_ambiguous_reads),
+        ];
+        for (name, value) in counters {
+            metrics.counter("annotate", name).add(value);
+        }
+    }
+
+    /// Mapped reads (the denominator of region fractions)
+    pub fn mapped_reads(&self) -> u64 {
+        self.exonic_reads + self.intronic_reads + self.intergenic_reads
+    }
+}
+
+/// Fractions of mapped reads by genomic region and of assigned reads by
+/// splicing state, for the QC report
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RegionMetrics {
+    pub fraction_exonic: f64,
+    pub fraction_intronic: f64,
+    pub fraction_intergenic: f64,
+    /// Reads overlapping genes only on the antisense strand
+    pub fraction_antisense: f64,
+    /// Reads overlapping more than one gene
+    pub fraction_multi_gene: f64,
+    /// Spliced share of assigned reads
+    pub fraction_spliced: f64,
+    /// Unspliced share of assigned reads
+    pub fraction_unspliced: f64,
+    /// Ambiguous share of assigned reads
+    pub fraction_splice_ambiguous: f64,
+}
+
+impl RegionMetrics {
+    pub fn from_stats(stats: &AnnotateStats) -> Self {
+        let mapped = stats.mapped_reads().max(1) as f64;
+        let assigned = stats.assigned_reads.max(1) as f64;
+        Self {
+            fraction_exonic: stats.exonic_reads as f64 / mapped,
+            fraction_intronic: stats.intronic_reads as f64 / mapped,
+            fraction_intergenic: stats.intergenic_reads as f64 / mapped,
+            fraction_antisense: stats.antisense_reads as f64 / mapped,
+            fraction_multi_gene: stats.ambiguous_reads as f64 / mapped,
+            fraction_spliced: stats.spliced_reads as f64 / assigned,
+            fraction_unspliced: stats.unspliced_reads as f64 / assigned,
+            fraction_splice_ambiguous: stats.splice_ambiguous_reads as f64 / assigned,
+        }
+    }
+}
+
+/// Tag every mapped read in `input` with RE (region) and, when uniquely
+/// assigned, GX/GN, writing the result to `output`.
+///
+/// Overlap policy, strandedness, and whether intronic reads are assigned all
+/// come from `assigner`.
+#[cfg(feature = "native")]
+pub fn annotate_bam<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    assigner: &GeneAssigner,
+) -> Result<AnnotateStats> {
+    let mut reader = bam::Reader::from_path(input.as_ref())
+        .map_err(|e| Error::BamParse(format!("Failed to open BAM: {}", e)))?;
+    let chrom_names: Vec<String> = reader
+        .header()
+        .target_names()
+        .iter()
+        .map(|n| String::from_utf8_lossy(n).to_string())
+        .collect();
+
+    let mut header = bam::Header::from_template(reader.header());
+    let mut pg = bam::header::HeaderRecord::new(b"PG");
+    pg.push_tag(b"ID", "sparc-annotate");
+    pg.push_tag(b"PN", "sparc");
+    pg.push_tag(b"VN", env!("CARGO_PKG_VERSION"));
+    header.push_record(&pg);
+    let mut writer = BamWriter::new(output.as_ref(), &header)?;
+
+    let mut stats = AnnotateStats::default();
+    let mut record = bam::Record::new();
+    while let Some(result) = reader.read(&mut record) {
+        result.map_err(|e| Error::BamParse(e.to_string()))?;
+        stats.total_reads += 1;
+
+        for tag in [b"GX", b"GN", b"RE"] {
+            let _ = record.remove_aux(tag);
+        }
+
+        let chrom = usize::try_from(record.tid())
+            .ok()
+            .and_then(|tid| chrom_names.get(tid));
+        let chrom = match chrom {
+            Some(c) if !record.is_unmapped() => c,
+            _ => {
+                stats.unmapped_reads += 1;
+                writer.write(&record)?;
+                continue;
+            }
+        };
+
+        let ann = assigner.assign(chrom, record.pos(), record.cigar().iter(), record.is_reverse());
+        stats.record(&ann);
+
+        let tag_err = |e: rust_htslib::errors::Error| Error::BamParse(format!("Failed to set tag: {}", e));
+        record.push_aux(b"RE", Aux::Char(ann.region.tag())).map_err(tag_err)?;
+
+        if let Some(gene) = ann.gene {
+            record.push_aux(b"GX", Aux::String(&gene.id)).map_err(tag_err)?;
+            record.push_aux(b"GN", Aux::String(&gene.name)).map_err(tag_err)?;
+        }
+
+        writer.write(&record)?;
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GTF: &str = "\
+#!genome-build test
+chr1\tsrc\tgene\t101\t1000\t.\t+\t.\tgene_id \"G1\"; gene_name \"Alpha\"; gene_type \"protein_coding\";
+chr1\tsrc\texon\t101\t200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tsrc\texon\t901\t1000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tsrc\texon\t151\t250\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";
+chr1\tsrc\texon\t5001\t5100\t.\t-\t.\tgene_id \"G2\"; gene_name \"Beta\";
+";
+
+    fn annotation() -> GeneAnnotation {
+        GeneAnnotation::from_reader(GTF.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_load_gtf() {
+        let ann = annotation();
+        assert_eq!(ann.len(), 2);
+        let g1 = ann.gene(0);
+        assert_eq!(g1.name, "Alpha");
+        assert_eq!(g1.biotype.as_deref(), Some("protein_coding"));
+        assert_eq!(g1.exons, vec![(100, 250), (900, 1000)]);
+        let g2 = ann.gene(1);
+        assert_eq!((g2.start, g2.end), (5000, 5100));
+        assert_eq!(g2.strand, Strand::Reverse);
+    }
+
+    #[test]
+    fn test_annotate_regions() {
+        let ann = annotation();
+        let exonic = ann.annotate("chr1", &[(110, 160)]);
+        assert_eq!(exonic.region, RegionType::Exonic);
+        assert_eq!(exonic.gene(), Some(0));
+
+        let intronic = ann.annotate("chr1", &[(400, 450)]);
+        assert_eq!(intronic.region, RegionType::Intronic);
+
+        let intergenic = ann.annotate("chr1", &[(2000, 2050)]);
+        assert_eq!(intergenic.region, RegionType::Intergenic);
+        assert!(ann.annotate("chr2", &[(110, 160)]).genes.is_empty());
+    }
+
+    #[test]
+    fn test_aligned_blocks_spliced() {
+        let cigar = [
+            Cigar::SoftClip(5),
+            Cigar::Match(50),
+            Cigar::RefSkip(650),
+            Cigar::Match(48),
+        ];
+        let blocks = aligned_blocks(200, cigar.iter());
+        assert_eq!(blocks, vec![(200, 250), (900, 948)]);
+        assert_eq!(annotation().annotate("chr1", &blocks).region, RegionType::Exonic);
+    }
+}
@@ -0,0 +1,111 @@
+//! Spliced/unspliced read classification for RNA velocity
+
+use super::Gene;
+use std::fmt;
+
+/// Splicing state of a read relative to its gene
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpliceClass {
+    /// Only exonic bases: mature mRNA
+    Spliced,
+    /// Intronic bases without a splice junction: pre-mRNA
+    Unspliced,
+    /// Conflicting evidence, e.g. a junction plus intronic bases, or no overlap
+    Ambiguous,
+}
+
+impl SpliceClass {
+    /// Combine the classes of reads from one molecule: agreement keeps the
+    /// class, disagreement is ambiguous
+    pub fn merge(self, other: SpliceClass) -> SpliceClass {
+        if self == other {
+            self
+        } else {
+            SpliceClass::Ambiguous
+        }
+    }
+
+    /// Position in `[spliced, unspliced, ambiguous]` arrays
+    pub fn index(self) -> usize {
+        match self {
+            SpliceClass::Spliced => 0,
+            SpliceClass::Unspliced => 1,
+            SpliceClass::Ambiguous => 2,
+        }
+    }
+}
+
+impl fmt::Display for SpliceClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SpliceClass::Spliced => "spliced",
+            SpliceClass::Unspliced => "unspliced",
+            SpliceClass::Ambiguous => "ambiguous",
+        })
+    }
+}
+
+/// Classify an alignment against `gene` from its aligned reference blocks.
+///
+/// Blocks come from [`super::aligned_blocks`], so more than one block means the
+/// CIGAR has a splice junction (`N`). Bases outside the gene span are ignored;
+/// bases inside the span but outside every exon are intronic.
+pub fn classify_splicing(gene: &Gene, blocks: &[(i64, i64)]) -> SpliceClass {
+    let (mut exonic, mut in_gene) = (0, 0);
+    for &(s, e) in blocks {
+        exonic += gene.exonic_overlap(s, e);
+        in_gene += (e.min(gene.end) - s.max(gene.start)).max(0);
+    }
+    let intronic = in_gene - exonic;
+    let junction = blocks.len() > 1;
+    match (exonic > 0, intronic > 0, junction) {
+        (_, true, true) | (false, false, _) => SpliceClass::Ambiguous,
+        (_, true, false) => SpliceClass::Unspliced,
+        (true, false, _) => SpliceClass::Spliced,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{aligned_blocks, GeneAnnotation};
+    use super::*;
+    use rust_htslib::bam::record::Cigar;
+
+    fn gene() -> Gene {
+        let gtf = "\
+chr1\tsrc\texon\t101\t200\t.\t+\t.\tgene_id \"G1\";
+chr1\tsrc\texon\t401\t500\t.\t+\t.\tgene_id \"G1\";
+";
+        GeneAnnotation::from_reader(gtf.as_bytes()).unwrap().gene(0).clone()
+    }
+
+    #[test]
+    fn test_classify_splicing() {
+        let gene = gene();
+        // Inside the first exon
+        assert_eq!(classify_splicing(&gene, &[(120, 170)]), SpliceClass::Spliced);
+        // Junction joining both exons
+        let cigar = [Cigar::Match(30), Cigar::RefSkip(200), Cigar::Match(20)];
+        let blocks = aligned_blocks(170, cigar.iter());
+        assert_eq!(classify_splicing(&gene, &blocks), SpliceClass::Spliced);
+        // Inside the intron, and across the exon-intron boundary
+        assert_eq!(classify_splicing(&gene, &[(250, 300)]), SpliceClass::Unspliced);
+        assert_eq!(classify_splicing(&gene, &[(180, 230)]), SpliceClass::Unspliced);
+        // A junction whose second block lands in the intron
+        assert_eq!(
+            classify_splicing(&gene, &[(150, 200), (300, 320)]),
+            SpliceClass::Ambiguous
+        );
+        // Outside the gene
+        assert_eq!(classify_splicing(&gene, &[(600, 650)]), SpliceClass::Ambiguous);
+    }
+
+    #[test]
+    fn test_merge_molecule_classes() {
+        use SpliceClass::*;
+        assert_eq!(Spliced.merge(Spliced), Spliced);
+        assert_eq!(Spliced.merge(Unspliced), Ambiguous);
+        assert_eq!(Unspliced.merge(Ambiguous), Ambiguous);
+    }
+}
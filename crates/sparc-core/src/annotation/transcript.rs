@@ -0,0 +1,236 @@
+//! Transcript models and read-to-transcript assignment
+//!
+//! A read is compatible with a transcript when at least [`EXONIC_FRACTION`]
+//! of its aligned bases fall in the transcript's exons and each of its
+//! splice junctions is an intron of the transcript. Of the compatible
+//! transcripts, those covering the most aligned bases are kept.
+
+use ahash::AHashMap;
+use std::io::BufRead;
+use std::path::Path;
+
+use super::{gtf_reader, GtfRecord, EXONIC_FRACTION};
+use crate::intervals::{IntervalIndex, Strand};
+use crate::{Error, Result};
+
+/// A transcript with its exons
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Transcript {
+    /// Transcript ID (GTF `transcript_id`)
+    pub id: String,
+    /// Transcript name (GTF `transcript_name`, falling back to the ID)
+    pub name: String,
+    /// Gene ID of the transcript
+    pub gene_id: String,
+    /// Gene symbol (GTF `gene_name`, falling back to the gene ID)
+    pub gene_name: String,
+    /// Chromosome
+    pub chrom: String,
+    /// Transcript start (0-based, inclusive)
+    pub start: i64,
+    /// Transcript end (0-based, exclusive)
+    pub end: i64,
+    /// Strand
+    pub strand: Strand,
+    /// Sorted exon intervals (0-based, half-open)
+    pub exons: Vec<(i64, i64)>,
+}
+
+impl Transcript {
+    /// Number of bases of `[start, end)` covered by this transcript's exons
+    pub fn exonic_overlap(&self, start: i64, end: i64) -> i64 {
+        self.exons
+            .iter()
+            .map(|&(s, e)| (end.min(e) - start.max(s)).max(0))
+            .sum()
+    }
+
+    /// Whether `[donor, acceptor)` is an intron between two adjacent exons
+    pub fn has_intron(&self, donor: i64, acceptor: i64) -> bool {
+        self.exons.windows(2).any(|w| w[0].1 == donor && w[1].0 == acceptor)
+    }
+
+    /// Exonic length in bases
+    pub fn length(&self) -> i64 {
+        self.exons.iter().map(|&(s, e)| e - s).sum()
+    }
+}
+
+/// Transcript models indexed by chromosome for overlap queries
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptAnnotation {
+    transcripts: Vec<Transcript>,
+    index: IntervalIndex,
+    /// Transcript ID -> index in `transcripts`
+    by_id: AHashMap<String, usize>,
+}
+
+impl TranscriptAnnotation {
+    /// Load transcript models from a GTF file (plain or gzip)
+    pub fn from_gtf<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        log::info!("Loading transcripts from GTF: {:?}", path);
+        let annotation = Self::from_reader(gtf_reader(path)?)?;
+        log::info!("Loaded {} transcripts", annotation.len());
+        Ok(annotation)
+    }
+
+    /// Load transcript models from the exon lines of GTF text; exons without
+    /// a `transcript_id` are skipped
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
+        let mut transcripts: Vec<Transcript> = Vec::new();
+        let mut index: AHashMap<String, usize> = AHashMap::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rec = GtfRecord::parse(&line, i + 1)?;
+            if rec.feature != "exon" {
+                continue;
+            }
+            let Some(id) = rec.attribute("transcript_id") else {
+                continue;
+            };
+            let gene_id = rec.attribute("gene_id").ok_or_else(|| {
+                Error::Annotation(format!("GTF line {}: missing gene_id attribute", i + 1))
+            })?;
+
+            let idx = *index.entry(id.to_string()).or_insert_with(|| {
+                transcripts.push(Transcript {
+                    id: id.to_string(),
+                    name: rec.attribute("transcript_name").unwrap_or(id).to_string(),
+                    gene_id: gene_id.to_string(),
+                    gene_name: rec.attribute("gene_name").unwrap_or(gene_id).to_string(),
+                    chrom: rec.seqname.clone(),
+                    start: rec.start,
+                    end: rec.end,
+                    strand: Strand::from_char(rec.strand),
+                    exons: Vec::new(),
+                });
+                transcripts.len() - 1
+            });
+            let transcript = &mut transcripts[idx];
+            transcript.start = transcript.start.min(rec.start);
+            transcript.end = transcript.end.max(rec.end);
+            transcript.exons.push((rec.start, rec.end));
+        }
+
+        for transcript in &mut transcripts {
+            transcript.exons.sort_unstable();
+        }
+        Ok(Self::from_transcripts(transcripts))
+    }
+
+    /// Build an index over already-constructed transcripts
+    pub fn from_transcripts(transcripts: Vec<Transcript>) -> Self {
+        let index =
+            IntervalIndex::new(transcripts.iter().map(|t| (t.chrom.as_str(), t.start, t.end)));
+        let by_id = transcripts.iter().enumerate().map(|(i, t)| (t.id.clone(), i)).collect();
+        Self {
+            transcripts,
+            index,
+            by_id,
+        }
+    }
+
+    /// All transcripts in GTF order
+    pub fn transcripts(&self) -> &[Transcript] {
+        &self.transcripts
+    }
+
+    /// Transcript by index
+    pub fn transcript(&self, idx: usize) -> &Transcript {
+        &self.transcripts[idx]
+    }
+
+    /// Transcript by ID
+    pub fn get(&self, id: &str) -> Option<&Transcript> {
+        self.by_id.get(id).map(|&i| &self.transcripts[i])
+    }
+
+    /// Number of transcripts
+    pub fn len(&self) -> usize {
+        self.transcripts.len()
+    }
+
+    /// Whether no transcripts were loaded
+    pub fn is_empty(&self) -> bool {
+        self.transcripts.is_empty()
+    }
+
+    /// Indices of the transcripts an alignment with these reference blocks
+    /// is most compatible with (empty when none is)
+    pub fn compatible(&self, chrom: &str, blocks: &[(i64, i64)]) -> Vec<usize> {
+        let (Some(&(start, _)), Some(&(_, end))) = (blocks.first(), blocks.last()) else {
+            return Vec::new();
+        };
+        let aligned: i64 = blocks.iter().map(|(s, e)| e - s).sum();
+        if aligned <= 0 {
+            return Vec::new();
+        }
+
+        let mut best = Vec::new();
+        let mut best_overlap = 0;
+        for i in self.index.overlapping(chrom, start, end) {
+            let transcript = &self.transcripts[i];
+            let overlap: i64 = blocks.iter().map(|&(s, e)| transcript.exonic_overlap(s, e)).sum();
+            if (overlap as f64 / aligned as f64) < EXONIC_FRACTION
+                || !blocks.windows(2).all(|w| transcript.has_intron(w[0].1, w[1].0))
+            {
+                continue;
+            }
+            if overlap > best_overlap {
+                best.clear();
+                best_overlap = overlap;
+            }
+            if overlap == best_overlap {
+                best.push(i);
+            }
+        }
+        best.sort_unstable();
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GTF: &str = "\
+chr1\tsrc\tgene\t101\t1000\t.\t+\t.\tgene_id \"G1\"; gene_name \"Alpha\";
+chr1\tsrc\texon\t101\t200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; gene_name \"Alpha\";
+chr1\tsrc\texon\t901\t1000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; gene_name \"Alpha\";
+chr1\tsrc\texon\t101\t200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\"; transcript_name \"A2\";
+chr1\tsrc\texon\t501\t600\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\"; transcript_name \"A2\";
+chr1\tsrc\texon\t901\t1000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\"; transcript_name \"A2\";
+";
+
+    #[test]
+    fn test_load_transcripts() {
+        let ann = TranscriptAnnotation::from_reader(GTF.as_bytes()).unwrap();
+        assert_eq!(ann.len(), 2);
+        let t1 = ann.get("T1").unwrap();
+        assert_eq!((t1.gene_id.as_str(), t1.gene_name.as_str()), ("G1", "Alpha"));
+        assert_eq!((t1.start, t1.end, t1.length()), (100, 1000, 200));
+        assert_eq!(ann.transcript(1).name, "A2");
+        assert_eq!(ann.transcript(1).gene_name, "G1");
+    }
+
+    #[test]
+    fn test_compatible_transcripts() {
+        let ann = TranscriptAnnotation::from_reader(GTF.as_bytes()).unwrap();
+        // Shared first exon: both isoforms
+        assert_eq!(ann.compatible("chr1", &[(110, 190)]), vec![0, 1]);
+        // Exon skipped by T1 only
+        assert_eq!(ann.compatible("chr1", &[(520, 580)]), vec![1]);
+        // Junction 200 -> 900 is an intron of T1 only
+        assert_eq!(ann.compatible("chr1", &[(150, 200), (900, 950)]), vec![0]);
+        assert_eq!(ann.compatible("chr1", &[(150, 200), (500, 550)]), vec![1]);
+        // Intronic or novel junction
+        assert!(ann.compatible("chr1", &[(300, 380)]).is_empty());
+        assert!(ann.compatible("chr1", &[(150, 190), (920, 980)]).is_empty());
+        assert!(ann.compatible("chr2", &[(110, 190)]).is_empty());
+    }
+}
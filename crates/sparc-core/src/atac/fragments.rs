@@ -0,0 +1,188 @@
+//! ATAC fragments: 10x fragment files and paired-end BAM extraction
+
+use crate::{Error, Result};
+#[cfg(feature = "native")]
+use ahash::AHashMap;
+use flate2::read::MultiGzDecoder;
+#[cfg(feature = "native")]
+use rust_htslib::bam::{self, record::Aux, Read};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Tn5 shift applied to the leftmost read start (10x convention)
+#[cfg(feature = "native")]
+const TN5_SHIFT_PLUS: i64 = 4;
+/// Tn5 shift applied to the rightmost read end
+#[cfg(feature = "native")]
+const TN5_SHIFT_MINUS: i64 = 5;
+
+/// One sequenced fragment (0-based, half-open), as in a 10x `fragments.tsv`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub chrom: String,
+    pub start: i64,
+    pub end: i64,
+    pub barcode: String,
+    /// Read pairs supporting the fragment (PCR duplicates)
+    pub count: u32,
+}
+
+/// Streaming reader for `chrom start end barcode [count]` fragment files
+pub struct FragmentReader {
+    lines: std::io::Lines<Box<dyn BufRead>>,
+    name: String,
+    line_num: usize,
+}
+
+impl FragmentReader {
+    /// Open a fragment file (plain or gzip/bgzip)
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        log::info!("Reading fragments from {:?}", path);
+        let file = File::open(path)?;
+        let reader: Box<dyn BufRead> = if path.extension().is_some_and(|ext| ext == "gz") {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+        Ok(Self::from_reader(reader, &path.display().to_string()))
+    }
+
+    /// Read fragments from text; `name` is used in error messages
+    pub fn from_reader<R: BufRead + 'static>(reader: R, name: &str) -> Self {
+        let reader: Box<dyn BufRead> = Box::new(reader);
+        Self {
+            lines: reader.lines(),
+            name: name.to_string(),
+            line_num: 0,
+        }
+    }
+
+    fn parse(&self, line: &str) -> Result<Fragment> {
+        let invalid = |what: &str| {
+            Error::BamParse(format!("{} line {}: invalid {}", self.name, self.line_num, what))
+        };
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 4 {
+            return Err(invalid("fragment (expected chrom, start, end, barcode)"));
+        }
+        let start: i64 = fields[1].parse().map_err(|_| invalid("start"))?;
+        let end: i64 = fields[2].parse().map_err(|_| invalid("end"))?;
+        if start < 0 || end <= start {
+            return Err(invalid("interval"));
+        }
+        let count = match fields.get(4) {
+            Some(count) => count.trim().parse().map_err(|_| invalid("count"))?,
+            None => 1,
+        };
+        Ok(Fragment {
+            chrom: fields[0].to_string(),
+            start,
+            end,
+            barcode: fields[3].to_string(),
+            count,
+        })
+    }
+}
+
+impl Iterator for FragmentReader {
+    type Item = Result<Fragment>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line_num += 1;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return Some(self.parse(&line));
+        }
+    }
+}
+
+/// Derive fragments from a paired-end BAM with CB tags.
+///
+/// Each properly paired, primary read pair with both mates at or above
+/// `min_mapq` gives one fragment from the leftmost mate's start to the
+/// rightmost mate's end, shifted +4/-5 bp for the Tn5 insertion. Identical
+/// fragments of one cell are collapsed and their duplicates counted. Output is
+/// sorted by chromosome (header order), start, end, and barcode.
+#[cfg(feature = "native")]
+pub fn fragments_from_bam<P: AsRef<Path>>(path: P, min_mapq: u8) -> Result<Vec<Fragment>> {
+    let mut reader = bam::Reader::from_path(path.as_ref())
+        .map_err(|e| Error::BamParse(format!("Failed to open BAM: {}", e)))?;
+    let chrom_names: Vec<String> = reader
+        .header()
+        .target_names()
+        .iter()
+        .map(|n| String::from_utf8_lossy(n).to_string())
+        .collect();
+
+    let mut fragments: AHashMap<(i32, i64, i64, String), u32> = AHashMap::new();
+    let mut record = bam::Record::new();
+    while let Some(result) = reader.read(&mut record) {
+        result.map_err(|e| Error::BamParse(e.to_string()))?;
+        if record.is_unmapped()
+            || record.is_mate_unmapped()
+            || record.is_secondary()
+            || record.is_supplementary()
+            || !record.is_proper_pair()
+            || record.mapq() < min_mapq
+            || record.tid() != record.mtid()
+        {
+            continue;
+        }
+        // The leftmost mate (positive template length) describes the fragment
+        let tlen = record.insert_size();
+        if tlen <= 0 {
+            continue;
+        }
+        let Ok(Aux::String(barcode)) = record.aux(b"CB") else {
+            continue;
+        };
+        let start = record.pos() + TN5_SHIFT_PLUS;
+        let end = record.pos() + tlen - TN5_SHIFT_MINUS;
+        if end <= start {
+            continue;
+        }
+        *fragments.entry((record.tid(), start, end, barcode.to_string())).or_default() += 1;
+    }
+
+    let mut fragments: Vec<_> = fragments.into_iter().collect();
+    fragments.sort_unstable();
+    Ok(fragments
+        .into_iter()
+        .map(|((tid, start, end, barcode), count)| Fragment {
+            chrom: chrom_names[tid as usize].clone(),
+            start,
+            end,
+            barcode,
+            count,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_fragments() {
+        let text = "# comment\nchr1\t100\t250\tAAAC-1\t2\nchr1\t300\t400\tGGGT-1\n";
+        let fragments: Vec<Fragment> = FragmentReader::from_reader(text.as_bytes(), "test")
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(fragments.len(), 2);
+        assert_eq!((fragments[0].start, fragments[0].end, fragments[0].count), (100, 250, 2));
+        assert_eq!(fragments[1].count, 1);
+
+        let bad = FragmentReader::from_reader("chr1\t300\t200\tAAAC\n".as_bytes(), "bad")
+            .next()
+            .unwrap();
+        assert!(bad.unwrap_err().to_string().contains("bad line 1"));
+    }
+}
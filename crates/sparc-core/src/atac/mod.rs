@@ -0,0 +1,13 @@
+//! scATAC-seq quantification
+//!
+//! Fragments come from a 10x `fragments.tsv[.gz]` or are derived from a
+//! paired-end BAM with CB tags; [`count_peaks`] turns them into a cell x peak
+//! [`CountMatrix`](crate::count::CountMatrix).
+
+mod fragments;
+mod peaks;
+
+#[cfg(feature = "native")]
+pub use fragments::fragments_from_bam;
+pub use fragments::{Fragment, FragmentReader};
+pub use peaks::{count_peaks, PeakCountMode, PeakCountStats, PeakSet};
@@ -0,0 +1,266 @@
+//! Peak sets and cell x peak counting
+
+use super::Fragment;
+use crate::atomic::AtomicFile;
+use crate::count::CountMatrix;
+use crate::intervals::{read_bed, write_bed, Interval, IntervalIndex};
+use crate::{Error, Result};
+use ahash::AHashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Peaks indexed for overlap queries
+#[derive(Debug, Clone, Default)]
+pub struct PeakSet {
+    peaks: Vec<Interval>,
+    index: IntervalIndex,
+}
+
+impl PeakSet {
+    /// Load peaks from a BED file (plain or gzipped)
+    pub fn from_bed<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let peaks = Self::from_peaks(read_bed(path)?);
+        log::info!("Loaded {} peaks from {:?}", peaks.len(), path);
+        Ok(peaks)
+    }
+
+    /// Index peaks, keeping their order as matrix rows
+    pub fn from_peaks(peaks: Vec<Interval>) -> Self {
+        let index = IntervalIndex::from_intervals(&peaks);
+        Self { peaks, index }
+    }
+
+    /// Number of peaks
+    pub fn len(&self) -> usize {
+        self.peaks.len()
+    }
+
+    /// Whether there are no peaks
+    pub fn is_empty(&self) -> bool {
+        self.peaks.is_empty()
+    }
+
+    /// All peaks in BED order
+    pub fn peaks(&self) -> &[Interval] {
+        &self.peaks
+    }
+
+    /// Append indices of peaks overlapping `[start, end)` on `chrom` to `out`
+    pub fn overlapping(&self, chrom: &str, start: i64, end: i64, out: &mut Vec<usize>) {
+        self.index.query(chrom, start, end, out);
+    }
+
+    /// Write a 10x-style `features.tsv` (`chrom:start-end` twice, then `Peaks`)
+    pub fn write_features<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = AtomicFile::create(path)?;
+        for peak in &self.peaks {
+            let name = peak.locus();
+            writeln!(writer, "{}\t{}\tPeaks", name, name)?;
+        }
+        writer.commit()
+    }
+
+    /// Write peaks as BED
+    pub fn write_bed<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_bed(path, &self.peaks)
+    }
+}
+
+/// What is counted per peak
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PeakCountMode {
+    /// Each fragment once for every peak it overlaps (Signac `FeatureMatrix`)
+    #[default]
+    Fragments,
+    /// Each Tn5 insertion (both fragment ends) in a peak (Cell Ranger ATAC)
+    CutSites,
+}
+
+impl FromStr for PeakCountMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fragments" => Ok(PeakCountMode::Fragments),
+            "cut-sites" => Ok(PeakCountMode::CutSites),
+            _ => Err(Error::Config(format!(
+                "unknown peak count mode '{}' (expected fragments or cut-sites)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for PeakCountMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PeakCountMode::Fragments => "fragments",
+            PeakCountMode::CutSites => "cut-sites",
+        })
+    }
+}
+
+/// Summary of a peak counting run
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct PeakCountStats {
+    /// Unique fragments read
+    pub total_fragments: u64,
+    /// Fragments from the counted cells
+    pub fragments_in_cells: u64,
+    /// Fragments from counted cells overlapping at least one peak
+    pub fragments_in_peaks: u64,
+}
+
+impl PeakCountStats {
+    /// Fraction of cell fragments overlapping a peak (FRiP)
+    pub fn frip(&self) -> f64 {
+        self.fragments_in_peaks as f64 / self.fragments_in_cells.max(1) as f64
+    }
+}
+
+/// Count fragments into a peak x cell matrix.
+///
+/// With `cells`, only those barcodes are counted and they become the matrix
+/// columns in the given order; otherwise every barcode is a column, sorted.
+/// Duplicate counts on fragments are ignored: each unique fragment counts once.
+pub fn count_peaks<I>(
+    fragments: I,
+    peaks: &PeakSet,
+    cells: Option<&[String]>,
+    mode: PeakCountMode,
+) -> Result<(CountMatrix, PeakCountStats)>
+where
+    I: IntoIterator<Item = Result<Fragment>>,
+{
+    let mut columns: AHashMap<String, usize> = cells
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (b.clone(), i))
+        .collect();
+    let mut stats = PeakCountStats::default();
+    let mut counts: AHashMap<(usize, usize), u32> = AHashMap::new();
+    let mut hits = Vec::new();
+
+    for fragment in fragments {
+        let fragment = fragment?;
+        stats.total_fragments += 1;
+        let col = match columns.get(&fragment.barcode) {
+            Some(&col) => col,
+            None if cells.is_none() => {
+                let col = columns.len();
+                columns.insert(fragment.barcode.clone(), col);
+                col
+            }
+            None => continue,
+        };
+        stats.fragments_in_cells += 1;
+
+        hits.clear();
+        match mode {
+            PeakCountMode::Fragments => {
+                peaks.overlapping(&fragment.chrom, fragment.start, fragment.end, &mut hits);
+            }
+            PeakCountMode::CutSites => {
+                for site in [fragment.start, fragment.end - 1] {
+                    peaks.overlapping(&fragment.chrom, site, site + 1, &mut hits);
+                }
+            }
+        }
+        if !hits.is_empty() {
+            stats.fragments_in_peaks += 1;
+        }
+        for &peak in &hits {
+            *counts.entry((col, peak)).or_default() += 1;
+        }
+    }
+
+    // Columns: the given cells in order, or every barcode sorted
+    let barcodes: Vec<String> = match cells {
+        Some(cells) => cells.to_vec(),
+        None => {
+            let mut barcodes: Vec<String> = columns.keys().cloned().collect();
+            barcodes.sort_unstable();
+            barcodes
+        }
+    };
+    let column: Vec<usize> = match cells {
+        Some(_) => (0..columns.len()).collect(),
+        None => {
+            let mut column = vec![0; columns.len()];
+            for (sorted, barcode) in barcodes.iter().enumerate() {
+                column[columns[barcode]] = sorted;
+            }
+            column
+        }
+    };
+    let mut entries: Vec<((usize, usize), u32)> =
+        counts.into_iter().map(|((col, peak), n)| ((column[col], peak), n)).collect();
+    entries.sort_unstable();
+
+    let mut matrix = CountMatrix {
+        genes: peaks.peaks().iter().map(Interval::locus).collect(),
+        n_rows: peaks.len(),
+        n_cols: barcodes.len(),
+        barcodes,
+        ..CountMatrix::new()
+    };
+    for ((col, peak), n) in entries {
+        matrix.rows.push(peak);
+        matrix.cols.push(col);
+        matrix.values.push(n);
+    }
+    Ok((matrix, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intervals::parse_bed;
+
+    const BED: &str =
+        "track name=peaks\nchr1\t100\t200\nchr1\t150\t300\nchr1\t1000\t1100\nchr2\t0\t50\n";
+
+    fn fragment(chrom: &str, start: i64, end: i64, barcode: &str) -> Result<Fragment> {
+        Ok(Fragment {
+            chrom: chrom.to_string(),
+            start,
+            end,
+            barcode: barcode.to_string(),
+            count: 1,
+        })
+    }
+
+    #[test]
+    fn test_count_fragments() {
+        let peaks = PeakSet::from_peaks(parse_bed(BED.as_bytes()).unwrap());
+        assert_eq!(peaks.len(), 4);
+        let fragments = || {
+            vec![
+                fragment("chr1", 120, 160, "BBB"), // peaks 0 and 1
+                fragment("chr1", 250, 400, "AAA"), // peak 1
+                fragment("chr1", 500, 600, "AAA"), // no peak
+                fragment("chr2", 10, 20, "CCC"),   // peak 3, not a listed cell below
+            ]
+        };
+        let (matrix, stats) =
+            count_peaks(fragments(), &peaks, None, PeakCountMode::Fragments).unwrap();
+        assert_eq!(matrix.barcodes, vec!["AAA", "BBB", "CCC"]);
+        assert_eq!(matrix.genes[1], "chr1:150-300");
+        assert_eq!((matrix.get(0, 1), matrix.get(1, 1), matrix.get(1, 0)), (1, 1, 1));
+        assert_eq!((stats.fragments_in_cells, stats.fragments_in_peaks), (4, 3));
+
+        let cells = vec!["BBB".to_string(), "AAA".to_string()];
+        let (matrix, stats) =
+            count_peaks(fragments(), &peaks, Some(&cells), PeakCountMode::CutSites).unwrap();
+        assert_eq!(matrix.barcodes, cells);
+        // BBB: cut sites 120 (peaks 0) and 159 (peaks 0 and 1)
+        assert_eq!((matrix.get(0, 0), matrix.get(1, 0)), (2, 1));
+        assert_eq!(matrix.get(1, 1), 1);
+        assert_eq!((stats.total_fragments, stats.fragments_in_cells), (4, 3));
+        assert!((stats.frip() - 2.0 / 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,189 @@
+//! Write-then-rename output files
+//!
+//! Outputs are written to a hidden temporary file next to their destination
+//! and renamed into place only once complete. Dropping a writer without
+//! committing it (an error, a panic, an interrupted job) removes the temporary,
+//! so a path holds either the finished file, its previous contents, or nothing.
+
+use crate::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Hidden temporary next to `path` that an atomic writer renames into place
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// A buffered file that appears at its path on [`AtomicFile::commit`]
+pub struct AtomicFile {
+    writer: Option<BufWriter<File>>,
+    tmp: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Start writing `path` through a temporary file in the same directory
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let tmp = temp_path(&path);
+        let file = File::create(&tmp)?;
+        Ok(Self {
+            writer: Some(BufWriter::new(file)),
+            tmp,
+            path,
+            committed: false,
+        })
+    }
+
+    /// Destination path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flush, sync, and rename the finished file into place
+    pub fn commit(mut self) -> Result<()> {
+        let writer = self.writer.take().expect("AtomicFile already committed");
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&self.tmp, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    fn writer(&mut self) -> &mut BufWriter<File> {
+        self.writer.as_mut().expect("AtomicFile already committed")
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.writer().write_all(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.writer.take();
+            let _ = std::fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// An [`AtomicFile`] holding plain, gzip-, or zstd-compressed text
+pub enum AtomicWriter {
+    Plain(AtomicFile),
+    Gzip(BufWriter<GzEncoder<AtomicFile>>),
+    #[cfg(feature = "native")]
+    Zstd(BufWriter<zstd::Encoder<'static, AtomicFile>>),
+}
+
+impl AtomicWriter {
+    /// Start writing `path`, compressing with gzip when `gzip` is set
+    pub fn create<P: AsRef<Path>>(path: P, gzip: bool) -> Result<Self> {
+        if gzip {
+            Self::gzip(path, Compression::default().level())
+        } else {
+            Ok(AtomicWriter::Plain(AtomicFile::create(path)?))
+        }
+    }
+
+    /// Start writing `path` gzip-compressed at `level` (0-9)
+    pub fn gzip<P: AsRef<Path>>(path: P, level: u32) -> Result<Self> {
+        let file = AtomicFile::create(path)?;
+        Ok(AtomicWriter::Gzip(BufWriter::new(GzEncoder::new(file, Compression::new(level)))))
+    }
+
+    /// Start writing `path` zstd-compressed at `level` (1-22)
+    #[cfg(feature = "native")]
+    pub fn zstd<P: AsRef<Path>>(path: P, level: i32) -> Result<Self> {
+        let file = AtomicFile::create(path)?;
+        Ok(AtomicWriter::Zstd(BufWriter::new(zstd::Encoder::new(file, level)?)))
+    }
+
+    /// Finish compression and rename the file into place
+    pub fn commit(self) -> Result<()> {
+        match self {
+            AtomicWriter::Plain(file) => file.commit(),
+            AtomicWriter::Gzip(writer) => {
+                let encoder = writer.into_inner().map_err(|e| e.into_error())?;
+                encoder.finish()?.commit()
+            }
+            #[cfg(feature = "native")]
+            AtomicWriter::Zstd(writer) => {
+                let encoder = writer.into_inner().map_err(|e| e.into_error())?;
+                encoder.finish()?.commit()
+            }
+        }
+    }
+}
+
+impl Write for AtomicWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            AtomicWriter::Plain(file) => file.write(buf),
+            AtomicWriter::Gzip(writer) => writer.write(buf),
+            #[cfg(feature = "native")]
+            AtomicWriter::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            AtomicWriter::Plain(file) => file.flush(),
+            AtomicWriter::Gzip(writer) => writer.flush(),
+            #[cfg(feature = "native")]
+            AtomicWriter::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Write `contents` to `path` atomically; the counterpart of [`std::fs::write`]
+pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(contents.as_ref())?;
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_or_clean_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.json");
+        write(&path, "old").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"partial").unwrap();
+        drop(file);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1, "temporary removed");
+
+        let mut file = AtomicWriter::create(dir.path().join("genes.tsv.gz"), true).unwrap();
+        writeln!(file, "G1").unwrap();
+        assert!(!dir.path().join("genes.tsv.gz").exists());
+        file.commit().unwrap();
+        let bytes = std::fs::read(dir.path().join("genes.tsv.gz")).unwrap();
+        let mut text = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&bytes[..]), &mut text)
+            .unwrap();
+        assert_eq!(text, "G1\n");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,158 @@
+//! Per-cell grouping of CB-sorted BAMs
+//!
+//! `samtools sort -t CB` puts each cell's records next to each other.
+//! [`CellGroups`] walks such a file and yields one cell at a time, so per-cell
+//! work (UMI deduplication, QC, genotyping) holds a single cell in memory
+//! instead of hashing every record by barcode.
+
+use super::{BamParser, BamRecord};
+use crate::{Error, Result};
+
+/// Groups consecutive records sharing a CB tag into `(barcode, records)`
+///
+/// Records without a CB tag (sorted first by samtools) are skipped and
+/// counted. A barcode that does not sort after the previous group's means the
+/// input is not CB-sorted and ends iteration with an error.
+pub struct CellGroups<I> {
+    records: I,
+    /// First record of the next group, read while finishing the previous one
+    pending: Option<BamRecord>,
+    /// Barcode of the last group yielded
+    previous: Option<String>,
+    untagged: u64,
+    failed: bool,
+}
+
+impl<I: Iterator<Item = Result<BamRecord>>> CellGroups<I> {
+    pub fn new(records: I) -> Self {
+        Self {
+            records,
+            pending: None,
+            previous: None,
+            untagged: 0,
+            failed: false,
+        }
+    }
+
+    /// Records skipped so far for lacking a CB tag
+    pub fn untagged(&self) -> u64 {
+        self.untagged
+    }
+
+    /// Next record with a CB tag
+    fn next_tagged(&mut self) -> Option<Result<BamRecord>> {
+        loop {
+            match self.records.next()? {
+                Ok(record) if record.cell_barcode.is_none() => self.untagged += 1,
+                other => return Some(other),
+            }
+        }
+    }
+
+    fn next_group(&mut self) -> Option<Result<(String, Vec<BamRecord>)>> {
+        let first = match self.pending.take() {
+            Some(record) => record,
+            None => match self.next_tagged()? {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e)),
+            },
+        };
+        let barcode = first.cell_barcode.clone().unwrap_or_default();
+        if let Some(previous) = &self.previous {
+            if barcode <= *previous {
+                return Some(Err(Error::BamParse(format!(
+                    "BAM is not sorted by cell barcode: {} follows {} (run `samtools sort -t CB`)",
+                    barcode, previous
+                ))));
+            }
+        }
+
+        let mut group = vec![first];
+        while let Some(result) = self.next_tagged() {
+            match result {
+                Ok(record) if record.cell_barcode.as_deref() == Some(barcode.as_str()) => {
+                    group.push(record)
+                }
+                Ok(record) => {
+                    self.pending = Some(record);
+                    break;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.previous = Some(barcode.clone());
+        Some(Ok((barcode, group)))
+    }
+}
+
+impl<I: Iterator<Item = Result<BamRecord>>> Iterator for CellGroups<I> {
+    type Item = Result<(String, Vec<BamRecord>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let group = self.next_group();
+        self.failed = matches!(group, Some(Err(_)));
+        group
+    }
+}
+
+impl BamParser {
+    /// Iterate a CB-sorted BAM one cell at a time
+    pub fn by_cell(self) -> CellGroups<Self> {
+        CellGroups::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str, barcode: Option<&str>) -> Result<BamRecord> {
+        let mut record = BamRecord::new(name.to_string(), b"ACGT".to_vec(), vec![30; 4]);
+        record.cell_barcode = barcode.map(str::to_string);
+        Ok(record)
+    }
+
+    fn names(records: &[BamRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_cell_groups() {
+        let records = vec![
+            record("u1", None),
+            record("a1", Some("AAAA")),
+            record("a2", Some("AAAA")),
+            record("c1", Some("CCCC")),
+            record("u2", None),
+            record("c2", Some("CCCC")),
+            record("g1", Some("GGGG")),
+        ];
+        let mut groups = CellGroups::new(records.into_iter());
+
+        let (barcode, cell) = groups.next().unwrap().unwrap();
+        assert_eq!((barcode.as_str(), names(&cell)), ("AAAA", vec!["a1", "a2"]));
+        let (barcode, cell) = groups.next().unwrap().unwrap();
+        assert_eq!((barcode.as_str(), names(&cell)), ("CCCC", vec!["c1", "c2"]));
+        let (barcode, cell) = groups.next().unwrap().unwrap();
+        assert_eq!((barcode.as_str(), names(&cell)), ("GGGG", vec!["g1"]));
+        assert!(groups.next().is_none());
+        assert_eq!(groups.untagged(), 2);
+    }
+
+    #[test]
+    fn test_cell_groups_unsorted() {
+        let records = vec![
+            record("c1", Some("CCCC")),
+            record("a1", Some("AAAA")),
+            record("c2", Some("CCCC")),
+        ];
+        let mut groups = CellGroups::new(records.into_iter());
+        assert!(groups.next().unwrap().is_ok());
+        let err = groups.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("not sorted"), "{}", err);
+        assert!(groups.next().is_none());
+    }
+}
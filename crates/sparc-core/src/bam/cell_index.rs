@@ -0,0 +1,256 @@
+//! Cell barcode index for random access by cell
+//!
+//! A `.cbi` sidecar maps each CB tag to the BGZF virtual offsets of its
+//! records, stored as runs of consecutive records. On a CB-sorted BAM every
+//! cell is one run, so [`BamParser::fetch_cell`] seeks once and reads only
+//! that cell; on other sort orders a cell spans many runs and the index grows
+//! with the number of records.
+
+use super::BamParser;
+use crate::atomic::AtomicFile;
+use crate::{Error, Result};
+use ahash::AHashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// File magic and format version of a saved cell index
+const MAGIC: &[u8; 8] = b"SPCBIDX1";
+
+/// Consecutive records of one cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRun {
+    /// BGZF virtual offset of the first record
+    pub offset: u64,
+    /// Records in the run
+    pub records: u32,
+}
+
+/// Cell barcode -> runs of records in one BAM
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CellIndex {
+    /// Size of the indexed BAM, to detect a stale index
+    bam_size: u64,
+    cells: AHashMap<String, Vec<CellRun>>,
+    /// Records without a CB tag
+    untagged: u64,
+}
+
+impl CellIndex {
+    /// Sidecar path of `bam`: `<bam>.cbi`
+    pub fn sidecar_path(bam: &Path) -> PathBuf {
+        let mut path = bam.as_os_str().to_owned();
+        path.push(".cbi");
+        PathBuf::from(path)
+    }
+
+    /// Scan every record of the BAM at `path`
+    pub fn build<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut parser = BamParser::open(path)?;
+        let mut index = Self {
+            bam_size: std::fs::metadata(path)?.len(),
+            ..Self::default()
+        };
+        let mut run: Option<(String, CellRun)> = None;
+        parser.scan_barcodes(|offset, barcode| {
+            match (&mut run, barcode) {
+                (Some((current, cell_run)), Some(barcode))
+                    if current.as_str() == barcode && cell_run.records < u32::MAX =>
+                {
+                    cell_run.records += 1;
+                    return;
+                }
+                _ => {}
+            }
+            if let Some((current, cell_run)) = run.take() {
+                index.cells.entry(current).or_default().push(cell_run);
+            }
+            match barcode {
+                Some(barcode) => {
+                    run = Some((barcode.to_string(), CellRun { offset, records: 1 }));
+                }
+                None => index.untagged += 1,
+            }
+        })?;
+        if let Some((current, cell_run)) = run {
+            index.cells.entry(current).or_default().push(cell_run);
+        }
+        Ok(index)
+    }
+
+    /// Number of cell barcodes
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Total runs over all cells (equal to [`len`](Self::len) for a CB-sorted BAM)
+    pub fn n_runs(&self) -> usize {
+        self.cells.values().map(Vec::len).sum()
+    }
+
+    /// Records with a CB tag
+    pub fn n_records(&self) -> u64 {
+        self.cells.values().flatten().map(|r| r.records as u64).sum()
+    }
+
+    /// Records without a CB tag
+    pub fn untagged(&self) -> u64 {
+        self.untagged
+    }
+
+    /// Runs of `barcode`, if it has any records
+    pub fn runs(&self, barcode: &str) -> Option<&[CellRun]> {
+        self.cells.get(barcode).map(Vec::as_slice)
+    }
+
+    /// Write the index (cells in barcode order) after a magic header
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = AtomicFile::create(path.as_ref())?;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.bam_size.to_le_bytes())?;
+        writer.write_all(&self.untagged.to_le_bytes())?;
+        writer.write_all(&(self.cells.len() as u64).to_le_bytes())?;
+        let mut barcodes: Vec<&String> = self.cells.keys().collect();
+        barcodes.sort_unstable();
+        for barcode in barcodes {
+            let runs = &self.cells[barcode];
+            writer.write_all(&(barcode.len() as u32).to_le_bytes())?;
+            writer.write_all(barcode.as_bytes())?;
+            writer.write_all(&(runs.len() as u32).to_le_bytes())?;
+            for run in runs {
+                writer.write_all(&run.offset.to_le_bytes())?;
+                writer.write_all(&run.records.to_le_bytes())?;
+            }
+        }
+        writer.commit()
+    }
+
+    /// Read an index written by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bad = |msg: &str| Error::BamParse(format!("{}: {}", path.display(), msg));
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 32];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| bad("too short for a cell index"))?;
+        if &header[..8] != MAGIC {
+            return Err(bad("not a SPARC cell index"));
+        }
+        let word = |range: std::ops::Range<usize>| {
+            u64::from_le_bytes(header[range].try_into().expect("8 bytes"))
+        };
+        let (bam_size, untagged, n_cells) = (word(8..16), word(16..24), word(24..32));
+
+        let u32_at = |reader: &mut BufReader<File>| -> Result<u32> {
+            let mut bytes = [0u8; 4];
+            reader.read_exact(&mut bytes).map_err(|_| bad("truncated cell index"))?;
+            Ok(u32::from_le_bytes(bytes))
+        };
+        let mut cells = AHashMap::new();
+        for _ in 0..n_cells {
+            let len = u32_at(&mut reader)? as usize;
+            let mut barcode = vec![0u8; len];
+            reader.read_exact(&mut barcode).map_err(|_| bad("truncated cell index"))?;
+            let barcode = String::from_utf8(barcode).map_err(|_| bad("corrupt barcode"))?;
+            let n_runs = u32_at(&mut reader)? as usize;
+            let mut runs = Vec::with_capacity(n_runs.min(1 << 16));
+            for _ in 0..n_runs {
+                let mut run = [0u8; 12];
+                reader.read_exact(&mut run).map_err(|_| bad("truncated cell index"))?;
+                runs.push(CellRun {
+                    offset: u64::from_le_bytes(run[..8].try_into().expect("8 bytes")),
+                    records: u32::from_le_bytes(run[8..].try_into().expect("4 bytes")),
+                });
+            }
+            cells.insert(barcode, runs);
+        }
+        Ok(Self {
+            bam_size,
+            cells,
+            untagged,
+        })
+    }
+
+    /// Load the sidecar of the BAM at `bam`, failing if the BAM changed size
+    /// since it was indexed
+    pub fn load_for<P: AsRef<Path>>(bam: P) -> Result<Self> {
+        let bam = bam.as_ref();
+        let path = Self::sidecar_path(bam);
+        if !path.exists() {
+            return Err(Error::BamParse(format!(
+                "No cell index {:?} (run `sparc index-cb -i {}`)",
+                path,
+                bam.display()
+            )));
+        }
+        let index = Self::load(&path)?;
+        if index.bam_size != std::fs::metadata(bam)?.len() {
+            return Err(Error::BamParse(format!(
+                "Cell index {:?} is stale; rerun `sparc index-cb -i {}`",
+                path,
+                bam.display()
+            )));
+        }
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bam::BamRecord;
+    use rust_htslib::bam::{self, record::Aux};
+    use tempfile::tempdir;
+
+    fn write_bam(path: &Path, barcodes: &[Option<&str>]) {
+        let mut header = bam::Header::new();
+        let mut sq = bam::header::HeaderRecord::new(b"SQ");
+        sq.push_tag(b"SN", "chr1");
+        sq.push_tag(b"LN", 1000);
+        header.push_record(&sq);
+        let mut writer = bam::Writer::from_path(path, &header, bam::Format::Bam).unwrap();
+        for (i, barcode) in barcodes.iter().enumerate() {
+            let mut record = bam::Record::new();
+            record.set(format!("r{}", i).as_bytes(), None, b"ACGT", &[30; 4]);
+            record.set_tid(0);
+            record.set_pos(i as i64);
+            if let Some(barcode) = barcode {
+                record.push_aux(b"CB", Aux::String(barcode)).unwrap();
+            }
+            writer.write(&record).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_cell_index() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cells.bam");
+        let barcodes = [None, Some("AAAA"), Some("AAAA"), Some("CCCC"), Some("AAAA")];
+        write_bam(&path, &barcodes);
+
+        let index = CellIndex::build(&path).unwrap();
+        assert_eq!((index.len(), index.n_runs(), index.n_records()), (2, 3, 4));
+        assert_eq!(index.untagged(), 1);
+        assert_eq!(index.runs("AAAA").unwrap()[0].records, 2);
+
+        let mut parser = BamParser::open(&path).unwrap();
+        assert!(parser.fetch_cell("AAAA").is_err(), "no sidecar yet");
+        index.save(CellIndex::sidecar_path(&path)).unwrap();
+        assert_eq!(CellIndex::load(CellIndex::sidecar_path(&path)).unwrap(), index);
+
+        let names = |records: Vec<BamRecord>| -> Vec<String> {
+            records.into_iter().map(|r| r.name).collect()
+        };
+        assert_eq!(names(parser.fetch_cell("AAAA").unwrap()), vec!["r1", "r2", "r4"]);
+        assert_eq!(names(parser.fetch_cell("CCCC").unwrap()), vec!["r3"]);
+        assert!(parser.fetch_cell("GGGG").unwrap().is_empty());
+        // Fetching leaves sequential reading where it was
+        assert_eq!(parser.next().unwrap().unwrap().name, "r0");
+    }
+}
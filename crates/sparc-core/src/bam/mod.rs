@@ -1,10 +1,20 @@
 //! BAM parsing and writing module
 
+mod by_cell;
+mod cell_index;
 mod parser;
+mod unaligned;
 mod writer;
 
+pub use by_cell::CellGroups;
+pub use cell_index::{CellIndex, CellRun};
 pub use parser::BamParser;
-pub use writer::BamWriter;
+pub use unaligned::UnalignedPairReader;
+pub use writer::{unaligned_record, AtomicBamWriter, BamWriter};
+
+/// htslib's record type, as built by [`unaligned_record`] and written by
+/// [`BamWriter::write`]
+pub use rust_htslib::bam::Record as RawBamRecord;
 
 /// A BAM record with extracted single-cell tags
 #[derive(Debug, Clone)]
@@ -25,16 +35,24 @@ pub struct BamRecord {
     pub cigar: String,
     /// Cell barcode (CB tag)
     pub cell_barcode: Option<String>,
+    /// Uncorrected cell barcode (CR tag)
+    pub raw_barcode: Option<String>,
     /// UMI (UB tag)
     pub umi: Option<String>,
+    /// Uncorrected UMI (UR tag)
+    pub raw_umi: Option<String>,
     /// Gene name (GN tag)
     pub gene_name: Option<String>,
     /// Gene ID (GX tag)
     pub gene_id: Option<String>,
+    /// Region type (RE tag: E exonic, N intronic, I intergenic)
+    pub region: Option<u8>,
     /// Is mapped
     pub is_mapped: bool,
     /// Is reverse strand
     pub is_reverse: bool,
+    /// Number of reported alignments of the read (NH tag)
+    pub hits: Option<u32>,
 }
 
 impl BamRecord {
@@ -48,11 +66,15 @@ impl BamRecord {
             pos: -1,
             cigar: String::new(),
             cell_barcode: None,
+            raw_barcode: None,
             umi: None,
+            raw_umi: None,
             gene_name: None,
             gene_id: None,
+            region: None,
             is_mapped: false,
             is_reverse: false,
+            hits: None,
         }
     }
 
@@ -65,4 +87,67 @@ impl BamRecord {
     pub fn is_assigned(&self) -> bool {
         self.gene_name.is_some() || self.gene_id.is_some()
     }
+
+    /// Whether the read has a single alignment; without an NH tag, any mapped
+    /// read counts as unique
+    pub fn is_unique(&self) -> bool {
+        self.is_mapped && self.hits.map_or(true, |nh| nh <= 1)
+    }
+
+    /// Reference span of the alignment, from the CIGAR (M, D, N, =, X)
+    pub fn reference_len(&self) -> i64 {
+        let mut len = 0;
+        let mut n = 0;
+        for c in self.cigar.bytes() {
+            if c.is_ascii_digit() {
+                n = n * 10 + (c - b'0') as i64;
+                continue;
+            }
+            if matches!(c, b'M' | b'D' | b'N' | b'=' | b'X') {
+                len += n;
+            }
+            n = 0;
+        }
+        len
+    }
+
+    /// Reference blocks covered by the alignment, from the CIGAR; `N` skips
+    /// (introns) split blocks
+    pub fn aligned_blocks(&self) -> Vec<(i64, i64)> {
+        let mut blocks = Vec::new();
+        let (mut start, mut end) = (self.pos, self.pos);
+        let mut n = 0;
+        for c in self.cigar.bytes() {
+            if c.is_ascii_digit() {
+                n = n * 10 + (c - b'0') as i64;
+                continue;
+            }
+            match c {
+                b'M' | b'D' | b'=' | b'X' => end += n,
+                b'N' => {
+                    if end > start {
+                        blocks.push((start, end));
+                    }
+                    end += n;
+                    start = end;
+                }
+                _ => {}
+            }
+            n = 0;
+        }
+        if end > start {
+            blocks.push((start, end));
+        }
+        blocks
+    }
+
+    /// 0-based position of the read's 5' end: the alignment start on the
+    /// forward strand, its last base on the reverse strand
+    pub fn five_prime(&self) -> i64 {
+        if self.is_reverse {
+            self.pos + self.reference_len().max(1) - 1
+        } else {
+            self.pos
+        }
+    }
 }
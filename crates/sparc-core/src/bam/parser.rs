@@ -1,23 +1,93 @@
 //! BAM file parser using rust-htslib
 
-use super::BamRecord;
-use crate::{Error, Result};
+use super::{BamRecord, CellIndex, CellRun};
+use crate::{remote, resources, Error, RecordContext, Result};
 use rust_htslib::bam::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// BAM file parser
 pub struct BamParser {
     reader: bam::Reader,
     header: bam::Header,
+    path: PathBuf,
+    /// Records read so far by sequential iteration
+    record_num: u64,
+    /// `<bam>.cbi` sidecar, loaded by the first `fetch_cell`
+    cell_index: Option<CellIndex>,
 }
 
 impl BamParser {
-    /// Open a BAM file
+    /// Open a BAM file, decompressing on the `--decompress-threads` htslib
+    /// threads when set.
+    ///
+    /// `http(s)://` and `s3://` URIs are read through htslib when built with the `remote` feature.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let reader = bam::Reader::from_path(path.as_ref())
-            .map_err(|e| Error::BamParse(format!("Failed to open BAM: {}", e)))?;
+        let mut reader = if remote::is_remote(path.as_ref()) {
+            Self::open_remote(&path.as_ref().to_string_lossy())?
+        } else {
+            bam::Reader::from_path(path.as_ref())
+                .map_err(|e| Error::BamParse(format!("Failed to open BAM: {}", e)))?
+        };
+        let threads = resources::global().threads.decompress_or(0);
+        if threads > 0 {
+            reader
+                .set_threads(threads)
+                .map_err(|e| Error::BamParse(format!("Failed to set BAM reader threads: {}", e)))?;
+        }
         let header = bam::Header::from_template(reader.header());
-        Ok(Self { reader, header })
+        Ok(Self {
+            reader,
+            header,
+            path: path.as_ref().to_path_buf(),
+            record_num: 0,
+            cell_index: None,
+        })
+    }
+
+    #[cfg(feature = "remote")]
+    fn open_remote(uri: &str) -> Result<bam::Reader> {
+        let url = url::Url::parse(uri)
+            .map_err(|e| Error::BamParse(format!("Invalid BAM URL {}: {}", uri, e)))?;
+        log::info!("Opening remote BAM: {}", url);
+        bam::Reader::from_url(&url)
+            .map_err(|e| Error::BamParse(format!("Failed to open remote BAM {}: {}", uri, e)))
+    }
+
+    #[cfg(not(feature = "remote"))]
+    fn open_remote(uri: &str) -> Result<bam::Reader> {
+        Err(Error::BamParse(format!(
+            "Remote input {} requires SPARC to be built with the `remote` feature",
+            uri
+        )))
+    }
+
+    #[cfg(feature = "remote")]
+    fn open_indexed_remote(uri: &str) -> Result<bam::IndexedReader> {
+        let url = url::Url::parse(uri)
+            .map_err(|e| Error::BamParse(format!("Invalid BAM URL {}: {}", uri, e)))?;
+        bam::IndexedReader::from_url(&url)
+            .map_err(|e| Error::BamParse(format!("Failed to open BAM index for {}: {}", uri, e)))
+    }
+
+    #[cfg(not(feature = "remote"))]
+    fn open_indexed_remote(uri: &str) -> Result<bam::IndexedReader> {
+        Err(Error::BamParse(format!(
+            "Remote input {} requires SPARC to be built with the `remote` feature",
+            uri
+        )))
+    }
+
+    /// Open a second, index-backed reader on the same file for region queries
+    fn open_indexed(&self) -> Result<bam::IndexedReader> {
+        if remote::is_remote(&self.path) {
+            return Self::open_indexed_remote(&self.path.to_string_lossy());
+        }
+        bam::IndexedReader::from_path(&self.path).map_err(|e| {
+            Error::BamParse(format!(
+                "Failed to open BAM index for {:?} (run `samtools index`?): {}",
+                self.path, e
+            ))
+        })
     }
 
     /// Get the header
@@ -35,6 +105,98 @@ impl BamParser {
             .collect()
     }
 
+    /// Get reference lengths, in header order
+    pub fn reference_lengths(&self) -> Vec<u64> {
+        let header = self.reader.header();
+        (0..header.target_count())
+            .map(|tid| header.target_len(tid).unwrap_or(0))
+            .collect()
+    }
+
+    /// Records overlapping `chrom:start-end` (0-based, half-open), read through
+    /// the BAM index. Independent of the sequential iteration position.
+    pub fn fetch(&self, chrom: &str, start: i64, end: i64) -> Result<Vec<BamRecord>> {
+        if self.reader.header().tid(chrom.as_bytes()).is_none() {
+            return Err(Error::BamParse(format!("Unknown reference '{}'", chrom)));
+        }
+        let mut reader = self.open_indexed()?;
+        reader.fetch((chrom, start, end)).map_err(|e| {
+            Error::BamParse(format!("Failed to fetch {}:{}-{}: {}", chrom, start, end, e))
+        })?;
+
+        let mut records = Vec::new();
+        let mut record = bam::Record::new();
+        while let Some(result) = reader.read(&mut record) {
+            result.map_err(|e| Error::BamParse(e.to_string()))?;
+            records.push(self.convert_record(&record));
+        }
+        Ok(records)
+    }
+
+    /// All records of cell `barcode`, read through the `<bam>.cbi` sidecar
+    /// written by `sparc index-cb`. Independent of the sequential iteration
+    /// position; a barcode not in the index has no records.
+    pub fn fetch_cell(&mut self, barcode: &str) -> Result<Vec<BamRecord>> {
+        if remote::is_remote(&self.path) {
+            return Err(Error::BamParse(format!(
+                "fetch_cell needs a local BAM, not {}",
+                self.path.display()
+            )));
+        }
+        if self.cell_index.is_none() {
+            self.cell_index = Some(CellIndex::load_for(&self.path)?);
+        }
+        let runs: Vec<CellRun> = match self.cell_index.as_ref().and_then(|i| i.runs(barcode)) {
+            Some(runs) => runs.to_vec(),
+            None => return Ok(Vec::new()),
+        };
+
+        let mut reader = bam::Reader::from_path(&self.path)
+            .map_err(|e| Error::BamParse(format!("Failed to open BAM: {}", e)))?;
+        let mut records = Vec::new();
+        let mut record = bam::Record::new();
+        for run in runs {
+            reader.seek(run.offset as i64).map_err(|e| {
+                Error::BamParse(format!("Failed to seek to cell {}: {}", barcode, e))
+            })?;
+            for _ in 0..run.records {
+                match reader.read(&mut record) {
+                    Some(result) => result.map_err(|e| Error::BamParse(e.to_string()))?,
+                    None => break,
+                }
+                let converted = self.convert_record(&record);
+                if converted.cell_barcode.as_deref() != Some(barcode) {
+                    return Err(Error::BamParse(format!(
+                        "Cell index {:?} does not match the BAM; rerun `sparc index-cb`",
+                        CellIndex::sidecar_path(&self.path)
+                    )));
+                }
+                records.push(converted);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Walk the remaining records, calling `f` with each one's BGZF virtual
+    /// offset and CB tag without converting the record
+    pub(crate) fn scan_barcodes<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(u64, Option<&str>),
+    {
+        let mut record = bam::Record::new();
+        loop {
+            let offset = self.reader.tell() as u64;
+            match self.read_next(&mut record) {
+                Some(result) => result?,
+                None => return Ok(()),
+            }
+            match record.aux(b"CB") {
+                Ok(rust_htslib::bam::record::Aux::String(barcode)) => f(offset, Some(barcode)),
+                _ => f(offset, None),
+            }
+        }
+    }
+
     /// Convert rust-htslib record to our BamRecord
     fn convert_record(&self, record: &bam::Record) -> BamRecord {
         let name = String::from_utf8_lossy(record.qname()).to_string();
@@ -67,6 +229,12 @@ impl BamParser {
                 bam_record.umi = Some(s.to_string());
             }
         }
+        if let Ok(rust_htslib::bam::record::Aux::String(s)) = record.aux(b"CR") {
+            bam_record.raw_barcode = Some(s.to_string());
+        }
+        if let Ok(rust_htslib::bam::record::Aux::String(s)) = record.aux(b"UR") {
+            bam_record.raw_umi = Some(s.to_string());
+        }
         if let Ok(aux) = record.aux(b"GN") {
             if let rust_htslib::bam::record::Aux::String(s) = aux {
                 bam_record.gene_name = Some(s.to_string());
@@ -77,17 +245,45 @@ impl BamParser {
                 bam_record.gene_id = Some(s.to_string());
             }
         }
+        if let Ok(rust_htslib::bam::record::Aux::Char(c)) = record.aux(b"RE") {
+            bam_record.region = Some(c);
+        }
+        bam_record.hits = record.aux(b"NH").ok().and_then(|aux| {
+            use rust_htslib::bam::record::Aux;
+            match aux {
+                Aux::U8(n) => Some(n as u32),
+                Aux::U16(n) => Some(n as u32),
+                Aux::U32(n) => Some(n),
+                Aux::I8(n) => u32::try_from(n).ok(),
+                Aux::I16(n) => u32::try_from(n).ok(),
+                Aux::I32(n) => u32::try_from(n).ok(),
+                _ => None,
+            }
+        });
 
         bam_record
     }
 
+    /// Read the next record into `record`; a failure names the record number
+    /// and the offset of its BGZF block
+    fn read_next(&mut self, record: &mut bam::Record) -> Option<Result<()>> {
+        let block = (self.reader.tell() as u64) >> 16;
+        let result = self.reader.read(record)?;
+        self.record_num += 1;
+        Some(result.map_err(|e| {
+            RecordContext::new(self.path.display().to_string(), self.record_num)
+                .with_byte_offset(block)
+                .bam_error(e.to_string())
+        }))
+    }
+
     /// Read all records
     pub fn read_all(&mut self) -> Result<Vec<BamRecord>> {
         let mut records = Vec::new();
         let mut record = bam::Record::new();
 
-        while let Some(result) = self.reader.read(&mut record) {
-            result.map_err(|e| Error::BamParse(e.to_string()))?;
+        while let Some(result) = self.read_next(&mut record) {
+            result?;
             records.push(self.convert_record(&record));
         }
 
@@ -99,8 +295,8 @@ impl BamParser {
         let mut records = Vec::new();
         let mut record = bam::Record::new();
 
-        while let Some(result) = self.reader.read(&mut record) {
-            result.map_err(|e| Error::BamParse(e.to_string()))?;
+        while let Some(result) = self.read_next(&mut record) {
+            result?;
             if record.mapq() >= min_mapq {
                 records.push(self.convert_record(&record));
             }
@@ -115,10 +311,62 @@ impl Iterator for BamParser {
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut record = bam::Record::new();
-        match self.reader.read(&mut record) {
-            Some(Ok(())) => Some(Ok(self.convert_record(&record))),
-            Some(Err(e)) => Some(Err(Error::BamParse(e.to_string()))),
-            None => None,
+        match self.read_next(&mut record)? {
+            Ok(()) => Some(Ok(self.convert_record(&record))),
+            Err(e) => Some(Err(e)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_htslib::bam::header::HeaderRecord;
+    use rust_htslib::bam::record::{Cigar, CigarString};
+    use tempfile::tempdir;
+
+    fn write_sorted_bam(path: &Path) {
+        let mut header = bam::Header::new();
+        let mut hd = HeaderRecord::new(b"HD");
+        hd.push_tag(b"VN", "1.6");
+        hd.push_tag(b"SO", "coordinate");
+        header.push_record(&hd);
+        for (name, len) in [("chr1", 1000), ("chr2", 500)] {
+            let mut sq = HeaderRecord::new(b"SQ");
+            sq.push_tag(b"SN", name);
+            sq.push_tag(b"LN", len);
+            header.push_record(&sq);
+        }
+
+        let mut writer = bam::Writer::from_path(path, &header, bam::Format::Bam).unwrap();
+        for (i, (tid, pos)) in [(0, 10), (0, 400), (1, 50)].into_iter().enumerate() {
+            let mut record = bam::Record::new();
+            let cigar = CigarString(vec![Cigar::Match(4)]);
+            record.set(format!("r{}", i).as_bytes(), Some(&cigar), b"ACGT", &[30; 4]);
+            record.set_tid(tid);
+            record.set_pos(pos);
+            writer.write(&record).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fetch_region() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("sorted.bam");
+        write_sorted_bam(&path);
+
+        let parser = BamParser::open(&path).unwrap();
+        assert_eq!(parser.reference_names(), vec!["chr1", "chr2"]);
+        assert_eq!(parser.reference_lengths(), vec![1000, 500]);
+        assert!(parser.fetch("chr1", 0, 100).is_err(), "no index yet");
+
+        bam::index::build(&path, None, bam::index::Type::Bai, 1).unwrap();
+        let names = |records: Vec<BamRecord>| -> Vec<String> {
+            records.into_iter().map(|r| r.name).collect()
+        };
+        assert_eq!(names(parser.fetch("chr1", 0, 100).unwrap()), vec!["r0"]);
+        assert_eq!(names(parser.fetch("chr1", 0, 1000).unwrap()), vec!["r0", "r1"]);
+        assert_eq!(names(parser.fetch("chr2", 0, 500).unwrap()), vec!["r2"]);
+        assert!(parser.fetch("chrX", 0, 10).is_err());
+    }
+}
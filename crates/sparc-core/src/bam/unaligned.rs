@@ -0,0 +1,195 @@
+//! Read pairs from unaligned BAM or CRAM
+//!
+//! Core facilities and SRA often deliver raw reads as unaligned BAM: both mates
+//! of a pair as adjacent records flagged first/second in pair. Decoding runs on
+//! a background thread, so the reader can feed [`crate::ChunkPipeline`].
+
+use crate::fastq::FastqRecord;
+use crate::{resources, Error, RecordContext, Result};
+use rust_htslib::bam::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+/// Pairs decoded per message from the reader thread
+const BATCH_SIZE: usize = 1024;
+
+type PairBatch = Result<Vec<(FastqRecord, FastqRecord)>>;
+
+/// Iterator over (R1, R2) pairs of an unaligned BAM or CRAM
+pub struct UnalignedPairReader {
+    batches: Receiver<PairBatch>,
+    pending: std::vec::IntoIter<(FastqRecord, FastqRecord)>,
+}
+
+impl UnalignedPairReader {
+    /// Open `path` and start decoding pairs. Secondary and supplementary
+    /// records are skipped; every other record must be paired, with its mate
+    /// next to it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        // Fail here on a missing or unreadable file rather than on first read
+        bam::Reader::from_path(&path)
+            .map_err(|e| Error::BamParse(format!("Failed to open {:?}: {}", path, e)))?;
+
+        let (tx, batches) = sync_channel(4);
+        std::thread::spawn(move || {
+            if let Err(e) = read_pairs(&path, &tx) {
+                let _ = tx.send(Err(e));
+            }
+        });
+        Ok(Self {
+            batches,
+            pending: Vec::new().into_iter(),
+        })
+    }
+}
+
+impl Iterator for UnalignedPairReader {
+    type Item = Result<(FastqRecord, FastqRecord)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pair) = self.pending.next() {
+                return Some(Ok(pair));
+            }
+            match self.batches.recv() {
+                Ok(Ok(batch)) => self.pending = batch.into_iter(),
+                Ok(Err(e)) => return Some(Err(e)),
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// Decode `path` into batches of pairs until the file ends, the receiver goes
+/// away, or a record breaks pairing
+fn read_pairs(path: &Path, tx: &SyncSender<PairBatch>) -> Result<()> {
+    let mut reader = bam::Reader::from_path(path)
+        .map_err(|e| Error::BamParse(format!("Failed to open {:?}: {}", path, e)))?;
+    let _ = reader.set_threads(resources::global().threads.decompress_or(2));
+
+    let mut record = bam::Record::new();
+    let mut r1: Option<FastqRecord> = None;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut num = 0u64;
+    let name = path.display().to_string();
+    loop {
+        let block = (reader.tell() as u64) >> 16;
+        let Some(result) = reader.read(&mut record) else {
+            break;
+        };
+        num += 1;
+        let context = RecordContext::new(name.as_str(), num).with_byte_offset(block);
+        if let Err(e) = result {
+            return Err(context.bam_error(e.to_string()));
+        }
+        if record.is_secondary() || record.is_supplementary() {
+            continue;
+        }
+        let read = to_fastq(&record);
+        let context = context.with_read_name(read.id.as_str());
+        if !record.is_paired() {
+            return Err(context.bam_error("record is not paired"));
+        }
+        match r1.take() {
+            None if record.is_first_in_template() => r1 = Some(read),
+            Some(mate) if record.is_last_in_template() && mate.id == read.id => {
+                batch.push((mate, read));
+                if batch.len() == BATCH_SIZE {
+                    let full = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+                    if tx.send(Ok(full)).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            _ => {
+                return Err(context
+                    .bam_error("record is out of order; mates must be adjacent, R1 first"));
+            }
+        }
+    }
+    if let Some(mate) = r1 {
+        let context = RecordContext::new(name, num).with_read_name(mate.id);
+        return Err(context.bam_error("R1 has no R2"));
+    }
+    if !batch.is_empty() {
+        let _ = tx.send(Ok(batch));
+    }
+    Ok(())
+}
+
+/// The read as sequenced: reverse-strand records are flipped back
+fn to_fastq(record: &bam::Record) -> FastqRecord {
+    let id = String::from_utf8_lossy(record.qname()).to_string();
+    let mut seq = record.seq().as_bytes();
+    let mut qual: Vec<u8> = record.qual().iter().map(|&q| q.saturating_add(33)).collect();
+    if record.is_reverse() {
+        seq.reverse();
+        for base in seq.iter_mut() {
+            *base = match *base {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                b'T' => b'A',
+                other => other,
+            };
+        }
+        qual.reverse();
+    }
+    FastqRecord::new(id, seq, qual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bam::{unaligned_record, BamWriter};
+
+    fn write_ubam(path: &Path, reads: &[(&str, u16, &[u8])]) {
+        let mut writer = BamWriter::new(path, &BamWriter::create_default_header()).unwrap();
+        for &(name, flags, seq) in reads {
+            let read = FastqRecord::new(name.to_string(), seq.to_vec(), vec![b'I'; seq.len()]);
+            let mut record = unaligned_record(&read, &[]).unwrap();
+            record.set_flags(flags);
+            writer.write(&record).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pairs_from_ubam() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.bam");
+        write_ubam(
+            &path,
+            &[
+                ("a", 0x4d, b"AACC"),
+                ("a", 0x8d, b"GGTT"),
+                ("b", 0x4d, b"ACGT"),
+                ("b", 0x9d, b"AAAC"),
+            ],
+        );
+
+        let pairs: Vec<_> = UnalignedPairReader::open(&path)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].0.seq, b"AACC".to_vec());
+        assert_eq!(pairs[0].1.seq, b"GGTT".to_vec());
+        assert_eq!(pairs[1].1.seq, b"GTTT".to_vec(), "reverse-strand mate flipped back");
+        assert_eq!(pairs[1].1.qual, b"IIII".to_vec());
+    }
+
+    #[test]
+    fn test_unpaired_ubam_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.bam");
+        write_ubam(&path, &[("a", 0x4d, b"AACC"), ("b", 0x8d, b"GGTT")]);
+
+        let err = UnalignedPairReader::open(&path)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("record 2"), "{}", err);
+    }
+}
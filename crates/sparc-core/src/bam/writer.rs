@@ -1,8 +1,17 @@
 //! BAM file writer using rust-htslib
 
+use crate::fastq::FastqRecord;
 use crate::{Error, Result};
-use rust_htslib::bam::{self, header::HeaderRecord, Header, Writer as BamWriterInner};
-use std::path::Path;
+use rust_htslib::bam::{self, header::HeaderRecord, record::Aux, Header, Writer as BamWriterInner};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The empty BGZF block htslib writes when it closes a BAM cleanly
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02,
+    0x00, 0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
 
 /// BAM file writer
 pub struct BamWriter {
@@ -35,6 +44,13 @@ impl BamWriter {
         header
     }
 
+    /// Compress with `threads` extra htslib threads
+    pub fn set_threads(&mut self, threads: usize) -> Result<()> {
+        self.writer
+            .set_threads(threads)
+            .map_err(|e| Error::BamParse(format!("Failed to set BAM writer threads: {}", e)))
+    }
+
     /// Write a record
     pub fn write(&mut self, record: &bam::Record) -> Result<()> {
         self.writer
@@ -42,3 +58,145 @@ impl BamWriter {
             .map_err(|e| Error::BamParse(format!("Failed to write record: {}", e)))
     }
 }
+
+/// A [`BamWriter`] whose file appears at its path only on
+/// [`AtomicBamWriter::commit`]; dropping it uncommitted removes the temporary.
+///
+/// htslib reports nothing when it closes a BAM, so `commit` checks that the
+/// closed file ends in the BGZF EOF block before renaming it into place.
+pub struct AtomicBamWriter {
+    writer: Option<BamWriter>,
+    tmp: PathBuf,
+    path: PathBuf,
+    committed: bool,
+}
+
+impl AtomicBamWriter {
+    /// Start writing `path` through a temporary file in the same directory
+    pub fn new<P: AsRef<Path>>(path: P, header: &Header) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let tmp = crate::atomic::temp_path(&path);
+        Ok(Self {
+            writer: Some(BamWriter::new(&tmp, header)?),
+            tmp,
+            path,
+            committed: false,
+        })
+    }
+
+    /// Destination path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Compress with `threads` extra htslib threads
+    pub fn set_threads(&mut self, threads: usize) -> Result<()> {
+        self.writer().set_threads(threads)
+    }
+
+    /// Write a record
+    pub fn write(&mut self, record: &bam::Record) -> Result<()> {
+        self.writer().write(record)
+    }
+
+    /// Close the BAM, check it is complete, and rename it into place
+    pub fn commit(mut self) -> Result<()> {
+        drop(self.writer.take());
+        let mut file = File::open(&self.tmp)?;
+        let mut tail = [0u8; 28];
+        let complete = file.seek(SeekFrom::End(-(tail.len() as i64))).is_ok()
+            && file.read_exact(&mut tail).is_ok()
+            && tail == BGZF_EOF;
+        if !complete {
+            return Err(Error::BamParse(format!(
+                "{:?}: BAM was not closed cleanly (missing BGZF EOF block)",
+                self.path
+            )));
+        }
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&self.tmp, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    fn writer(&mut self) -> &mut BamWriter {
+        self.writer.as_mut().expect("AtomicBamWriter already committed")
+    }
+}
+
+impl Drop for AtomicBamWriter {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.writer.take();
+            let _ = std::fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// An unmapped record for `read`, named without its FASTQ comment and carrying
+/// `tags` as `Z` strings. Qualities are converted from Phred+33.
+pub fn unaligned_record(read: &FastqRecord, tags: &[(&[u8; 2], &str)]) -> Result<bam::Record> {
+    let qual: Vec<u8> = read.qual.iter().map(|q| q.saturating_sub(33)).collect();
+    let mut record = bam::Record::new();
+    record.set(read.name().as_bytes(), None, &read.seq, &qual);
+    record.set_tid(-1);
+    record.set_pos(-1);
+    record.set_mtid(-1);
+    record.set_mpos(-1);
+    record.set_unmapped();
+    for (tag, value) in tags {
+        record
+            .push_aux(&tag[..], Aux::String(value))
+            .map_err(|e| Error::BamParse(format!("Failed to set tag: {}", e)))?;
+    }
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bam::BamParser;
+
+    #[test]
+    fn test_unaligned_record_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.bam");
+        let read = FastqRecord::new("r1 1:N:0".to_string(), b"ACGT".to_vec(), b"I#5I".to_vec());
+        let tags = [(b"CR", "AAAT"), (b"CB", "AAAA-1"), (b"UB", "GGCC")];
+        {
+            let mut writer = BamWriter::new(&path, &BamWriter::create_default_header()).unwrap();
+            writer.write(&unaligned_record(&read, &tags).unwrap()).unwrap();
+        }
+
+        let records = BamParser::open(&path).unwrap().read_all().unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.name, "r1");
+        assert!(!record.is_mapped);
+        assert_eq!(record.qual, vec![40, 2, 20, 40]);
+        assert_eq!(record.cell_barcode.as_deref(), Some("AAAA-1"));
+        assert_eq!(record.raw_barcode.as_deref(), Some("AAAT"));
+        assert_eq!(record.umi.as_deref(), Some("GGCC"));
+    }
+
+    #[test]
+    fn test_atomic_bam_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.bam");
+        let read = FastqRecord::new("r1".to_string(), b"ACGT".to_vec(), b"IIII".to_vec());
+        let header = BamWriter::create_default_header();
+
+        let mut writer = AtomicBamWriter::new(&path, &header).unwrap();
+        writer.write(&unaligned_record(&read, &[]).unwrap()).unwrap();
+        drop(writer);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let mut writer = AtomicBamWriter::new(&path, &header).unwrap();
+        writer.write(&unaligned_record(&read, &[]).unwrap()).unwrap();
+        assert!(!path.exists());
+        writer.commit().unwrap();
+        assert_eq!(BamParser::open(&path).unwrap().read_all().unwrap().len(), 1);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
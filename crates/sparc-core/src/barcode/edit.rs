@@ -0,0 +1,209 @@
+//! One-edit barcode correction for indel-prone reads
+//!
+//! Long-read barcodes carry insertions and deletions about as often as
+//! substitutions, and an indel shifts every later base past Hamming
+//! correction. Two sequences are within one edit only if they are equal, one
+//! is a single-base deletion of the other, or they share one (symmetric
+//! deletion, as in SymSpell), so each whitelist barcode's deletion variants
+//! are stored sorted and candidates are confirmed by edit distance.
+
+use super::Whitelist;
+use crate::seq_util::{edit_distance_within, pack_2bit, unpack_2bit};
+use crate::{Error, Result};
+use rayon::prelude::*;
+
+/// A whitelist barcode found at the start of a read window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditMatch {
+    pub barcode: String,
+    /// Bases of the window the barcode spans (barcode length, or one more or
+    /// fewer after an indel)
+    pub len: usize,
+    /// Edits between those bases and the barcode (0 or 1)
+    pub distance: u32,
+}
+
+/// Whitelist barcodes and their single-deletion variants, packed and sorted
+#[derive(Debug, Clone)]
+pub struct EditCorrector {
+    barcode_len: usize,
+    barcodes: Vec<u64>,
+    /// (packed deletion variant, position in `barcodes` of its source)
+    variants: Vec<(u64, u32)>,
+}
+
+impl EditCorrector {
+    /// Index a whitelist of A/C/G/T barcodes of at most 32 bases
+    pub fn new(whitelist: &Whitelist) -> Result<Self> {
+        let barcode_len = whitelist.barcode_len();
+        let mut barcodes = whitelist
+            .iter()
+            .map(|b| pack_2bit(b.as_bytes()))
+            .collect::<Option<Vec<u64>>>()
+            .filter(|_| barcode_len > 0)
+            .ok_or_else(|| {
+                Error::Barcode(
+                    "Edit-distance correction needs A/C/G/T barcodes of 1 to 32 bases".into(),
+                )
+            })?;
+        barcodes.sort_unstable();
+
+        let mut variants: Vec<(u64, u32)> = barcodes
+            .par_iter()
+            .enumerate()
+            .flat_map_iter(|(id, &barcode)| {
+                let barcode = unpack_2bit(barcode, barcode_len).into_bytes();
+                deletions(&barcode)
+                    .map(move |variant| (variant, id as u32))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        variants.par_sort_unstable();
+        variants.dedup();
+
+        Ok(Self {
+            barcode_len,
+            barcodes,
+            variants,
+        })
+    }
+
+    pub fn barcode_len(&self) -> usize {
+        self.barcode_len
+    }
+
+    /// Whitelist barcode within one edit of the start of `window`.
+    ///
+    /// `window` should hold at least barcode length + 1 bases; prefixes one
+    /// base shorter, equal to, and one base longer than a barcode are tried,
+    /// so the match also says where the next field (the UMI) starts. `None`
+    /// if no barcode or more than one is that close.
+    pub fn correct(&self, window: &[u8]) -> Option<EditMatch> {
+        let len = self.barcode_len;
+        if let Some(id) = window
+            .get(..len)
+            .and_then(pack_2bit)
+            .and_then(|packed| self.barcodes.binary_search(&packed).ok())
+        {
+            return Some(self.matched(id as u32, len, 0));
+        }
+
+        let mut found: Option<(u32, usize)> = None;
+        for query_len in [len, len - 1, len + 1] {
+            let Some(query) = window.get(..query_len) else {
+                continue;
+            };
+            for id in self.candidates(query) {
+                let barcode = unpack_2bit(self.barcodes[id as usize], len);
+                if edit_distance_within(query, barcode.as_bytes(), 1).is_none() {
+                    continue;
+                }
+                match found {
+                    None => found = Some((id, query_len)),
+                    Some((other, _)) if other != id => return None,
+                    Some(_) => {}
+                }
+            }
+        }
+        found.map(|(id, query_len)| self.matched(id, query_len, 1))
+    }
+
+    /// Barcodes that may be one edit from `query`, to be confirmed
+    fn candidates(&self, query: &[u8]) -> Vec<u32> {
+        let mut ids = Vec::new();
+        match query.len().cmp(&self.barcode_len) {
+            // Substitution: query and barcode share the deletion at its position
+            std::cmp::Ordering::Equal => {
+                for variant in deletions(query) {
+                    ids.extend(self.variant_sources(variant));
+                }
+            }
+            // Deletion from the barcode: the query is one of its variants
+            std::cmp::Ordering::Less => {
+                if let Some(packed) = pack_2bit(query) {
+                    ids.extend(self.variant_sources(packed));
+                }
+            }
+            // Insertion into the barcode: deleting it from the query gives the barcode
+            std::cmp::Ordering::Greater => {
+                for variant in deletions(query) {
+                    if let Ok(id) = self.barcodes.binary_search(&variant) {
+                        ids.push(id as u32);
+                    }
+                }
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    fn variant_sources(&self, variant: u64) -> impl Iterator<Item = u32> + '_ {
+        let start = self.variants.partition_point(|&(v, _)| v < variant);
+        self.variants[start..]
+            .iter()
+            .take_while(move |&&(v, _)| v == variant)
+            .map(|&(_, id)| id)
+    }
+
+    fn matched(&self, id: u32, len: usize, distance: u32) -> EditMatch {
+        EditMatch {
+            barcode: unpack_2bit(self.barcodes[id as usize], self.barcode_len),
+            len,
+            distance,
+        }
+    }
+}
+
+/// Packed sequences left by deleting each base of `seq` (at most 33 bases);
+/// deletions that leave a non-A/C/G/T base are skipped
+fn deletions(seq: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    (0..seq.len()).filter_map(move |pos| {
+        let mut buf = [0u8; 33];
+        let len = seq.len() - 1;
+        buf[..pos].copy_from_slice(&seq[..pos]);
+        buf[pos..len].copy_from_slice(&seq[pos + 1..]);
+        pack_2bit(&buf[..len])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corrector() -> EditCorrector {
+        let whitelist = Whitelist::from_vec(vec![
+            "AAACCCGGGTTT".to_string(),
+            "ACGTACGTACGT".to_string(),
+            "TTTTGGGGCCCC".to_string(),
+        ])
+        .unwrap();
+        EditCorrector::new(&whitelist).unwrap()
+    }
+
+    #[test]
+    fn test_edit_correction() {
+        let corrector = corrector();
+        let found = |window: &[u8]| corrector.correct(window).map(|m| (m.barcode, m.len));
+
+        assert_eq!(found(b"ACGTACGTACGTNNN"), Some(("ACGTACGTACGT".into(), 12)));
+        // Substitution
+        assert_eq!(found(b"ACGTACCTACGTNNN"), Some(("ACGTACGTACGT".into(), 12)));
+        // Deletion: the barcode spans 11 bases and the UMI starts one early
+        assert_eq!(found(b"AAACCGGGTTTNNN"), Some(("AAACCCGGGTTT".into(), 11)));
+        // Insertion
+        assert_eq!(found(b"TTTTGGAGGCCCCNN"), Some(("TTTTGGGGCCCC".into(), 13)));
+        assert_eq!(corrector.correct(b"AAACCCGGGTTTNN").unwrap().distance, 0);
+        assert_eq!(found(b"GGGGGGGGGGGGGG"), None);
+        assert_eq!(found(b"ACGT"), None);
+    }
+
+    #[test]
+    fn test_edit_correction_ambiguous() {
+        let whitelist =
+            Whitelist::from_vec(vec!["AAAAAAAA".to_string(), "AAAAAAAC".to_string()]).unwrap();
+        let corrector = EditCorrector::new(&whitelist).unwrap();
+        assert_eq!(corrector.correct(b"AAAAAAAGTT"), None);
+        assert!(EditCorrector::new(&Whitelist::from_vec(vec!["ACGN".into()]).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! Packed one-mismatch index for barcode correction
+//!
+//! Every whitelist barcode is packed two bits per base; each of its
+//! single-substitution (A/C/G/T) variants is stored sorted, pointing back at
+//! the barcode it came from, or marked ambiguous when several barcodes share
+//! it. The arrays serialize as-is, so a saved index loads without rebuilding.
+
+use crate::atomic::AtomicFile;
+use crate::seq_util::{pack_2bit, unpack_2bit};
+use crate::{Error, Result};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+/// File magic and format version of a saved whitelist index
+const MAGIC: &[u8; 8] = b"SPWLIDX1";
+
+/// Target of a variant shared by more than one barcode
+const AMBIGUOUS: u32 = u32::MAX;
+
+/// Whitelist barcodes within one substitution of a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Neighbour {
+    None,
+    One(String),
+    Ambiguous,
+}
+
+/// Sorted packed barcodes and their one-mismatch variants
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MismatchIndex {
+    barcode_len: usize,
+    /// Packed whitelist barcodes, sorted
+    barcodes: Vec<u64>,
+    /// Packed variants one substitution from a barcode, sorted
+    variants: Vec<u64>,
+    /// Position in `barcodes` of each variant's source, or `AMBIGUOUS`
+    targets: Vec<u32>,
+}
+
+impl MismatchIndex {
+    /// Index equal-length barcodes; `None` if any is longer than 32 bases or
+    /// has a base other than A/C/G/T
+    pub(crate) fn build<'a, I>(barcodes: I, barcode_len: usize) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let mut packed = barcodes
+            .into_iter()
+            .map(|b| pack_2bit(b.as_bytes()))
+            .collect::<Option<Vec<u64>>>()?;
+        packed.sort_unstable();
+
+        let mut pairs: Vec<(u64, u32)> = Vec::with_capacity(packed.len() * barcode_len * 3);
+        for (id, &barcode) in packed.iter().enumerate() {
+            for pos in 0..barcode_len {
+                let shift = 2 * (barcode_len - 1 - pos);
+                let base = (barcode >> shift) & 3;
+                for other in (0..4u64).filter(|&b| b != base) {
+                    pairs.push(((barcode & !(3 << shift)) | (other << shift), id as u32));
+                }
+            }
+        }
+        pairs.par_sort_unstable();
+
+        let mut variants = Vec::with_capacity(pairs.len());
+        let mut targets: Vec<u32> = Vec::with_capacity(pairs.len());
+        for (variant, id) in pairs {
+            if variants.last() == Some(&variant) {
+                *targets.last_mut().expect("targets parallel to variants") = AMBIGUOUS;
+            } else {
+                variants.push(variant);
+                targets.push(id);
+            }
+        }
+
+        Some(Self {
+            barcode_len,
+            barcodes: packed,
+            variants,
+            targets,
+        })
+    }
+
+    /// Whitelist barcodes, unpacked
+    pub(crate) fn barcodes(&self) -> impl Iterator<Item = String> + '_ {
+        self.barcodes.iter().map(|&b| unpack_2bit(b, self.barcode_len))
+    }
+
+    pub(crate) fn barcode_len(&self) -> usize {
+        self.barcode_len
+    }
+
+    pub(crate) fn n_variants(&self) -> usize {
+        self.variants.len()
+    }
+
+    /// Whitelist barcode one substitution from `query`. A single `N` in the
+    /// query matches any base at that position.
+    pub(crate) fn neighbour(&self, query: &[u8]) -> Neighbour {
+        if query.len() != self.barcode_len {
+            return Neighbour::None;
+        }
+        let mut n_positions = query.iter().enumerate().filter(|(_, &b)| b == b'N');
+        match (n_positions.next(), n_positions.next()) {
+            (None, _) => self.variant_target(query),
+            (Some((pos, _)), None) => self.fill_n(query, pos),
+            _ => Neighbour::None,
+        }
+    }
+
+    fn variant_target(&self, query: &[u8]) -> Neighbour {
+        let Some(packed) = pack_2bit(query) else {
+            return Neighbour::None;
+        };
+        match self.variants.binary_search(&packed) {
+            Ok(i) if self.targets[i] == AMBIGUOUS => Neighbour::Ambiguous,
+            Ok(i) => Neighbour::One(self.unpack(self.targets[i])),
+            Err(_) => Neighbour::None,
+        }
+    }
+
+    fn fill_n(&self, query: &[u8], pos: usize) -> Neighbour {
+        let mut filled = query.to_vec();
+        let mut hits = b"ACGT".iter().filter_map(|&base| {
+            filled[pos] = base;
+            let packed = pack_2bit(&filled)?;
+            self.barcodes.binary_search(&packed).ok()
+        });
+        match (hits.next(), hits.next()) {
+            (Some(id), None) => Neighbour::One(self.unpack(id as u32)),
+            (Some(_), Some(_)) => Neighbour::Ambiguous,
+            (None, _) => Neighbour::None,
+        }
+    }
+
+    fn unpack(&self, id: u32) -> String {
+        unpack_2bit(self.barcodes[id as usize], self.barcode_len)
+    }
+
+    /// Write the index as little-endian arrays after a magic header
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = AtomicFile::create(path)?;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(self.barcode_len as u32).to_le_bytes())?;
+        writer.write_all(&(self.barcodes.len() as u64).to_le_bytes())?;
+        writer.write_all(&(self.variants.len() as u64).to_le_bytes())?;
+        for &barcode in &self.barcodes {
+            writer.write_all(&barcode.to_le_bytes())?;
+        }
+        for &variant in &self.variants {
+            writer.write_all(&variant.to_le_bytes())?;
+        }
+        for &target in &self.targets {
+            writer.write_all(&target.to_le_bytes())?;
+        }
+        writer.commit()
+    }
+
+    /// Read an index written by `save`
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let bad = |msg: &str| Error::Barcode(format!("{}: {}", path.display(), msg));
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 28];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| bad("too short for a whitelist index"))?;
+        if &header[..8] != MAGIC {
+            return Err(bad("not a SPARC whitelist index"));
+        }
+        let barcode_len = u32::from_le_bytes(header[8..12].try_into().expect("4 bytes")) as usize;
+        let n_barcodes = u64::from_le_bytes(header[12..20].try_into().expect("8 bytes")) as usize;
+        let n_variants = u64::from_le_bytes(header[20..28].try_into().expect("8 bytes")) as usize;
+        if barcode_len > 32 || n_variants > n_barcodes.saturating_mul(barcode_len * 3) {
+            return Err(bad("corrupt header"));
+        }
+
+        let mut read_array = |n: usize, width: usize| -> Result<Vec<u8>> {
+            let mut bytes = vec![0u8; n * width];
+            reader
+                .read_exact(&mut bytes)
+                .map_err(|_| bad("truncated whitelist index"))?;
+            Ok(bytes)
+        };
+        let words = |bytes: Vec<u8>| -> Vec<u64> {
+            bytes
+                .chunks_exact(8)
+                .map(|c| u64::from_le_bytes(c.try_into().expect("8 bytes")))
+                .collect()
+        };
+        let barcodes = words(read_array(n_barcodes, 8)?);
+        let variants = words(read_array(n_variants, 8)?);
+        let targets: Vec<u32> = read_array(n_variants, 4)?
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().expect("4 bytes")))
+            .collect();
+        if targets.iter().any(|&t| t != AMBIGUOUS && t as usize >= barcodes.len()) {
+            return Err(bad("corrupt variant targets"));
+        }
+
+        Ok(Self {
+            barcode_len,
+            barcodes,
+            variants,
+            targets,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(barcodes: &[&str]) -> MismatchIndex {
+        let barcodes: Vec<String> = barcodes.iter().map(|b| b.to_string()).collect();
+        MismatchIndex::build(&barcodes, barcodes[0].len()).unwrap()
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let index = index(&["AAAA", "CCCC", "AAAT"]);
+        assert_eq!(index.neighbour(b"CCGC"), Neighbour::One("CCCC".to_string()));
+        // AAAG is one substitution from both AAAA and AAAT
+        assert_eq!(index.neighbour(b"AAAG"), Neighbour::Ambiguous);
+        assert_eq!(index.neighbour(b"GGGG"), Neighbour::None);
+        assert_eq!(index.neighbour(b"CCCCC"), Neighbour::None);
+        assert_eq!(index.neighbour(b"CNCC"), Neighbour::One("CCCC".to_string()));
+        assert_eq!(index.neighbour(b"AAAN"), Neighbour::Ambiguous);
+        assert_eq!(index.neighbour(b"NNCC"), Neighbour::None);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wl.idx");
+        let index = index(&["ACGTACGT", "TTTTGGGG", "ACGTACGA"]);
+        index.save(&path).unwrap();
+        assert_eq!(MismatchIndex::load(&path).unwrap(), index);
+
+        std::fs::write(&path, b"ACGT\n").unwrap();
+        assert!(MismatchIndex::load(&path).is_err());
+        assert!(MismatchIndex::build(&["ACGN".to_string()], 4).is_none());
+    }
+}
@@ -1,7 +1,18 @@
 //! Barcode matching and correction
 
+use super::index::{MismatchIndex, Neighbour};
 use super::{BarcodeMatch, Whitelist};
-use ahash::AHashMap;
+use crate::seq_util::hamming_within;
+use std::sync::Arc;
+
+/// Share of the candidates' total likelihood the best candidate needs before
+/// a quality-weighted correction is made
+pub const DEFAULT_MIN_POSTERIOR: f64 = 0.975;
+
+/// Probability that a base with Phred+33 quality `qual` was miscalled
+fn error_probability(qual: u8) -> f64 {
+    10f64.powf(-(qual.saturating_sub(33) as f64) / 10.0)
+}
 
 /// Barcode matcher with exact matching
 pub struct BarcodeMatcher {
@@ -33,27 +44,34 @@ pub struct BarcodeCorrector {
     whitelist: Whitelist,
     /// Maximum Hamming distance for correction
     max_distance: u32,
-    /// Pre-computed index for 1-mismatch lookup
-    mismatch_index: AHashMap<String, Vec<String>>,
+    /// Pre-computed index for 1-mismatch lookup (`None` for unpackable whitelists)
+    mismatch_index: Option<Arc<MismatchIndex>>,
 }
 
 impl BarcodeCorrector {
-    /// Create a new barcode corrector
-    pub fn new(whitelist: Whitelist, max_distance: u32) -> Self {
+    /// Create a new barcode corrector, reusing the whitelist's index if it
+    /// was loaded with `Whitelist::load_index`
+    pub fn new(mut whitelist: Whitelist, max_distance: u32) -> Self {
         log::info!(
             "Building barcode corrector (whitelist={} barcodes, max_distance={})",
             whitelist.len(),
             max_distance
         );
         let mismatch_index = if max_distance >= 1 {
-            Self::build_mismatch_index(&whitelist)
+            whitelist.mismatch_index()
         } else {
-            AHashMap::new()
+            None
         };
-        log::debug!(
-            "Mismatch index built ({} entries)",
-            mismatch_index.len()
-        );
+        match &mismatch_index {
+            Some(index) => {
+                log::debug!("Mismatch index ready ({} variants)", index.n_variants());
+                whitelist.attach_index(Arc::clone(index));
+            }
+            None if max_distance >= 1 => {
+                log::debug!("Whitelist not 2-bit packable; correcting by full scan");
+            }
+            None => {}
+        }
 
         Self {
             whitelist,
@@ -62,37 +80,9 @@ impl BarcodeCorrector {
         }
     }
 
-    /// Build index for 1-mismatch lookup
-    fn build_mismatch_index(whitelist: &Whitelist) -> AHashMap<String, Vec<String>> {
-        let mut index: AHashMap<String, Vec<String>> = AHashMap::new();
-
-        for barcode in whitelist.iter() {
-            // Generate all 1-mismatch variants
-            let chars: Vec<char> = barcode.chars().collect();
-            for i in 0..chars.len() {
-                for &base in &['A', 'C', 'G', 'T', 'N'] {
-                    if chars[i] != base {
-                        let mut variant = chars.clone();
-                        variant[i] = base;
-                        let variant_str: String = variant.into_iter().collect();
-                        index
-                            .entry(variant_str)
-                            .or_default()
-                            .push(barcode.clone());
-                    }
-                }
-            }
-        }
-
-        index
-    }
-
-    /// Calculate Hamming distance between two sequences
-    fn hamming_distance(a: &str, b: &str) -> u32 {
-        if a.len() != b.len() {
-            return u32::MAX;
-        }
-        a.chars().zip(b.chars()).filter(|(a, b)| a != b).count() as u32
+    /// Get the whitelist, with the corrector's mismatch index attached
+    pub fn whitelist(&self) -> &Whitelist {
+        &self.whitelist
     }
 
     /// Match a barcode with correction
@@ -107,22 +97,26 @@ impl BarcodeCorrector {
         }
 
         // Try 1-mismatch lookup using index
-        if let Some(candidates) = self.mismatch_index.get(barcode) {
-            if candidates.len() == 1 {
-                return BarcodeMatch::Corrected(barcode.to_string(), candidates[0].clone(), 1);
+        if let Some(index) = &self.mismatch_index {
+            match index.neighbour(barcode.as_bytes()) {
+                Neighbour::One(corrected) => {
+                    return BarcodeMatch::Corrected(barcode.to_string(), corrected, 1);
+                }
+                // Multiple candidates - ambiguous, no correction
+                Neighbour::Ambiguous => return BarcodeMatch::NoMatch(barcode.to_string()),
+                Neighbour::None => {}
             }
-            // Multiple candidates - ambiguous, no correction
-            return BarcodeMatch::NoMatch(barcode.to_string());
         }
 
         // For higher distances, do brute force search
-        if self.max_distance > 1 {
+        if self.max_distance > 1 || self.mismatch_index.is_none() {
             let mut best_match: Option<(String, u32)> = None;
             let mut ambiguous = false;
 
+            let query = barcode.as_bytes();
             for wl_barcode in self.whitelist.iter() {
-                let dist = Self::hamming_distance(barcode, wl_barcode);
-                if dist <= self.max_distance {
+                let dist = hamming_within(query, wl_barcode.as_bytes(), self.max_distance);
+                if let Some(dist) = dist {
                     match &best_match {
                         None => best_match = Some((wl_barcode.clone(), dist)),
                         Some((_, best_dist)) => {
@@ -146,6 +140,79 @@ impl BarcodeCorrector {
 
         BarcodeMatch::NoMatch(barcode.to_string())
     }
+
+    /// Match a barcode, weighing equally distant whitelist candidates by the
+    /// probability that their mismatched bases are sequencing errors given
+    /// the Phred+33 `qual`. The most likely candidate wins when it holds at
+    /// least [`DEFAULT_MIN_POSTERIOR`] of the total likelihood, so a
+    /// mismatch at a low-quality cycle beats one at a high-quality cycle.
+    pub fn match_barcode_with_quality(&self, barcode: &str, qual: &[u8]) -> BarcodeMatch {
+        if qual.len() != barcode.len() {
+            return self.match_barcode(barcode);
+        }
+        if self.whitelist.contains(barcode) {
+            return BarcodeMatch::Exact(barcode.to_string());
+        }
+        if self.max_distance == 0 {
+            return BarcodeMatch::NoMatch(barcode.to_string());
+        }
+
+        let query = barcode.as_bytes();
+        let error: Vec<f64> = qual.iter().map(|&q| error_probability(q)).collect();
+        // (candidate, distance, likelihood)
+        let mut candidates: Vec<(String, u32, f64)> = Vec::new();
+
+        // Every single substitution that lands in the whitelist
+        let mut variant = query.to_vec();
+        for pos in 0..query.len() {
+            for &base in b"ACGT" {
+                if base == query[pos] {
+                    continue;
+                }
+                variant[pos] = base;
+                if let Ok(candidate) = std::str::from_utf8(&variant) {
+                    if self.whitelist.contains(candidate) {
+                        candidates.push((candidate.to_string(), 1, error[pos]));
+                    }
+                }
+            }
+            variant[pos] = query[pos];
+        }
+
+        // Otherwise the nearest barcodes by full scan
+        if candidates.is_empty() && (self.max_distance > 1 || self.mismatch_index.is_none()) {
+            let mut best_dist = u32::MAX;
+            for wl_barcode in self.whitelist.iter() {
+                let Some(dist) = hamming_within(query, wl_barcode.as_bytes(), self.max_distance)
+                else {
+                    continue;
+                };
+                if dist > best_dist {
+                    continue;
+                }
+                if dist < best_dist {
+                    candidates.clear();
+                    best_dist = dist;
+                }
+                let likelihood = query
+                    .iter()
+                    .zip(wl_barcode.as_bytes())
+                    .zip(&error)
+                    .filter(|((a, b), _)| a != b)
+                    .map(|(_, p)| p)
+                    .product();
+                candidates.push((wl_barcode.clone(), dist, likelihood));
+            }
+        }
+
+        let total: f64 = candidates.iter().map(|c| c.2).sum();
+        match candidates.into_iter().max_by(|a, b| a.2.total_cmp(&b.2)) {
+            Some((corrected, dist, likelihood)) if likelihood >= DEFAULT_MIN_POSTERIOR * total => {
+                BarcodeMatch::Corrected(barcode.to_string(), corrected, dist)
+            }
+            _ => BarcodeMatch::NoMatch(barcode.to_string()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -189,4 +256,67 @@ mod tests {
         let result = corrector.match_barcode("TTACCCAAGAAACACT");
         assert!(matches!(result, BarcodeMatch::NoMatch(_)));
     }
+
+    #[test]
+    fn test_loaded_index_corrects_like_built() {
+        let barcodes = vec!["AAAACCCC".to_string(), "AAAACCCG".to_string(), "GGGGTTTT".to_string()];
+        let built = BarcodeCorrector::new(Whitelist::from_vec(barcodes).unwrap(), 1);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("whitelist.idx");
+        built.whitelist().save_index(&path).unwrap();
+        let loaded = BarcodeCorrector::new(Whitelist::load_index(&path).unwrap(), 1);
+
+        for query in ["GGGGTTTA", "GGGGNTTT", "AAAACCCT", "AAAACCCN", "CCCCCCCC", "GGGG"] {
+            assert_eq!(
+                format!("{:?}", built.match_barcode(query)),
+                format!("{:?}", loaded.match_barcode(query)),
+                "{}",
+                query
+            );
+        }
+        assert_eq!(built.match_barcode("GGGGNTTT").barcode(), Some("GGGGTTTT"));
+        assert!(!built.match_barcode("AAAACCCT").is_valid());
+    }
+
+    #[test]
+    fn test_unpackable_whitelist_still_corrects() {
+        let whitelist = Whitelist::from_vec(vec!["AAAN".to_string()]).unwrap();
+        let corrector = BarcodeCorrector::new(whitelist, 1);
+        assert!(matches!(corrector.match_barcode("AAAA"), BarcodeMatch::Corrected(_, _, 1)));
+    }
+
+    #[test]
+    fn test_quality_weighted_correction() {
+        let barcodes = vec!["AAAACCCC".to_string(), "TAAACCCG".to_string()];
+        let corrector = BarcodeCorrector::new(Whitelist::from_vec(barcodes).unwrap(), 1);
+        // One mismatch from each candidate: first base vs last base
+        let query = "AAAACCCG";
+        assert!(!corrector.match_barcode(query).is_valid());
+
+        // Low quality at the last cycle makes AAAACCCC's mismatch the likely error
+        let qual = b"IIIIIII#";
+        assert_eq!(
+            corrector.match_barcode_with_quality(query, qual).barcode(),
+            Some("AAAACCCC")
+        );
+        let qual = b"#IIIIIII";
+        assert_eq!(
+            corrector.match_barcode_with_quality(query, qual).barcode(),
+            Some("TAAACCCG")
+        );
+        // Equal qualities leave it ambiguous
+        let qual = b"IIIIIIII";
+        assert!(!corrector.match_barcode_with_quality(query, qual).is_valid());
+        assert!(matches!(
+            corrector.match_barcode_with_quality("AAAACCCC", qual),
+            BarcodeMatch::Exact(_)
+        ));
+
+        let whitelist = Whitelist::from_vec(vec!["AAAACCCC".to_string()]).unwrap();
+        let far = BarcodeCorrector::new(whitelist, 2);
+        assert!(matches!(
+            far.match_barcode_with_quality("AAAACCGG", qual),
+            BarcodeMatch::Corrected(_, _, 2)
+        ));
+    }
 }
@@ -1,9 +1,16 @@
 //! Barcode detection and matching module
 
+mod edit;
+mod index;
 mod matcher;
+mod report;
+mod tiered;
 mod whitelist;
 
-pub use matcher::{BarcodeCorrector, BarcodeMatcher};
+pub use edit::{EditCorrector, EditMatch};
+pub use matcher::{BarcodeCorrector, BarcodeMatcher, DEFAULT_MIN_POSTERIOR};
+pub use report::{Correction, CorrectionReport, CorrectionSummary};
+pub use tiered::TieredCorrector;
 pub use whitelist::Whitelist;
 
 /// Result of barcode matching
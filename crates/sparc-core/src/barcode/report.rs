@@ -0,0 +1,165 @@
+//! Tally of observed barcode corrections
+//!
+//! Records how each raw barcode was matched so chemistry or whitelist
+//! mismatches show up as a handful of very frequent corrections, or as a high
+//! no-match rate.
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+use super::BarcodeMatch;
+use crate::atomic::AtomicFile;
+use crate::Result;
+
+/// Exact, corrected, and unmatched barcode totals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorrectionSummary {
+    pub exact: u64,
+    pub corrected: u64,
+    pub no_match: u64,
+    /// Distinct raw barcodes that were corrected
+    pub distinct_corrections: u64,
+}
+
+/// One corrected raw barcode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correction {
+    pub raw: String,
+    pub corrected: String,
+    pub count: u64,
+    pub distance: u32,
+}
+
+/// Match outcomes per raw barcode, mergeable across workers
+#[derive(Debug, Clone, Default)]
+pub struct CorrectionReport {
+    exact: u64,
+    no_match: u64,
+    /// Raw barcode -> (corrected barcode, distance, reads)
+    corrections: AHashMap<String, (String, u32, u64)>,
+}
+
+impl CorrectionReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one matched (or unmatched) read
+    pub fn record(&mut self, barcode_match: &BarcodeMatch) {
+        match barcode_match {
+            BarcodeMatch::Exact(_) => self.exact += 1,
+            BarcodeMatch::NoMatch(_) => self.no_match += 1,
+            BarcodeMatch::Corrected(raw, corrected, distance) => {
+                if let Some(entry) = self.corrections.get_mut(raw.as_str()) {
+                    entry.2 += 1;
+                } else {
+                    self.corrections.insert(raw.clone(), (corrected.clone(), *distance, 1));
+                }
+            }
+        }
+    }
+
+    /// Add another report's totals into this one
+    pub fn merge(&mut self, other: &Self) {
+        self.exact += other.exact;
+        self.no_match += other.no_match;
+        for (raw, (corrected, distance, count)) in &other.corrections {
+            self.corrections
+                .entry(raw.clone())
+                .or_insert_with(|| (corrected.clone(), *distance, 0))
+                .2 += count;
+        }
+    }
+
+    pub fn exact(&self) -> u64 {
+        self.exact
+    }
+
+    /// Reads whose barcode was corrected
+    pub fn corrected(&self) -> u64 {
+        self.corrections.values().map(|c| c.2).sum()
+    }
+
+    pub fn no_match(&self) -> u64 {
+        self.no_match
+    }
+
+    pub fn summary(&self) -> CorrectionSummary {
+        CorrectionSummary {
+            exact: self.exact,
+            corrected: self.corrected(),
+            no_match: self.no_match,
+            distinct_corrections: self.corrections.len() as u64,
+        }
+    }
+
+    /// Corrections, most frequent first (ties by raw barcode)
+    pub fn corrections(&self) -> Vec<Correction> {
+        let mut corrections: Vec<Correction> = self
+            .corrections
+            .iter()
+            .map(|(raw, (corrected, distance, count))| Correction {
+                raw: raw.clone(),
+                corrected: corrected.clone(),
+                count: *count,
+                distance: *distance,
+            })
+            .collect();
+        corrections.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.raw.cmp(&b.raw)));
+        corrections
+    }
+
+    /// Write `raw corrected count distance` rows, most frequent first
+    pub fn write_tsv<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = AtomicFile::create(path)?;
+        writeln!(writer, "raw_barcode\tcorrected_barcode\tcount\tedit_distance")?;
+        for c in self.corrections() {
+            writeln!(writer, "{}\t{}\t{}\t{}", c.raw, c.corrected, c.count, c.distance)?;
+        }
+        writer.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corrected(raw: &str, to: &str) -> BarcodeMatch {
+        BarcodeMatch::Corrected(raw.to_string(), to.to_string(), 1)
+    }
+
+    #[test]
+    fn test_report_merges_and_sorts() {
+        let mut a = CorrectionReport::new();
+        a.record(&BarcodeMatch::Exact("AAAA".to_string()));
+        a.record(&corrected("AAAT", "AAAA"));
+        a.record(&BarcodeMatch::NoMatch("GGGG".to_string()));
+        let mut b = CorrectionReport::new();
+        b.record(&corrected("CCCG", "CCCC"));
+        b.record(&corrected("CCCG", "CCCC"));
+        b.record(&corrected("AAAT", "AAAA"));
+        b.record(&corrected("AAAT", "AAAA"));
+        a.merge(&b);
+
+        let summary = a.summary();
+        assert_eq!(
+            summary,
+            CorrectionSummary {
+                exact: 1,
+                corrected: 5,
+                no_match: 1,
+                distinct_corrections: 2
+            }
+        );
+        let top = &a.corrections()[0];
+        assert_eq!((top.raw.as_str(), top.corrected.as_str(), top.count), ("AAAT", "AAAA", 3));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("barcode_corrections.tsv");
+        a.write_tsv(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().nth(2), Some("CCCG\tCCCC\t2\t1"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Per-tier correction of combinatorial barcodes
+//!
+//! Chemistries such as PIPseq build a cell barcode from several short
+//! segments, each drawn from its own small whitelist. The full combination is
+//! too large to list, so each tier is corrected on its own and the corrected
+//! tiers are joined back together.
+
+use super::{BarcodeCorrector, BarcodeMatch, Whitelist};
+use crate::{Error, Result};
+use flate2::read::MultiGzDecoder;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Corrects a concatenated barcode one tier at a time
+pub struct TieredCorrector {
+    tiers: Vec<BarcodeCorrector>,
+    lens: Vec<usize>,
+}
+
+impl TieredCorrector {
+    /// One corrector per tier whitelist, in barcode order
+    pub fn new(whitelists: Vec<Whitelist>, max_distance: u32) -> Result<Self> {
+        if whitelists.is_empty() {
+            return Err(Error::Barcode("Tiered whitelist has no tiers".to_string()));
+        }
+        if let Some(i) = whitelists.iter().position(|w| w.is_empty()) {
+            return Err(Error::Barcode(format!("Barcode tier {} has no barcodes", i + 1)));
+        }
+        let lens = whitelists.iter().map(|w| w.barcode_len()).collect();
+        let tiers = whitelists
+            .into_iter()
+            .map(|w| BarcodeCorrector::new(w, max_distance))
+            .collect();
+        Ok(Self { tiers, lens })
+    }
+
+    /// Load `<tier> <barcode>` lines, tiers numbered from 1 (`.gz` supported)
+    pub fn from_file<P: AsRef<Path>>(path: P, max_distance: u32) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        if path.extension().map_or(false, |ext| ext == "gz") {
+            Self::from_reader(BufReader::new(MultiGzDecoder::new(file)), max_distance)
+        } else {
+            Self::from_reader(BufReader::new(file), max_distance)
+        }
+    }
+
+    /// Read `<tier> <barcode>` lines (whitespace-separated, `#` comments)
+    pub fn from_reader<R: BufRead>(reader: R, max_distance: u32) -> Result<Self> {
+        let mut tiers: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(tier), Some(barcode), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(Error::Barcode(format!(
+                    "Expected '<tier> <barcode>' in tiered whitelist, got '{}'",
+                    line
+                )));
+            };
+            let tier = tier
+                .parse::<usize>()
+                .ok()
+                .filter(|&t| t >= 1)
+                .ok_or_else(|| Error::Barcode(format!("Invalid barcode tier '{}'", tier)))?;
+            tiers.entry(tier).or_default().push(barcode.to_string());
+        }
+        if let Some(missing) = (1..=tiers.len()).find(|t| !tiers.contains_key(t)) {
+            return Err(Error::Barcode(format!("Barcode tier {} has no barcodes", missing)));
+        }
+        let whitelists = tiers
+            .into_values()
+            .map(Whitelist::from_vec)
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(whitelists, max_distance)
+    }
+
+    /// Barcode length of each tier
+    pub fn tier_lens(&self) -> &[usize] {
+        &self.lens
+    }
+
+    /// Match a concatenated barcode, correcting each tier separately
+    pub fn match_barcode(&self, barcode: &str) -> BarcodeMatch {
+        self.match_tiers(barcode, |corrector, start, end| {
+            corrector.match_barcode(&barcode[start..end])
+        })
+    }
+
+    /// Match a concatenated barcode, weighing each tier's candidates by base
+    /// quality (see [`BarcodeCorrector::match_barcode_with_quality`])
+    pub fn match_barcode_with_quality(&self, barcode: &str, qual: &[u8]) -> BarcodeMatch {
+        if qual.len() != barcode.len() {
+            return self.match_barcode(barcode);
+        }
+        self.match_tiers(barcode, |corrector, start, end| {
+            corrector.match_barcode_with_quality(&barcode[start..end], &qual[start..end])
+        })
+    }
+
+    /// Join per-tier matches: exact only if every tier is, corrected with the
+    /// summed distance, or no match if any tier fails
+    fn match_tiers<F>(&self, barcode: &str, match_tier: F) -> BarcodeMatch
+    where
+        F: Fn(&BarcodeCorrector, usize, usize) -> BarcodeMatch,
+    {
+        let no_match = || BarcodeMatch::NoMatch(barcode.to_string());
+        if barcode.len() != self.lens.iter().sum::<usize>() || !barcode.is_ascii() {
+            return no_match();
+        }
+        let mut corrected = String::with_capacity(barcode.len());
+        let mut distance = 0;
+        let mut start = 0;
+        for (corrector, &len) in self.tiers.iter().zip(&self.lens) {
+            match match_tier(corrector, start, start + len) {
+                BarcodeMatch::Exact(tier) => corrected.push_str(&tier),
+                BarcodeMatch::Corrected(_, tier, d) => {
+                    corrected.push_str(&tier);
+                    distance += d;
+                }
+                BarcodeMatch::NoMatch(_) => return no_match(),
+            }
+            start += len;
+        }
+        if distance == 0 {
+            BarcodeMatch::Exact(corrected)
+        } else {
+            BarcodeMatch::Corrected(barcode.to_string(), corrected, distance)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiered_correction() {
+        let list = "# tier barcode\n1 AAAA\n1 CCCC\n2 GGG\n2 TTT\n3 ACGTAC\n";
+        let corrector = TieredCorrector::from_reader(list.as_bytes(), 1).unwrap();
+        assert_eq!(corrector.tier_lens(), &[4, 3, 6]);
+
+        assert!(matches!(corrector.match_barcode("CCCCTTTACGTAC"), BarcodeMatch::Exact(_)));
+        // One error in each of two tiers is still corrected
+        match corrector.match_barcode("CCGCTTAACGTAC") {
+            BarcodeMatch::Corrected(_, barcode, 2) => assert_eq!(barcode, "CCCCTTTACGTAC"),
+            other => panic!("expected correction, got {:?}", other),
+        }
+        // Two errors in one tier are not
+        assert!(!corrector.match_barcode("CCGGTTTACGTAC").is_valid());
+        assert!(!corrector.match_barcode("CCCCTTT").is_valid());
+
+        assert!(TieredCorrector::from_reader("1 AAAA\n3 GGG\n".as_bytes(), 1).is_err());
+        assert!(TieredCorrector::from_reader("AAAA\n".as_bytes(), 1).is_err());
+    }
+}
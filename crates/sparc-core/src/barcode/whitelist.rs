@@ -1,16 +1,21 @@
 //! Barcode whitelist handling
 
+use super::index::MismatchIndex;
 use crate::{Error, Result};
 use ahash::AHashSet;
+use flate2::read::MultiGzDecoder;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
+use std::sync::Arc;
 
 /// Barcode whitelist for exact matching
 #[derive(Debug, Clone)]
 pub struct Whitelist {
     barcodes: AHashSet<String>,
     barcode_len: usize,
+    /// One-mismatch index, built by the corrector or loaded with `load_index`
+    mismatch_index: Option<Arc<MismatchIndex>>,
 }
 
 impl Whitelist {
@@ -19,14 +24,23 @@ impl Whitelist {
         Self {
             barcodes: AHashSet::new(),
             barcode_len: 0,
+            mismatch_index: None,
         }
     }
 
-    /// Load whitelist from file (one barcode per line)
+    /// Load whitelist from file (one barcode per line, `.gz` supported)
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = File::open(path.as_ref())?;
-        let reader = BufReader::new(file);
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        if path.extension().map_or(false, |ext| ext == "gz") {
+            Self::from_reader(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Self::from_reader(BufReader::new(file))
+        }
+    }
 
+    /// Load whitelist from any reader (one barcode per line, `#` comments)
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
         let mut barcodes = AHashSet::new();
         let mut barcode_len = 0;
 
@@ -59,6 +73,7 @@ impl Whitelist {
         Ok(Self {
             barcodes,
             barcode_len,
+            mismatch_index: None,
         })
     }
 
@@ -82,6 +97,7 @@ impl Whitelist {
         Ok(Self {
             barcodes: barcodes.into_iter().collect(),
             barcode_len,
+            mismatch_index: None,
         })
     }
 
@@ -114,6 +130,87 @@ impl Whitelist {
     pub fn iter(&self) -> impl Iterator<Item = &String> {
         self.barcodes.iter()
     }
+
+    /// Save the whitelist with its one-mismatch index to a binary file that
+    /// `load_index` reads back without rebuilding the index
+    pub fn save_index<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match self.mismatch_index() {
+            Some(index) => index.save(path.as_ref()),
+            None => Err(Error::Barcode(
+                "Whitelist index needs barcodes of at most 32 A/C/G/T bases".to_string(),
+            )),
+        }
+    }
+
+    /// Load a whitelist saved by `save_index`
+    pub fn load_index<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let index = MismatchIndex::load(path.as_ref())?;
+        let barcodes: AHashSet<String> = index.barcodes().collect();
+        log::info!(
+            "Loaded whitelist index: {} barcodes (length={})",
+            barcodes.len(),
+            index.barcode_len()
+        );
+        Ok(Self {
+            barcode_len: if barcodes.is_empty() { 0 } else { index.barcode_len() },
+            barcodes,
+            mismatch_index: Some(Arc::new(index)),
+        })
+    }
+
+    /// The one-mismatch index, built now if not already attached. `None` if
+    /// the barcodes cannot be 2-bit packed.
+    pub(crate) fn mismatch_index(&self) -> Option<Arc<MismatchIndex>> {
+        if let Some(index) = &self.mismatch_index {
+            return Some(Arc::clone(index));
+        }
+        MismatchIndex::build(&self.barcodes, self.barcode_len).map(Arc::new)
+    }
+
+    /// Keep `index` for later corrector and `save_index` calls
+    pub(crate) fn attach_index(&mut self, index: Arc<MismatchIndex>) {
+        self.mismatch_index = Some(index);
+    }
+
+    /// Barcodes in both whitelists
+    pub fn intersection(&self, other: &Whitelist) -> Whitelist {
+        let barcodes: AHashSet<String> =
+            self.barcodes.intersection(&other.barcodes).cloned().collect();
+        Self::with_len(barcodes, self.barcode_len)
+    }
+
+    /// Barcodes in either whitelist (lengths must match unless one is empty)
+    pub fn union(&self, other: &Whitelist) -> Result<Whitelist> {
+        self.check_compatible(other)?;
+        let barcodes: AHashSet<String> = self.barcodes.union(&other.barcodes).cloned().collect();
+        Ok(Self::with_len(barcodes, self.barcode_len.max(other.barcode_len)))
+    }
+
+    /// Barcodes in this whitelist but not in `other`
+    pub fn difference(&self, other: &Whitelist) -> Whitelist {
+        let barcodes: AHashSet<String> =
+            self.barcodes.difference(&other.barcodes).cloned().collect();
+        Self::with_len(barcodes, self.barcode_len)
+    }
+
+    fn with_len(barcodes: AHashSet<String>, barcode_len: usize) -> Self {
+        let barcode_len = if barcodes.is_empty() { 0 } else { barcode_len };
+        Self {
+            barcodes,
+            barcode_len,
+            mismatch_index: None,
+        }
+    }
+
+    fn check_compatible(&self, other: &Whitelist) -> Result<()> {
+        if self.is_empty() || other.is_empty() || self.barcode_len == other.barcode_len {
+            return Ok(());
+        }
+        Err(Error::Barcode(format!(
+            "Inconsistent barcode length: expected {}, got {}",
+            self.barcode_len, other.barcode_len
+        )))
+    }
 }
 
 impl Default for Whitelist {
@@ -140,4 +237,38 @@ mod tests {
         assert!(whitelist.contains("AAACCCAAGAAACACT"));
         assert!(!whitelist.contains("AAACCCAAGAAACXXX"));
     }
+
+    #[test]
+    fn test_whitelist_set_operations() {
+        let a = Whitelist::from_vec(vec!["AAAA".to_string(), "CCCC".to_string()]).unwrap();
+        let b = Whitelist::from_vec(vec!["CCCC".to_string(), "GGGG".to_string()]).unwrap();
+
+        let both = a.intersection(&b);
+        assert_eq!(both.to_vec(), vec!["CCCC".to_string()]);
+        assert_eq!(a.union(&b).unwrap().len(), 3);
+        let only_a = a.difference(&b);
+        assert!(only_a.contains("AAAA") && !only_a.contains("CCCC"));
+        assert_eq!(a.difference(&a).barcode_len(), 0);
+
+        let short = Whitelist::from_vec(vec!["AAA".to_string()]).unwrap();
+        assert!(a.union(&short).is_err());
+        assert_eq!(a.union(&Whitelist::new()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_whitelist_index_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("whitelist.idx");
+        let whitelist =
+            Whitelist::from_vec(vec!["ACGTACGT".to_string(), "TTTTCCCC".to_string()]).unwrap();
+        whitelist.save_index(&path).unwrap();
+
+        let loaded = Whitelist::load_index(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.barcode_len(), 8);
+        assert!(loaded.contains("TTTTCCCC"));
+
+        let with_n = Whitelist::from_vec(vec!["ACGN".to_string()]).unwrap();
+        assert!(with_n.save_index(&path).is_err());
+    }
 }
@@ -0,0 +1,200 @@
+//! Synthetic workloads for benchmarking the hot paths
+//!
+//! A [`Workload`] holds simulated reads (see [`crate::sim`]) in memory. Each
+//! [`Stage`] runs one pipeline step over them; the criterion suite
+//! (`benches/pipeline.rs`) and `sparc bench` time the same stages. Parallel
+//! stages run on the current rayon pool, so callers pick the thread count by
+//! installing a pool.
+
+use ahash::AHashMap;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::hint::black_box;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use crate::barcode::{BarcodeCorrector, Whitelist};
+use crate::count::GeneCounter;
+use crate::fastq::{encode_block, FastqParser, FastqRecord};
+use crate::sim::{simulate, SimConfig};
+use crate::umi::{Umi, UmiDeduplicator};
+use crate::{Error, Result};
+
+/// A benchmarked pipeline step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Parse R1 FASTQ text
+    FastqParse,
+    /// Correct R1 barcodes against the whitelist (1 mismatch)
+    BarcodeCorrection,
+    /// Directional UMI deduplication per cell and gene
+    UmiDedup,
+    /// Count reads per cell and gene, then build the matrix
+    Counting,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [
+        Stage::FastqParse,
+        Stage::BarcodeCorrection,
+        Stage::UmiDedup,
+        Stage::Counting,
+    ];
+
+    /// Display name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::FastqParse => "fastq_parse",
+            Stage::BarcodeCorrection => "barcode_correction",
+            Stage::UmiDedup => "umi_dedup",
+            Stage::Counting => "counting",
+        }
+    }
+
+    /// Whether the stage uses the rayon pool
+    pub fn is_parallel(&self) -> bool {
+        matches!(self, Stage::BarcodeCorrection | Stage::UmiDedup)
+    }
+}
+
+/// Simulated reads and the structures each stage needs
+pub struct Workload {
+    /// R1 records as FASTQ text
+    r1_fastq: Arc<[u8]>,
+    r1: Vec<FastqRecord>,
+    barcode_len: usize,
+    corrector: BarcodeCorrector,
+    /// True (barcode, gene) of each read, from the simulated read name
+    tags: Vec<(String, String)>,
+    /// Observed UMIs per true (barcode, gene)
+    umi_groups: Vec<Vec<Umi>>,
+}
+
+impl Workload {
+    /// Default simulation with `n_cells` cells
+    pub fn generate(n_cells: usize, seed: u64) -> Result<Self> {
+        Self::from_config(&SimConfig {
+            n_cells,
+            seed,
+            ..Default::default()
+        })
+    }
+
+    /// Simulate reads with `config` and prepare every stage's inputs
+    pub fn from_config(config: &SimConfig) -> Result<Self> {
+        let mut r1 = Vec::new();
+        let truth = simulate(config, |read1, _| {
+            r1.push(read1);
+            Ok(())
+        })?;
+
+        let mut tags = Vec::with_capacity(r1.len());
+        let mut groups: AHashMap<(String, String), AHashMap<String, u32>> = AHashMap::new();
+        for record in &r1 {
+            // Simulated names are sim<N>:<barcode>:<umi>:<gene>
+            let fields: Vec<&str> = record.id.split(':').collect();
+            let [_, barcode, _, gene] = fields[..] else {
+                return Err(Error::Config(format!("unexpected read name {}", record.id)));
+            };
+            let umi = String::from_utf8_lossy(&record.seq[config.barcode_len..]).to_string();
+            let key = (barcode.to_string(), gene.to_string());
+            *groups.entry(key.clone()).or_default().entry(umi).or_insert(0) += 1;
+            tags.push(key);
+        }
+        let umi_groups = groups
+            .into_values()
+            .map(|umis| umis.into_iter().map(|(seq, n)| Umi::with_count(seq, n)).collect())
+            .collect();
+
+        Ok(Self {
+            r1_fastq: encode_block(&r1, false)?.into(),
+            r1,
+            barcode_len: config.barcode_len,
+            corrector: BarcodeCorrector::new(Whitelist::from_vec(truth.whitelist)?, 1),
+            tags,
+            umi_groups,
+        })
+    }
+
+    /// Simulated read pairs
+    pub fn reads(&self) -> usize {
+        self.r1.len()
+    }
+
+    /// Run one stage; returns the number of reads processed
+    pub fn run(&self, stage: Stage) -> Result<u64> {
+        match stage {
+            Stage::FastqParse => self.parse_fastq(),
+            Stage::BarcodeCorrection => Ok(self.correct_barcodes()),
+            Stage::UmiDedup => Ok(self.dedup_umis()),
+            Stage::Counting => self.count(),
+        }
+    }
+
+    fn parse_fastq(&self) -> Result<u64> {
+        let text = Cursor::new(Arc::clone(&self.r1_fastq));
+        let mut parser = FastqParser::from_reader(text, "bench")?;
+        let mut reads = 0u64;
+        while let Some(record) = parser.next_ref() {
+            record?;
+            reads += 1;
+        }
+        Ok(reads)
+    }
+
+    fn correct_barcodes(&self) -> u64 {
+        let matched = self
+            .r1
+            .par_iter()
+            .filter(|record| {
+                let barcode = std::str::from_utf8(&record.seq[..self.barcode_len]).unwrap_or("");
+                self.corrector.match_barcode(barcode).barcode().is_some()
+            })
+            .count();
+        black_box(matched);
+        self.r1.len() as u64
+    }
+
+    fn dedup_umis(&self) -> u64 {
+        let dedup = UmiDeduplicator::new(1);
+        self.umi_groups
+            .par_iter()
+            .map(|umis| {
+                black_box(dedup.deduplicate(umis));
+                umis.iter().map(|u| u.count as u64).sum::<u64>()
+            })
+            .sum()
+    }
+
+    fn count(&self) -> Result<u64> {
+        let mut counter = GeneCounter::new();
+        for (barcode, gene) in &self.tags {
+            counter.increment(barcode, gene);
+        }
+        black_box(counter.try_build()?);
+        Ok(self.tags.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages_process_every_read() {
+        let config = SimConfig {
+            n_cells: 3,
+            n_empty: 2,
+            n_genes: 20,
+            mean_umis: 30.0,
+            whitelist_decoys: 10,
+            ..Default::default()
+        };
+        let workload = Workload::from_config(&config).unwrap();
+        assert!(workload.reads() > 0);
+        for stage in Stage::ALL {
+            assert_eq!(workload.run(stage).unwrap(), workload.reads() as u64, "{}", stage.name());
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Gene biotypes for filtering counts and QC fractions
+
+use ahash::{AHashMap, AHashSet};
+use std::collections::BTreeMap;
+
+use super::CountMatrix;
+use crate::annotation::GeneAnnotation;
+
+/// Biotype reported for genes the annotation gives none
+pub const UNKNOWN_BIOTYPE: &str = "unknown";
+
+/// Gene ID or symbol -> biotype (GTF `gene_type` / `gene_biotype`)
+#[derive(Debug, Clone, Default)]
+pub struct GeneBiotypes {
+    by_gene: AHashMap<String, String>,
+}
+
+impl GeneBiotypes {
+    /// Biotypes of every annotated gene, looked up by ID or symbol. A symbol
+    /// shared by several genes keeps the first gene's biotype.
+    pub fn from_annotation(annotation: &GeneAnnotation) -> Self {
+        let mut by_gene = AHashMap::new();
+        for gene in annotation.genes() {
+            let Some(biotype) = &gene.biotype else {
+                continue;
+            };
+            by_gene.entry(gene.name.clone()).or_insert_with(|| biotype.clone());
+        }
+        for gene in annotation.genes() {
+            if let Some(biotype) = &gene.biotype {
+                by_gene.insert(gene.id.clone(), biotype.clone());
+            }
+        }
+        Self { by_gene }
+    }
+
+    /// Biotype of `gene`, or [`UNKNOWN_BIOTYPE`]
+    pub fn get(&self, gene: &str) -> &str {
+        self.by_gene.get(gene).map_or(UNKNOWN_BIOTYPE, String::as_str)
+    }
+
+    /// Number of genes with a known biotype
+    pub fn len(&self) -> usize {
+        self.by_gene.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_gene.is_empty()
+    }
+
+    /// Fraction of the matrix's counts in each biotype
+    pub fn fractions(&self, matrix: &CountMatrix) -> BTreeMap<String, f64> {
+        let row_biotypes: Vec<&str> = matrix.genes.iter().map(|g| self.get(g)).collect();
+        let mut totals: BTreeMap<String, u64> = BTreeMap::new();
+        for (&row, &value) in matrix.rows.iter().zip(&matrix.values) {
+            *totals.entry(row_biotypes[row].to_string()).or_insert(0) += value as u64;
+        }
+        let total = totals.values().sum::<u64>().max(1) as f64;
+        totals.into_iter().map(|(b, n)| (b, n as f64 / total)).collect()
+    }
+}
+
+/// Keeps genes by biotype: in `include` (when given) and not in `exclude`
+#[derive(Debug, Clone)]
+pub struct BiotypeFilter {
+    biotypes: GeneBiotypes,
+    include: AHashSet<String>,
+    exclude: AHashSet<String>,
+}
+
+impl BiotypeFilter {
+    pub fn new(biotypes: GeneBiotypes, include: &[String], exclude: &[String]) -> Self {
+        Self {
+            biotypes,
+            include: include.iter().cloned().collect(),
+            exclude: exclude.iter().cloned().collect(),
+        }
+    }
+
+    /// Whether counts for `gene` are kept
+    pub fn allows(&self, gene: &str) -> bool {
+        let biotype = self.biotypes.get(gene);
+        (self.include.is_empty() || self.include.contains(biotype))
+            && !self.exclude.contains(biotype)
+    }
+
+    pub fn biotypes(&self) -> &GeneBiotypes {
+        &self.biotypes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GTF: &str = "\
+chr1\tsrc\tgene\t1\t100\t.\t+\t.\tgene_id \"G1\"; gene_name \"Alpha\"; gene_type \"protein_coding\";
+chr1\tsrc\tgene\t201\t300\t.\t+\t.\tgene_id \"G2\"; gene_name \"RNA5S1\"; gene_biotype \"rRNA\";
+chr1\tsrc\tgene\t401\t500\t.\t-\t.\tgene_id \"G3\"; gene_name \"Gamma\";
+";
+
+    #[test]
+    fn test_biotype_filter_and_fractions() {
+        let annotation = GeneAnnotation::from_reader(GTF.as_bytes()).unwrap();
+        let biotypes = GeneBiotypes::from_annotation(&annotation);
+        assert_eq!(biotypes.get("G1"), "protein_coding");
+        assert_eq!(biotypes.get("RNA5S1"), "rRNA");
+        assert_eq!(biotypes.get("Gamma"), UNKNOWN_BIOTYPE);
+
+        let no_rrna = BiotypeFilter::new(biotypes.clone(), &[], &["rRNA".to_string()]);
+        assert!(no_rrna.allows("Alpha") && no_rrna.allows("G3"));
+        assert!(!no_rrna.allows("G2"));
+        let coding = BiotypeFilter::new(biotypes.clone(), &["protein_coding".to_string()], &[]);
+        assert!(coding.allows("G1") && !coding.allows("Gamma"));
+
+        let matrix = CountMatrix::from_dense(
+            vec!["CELL1".to_string()],
+            vec!["G1".to_string(), "G2".to_string(), "G3".to_string()],
+            vec![vec![6], vec![3], vec![1]],
+        );
+        let fractions = biotypes.fractions(&matrix);
+        assert!((fractions["protein_coding"] - 0.6).abs() < 1e-9);
+        assert!((fractions["rRNA"] - 0.3).abs() < 1e-9);
+        assert!((fractions[UNKNOWN_BIOTYPE] - 0.1).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,220 @@
+//! Per-cell read statistics written beside the count matrix
+
+use std::io::Write;
+use std::path::Path;
+
+use super::matrix::create_text;
+use super::CountMatrix;
+use crate::annotation::RegionType;
+use crate::intern::Interner;
+use crate::Result;
+
+/// Read and molecule totals for one cell barcode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CellStat {
+    /// Mapped reads carrying the barcode
+    pub reads: u64,
+    /// Reads counted toward a gene
+    pub assigned_reads: u64,
+    /// Matrix total (molecules, or reads with `--no-dedup`)
+    pub umis: u64,
+    /// Genes with a non-zero count
+    pub genes: u64,
+    /// Reads with a region tag
+    pub region_reads: u64,
+    /// Reads tagged intronic
+    pub intronic_reads: u64,
+    /// Assigned reads marked as position duplicates, when counting without UMIs
+    pub duplicate_reads: Option<u64>,
+}
+
+impl CellStat {
+    /// Fraction of assigned reads duplicating an earlier molecule, or an
+    /// earlier read's position when counting without UMIs
+    pub fn duplication_rate(&self) -> f64 {
+        if self.assigned_reads == 0 {
+            return 0.0;
+        }
+        match self.duplicate_reads {
+            Some(duplicates) => duplicates as f64 / self.assigned_reads as f64,
+            None => 1.0 - self.umis as f64 / self.assigned_reads as f64,
+        }
+    }
+
+    /// Fraction of region-tagged reads that are intronic
+    pub fn fraction_intronic(&self) -> Option<f64> {
+        (self.region_reads > 0).then(|| self.intronic_reads as f64 / self.region_reads as f64)
+    }
+}
+
+/// Per-barcode statistics collected while counting
+#[derive(Debug, Default)]
+pub struct CellStats {
+    barcodes: Interner,
+    stats: Vec<CellStat>,
+    /// Reads are counted without UMIs, with duplicates marked by position
+    by_position: bool,
+}
+
+impl CellStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Statistics for read counting without UMIs: duplication comes from
+    /// [`record_duplicate`](Self::record_duplicate) and there is no UMI column
+    pub fn by_position() -> Self {
+        Self {
+            by_position: true,
+            ..Self::default()
+        }
+    }
+
+    fn stat_mut(&mut self, barcode: &str) -> &mut CellStat {
+        let id = self.barcodes.intern(barcode) as usize;
+        if id == self.stats.len() {
+            self.stats.push(CellStat {
+                duplicate_reads: self.by_position.then_some(0),
+                ..CellStat::default()
+            });
+        }
+        &mut self.stats[id]
+    }
+
+    /// Record a mapped read from `barcode` and its region, if tagged
+    pub fn record_read(&mut self, barcode: &str, region: Option<RegionType>) {
+        let stat = self.stat_mut(barcode);
+        stat.reads += 1;
+        if let Some(region) = region {
+            stat.region_reads += 1;
+            stat.intronic_reads += (region == RegionType::Intronic) as u64;
+        }
+    }
+
+    /// Record that a read from `barcode` was counted toward a gene
+    pub fn record_assigned(&mut self, barcode: &str) {
+        self.stat_mut(barcode).assigned_reads += 1;
+    }
+
+    /// Record that an assigned read from `barcode` was a position duplicate
+    pub fn record_duplicate(&mut self, barcode: &str) {
+        let stat = self.stat_mut(barcode);
+        stat.duplicate_reads = Some(stat.duplicate_reads.unwrap_or(0) + 1);
+    }
+
+    /// Fill in UMI and gene totals from the finished matrix
+    pub fn add_matrix(&mut self, matrix: &CountMatrix) {
+        let umis = matrix.counts_per_cell();
+        let genes = matrix.genes_per_cell();
+        for (i, barcode) in matrix.barcodes.iter().enumerate() {
+            let stat = self.stat_mut(barcode);
+            stat.umis = umis[i];
+            stat.genes = genes[i];
+        }
+    }
+
+    /// Statistics for `barcode`, if it was seen
+    pub fn get(&self, barcode: &str) -> Option<&CellStat> {
+        self.barcodes.get(barcode).map(|id| &self.stats[id as usize])
+    }
+
+    /// Number of barcodes seen
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.is_empty()
+    }
+
+    /// Write one row per barcode, in first-seen order (gzipped for `.gz`).
+    /// Without UMIs, a `duplicate_reads` column replaces `umis`.
+    pub fn write_tsv<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = create_text(path.as_ref())?;
+        let count_column = if self.by_position { "duplicate_reads" } else { "umis" };
+        writeln!(
+            writer,
+            "barcode\treads\tassigned_reads\t{}\tgenes\tduplication_rate\tfraction_intronic",
+            count_column
+        )?;
+        for (barcode, stat) in self.barcodes.iter().zip(&self.stats) {
+            let intronic = stat
+                .fraction_intronic()
+                .map_or("NA".to_string(), |f| format!("{:.4}", f));
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{:.4}\t{}",
+                barcode,
+                stat.reads,
+                stat.assigned_reads,
+                stat.duplicate_reads.unwrap_or(stat.umis),
+                stat.genes,
+                stat.duplication_rate(),
+                intronic
+            )?;
+        }
+        writer.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_stats() {
+        let mut stats = CellStats::new();
+        for region in [RegionType::Exonic, RegionType::Exonic, RegionType::Intronic] {
+            stats.record_read("CELL1", Some(region));
+            stats.record_assigned("CELL1");
+        }
+        stats.record_read("CELL1", Some(RegionType::Intergenic));
+        stats.record_read("CELL2", None);
+
+        let matrix = CountMatrix::from_dense(
+            vec!["CELL1".to_string()],
+            vec!["G1".to_string(), "G2".to_string()],
+            vec![vec![1], vec![1]],
+        );
+        stats.add_matrix(&matrix);
+
+        let cell1 = stats.get("CELL1").unwrap();
+        assert_eq!((cell1.reads, cell1.assigned_reads, cell1.umis, cell1.genes), (4, 3, 2, 2));
+        assert!((cell1.duplication_rate() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(cell1.fraction_intronic(), Some(0.25));
+        assert_eq!(stats.get("CELL2").unwrap().fraction_intronic(), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cell_stats.tsv");
+        stats.write_tsv(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "CELL1\t4\t3\t2\t2\t0.3333\t0.2500");
+        assert_eq!(lines[2], "CELL2\t1\t0\t0\t0\t0.0000\tNA");
+    }
+
+    #[test]
+    fn test_cell_stats_by_position() {
+        let mut stats = CellStats::by_position();
+        for _ in 0..4 {
+            stats.record_read("CELL1", None);
+            stats.record_assigned("CELL1");
+        }
+        stats.record_duplicate("CELL1");
+        stats.record_read("CELL2", None);
+
+        let cell1 = stats.get("CELL1").unwrap();
+        assert_eq!(cell1.duplicate_reads, Some(1));
+        assert!((cell1.duplication_rate() - 0.25).abs() < 1e-9);
+        assert_eq!(stats.get("CELL2").unwrap().duplicate_reads, Some(0));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cell_stats.tsv");
+        stats.write_tsv(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].contains("\tduplicate_reads\t"), "{}", lines[0]);
+        assert_eq!(lines[1], "CELL1\t4\t4\t1\t0\t0.2500\tNA");
+    }
+}
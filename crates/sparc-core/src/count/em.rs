@@ -0,0 +1,330 @@
+//! EM rescue of reads compatible with several genes
+//!
+//! Instead of discarding a read (or molecule) that overlaps several genes, it
+//! is shared among them in proportion to each gene's estimated abundance in
+//! the same cell, as in STARsolo's `GeneFull` EM mode. Unique counts seed the
+//! estimate, and the fractional totals are rounded per [`EmRounding`].
+
+use ahash::{AHashMap, AHashSet};
+use rayon::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+use super::CountMatrix;
+use crate::intern::Interner;
+use crate::{Error, Result};
+
+/// Iteration cap per cell
+const MAX_ITERATIONS: usize = 100;
+
+/// Stop once no gene's estimate moves by more than this
+const TOLERANCE: f64 = 0.01;
+
+/// How fractional EM counts become integer matrix counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmRounding {
+    /// Round to the nearest integer
+    #[default]
+    Nearest,
+    /// Round down, counting only whole molecules
+    Floor,
+}
+
+impl EmRounding {
+    fn apply(self, value: f64) -> u32 {
+        match self {
+            EmRounding::Nearest => value.round() as u32,
+            EmRounding::Floor => value.floor() as u32,
+        }
+    }
+}
+
+impl FromStr for EmRounding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "nearest" => Ok(EmRounding::Nearest),
+            "floor" => Ok(EmRounding::Floor),
+            _ => Err(Error::Config(format!(
+                "unknown EM rounding '{}' (expected nearest or floor)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for EmRounding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EmRounding::Nearest => "nearest",
+            EmRounding::Floor => "floor",
+        })
+    }
+}
+
+/// Totals from an EM resolution
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EmStats {
+    /// Multi-gene reads or molecules
+    pub units: u64,
+    /// Sum of fractional counts before rounding
+    pub fractional: f64,
+    /// Counts added to the matrix after rounding
+    pub added: u64,
+}
+
+/// Reads or molecules sharing a cell and a set of compatible genes
+#[derive(Debug, Default)]
+struct MultiUnits {
+    /// Distinct UMIs, when deduplicating
+    umis: AHashSet<u32>,
+    /// Reads counted without a UMI
+    reads: u32,
+}
+
+impl MultiUnits {
+    fn count(&self) -> u32 {
+        self.umis.len() as u32 + self.reads
+    }
+}
+
+/// Collects multi-gene reads per cell for EM resolution
+#[derive(Debug, Default)]
+pub struct MultiGeneCounter {
+    barcodes: Interner,
+    genes: Interner,
+    umis: Interner,
+    /// (cell_id, sorted gene ids) -> units
+    classes: AHashMap<(u32, Box<[u32]>), MultiUnits>,
+}
+
+impl MultiGeneCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a read compatible with `genes`. Reads with a UMI count once per
+    /// distinct UMI; reads without one count individually.
+    pub fn add(&mut self, barcode: &str, genes: &[&str], umi: Option<&str>) {
+        let cell_id = self.barcodes.intern(barcode);
+        let mut gene_ids: Vec<u32> = genes.iter().map(|g| self.genes.intern(g)).collect();
+        gene_ids.sort_unstable();
+        gene_ids.dedup();
+        let umi_id = umi.map(|u| self.umis.intern(u));
+        let units = self.classes.entry((cell_id, gene_ids.into())).or_default();
+        match umi_id {
+            Some(id) => {
+                units.umis.insert(id);
+            }
+            None => units.reads += 1,
+        }
+    }
+
+    /// Number of distinct (cell, gene set) classes
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    /// Add EM-resolved multi-gene counts to `matrix`, seeding each cell's
+    /// abundance estimate with its unique counts
+    pub fn resolve(self, matrix: CountMatrix, rounding: EmRounding) -> (CountMatrix, EmStats) {
+        let CountMatrix {
+            mut barcodes,
+            mut genes,
+            mut gene_names,
+            rows,
+            cols,
+            values,
+            ..
+        } = matrix;
+        let mut col_of: AHashMap<String, usize> =
+            barcodes.iter().enumerate().map(|(i, b)| (b.clone(), i)).collect();
+        let mut row_of: AHashMap<String, usize> =
+            genes.iter().enumerate().map(|(i, g)| (g.clone(), i)).collect();
+        let gene_rows: Vec<usize> =
+            self.genes.iter().map(|g| index_of(&mut genes, &mut row_of, g)).collect();
+        // Genes seen only in multi-gene reads have no symbol beyond their label
+        if !gene_names.is_empty() {
+            gene_names.extend(genes[gene_names.len()..].iter().cloned());
+        }
+
+        let mut stats = EmStats::default();
+        let mut by_cell: AHashMap<usize, Vec<(Vec<usize>, u32)>> = AHashMap::new();
+        // Sorted so cells first seen here get columns in a stable order
+        let mut classes: Vec<_> = self.classes.into_iter().collect();
+        classes.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        for ((cell_id, gene_ids), units) in classes {
+            let col = index_of(&mut barcodes, &mut col_of, self.barcodes.resolve(cell_id));
+            let class_rows = gene_ids.iter().map(|&g| gene_rows[g as usize]).collect();
+            stats.units += units.count() as u64;
+            by_cell.entry(col).or_default().push((class_rows, units.count()));
+        }
+
+        let mut unique: AHashMap<usize, AHashMap<usize, u32>> = AHashMap::new();
+        let (mut new_rows, mut new_cols, mut new_values) = (Vec::new(), Vec::new(), Vec::new());
+        for ((row, col), value) in rows.into_iter().zip(cols).zip(values) {
+            if by_cell.contains_key(&col) {
+                unique.entry(col).or_default().insert(row, value);
+            } else {
+                new_rows.push(row);
+                new_cols.push(col);
+                new_values.push(value);
+            }
+        }
+
+        let by_cell: Vec<_> = by_cell.into_iter().collect();
+        let mut resolved: Vec<(usize, AHashMap<usize, u32>, f64, u64)> = by_cell
+            .into_par_iter()
+            .map(|(col, classes)| {
+                let mut counts = unique.get(&col).cloned().unwrap_or_default();
+                let fractions = em_fractions(&counts, &classes);
+                let (mut fractional, mut added) = (0.0, 0u64);
+                for (row, f) in fractions {
+                    let n = rounding.apply(f);
+                    fractional += f;
+                    added += n as u64;
+                    if n > 0 {
+                        *counts.entry(row).or_insert(0) += n;
+                    }
+                }
+                (col, counts, fractional, added)
+            })
+            .collect();
+        resolved.sort_unstable_by_key(|r| r.0);
+
+        for (col, counts, fractional, added) in resolved {
+            stats.fractional += fractional;
+            stats.added += added;
+            let mut counts: Vec<(usize, u32)> = counts.into_iter().collect();
+            counts.sort_unstable();
+            for (row, value) in counts {
+                new_rows.push(row);
+                new_cols.push(col);
+                new_values.push(value);
+            }
+        }
+
+        let matrix = CountMatrix {
+            n_rows: genes.len(),
+            n_cols: barcodes.len(),
+            barcodes,
+            genes,
+            gene_names,
+            rows: new_rows,
+            cols: new_cols,
+            values: new_values,
+            ..CountMatrix::new()
+        };
+        (matrix, stats)
+    }
+}
+
+/// Position of `name` in `names`, appending it if new
+fn index_of(names: &mut Vec<String>, of: &mut AHashMap<String, usize>, name: &str) -> usize {
+    *of.entry(name.to_string()).or_insert_with(|| {
+        names.push(name.to_string());
+        names.len() - 1
+    })
+}
+
+/// Fractional counts per gene row for one cell's multi-gene `classes`
+/// (gene rows, units), given its unique counts
+fn em_fractions(
+    unique: &AHashMap<usize, u32>,
+    classes: &[(Vec<usize>, u32)],
+) -> AHashMap<usize, f64> {
+    let seed = |row: &usize| unique.get(row).copied().unwrap_or(0) as f64;
+
+    // Start from unique counts plus an even share of every class
+    let mut theta: AHashMap<usize, f64> = AHashMap::new();
+    for (rows, n) in classes {
+        for row in rows {
+            *theta.entry(*row).or_insert_with(|| seed(row)) += *n as f64 / rows.len() as f64;
+        }
+    }
+
+    let mut fractions = AHashMap::new();
+    for _ in 0..MAX_ITERATIONS {
+        fractions = AHashMap::with_capacity(theta.len());
+        for (rows, n) in classes {
+            let total: f64 = rows.iter().map(|r| theta[r]).sum();
+            for row in rows {
+                let share = if total > 0.0 {
+                    theta[row] / total
+                } else {
+                    1.0 / rows.len() as f64
+                };
+                *fractions.entry(*row).or_insert(0.0) += *n as f64 * share;
+            }
+        }
+        let mut delta: f64 = 0.0;
+        for (row, estimate) in theta.iter_mut() {
+            let next = seed(row) + fractions[row];
+            delta = delta.max((next - *estimate).abs());
+            *estimate = next;
+        }
+        if delta < TOLERANCE {
+            break;
+        }
+    }
+    fractions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_em_follows_unique_abundance() {
+        let unique: AHashMap<usize, u32> = [(0, 90), (1, 10)].into_iter().collect();
+        let fractions = em_fractions(&unique, &[(vec![0, 1], 10)]);
+        assert!((fractions[&0] + fractions[&1] - 10.0).abs() < 1e-9);
+        assert!(fractions[&0] > 8.9, "{:?}", fractions);
+
+        // Without unique evidence the class is split evenly
+        let even = em_fractions(&AHashMap::new(), &[(vec![2, 3], 4)]);
+        assert!((even[&2] - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_resolve_adds_rounded_counts() {
+        let matrix = CountMatrix::from_dense(
+            vec!["CELL1".to_string(), "CELL2".to_string()],
+            vec!["A".to_string(), "B".to_string()],
+            vec![vec![9, 1], vec![1, 0]],
+        );
+        let mut multi = MultiGeneCounter::new();
+        for umi in ["AAAA", "CCCC", "GGGG", "GGGG"] {
+            multi.add("CELL1", &["A", "B"], Some(umi));
+        }
+        multi.add("CELL3", &["B", "C"], None);
+        multi.add("CELL3", &["C", "B"], None);
+        assert_eq!(multi.len(), 2);
+
+        let (resolved, stats) = multi.resolve(matrix, EmRounding::Nearest);
+        assert_eq!(stats.units, 5);
+        assert!((stats.fractional - 5.0).abs() < 1e-9);
+        assert_eq!(resolved.barcodes, vec!["CELL1", "CELL2", "CELL3"]);
+        assert_eq!(resolved.genes, vec!["A", "B", "C"]);
+        // CELL1's 3 molecules mostly go to A (9 unique vs 1)
+        assert_eq!(resolved.get(0, 0), 12);
+        assert_eq!(resolved.get(1, 0), 1);
+        assert_eq!(resolved.get(0, 1), 1);
+        assert_eq!(resolved.get(1, 2) + resolved.get(2, 2), 2);
+
+        let one = |name: &str| vec![name.to_string()];
+        let matrix = CountMatrix::from_dense(one("CELL1"), one("A"), vec![vec![1]]);
+        let mut multi = MultiGeneCounter::new();
+        multi.add("CELL1", &["A", "B"], None);
+        let (floored, stats) = multi.resolve(matrix, EmRounding::Floor);
+        assert_eq!(stats.added, 0);
+        assert_eq!(floored.get(0, 0), 1);
+        assert_eq!("floor".parse::<EmRounding>().unwrap(), EmRounding::Floor);
+    }
+}
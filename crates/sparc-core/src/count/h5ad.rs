@@ -0,0 +1,103 @@
+//! Reading AnnData (`.h5ad`) count matrices (feature `h5ad`)
+//!
+//! Supports `X` stored as a dense array or as a CSR/CSC sparse group, with
+//! cells as `obs` and genes as `var`. Values must be raw integer counts.
+
+use hdf5::types::VarLenUnicode;
+use hdf5::Group;
+use std::path::Path;
+
+use super::io::integer_count;
+use super::CountMatrix;
+use crate::{Error, Result};
+
+fn h5_error(path: &Path) -> impl Fn(hdf5::Error) -> Error + '_ {
+    move |e| Error::Matrix(format!("{:?}: {}", path, e))
+}
+
+impl CountMatrix {
+    /// Read the `X` matrix and obs/var names from an `.h5ad` file
+    pub fn read_h5ad<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let err = h5_error(path);
+        let file = hdf5::File::open(path).map_err(&err)?;
+        let barcodes = read_index(&file.group("obs").map_err(&err)?).map_err(&err)?;
+        let genes = read_index(&file.group("var").map_err(&err)?).map_err(&err)?;
+        let (n_cells, n_genes) = (barcodes.len(), genes.len());
+
+        // (cell, gene, value) triplets from X, which is cells x genes
+        let mut entries: Vec<(usize, usize, f64)> = Vec::new();
+        if let Ok(x) = file.group("X") {
+            let encoding = x
+                .attr("encoding-type")
+                .and_then(|a| a.read_scalar::<VarLenUnicode>())
+                .map_err(&err)?;
+            let data: Vec<f64> = x.dataset("data").and_then(|d| d.read_raw()).map_err(&err)?;
+            let indices: Vec<i64> = x.dataset("indices").and_then(|d| d.read_raw()).map_err(&err)?;
+            let indptr: Vec<i64> = x.dataset("indptr").and_then(|d| d.read_raw()).map_err(&err)?;
+            let cells_are_rows = match encoding.as_str() {
+                "csr_matrix" => true,
+                "csc_matrix" => false,
+                other => {
+                    return Err(Error::Matrix(format!(
+                        "{:?}: unsupported X encoding '{}'",
+                        path, other
+                    )))
+                }
+            };
+            for (major, bounds) in indptr.windows(2).enumerate() {
+                for k in bounds[0] as usize..bounds[1] as usize {
+                    let minor = indices[k] as usize;
+                    let (cell, gene) = if cells_are_rows { (major, minor) } else { (minor, major) };
+                    entries.push((cell, gene, data[k]));
+                }
+            }
+        } else {
+            let x = file.dataset("X").map_err(&err)?;
+            let data: Vec<f64> = x.read_raw().map_err(&err)?;
+            for (k, &value) in data.iter().enumerate() {
+                if value != 0.0 {
+                    entries.push((k / n_genes.max(1), k % n_genes.max(1), value));
+                }
+            }
+        }
+
+        let mut matrix = CountMatrix::new();
+        for (cell, gene, value) in entries {
+            if cell >= n_cells || gene >= n_genes {
+                return Err(Error::Matrix(format!(
+                    "{:?}: X entry ({}, {}) outside {} cells x {} genes",
+                    path, cell, gene, n_cells, n_genes
+                )));
+            }
+            let count = integer_count(value).ok_or_else(|| {
+                Error::Matrix(format!(
+                    "{:?}: X holds non-integer value {} (expected raw counts)",
+                    path, value
+                ))
+            })?;
+            if count > 0 {
+                matrix.rows.push(gene);
+                matrix.cols.push(cell);
+                matrix.values.push(count);
+            }
+        }
+        matrix.n_rows = n_genes;
+        matrix.n_cols = n_cells;
+        matrix.genes = genes;
+        matrix.barcodes = barcodes;
+        matrix.validate()?;
+        Ok(matrix)
+    }
+}
+
+/// Names from an obs/var dataframe group (the column named by its `_index` attribute)
+fn read_index(group: &Group) -> hdf5::Result<Vec<String>> {
+    let column = group
+        .attr("_index")
+        .and_then(|a| a.read_scalar::<VarLenUnicode>())
+        .map(|name| name.as_str().to_string())
+        .unwrap_or_else(|_| "_index".to_string());
+    let names: Vec<VarLenUnicode> = group.dataset(&column)?.read_raw()?;
+    Ok(names.iter().map(|n| n.as_str().to_string()).collect())
+}
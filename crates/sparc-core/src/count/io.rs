@@ -0,0 +1,299 @@
+//! Reading count matrices back from disk
+//!
+//! Accepts the Matrix Market layout written by `sparc count` and Cell Ranger:
+//! `matrix.mtx`, `barcodes.tsv`, and `genes.tsv` (or `features.tsv`), each
+//! optionally gzipped. Feature fields after the type column (the gene metadata
+//! [`CountMatrix::write_features`] appends) are kept as text gene metadata
+//! named by field number: `field4`, `field5`, ...
+
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use super::{CountMatrix, MetadataColumn};
+use crate::{Error, Result};
+
+impl CountMatrix {
+    /// Read a Matrix Market directory, or a `matrix.mtx[.gz]` file with its
+    /// barcode and gene files alongside
+    pub fn read_mtx<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let (dir, mtx_path) = if path.is_dir() {
+            (path, find_file(path, &["matrix.mtx"])?)
+        } else {
+            (path.parent().unwrap_or(Path::new(".")), path.to_path_buf())
+        };
+        let barcodes = read_names(&find_file(dir, &["barcodes.tsv"])?)?;
+        let (genes, gene_names, extra_fields) =
+            read_features(&find_file(dir, &["features.tsv", "genes.tsv"])?)?;
+
+        let mut lines = open_text(&mtx_path)?.lines();
+        let header = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| Error::Matrix(format!("{:?} is empty", mtx_path)))?;
+        if !header.starts_with("%%MatrixMarket matrix coordinate") {
+            return Err(Error::Matrix(format!(
+                "{:?} is not a Matrix Market coordinate file",
+                mtx_path
+            )));
+        }
+
+        let mut matrix = CountMatrix::new();
+        let mut size: Option<(usize, usize)> = None;
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('%') {
+                continue;
+            }
+            let bad_line = || {
+                Error::Matrix(format!("{:?} line {}: malformed entry '{}'", mtx_path, i + 2, line))
+            };
+            let mut fields = line.split_whitespace();
+            let mut field = || fields.next().ok_or_else(bad_line);
+            let (a, b, c) = (field()?, field()?, field()?);
+
+            let Some((n_rows, n_cols)) = size else {
+                let parse = |s: &str| s.parse::<usize>().map_err(|_| bad_line());
+                let (n_rows, n_cols, nnz) = (parse(a)?, parse(b)?, parse(c)?);
+                if n_rows != genes.len() || n_cols != barcodes.len() {
+                    return Err(Error::Matrix(format!(
+                        "{:?} is {} x {} but there are {} genes and {} barcodes",
+                        mtx_path,
+                        n_rows,
+                        n_cols,
+                        genes.len(),
+                        barcodes.len()
+                    )));
+                }
+                matrix.rows.reserve(nnz);
+                matrix.cols.reserve(nnz);
+                matrix.values.reserve(nnz);
+                size = Some((n_rows, n_cols));
+                continue;
+            };
+
+            let row: usize = a.parse().map_err(|_| bad_line())?;
+            let col: usize = b.parse().map_err(|_| bad_line())?;
+            let value: f64 = c.parse().map_err(|_| bad_line())?;
+            if row == 0 || row > n_rows || col == 0 || col > n_cols {
+                return Err(bad_line());
+            }
+            matrix.rows.push(row - 1);
+            matrix.cols.push(col - 1);
+            matrix.values.push(integer_count(value).ok_or_else(bad_line)?);
+        }
+
+        matrix.n_rows = genes.len();
+        matrix.n_cols = barcodes.len();
+        matrix.genes = genes;
+        matrix.gene_names = gene_names;
+        matrix.barcodes = barcodes;
+        for (i, values) in extra_fields.into_iter().enumerate() {
+            matrix
+                .gene_metadata
+                .insert(format!("field{}", i + 4), MetadataColumn::Text(values));
+        }
+        matrix.validate()?;
+        Ok(matrix)
+    }
+
+    /// Feature types (third column of `features.tsv`, e.g. `Antibody Capture`)
+    /// for the matrix at `path`, in row order. Features without a type column,
+    /// including every row of a legacy `genes.tsv`, are `Gene Expression`.
+    pub fn read_feature_types<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+        let path = path.as_ref();
+        let dir = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(Path::new("."))
+        };
+        let mut types = Vec::new();
+        for line in open_text(&find_file(dir, &["features.tsv", "genes.tsv"])?)?.lines() {
+            let line = line?;
+            if line.split('\t').next().map_or(true, str::is_empty) {
+                continue;
+            }
+            let feature_type = line.split('\t').nth(2).unwrap_or(GENE_EXPRESSION);
+            types.push(feature_type.to_string());
+        }
+        Ok(types)
+    }
+}
+
+/// Default feature type for rows without one
+pub const GENE_EXPRESSION: &str = "Gene Expression";
+
+/// A count stored as a float, if it is a non-negative integer
+pub(crate) fn integer_count(value: f64) -> Option<u32> {
+    (value >= 0.0 && value.fract() == 0.0 && value <= u32::MAX as f64).then_some(value as u32)
+}
+
+/// First existing `<name>` or `<name>.gz` in `dir`
+fn find_file(dir: &Path, names: &[&str]) -> Result<PathBuf> {
+    names
+        .iter()
+        .flat_map(|name| [dir.join(name), dir.join(format!("{}.gz", name))])
+        .find(|p| p.is_file())
+        .ok_or_else(|| Error::Matrix(format!("no {} in {:?}", names.join(" or "), dir)))
+}
+
+fn open_text(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    if path.extension().map_or(false, |ext| ext == "gz") {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// First tab-separated column of every non-empty line
+fn read_names(path: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for line in open_text(path)?.lines() {
+        let line = line?;
+        if let Some(name) = line.split('\t').next().filter(|s| !s.is_empty()) {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Gene IDs and symbols from the first two columns of a features file. The
+/// symbols are empty when no line has a second column.
+/// Feature IDs, names (empty if the file has none), and the fields after the
+/// type column, one vector per field (blank where a row is shorter)
+fn read_features(path: &Path) -> Result<(Vec<String>, Vec<String>, Vec<Vec<String>>)> {
+    let (mut ids, mut names) = (Vec::new(), Vec::new());
+    let mut extra: Vec<Vec<String>> = Vec::new();
+    let mut has_names = false;
+    for line in open_text(path)?.lines() {
+        let line = line?;
+        let mut fields = line.split('\t');
+        let Some(id) = fields.next().filter(|s| !s.is_empty()) else {
+            continue;
+        };
+        let name = fields.next().filter(|s| !s.is_empty());
+        has_names |= name.is_some();
+        for (i, value) in fields.skip(1).enumerate() {
+            if i == extra.len() {
+                extra.push(vec![String::new(); ids.len()]);
+            }
+            extra[i].push(value.to_string());
+        }
+        ids.push(id.to_string());
+        names.push(name.unwrap_or(id).to_string());
+        for column in extra.iter_mut().filter(|c| c.len() < ids.len()) {
+            column.push(String::new());
+        }
+    }
+    if !has_names {
+        names.clear();
+    }
+    Ok((ids, names, extra))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn sample() -> CountMatrix {
+        CountMatrix::from_dense(
+            vec!["C1".to_string(), "C2".to_string(), "C3".to_string()],
+            vec!["G1".to_string(), "G2".to_string()],
+            vec![vec![10, 0, 4], vec![0, 8, 0]],
+        )
+    }
+
+    #[test]
+    fn test_read_mtx_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let matrix = sample();
+        matrix.write_mtx(dir.path().join("matrix.mtx")).unwrap();
+        matrix.write_barcodes(dir.path().join("barcodes.tsv")).unwrap();
+        matrix.write_genes(dir.path().join("genes.tsv")).unwrap();
+
+        let read = CountMatrix::read_mtx(dir.path()).unwrap();
+        assert_eq!(read.barcodes, matrix.barcodes);
+        assert_eq!(read.genes, matrix.genes);
+        assert_eq!((read.n_rows, read.n_cols), (2, 3));
+        assert_eq!(read.get(0, 2), 4);
+        assert_eq!(read.get(1, 1), 8);
+
+        let by_file = CountMatrix::read_mtx(dir.path().join("matrix.mtx")).unwrap();
+        assert_eq!(by_file.values, read.values);
+    }
+
+    #[test]
+    fn test_read_mtx_gzipped_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let matrix = sample();
+        matrix.write_mtx(dir.path().join("matrix.mtx.gz")).unwrap();
+        matrix.write_barcodes(dir.path().join("barcodes.tsv.gz")).unwrap();
+        matrix.write_genes(dir.path().join("genes.tsv.gz")).unwrap();
+
+        let read = CountMatrix::read_mtx(dir.path()).unwrap();
+        assert_eq!(read.barcodes, matrix.barcodes);
+        assert_eq!(read.genes, matrix.genes);
+        assert_eq!(read.values, matrix.values);
+    }
+
+    #[test]
+    fn test_read_mtx_gzipped_features() {
+        let dir = tempfile::tempdir().unwrap();
+        let write_gz = |name: &str, text: &str| {
+            let file = File::create(dir.path().join(name)).unwrap();
+            let mut gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            gz.write_all(text.as_bytes()).unwrap();
+            gz.finish().unwrap();
+        };
+        write_gz(
+            "matrix.mtx.gz",
+            "%%MatrixMarket matrix coordinate real general\n%\n2 1 2\n1 1 3.0\n2 1 1\n",
+        );
+        write_gz("barcodes.tsv.gz", "AAAC-1\n");
+        write_gz("features.tsv.gz", "ENSG1\tA\tGene Expression\nENSG2\tB\tGene Expression\n");
+
+        let read = CountMatrix::read_mtx(dir.path()).unwrap();
+        assert_eq!(read.genes, vec!["ENSG1", "ENSG2"]);
+        assert_eq!(read.gene_names, vec!["A", "B"]);
+        assert_eq!(read.values, vec![3, 1]);
+        let types = CountMatrix::read_feature_types(dir.path()).unwrap();
+        assert_eq!(types, vec![GENE_EXPRESSION, GENE_EXPRESSION]);
+        assert!(read.gene_metadata.is_empty());
+    }
+
+    #[test]
+    fn test_read_mtx_keeps_feature_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut matrix = sample();
+        let chromosomes = MetadataColumn::Text(vec!["chr1".into(), "chrM".into()]);
+        matrix.set_gene_metadata("chromosome", chromosomes).unwrap();
+        matrix.set_gene_metadata("length", MetadataColumn::Int(vec![1200, 800])).unwrap();
+        matrix.write_mtx(dir.path().join("matrix.mtx")).unwrap();
+        matrix.write_barcodes(dir.path().join("barcodes.tsv")).unwrap();
+        matrix.write_features(dir.path().join("features.tsv"), GENE_EXPRESSION).unwrap();
+
+        let read = CountMatrix::read_mtx(dir.path()).unwrap();
+        let field = |name| match read.gene_metadata.get(name) {
+            Some(MetadataColumn::Text(values)) => values.clone(),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(field("field4"), vec!["chr1", "chrM"]);
+        assert_eq!(field("field5"), vec!["1200", "800"]);
+    }
+
+    #[test]
+    fn test_read_mtx_rejects_mismatched_dims() {
+        let dir = tempfile::tempdir().unwrap();
+        let matrix = sample();
+        matrix.write_mtx(dir.path().join("matrix.mtx")).unwrap();
+        std::fs::write(dir.path().join("barcodes.tsv"), "C1\n").unwrap();
+        matrix.write_genes(dir.path().join("genes.tsv")).unwrap();
+
+        assert!(CountMatrix::read_mtx(dir.path()).is_err());
+    }
+}
@@ -1,20 +1,32 @@
 //! Count matrix generation
 
 use ahash::AHashMap;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
-use crate::Result;
+use super::Metadata;
+use crate::atomic::{AtomicFile, AtomicWriter};
+use crate::intern::Interner;
+use crate::resources::{ResourceConfig, SpillFile};
+use crate::{Error, Result};
 
 /// Sparse count matrix in COO format
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CountMatrix {
     /// Cell barcodes (column names)
     pub barcodes: Vec<String>,
-    /// Gene names/IDs (row names)
+    /// Gene IDs (row names), or symbols when no ID is known
     pub genes: Vec<String>,
+    /// Gene symbols parallel to `genes`; empty when rows have no separate symbol
+    #[serde(default)]
+    pub gene_names: Vec<String>,
     /// Row indices (gene indices)
     pub rows: Vec<usize>,
     /// Column indices (cell indices)
@@ -25,6 +37,12 @@ pub struct CountMatrix {
     pub n_rows: usize,
     /// Number of columns (cells)
     pub n_cols: usize,
+    /// Per-gene columns (chromosome, biotype, ...), parallel to `genes`
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub gene_metadata: Metadata,
+    /// Per-cell columns, parallel to `barcodes`
+    #[serde(default, skip_serializing_if = "Metadata::is_empty")]
+    pub cell_metadata: Metadata,
 }
 
 impl CountMatrix {
@@ -32,11 +50,14 @@ impl CountMatrix {
         Self {
             barcodes: Vec::new(),
             genes: Vec::new(),
+            gene_names: Vec::new(),
             rows: Vec::new(),
             cols: Vec::new(),
             values: Vec::new(),
             n_rows: 0,
             n_cols: 0,
+            gene_metadata: Metadata::new(),
+            cell_metadata: Metadata::new(),
         }
     }
 
@@ -67,9 +88,15 @@ impl CountMatrix {
             values,
             n_rows,
             n_cols,
+            ..Self::new()
         }
     }
 
+    /// Symbol of gene `row`, falling back to its ID
+    pub fn gene_name(&self, row: usize) -> &str {
+        self.gene_names.get(row).unwrap_or(&self.genes[row])
+    }
+
     /// Get count for a specific gene and cell
     pub fn get(&self, gene_idx: usize, cell_idx: usize) -> u32 {
         for (i, (&r, &c)) in self.rows.iter().zip(self.cols.iter()).enumerate() {
@@ -80,6 +107,84 @@ impl CountMatrix {
         0
     }
 
+    /// Row of `gene`, matched by ID first and then by symbol
+    pub fn gene_index(&self, gene: &str) -> Option<usize> {
+        self.genes
+            .iter()
+            .position(|g| g == gene)
+            .or_else(|| self.gene_names.iter().position(|g| g == gene))
+    }
+
+    /// Column of `barcode`
+    pub fn cell_index(&self, barcode: &str) -> Option<usize> {
+        self.barcodes.iter().position(|b| b == barcode)
+    }
+
+    /// Non-zero `(cell_idx, count)` pairs of `gene` (ID or symbol), by cell
+    pub fn gene_vector(&self, gene: &str) -> Option<Vec<(usize, u32)>> {
+        let row = self.gene_index(gene)?;
+        Some(self.select(&self.rows, &self.cols, row))
+    }
+
+    /// Non-zero `(gene_idx, count)` pairs of `barcode`, by gene
+    pub fn cell_vector(&self, barcode: &str) -> Option<Vec<(usize, u32)>> {
+        let col = self.cell_index(barcode)?;
+        Some(self.select(&self.cols, &self.rows, col))
+    }
+
+    /// `(minor, value)` of the entries whose `major` index is `at`, sorted
+    fn select(&self, major: &[usize], minor: &[usize], at: usize) -> Vec<(usize, u32)> {
+        let mut entries: Vec<(usize, u32)> = major
+            .iter()
+            .enumerate()
+            .filter(|&(_, &m)| m == at)
+            .map(|(i, _)| (minor[i], self.values[i]))
+            .collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    /// Every row (gene) in order with its non-zero `(cell_idx, count)` pairs.
+    /// Entries are grouped once up front; rows with no counts yield empty.
+    pub fn iter_rows(&self) -> impl Iterator<Item = (usize, Vec<(usize, u32)>)> + '_ {
+        self.grouped(&self.rows, &self.cols, self.n_rows)
+    }
+
+    /// Every column (cell) in order with its non-zero `(gene_idx, count)` pairs
+    pub fn iter_cols(&self) -> impl Iterator<Item = (usize, Vec<(usize, u32)>)> + '_ {
+        self.grouped(&self.cols, &self.rows, self.n_cols)
+    }
+
+    /// Counting-sort entry positions by `major`, then yield each group
+    fn grouped<'a>(
+        &'a self,
+        major: &'a [usize],
+        minor: &'a [usize],
+        n_major: usize,
+    ) -> impl Iterator<Item = (usize, Vec<(usize, u32)>)> + 'a {
+        let mut indptr = vec![0usize; n_major + 1];
+        for &m in major {
+            indptr[m + 1] += 1;
+        }
+        for i in 1..=n_major {
+            indptr[i] += indptr[i - 1];
+        }
+        let mut order = vec![0usize; major.len()];
+        let mut next = indptr.clone();
+        for (i, &m) in major.iter().enumerate() {
+            order[next[m]] = i;
+            next[m] += 1;
+        }
+        (0..n_major).map(move |m| {
+            let mut entries: Vec<(usize, u32)> = order[indptr[m]..indptr[m + 1]]
+                .iter()
+                .map(|&i| (minor[i], self.values[i]))
+                .collect();
+            entries.sort_unstable();
+            (m, entries)
+        })
+    }
+
     /// Get total counts per cell
     pub fn counts_per_cell(&self) -> Vec<u64> {
         let mut counts = vec![0u64; self.n_cols];
@@ -118,60 +223,207 @@ impl CountMatrix {
         cells.iter().map(|s| s.len() as u64).collect()
     }
 
-    /// Write to Matrix Market format
+    /// Keep only the given rows (genes/features), in the given order
+    pub fn subset_rows(&self, rows: &[usize]) -> CountMatrix {
+        let mut new_index = vec![None; self.n_rows];
+        for (new, &old) in rows.iter().enumerate() {
+            new_index[old] = Some(new);
+        }
+        let mut subset = CountMatrix {
+            barcodes: self.barcodes.clone(),
+            genes: rows.iter().map(|&r| self.genes[r].clone()).collect(),
+            gene_names: match self.gene_names.is_empty() {
+                true => Vec::new(),
+                false => rows.iter().map(|&r| self.gene_names[r].clone()).collect(),
+            },
+            n_rows: rows.len(),
+            n_cols: self.n_cols,
+            gene_metadata: self.gene_metadata.select(rows),
+            cell_metadata: self.cell_metadata.clone(),
+            ..CountMatrix::new()
+        };
+        for ((&r, &c), &v) in self.rows.iter().zip(&self.cols).zip(&self.values) {
+            if let Some(new) = new_index[r] {
+                subset.rows.push(new);
+                subset.cols.push(c);
+                subset.values.push(v);
+            }
+        }
+        subset
+    }
+
+    /// Keep only the given columns (cells), in the given order
+    pub fn subset_cols(&self, cols: &[usize]) -> CountMatrix {
+        let mut new_index = vec![None; self.n_cols];
+        for (new, &old) in cols.iter().enumerate() {
+            new_index[old] = Some(new);
+        }
+        let mut subset = CountMatrix {
+            barcodes: cols.iter().map(|&c| self.barcodes[c].clone()).collect(),
+            genes: self.genes.clone(),
+            gene_names: self.gene_names.clone(),
+            n_rows: self.n_rows,
+            n_cols: cols.len(),
+            gene_metadata: self.gene_metadata.clone(),
+            cell_metadata: self.cell_metadata.select(cols),
+            ..CountMatrix::new()
+        };
+        for ((&r, &c), &v) in self.rows.iter().zip(&self.cols).zip(&self.values) {
+            if let Some(new) = new_index[c] {
+                subset.rows.push(r);
+                subset.cols.push(new);
+                subset.values.push(v);
+            }
+        }
+        subset
+    }
+
+    /// Write to Matrix Market format, gzipped when the path ends in `.gz`.
+    ///
+    /// Entries are formatted (and compressed) in parallel chunks on the rayon
+    /// pool and appended in order; gzip chunks are separate members of one
+    /// valid gzip stream. The matrix is validated first.
     pub fn write_mtx<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let file = File::create(path)?;
-        let mut writer = BufWriter::new(file);
-
-        // Header
-        writeln!(writer, "%%MatrixMarket matrix coordinate integer general")?;
-        writeln!(writer, "%")?;
-        writeln!(
-            writer,
-            "{} {} {}",
+        self.validate()?;
+        let path = path.as_ref();
+        let gzip = is_gzip_path(path);
+        let mut writer = AtomicFile::create(path)?;
+
+        let header = format!(
+            "%%MatrixMarket matrix coordinate integer general\n%\n{} {} {}\n",
             self.n_rows,
             self.n_cols,
             self.values.len()
-        )?;
-
-        // Data (1-indexed)
-        for (i, ((&r, &c), &v)) in self
-            .rows
-            .iter()
-            .zip(self.cols.iter())
-            .zip(self.values.iter())
-            .enumerate()
-        {
-            if i > 0 {
-                writeln!(writer)?;
+        );
+        writer.write_all(&encode_text(header.into_bytes(), gzip)?)?;
+
+        // Bound buffered output to a few chunks per thread
+        let batch = MTX_CHUNK_ENTRIES * rayon::current_num_threads().max(1);
+        for batch_start in (0..self.values.len()).step_by(batch) {
+            let batch_end = (batch_start + batch).min(self.values.len());
+            let blocks = (batch_start..batch_end)
+                .step_by(MTX_CHUNK_ENTRIES)
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|start| {
+                    let end = (start + MTX_CHUNK_ENTRIES).min(batch_end);
+                    self.encode_mtx_entries(start..end, gzip)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            for block in blocks {
+                writer.write_all(&block)?;
             }
-            write!(writer, "{} {} {}", r + 1, c + 1, v)?;
         }
+        writer.commit()
+    }
 
-        Ok(())
+    /// Format a range of entries as 1-based `row col value` lines
+    fn encode_mtx_entries(&self, range: std::ops::Range<usize>, gzip: bool) -> Result<Vec<u8>> {
+        let mut text = Vec::with_capacity(range.len() * 16);
+        for i in range {
+            push_uint(&mut text, self.rows[i] as u64 + 1);
+            text.push(b' ');
+            push_uint(&mut text, self.cols[i] as u64 + 1);
+            text.push(b' ');
+            push_uint(&mut text, self.values[i] as u64);
+            text.push(b'\n');
+        }
+        encode_text(text, gzip)
     }
 
-    /// Write barcodes to file
+    /// Write barcodes to file, gzipped when the path ends in `.gz`
     pub fn write_barcodes<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let file = File::create(path)?;
-        let mut writer = BufWriter::new(file);
+        let mut writer = create_text(path.as_ref())?;
         for barcode in &self.barcodes {
             writeln!(writer, "{}", barcode)?;
         }
-        Ok(())
+        writer.commit()
     }
 
-    /// Write genes to file
+    /// Write `gene_id<TAB>gene_name` lines (Cell Ranger v2 `genes.tsv`),
+    /// gzipped when the path ends in `.gz`
     pub fn write_genes<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let file = File::create(path)?;
-        let mut writer = BufWriter::new(file);
-        for gene in &self.genes {
-            writeln!(writer, "{}\t{}", gene, gene)?; // gene_id, gene_name
+        let mut writer = create_text(path.as_ref())?;
+        for (row, gene) in self.genes.iter().enumerate() {
+            writeln!(writer, "{}\t{}", gene, self.gene_name(row))?;
         }
-        Ok(())
+        writer.commit()
+    }
+
+    /// Write `gene_id<TAB>gene_name<TAB>feature_type` lines (Cell Ranger v3
+    /// `features.tsv`), gzipped when the path ends in `.gz`. Gene metadata
+    /// columns follow as extra fields, which 10x readers ignore.
+    pub fn write_features<P: AsRef<Path>>(&self, path: P, feature_type: &str) -> Result<()> {
+        self.write_feature_rows(path.as_ref(), |_| feature_type)
+    }
+
+    /// [`write_features`](Self::write_features) with a feature type per row,
+    /// e.g. as read back by [`read_feature_types`](Self::read_feature_types)
+    pub fn write_features_typed<P: AsRef<Path>>(&self, path: P, types: &[String]) -> Result<()> {
+        if types.len() != self.n_rows {
+            return Err(Error::Matrix(format!(
+                "{} feature types for {} features",
+                types.len(),
+                self.n_rows
+            )));
+        }
+        self.write_feature_rows(path.as_ref(), |row| types[row].as_str())
+    }
+
+    fn write_feature_rows<'a>(
+        &self,
+        path: &Path,
+        feature_type: impl Fn(usize) -> &'a str,
+    ) -> Result<()> {
+        let mut writer = create_text(path)?;
+        for (row, gene) in self.genes.iter().enumerate() {
+            write!(writer, "{}\t{}\t{}", gene, self.gene_name(row), feature_type(row))?;
+            for (_, column) in self.gene_metadata.iter() {
+                write!(writer, "\t{}", column.format(row))?;
+            }
+            writeln!(writer)?;
+        }
+        writer.commit()
     }
 }
 
+/// Matrix Market entries formatted per parallel chunk
+const MTX_CHUNK_ENTRIES: usize = 1 << 18;
+
+fn is_gzip_path(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "gz")
+}
+
+/// Atomic text output, gzipped when the path ends in `.gz`
+pub(super) fn create_text(path: &Path) -> Result<AtomicWriter> {
+    AtomicWriter::create(path, is_gzip_path(path))
+}
+
+/// Text as-is, or as a standalone gzip member
+fn encode_text(text: Vec<u8>, gzip: bool) -> Result<Vec<u8>> {
+    if !gzip {
+        return Ok(text);
+    }
+    let mut encoder = GzEncoder::new(Vec::with_capacity(text.len() / 4), Compression::default());
+    encoder.write_all(&text)?;
+    Ok(encoder.finish()?)
+}
+
+/// Append the decimal digits of `n`
+fn push_uint(buf: &mut Vec<u8>, mut n: u64) {
+    let mut digits = [0u8; 20];
+    let mut i = digits.len();
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    buf.extend_from_slice(&digits[i..]);
+}
+
 impl Default for CountMatrix {
     fn default() -> Self {
         Self::new()
@@ -235,46 +487,180 @@ impl CountMatrix {
     }
 }
 
+/// (gene_id, cell_id) -> count
+type CountEntry = ((u32, u32), u32);
+
+/// Approximate bytes per in-memory count entry, including hash table overhead
+pub const COUNT_ENTRY_BYTES: u64 = 16;
+
+/// Disk spilling state for a counter with a memory budget
+struct SpillState {
+    config: ResourceConfig,
+    max_entries: usize,
+    /// Runs sorted by (gene_id, cell_id)
+    runs: Vec<SpillFile>,
+    /// First spill failure, reported by `try_build`
+    error: Option<crate::Error>,
+}
+
 /// Gene counter for building count matrix
 pub struct GeneCounter {
-    /// Barcode -> index mapping
-    barcode_index: AHashMap<String, usize>,
-    /// Gene -> index mapping
-    gene_index: AHashMap<String, usize>,
-    /// Counts: (gene_idx, cell_idx) -> count
-    counts: AHashMap<(usize, usize), u32>,
-    /// Barcodes in order
-    barcodes: Vec<String>,
-    /// Genes in order
-    genes: Vec<String>,
+    /// Barcode IDs, which become matrix columns
+    barcodes: Interner,
+    /// Gene IDs, which become matrix rows
+    genes: Interner,
+    /// Counts: (gene_id, cell_id) -> count
+    counts: AHashMap<(u32, u32), u32>,
+    /// Gene ID -> symbol, for rows whose symbol differs from the ID
+    gene_names: AHashMap<String, String>,
+    /// Set when counts spill to disk past a memory budget
+    spill: Option<SpillState>,
 }
 
 impl GeneCounter {
     pub fn new() -> Self {
         Self {
-            barcode_index: AHashMap::new(),
-            gene_index: AHashMap::new(),
+            barcodes: Interner::new(),
+            genes: Interner::new(),
             counts: AHashMap::new(),
-            barcodes: Vec::new(),
-            genes: Vec::new(),
+            gene_names: AHashMap::new(),
+            spill: None,
         }
     }
 
+    /// Counter that spills sorted runs to `config.tmp_dir` whenever the count
+    /// table would exceed `config.max_memory` (purely in-memory without a budget)
+    pub fn with_resources(config: &ResourceConfig) -> Self {
+        let mut counter = Self::new();
+        counter.spill = config.max_memory.map(|bytes| SpillState {
+            config: config.clone(),
+            max_entries: (bytes / COUNT_ENTRY_BYTES).max(1) as usize,
+            runs: Vec::new(),
+            error: None,
+        });
+        counter
+    }
+
     /// Add a count for a barcode-gene pair
     pub fn add_count(&mut self, barcode: &str, gene: &str, count: u32) {
-        let cell_idx = *self.barcode_index.entry(barcode.to_string()).or_insert_with(|| {
-            let idx = self.barcodes.len();
-            self.barcodes.push(barcode.to_string());
-            idx
-        });
+        let cell_id = self.barcodes.intern(barcode);
+        let gene_id = self.genes.intern(gene);
+        self.add_entry(gene_id, cell_id, count);
+    }
+
+    /// ID of `barcode` for [`GeneCounter::add_batch`], assigned on first use
+    pub fn barcode_id(&mut self, barcode: &str) -> u32 {
+        self.barcodes.intern(barcode)
+    }
 
-        let gene_idx = *self.gene_index.entry(gene.to_string()).or_insert_with(|| {
-            let idx = self.genes.len();
-            self.genes.push(gene.to_string());
-            idx
+    /// ID of `gene` for [`GeneCounter::add_batch`], assigned on first use
+    pub fn gene_id(&mut self, gene: &str) -> u32 {
+        self.genes.intern(gene)
+    }
+
+    /// Add `(barcode_id, gene_id, count)` entries, with IDs from
+    /// [`GeneCounter::barcode_id`] and [`GeneCounter::gene_id`]
+    pub fn add_batch(&mut self, entries: &[(u32, u32, u32)]) {
+        self.counts.reserve(entries.len());
+        for &(cell_id, gene_id, count) in entries {
+            debug_assert!((cell_id as usize) < self.barcodes.len(), "unknown barcode ID");
+            debug_assert!((gene_id as usize) < self.genes.len(), "unknown gene ID");
+            self.add_entry(gene_id, cell_id, count);
+        }
+    }
+
+    /// Add another counter's counts and gene symbols to this one. Barcodes and
+    /// genes new to this counter follow its own in `other`'s order.
+    pub fn merge(&mut self, mut other: GeneCounter) -> Result<()> {
+        let cell_ids: Vec<u32> = other.barcodes.iter().map(|b| self.barcodes.intern(b)).collect();
+        let gene_ids: Vec<u32> = other.genes.iter().map(|g| self.genes.intern(g)).collect();
+        for (gene, name) in other.gene_names.drain() {
+            self.gene_names.entry(gene).or_insert(name);
+        }
+
+        for ((gene_id, cell_id), count) in other.counts.drain() {
+            self.add_entry(gene_ids[gene_id as usize], cell_ids[cell_id as usize], count);
+        }
+        if let Some(spill) = other.spill.take() {
+            if let Some(e) = spill.error {
+                return Err(e);
+            }
+            for run in &spill.runs {
+                for entry in RunReader::open(run.path())? {
+                    let ((gene_id, cell_id), count) = entry?;
+                    self.add_entry(gene_ids[gene_id as usize], cell_ids[cell_id as usize], count);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_entry(&mut self, gene_id: u32, cell_id: u32, count: u32) {
+        *self.counts.entry((gene_id, cell_id)).or_insert(0) += count;
+
+        if let Some(spill) = &self.spill {
+            if self.counts.len() >= spill.max_entries && spill.error.is_none() {
+                self.spill_counts();
+            }
+        }
+    }
+
+    /// Move the in-memory counts to a sorted run on disk
+    fn spill_counts(&mut self) {
+        let Some(spill) = self.spill.as_mut() else {
+            return;
+        };
+        let mut entries: Vec<CountEntry> = self.counts.drain().collect();
+        entries.sort_unstable_by_key(|&(key, _)| key);
+        match write_run(&spill.config, &entries) {
+            Ok(run) => {
+                log::debug!("Spilled {} count entries to {:?}", entries.len(), run.path());
+                spill.runs.push(run);
+            }
+            Err(e) => {
+                log::warn!("Count spilling failed, continuing in memory: {}", e);
+                spill.error = Some(e);
+                self.counts.extend(entries);
+            }
+        }
+    }
+
+    /// Assign IDs to names before their counts arrive, fixing matrix order
+    pub(crate) fn register_names<'a>(
+        &mut self,
+        barcodes: impl Iterator<Item = &'a str>,
+        genes: impl Iterator<Item = &'a str>,
+    ) {
+        barcodes.for_each(|b| {
+            self.barcodes.intern(b);
+        });
+        genes.for_each(|g| {
+            self.genes.intern(g);
         });
+    }
+
+    /// Record the symbol written beside `gene` (its ID) in the features file.
+    /// The first symbol recorded for a gene is kept.
+    pub fn set_gene_name(&mut self, gene: &str, name: &str) {
+        if gene != name && !self.gene_names.contains_key(gene) {
+            self.gene_names.insert(gene.to_string(), name.to_string());
+        }
+    }
+
+    /// Symbols parallel to `genes`, or empty when none were recorded
+    fn gene_names_for(&self, genes: &[String]) -> Vec<String> {
+        if self.gene_names.is_empty() {
+            return Vec::new();
+        }
+        genes
+            .iter()
+            .map(|g| self.gene_names.get(g).unwrap_or(g).clone())
+            .collect()
+    }
 
-        *self.counts.entry((gene_idx, cell_idx)).or_insert(0) += count;
+    /// Number of runs spilled to disk so far
+    pub fn num_spilled_runs(&self) -> usize {
+        self.spill.as_ref().map_or(0, |s| s.runs.len())
     }
 
     /// Increment count by 1
@@ -282,35 +668,112 @@ impl GeneCounter {
         self.add_count(barcode, gene, 1);
     }
 
-    /// Build the count matrix
+    /// Build the count matrix.
+    ///
+    /// Panics if spilled runs cannot be read back; use `try_build` for
+    /// counters created with a memory budget.
     pub fn build(self) -> CountMatrix {
+        self.try_build().expect("failed to merge spilled count runs")
+    }
+
+    /// Build the count matrix, merging any runs spilled to disk
+    pub fn try_build(mut self) -> Result<CountMatrix> {
+        let Some(spill) = self.spill.take() else {
+            return Ok(self.build_in_memory());
+        };
+        if let Some(e) = spill.error {
+            return Err(e);
+        }
+        if spill.runs.is_empty() {
+            return Ok(self.build_in_memory());
+        }
+        log::info!(
+            "Merging {} spilled count runs: {} genes x {} cells",
+            spill.runs.len(),
+            self.genes.len(),
+            self.barcodes.len()
+        );
+
+        let mut memory: Vec<CountEntry> = self.counts.drain().collect();
+        memory.sort_unstable_by_key(|&(key, _)| key);
+        let mut sources: Vec<Box<dyn Iterator<Item = Result<CountEntry>>>> =
+            vec![Box::new(memory.into_iter().map(Ok))];
+        for run in &spill.runs {
+            sources.push(Box::new(RunReader::open(run.path())?));
+        }
+
+        let mut heap = BinaryHeap::new();
+        for (i, source) in sources.iter_mut().enumerate() {
+            if let Some(entry) = source.next() {
+                let (key, count) = entry?;
+                heap.push(Reverse((key, count, i)));
+            }
+        }
+
+        // K-way merge; equal keys from different runs are summed
+        let (mut rows, mut cols, mut values): (Vec<usize>, Vec<usize>, Vec<u32>) =
+            (Vec::new(), Vec::new(), Vec::new());
+        while let Some(Reverse(((gene_id, cell_id), count, i))) = heap.pop() {
+            let (gene_idx, cell_idx) = (gene_id as usize, cell_id as usize);
+            if rows.last() == Some(&gene_idx) && cols.last() == Some(&cell_idx) {
+                *values.last_mut().expect("values parallel to rows") += count;
+            } else {
+                rows.push(gene_idx);
+                cols.push(cell_idx);
+                values.push(count);
+            }
+            if let Some(entry) = sources[i].next() {
+                let (key, count) = entry?;
+                heap.push(Reverse((key, count, i)));
+            }
+        }
+
+        let genes = std::mem::take(&mut self.genes).into_names();
+        Ok(CountMatrix {
+            n_rows: genes.len(),
+            n_cols: self.barcodes.len(),
+            gene_names: self.gene_names_for(&genes),
+            barcodes: self.barcodes.into_names(),
+            genes,
+            rows,
+            cols,
+            values,
+            ..CountMatrix::new()
+        })
+    }
+
+    fn build_in_memory(mut self) -> CountMatrix {
         log::info!(
             "Building count matrix: {} genes x {} cells ({} entries)",
             self.genes.len(),
             self.barcodes.len(),
             self.counts.len()
         );
-        let n_rows = self.genes.len();
+        let genes = std::mem::take(&mut self.genes).into_names();
+        let gene_names = self.gene_names_for(&genes);
+        let n_rows = genes.len();
         let n_cols = self.barcodes.len();
 
         let mut rows = Vec::with_capacity(self.counts.len());
         let mut cols = Vec::with_capacity(self.counts.len());
         let mut values = Vec::with_capacity(self.counts.len());
 
-        for ((gene_idx, cell_idx), count) in self.counts {
-            rows.push(gene_idx);
-            cols.push(cell_idx);
+        for ((gene_id, cell_id), count) in self.counts {
+            rows.push(gene_id as usize);
+            cols.push(cell_id as usize);
             values.push(count);
         }
 
         CountMatrix {
-            barcodes: self.barcodes,
-            genes: self.genes,
+            barcodes: self.barcodes.into_names(),
+            genes,
+            gene_names,
             rows,
             cols,
             values,
             n_rows,
             n_cols,
+            ..CountMatrix::new()
         }
     }
 
@@ -331,10 +794,69 @@ impl Default for GeneCounter {
     }
 }
 
+/// Write sorted entries as little-endian (gene u32, cell u32, count u32) records
+fn write_run(config: &ResourceConfig, entries: &[CountEntry]) -> Result<SpillFile> {
+    let (run, file) = SpillFile::create(config, "counts")?;
+    let mut writer = BufWriter::new(file);
+    for &((gene_id, cell_id), count) in entries {
+        writer.write_all(&gene_id.to_le_bytes())?;
+        writer.write_all(&cell_id.to_le_bytes())?;
+        writer.write_all(&count.to_le_bytes())?;
+    }
+    writer.flush()?;
+    Ok(run)
+}
+
+/// Sequential reader over one spilled run
+struct RunReader {
+    reader: BufReader<File>,
+}
+
+impl RunReader {
+    fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(File::open(path)?),
+        })
+    }
+}
+
+impl Iterator for RunReader {
+    type Item = Result<CountEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = [0u8; 12];
+        match self.reader.read_exact(&mut buf) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e.into())),
+        }
+        let word = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        Some(Ok(((word(0), word(4)), word(8))))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_mtx_chunks_concatenate() {
+        let matrix = CountMatrix::from_dense(
+            vec!["C1".to_string(), "C2".to_string()],
+            vec!["G1".to_string(), "G2".to_string()],
+            vec![vec![10, 0], vec![1234567, 8]],
+        );
+        let whole = matrix.encode_mtx_entries(0..3, false).unwrap();
+        assert_eq!(String::from_utf8(whole.clone()).unwrap(), "1 1 10\n2 1 1234567\n2 2 8\n");
+
+        // Separately gzipped chunks decode to the same text
+        let mut gz = matrix.encode_mtx_entries(0..1, true).unwrap();
+        gz.extend(matrix.encode_mtx_entries(1..3, true).unwrap());
+        let mut text = Vec::new();
+        flate2::read::MultiGzDecoder::new(&gz[..]).read_to_end(&mut text).unwrap();
+        assert_eq!(text, whole);
+    }
+
     #[test]
     fn test_gene_counter() {
         let mut counter = GeneCounter::new();
@@ -351,6 +873,109 @@ mod tests {
         assert_eq!(matrix.values.len(), 3);
     }
 
+    #[test]
+    fn test_add_batch_and_merge() {
+        let mut a = GeneCounter::new();
+        let (c1, g1) = (a.barcode_id("CELL1"), a.gene_id("GENE1"));
+        a.add_batch(&[(c1, g1, 2), (c1, g1, 1)]);
+
+        let mut b = GeneCounter::new();
+        let (c2, g2) = (b.barcode_id("CELL2"), b.gene_id("GENE2"));
+        let c1_in_b = b.barcode_id("CELL1");
+        b.add_batch(&[(c2, g2, 4), (c1_in_b, g2, 1)]);
+        b.increment("CELL1", "GENE1");
+        b.set_gene_name("GENE2", "Beta");
+
+        a.merge(b).unwrap();
+        let matrix = a.build();
+        assert_eq!(matrix.barcodes, vec!["CELL1", "CELL2"]);
+        assert_eq!(matrix.genes, vec!["GENE1", "GENE2"]);
+        assert_eq!(matrix.get(0, 0), 4);
+        assert_eq!(matrix.get(1, 0), 1);
+        assert_eq!(matrix.get(1, 1), 4);
+        assert_eq!(matrix.gene_name(1), "Beta");
+    }
+
+    #[test]
+    fn test_row_and_col_accessors() {
+        let mut matrix = CountMatrix::from_dense(
+            vec!["C1".to_string(), "C2".to_string(), "C3".to_string()],
+            vec!["ENSG1".to_string(), "ENSG2".to_string()],
+            vec![vec![5, 0, 2], vec![0, 0, 7]],
+        );
+        matrix.gene_names = vec!["ACTB".to_string(), "CD3E".to_string()];
+
+        assert_eq!(matrix.gene_vector("ENSG1"), Some(vec![(0, 5), (2, 2)]));
+        assert_eq!(matrix.gene_vector("CD3E"), Some(vec![(2, 7)]));
+        assert_eq!(matrix.gene_vector("MISSING"), None);
+        assert_eq!(matrix.cell_vector("C3"), Some(vec![(0, 2), (1, 7)]));
+        assert_eq!(matrix.cell_vector("C2"), Some(vec![]));
+
+        let rows: Vec<_> = matrix.iter_rows().collect();
+        assert_eq!(rows, vec![(0, vec![(0, 5), (2, 2)]), (1, vec![(2, 7)])]);
+        let cols: Vec<_> = matrix.iter_cols().map(|(_, e)| e.len()).collect();
+        assert_eq!(cols, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_gene_ids_and_names_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut counter = GeneCounter::new();
+        counter.increment("CELL1", "ENSG01");
+        counter.increment("CELL1", "ENSG02");
+        counter.set_gene_name("ENSG01", "ACTB");
+        counter.set_gene_name("ENSG01", "ignored");
+        let matrix = counter.build();
+        assert_eq!(matrix.gene_names, vec!["ACTB", "ENSG02"]);
+
+        let genes = dir.path().join("genes.tsv");
+        matrix.write_genes(&genes).unwrap();
+        let text = std::fs::read_to_string(&genes).unwrap();
+        assert_eq!(text, "ENSG01\tACTB\nENSG02\tENSG02\n");
+
+        let features = dir.path().join("features.tsv");
+        matrix.write_features(&features, "Gene Expression").unwrap();
+        let text = std::fs::read_to_string(&features).unwrap();
+        assert!(text.starts_with("ENSG01\tACTB\tGene Expression\n"));
+    }
+
+    #[test]
+    fn test_gene_counter_spills_to_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ResourceConfig {
+            tmp_dir: dir.path().to_path_buf(),
+            max_memory: Some(2 * COUNT_ENTRY_BYTES),
+            ..ResourceConfig::default()
+        };
+        let mut spilling = GeneCounter::with_resources(&config);
+        let mut in_memory = GeneCounter::new();
+        let pairs = [
+            ("C1", "G1"),
+            ("C2", "G1"),
+            ("C1", "G2"),
+            ("C1", "G1"),
+            ("C3", "G2"),
+            ("C2", "G1"),
+        ];
+        for (cell, gene) in pairs {
+            spilling.increment(cell, gene);
+            in_memory.increment(cell, gene);
+        }
+        assert!(spilling.num_spilled_runs() > 0);
+
+        let merged = spilling.try_build().unwrap();
+        let expected = in_memory.build();
+        assert_eq!(merged.values.len(), expected.values.len());
+        for gene in 0..expected.n_rows {
+            for cell in 0..expected.n_cols {
+                assert_eq!(merged.get(gene, cell), expected.get(gene, cell));
+            }
+        }
+        assert_eq!(merged.values.iter().sum::<u32>(), 6);
+        // Spill files are removed once merged
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
     #[test]
     fn test_count_matrix_stats() {
         let barcodes = vec!["CELL1".to_string(), "CELL2".to_string()];
@@ -365,4 +990,22 @@ mod tests {
         let counts_per_gene = matrix.counts_per_gene();
         assert_eq!(counts_per_gene, vec![15, 11]);
     }
+
+    #[test]
+    fn test_subset_rows_and_cols() {
+        let matrix = CountMatrix::from_dense(
+            vec!["C1".to_string(), "C2".to_string(), "C3".to_string()],
+            vec!["G1".to_string(), "G2".to_string()],
+            vec![vec![1, 0, 2], vec![0, 3, 4]],
+        );
+
+        let g2 = matrix.subset_rows(&[1]);
+        assert_eq!(g2.genes, vec!["G2"]);
+        assert_eq!(g2.counts_per_cell(), vec![0, 3, 4]);
+
+        let cells = matrix.subset_cols(&[2, 0]);
+        assert_eq!(cells.barcodes, vec!["C3", "C1"]);
+        assert_eq!(cells.get(1, 0), 4);
+        assert_eq!(cells.counts_per_cell(), vec![6, 1]);
+    }
 }
@@ -0,0 +1,241 @@
+//! Per-gene and per-cell metadata carried by a count matrix
+//!
+//! Columns are named and parallel to the matrix rows (genes) or columns
+//! (cells); they follow the matrix through subsetting and become `var`/`obs`
+//! columns in AnnData output. [`CountMatrix::annotate_genes`] fills the
+//! standard gene columns from a GTF annotation.
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+
+use super::{CountMatrix, UNKNOWN_BIOTYPE};
+use crate::annotation::{Gene, GeneAnnotation};
+use crate::{Error, Result};
+
+/// Gene column: chromosome of the gene (empty when unannotated)
+pub const GENE_CHROMOSOME: &str = "chromosome";
+/// Gene column: GTF `gene_type`/`gene_biotype`
+pub const GENE_BIOTYPE: &str = "biotype";
+/// Gene column: exonic length in bases (0 when unannotated)
+pub const GENE_LENGTH: &str = "length";
+
+/// Values of one metadata column
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "values", rename_all = "lowercase")]
+pub enum MetadataColumn {
+    Text(Vec<String>),
+    Int(Vec<i64>),
+    Float(Vec<f64>),
+}
+
+impl MetadataColumn {
+    pub fn len(&self) -> usize {
+        match self {
+            MetadataColumn::Text(v) => v.len(),
+            MetadataColumn::Int(v) => v.len(),
+            MetadataColumn::Float(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Value `i` as text, as written to TSV files
+    pub fn format(&self, i: usize) -> String {
+        match self {
+            MetadataColumn::Text(v) => v[i].clone(),
+            MetadataColumn::Int(v) => v[i].to_string(),
+            MetadataColumn::Float(v) => v[i].to_string(),
+        }
+    }
+
+    /// The values at `indices`, in that order
+    pub fn select(&self, indices: &[usize]) -> Self {
+        match self {
+            MetadataColumn::Text(v) => {
+                MetadataColumn::Text(indices.iter().map(|&i| v[i].clone()).collect())
+            }
+            MetadataColumn::Int(v) => MetadataColumn::Int(indices.iter().map(|&i| v[i]).collect()),
+            MetadataColumn::Float(v) => {
+                MetadataColumn::Float(indices.iter().map(|&i| v[i]).collect())
+            }
+        }
+    }
+}
+
+/// Named metadata columns, in insertion order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Metadata {
+    columns: Vec<(String, MetadataColumn)>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of columns
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Column `name`, if present
+    pub fn get(&self, name: &str) -> Option<&MetadataColumn> {
+        self.columns.iter().find(|(n, _)| n == name).map(|(_, c)| c)
+    }
+
+    /// Add column `name`, replacing any column of that name in place
+    pub fn insert(&mut self, name: impl Into<String>, column: MetadataColumn) {
+        let name = name.into();
+        match self.columns.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => *existing = column,
+            None => self.columns.push((name, column)),
+        }
+    }
+
+    /// Remove and return column `name`
+    pub fn remove(&mut self, name: &str) -> Option<MetadataColumn> {
+        let i = self.columns.iter().position(|(n, _)| n == name)?;
+        Some(self.columns.remove(i).1)
+    }
+
+    /// `(name, column)` pairs in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MetadataColumn)> {
+        self.columns.iter().map(|(n, c)| (n.as_str(), c))
+    }
+
+    /// Every column restricted to `indices`, in that order
+    pub fn select(&self, indices: &[usize]) -> Self {
+        Self {
+            columns: self
+                .columns
+                .iter()
+                .map(|(n, c)| (n.clone(), c.select(indices)))
+                .collect(),
+        }
+    }
+}
+
+impl CountMatrix {
+    /// Add (or replace) a gene metadata column; it must have one value per row
+    pub fn set_gene_metadata(&mut self, name: &str, column: MetadataColumn) -> Result<()> {
+        check_length("gene", name, column.len(), self.n_rows)?;
+        self.gene_metadata.insert(name, column);
+        Ok(())
+    }
+
+    /// Add (or replace) a cell metadata column; it must have one value per
+    /// column
+    pub fn set_cell_metadata(&mut self, name: &str, column: MetadataColumn) -> Result<()> {
+        check_length("cell", name, column.len(), self.n_cols)?;
+        self.cell_metadata.insert(name, column);
+        Ok(())
+    }
+
+    /// Fill the chromosome, biotype, and length gene columns (and symbols,
+    /// when the matrix has none) from `annotation`. Rows are matched by gene
+    /// ID, then by symbol; unmatched rows get an empty chromosome, the
+    /// `unknown` biotype, and length 0. Returns the number of matched rows.
+    pub fn annotate_genes(&mut self, annotation: &GeneAnnotation) -> usize {
+        let mut by_name: AHashMap<&str, &Gene> = AHashMap::new();
+        for gene in annotation.genes() {
+            by_name.entry(gene.name.as_str()).or_insert(gene);
+        }
+        for gene in annotation.genes() {
+            by_name.insert(gene.id.as_str(), gene);
+        }
+        let matched: Vec<Option<&Gene>> =
+            self.genes.iter().map(|g| by_name.get(g.as_str()).copied()).collect();
+
+        let chromosomes = matched.iter().map(|g| g.map_or("", |g| g.chrom.as_str()).to_string());
+        let biotypes = matched
+            .iter()
+            .map(|g| g.and_then(|g| g.biotype.as_deref()).unwrap_or(UNKNOWN_BIOTYPE).to_string());
+        let lengths = matched.iter().map(|g| g.map_or(0, exonic_length));
+        self.gene_metadata.insert(GENE_CHROMOSOME, MetadataColumn::Text(chromosomes.collect()));
+        self.gene_metadata.insert(GENE_BIOTYPE, MetadataColumn::Text(biotypes.collect()));
+        self.gene_metadata.insert(GENE_LENGTH, MetadataColumn::Int(lengths.collect()));
+        if self.gene_names.is_empty() {
+            self.gene_names = matched
+                .iter()
+                .zip(&self.genes)
+                .map(|(g, id)| g.map_or(id, |g| &g.name).clone())
+                .collect();
+        }
+        matched.iter().filter(|g| g.is_some()).count()
+    }
+}
+
+/// Bases covered by the gene's exons, or its whole span without exons
+fn exonic_length(gene: &Gene) -> i64 {
+    match gene.exons.is_empty() {
+        true => gene.end - gene.start,
+        false => gene.exons.iter().map(|&(s, e)| e - s).sum(),
+    }
+}
+
+fn check_length(axis: &str, name: &str, found: usize, expected: usize) -> Result<()> {
+    if found != expected {
+        return Err(Error::Matrix(format!(
+            "{} metadata '{}' has {} values for {} {}s",
+            axis, name, found, expected, axis
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intervals::Strand;
+
+    fn gene(id: &str, name: &str, exons: Vec<(i64, i64)>) -> Gene {
+        Gene {
+            id: id.to_string(),
+            name: name.to_string(),
+            biotype: Some("protein_coding".to_string()),
+            chrom: "chr2".to_string(),
+            start: 100,
+            end: 1000,
+            strand: Strand::Forward,
+            exons,
+        }
+    }
+
+    #[test]
+    fn test_annotate_and_subset_genes() {
+        let mut matrix = CountMatrix::from_dense(
+            vec!["C1".to_string(), "C2".to_string()],
+            vec!["ENSG1".to_string(), "CD3E".to_string(), "novel".to_string()],
+            vec![vec![1, 0], vec![0, 2], vec![3, 0]],
+        );
+        let annotation = GeneAnnotation::from_genes(vec![
+            gene("ENSG1", "ACTB", vec![(100, 200), (300, 350)]),
+            gene("ENSG2", "CD3E", Vec::new()),
+        ]);
+        assert_eq!(matrix.annotate_genes(&annotation), 2);
+        assert_eq!(matrix.gene_names, ["ACTB", "CD3E", "novel"]);
+        let lengths = MetadataColumn::Int(vec![150, 900, 0]);
+        assert_eq!(matrix.gene_metadata.get(GENE_LENGTH), Some(&lengths));
+        assert_eq!(matrix.gene_metadata.get(GENE_BIOTYPE).unwrap().format(2), UNKNOWN_BIOTYPE);
+
+        let labels = MetadataColumn::Text(vec!["T".to_string(), "B".to_string()]);
+        matrix.set_cell_metadata("cell_type", labels).unwrap();
+        assert!(matrix.set_cell_metadata("score", MetadataColumn::Float(vec![0.5])).is_err());
+
+        let subset = matrix.subset_rows(&[2, 0]).subset_cols(&[1]);
+        assert_eq!(subset.gene_metadata.get(GENE_CHROMOSOME).unwrap().format(0), "");
+        assert_eq!(subset.gene_metadata.get(GENE_CHROMOSOME).unwrap().format(1), "chr2");
+        assert_eq!(
+            subset.cell_metadata.get("cell_type"),
+            Some(&MetadataColumn::Text(vec!["B".to_string()]))
+        );
+        assert!(subset.validate().is_ok());
+    }
+}
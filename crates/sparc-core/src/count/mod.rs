@@ -1,5 +1,25 @@
 //! Gene counting and count matrix module
 
+mod biotype;
+mod cell_stats;
+mod duplicates;
+mod em;
+#[cfg(feature = "h5ad")]
+mod h5ad;
+mod io;
 mod matrix;
+mod metadata;
+mod molecules;
+mod transcripts;
+mod validate;
 
-pub use matrix::{CountMatrix, CsrMatrix, GeneCounter};
+pub use biotype::{BiotypeFilter, GeneBiotypes, UNKNOWN_BIOTYPE};
+pub use cell_stats::{CellStat, CellStats};
+pub use duplicates::DuplicateMarker;
+pub use em::{EmRounding, EmStats, MultiGeneCounter};
+pub use io::GENE_EXPRESSION;
+pub use matrix::{CountMatrix, CsrMatrix, GeneCounter, COUNT_ENTRY_BYTES};
+pub use metadata::{Metadata, MetadataColumn, GENE_BIOTYPE, GENE_CHROMOSOME, GENE_LENGTH};
+pub use molecules::{DedupStats, MoleculeCounter};
+pub use transcripts::{TRANSCRIPT_GENE_ID, TRANSCRIPT_GENE_NAME};
+pub use validate::MatrixIssue;
@@ -0,0 +1,329 @@
+//! UMI-aware counting
+//!
+//! Reads are grouped by (cell, gene) with per-UMI read counts. `finish`
+//! deduplicates each group's UMIs and feeds the molecule counts into a
+//! [`GeneCounter`], so PCR duplicates count once. Molecules backed by fewer
+//! reads than [`MoleculeCounter::with_min_reads`] asks for are discarded.
+//! UMIs containing `N` are kept, dropped, or corrected per [`UmiNPolicy`].
+//!
+//! With [`MoleculeCounter::with_position_bins`], a group is further split by
+//! the read's 5' alignment position, so the same UMI at two distinct priming
+//! sites of a gene (e.g. on different isoforms) counts as two molecules.
+
+use ahash::AHashMap;
+use rayon::prelude::*;
+
+use super::GeneCounter;
+use crate::intern::Interner;
+use crate::umi::{Umi, UmiDeduplicator, UmiNPolicy};
+
+/// Read and molecule totals after deduplication
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    /// Reads with a cell, gene, and UMI
+    pub reads: u64,
+    /// Distinct molecules after UMI deduplication
+    pub molecules: u64,
+    /// Reads whose UMI contained an `N`
+    pub n_umi_reads: u64,
+    /// Of those, reads resolved to another UMI of their cell and gene
+    pub n_umi_corrected: u64,
+    /// Of those, reads not counted (dropped, or left uncorrected)
+    pub n_umi_dropped: u64,
+    /// Molecules discarded for too few supporting reads
+    pub low_evidence_molecules: u64,
+}
+
+impl DedupStats {
+    /// Fraction of reads that duplicate an earlier molecule
+    pub fn duplication_rate(&self) -> f64 {
+        if self.reads == 0 {
+            return 0.0;
+        }
+        1.0 - self.molecules as f64 / self.reads as f64
+    }
+}
+
+/// Position bin of reads without a position, or of every read when not binning
+const NO_BIN: u32 = u32::MAX;
+
+/// (gene_id, cell_id, position bin)
+type GroupKey = (u32, u32, u32);
+
+/// Collects reads per (cell, gene, UMI) for molecule counting
+#[derive(Debug, Default)]
+pub struct MoleculeCounter {
+    barcodes: Interner,
+    genes: Interner,
+    umis: Interner,
+    /// (gene_id, cell_id, bin) -> UMI id -> reads
+    groups: AHashMap<GroupKey, AHashMap<u32, u32>>,
+    reads: u64,
+    n_policy: UmiNPolicy,
+    /// Width in bp of the position bins, when binning
+    bin_width: Option<u32>,
+    /// Reads a molecule needs, after UMI correction, to be counted
+    min_reads: u32,
+    /// Reads with an `N` UMI awaiting correction: (group, UMI id)
+    n_pending: Vec<(GroupKey, u32)>,
+    n_umi_reads: u64,
+    n_umi_dropped: u64,
+}
+
+impl MoleculeCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle UMIs containing `N` per `policy` (default: keep)
+    pub fn with_n_policy(mut self, policy: UmiNPolicy) -> Self {
+        self.n_policy = policy;
+        self
+    }
+
+    /// Discard molecules supported by fewer than `min_reads` reads once their
+    /// UMIs are clustered (0 or 1 keeps every molecule)
+    pub fn with_min_reads(mut self, min_reads: u32) -> Self {
+        self.min_reads = min_reads;
+        self
+    }
+
+    /// Deduplicate UMIs separately within `width`-bp bins of 5' alignment
+    /// position (0 keeps one group per cell and gene)
+    pub fn with_position_bins(mut self, width: u32) -> Self {
+        self.bin_width = (width > 0).then_some(width);
+        self
+    }
+
+    /// Record one read; false if it was dropped for an `N` in its UMI
+    pub fn add_read(&mut self, barcode: &str, gene: &str, umi: &str) -> bool {
+        self.add_read_at(barcode, gene, umi, None)
+    }
+
+    /// Record one read whose 5' end aligns at `position` (0-based), binned
+    /// when [`with_position_bins`](Self::with_position_bins) is set
+    pub fn add_read_at(
+        &mut self,
+        barcode: &str,
+        gene: &str,
+        umi: &str,
+        position: Option<i64>,
+    ) -> bool {
+        let has_n = umi.contains('N');
+        if has_n {
+            self.n_umi_reads += 1;
+            if self.n_policy == UmiNPolicy::Drop {
+                self.n_umi_dropped += 1;
+                return false;
+            }
+        }
+        let cell_id = self.barcodes.intern(barcode);
+        let gene_id = self.genes.intern(gene);
+        let umi_id = self.umis.intern(umi);
+        let bin = match (self.bin_width, position) {
+            (Some(width), Some(position)) => (position.max(0) / width as i64) as u32,
+            _ => NO_BIN,
+        };
+        if has_n && self.n_policy == UmiNPolicy::Correct {
+            self.n_pending.push(((gene_id, cell_id, bin), umi_id));
+            return true;
+        }
+        *self
+            .groups
+            .entry((gene_id, cell_id, bin))
+            .or_default()
+            .entry(umi_id)
+            .or_insert(0) += 1;
+        self.reads += 1;
+        true
+    }
+
+    /// Fold reads with a single-`N` UMI into the most-read UMI of their group
+    /// that matches elsewhere; returns how many were corrected
+    fn correct_n_umis(&mut self) -> u64 {
+        let mut corrected = 0u64;
+        for (key, umi_id) in std::mem::take(&mut self.n_pending) {
+            let umi = self.umis.resolve(umi_id).as_bytes();
+            let Some(group) = self.groups.get_mut(&key) else {
+                continue;
+            };
+            let single_n = umi.iter().filter(|&&b| b == b'N').count() == 1;
+            let mut best: Option<(u32, u32)> = None;
+            let mut tied = false;
+            for (&candidate_id, &reads) in group.iter() {
+                let candidate = self.umis.resolve(candidate_id).as_bytes();
+                let fits = single_n
+                    && candidate.len() == umi.len()
+                    && !candidate.contains(&b'N')
+                    && umi.iter().zip(candidate).all(|(&a, &b)| a == b'N' || a == b);
+                if !fits {
+                    continue;
+                }
+                match best {
+                    Some((_, most)) if reads < most => {}
+                    Some((_, most)) if reads == most => tied = true,
+                    _ => {
+                        best = Some((candidate_id, reads));
+                        tied = false;
+                    }
+                }
+            }
+            if let (Some((candidate_id, _)), false) = (best, tied) {
+                *group.get_mut(&candidate_id).expect("candidate is in group") += 1;
+                self.reads += 1;
+                corrected += 1;
+            }
+        }
+        corrected
+    }
+
+    /// Reads recorded so far
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Deduplicate every (cell, gene, bin) group in parallel and add its
+    /// molecule count to `counter`. Cells and genes keep their first-seen order.
+    pub fn finish(
+        mut self,
+        dedup: &UmiDeduplicator,
+        mut counter: GeneCounter,
+    ) -> (GeneCounter, DedupStats) {
+        let pending = self.n_pending.len() as u64;
+        let n_umi_corrected = self.correct_n_umis();
+        let umis = &self.umis;
+        let min_reads = self.min_reads;
+        let groups: Vec<_> = self.groups.into_iter().collect();
+        // (group, molecules kept, molecules discarded for too few reads)
+        let mut molecules: Vec<(GroupKey, u32, u32)> = groups
+            .into_par_iter()
+            .map(|(key, reads)| {
+                if reads.len() == 1 {
+                    let supported = reads.values().all(|&n| n >= min_reads);
+                    return (key, supported as u32, !supported as u32);
+                }
+                let group: Vec<Umi> = reads
+                    .into_iter()
+                    .map(|(id, n)| Umi::with_count(umis.resolve(id).to_string(), n))
+                    .collect();
+                let clusters = dedup.deduplicate(&group);
+                let kept = clusters.iter().filter(|c| c.total_count >= min_reads).count() as u32;
+                (key, kept, clusters.len() as u32 - kept)
+            })
+            .collect();
+        molecules.sort_unstable_by_key(|&(key, _, _)| key);
+
+        counter.register_names(self.barcodes.iter(), self.genes.iter());
+        let mut stats = DedupStats {
+            reads: self.reads,
+            molecules: 0,
+            n_umi_reads: self.n_umi_reads,
+            n_umi_corrected,
+            n_umi_dropped: self.n_umi_dropped + pending - n_umi_corrected,
+            low_evidence_molecules: 0,
+        };
+        for ((gene_id, cell_id, _), n, discarded) in molecules {
+            stats.low_evidence_molecules += discarded as u64;
+            if n == 0 {
+                continue;
+            }
+            counter.add_count(self.barcodes.resolve(cell_id), self.genes.resolve(gene_id), n);
+            stats.molecules += n as u64;
+        }
+        (counter, stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_molecules_deduplicated() {
+        let mut counter = MoleculeCounter::new();
+        for umi in ["AAAAAAAA", "AAAAAAAA", "AAAAAAAC", "GGGGGGGG"] {
+            counter.add_read("CELL1", "GENE_A", umi);
+        }
+        counter.add_read("CELL2", "GENE_A", "AAAAAAAA");
+        counter.add_read("CELL2", "GENE_B", "TTTTTTTT");
+        assert_eq!(counter.reads(), 6);
+
+        let (counter, stats) = counter.finish(&UmiDeduplicator::new(1), GeneCounter::new());
+        // AAAAAAAC is one mismatch from the more abundant AAAAAAAA
+        assert_eq!(
+            stats,
+            DedupStats {
+                reads: 6,
+                molecules: 4,
+                ..Default::default()
+            }
+        );
+        assert!((stats.duplication_rate() - 1.0 / 3.0).abs() < 1e-9);
+
+        let matrix = counter.build();
+        assert_eq!(matrix.barcodes, vec!["CELL1", "CELL2"]);
+        assert_eq!(matrix.genes, vec!["GENE_A", "GENE_B"]);
+        assert_eq!(matrix.get(0, 0), 2);
+        assert_eq!(matrix.get(0, 1), 1);
+        assert_eq!(matrix.get(1, 1), 1);
+    }
+
+    fn count_with(policy: UmiNPolicy) -> (u32, DedupStats) {
+        let mut counter = MoleculeCounter::new().with_n_policy(policy);
+        for umi in ["AAAACCCC", "AAAACCCC", "GGGGTTTT", "AAAANCCC", "NNAACCCC", "TTTTNAAA"] {
+            counter.add_read("CELL1", "GENE_A", umi);
+        }
+        let dedup = UmiDeduplicator::new(0);
+        let (counter, stats) = counter.finish(&dedup, GeneCounter::new());
+        (counter.build().get(0, 0), stats)
+    }
+
+    #[test]
+    fn test_umi_n_policies() {
+        let (molecules, stats) = count_with(UmiNPolicy::Keep);
+        assert_eq!((molecules, stats.reads, stats.n_umi_reads), (5, 6, 3));
+
+        let (molecules, stats) = count_with(UmiNPolicy::Drop);
+        assert_eq!((molecules, stats.reads, stats.n_umi_dropped), (2, 3, 3));
+
+        // AAAANCCC joins AAAACCCC; two Ns or no match are dropped
+        let (molecules, stats) = count_with(UmiNPolicy::Correct);
+        assert_eq!((molecules, stats.reads), (2, 4));
+        assert_eq!((stats.n_umi_corrected, stats.n_umi_dropped), (1, 2));
+    }
+
+    #[test]
+    fn test_min_reads() {
+        let mut counter = MoleculeCounter::new().with_min_reads(2);
+        // AAAAAAAC joins AAAAAAAA, so that molecule has enough reads
+        for umi in ["AAAAAAAA", "AAAAAAAC", "GGGGGGGG"] {
+            counter.add_read("CELL1", "GENE_A", umi);
+        }
+        counter.add_read("CELL1", "GENE_B", "TTTTTTTT");
+        let (counter, stats) = counter.finish(&UmiDeduplicator::new(1), GeneCounter::new());
+        assert_eq!((stats.molecules, stats.low_evidence_molecules), (1, 2));
+
+        let matrix = counter.build();
+        assert_eq!(matrix.genes, vec!["GENE_A", "GENE_B"]);
+        assert_eq!(matrix.get(0, 0), 1);
+        assert_eq!(matrix.get(1, 0), 0);
+    }
+
+    #[test]
+    fn test_position_bins() {
+        let count = |width: u32| {
+            let mut counter = MoleculeCounter::new().with_position_bins(width);
+            for position in [100, 120, 5000] {
+                counter.add_read_at("CELL1", "GENE_A", "AAAACCCC", Some(position));
+            }
+            counter.add_read("CELL1", "GENE_A", "AAAACCCC");
+            let (counter, stats) = counter.finish(&UmiDeduplicator::new(1), GeneCounter::new());
+            assert_eq!(stats.reads, 4);
+            counter.build().get(0, 0)
+        };
+        // One UMI at two priming sites, plus a read without a position
+        assert_eq!(count(1000), 3);
+        assert_eq!(count(0), 1);
+        assert_eq!(count(10), 4);
+    }
+}
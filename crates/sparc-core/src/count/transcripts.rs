@@ -0,0 +1,142 @@
+//! Transcript-level count matrices
+//!
+//! Rows of a transcript matrix are transcript IDs; their gene is kept in the
+//! [`TRANSCRIPT_GENE_ID`] metadata column so the counts can later be summed
+//! per gene with [`CountMatrix::rollup_to_genes`].
+
+use ahash::AHashMap;
+use std::collections::BTreeMap;
+
+use super::{CountMatrix, MetadataColumn, GENE_LENGTH};
+use crate::annotation::{Transcript, TranscriptAnnotation};
+use crate::{Error, Result};
+
+/// Transcript column: gene ID of the transcript (empty when unannotated)
+pub const TRANSCRIPT_GENE_ID: &str = "gene_id";
+/// Transcript column: gene symbol of the transcript (empty when unannotated)
+pub const TRANSCRIPT_GENE_NAME: &str = "gene_name";
+
+impl CountMatrix {
+    /// Label transcript rows from `annotation`: transcript names become the
+    /// row symbols, and the gene ID, gene symbol, and exonic length go in
+    /// metadata columns. Returns the number of rows found in `annotation`.
+    pub fn annotate_transcripts(&mut self, annotation: &TranscriptAnnotation) -> usize {
+        let matched: Vec<Option<&Transcript>> =
+            self.genes.iter().map(|id| annotation.get(id)).collect();
+        let text = |f: fn(&Transcript) -> &str| -> MetadataColumn {
+            MetadataColumn::Text(matched.iter().map(|t| t.map_or("", f).to_string()).collect())
+        };
+        self.gene_metadata.insert(TRANSCRIPT_GENE_ID, text(|t| t.gene_id.as_str()));
+        self.gene_metadata.insert(TRANSCRIPT_GENE_NAME, text(|t| t.gene_name.as_str()));
+        let lengths = matched.iter().map(|t| t.map_or(0, Transcript::length)).collect();
+        self.gene_metadata.insert(GENE_LENGTH, MetadataColumn::Int(lengths));
+        self.gene_names = matched
+            .iter()
+            .zip(&self.genes)
+            .map(|(t, id)| t.map_or(id, |t| &t.name).clone())
+            .collect();
+        matched.iter().filter(|t| t.is_some()).count()
+    }
+
+    /// Sum transcript rows into one row per gene of the [`TRANSCRIPT_GENE_ID`]
+    /// column, in order of first appearance. Rows without a gene keep their
+    /// own ID. Cell metadata carries over; transcript metadata does not.
+    pub fn rollup_to_genes(&self) -> Result<CountMatrix> {
+        let Some(MetadataColumn::Text(gene_ids)) = self.gene_metadata.get(TRANSCRIPT_GENE_ID)
+        else {
+            return Err(Error::Matrix(format!(
+                "rollup needs a text '{}' metadata column (see annotate_transcripts)",
+                TRANSCRIPT_GENE_ID
+            )));
+        };
+        let symbols = match self.gene_metadata.get(TRANSCRIPT_GENE_NAME) {
+            Some(MetadataColumn::Text(symbols)) => Some(symbols),
+            _ => None,
+        };
+
+        let mut genes: Vec<String> = Vec::new();
+        let mut gene_names: Vec<String> = Vec::new();
+        let mut gene_of_row = Vec::with_capacity(self.n_rows);
+        let mut index: AHashMap<&str, usize> = AHashMap::new();
+        for (row, transcript) in self.genes.iter().enumerate() {
+            let (id, symbol) = match gene_ids[row].as_str() {
+                "" => (transcript.as_str(), self.gene_name(row)),
+                id => (id, symbols.map_or("", |s| s[row].as_str())),
+            };
+            let gene = *index.entry(id).or_insert_with(|| {
+                genes.push(id.to_string());
+                gene_names.push(if symbol.is_empty() { id } else { symbol }.to_string());
+                genes.len() - 1
+            });
+            gene_of_row.push(gene);
+        }
+
+        let mut sums: BTreeMap<(usize, usize), u32> = BTreeMap::new();
+        for ((&row, &col), &value) in self.rows.iter().zip(&self.cols).zip(&self.values) {
+            *sums.entry((gene_of_row[row], col)).or_default() += value;
+        }
+        let mut matrix = CountMatrix {
+            barcodes: self.barcodes.clone(),
+            n_rows: genes.len(),
+            n_cols: self.n_cols,
+            genes,
+            gene_names,
+            cell_metadata: self.cell_metadata.clone(),
+            ..CountMatrix::new()
+        };
+        for ((row, col), value) in sums {
+            matrix.rows.push(row);
+            matrix.cols.push(col);
+            matrix.values.push(value);
+        }
+        Ok(matrix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intervals::Strand;
+
+    fn transcript(id: &str, gene_id: &str, exons: Vec<(i64, i64)>) -> Transcript {
+        Transcript {
+            id: id.to_string(),
+            name: format!("{}-name", id),
+            gene_id: gene_id.to_string(),
+            gene_name: format!("{}-sym", gene_id),
+            chrom: "chr1".to_string(),
+            start: exons[0].0,
+            end: exons[exons.len() - 1].1,
+            strand: Strand::Forward,
+            exons,
+        }
+    }
+
+    #[test]
+    fn test_annotate_and_rollup_transcripts() {
+        let mut matrix = CountMatrix::from_dense(
+            vec!["C1".to_string(), "C2".to_string()],
+            vec!["T1".to_string(), "T3".to_string(), "T2".to_string(), "novel".to_string()],
+            vec![vec![1, 0], vec![4, 0], vec![2, 3], vec![0, 5]],
+        );
+        assert!(matrix.rollup_to_genes().is_err());
+
+        let annotation = TranscriptAnnotation::from_transcripts(vec![
+            transcript("T1", "G1", vec![(0, 100), (200, 250)]),
+            transcript("T2", "G1", vec![(0, 100)]),
+            transcript("T3", "G2", vec![(500, 600)]),
+        ]);
+        assert_eq!(matrix.annotate_transcripts(&annotation), 3);
+        assert_eq!(matrix.gene_names, ["T1-name", "T3-name", "T2-name", "novel"]);
+        let lengths = MetadataColumn::Int(vec![150, 100, 100, 0]);
+        assert_eq!(matrix.gene_metadata.get(GENE_LENGTH), Some(&lengths));
+
+        let genes = matrix.rollup_to_genes().unwrap();
+        assert_eq!(genes.genes, ["G1", "G2", "novel"]);
+        assert_eq!(genes.gene_names, ["G1-sym", "G2-sym", "novel"]);
+        assert_eq!((genes.get(0, 0), genes.get(0, 1)), (3, 3));
+        assert_eq!((genes.get(1, 0), genes.get(2, 1)), (4, 5));
+        assert_eq!(genes.counts_per_cell(), matrix.counts_per_cell());
+        assert!(genes.validate().is_ok());
+    }
+}
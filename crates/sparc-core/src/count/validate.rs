@@ -0,0 +1,219 @@
+//! Structural checks on count matrices
+//!
+//! Readers validate what they parse and `write_mtx` validates before writing,
+//! so a corrupt matrix fails with a [`MatrixIssue`] instead of reaching disk.
+
+use ahash::AHashSet;
+use rayon::prelude::*;
+
+use super::CountMatrix;
+use crate::Result;
+
+/// The first structural problem found in a [`CountMatrix`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MatrixIssue {
+    #[error("{rows} row indices, {cols} column indices, and {values} values")]
+    LengthMismatch {
+        rows: usize,
+        cols: usize,
+        values: usize,
+    },
+
+    #[error("expected {expected} {axis}, found {labels}")]
+    DimensionMismatch {
+        axis: &'static str,
+        expected: usize,
+        labels: usize,
+    },
+
+    #[error("entry {entry} at ({row}, {col}) is outside {n_rows} x {n_cols}")]
+    OutOfBounds {
+        entry: usize,
+        row: usize,
+        col: usize,
+        n_rows: usize,
+        n_cols: usize,
+    },
+
+    #[error("more than one entry at ({row}, {col})")]
+    DuplicateEntry { row: usize, col: usize },
+
+    #[error("{axis} label '{label}' appears more than once")]
+    DuplicateLabel { axis: &'static str, label: String },
+
+    #[error("{axis} metadata '{column}' has {values} values, expected {expected}")]
+    MetadataLength {
+        axis: &'static str,
+        column: String,
+        values: usize,
+        expected: usize,
+    },
+}
+
+impl CountMatrix {
+    /// Check that entry arrays line up, indices are in bounds, dimensions
+    /// match the labels (and gene names and metadata, if any), and no
+    /// (row, col) or label repeats
+    pub fn validate(&self) -> Result<()> {
+        if self.rows.len() != self.values.len() || self.cols.len() != self.values.len() {
+            return Err(MatrixIssue::LengthMismatch {
+                rows: self.rows.len(),
+                cols: self.cols.len(),
+                values: self.values.len(),
+            }
+            .into());
+        }
+        let gene_names = match self.gene_names.len() {
+            0 => self.n_rows,
+            n => n,
+        };
+        for (axis, expected, labels) in [
+            ("genes", self.n_rows, self.genes.len()),
+            ("barcodes", self.n_cols, self.barcodes.len()),
+            ("gene names", self.n_rows, gene_names),
+        ] {
+            if expected != labels {
+                return Err(MatrixIssue::DimensionMismatch {
+                    axis,
+                    expected,
+                    labels,
+                }
+                .into());
+            }
+        }
+
+        for (axis, metadata, expected) in [
+            ("gene", &self.gene_metadata, self.n_rows),
+            ("cell", &self.cell_metadata, self.n_cols),
+        ] {
+            if let Some((column, values)) = metadata.iter().find(|(_, c)| c.len() != expected) {
+                return Err(MatrixIssue::MetadataLength {
+                    axis,
+                    column: column.to_string(),
+                    values: values.len(),
+                    expected,
+                }
+                .into());
+            }
+        }
+
+        let (n_rows, n_cols) = (self.n_rows, self.n_cols);
+        let out_of_bounds = self
+            .rows
+            .par_iter()
+            .zip(&self.cols)
+            .position_first(|(&row, &col)| row >= n_rows || col >= n_cols);
+        if let Some(entry) = out_of_bounds {
+            return Err(MatrixIssue::OutOfBounds {
+                entry,
+                row: self.rows[entry],
+                col: self.cols[entry],
+                n_rows,
+                n_cols,
+            }
+            .into());
+        }
+
+        let mut keys: Vec<(usize, usize)> =
+            self.rows.iter().copied().zip(self.cols.iter().copied()).collect();
+        keys.par_sort_unstable();
+        if let Some(pair) = keys.windows(2).find(|pair| pair[0] == pair[1]) {
+            let (row, col) = pair[0];
+            return Err(MatrixIssue::DuplicateEntry { row, col }.into());
+        }
+
+        for (axis, labels) in [("gene", &self.genes), ("barcode", &self.barcodes)] {
+            let mut seen = AHashSet::with_capacity(labels.len());
+            if let Some(label) = labels.iter().find(|l| !seen.insert(l.as_str())) {
+                return Err(MatrixIssue::DuplicateLabel {
+                    axis,
+                    label: label.clone(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count::MetadataColumn;
+    use crate::Error;
+
+    fn sample() -> CountMatrix {
+        CountMatrix::from_dense(
+            vec!["C1".to_string(), "C2".to_string()],
+            vec!["G1".to_string(), "G2".to_string()],
+            vec![vec![1, 0], vec![2, 3]],
+        )
+    }
+
+    fn issue(matrix: &CountMatrix) -> MatrixIssue {
+        match matrix.validate() {
+            Err(Error::InvalidMatrix(issue)) => issue,
+            other => panic!("expected an invalid matrix, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_each_issue() {
+        assert!(sample().validate().is_ok());
+
+        let mut m = sample();
+        m.values.pop();
+        assert!(matches!(issue(&m), MatrixIssue::LengthMismatch { values: 2, .. }));
+
+        let mut m = sample();
+        m.n_cols = 3;
+        assert!(matches!(issue(&m), MatrixIssue::DimensionMismatch { axis: "barcodes", .. }));
+
+        let mut m = sample();
+        m.gene_names = vec!["A".to_string()];
+        assert!(matches!(issue(&m), MatrixIssue::DimensionMismatch { axis: "gene names", .. }));
+
+        let mut m = sample();
+        m.cell_metadata.insert("score", MetadataColumn::Float(vec![1.0]));
+        assert!(matches!(issue(&m), MatrixIssue::MetadataLength { axis: "cell", values: 1, .. }));
+
+        let mut m = sample();
+        m.rows[1] = 2;
+        assert!(matches!(issue(&m), MatrixIssue::OutOfBounds { entry: 1, row: 2, .. }));
+
+        let mut m = sample();
+        m.rows.push(1);
+        m.cols.push(1);
+        m.values.push(5);
+        assert_eq!(issue(&m), MatrixIssue::DuplicateEntry { row: 1, col: 1 });
+
+        let mut m = sample();
+        m.barcodes[1] = "C1".to_string();
+        assert_eq!(
+            issue(&m),
+            MatrixIssue::DuplicateLabel {
+                axis: "barcode",
+                label: "C1".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_and_read_reject_corrupt_matrix() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut m = sample();
+        m.rows[0] = 7;
+        assert!(m.write_mtx(dir.path().join("matrix.mtx")).is_err());
+        assert!(!dir.path().join("matrix.mtx").exists());
+
+        let m = sample();
+        m.write_barcodes(dir.path().join("barcodes.tsv")).unwrap();
+        m.write_genes(dir.path().join("genes.tsv")).unwrap();
+        std::fs::write(
+            dir.path().join("matrix.mtx"),
+            "%%MatrixMarket matrix coordinate integer general\n2 2 2\n1 1 1\n1 1 4\n",
+        )
+        .unwrap();
+        assert!(CountMatrix::read_mtx(dir.path()).is_err());
+    }
+}
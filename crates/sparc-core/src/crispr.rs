@@ -0,0 +1,364 @@
+//! CRISPR guide calling from a guide (protospacer) count matrix
+//!
+//! Each guide's UMI counts across cells are modelled as a two-component
+//! Gaussian mixture on `ln(1 + umis)`: a low ambient component from free-floating
+//! guide molecules and a high component from cells that express the guide. The
+//! per-guide UMI threshold is the smallest count the high component explains.
+
+use crate::count::CountMatrix;
+use crate::atomic::AtomicFile;
+use crate::Result;
+use std::io::Write;
+use std::path::Path;
+
+/// Guide calling parameters
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GuideCallerConfig {
+    /// Never call a guide with fewer UMIs than this
+    pub min_umis: u32,
+    /// Posterior probability of the expressed component required to call
+    pub min_posterior: f64,
+    /// EM iterations for the per-guide mixture fit
+    pub max_iter: usize,
+}
+
+impl Default for GuideCallerConfig {
+    fn default() -> Self {
+        Self {
+            min_umis: 3,
+            min_posterior: 0.5,
+            max_iter: 100,
+        }
+    }
+}
+
+/// Fitted background model and call threshold for one guide
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GuideThreshold {
+    /// Guide (protospacer) name
+    pub guide: String,
+    /// Minimum UMIs for a cell to be called as carrying the guide
+    pub umi_threshold: u32,
+    /// Mean ambient UMIs per cell (low mixture component)
+    pub ambient_umis: f64,
+}
+
+/// Outcome for one cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum GuideCallType {
+    /// No guide UMIs at all
+    NoMolecules,
+    /// Guide UMIs present but all below threshold
+    NoCall,
+    /// Exactly one guide called
+    Single,
+    /// More than one guide called
+    Multiple,
+}
+
+/// Guides called in one cell
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CellGuideCall {
+    /// Cell barcode
+    pub barcode: String,
+    /// Called guide indices (matrix rows), sorted by name
+    pub guides: Vec<usize>,
+    /// UMIs of each called guide
+    pub umis: Vec<u32>,
+    /// Call category
+    pub call_type: GuideCallType,
+}
+
+/// Guide calls for all cells
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GuideCalls {
+    /// Guide names (matrix rows)
+    pub guides: Vec<String>,
+    /// Per-guide thresholds, in guide order
+    pub thresholds: Vec<GuideThreshold>,
+    /// Per-cell calls, in barcode order
+    pub cells: Vec<CellGuideCall>,
+}
+
+/// Call guides for every cell of a guides x cells count matrix
+pub fn call_guides(matrix: &CountMatrix, config: &GuideCallerConfig) -> GuideCalls {
+    let mut per_guide: Vec<Vec<(usize, u32)>> = vec![Vec::new(); matrix.n_rows];
+    for ((&guide, &cell), &count) in matrix.rows.iter().zip(&matrix.cols).zip(&matrix.values) {
+        if count > 0 {
+            per_guide[guide].push((cell, count));
+        }
+    }
+
+    let thresholds: Vec<GuideThreshold> = per_guide
+        .iter()
+        .enumerate()
+        .map(|(g, entries)| {
+            let counts: Vec<u32> = entries.iter().map(|&(_, c)| c).collect();
+            let (umi_threshold, ambient_umis) = fit_threshold(&counts, config);
+            GuideThreshold {
+                guide: matrix.genes.get(g).cloned().unwrap_or_default(),
+                umi_threshold,
+                ambient_umis,
+            }
+        })
+        .collect();
+
+    let mut has_molecules = vec![false; matrix.n_cols];
+    let mut called: Vec<Vec<(usize, u32)>> = vec![Vec::new(); matrix.n_cols];
+    for (g, entries) in per_guide.iter().enumerate() {
+        for &(cell, count) in entries {
+            has_molecules[cell] = true;
+            if count >= thresholds[g].umi_threshold {
+                called[cell].push((g, count));
+            }
+        }
+    }
+
+    let cells = called
+        .into_iter()
+        .enumerate()
+        .map(|(cell, mut hits)| {
+            hits.sort_by(|a, b| thresholds[a.0].guide.cmp(&thresholds[b.0].guide));
+            let call_type = match hits.len() {
+                0 if has_molecules[cell] => GuideCallType::NoCall,
+                0 => GuideCallType::NoMolecules,
+                1 => GuideCallType::Single,
+                _ => GuideCallType::Multiple,
+            };
+            CellGuideCall {
+                barcode: matrix.barcodes.get(cell).cloned().unwrap_or_default(),
+                guides: hits.iter().map(|h| h.0).collect(),
+                umis: hits.iter().map(|h| h.1).collect(),
+                call_type,
+            }
+        })
+        .collect();
+
+    GuideCalls {
+        guides: matrix.genes.clone(),
+        thresholds,
+        cells,
+    }
+}
+
+/// Fit the ambient/expressed mixture to one guide's non-zero counts and return
+/// (UMI threshold, mean ambient UMIs)
+fn fit_threshold(counts: &[u32], config: &GuideCallerConfig) -> (u32, f64) {
+    let x: Vec<f64> = counts.iter().map(|&c| (c as f64).ln_1p()).collect();
+    let (Some(lo), Some(hi)) = (
+        x.iter().copied().reduce(f64::min),
+        x.iter().copied().reduce(f64::max),
+    ) else {
+        return (config.min_umis, 0.0);
+    };
+    if hi - lo < f64::EPSILON {
+        return (config.min_umis, 0.0);
+    }
+
+    // Components: (weight, mean, variance); start at the extremes
+    let spread = ((hi - lo) / 4.0).powi(2);
+    let mut low = (0.5, lo, spread);
+    let mut high = (0.5, hi, spread);
+    let mut resp = vec![0.0; x.len()];
+    for _ in 0..config.max_iter {
+        for (r, &xi) in resp.iter_mut().zip(&x) {
+            *r = posterior_high(xi, low, high);
+        }
+        let n_high: f64 = resp.iter().sum();
+        if n_high < 1e-9 || x.len() as f64 - n_high < 1e-9 {
+            break;
+        }
+        let next_high = weighted_component(&x, resp.iter().copied());
+        let next_low = weighted_component(&x, resp.iter().map(|r| 1.0 - r));
+        let converged = (next_high.1 - high.1).abs() < 1e-6 && (next_low.1 - low.1).abs() < 1e-6;
+        high = next_high;
+        low = next_low;
+        if converged {
+            break;
+        }
+    }
+
+    // Components less than e-fold apart are one population, not ambient + expressed
+    if high.1 - low.1 < 1.0 {
+        return (config.min_umis, 0.0);
+    }
+
+    let max_count = counts.iter().copied().max().unwrap_or(0);
+    let threshold = (1..=max_count)
+        .find(|&k| {
+            let xk = (k as f64).ln_1p();
+            xk > low.1 && posterior_high(xk, low, high) >= config.min_posterior
+        })
+        .unwrap_or(max_count.saturating_add(1));
+    (threshold.max(config.min_umis), low.1.exp_m1())
+}
+
+/// Weight, mean, and variance of `x` under per-point responsibilities `w`
+fn weighted_component(x: &[f64], w: impl Iterator<Item = f64> + Clone) -> (f64, f64, f64) {
+    let total: f64 = w.clone().sum();
+    let mean = w.clone().zip(x).map(|(wi, xi)| wi * xi).sum::<f64>() / total;
+    let var = w.zip(x).map(|(wi, xi)| wi * (xi - mean).powi(2)).sum::<f64>() / total;
+    (total / x.len() as f64, mean, var.max(1e-3))
+}
+
+/// Posterior probability that `x` comes from the `high` component
+fn posterior_high(x: f64, low: (f64, f64, f64), high: (f64, f64, f64)) -> f64 {
+    let density = |(w, mean, var): (f64, f64, f64)| {
+        w * (-(x - mean).powi(2) / (2.0 * var)).exp() / var.sqrt()
+    };
+    let (dl, dh) = (density(low), density(high));
+    if dl + dh == 0.0 {
+        // Far in a tail of both: side with the nearer mean
+        return if (x - high.1).abs() < (x - low.1).abs() { 1.0 } else { 0.0 };
+    }
+    dh / (dl + dh)
+}
+
+impl GuideCalls {
+    /// Number of cells in each call category
+    pub fn count(&self, call_type: GuideCallType) -> usize {
+        self.cells.iter().filter(|c| c.call_type == call_type).count()
+    }
+
+    /// Write `protospacer_calls_per_cell.csv` (Cell Ranger layout): one row per
+    /// cell with at least one call, guides and UMIs joined by `|`
+    pub fn write_per_cell<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut out = AtomicFile::create(path)?;
+        writeln!(out, "cell_barcode,num_features,feature_call,num_umis")?;
+        for cell in self.cells.iter().filter(|c| !c.guides.is_empty()) {
+            let names: Vec<&str> = cell.guides.iter().map(|&g| self.guides[g].as_str()).collect();
+            let umis: Vec<String> = cell.umis.iter().map(|u| u.to_string()).collect();
+            writeln!(
+                out,
+                "{},{},{},{}",
+                cell.barcode,
+                cell.guides.len(),
+                names.join("|"),
+                umis.join("|")
+            )?;
+        }
+        out.commit()
+    }
+
+    /// Write `protospacer_calls_summary.csv` (Cell Ranger layout): call
+    /// categories followed by each observed guide combination
+    pub fn write_summary<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let total = self.cells.len().max(1) as f64;
+        let mut out = AtomicFile::create(path)?;
+        writeln!(out, "feature_call,num_cells,pct_cells")?;
+        for (label, call_type) in [
+            ("No guide molecules", GuideCallType::NoMolecules),
+            ("No confident call", GuideCallType::NoCall),
+            ("1 protospacer assigned", GuideCallType::Single),
+            ("More than 1 protospacer assigned", GuideCallType::Multiple),
+        ] {
+            let n = self.count(call_type);
+            writeln!(out, "{},{},{:.4}", label, n, n as f64 / total * 100.0)?;
+        }
+
+        let mut combos: Vec<(String, usize)> = Vec::new();
+        let mut index = std::collections::HashMap::new();
+        for cell in self.cells.iter().filter(|c| !c.guides.is_empty()) {
+            let names: Vec<&str> = cell.guides.iter().map(|&g| self.guides[g].as_str()).collect();
+            let key = names.join("|");
+            let i = *index.entry(key.clone()).or_insert_with(|| {
+                combos.push((key, 0));
+                combos.len() - 1
+            });
+            combos[i].1 += 1;
+        }
+        combos.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        for (combo, n) in combos {
+            writeln!(out, "{},{},{:.4}", combo, n, n as f64 / total * 100.0)?;
+        }
+        out.commit()
+    }
+
+    /// Write `protospacer_umi_thresholds.csv`
+    pub fn write_thresholds<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut out = AtomicFile::create(path)?;
+        writeln!(out, "Protospacer,UMI threshold")?;
+        for t in &self.thresholds {
+            writeln!(out, "{},{}", t.guide, t.umi_threshold)?;
+        }
+        out.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 40 cells: cells 0-9 carry gRNA-A, 10-19 gRNA-B, 20-24 both; everyone
+    /// sees 0-2 ambient UMIs of each guide
+    fn guide_matrix() -> CountMatrix {
+        let barcodes: Vec<String> = (0..40).map(|i| format!("CELL{:02}", i)).collect();
+        let guides = vec!["gRNA-A".to_string(), "gRNA-B".to_string()];
+        let ambient = |cell: usize, g: usize| ((cell * 7 + g * 3) % 3) as u32;
+        let counts = (0..2)
+            .map(|g| {
+                (0..40)
+                    .map(|cell| {
+                        let expressed = match g {
+                            0 => cell < 10 || (20..25).contains(&cell),
+                            _ => (10..25).contains(&cell),
+                        };
+                        if expressed {
+                            40 + (cell as u32 % 5) * 10
+                        } else {
+                            ambient(cell, g)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        CountMatrix::from_dense(barcodes, guides, counts)
+    }
+
+    #[test]
+    fn test_call_guides() {
+        let calls = call_guides(&guide_matrix(), &GuideCallerConfig::default());
+        for t in &calls.thresholds {
+            assert!(t.umi_threshold > 2 && t.umi_threshold <= 40, "{:?}", t);
+            assert!(t.ambient_umis < 3.0, "{:?}", t);
+        }
+
+        assert_eq!(calls.cells[0].call_type, GuideCallType::Single);
+        assert_eq!(calls.cells[0].guides, vec![0]);
+        assert_eq!(calls.cells[12].guides, vec![1]);
+        assert_eq!(calls.cells[22].call_type, GuideCallType::Multiple);
+        assert_eq!(calls.count(GuideCallType::Single), 20);
+        assert_eq!(calls.count(GuideCallType::Multiple), 5);
+        assert_eq!(
+            calls.count(GuideCallType::NoCall) + calls.count(GuideCallType::NoMolecules),
+            15
+        );
+    }
+
+    #[test]
+    fn test_write_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let calls = call_guides(&guide_matrix(), &GuideCallerConfig::default());
+        calls.write_per_cell(dir.path().join("calls.csv")).unwrap();
+        calls.write_summary(dir.path().join("summary.csv")).unwrap();
+
+        let per_cell = std::fs::read_to_string(dir.path().join("calls.csv")).unwrap();
+        let lines: Vec<&str> = per_cell.lines().collect();
+        assert_eq!(lines[0], "cell_barcode,num_features,feature_call,num_umis");
+        assert_eq!(lines.len(), 1 + 25);
+        assert!(lines.contains(&"CELL20,2,gRNA-A|gRNA-B,40|40"));
+
+        let summary = std::fs::read_to_string(dir.path().join("summary.csv")).unwrap();
+        assert!(summary.contains("1 protospacer assigned,20,50.0000"));
+        assert!(summary.contains("gRNA-A|gRNA-B,5,12.5000"));
+    }
+
+    #[test]
+    fn test_no_signal_uses_min_umis() {
+        let config = GuideCallerConfig::default();
+        assert_eq!(fit_threshold(&[1, 1, 1], &config).0, 3);
+        assert_eq!(fit_threshold(&[], &config).0, 3);
+        // A single expressed population without ambient background
+        assert_eq!(fit_threshold(&[40, 50, 60, 70, 80], &config).0, 3);
+    }
+}
@@ -0,0 +1,274 @@
+//! Sample demultiplexing from index reads (I1/I2)
+
+use crate::seq_util::hamming;
+use crate::{Error, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// A sample and its index sequences
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SampleIndex {
+    /// Sample name
+    pub sample: String,
+    /// i7 index (read from I1)
+    pub index: String,
+    /// Optional i5 index (read from I2)
+    pub index2: Option<String>,
+}
+
+/// Sample sheet mapping index sequences to samples
+#[derive(Debug, Clone, Default)]
+pub struct SampleSheet {
+    pub samples: Vec<SampleIndex>,
+}
+
+impl SampleSheet {
+    /// Load a CSV sample sheet (columns: sample,index[,index2]; header optional)
+    ///
+    /// A sample may span several rows (one per index oligo, as in 10x SI-GA
+    /// sets); those rows demultiplex into the same outputs.
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = BufReader::new(File::open(path)?);
+        let mut samples = Vec::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if i == 0 && line.to_lowercase().starts_with("sample") {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split(',').map(|p| p.trim()).collect();
+            if parts.len() < 2 || parts[0].is_empty() || parts[1].is_empty() {
+                return Err(Error::Config(format!(
+                    "Sample sheet {:?} line {}: expected sample,index[,index2]",
+                    path,
+                    i + 1
+                )));
+            }
+            if let Err(why) = check_sample_name(parts[0]) {
+                return Err(Error::Config(format!(
+                    "Sample sheet {:?} line {}: sample name '{}' {}",
+                    path,
+                    i + 1,
+                    parts[0],
+                    why
+                )));
+            }
+
+            samples.push(SampleIndex {
+                sample: parts[0].to_string(),
+                index: parts[1].to_uppercase(),
+                index2: parts
+                    .get(2)
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_uppercase()),
+            });
+        }
+
+        Ok(Self { samples })
+    }
+
+    /// Distinct sample names in order of first appearance
+    pub fn sample_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for s in &self.samples {
+            if !names.contains(&s.sample.as_str()) {
+                names.push(&s.sample);
+            }
+        }
+        names
+    }
+
+    /// Whether any sample uses a second (i5) index
+    pub fn is_dual_index(&self) -> bool {
+        self.samples.iter().any(|s| s.index2.is_some())
+    }
+}
+
+/// Sample names become output file names next to `Undetermined_R1.fastq.gz`
+fn check_sample_name(name: &str) -> std::result::Result<(), &'static str> {
+    if name.eq_ignore_ascii_case("Undetermined") {
+        Err("is reserved for unassigned reads")
+    } else if name.contains(['/', '\\']) || name.contains("..") {
+        Err("must not contain path separators or '..'")
+    } else {
+        Ok(())
+    }
+}
+
+/// Result of assigning a read to a sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemuxMatch {
+    /// Assigned to sample at this index with this many total mismatches
+    Sample(usize, u32),
+    /// Two or more samples tie at the best distance
+    Ambiguous,
+    /// No sample within the mismatch tolerance
+    Undetermined,
+}
+
+/// Assigns index reads to samples with mismatch tolerance
+pub struct SampleDemuxer {
+    sheet: SampleSheet,
+    max_mismatch: u32,
+}
+
+impl SampleDemuxer {
+    pub fn new(sheet: SampleSheet, max_mismatch: u32) -> Self {
+        for (i, a) in sheet.samples.iter().enumerate() {
+            for b in sheet.samples.iter().skip(i + 1) {
+                let d = hamming(a.index.as_bytes(), b.index.as_bytes());
+                if d <= 2 * max_mismatch && a.index2 == b.index2 {
+                    log::warn!(
+                        "Indices for samples '{}' and '{}' are within {} mismatches; reads may be ambiguous",
+                        a.sample,
+                        b.sample,
+                        d
+                    );
+                }
+            }
+        }
+        Self {
+            sheet,
+            max_mismatch,
+        }
+    }
+
+    /// Samples in sheet order
+    pub fn samples(&self) -> &[SampleIndex] {
+        &self.sheet.samples
+    }
+
+    /// Assign an index read pair to a sample
+    pub fn assign(&self, i1: &[u8], i2: Option<&[u8]>) -> DemuxMatch {
+        let mut best: Option<(usize, u32)> = None;
+        let mut tied = false;
+
+        for (idx, sample) in self.sheet.samples.iter().enumerate() {
+            let mut dist = hamming_prefix(i1, sample.index.as_bytes());
+            if let (Some(expected), Some(observed)) = (&sample.index2, i2) {
+                dist = dist.saturating_add(hamming_prefix(observed, expected.as_bytes()));
+            }
+            if dist > self.max_mismatch {
+                continue;
+            }
+            match best {
+                None => best = Some((idx, dist)),
+                Some((_, d)) if dist < d => {
+                    best = Some((idx, dist));
+                    tied = false;
+                }
+                Some((_, d)) if dist == d => tied = true,
+                _ => {}
+            }
+        }
+
+        match best {
+            Some(_) if tied => DemuxMatch::Ambiguous,
+            Some((idx, dist)) => DemuxMatch::Sample(idx, dist),
+            None => DemuxMatch::Undetermined,
+        }
+    }
+}
+
+/// Hamming distance against the expected index, comparing only the first
+/// `expected.len()` bases of the observed read (index reads are often longer).
+fn hamming_prefix(observed: &[u8], expected: &[u8]) -> u32 {
+    if observed.len() < expected.len() {
+        return u32::MAX;
+    }
+    hamming(&observed[..expected.len()], expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet() -> SampleSheet {
+        SampleSheet {
+            samples: vec![
+                SampleIndex {
+                    sample: "S1".to_string(),
+                    index: "ACGTACGT".to_string(),
+                    index2: None,
+                },
+                SampleIndex {
+                    sample: "S2".to_string(),
+                    index: "TTTTGGGG".to_string(),
+                    index2: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_assign_exact_and_mismatch() {
+        let demux = SampleDemuxer::new(sheet(), 1);
+        assert_eq!(demux.assign(b"ACGTACGT", None), DemuxMatch::Sample(0, 0));
+        assert_eq!(demux.assign(b"ACGTACGA", None), DemuxMatch::Sample(0, 1));
+        assert_eq!(demux.assign(b"TTTTGGGGAT", None), DemuxMatch::Sample(1, 0));
+        assert_eq!(demux.assign(b"AAAAAAAA", None), DemuxMatch::Undetermined);
+    }
+
+    fn write_sheet(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_multi_index_sample() {
+        let file = write_sheet(
+            "sample,index\nA,AAAAAAAA\nA,CCCCCCCC\nB,GGGGGGGG\nA,TTTTTTTT\n",
+        );
+        let sheet = SampleSheet::from_csv(file.path()).unwrap();
+        assert_eq!(sheet.samples.len(), 4);
+        assert_eq!(sheet.sample_names(), vec!["A", "B"]);
+
+        let demux = SampleDemuxer::new(sheet, 1);
+        for (index, sample) in [(b"CCCCCCCC", "A"), (b"TTTTTTTT", "A"), (b"GGGGGGGG", "B")] {
+            match demux.assign(index, None) {
+                DemuxMatch::Sample(row, 0) => assert_eq!(demux.samples()[row].sample, sample),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_sample_names() {
+        for name in ["Undetermined", "../escape", "a/b"] {
+            let file = write_sheet(&format!("{},ACGTACGT\n", name));
+            let err = SampleSheet::from_csv(file.path()).unwrap_err().to_string();
+            assert!(err.contains("line 1"), "{}", err);
+        }
+        let file = write_sheet("S1\n");
+        assert!(matches!(
+            SampleSheet::from_csv(file.path()),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_assign_dual_index() {
+        let mut s = sheet();
+        s.samples[0].index2 = Some("CCCCAAAA".to_string());
+        s.samples[1].index = "ACGTACGT".to_string();
+        s.samples[1].index2 = Some("GGGGTTTT".to_string());
+        let demux = SampleDemuxer::new(s, 1);
+
+        assert_eq!(
+            demux.assign(b"ACGTACGT", Some(b"GGGGTTTT")),
+            DemuxMatch::Sample(1, 0)
+        );
+        assert_eq!(
+            demux.assign(b"ACGTACGT", Some(b"CCCCAAAT")),
+            DemuxMatch::Sample(0, 1)
+        );
+    }
+}
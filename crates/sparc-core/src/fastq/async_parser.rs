@@ -0,0 +1,209 @@
+//! Async FASTQ parser for tokio-based services (requires the `async` feature)
+
+use super::parser::normalize_qual;
+use super::{FastqRecord, QualityEncoding};
+use crate::{Error, RecordContext, Result};
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use futures_util::stream::{self, Stream};
+use std::path::Path;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+
+/// Async FASTQ parser yielding records as a [`Stream`]
+///
+/// Reads plain, gzip (multi-member), and zstd input without blocking the runtime.
+/// Records are validated like [`super::FastqParser`]'s: sequence and quality
+/// lengths must match and qualities must be Phred+33. Blank lines are only
+/// allowed at the end of the input. Malformed records produce
+/// [`Error::FastqRecord`] errors naming the source, 1-based record number,
+/// read name, and byte offset.
+pub struct AsyncFastqParser {
+    reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    name: String,
+    record_num: u64,
+    line: Vec<u8>,
+    /// Bytes consumed from the (decompressed) input
+    offset: u64,
+    /// Offset of the last line returned by `read_line`
+    line_start: u64,
+    /// Offset and read name of the record being parsed
+    record_start: u64,
+    read_name: Option<String>,
+}
+
+impl AsyncFastqParser {
+    /// Open a FASTQ file (compression detected from the `.gz`/`.zst` extension)
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        log::info!("Opening FASTQ file (async): {:?}", path);
+        let file = BufReader::new(tokio::fs::File::open(path).await?);
+
+        let reader: Box<dyn AsyncBufRead + Unpin + Send> =
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("gz") | Some("gzip") => {
+                    let mut decoder = GzipDecoder::new(file);
+                    decoder.multiple_members(true);
+                    Box::new(BufReader::new(decoder))
+                }
+                Some("zst") => Box::new(BufReader::new(ZstdDecoder::new(file))),
+                _ => Box::new(file),
+            };
+
+        Ok(Self::from_reader(reader, path.display().to_string()))
+    }
+
+    /// Parse uncompressed FASTQ from any async buffered reader (e.g. an upload body)
+    pub fn from_reader<R>(reader: R, name: impl Into<String>) -> Self
+    where
+        R: AsyncBufRead + Unpin + Send + 'static,
+    {
+        Self {
+            reader: Box::new(reader),
+            name: name.into(),
+            record_num: 0,
+            line: Vec::new(),
+            offset: 0,
+            line_start: 0,
+            record_start: 0,
+            read_name: None,
+        }
+    }
+
+    /// Read the next record, or `None` at end of input
+    pub async fn next_record(&mut self) -> Option<Result<FastqRecord>> {
+        let mut blank = false;
+        let header = loop {
+            match self.read_line().await {
+                Ok(Some(line)) if line.is_empty() => blank = true,
+                Ok(Some(line)) => break line,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        };
+        self.record_num += 1;
+        self.record_start = self.line_start;
+        self.read_name = None;
+        if blank {
+            return Some(Err(self.error("blank line before record header")));
+        }
+        Some(self.read_body(header).await)
+    }
+
+    /// Convert into a stream of records
+    pub fn into_stream(self) -> impl Stream<Item = Result<FastqRecord>> + Send {
+        stream::unfold(self, |mut parser| async move {
+            parser.next_record().await.map(|record| (record, parser))
+        })
+    }
+
+    /// Read all remaining records into memory
+    pub async fn read_all(&mut self) -> Result<Vec<FastqRecord>> {
+        let mut records = Vec::new();
+        while let Some(record) = self.next_record().await {
+            records.push(record?);
+        }
+        Ok(records)
+    }
+
+    async fn read_body(&mut self, header: Vec<u8>) -> Result<FastqRecord> {
+        let id = header
+            .strip_prefix(b"@")
+            .ok_or_else(|| self.error("header does not start with '@'"))?;
+        let id = String::from_utf8_lossy(id).to_string();
+        self.read_name = Some(id.clone());
+
+        let seq = self.expect_line("sequence").await?;
+        let plus = self.expect_line("separator").await?;
+        if !plus.starts_with(b"+") {
+            return Err(self.error("separator line does not start with '+'"));
+        }
+        let mut qual = self.expect_line("quality").await?;
+        if seq.len() != qual.len() {
+            return Err(self.error(&format!(
+                "sequence length {} does not match quality length {}",
+                seq.len(),
+                qual.len()
+            )));
+        }
+        normalize_qual(QualityEncoding::Phred33, &mut qual).map_err(|msg| self.error(&msg))?;
+
+        Ok(FastqRecord::new(id, seq, qual))
+    }
+
+    async fn expect_line(&mut self, what: &str) -> Result<Vec<u8>> {
+        self.read_line()
+            .await?
+            .ok_or_else(|| self.error(&format!("truncated record (missing {} line)", what)))
+    }
+
+    /// Next line with the line terminator stripped
+    async fn read_line(&mut self) -> Result<Option<Vec<u8>>> {
+        self.line.clear();
+        let n = self.reader.read_until(b'\n', &mut self.line).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        self.line_start = self.offset;
+        self.offset += n as u64;
+        while matches!(self.line.last(), Some(b'\n') | Some(b'\r')) {
+            self.line.pop();
+        }
+        Ok(Some(self.line.clone()))
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        let mut context = RecordContext::new(self.name.as_str(), self.record_num)
+            .with_byte_offset(self.record_start);
+        if let Some(name) = &self.read_name {
+            context = context.with_read_name(name.as_str());
+        }
+        context.fastq_error(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn test_stream_records() {
+        let data: &[u8] = b"@r1 extra\nACGT\n+\nIIII\n@r2\nGGCC\n+r2\n####\n\n";
+        let records: Vec<FastqRecord> = AsyncFastqParser::from_reader(data, "mem")
+            .into_stream()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].name(), "r1");
+        assert_eq!(records[1].seq, b"GGCC".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_record_errors() {
+        let data: &[u8] = b"@r1\nACGT\n+\nIIII\n@r2\nACGT\n";
+        let err = AsyncFastqParser::from_reader(data, "upload.fastq")
+            .read_all()
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("upload.fastq: record 2 ('r2') at byte 16"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_validates_like_sync_parser() {
+        let cases: [(&[u8], &str); 3] = [
+            (b"@r1\nACGT\n+\nIII\n", "does not match quality length"),
+            (b"@r1\nACGT\n+\nII I\n", "outside the Phred+33 range"),
+            (b"@r1\nACGT\n+\nIIII\n\n@r2\nACGT\n+\nIIII\n", "blank line"),
+        ];
+        for (data, message) in cases {
+            let err = AsyncFastqParser::from_reader(data, "mem").read_all().await.unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+        // An empty sequence is a record, not a skipped line
+        let data: &[u8] = b"@r1\n\n+\n\n";
+        let records = AsyncFastqParser::from_reader(data, "mem").read_all().await.unwrap();
+        assert!(records[0].seq.is_empty());
+    }
+}
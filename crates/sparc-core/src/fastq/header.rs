@@ -0,0 +1,89 @@
+//! Cell barcode and UMI carried in read headers
+//!
+//! Reads tagged upstream (`sparc extract`, umi_tools, or a custom tool) can be
+//! counted without re-extracting, as long as the barcode and UMI can be found
+//! in the read name or comment.
+
+use regex::Regex;
+
+use crate::{Error, Result};
+
+/// Where a pre-tagged read's header keeps its cell barcode and UMI
+#[derive(Debug, Clone, Default)]
+pub enum HeaderTags {
+    /// `CB:Z:`/`UB:Z:` comment tags, else a `_CB_UMI` name suffix as written
+    /// by `sparc extract` and `umi_tools extract`
+    #[default]
+    Sparc,
+    /// Named groups `cb` and `umi` of a regex, matched against the whole header
+    Regex(Regex),
+}
+
+impl HeaderTags {
+    /// A custom format; `pattern` must have named groups `cb` and `umi`
+    pub fn regex(pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| Error::Config(format!("Invalid header regex: {}", e)))?;
+        for group in ["cb", "umi"] {
+            if !regex.capture_names().any(|name| name == Some(group)) {
+                return Err(Error::Config(format!(
+                    "Header regex '{}' has no (?P<{}>...) group",
+                    pattern, group
+                )));
+            }
+        }
+        Ok(Self::Regex(regex))
+    }
+
+    /// Cell barcode and UMI in `header`, if it carries both
+    pub fn parse<'a>(&self, header: &'a str) -> Option<(&'a str, &'a str)> {
+        match self {
+            HeaderTags::Sparc => sparc_tags(header),
+            HeaderTags::Regex(regex) => {
+                let caps = regex.captures(header)?;
+                Some((caps.name("cb")?.as_str(), caps.name("umi")?.as_str()))
+            }
+        }
+    }
+}
+
+fn sparc_tags(header: &str) -> Option<(&str, &str)> {
+    let mut fields = header.split_whitespace();
+    let name = fields.next()?;
+    let (mut barcode, mut umi) = (None, None);
+    for field in fields {
+        if let Some(value) = field.strip_prefix("CB:Z:") {
+            barcode = Some(value);
+        } else if let Some(value) = field.strip_prefix("UB:Z:") {
+            umi = Some(value);
+        }
+    }
+    if let (Some(barcode), Some(umi)) = (barcode, umi) {
+        return Some((barcode, umi));
+    }
+    let mut parts = name.rsplitn(3, '_');
+    let umi = parts.next()?;
+    let barcode = parts.next()?;
+    parts.next()?;
+    Some((barcode, umi))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_formats() {
+        let sparc = HeaderTags::default();
+        assert_eq!(sparc.parse("r1_AAAC_GGTT"), Some(("AAAC", "GGTT")));
+        assert_eq!(sparc.parse("r1 CB:Z:AAAC-1 UB:Z:GGTT"), Some(("AAAC-1", "GGTT")));
+        assert_eq!(sparc.parse("r1"), None);
+
+        let custom = HeaderTags::regex(r"BC=(?P<cb>[ACGTN]+);UMI=(?P<umi>[ACGTN]+)").unwrap();
+        assert_eq!(custom.parse("r1 BC=AAAC;UMI=GGTT"), Some(("AAAC", "GGTT")));
+        assert_eq!(custom.parse("r1_AAAC_GGTT"), None);
+
+        assert!(HeaderTags::regex(r"(?P<cb>\w+)").is_err());
+        assert!(HeaderTags::regex(r"(?P<cb>[").is_err());
+    }
+}
@@ -1,10 +1,121 @@
 //! FASTQ parsing and writing module
 
+#[cfg(feature = "async")]
+mod async_parser;
+mod header;
 mod parser;
+pub mod router;
+pub mod subsample;
+pub mod trim;
 mod writer;
 
-pub use parser::FastqParser;
-pub use writer::FastqWriter;
+#[cfg(feature = "async")]
+pub use async_parser::AsyncFastqParser;
+pub use header::HeaderTags;
+pub use parser::{check_mates, FastqParser, PairedFastqParser, ParsePolicy};
+pub use router::{FastqRouter, RouterConfig};
+pub use trim::{TrimConfig, TrimStats, Trimmer};
+pub use writer::{encode_block, FastqCompression, FastqWriter, WriterOptions};
+
+/// FASTQ quality score encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum QualityEncoding {
+    /// Sanger / Illumina 1.8+ (offset 33)
+    Phred33,
+    /// Illumina 1.3-1.7 (offset 64)
+    Phred64,
+}
+
+impl QualityEncoding {
+    /// ASCII offset of quality score 0
+    pub fn offset(&self) -> u8 {
+        match self {
+            QualityEncoding::Phred33 => 33,
+            QualityEncoding::Phred64 => 64,
+        }
+    }
+
+    /// Display name
+    pub fn name(&self) -> &'static str {
+        match self {
+            QualityEncoding::Phred33 => "Phred+33",
+            QualityEncoding::Phred64 => "Phred+64",
+        }
+    }
+
+    /// Detect the encoding from a sample of quality strings.
+    ///
+    /// Phred+64 is reported only when no byte is below `@` (Phred+64 Q0),
+    /// some exceed `J` (74, the Phred+33 ceiling for Illumina 1.8+), and none
+    /// exceed `j` (106, Q42 in Phred+64, beyond what Illumina 1.3-1.7 emits).
+    /// High-quality long reads (HiFi, ONT) with no base below Q31 therefore
+    /// stay Phred+33. Anything else, including ambiguous or empty input, is
+    /// treated as Phred+33; pass an explicit encoding when detection is wrong.
+    pub fn detect<'a, I: IntoIterator<Item = &'a [u8]>>(quals: I) -> Self {
+        let mut min = u8::MAX;
+        let mut max = 0u8;
+        for qual in quals {
+            for &q in qual {
+                min = min.min(q);
+                max = max.max(q);
+            }
+        }
+        if min >= b'@' && max > b'J' && max <= b'j' {
+            QualityEncoding::Phred64
+        } else {
+            QualityEncoding::Phred33
+        }
+    }
+}
+
+impl std::str::FromStr for QualityEncoding {
+    type Err = String;
+
+    /// Parse `phred33`/`phred+33`/`33` or `phred64`/`phred+64`/`64`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('+', "").as_str() {
+            "phred33" | "33" | "sanger" => Ok(QualityEncoding::Phred33),
+            "phred64" | "64" => Ok(QualityEncoding::Phred64),
+            _ => Err(format!(
+                "unknown quality encoding '{}' (expected phred33 or phred64)",
+                s
+            )),
+        }
+    }
+}
+
+static GLOBAL_ENCODING: std::sync::OnceLock<QualityEncoding> = std::sync::OnceLock::new();
+
+/// Force every [`FastqParser`] in the process to this quality encoding
+/// instead of detecting it (ignored if already set)
+pub fn set_global_encoding(encoding: QualityEncoding) {
+    let _ = GLOBAL_ENCODING.set(encoding);
+}
+
+/// The process-wide quality encoding override, if any
+pub fn global_encoding() -> Option<QualityEncoding> {
+    GLOBAL_ENCODING.get().copied()
+}
+
+/// How corrected barcode/UMI annotations are attached to a read header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationStyle {
+    /// Append to the read name as `name_CB_UMI` (umi_tools compatible)
+    ReadName,
+    /// Append SAM-style tags as a header comment (`CB:Z:... UB:Z:...`)
+    SamTags,
+}
+
+impl AnnotationStyle {
+    /// Parse an annotation style name ("name" or "comment")
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" | "read-name" => Some(AnnotationStyle::ReadName),
+            "comment" | "sam-tags" => Some(AnnotationStyle::SamTags),
+            _ => None,
+        }
+    }
+}
 
 /// A FASTQ record
 #[derive(Debug, Clone)]
@@ -22,24 +133,109 @@ impl FastqRecord {
         Self { id, seq, qual }
     }
 
+    /// Borrow this record as a [`FastqRecordRef`]
+    pub fn as_record_ref(&self) -> FastqRecordRef<'_> {
+        FastqRecordRef {
+            id: &self.id,
+            seq: &self.seq,
+            qual: &self.qual,
+        }
+    }
+
     /// Extract a subsequence from the record
     pub fn subsequence(&self, start: usize, len: usize) -> Option<&[u8]> {
-        if start + len <= self.seq.len() {
-            Some(&self.seq[start..start + len])
-        } else {
-            None
-        }
+        self.as_record_ref().subsequence(start, len)
     }
 
     /// Extract quality scores for a subsequence
     pub fn subqual(&self, start: usize, len: usize) -> Option<&[u8]> {
-        if start + len <= self.qual.len() {
-            Some(&self.qual[start..start + len])
-        } else {
-            None
+        self.as_record_ref().subqual(start, len)
+    }
+
+    /// Read name without the header comment (text after the first whitespace)
+    pub fn name(&self) -> &str {
+        self.as_record_ref().name()
+    }
+
+    /// Attach a cell barcode and UMI to the read header.
+    ///
+    /// `raw_barcode` is the uncorrected barcode and is only emitted (as `CR`)
+    /// in the SAM-tag style. Any existing header comment is dropped.
+    pub fn annotate(&mut self, raw_barcode: &str, barcode: &str, umi: &str, style: AnnotationStyle) {
+        let name = self.name().to_string();
+        self.id = match style {
+            AnnotationStyle::ReadName => format!("{}_{}_{}", name, barcode, umi),
+            AnnotationStyle::SamTags => format!(
+                "{}\tCR:Z:{}\tCB:Z:{}\tUR:Z:{}\tUB:Z:{}",
+                name, raw_barcode, barcode, umi, umi
+            ),
+        };
+    }
+
+    /// Cell barcode and UMI attached by [`FastqRecord::annotate`], in either style
+    pub fn barcode_umi(&self) -> Option<(&str, &str)> {
+        self.as_record_ref().barcode_umi()
+    }
+
+    /// Calculate mean quality score for the entire read
+    pub fn mean_quality(&self) -> f64 {
+        self.as_record_ref().mean_quality()
+    }
+
+    /// Calculate mean quality score for a region
+    pub fn mean_quality_region(&self, start: usize, len: usize) -> Option<f64> {
+        self.as_record_ref().mean_quality_region(start, len)
+    }
+}
+
+/// A FASTQ record borrowed from a parser's buffers (see [`FastqParser::next_ref`])
+/// or from an owned [`FastqRecord`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FastqRecordRef<'a> {
+    /// Read identifier
+    pub id: &'a str,
+    /// Sequence data
+    pub seq: &'a [u8],
+    /// Quality scores (Phred+33 encoded)
+    pub qual: &'a [u8],
+}
+
+impl<'a> FastqRecordRef<'a> {
+    /// Copy into an owned record
+    pub fn to_owned_record(&self) -> FastqRecord {
+        FastqRecord::new(self.id.to_string(), self.seq.to_vec(), self.qual.to_vec())
+    }
+
+    /// Extract a subsequence from the record
+    pub fn subsequence(&self, start: usize, len: usize) -> Option<&'a [u8]> {
+        self.seq.get(start..start.checked_add(len)?)
+    }
+
+    /// Extract quality scores for a subsequence
+    pub fn subqual(&self, start: usize, len: usize) -> Option<&'a [u8]> {
+        self.qual.get(start..start.checked_add(len)?)
+    }
+
+    /// The record restricted to bases `[start, end)`; quality is cut to the
+    /// same window, clamped to its length
+    pub fn window(&self, start: usize, end: usize) -> Self {
+        Self {
+            id: self.id,
+            seq: &self.seq[start..end],
+            qual: &self.qual[start.min(self.qual.len())..end.min(self.qual.len())],
         }
     }
 
+    /// Read name without the header comment (text after the first whitespace)
+    pub fn name(&self) -> &'a str {
+        self.id.split_whitespace().next().unwrap_or("")
+    }
+
+    /// Cell barcode and UMI attached by [`FastqRecord::annotate`], in either style
+    pub fn barcode_umi(&self) -> Option<(&'a str, &'a str)> {
+        HeaderTags::Sparc.parse(self.id)
+    }
+
     /// Calculate mean quality score for the entire read
     pub fn mean_quality(&self) -> f64 {
         if self.qual.is_empty() {
@@ -59,3 +255,73 @@ impl FastqRecord {
         Some(sum as f64 / region.len() as f64)
     }
 }
+
+impl From<FastqRecordRef<'_>> for FastqRecord {
+    fn from(record: FastqRecordRef<'_>) -> Self {
+        record.to_owned_record()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_encoding() {
+        let phred33: Vec<&[u8]> = vec![b"##II5?", b"IIIIJJ"];
+        assert_eq!(QualityEncoding::detect(phred33), QualityEncoding::Phred33);
+
+        let phred64: Vec<&[u8]> = vec![b"hhhhBB", b"@@hhfe"];
+        assert_eq!(QualityEncoding::detect(phred64), QualityEncoding::Phred64);
+
+        // Binned Phred+33 (NovaSeq `#`, `,`, `:`, `F`) never switches
+        let binned: Vec<&[u8]> = vec![b"FFFF:F", b"FF,F#F", b"FFFFFF"];
+        assert_eq!(QualityEncoding::detect(binned), QualityEncoding::Phred33);
+
+        let empty: Vec<&[u8]> = Vec::new();
+        assert_eq!(QualityEncoding::detect(empty), QualityEncoding::Phred33);
+
+        // Q45 Phred+33 long reads whose lowest base is Q30 (`?`)
+        let long_reads: Vec<&[u8]> = vec![b"NNNNNNNNNN", b"NNN?NNNNLN"];
+        assert_eq!(QualityEncoding::detect(long_reads), QualityEncoding::Phred33);
+
+        // HiFi caps at Q93 (`~`), which Phred+64 data never reaches
+        let hifi: Vec<&[u8]> = vec![b"~~~~N~~~~~", b"~~~~~~~~~~"];
+        assert_eq!(QualityEncoding::detect(hifi), QualityEncoding::Phred33);
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!("phred+33".parse(), Ok(QualityEncoding::Phred33));
+        assert_eq!("Phred64".parse(), Ok(QualityEncoding::Phred64));
+        assert!("phred42".parse::<QualityEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_annotate_read_name() {
+        let mut record = FastqRecord::new(
+            "read1 1:N:0:ACGT".to_string(),
+            b"ACGT".to_vec(),
+            b"IIII".to_vec(),
+        );
+        record.annotate("AAAC", "AAAA", "GGGG", AnnotationStyle::ReadName);
+        assert_eq!(record.id, "read1_AAAA_GGGG");
+    }
+
+    #[test]
+    fn test_annotate_sam_tags() {
+        let mut record = FastqRecord::new("read1".to_string(), b"ACGT".to_vec(), b"IIII".to_vec());
+        record.annotate("AAAC", "AAAA", "GGGG", AnnotationStyle::SamTags);
+        assert_eq!(record.id, "read1\tCR:Z:AAAC\tCB:Z:AAAA\tUR:Z:GGGG\tUB:Z:GGGG");
+        assert_eq!(record.name(), "read1");
+        assert_eq!(record.barcode_umi(), Some(("AAAA", "GGGG")));
+    }
+
+    #[test]
+    fn test_barcode_umi_from_read_name() {
+        let mut record = FastqRecord::new("read_1".to_string(), b"ACGT".to_vec(), b"IIII".to_vec());
+        assert_eq!(record.barcode_umi(), None);
+        record.annotate("AAAC", "AAAA", "GGGG", AnnotationStyle::ReadName);
+        assert_eq!(record.barcode_umi(), Some(("AAAA", "GGGG")));
+    }
+}
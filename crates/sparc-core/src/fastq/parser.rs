@@ -1,37 +1,477 @@
 //! FASTQ file parser with parallel processing support
 
-use super::FastqRecord;
-use crate::{Error, Result};
-use needletail::{parse_fastx_file, FastxReader};
+use super::{FastqRecord, FastqRecordRef, QualityEncoding};
+#[cfg(feature = "native")]
+use crate::resources;
+use crate::{remote, Error, RecordContext, Result};
+#[cfg(feature = "native")]
+use needletail::parse_fastx_file;
+use needletail::{parse_fastx_reader, FastxReader};
 use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Read;
+#[cfg(feature = "native")]
+use std::io::{self, BufRead};
 use std::path::Path;
+use std::str::FromStr;
+#[cfg(feature = "native")]
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+/// Number of leading records inspected to detect the quality encoding
+const ENCODING_DETECT_RECORDS: usize = 1000;
+
+/// Consecutive malformed records after which lenient parsing gives up, so
+/// input that is not FASTQ at all still fails
+const MAX_CONSECUTIVE_SKIPS: u64 = 1000;
+
+/// Skipped records logged individually before going quiet
+const LOGGED_SKIPS: u64 = 10;
+
+/// What a parser does with a malformed record (bad header, sequence and
+/// quality of different lengths, quality characters out of range)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParsePolicy {
+    /// Fail with the record's location
+    #[default]
+    Strict,
+    /// Skip the record and count it
+    Lenient,
+}
+
+impl FromStr for ParsePolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strict" => Ok(ParsePolicy::Strict),
+            "lenient" => Ok(ParsePolicy::Lenient),
+            _ => Err(Error::Config(format!(
+                "unknown parse policy '{}' (expected strict or lenient)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ParsePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParsePolicy::Strict => "strict",
+            ParsePolicy::Lenient => "lenient",
+        })
+    }
+}
+
+/// Decompressed bytes per message from a read-ahead thread
+#[cfg(feature = "native")]
+const READ_AHEAD_CHUNK: u64 = 1 << 20;
+
+/// Open a FASTX reader over a file (compression detected by needletail). With
+/// `--decompress-threads`, gzip and zstd input is decoded on a read-ahead
+/// thread instead of the parsing thread.
+#[cfg(feature = "native")]
+fn fastx_file(path: &Path) -> std::result::Result<Box<dyn FastxReader>, String> {
+    if resources::global().threads.decompress_or(0) > 0 {
+        if let Some(reader) = ReadAhead::open(path).map_err(|e| e.to_string())? {
+            return fastx_reader(reader);
+        }
+    }
+    parse_fastx_file(path).map_err(|e| e.to_string())
+}
+
+/// Compressed input decoded on a background thread, a few chunks ahead of
+/// the parser
+#[cfg(feature = "native")]
+struct ReadAhead {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+#[cfg(feature = "native")]
+impl ReadAhead {
+    /// Start decoding `path` if it is gzip or zstd; `None` for plain files
+    fn open(path: &Path) -> io::Result<Option<Self>> {
+        let mut file = io::BufReader::new(std::fs::File::open(path)?);
+        let (gzip, zstd) = {
+            let magic = file.fill_buf()?;
+            (magic.starts_with(&[0x1f, 0x8b]), magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]))
+        };
+        let decoder: Box<dyn Read + Send> = match (gzip, zstd) {
+            (true, _) => Box::new(flate2::read::MultiGzDecoder::new(file)),
+            (_, true) => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+            _ => return Ok(None),
+        };
+        let (tx, chunks) = sync_channel(4);
+        std::thread::spawn(move || decode_ahead(decoder, &tx));
+        Ok(Some(Self {
+            chunks,
+            chunk: Vec::new(),
+            pos: 0,
+        }))
+    }
+}
+
+#[cfg(feature = "native")]
+impl Read for ReadAhead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(Ok(chunk)) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Send `decoder`'s output in chunks until it ends, fails, or the reader
+/// goes away
+#[cfg(feature = "native")]
+fn decode_ahead(mut decoder: Box<dyn Read + Send>, tx: &SyncSender<io::Result<Vec<u8>>>) {
+    loop {
+        let mut chunk = Vec::with_capacity(READ_AHEAD_CHUNK as usize);
+        match decoder.by_ref().take(READ_AHEAD_CHUNK).read_to_end(&mut chunk) {
+            Ok(0) => return,
+            Ok(_) => {
+                if tx.send(Ok(chunk)).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                return;
+            }
+        }
+    }
+}
+
+/// Open a FASTX reader over a file (plain or gzip)
+#[cfg(not(feature = "native"))]
+fn fastx_file(path: &Path) -> std::result::Result<Box<dyn FastxReader>, String> {
+    fastx_reader(std::fs::File::open(path).map_err(|e| e.to_string())?)
+}
+
+/// Open a FASTX reader over a stream (compression detected by needletail)
+#[cfg(feature = "native")]
+fn fastx_reader<R: Read + Send + 'static>(
+    reader: R,
+) -> std::result::Result<Box<dyn FastxReader>, String> {
+    parse_fastx_reader(reader).map_err(|e| e.to_string())
+}
+
+/// Open a FASTX reader over a stream. Without the native decompressors only
+/// gzip is recognised, and decoded in pure Rust so it also works on wasm32.
+#[cfg(not(feature = "native"))]
+fn fastx_reader<R: Read + Send + 'static>(
+    reader: R,
+) -> std::result::Result<Box<dyn FastxReader>, String> {
+    use std::io::BufRead;
+
+    let mut reader = std::io::BufReader::new(reader);
+    let gzipped = reader.fill_buf().map_err(|e| e.to_string())?.starts_with(&[0x1f, 0x8b]);
+    let reader: Box<dyn Read + Send> = if gzipped {
+        Box::new(flate2::read::MultiGzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+    parse_fastx_reader(reader).map_err(|e| e.to_string())
+}
 
 /// Parallel FASTQ parser using needletail
+///
+/// The quality encoding is detected from the first records when they are
+/// first read (not when the file is opened). Phred+64 input is
+/// converted to Phred+33 on the fly so downstream quality math stays correct.
+/// Malformed records produce [`Error::FastqRecord`] errors naming the file,
+/// 1-based record number, and (where known) read name and byte offset, or are
+/// skipped under [`ParsePolicy::Lenient`].
 pub struct FastqParser {
     reader: Box<dyn FastxReader>,
+    path: String,
+    /// Records read from the input, including those still in `pending`
+    record_num: u64,
+    /// Quality encoding, once detected
+    encoding: Option<QualityEncoding>,
+    pending: VecDeque<Result<(RecordPos, FastqRecord)>>,
+    /// Reused buffers behind the record lent by `next_ref`
+    scratch: FastqRecord,
+    policy: ParsePolicy,
+    skipped: u64,
+    consecutive_skips: u64,
+}
+
+/// Record number and byte offset (into the decompressed stream) of a record
+#[derive(Debug, Clone, Copy)]
+struct RecordPos {
+    num: u64,
+    byte: u64,
 }
 
 impl FastqParser {
-    /// Open a FASTQ file (supports .gz and .zst compression)
+    /// Open a FASTQ file (supports .gz and .zst compression).
+    ///
+    /// `http(s)://` and `s3://` URIs are streamed when built with the `remote` feature.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let p = path.as_ref();
         log::info!("Opening FASTQ file: {:?}", p);
-        let reader = parse_fastx_file(p)
-            .map_err(|e| Error::FastqParse(format!("Failed to open FASTQ: {}", e)))?;
-        Ok(Self { reader })
+        let reader = if remote::is_remote(p) {
+            let uri = p.to_string_lossy();
+            fastx_reader(remote::open_stream(&uri)?)
+        } else {
+            fastx_file(p)
+        }
+        .map_err(|e| Error::FastqParse(format!("{}: failed to open FASTQ: {}", p.display(), e)))?;
+
+        Self::from_fastx(reader, p.display().to_string())
+    }
+
+    /// Parse FASTQ from any reader (compression is detected from the stream).
+    ///
+    /// `name` is used in log and error messages in place of a file path.
+    pub fn from_reader<R: Read + Send + 'static>(reader: R, name: &str) -> Result<Self> {
+        let reader = fastx_reader(reader)
+            .map_err(|e| Error::FastqParse(format!("{}: failed to open FASTQ: {}", name, e)))?;
+        Self::from_fastx(reader, name.to_string())
+    }
+
+    fn from_fastx(reader: Box<dyn FastxReader>, path: String) -> Result<Self> {
+        Ok(Self {
+            reader,
+            path,
+            record_num: 0,
+            encoding: super::global_encoding(),
+            pending: VecDeque::new(),
+            scratch: FastqRecord::new(String::new(), Vec::new(), Vec::new()),
+            policy: ParsePolicy::Strict,
+            skipped: 0,
+            consecutive_skips: 0,
+        })
+    }
+
+    /// Skip malformed records instead of failing on them
+    pub fn with_policy(mut self, policy: ParsePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Use this quality encoding instead of detecting it
+    pub fn with_encoding(mut self, encoding: QualityEncoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Quality encoding in effect: the forced or detected one, or Phred+33
+    /// before the first read (see [`FastqParser::detect_encoding`])
+    pub fn encoding(&self) -> QualityEncoding {
+        self.encoding.unwrap_or(QualityEncoding::Phred33)
+    }
+
+    /// Quality encoding of the input, reading ahead to detect it if needed
+    pub fn detect_encoding(&mut self) -> QualityEncoding {
+        match self.encoding {
+            Some(encoding) => encoding,
+            None => self.sample_encoding(),
+        }
+    }
+
+    /// Malformed records skipped so far (always 0 under the strict policy)
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Number of the last record taken from the input, skipped or not
+    fn consumed(&self) -> u64 {
+        self.record_num - self.pending.len() as u64
+    }
+
+    /// Buffer the leading records and detect their quality encoding. A
+    /// malformed record ends the sample; its error is raised (or skipped) when
+    /// iteration reaches it.
+    fn sample_encoding(&mut self) -> QualityEncoding {
+        while self.pending.len() < ENCODING_DETECT_RECORDS {
+            match self.read_raw() {
+                Some(item) => {
+                    let malformed = item.is_err();
+                    self.pending.push_back(item);
+                    if malformed {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        let quals = self.pending.iter().flatten().map(|(_, r)| r.qual.as_slice());
+        let encoding = QualityEncoding::detect(quals);
+        if encoding == QualityEncoding::Phred64 {
+            log::warn!(
+                "{}: detected Phred+64 quality encoding; converting to Phred+33",
+                self.path
+            );
+        }
+        self.encoding = Some(encoding);
+        encoding
+    }
+
+    /// Error context for the record at `pos` named `id`
+    fn context(&self, pos: RecordPos, id: &str) -> RecordContext {
+        RecordContext::new(self.path.as_str(), pos.num)
+            .with_read_name(id)
+            .with_byte_offset(pos.byte)
+    }
+
+    /// Error context for a record needletail could not parse. Read failures
+    /// are not tied to a record and are never skipped.
+    fn parse_error(&self, num: u64, e: needletail::errors::ParseError) -> Error {
+        if matches!(e.kind, needletail::errors::ParseErrorKind::Io) {
+            return Error::FastqParse(format!("{}: record {}: {}", self.path, num, e));
+        }
+        let mut context = RecordContext::new(self.path.as_str(), num);
+        if let Some(id) = &e.position.id {
+            context = context.with_read_name(id.as_str());
+        }
+        context.fastq_error(e.to_string())
+    }
+
+    /// Read the next record, checking sequence/quality length consistency
+    fn read_raw(&mut self) -> Option<Result<(RecordPos, FastqRecord)>> {
+        let result = self.reader.next()?;
+        self.record_num += 1;
+        let num = self.record_num;
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => return Some(Err(self.parse_error(num, e))),
+        };
+        let pos = RecordPos {
+            num,
+            byte: record.position().byte(),
+        };
+        let id = String::from_utf8_lossy(record.id()).to_string();
+        let seq = record.seq().to_vec();
+        let qual = record.qual().map(|q| q.to_vec()).unwrap_or_default();
+        if let Err(msg) = check_lengths(&seq, &qual) {
+            return Some(Err(self.context(pos, &id).fastq_error(msg)));
+        }
+        Some(Ok((pos, FastqRecord::new(id, seq, qual))))
+    }
+
+    /// Validate quality bytes and normalize them to Phred+33
+    fn normalize(&mut self, pos: RecordPos, mut record: FastqRecord) -> Result<FastqRecord> {
+        normalize_qual(self.detect_encoding(), &mut record.qual)
+            .map_err(|msg| self.context(pos, &record.id).fastq_error(msg))?;
+        Ok(record)
+    }
+
+    /// Under the lenient policy, count and swallow a malformed record's
+    /// error; any other error is handed back
+    fn skip(&mut self, e: Error) -> Result<()> {
+        let skippable = self.policy == ParsePolicy::Lenient && e.record_context().is_some();
+        if !skippable || self.consecutive_skips >= MAX_CONSECUTIVE_SKIPS {
+            return Err(e);
+        }
+        self.skipped += 1;
+        self.consecutive_skips += 1;
+        if self.skipped <= LOGGED_SKIPS {
+            log::warn!("Skipping malformed record: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Next record with its record number
+    fn next_numbered(&mut self) -> Option<Result<(u64, FastqRecord)>> {
+        self.detect_encoding();
+        loop {
+            let item = match self.pending.pop_front() {
+                Some(item) => item,
+                None => self.read_raw()?,
+            };
+            match item.and_then(|(pos, record)| Ok((pos.num, self.normalize(pos, record)?))) {
+                Ok(item) => {
+                    self.consecutive_skips = 0;
+                    return Some(Ok(item));
+                }
+                Err(e) => {
+                    if let Err(e) = self.skip(e) {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Next record, borrowed from buffers the parser reuses for every call.
+    ///
+    /// Avoids allocating the id, sequence, and quality of each read; call
+    /// [`FastqRecordRef::to_owned_record`] to keep a record past the next call.
+    pub fn next_ref(&mut self) -> Option<Result<FastqRecordRef<'_>>> {
+        loop {
+            match self.fill_scratch()? {
+                Ok(()) => break,
+                Err(e) => {
+                    if let Err(e) = self.skip(e) {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+        self.consecutive_skips = 0;
+        Some(Ok(self.scratch.as_record_ref()))
+    }
+
+    /// Load the next record into `scratch`
+    fn fill_scratch(&mut self) -> Option<Result<()>> {
+        let encoding = self.detect_encoding();
+        let pos = match self.pending.pop_front() {
+            Some(Ok((pos, record))) => {
+                self.scratch = record;
+                pos
+            }
+            Some(Err(e)) => return Some(Err(e)),
+            None => {
+                let result = self.reader.next()?;
+                self.record_num += 1;
+                let num = self.record_num;
+                let record = match result {
+                    Ok(record) => record,
+                    Err(e) => return Some(Err(self.parse_error(num, e))),
+                };
+                let pos = RecordPos {
+                    num,
+                    byte: record.position().byte(),
+                };
+                let scratch = &mut self.scratch;
+                scratch.id.clear();
+                scratch.id.push_str(&String::from_utf8_lossy(record.id()));
+                scratch.seq.clear();
+                scratch.seq.extend_from_slice(&record.seq());
+                scratch.qual.clear();
+                scratch.qual.extend_from_slice(record.qual().unwrap_or_default());
+                if let Err(msg) = check_lengths(&scratch.seq, &scratch.qual) {
+                    return Some(Err(self.context(pos, &self.scratch.id).fastq_error(msg)));
+                }
+                pos
+            }
+        };
+        if let Err(msg) = normalize_qual(encoding, &mut self.scratch.qual) {
+            return Some(Err(self.context(pos, &self.scratch.id).fastq_error(msg)));
+        }
+        Some(Ok(()))
     }
 
     /// Read all records into memory
     pub fn read_all(&mut self) -> Result<Vec<FastqRecord>> {
         let mut records = Vec::new();
-        while let Some(record) = self.reader.next() {
-            let record =
-                record.map_err(|e| Error::FastqParse(format!("Failed to read record: {}", e)))?;
-            records.push(FastqRecord::new(
-                String::from_utf8_lossy(record.id()).to_string(),
-                record.seq().to_vec(),
-                record.qual().map(|q| q.to_vec()).unwrap_or_default(),
-            ));
+        for record in self.by_ref() {
+            records.push(record?);
         }
         Ok(records)
     }
@@ -55,46 +495,346 @@ impl Iterator for FastqParser {
     type Item = Result<FastqRecord>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.reader.next().map(|result| {
-            result
-                .map(|record| {
-                    FastqRecord::new(
-                        String::from_utf8_lossy(record.id()).to_string(),
-                        record.seq().to_vec(),
-                        record.qual().map(|q| q.to_vec()).unwrap_or_default(),
-                    )
-                })
-                .map_err(|e| Error::FastqParse(format!("Failed to read record: {}", e)))
-        })
+        Some(self.next_numbered()?.map(|(_, record)| record))
+    }
+}
+
+/// Fail when a record has quality scores that do not cover its sequence
+fn check_lengths(seq: &[u8], qual: &[u8]) -> std::result::Result<(), String> {
+    if !qual.is_empty() && qual.len() != seq.len() {
+        return Err(format!(
+            "sequence length {} does not match quality length {}",
+            seq.len(),
+            qual.len()
+        ));
     }
+    Ok(())
+}
+
+/// Validate quality bytes against the encoding and convert them to Phred+33
+pub(super) fn normalize_qual(
+    encoding: QualityEncoding,
+    qual: &mut [u8],
+) -> std::result::Result<(), String> {
+    let offset = encoding.offset();
+    if let Some(&bad) = qual.iter().find(|&&q| q < offset || q > b'~') {
+        return Err(format!(
+            "quality character {:?} is outside the {} range",
+            bad as char,
+            encoding.name()
+        ));
+    }
+    if encoding == QualityEncoding::Phred64 {
+        for q in qual.iter_mut() {
+            *q -= 31;
+        }
+    }
+    Ok(())
 }
 
 /// Parse paired-end FASTQ files together
 pub struct PairedFastqParser {
     r1_parser: FastqParser,
     r2_parser: FastqParser,
+    check_names: bool,
+    pair_num: u64,
+    /// Well-formed mates dropped because the other read was skipped
+    orphans: u64,
 }
 
+/// A record and its number, as read by [`FastqParser::next_numbered`]
+type Numbered = Option<Result<(u64, FastqRecord)>>;
+
 impl PairedFastqParser {
     pub fn open<P: AsRef<Path>>(r1_path: P, r2_path: P) -> Result<Self> {
         Ok(Self {
             r1_parser: FastqParser::open(r1_path)?,
             r2_parser: FastqParser::open(r2_path)?,
+            check_names: false,
+            pair_num: 0,
+            orphans: 0,
         })
     }
+
+    /// Fail when R1 and R2 read names differ (ignoring `/1`, `/2` and comments)
+    pub fn check_names(mut self, check: bool) -> Self {
+        self.check_names = check;
+        self
+    }
+
+    /// Skip malformed records in either file; the other mate is dropped too
+    pub fn with_policy(mut self, policy: ParsePolicy) -> Self {
+        self.r1_parser.policy = policy;
+        self.r2_parser.policy = policy;
+        self
+    }
+
+    /// Malformed records skipped in R1 and R2
+    pub fn skipped(&self) -> u64 {
+        self.r1_parser.skipped() + self.r2_parser.skipped()
+    }
+
+    /// Well-formed mates dropped along with a skipped record
+    pub fn orphans(&self) -> u64 {
+        self.orphans
+    }
+}
+
+/// Whether `item` is a record whose mate the other parser has already skipped
+fn is_orphan(item: &Numbered, mate: &Numbered, mate_parser: &FastqParser) -> bool {
+    let Some(Ok((num, _))) = item else {
+        return false;
+    };
+    match mate {
+        Some(Ok((mate_num, _))) => num < mate_num,
+        Some(Err(_)) => false,
+        None => mate_parser.consumed() >= *num,
+    }
+}
+
+/// Check that `a` and `b`, read from the `inputs` named, are mates of pair
+/// `pair_num`; their names must match up to the comment and `/1`/`/2` suffix
+pub fn check_mates(
+    a: &FastqRecord,
+    b: &FastqRecord,
+    inputs: (&str, &str),
+    pair_num: u64,
+) -> Result<()> {
+    if mate_name(&a.id) == mate_name(&b.id) {
+        return Ok(());
+    }
+    Err(Error::FastqParse(format!(
+        "{}/{} out of sync at pair {}: '{}' vs '{}'",
+        inputs.0, inputs.1, pair_num, a.id, b.id
+    )))
+}
+
+/// Read name without the comment or a `/1`/`/2` mate suffix
+fn mate_name(id: &str) -> &str {
+    let name = id.split_whitespace().next().unwrap_or("");
+    name.strip_suffix("/1")
+        .or_else(|| name.strip_suffix("/2"))
+        .unwrap_or(name)
 }
 
 impl Iterator for PairedFastqParser {
     type Item = Result<(FastqRecord, FastqRecord)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match (self.r1_parser.next(), self.r2_parser.next()) {
-            (Some(Ok(r1)), Some(Ok(r2))) => Some(Ok((r1, r2))),
+        self.pair_num += 1;
+        let mut r1 = self.r1_parser.next_numbered();
+        let mut r2 = self.r2_parser.next_numbered();
+        // Record numbers only drift apart when a malformed record was skipped
+        loop {
+            if is_orphan(&r1, &r2, &self.r2_parser) {
+                self.orphans += 1;
+                r1 = self.r1_parser.next_numbered();
+            } else if is_orphan(&r2, &r1, &self.r1_parser) {
+                self.orphans += 1;
+                r2 = self.r2_parser.next_numbered();
+            } else {
+                break;
+            }
+        }
+        match (r1, r2) {
+            (Some(Ok((_, r1))), Some(Ok((_, r2)))) => {
+                if self.check_names {
+                    if let Err(e) = check_mates(&r1, &r2, ("R1", "R2"), self.pair_num) {
+                        return Some(Err(e));
+                    }
+                }
+                Some(Ok((r1, r2)))
+            }
             (Some(Err(e)), _) | (_, Some(Err(e))) => Some(Err(e)),
             (None, None) => None,
-            _ => Some(Err(Error::FastqParse(
-                "Paired FASTQ files have different lengths".to_string(),
-            ))),
+            (Some(_), None) => Some(Err(Error::FastqParse(format!(
+                "R2 ended before R1 at pair {}",
+                self.pair_num
+            )))),
+            (None, Some(_)) => Some(Err(Error::FastqParse(format!(
+                "R1 ended before R2 at pair {}",
+                self.pair_num
+            )))),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_read_ahead_decodes_gzip() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir = tempdir().unwrap();
+        let text = "@r1\nACGT\n+\nIIII\n".repeat(100_000);
+        let gz = dir.path().join("reads.fastq.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(text.as_bytes()).unwrap();
+        std::fs::write(&gz, encoder.finish().unwrap()).unwrap();
+
+        let mut decoded = String::new();
+        let mut reader = ReadAhead::open(&gz).unwrap().unwrap();
+        reader.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+
+        let plain = dir.path().join("reads.fastq");
+        std::fs::write(&plain, &text).unwrap();
+        assert!(ReadAhead::open(&plain).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_phred64_converted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("old.fastq");
+        std::fs::write(&path, "@r1\nACGT\n+\nhhhB\n@r2\nACGT\n+\n@@hh\n").unwrap();
+
+        let mut parser = FastqParser::open(&path).unwrap();
+        // Detection waits for the first read
+        assert_eq!((parser.record_num, parser.encoding), (0, None));
+        assert_eq!(parser.detect_encoding(), QualityEncoding::Phred64);
+        assert_eq!(parser.encoding(), QualityEncoding::Phred64);
+        let records = parser.read_all().unwrap();
+        assert_eq!(records[0].qual, b"III#".to_vec());
+        assert!((records[1].mean_quality() - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_forced_encoding() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("hq.fastq");
+        // Q45 Phred+33 that detection would take for Phred+64
+        std::fs::write(&path, "@r1\nACGT\n+\nNNNN\n@r2\nACGT\n+\nNNKN\n").unwrap();
+
+        let records = FastqParser::open(&path)
+            .unwrap()
+            .with_encoding(QualityEncoding::Phred33)
+            .read_all()
+            .unwrap();
+        assert_eq!(records[0].qual, b"NNNN".to_vec());
+        assert!((records[0].mean_quality() - 45.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_length_mismatch_reports_record() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bad.fastq");
+        std::fs::write(&path, "@r1\nACGT\n+\nIIII\n@r2\nACGT\n+\nIII\n").unwrap();
+
+        let err = FastqParser::open(&path)
+            .and_then(|mut p| p.read_all())
+            .unwrap_err();
+        let context = err.record_context().unwrap();
+        assert_eq!(context.record, 2);
+        assert_eq!(context.read_name.as_deref(), Some("r2"));
+        assert_eq!(context.byte_offset, Some(16));
+        let err = err.to_string();
+        assert!(err.contains("bad.fastq: record 2 ('r2') at byte 16"), "{}", err);
+    }
+
+    #[test]
+    fn test_next_ref_matches_owned() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("old.fastq");
+        // More records than the encoding sniffer buffers, so both paths run
+        let text: String = (0..ENCODING_DETECT_RECORDS + 5)
+            .map(|i| format!("@r{} c\nACGT\n+\nhhhB\n", i))
+            .collect();
+        std::fs::write(&path, text).unwrap();
+
+        let owned = FastqParser::open(&path).unwrap().read_all().unwrap();
+        let mut parser = FastqParser::open(&path).unwrap();
+        let mut borrowed = Vec::new();
+        while let Some(record) = parser.next_ref() {
+            borrowed.push(record.unwrap().to_owned_record());
+        }
+        assert_eq!(borrowed.len(), ENCODING_DETECT_RECORDS + 5);
+        for (a, b) in owned.iter().zip(&borrowed) {
+            assert_eq!(a.as_record_ref(), b.as_record_ref());
+        }
+        let last = borrowed.last().unwrap();
+        assert_eq!(last.qual, b"III#".to_vec());
+        assert_eq!(last.name(), format!("r{}", ENCODING_DETECT_RECORDS + 4));
+    }
+
+    #[test]
+    fn test_paired_name_check() {
+        let dir = tempdir().unwrap();
+        let r1 = dir.path().join("r1.fastq");
+        let r2 = dir.path().join("r2.fastq");
+        std::fs::write(&r1, "@a/1\nACGT\n+\nIIII\n@b/1\nACGT\n+\nIIII\n").unwrap();
+        std::fs::write(&r2, "@a/2 x\nTTTT\n+\nIIII\n@c/2\nTTTT\n+\nIIII\n").unwrap();
+
+        let mut pairs = PairedFastqParser::open(&r1, &r2).unwrap().check_names(true);
+        assert!(pairs.next().unwrap().is_ok());
+        let err = pairs.next().unwrap().unwrap_err().to_string();
+        assert!(err.contains("pair 2"), "{}", err);
+
+        let unchecked = PairedFastqParser::open(&r1, &r2).unwrap();
+        assert_eq!(unchecked.filter(|p| p.is_ok()).count(), 2);
+
+        let read = |id: &str| FastqRecord::new(id.to_string(), b"AC".to_vec(), b"II".to_vec());
+        assert!(check_mates(&read("a 1:N:0"), &read("a 3:N:0"), ("R1", "I1"), 1).is_ok());
+        let err = check_mates(&read("a"), &read("b"), ("R1", "I1"), 7).unwrap_err().to_string();
+        assert!(err.contains("R1/I1 out of sync at pair 7"), "{}", err);
+    }
+
+    #[test]
+    fn test_paired_length_mismatch_reports_pair() {
+        let dir = tempdir().unwrap();
+        let r1 = dir.path().join("r1.fastq");
+        let r2 = dir.path().join("r2.fastq");
+        std::fs::write(&r1, "@a\nACGT\n+\nIIII\n@b\nACGT\n+\nIIII\n").unwrap();
+        std::fs::write(&r2, "@a\nTTTT\n+\nIIII\n").unwrap();
+
+        let mut pairs = PairedFastqParser::open(&r1, &r2).unwrap();
+        assert!(pairs.next().unwrap().is_ok());
+        let err = pairs.next().unwrap().unwrap_err().to_string();
+        assert!(err.contains("R2 ended before R1 at pair 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_lenient_skips_malformed_records() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("bad.fastq");
+        std::fs::write(&path, "@a\nACGT\n+\nIIII\n@b\nACGT\n+\nIII\n@c\nACGT\n+\nIIII\n")
+            .unwrap();
+
+        assert!(FastqParser::open(&path).unwrap().read_all().is_err());
+        let mut parser = FastqParser::open(&path).unwrap().with_policy(ParsePolicy::Lenient);
+        let names: Vec<String> = parser.by_ref().map(|r| r.unwrap().id).collect();
+        assert_eq!(names, ["a", "c"]);
+        assert_eq!(parser.skipped(), 1);
+
+        let mut parser = FastqParser::open(&path).unwrap().with_policy(ParsePolicy::Lenient);
+        let mut borrowed = 0;
+        while let Some(record) = parser.next_ref() {
+            record.unwrap();
+            borrowed += 1;
+        }
+        assert_eq!((borrowed, parser.skipped()), (2, 1));
+    }
+
+    #[test]
+    fn test_lenient_pairs_drop_orphaned_mates() {
+        let dir = tempdir().unwrap();
+        let r1 = dir.path().join("r1.fastq");
+        let r2 = dir.path().join("r2.fastq");
+        std::fs::write(&r1, "@a\nACGT\n+\nIIII\n@b\nACGT\n+\nII\n@c\nACGT\n+\nIIII\n")
+            .unwrap();
+        std::fs::write(&r2, "@a\nTTTT\n+\nIIII\n@b\nTTTT\n+\nIIII\n@c\nTTTT\n+\nIII\n")
+            .unwrap();
+
+        let mut pairs = PairedFastqParser::open(&r1, &r2)
+            .unwrap()
+            .check_names(true)
+            .with_policy(ParsePolicy::Lenient);
+        let names: Vec<String> = pairs.by_ref().map(|p| p.unwrap().0.id).collect();
+        assert_eq!(names, ["a"]);
+        assert_eq!((pairs.skipped(), pairs.orphans()), (2, 2));
+    }
+}
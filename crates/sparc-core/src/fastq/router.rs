@@ -0,0 +1,323 @@
+//! Many-file FASTQ output, routing records to one file per cell or group
+//!
+//! Records are buffered per key and flushed in batches. Only `max_open` file
+//! handles are kept open at a time; the least recently used handle is closed
+//! when another is needed and the file is reopened in append mode later.
+//! Buffers share a global `max_buffered_bytes` budget: when it is exceeded the
+//! largest buffers are flushed, so memory stays bounded however many keys exist.
+//! Gzip output stays valid because concatenated gzip members form a valid stream.
+
+use super::subsample::read_hash;
+use super::FastqRecord;
+use crate::{Error, Result};
+use ahash::{AHashMap, AHashSet};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Configuration for [`FastqRouter`]
+#[derive(Debug, Clone)]
+pub struct RouterConfig {
+    /// Maximum number of simultaneously open output files
+    pub max_open: usize,
+    /// Per-key buffer size (bytes) that triggers a flush
+    pub batch_bytes: usize,
+    /// Total bytes buffered across all keys before the largest buffers are flushed
+    pub max_buffered_bytes: usize,
+    /// Write gzip-compressed output (`.fastq.gz`)
+    pub compress: bool,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            max_open: 256,
+            batch_bytes: 64 * 1024,
+            max_buffered_bytes: 64 * 1024 * 1024,
+            compress: true,
+        }
+    }
+}
+
+/// An open per-key output file
+enum Handle {
+    Plain(BufWriter<File>),
+    Gzip(BufWriter<GzEncoder<File>>),
+}
+
+impl Handle {
+    fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        match self {
+            Handle::Plain(writer) => writer.write_all(data)?,
+            Handle::Gzip(writer) => writer.write_all(data)?,
+        }
+        Ok(())
+    }
+
+    /// Flush and close the file, ending a gzip member with its trailer
+    fn close(self) -> Result<()> {
+        match self {
+            Handle::Plain(mut writer) => writer.flush()?,
+            Handle::Gzip(writer) => {
+                let encoder = writer.into_inner().map_err(|e| e.into_error())?;
+                encoder.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Routes FASTQ records to per-key files under an output directory
+pub struct FastqRouter {
+    dir: PathBuf,
+    config: RouterConfig,
+    buffers: AHashMap<String, Vec<u8>>,
+    buffered: usize,
+    handles: AHashMap<String, (Handle, u64)>,
+    created: AHashSet<String>,
+    /// Output file name -> the key that owns it
+    files: AHashMap<PathBuf, String>,
+    counts: AHashMap<String, u64>,
+    clock: u64,
+}
+
+impl FastqRouter {
+    /// Create a router writing into `dir` (created if missing)
+    pub fn new<P: AsRef<Path>>(dir: P, config: RouterConfig) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            config,
+            buffers: AHashMap::new(),
+            buffered: 0,
+            handles: AHashMap::new(),
+            created: AHashSet::new(),
+            files: AHashMap::new(),
+            counts: AHashMap::new(),
+            clock: 0,
+        })
+    }
+
+    /// Output path for a key
+    ///
+    /// Characters outside `[A-Za-z0-9.-]` become `_`; keys changed that way get
+    /// a hash of the original key appended so that e.g. `A/B` and `A_B` differ.
+    pub fn path_for(&self, key: &str) -> PathBuf {
+        let mut name: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect();
+        if name != key {
+            name = format!("{}_{:08x}", name, read_hash(key, 0) as u32);
+        }
+        let ext = if self.config.compress { "fastq.gz" } else { "fastq" };
+        self.dir.join(format!("{}.{}", name, ext))
+    }
+
+    /// Buffer a record for `key`, flushing the key's batch when full
+    pub fn write(&mut self, key: &str, record: &FastqRecord) -> Result<()> {
+        let buf = self.buffers.entry(key.to_string()).or_default();
+        let before = buf.len();
+        buf.push(b'@');
+        buf.extend_from_slice(record.id.as_bytes());
+        buf.push(b'\n');
+        buf.extend_from_slice(&record.seq);
+        buf.extend_from_slice(b"\n+\n");
+        buf.extend_from_slice(&record.qual);
+        buf.push(b'\n');
+        let full = buf.len() >= self.config.batch_bytes;
+        self.buffered += buf.len() - before;
+
+        *self.counts.entry(key.to_string()).or_insert(0) += 1;
+
+        if full {
+            self.flush_key(key)?;
+        }
+        if self.buffered > self.config.max_buffered_bytes {
+            self.flush_largest()?;
+        }
+        Ok(())
+    }
+
+    /// Bytes currently buffered across all keys
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered
+    }
+
+    /// Number of distinct keys seen
+    pub fn num_keys(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Number of currently open file handles
+    pub fn open_handles(&self) -> usize {
+        self.handles.len()
+    }
+
+    fn flush_key(&mut self, key: &str) -> Result<()> {
+        let data = match self.buffers.get_mut(key) {
+            Some(buf) if !buf.is_empty() => std::mem::take(buf),
+            _ => return Ok(()),
+        };
+        self.buffered -= data.len();
+
+        self.clock += 1;
+        if !self.handles.contains_key(key) {
+            if self.handles.len() >= self.config.max_open.max(1) {
+                self.evict_lru()?;
+            }
+            let writer = self.open(key)?;
+            self.handles.insert(key.to_string(), (writer, self.clock));
+        }
+
+        let (writer, last_used) = self
+            .handles
+            .get_mut(key)
+            .expect("handle inserted above");
+        *last_used = self.clock;
+        writer.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Flush the largest buffers until at most half the budget remains, so
+    /// the scan over keys is amortized over many writes
+    fn flush_largest(&mut self) -> Result<()> {
+        let mut sizes: Vec<(usize, String)> = self
+            .buffers
+            .iter()
+            .filter(|(_, buf)| !buf.is_empty())
+            .map(|(key, buf)| (buf.len(), key.clone()))
+            .collect();
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+        let target = self.config.max_buffered_bytes / 2;
+        for (_, key) in sizes {
+            if self.buffered <= target {
+                break;
+            }
+            self.flush_key(&key)?;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, key: &str) -> Result<Handle> {
+        let path = self.path_for(key);
+        let file = if self.created.insert(key.to_string()) {
+            if let Some(owner) = self.files.insert(path.clone(), key.to_string()) {
+                return Err(Error::Config(format!(
+                    "Keys '{}' and '{}' both map to output file {:?}",
+                    owner, key, path
+                )));
+            }
+            File::create(&path)?
+        } else {
+            OpenOptions::new().append(true).open(&path)?
+        };
+        Ok(if self.config.compress {
+            Handle::Gzip(BufWriter::new(GzEncoder::new(file, Compression::default())))
+        } else {
+            Handle::Plain(BufWriter::new(file))
+        })
+    }
+
+    fn evict_lru(&mut self) -> Result<()> {
+        let oldest = self
+            .handles
+            .iter()
+            .min_by_key(|(_, (_, used))| *used)
+            .map(|(k, _)| k.clone());
+        if let Some(key) = oldest {
+            if let Some((handle, _)) = self.handles.remove(&key) {
+                handle.close()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush all buffers, close all files, and return reads written per key
+    pub fn finish(mut self) -> Result<AHashMap<String, u64>> {
+        let keys: Vec<String> = self.buffers.keys().cloned().collect();
+        for key in keys {
+            self.flush_key(&key)?;
+        }
+        for (_, (handle, _)) in self.handles.drain() {
+            handle.close()?;
+        }
+        Ok(std::mem::take(&mut self.counts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fastq::FastqParser;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_router_lru_reopen() {
+        let dir = tempdir().unwrap();
+        let config = RouterConfig {
+            max_open: 2,
+            batch_bytes: 1,
+            compress: true,
+            ..Default::default()
+        };
+        let mut router = FastqRouter::new(dir.path(), config).unwrap();
+
+        for i in 0..30 {
+            let key = format!("CELL{}", i % 5);
+            let record = FastqRecord::new(format!("r{}", i), b"ACGT".to_vec(), b"IIII".to_vec());
+            router.write(&key, &record).unwrap();
+            assert!(router.open_handles() <= 2);
+        }
+        let path = router.path_for("CELL3");
+        let counts = router.finish().unwrap();
+        assert_eq!(counts.len(), 5);
+        assert_eq!(counts["CELL3"], 6);
+
+        let records = FastqParser::open(&path).unwrap().read_all().unwrap();
+        assert_eq!(records.len(), 6);
+        assert_eq!(records[0].id, "r3");
+    }
+
+    #[test]
+    fn test_router_buffer_budget() {
+        let dir = tempdir().unwrap();
+        let config = RouterConfig {
+            max_open: 4,
+            batch_bytes: 1 << 20,
+            max_buffered_bytes: 1000,
+            compress: false,
+        };
+        let mut router = FastqRouter::new(dir.path(), config).unwrap();
+
+        for i in 0..500 {
+            let key = format!("CELL{}", i % 50);
+            let record =
+                FastqRecord::new(format!("r{}", i), b"ACGTACGT".to_vec(), b"IIIIIIII".to_vec());
+            router.write(&key, &record).unwrap();
+            assert!(router.buffered_bytes() <= 1000);
+        }
+        let counts = router.finish().unwrap();
+        assert_eq!(counts.values().sum::<u64>(), 500);
+    }
+
+    #[test]
+    fn test_router_sanitized_keys_stay_distinct() {
+        let dir = tempdir().unwrap();
+        let mut router = FastqRouter::new(dir.path(), RouterConfig::default()).unwrap();
+        assert_ne!(router.path_for("A/B"), router.path_for("A_B"));
+        assert_ne!(router.path_for("A/B"), router.path_for("A:B"));
+        assert_eq!(router.path_for("AC-GT.1"), dir.path().join("AC-GT.1.fastq.gz"));
+
+        let record = FastqRecord::new("r".to_string(), b"ACGT".to_vec(), b"IIII".to_vec());
+        for key in ["A/B", "A_B", "A:B"] {
+            router.write(key, &record).unwrap();
+        }
+        let counts = router.finish().unwrap();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+}
@@ -0,0 +1,134 @@
+//! Reproducible read subsampling
+//!
+//! Fraction-based sampling hashes the read name with the seed, so R1 and R2
+//! make the same decision independently and reruns select identical reads.
+//! Count-based sampling uses a seeded reservoir over read pairs.
+
+use super::FastqRecord;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How many reads to keep
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleMode {
+    /// Keep approximately this fraction of reads (0.0 - 1.0)
+    Fraction(f64),
+    /// Keep exactly this many reads (or all, if fewer)
+    Count(usize),
+}
+
+/// Deterministic 64-bit hash of a read name under a seed (FNV-1a + splitmix finalizer)
+pub fn read_hash(name: &str, seed: u64) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325 ^ seed;
+    for &b in name.as_bytes() {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+/// Whether a read is kept under hash-based fraction sampling
+pub fn keep_by_hash(name: &str, seed: u64, fraction: f64) -> bool {
+    if fraction >= 1.0 {
+        return true;
+    }
+    if fraction <= 0.0 {
+        return false;
+    }
+    (read_hash(name, seed) as f64 / u64::MAX as f64) < fraction
+}
+
+/// Seeded reservoir sampler over read pairs (Algorithm R)
+///
+/// Holds at most `capacity` pairs; `into_sorted` returns them in input order.
+pub struct PairReservoir {
+    capacity: usize,
+    seen: u64,
+    rng: StdRng,
+    items: Vec<(u64, FastqRecord, Option<FastqRecord>)>,
+}
+
+impl PairReservoir {
+    pub fn new(capacity: usize, seed: u64) -> Self {
+        Self {
+            capacity,
+            seen: 0,
+            rng: StdRng::seed_from_u64(seed),
+            items: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Offer a read (and optional mate) to the reservoir
+    pub fn offer(&mut self, r1: FastqRecord, r2: Option<FastqRecord>) {
+        let idx = self.seen;
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push((idx, r1, r2));
+        } else if self.capacity > 0 {
+            let j = self.rng.gen_range(0..self.seen);
+            if (j as usize) < self.capacity {
+                self.items[j as usize] = (idx, r1, r2);
+            }
+        }
+    }
+
+    /// Number of reads offered so far
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Sampled pairs in original input order
+    pub fn into_sorted(mut self) -> Vec<(FastqRecord, Option<FastqRecord>)> {
+        self.items.sort_by_key(|(idx, _, _)| *idx);
+        self.items.into_iter().map(|(_, r1, r2)| (r1, r2)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(i: usize) -> FastqRecord {
+        FastqRecord::new(format!("read{}", i), b"ACGT".to_vec(), b"IIII".to_vec())
+    }
+
+    #[test]
+    fn test_hash_fraction_reproducible() {
+        let kept: Vec<bool> = (0..1000)
+            .map(|i| keep_by_hash(&format!("read{}", i), 7, 0.25))
+            .collect();
+        let again: Vec<bool> = (0..1000)
+            .map(|i| keep_by_hash(&format!("read{}", i), 7, 0.25))
+            .collect();
+        assert_eq!(kept, again);
+
+        let n = kept.iter().filter(|&&k| k).count();
+        assert!(n > 180 && n < 320, "kept {} of 1000", n);
+    }
+
+    #[test]
+    fn test_reservoir_count_and_order() {
+        let mut reservoir = PairReservoir::new(10, 42);
+        for i in 0..500 {
+            reservoir.offer(record(i), Some(record(i)));
+        }
+        let sampled = reservoir.into_sorted();
+        assert_eq!(sampled.len(), 10);
+
+        let indices: Vec<usize> = sampled
+            .iter()
+            .map(|(r1, _)| r1.id[4..].parse().unwrap())
+            .collect();
+        let mut sorted = indices.clone();
+        sorted.sort();
+        assert_eq!(indices, sorted);
+
+        for (r1, r2) in &sampled {
+            assert_eq!(r1.id, r2.as_ref().unwrap().id);
+        }
+    }
+}
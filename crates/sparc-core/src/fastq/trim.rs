@@ -0,0 +1,303 @@
+//! Adapter, polyA, and template-switch oligo (TSO) trimming
+//!
+//! Matching is done on plain byte slices with branch-free mismatch counting so
+//! the inner loops auto-vectorize.
+
+use super::{FastqRecord, FastqRecordRef};
+
+/// Illumina TruSeq adapter (read-through into the 3' end of R2)
+pub const TRUSEQ_ADAPTER: &[u8] = b"AGATCGGAAGAGC";
+
+/// Nextera / Tn5 adapter
+pub const NEXTERA_ADAPTER: &[u8] = b"CTGTCTCTTATACACATCT";
+
+/// 10x Genomics template-switch oligo (found at the 5' end of R2)
+pub const TENX_TSO: &[u8] = b"AAGCAGTGGTATCAACGCAGAGTACATGGG";
+
+/// Trimming configuration
+#[derive(Debug, Clone)]
+pub struct TrimConfig {
+    /// 3' adapters searched for in order; the leftmost hit wins
+    pub adapters: Vec<Vec<u8>>,
+    /// 5' template-switch oligo
+    pub tso: Option<Vec<u8>>,
+    /// Minimum polyA run to trim (0 disables polyA trimming)
+    pub min_polya_len: usize,
+    /// Minimum adapter overlap at the read end to trim
+    pub min_overlap: usize,
+    /// Maximum mismatch rate within an adapter/TSO match
+    pub max_error_rate: f64,
+    /// Reads shorter than this after trimming are reported as too short
+    pub min_length: usize,
+}
+
+impl Default for TrimConfig {
+    fn default() -> Self {
+        Self {
+            adapters: vec![TRUSEQ_ADAPTER.to_vec(), NEXTERA_ADAPTER.to_vec()],
+            tso: Some(TENX_TSO.to_vec()),
+            min_polya_len: 10,
+            min_overlap: 5,
+            max_error_rate: 0.1,
+            min_length: 20,
+        }
+    }
+}
+
+/// Outcome of trimming a single read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrimResult {
+    /// Bases removed from the 5' end (TSO)
+    pub tso_trimmed: usize,
+    /// Bases removed from the 3' end by adapter matching
+    pub adapter_trimmed: usize,
+    /// Bases removed from the 3' end by polyA matching
+    pub polya_trimmed: usize,
+    /// Remaining read length
+    pub length: usize,
+}
+
+impl TrimResult {
+    /// Whether any bases were removed
+    pub fn is_trimmed(&self) -> bool {
+        self.tso_trimmed + self.adapter_trimmed + self.polya_trimmed > 0
+    }
+}
+
+/// Aggregate trimming statistics
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct TrimStats {
+    pub total_reads: u64,
+    pub tso_trimmed_reads: u64,
+    pub adapter_trimmed_reads: u64,
+    pub polya_trimmed_reads: u64,
+    pub too_short_reads: u64,
+    pub bases_removed: u64,
+}
+
+impl TrimStats {
+    /// Record the result of trimming one read
+    pub fn update(&mut self, result: &TrimResult, min_length: usize) {
+        self.total_reads += 1;
+        if result.tso_trimmed > 0 {
+            self.tso_trimmed_reads += 1;
+        }
+        if result.adapter_trimmed > 0 {
+            self.adapter_trimmed_reads += 1;
+        }
+        if result.polya_trimmed > 0 {
+            self.polya_trimmed_reads += 1;
+        }
+        if result.length < min_length {
+            self.too_short_reads += 1;
+        }
+        self.bases_removed +=
+            (result.tso_trimmed + result.adapter_trimmed + result.polya_trimmed) as u64;
+    }
+}
+
+/// Read trimmer
+pub struct Trimmer {
+    config: TrimConfig,
+}
+
+impl Trimmer {
+    pub fn new(config: TrimConfig) -> Self {
+        Self { config }
+    }
+
+    /// Get the trimming configuration
+    pub fn config(&self) -> &TrimConfig {
+        &self.config
+    }
+
+    /// Trim a record in place, returning what was removed. Qualities are cut
+    /// to the same window, so a short quality string stays aligned with the
+    /// bases it covers.
+    pub fn trim(&self, record: &mut FastqRecord) -> TrimResult {
+        let (start, end, result) = self.trim_bounds(&record.seq);
+        record.seq.truncate(end);
+        record.seq.drain(..start);
+        record.qual.truncate(end);
+        record.qual.drain(..start.min(record.qual.len()));
+        result
+    }
+
+    /// Trim a borrowed record, returning the retained window and what was removed
+    pub fn trim_ref<'a>(&self, record: FastqRecordRef<'a>) -> (FastqRecordRef<'a>, TrimResult) {
+        let (start, end, result) = self.trim_bounds(record.seq);
+        (record.window(start, end), result)
+    }
+
+    /// Compute the retained `[start, end)` window for a sequence
+    pub fn trim_bounds(&self, seq: &[u8]) -> (usize, usize, TrimResult) {
+        let mut result = TrimResult::default();
+        let mut start = 0;
+        let mut end = seq.len();
+
+        if let Some(tso) = &self.config.tso {
+            if let Some(tso_end) = find_prefix(seq, tso, self.config.max_error_rate) {
+                start = tso_end;
+                result.tso_trimmed = tso_end;
+            }
+        }
+
+        let mut adapter_pos: Option<usize> = None;
+        for adapter in &self.config.adapters {
+            if let Some(pos) = find_suffix(
+                &seq[start..end],
+                adapter,
+                self.config.min_overlap,
+                self.config.max_error_rate,
+            ) {
+                let pos = start + pos;
+                adapter_pos = Some(adapter_pos.map_or(pos, |p| p.min(pos)));
+            }
+        }
+        if let Some(pos) = adapter_pos {
+            result.adapter_trimmed = end - pos;
+            end = pos;
+        }
+
+        if self.config.min_polya_len > 0 {
+            let tail = polya_tail_len(&seq[start..end], self.config.min_polya_len);
+            result.polya_trimmed = tail;
+            end -= tail;
+        }
+
+        result.length = end - start;
+        (start, end, result)
+    }
+}
+
+impl Default for Trimmer {
+    fn default() -> Self {
+        Self::new(TrimConfig::default())
+    }
+}
+
+/// Count mismatching positions between two equal-length slices
+#[inline]
+pub fn mismatches(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).map(|(x, y)| (x != y) as usize).sum()
+}
+
+/// Find where a 5' sequence ends if `seq` starts with it
+pub fn find_prefix(seq: &[u8], prefix: &[u8], max_error_rate: f64) -> Option<usize> {
+    if prefix.is_empty() || seq.len() < prefix.len() {
+        return None;
+    }
+    let max_err = (prefix.len() as f64 * max_error_rate) as usize;
+    if mismatches(&seq[..prefix.len()], prefix) <= max_err {
+        Some(prefix.len())
+    } else {
+        None
+    }
+}
+
+/// Find the leftmost start of `adapter` in `seq`, allowing partial overlap at the 3' end
+pub fn find_suffix(
+    seq: &[u8],
+    adapter: &[u8],
+    min_overlap: usize,
+    max_error_rate: f64,
+) -> Option<usize> {
+    if adapter.is_empty() || seq.len() < min_overlap.max(1) {
+        return None;
+    }
+    let last = seq.len() - min_overlap.max(1);
+    for pos in 0..=last {
+        let overlap = (seq.len() - pos).min(adapter.len());
+        let max_err = (overlap as f64 * max_error_rate) as usize;
+        if mismatches(&seq[pos..pos + overlap], &adapter[..overlap]) <= max_err {
+            return Some(pos);
+        }
+    }
+    None
+}
+
+/// Length of the polyA tail at the 3' end of `seq`.
+///
+/// Tolerates one non-A base per 10 bases so sequencing errors inside the tail
+/// do not stop the scan. Returns 0 if the tail is shorter than `min_len`.
+pub fn polya_tail_len(seq: &[u8], min_len: usize) -> usize {
+    let mut best = 0;
+    let mut non_a = 0;
+    for (i, &base) in seq.iter().rev().enumerate() {
+        if base != b'A' && base != b'a' {
+            non_a += 1;
+        }
+        let len = i + 1;
+        if non_a * 10 > len {
+            break;
+        }
+        if base == b'A' || base == b'a' {
+            best = len;
+        }
+    }
+    if best >= min_len {
+        best
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_polya_tail() {
+        assert_eq!(polya_tail_len(b"ACGTACGTAAAAAAAAAAAA", 10), 12);
+        assert_eq!(polya_tail_len(b"ACGTACGTAAAA", 10), 0);
+        // A single error inside a long tail is tolerated
+        assert_eq!(polya_tail_len(b"CGTCAAAAAAGAAAAAAAAAAA", 10), 18);
+    }
+
+    #[test]
+    fn test_adapter_full_and_partial() {
+        let seq = b"ACGTACGTACGTAGATCGGAAGAGCAAA";
+        assert_eq!(find_suffix(seq, TRUSEQ_ADAPTER, 5, 0.1), Some(12));
+
+        let partial = b"ACGTACGTACGTAGATCG";
+        assert_eq!(find_suffix(partial, TRUSEQ_ADAPTER, 5, 0.1), Some(12));
+
+        assert_eq!(find_suffix(b"ACGTACGTACGT", TRUSEQ_ADAPTER, 5, 0.1), None);
+    }
+
+    #[test]
+    fn test_trim_record() {
+        let mut seq = TENX_TSO.to_vec();
+        seq.extend_from_slice(b"CCCCGGGGTTTTCCCCGGGGTTTT");
+        seq.extend_from_slice(b"AAAAAAAAAAAAAAA");
+        let qual = vec![b'I'; seq.len()];
+        let mut record = FastqRecord::new("r".to_string(), seq, qual);
+        let original = record.clone();
+
+        let result = Trimmer::default().trim(&mut record);
+        assert_eq!(result.tso_trimmed, TENX_TSO.len());
+        assert_eq!(result.polya_trimmed, 15);
+        assert_eq!(record.seq, b"CCCCGGGGTTTTCCCCGGGGTTTT".to_vec());
+        assert_eq!(record.qual.len(), record.seq.len());
+
+        let (window, ref_result) = Trimmer::default().trim_ref(original.as_record_ref());
+        assert_eq!(window, record.as_record_ref());
+        assert_eq!(ref_result.length, result.length);
+    }
+
+    #[test]
+    fn test_trim_short_qual() {
+        let mut seq = TENX_TSO.to_vec();
+        seq.extend_from_slice(b"CCCCGGGGTTTTCCCCGGGGTTTT");
+        seq.extend_from_slice(b"AAAAAAAAAAAAAAA");
+        let qual = vec![b'I'; seq.len() - 20];
+        let mut record = FastqRecord::new("r".to_string(), seq, qual);
+        let original = record.clone();
+
+        Trimmer::default().trim(&mut record);
+        // Qualities cover the first 19 of the 24 retained bases
+        assert_eq!(record.qual.len(), 19);
+        let (window, _) = Trimmer::default().trim_ref(original.as_record_ref());
+        assert_eq!(window, record.as_record_ref());
+    }
+}
@@ -1,45 +1,190 @@
 //! FASTQ file writer with compression support
 
-use super::FastqRecord;
-use crate::{Error, Result};
+use super::{FastqRecord, FastqRecordRef};
+use crate::atomic::AtomicWriter;
+use crate::{resources, Error, Result};
 use flate2::write::GzEncoder;
 use flate2::Compression;
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use rayon::prelude::*;
+use std::fmt;
+use std::io::Write;
 use std::path::Path;
+use std::str::FromStr;
 
-/// FASTQ writer supporting plain text and gzip compression
+/// Uncompressed bytes per block when compressing on several threads
+const BLOCK_BYTES: usize = 4 << 20;
+
+/// Compression of a FASTQ output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FastqCompression {
+    #[default]
+    Plain,
+    Gzip,
+    Zstd,
+}
+
+impl FastqCompression {
+    /// Compression implied by the file extension (`.gz`, `.zst`)
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz" | "gzip") => FastqCompression::Gzip,
+            Some("zst" | "zstd") => FastqCompression::Zstd,
+            _ => FastqCompression::Plain,
+        }
+    }
+
+    /// Level used when none is given
+    pub fn default_level(&self) -> i32 {
+        match self {
+            FastqCompression::Plain => 0,
+            FastqCompression::Gzip => Compression::default().level() as i32,
+            FastqCompression::Zstd => 3,
+        }
+    }
+
+    /// Accepted compression levels
+    pub fn levels(&self) -> std::ops::RangeInclusive<i32> {
+        match self {
+            FastqCompression::Plain => 0..=0,
+            FastqCompression::Gzip => 0..=9,
+            FastqCompression::Zstd => 1..=22,
+        }
+    }
+}
+
+impl FromStr for FastqCompression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plain" | "none" => Ok(FastqCompression::Plain),
+            "gzip" | "gz" => Ok(FastqCompression::Gzip),
+            "zstd" | "zst" => Ok(FastqCompression::Zstd),
+            _ => Err(Error::Config(format!(
+                "unknown compression '{}' (expected plain, gzip, or zstd)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for FastqCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FastqCompression::Plain => "plain",
+            FastqCompression::Gzip => "gzip",
+            FastqCompression::Zstd => "zstd",
+        })
+    }
+}
+
+/// How a [`FastqWriter`] compresses its output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriterOptions {
+    pub compression: FastqCompression,
+    /// Compression level; `None` for the format's default (gzip 6, zstd 3)
+    pub level: Option<i32>,
+    /// Threads compressing in parallel. With more than one, the output is a
+    /// series of independently compressed blocks (gzip members or zstd
+    /// frames), which every decompressor reads as one stream.
+    pub threads: usize,
+}
+
+impl WriterOptions {
+    /// Compression from the file extension at the default level, on the
+    /// `--compress-threads` threads (one when unset)
+    pub fn for_path(path: &Path) -> Self {
+        Self {
+            compression: FastqCompression::from_path(path),
+            level: None,
+            threads: resources::global().threads.compress_or(1).max(1),
+        }
+    }
+
+    /// The level to compress at, checked against the format's range
+    fn resolved_level(&self) -> Result<i32> {
+        let level = self.level.unwrap_or_else(|| self.compression.default_level());
+        let levels = self.compression.levels();
+        if !levels.contains(&level) {
+            return Err(Error::Config(format!(
+                "{} compression level {} is out of range {}-{}",
+                self.compression,
+                level,
+                levels.start(),
+                levels.end()
+            )));
+        }
+        Ok(level)
+    }
+}
+
+/// FASTQ writer supporting plain text, gzip, and zstd compression
+///
+/// The file appears at its path only after [`FastqWriter::finish`]; a writer
+/// dropped before that removes its partial output.
 pub struct FastqWriter {
-    writer: Box<dyn Write>,
+    writer: AtomicWriter,
+    /// Blocks awaiting parallel compression, when writing on several threads
+    blocks: Option<BlockEncoder>,
 }
 
 impl FastqWriter {
-    /// Create a new FASTQ writer
+    /// Create a new FASTQ writer, compressing by the file extension
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
-        let file = File::create(path)?;
-
-        let writer: Box<dyn Write> = if path
-            .extension()
-            .map_or(false, |ext| ext == "gz" || ext == "gzip")
-        {
-            Box::new(BufWriter::new(GzEncoder::new(file, Compression::default())))
-        } else {
-            Box::new(BufWriter::new(file))
-        };
+        Self::with_options(path, WriterOptions::for_path(path))
+    }
 
-        Ok(Self { writer })
+    /// Create a FASTQ writer with explicit compression, whatever the extension
+    pub fn with_options<P: AsRef<Path>>(path: P, options: WriterOptions) -> Result<Self> {
+        let level = options.resolved_level()?;
+        let compression = options.compression;
+        if cfg!(not(feature = "native")) && compression == FastqCompression::Zstd {
+            return Err(Error::Config("zstd output needs the native feature".into()));
+        }
+        if options.threads > 1 && compression != FastqCompression::Plain {
+            // Compressed blocks are appended to an uncompressed file
+            return Ok(Self {
+                writer: AtomicWriter::create(path, false)?,
+                blocks: Some(BlockEncoder {
+                    compression,
+                    level,
+                    threads: options.threads,
+                    blocks: Vec::new(),
+                }),
+            });
+        }
+        let writer = match compression {
+            FastqCompression::Plain => AtomicWriter::create(path, false)?,
+            FastqCompression::Gzip => AtomicWriter::gzip(path, level as u32)?,
+            #[cfg(feature = "native")]
+            FastqCompression::Zstd => AtomicWriter::zstd(path, level)?,
+            #[cfg(not(feature = "native"))]
+            FastqCompression::Zstd => unreachable!("rejected above"),
+        };
+        Ok(Self {
+            writer,
+            blocks: None,
+        })
     }
 
     /// Write a FASTQ record
     pub fn write_record(&mut self, record: &FastqRecord) -> Result<()> {
-        writeln!(self.writer, "@{}", record.id)?;
-        self.writer.write_all(&record.seq)?;
-        writeln!(self.writer)?;
-        writeln!(self.writer, "+")?;
-        self.writer.write_all(&record.qual)?;
-        writeln!(self.writer)?;
-        Ok(())
+        self.write_record_ref(record.as_record_ref())
+    }
+
+    /// Write a borrowed FASTQ record
+    pub fn write_record_ref(&mut self, record: FastqRecordRef<'_>) -> Result<()> {
+        match &mut self.blocks {
+            Some(blocks) => {
+                write_fastq(blocks.buffer(), record)?;
+                if blocks.is_full() {
+                    blocks.encode_into(&mut self.writer)?;
+                }
+                Ok(())
+            }
+            None => write_fastq(&mut self.writer, record),
+        }
     }
 
     /// Write multiple records
@@ -50,16 +195,104 @@ impl FastqWriter {
         Ok(())
     }
 
-    /// Flush the writer
+    /// Flush the writer, compressing any buffered blocks
     pub fn flush(&mut self) -> Result<()> {
+        if let Some(blocks) = &mut self.blocks {
+            blocks.encode_into(&mut self.writer)?;
+        }
         self.writer.flush().map_err(Error::from)
     }
+
+    /// Complete the file and move it into place
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(blocks) = &mut self.blocks {
+            blocks.encode_into(&mut self.writer)?;
+        }
+        self.writer.commit()
+    }
+}
+
+/// Buffers FASTQ text in blocks and compresses a batch of them in parallel
+struct BlockEncoder {
+    compression: FastqCompression,
+    level: i32,
+    threads: usize,
+    blocks: Vec<Vec<u8>>,
+}
+
+impl BlockEncoder {
+    /// The block to append the next record to
+    fn buffer(&mut self) -> &mut Vec<u8> {
+        if self.blocks.last().map_or(true, |block| block.len() >= BLOCK_BYTES) {
+            self.blocks.push(Vec::with_capacity(BLOCK_BYTES + (BLOCK_BYTES >> 4)));
+        }
+        self.blocks.last_mut().expect("block just pushed")
+    }
+
+    /// Whether there is a full block for every thread
+    fn is_full(&self) -> bool {
+        self.blocks.len() >= self.threads
+            && self.blocks.last().is_some_and(|block| block.len() >= BLOCK_BYTES)
+    }
+
+    /// Compress the buffered blocks in parallel and append them in order
+    fn encode_into<W: Write>(&mut self, out: &mut W) -> Result<()> {
+        let (compression, level) = (self.compression, self.level);
+        let encoded: Vec<Vec<u8>> = self
+            .blocks
+            .par_iter()
+            .map(|text| compress(text, compression, level))
+            .collect::<Result<_>>()?;
+        for block in encoded {
+            out.write_all(&block)?;
+        }
+        self.blocks.clear();
+        Ok(())
+    }
+}
+
+/// Compress `text` as one standalone gzip member or zstd frame
+fn compress(text: &[u8], compression: FastqCompression, level: i32) -> Result<Vec<u8>> {
+    match compression {
+        FastqCompression::Plain => Ok(text.to_vec()),
+        FastqCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level as u32));
+            encoder.write_all(text)?;
+            Ok(encoder.finish()?)
+        }
+        #[cfg(feature = "native")]
+        FastqCompression::Zstd => Ok(zstd::bulk::compress(text, level)?),
+        #[cfg(not(feature = "native"))]
+        FastqCompression::Zstd => {
+            Err(Error::Config("zstd output needs the native feature".into()))
+        }
+    }
 }
 
-impl Drop for FastqWriter {
-    fn drop(&mut self) {
-        let _ = self.flush();
+/// Write one record as FASTQ text
+fn write_fastq<W: Write>(writer: &mut W, record: FastqRecordRef<'_>) -> Result<()> {
+    writeln!(writer, "@{}", record.id)?;
+    writer.write_all(record.seq)?;
+    writeln!(writer)?;
+    writeln!(writer, "+")?;
+    writer.write_all(record.qual)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Encode records as FASTQ text, as a standalone gzip member when `gzip` is set.
+///
+/// Concatenated gzip members form a valid gzip file, so blocks can be encoded
+/// on worker threads and appended to one output in order.
+pub fn encode_block(records: &[FastqRecord], gzip: bool) -> Result<Vec<u8>> {
+    let mut text = Vec::new();
+    for record in records {
+        write_fastq(&mut text, record.as_record_ref())?;
+    }
+    if !gzip {
+        return Ok(text);
     }
+    compress(&text, FastqCompression::Gzip, FastqCompression::Gzip.default_level())
 }
 
 #[cfg(test)]
@@ -81,9 +314,72 @@ mod tests {
         let mut writer = FastqWriter::new(&path).unwrap();
         writer.write_record(&record).unwrap();
         writer.flush().unwrap();
+        assert!(!path.exists(), "not visible before finish");
+        writer.finish().unwrap();
 
         let content = std::fs::read_to_string(&path).unwrap();
         assert!(content.contains("@read1"));
         assert!(content.contains("ACGTACGT"));
     }
+
+    #[test]
+    fn test_concatenated_blocks() {
+        use crate::fastq::FastqParser;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("blocks.fastq.gz");
+        let record =
+            |id: &str| FastqRecord::new(id.to_string(), b"ACGT".to_vec(), b"IIII".to_vec());
+        let mut bytes = encode_block(&[record("r1"), record("r2")], true).unwrap();
+        bytes.extend(encode_block(&[record("r3")], true).unwrap());
+        std::fs::write(&path, bytes).unwrap();
+
+        let records = FastqParser::open(&path).unwrap().read_all().unwrap();
+        let ids: Vec<&str> = records.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["r1", "r2", "r3"]);
+        assert_eq!(encode_block(&[record("r1")], false).unwrap(), b"@r1\nACGT\n+\nIIII\n");
+    }
+
+    #[test]
+    fn test_compression_options() {
+        use crate::fastq::FastqParser;
+
+        let dir = tempdir().unwrap();
+        let records: Vec<FastqRecord> = (0..100)
+            .map(|i| FastqRecord::new(format!("r{}", i), b"ACGTN".to_vec(), b"IIII#".to_vec()))
+            .collect();
+        for (name, compression, threads) in [
+            ("plain.fq.gz", FastqCompression::Plain, 1),
+            ("zstd.fq", FastqCompression::Zstd, 1),
+            ("gzip.fq", FastqCompression::Gzip, 4),
+            ("zstd_mt.fq", FastqCompression::Zstd, 4),
+        ] {
+            let path = dir.path().join(name);
+            let options = WriterOptions {
+                compression,
+                level: Some(1),
+                threads,
+            };
+            let mut writer = FastqWriter::with_options(&path, options).unwrap();
+            writer.write_records(&records).unwrap();
+            writer.finish().unwrap();
+            let magic = std::fs::read(&path).unwrap()[..2].to_vec();
+            match compression {
+                FastqCompression::Plain => assert_eq!(magic, b"@r"),
+                FastqCompression::Gzip => assert_eq!(magic, [0x1f, 0x8b]),
+                FastqCompression::Zstd => assert_eq!(magic, [0x28, 0xb5]),
+            }
+            let read = FastqParser::open(&path).unwrap().read_all().unwrap();
+            assert_eq!(read.len(), 100, "{}", name);
+            assert_eq!(read[99].id, "r99");
+        }
+
+        let bad = WriterOptions {
+            compression: FastqCompression::Gzip,
+            level: Some(12),
+            threads: 1,
+        };
+        assert!(FastqWriter::with_options(dir.path().join("bad.fq.gz"), bad).is_err());
+        assert_eq!("zst".parse::<FastqCompression>().unwrap(), FastqCompression::Zstd);
+    }
 }
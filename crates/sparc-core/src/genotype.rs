@@ -0,0 +1,868 @@
+//! Genotype-based donor demultiplexing (demuxlet-lite)
+//!
+//! Reads are piled up per cell barcode at known biallelic SNPs from a VCF of
+//! common variants. Donor allele fractions are learned without reference
+//! genotypes by hard-assignment EM on a binomial read model, then each cell is
+//! scored against every donor and every donor pair to call singlets and
+//! cross-donor doublets.
+
+use crate::annotation::aligned_blocks;
+use crate::atomic::AtomicFile;
+use crate::{Error, Result};
+use ahash::{AHashMap, AHashSet};
+use flate2::read::MultiGzDecoder;
+use rust_htslib::bam::record::{Aux, Cigar};
+use rust_htslib::bam::{self, Read};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Seeds are drawn from this many of the best-covered cells per donor
+const SEED_CANDIDATES_PER_DONOR: usize = 50;
+
+/// Cells sharing fewer SNPs than this are not compared when seeding
+const MIN_SHARED_SNPS: usize = 5;
+
+/// A biallelic single-nucleotide variant
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Snp {
+    /// Chromosome
+    pub chrom: String,
+    /// Position (0-based)
+    pub pos: i64,
+    /// Reference base (uppercase)
+    pub ref_base: u8,
+    /// Alternate base (uppercase)
+    pub alt_base: u8,
+}
+
+/// SNPs indexed by chromosome and position
+#[derive(Debug, Clone, Default)]
+pub struct SnpSet {
+    snps: Vec<Snp>,
+    /// Per chromosome: SNP indices sorted by position
+    by_chrom: AHashMap<String, Vec<usize>>,
+}
+
+impl SnpSet {
+    /// Index a list of SNPs
+    pub fn new(snps: Vec<Snp>) -> Self {
+        let mut by_chrom: AHashMap<String, Vec<usize>> = AHashMap::new();
+        for (i, snp) in snps.iter().enumerate() {
+            by_chrom.entry(snp.chrom.clone()).or_default().push(i);
+        }
+        for indices in by_chrom.values_mut() {
+            indices.sort_by_key(|&i| snps[i].pos);
+        }
+        Self { snps, by_chrom }
+    }
+
+    /// Load SNPs from a VCF file (plain or gzip)
+    pub fn from_vcf<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        log::info!("Loading VCF: {:?}", path);
+        let file = File::open(path)?;
+        let snps = if path.extension().map_or(false, |ext| ext == "gz") {
+            Self::from_reader(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Self::from_reader(BufReader::new(file))
+        }?;
+        log::info!("Loaded {} SNPs", snps.len());
+        Ok(snps)
+    }
+
+    /// Load SNPs from VCF text. Sites with a single-base REF and ALT that pass
+    /// filters (`PASS` or `.`) are kept; indels and multi-allelic sites are skipped.
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
+        let mut snps = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 5 {
+                return Err(Error::Annotation(format!(
+                    "VCF line {}: expected at least 5 tab-separated columns",
+                    i + 1
+                )));
+            }
+            let pos: i64 = fields[1].parse().map_err(|_| {
+                Error::Annotation(format!("VCF line {}: invalid position '{}'", i + 1, fields[1]))
+            })?;
+            let filter = fields.get(6).copied().unwrap_or(".");
+            let (ref_allele, alt_allele) = (fields[3].as_bytes(), fields[4].as_bytes());
+            if ref_allele.len() != 1 || alt_allele.len() != 1 || !matches!(filter, "PASS" | ".")
+            {
+                continue;
+            }
+            snps.push(Snp {
+                chrom: fields[0].to_string(),
+                pos: pos - 1,
+                ref_base: ref_allele[0].to_ascii_uppercase(),
+                alt_base: alt_allele[0].to_ascii_uppercase(),
+            });
+        }
+        Ok(Self::new(snps))
+    }
+
+    /// Number of SNPs
+    pub fn len(&self) -> usize {
+        self.snps.len()
+    }
+
+    /// Whether there are no SNPs
+    pub fn is_empty(&self) -> bool {
+        self.snps.is_empty()
+    }
+
+    /// SNP by index
+    pub fn get(&self, index: usize) -> &Snp {
+        &self.snps[index]
+    }
+
+    /// All SNPs, in input order
+    pub fn snps(&self) -> &[Snp] {
+        &self.snps
+    }
+
+    /// Indices of SNPs on `chrom` within `[start, end)`, sorted by position
+    pub fn in_range(&self, chrom: &str, start: i64, end: i64) -> &[usize] {
+        let Some(indices) = self.by_chrom.get(chrom) else {
+            return &[];
+        };
+        let lo = indices.partition_point(|&i| self.snps[i].pos < start);
+        let hi = indices.partition_point(|&i| self.snps[i].pos < end);
+        &indices[lo..hi]
+    }
+}
+
+/// Read filters for the SNP pileup
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PileupConfig {
+    /// Minimum mapping quality
+    pub min_mapq: u8,
+    /// Minimum base quality at the SNP
+    pub min_base_qual: u8,
+    /// Count each UMI (UB tag) once per cell and SNP
+    pub dedup_umis: bool,
+}
+
+impl Default for PileupConfig {
+    fn default() -> Self {
+        Self {
+            min_mapq: 20,
+            min_base_qual: 13,
+            dedup_umis: true,
+        }
+    }
+}
+
+/// Reference and alternate allele observations per cell
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct AlleleCounts {
+    /// Cell barcodes
+    pub barcodes: Vec<String>,
+    /// Per cell: `(snp, ref_count, alt_count)` for each covered SNP, sorted by SNP
+    pub cells: Vec<Vec<(usize, u32, u32)>>,
+}
+
+impl AlleleCounts {
+    /// Allele observations in cell `i`
+    pub fn depth(&self, i: usize) -> u32 {
+        self.cells[i].iter().map(|&(_, r, a)| r + a).sum()
+    }
+}
+
+/// Read offset aligned to reference position `target`, if a base is aligned there
+fn query_offset<'a, I>(pos: i64, cigar: I, target: i64) -> Option<usize>
+where
+    I: IntoIterator<Item = &'a Cigar>,
+{
+    let (mut ref_pos, mut query) = (pos, 0usize);
+    for op in cigar {
+        match *op {
+            Cigar::Match(len) | Cigar::Equal(len) | Cigar::Diff(len) => {
+                let len = len as i64;
+                if target < ref_pos + len {
+                    return (target >= ref_pos).then(|| query + (target - ref_pos) as usize);
+                }
+                ref_pos += len;
+                query += len as usize;
+            }
+            Cigar::Del(len) | Cigar::RefSkip(len) => {
+                ref_pos += len as i64;
+                if target < ref_pos {
+                    return None;
+                }
+            }
+            Cigar::Ins(len) | Cigar::SoftClip(len) => query += len as usize,
+            Cigar::HardClip(_) | Cigar::Pad(_) => {}
+        }
+    }
+    None
+}
+
+/// Count reference and alternate alleles at `snps` per cell barcode (CB tag).
+///
+/// With `cells`, only those barcodes are counted, in that order; otherwise every
+/// barcode covering a SNP is reported in order of first appearance.
+pub fn pileup<P: AsRef<Path>>(
+    bam_path: P,
+    snps: &SnpSet,
+    cells: Option<&[String]>,
+    config: &PileupConfig,
+) -> Result<AlleleCounts> {
+    let mut reader = bam::Reader::from_path(bam_path.as_ref())
+        .map_err(|e| Error::BamParse(format!("Failed to open BAM: {}", e)))?;
+    let chrom_names: Vec<String> = reader
+        .header()
+        .target_names()
+        .iter()
+        .map(|n| String::from_utf8_lossy(n).to_string())
+        .collect();
+
+    let mut barcodes: Vec<String> = cells.map(<[String]>::to_vec).unwrap_or_default();
+    let mut index: AHashMap<String, usize> =
+        barcodes.iter().enumerate().map(|(i, b)| (b.clone(), i)).collect();
+    let mut counts: AHashMap<(usize, usize), (u32, u32)> = AHashMap::new();
+    let mut seen_umis: AHashSet<(usize, usize, Vec<u8>)> = AHashSet::new();
+
+    let mut record = bam::Record::new();
+    while let Some(result) = reader.read(&mut record) {
+        result.map_err(|e| Error::BamParse(e.to_string()))?;
+        if record.is_unmapped()
+            || record.is_secondary()
+            || record.is_supplementary()
+            || record.is_duplicate()
+            || record.mapq() < config.min_mapq
+        {
+            continue;
+        }
+        let Some(chrom) = usize::try_from(record.tid()).ok().and_then(|t| chrom_names.get(t))
+        else {
+            continue;
+        };
+        let cigar = record.cigar();
+        let blocks = aligned_blocks(record.pos(), cigar.iter());
+        let (Some(start), Some(end)) = (blocks.first(), blocks.last()) else {
+            continue;
+        };
+        let hits = snps.in_range(chrom, start.0, end.1);
+        if hits.is_empty() {
+            continue;
+        }
+
+        let cell = match record.aux(b"CB") {
+            Ok(Aux::String(barcode)) => match index.get(barcode) {
+                Some(&cell) => cell,
+                None if cells.is_some() => continue,
+                None => {
+                    index.insert(barcode.to_string(), barcodes.len());
+                    barcodes.push(barcode.to_string());
+                    barcodes.len() - 1
+                }
+            },
+            _ => continue,
+        };
+        let umi = match record.aux(b"UB") {
+            Ok(Aux::String(umi)) if config.dedup_umis => Some(umi.as_bytes().to_vec()),
+            _ => None,
+        };
+
+        let (seq, qual) = (record.seq(), record.qual());
+        for &s in hits {
+            let snp = snps.get(s);
+            let Some(offset) = query_offset(record.pos(), cigar.iter(), snp.pos) else {
+                continue;
+            };
+            if qual[offset] < config.min_base_qual {
+                continue;
+            }
+            let base = seq[offset].to_ascii_uppercase();
+            let is_alt = match base {
+                b if b == snp.ref_base => false,
+                b if b == snp.alt_base => true,
+                _ => continue,
+            };
+            if let Some(umi) = &umi {
+                if !seen_umis.insert((cell, s, umi.clone())) {
+                    continue;
+                }
+            }
+            let entry = counts.entry((cell, s)).or_default();
+            if is_alt {
+                entry.1 += 1;
+            } else {
+                entry.0 += 1;
+            }
+        }
+    }
+
+    let mut per_cell = vec![Vec::new(); barcodes.len()];
+    for ((cell, snp), (r, a)) in counts {
+        per_cell[cell].push((snp, r, a));
+    }
+    for cell in per_cell.iter_mut() {
+        cell.sort_unstable();
+    }
+    Ok(AlleleCounts {
+        barcodes,
+        cells: per_cell,
+    })
+}
+
+/// Donor demultiplexing parameters
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DonorDemuxConfig {
+    /// Number of pooled donors
+    pub n_donors: usize,
+    /// Probability of observing the other allele (sequencing error, ambient RNA)
+    pub error_rate: f64,
+    /// Prior probability that a droplet holds cells from two donors
+    pub doublet_prior: f64,
+    /// Posterior probability required for a singlet or doublet call
+    pub min_posterior: f64,
+    /// Cells covering fewer SNPs are left unassigned
+    pub min_snps: usize,
+    /// Maximum EM iterations
+    pub max_iter: usize,
+}
+
+impl Default for DonorDemuxConfig {
+    fn default() -> Self {
+        Self {
+            n_donors: 2,
+            error_rate: 0.01,
+            doublet_prior: 0.05,
+            min_posterior: 0.9,
+            min_snps: 5,
+            max_iter: 50,
+        }
+    }
+}
+
+/// Donor assignment of one droplet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DonorCall {
+    /// Cells from one donor
+    Singlet(usize),
+    /// Cells from two donors
+    Doublet(usize, usize),
+    /// Too few SNPs or no confident call
+    Unassigned,
+}
+
+impl DonorCall {
+    /// `singlet`, `doublet`, or `unassigned`
+    pub fn status(&self) -> &'static str {
+        match self {
+            DonorCall::Singlet(_) => "singlet",
+            DonorCall::Doublet(..) => "doublet",
+            DonorCall::Unassigned => "unassigned",
+        }
+    }
+}
+
+/// Donor call and evidence for one cell
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CellDonorCall {
+    /// Cell barcode
+    pub barcode: String,
+    /// Final call
+    pub call: DonorCall,
+    /// Most likely single donor, regardless of the call
+    pub best_donor: usize,
+    /// SNPs with at least one observation
+    pub n_snps: usize,
+    /// Allele observations across SNPs
+    pub n_alleles: u32,
+    /// Posterior probability of the most likely hypothesis
+    pub posterior: f64,
+    /// Posterior probability that the droplet is a doublet
+    pub doublet_posterior: f64,
+    /// Log-likelihood of the best single donor
+    pub singlet_llk: f64,
+    /// Log-likelihood of the best donor pair
+    pub doublet_llk: f64,
+}
+
+/// Donor genotypes and per-cell calls
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DonorDemux {
+    /// Estimated alternate allele fraction of each donor at each SNP
+    pub genotypes: Vec<Vec<f64>>,
+    /// Per-cell calls, in barcode order
+    pub cells: Vec<CellDonorCall>,
+    /// EM iterations run
+    pub iterations: usize,
+}
+
+/// Log-likelihood of a cell's alleles given the alternate allele fraction at each SNP
+fn allele_llk(cell: &[(usize, u32, u32)], error_rate: f64, alt: impl Fn(usize) -> f64) -> f64 {
+    cell.iter()
+        .map(|&(s, r, a)| {
+            let q = error_rate + alt(s) * (1.0 - 2.0 * error_rate);
+            a as f64 * q.ln() + r as f64 * (1.0 - q).ln()
+        })
+        .sum()
+}
+
+/// Posterior over all singlet and doublet hypotheses for one cell
+struct CellScore {
+    best: (DonorCall, f64),
+    best_donor: usize,
+    best_pair: (usize, usize),
+    doublet_posterior: f64,
+    singlet_llk: f64,
+    doublet_llk: f64,
+}
+
+fn score_cell(
+    cell: &[(usize, u32, u32)],
+    genotypes: &[Vec<f64>],
+    config: &DonorDemuxConfig,
+) -> CellScore {
+    let k = genotypes.len();
+    let singlet_prior = ((1.0 - config.doublet_prior) / k as f64).ln();
+    let doublet_prior = (config.doublet_prior / (k * (k - 1) / 2) as f64).ln();
+
+    let mut hypotheses: Vec<(DonorCall, f64, f64)> = Vec::new();
+    for (d, g) in genotypes.iter().enumerate() {
+        let llk = allele_llk(cell, config.error_rate, |s| g[s]);
+        hypotheses.push((DonorCall::Singlet(d), llk, singlet_prior + llk));
+    }
+    for (i, gi) in genotypes.iter().enumerate() {
+        for (j, gj) in genotypes.iter().enumerate().skip(i + 1) {
+            let llk = allele_llk(cell, config.error_rate, |s| (gi[s] + gj[s]) / 2.0);
+            hypotheses.push((DonorCall::Doublet(i, j), llk, doublet_prior + llk));
+        }
+    }
+
+    let max = hypotheses.iter().map(|h| h.2).fold(f64::NEG_INFINITY, f64::max);
+    let norm: f64 = hypotheses.iter().map(|h| (h.2 - max).exp()).sum();
+    let posterior = |h: &(DonorCall, f64, f64)| (h.2 - max).exp() / norm;
+    let by_llk = |a: &&(DonorCall, f64, f64), b: &&(DonorCall, f64, f64)| a.1.total_cmp(&b.1);
+
+    let best = hypotheses.iter().max_by(|a, b| a.2.total_cmp(&b.2)).expect("k >= 2");
+    let singlet = hypotheses[..k].iter().max_by(by_llk).expect("k >= 2");
+    let doublet = hypotheses[k..].iter().max_by(by_llk).expect("k >= 2");
+    CellScore {
+        best: (best.0, posterior(best)),
+        best_donor: match singlet.0 {
+            DonorCall::Singlet(d) => d,
+            _ => unreachable!(),
+        },
+        best_pair: match doublet.0 {
+            DonorCall::Doublet(i, j) => (i, j),
+            _ => unreachable!(),
+        },
+        doublet_posterior: hypotheses[k..].iter().map(posterior).sum(),
+        singlet_llk: singlet.1,
+        doublet_llk: doublet.1,
+    }
+}
+
+/// Alternate allele fraction per donor and SNP from the cells assigned to it
+fn donor_genotypes(
+    counts: &AlleleCounts,
+    assignment: &[Option<usize>],
+    n_donors: usize,
+    n_snps: usize,
+) -> Vec<Vec<f64>> {
+    let mut alt = vec![vec![0.0; n_snps]; n_donors];
+    let mut total = vec![vec![0.0; n_snps]; n_donors];
+    for (cell, donor) in counts.cells.iter().zip(assignment) {
+        if let Some(d) = *donor {
+            for &(s, r, a) in cell {
+                alt[d][s] += a as f64;
+                total[d][s] += (r + a) as f64;
+            }
+        }
+    }
+    alt.iter()
+        .zip(&total)
+        .map(|(alt, total)| alt.iter().zip(total).map(|(&a, &t)| (a + 0.5) / (t + 1.0)).collect())
+        .collect()
+}
+
+/// Mean absolute allele-fraction difference over SNPs both cells cover, or 0
+/// when they share fewer than [`MIN_SHARED_SNPS`]
+fn allele_fraction_distance(x: &[(usize, u32, u32)], y: &[(usize, u32, u32)]) -> f64 {
+    let fraction = |(_, r, a): (usize, u32, u32)| a as f64 / (r + a) as f64;
+    let (mut i, mut j, mut sum, mut shared) = (0, 0, 0.0, 0);
+    while i < x.len() && j < y.len() {
+        match x[i].0.cmp(&y[j].0) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                sum += (fraction(x[i]) - fraction(y[j])).abs();
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    if shared < MIN_SHARED_SNPS {
+        0.0
+    } else {
+        sum / shared as f64
+    }
+}
+
+/// Initial assignment: one well-covered seed cell per donor, chosen by
+/// farthest-point selection on allele fractions
+fn seed_donors(
+    counts: &AlleleCounts,
+    informative: &[usize],
+    n_donors: usize,
+) -> Vec<Option<usize>> {
+    let mut candidates = informative.to_vec();
+    candidates.sort_by_key(|&c| std::cmp::Reverse(counts.depth(c)));
+    candidates.truncate(SEED_CANDIDATES_PER_DONOR * n_donors);
+
+    let mut seeds = vec![candidates[0]];
+    while seeds.len() < n_donors {
+        let mut best: Option<(usize, f64)> = None;
+        for &c in candidates.iter().filter(|&&c| !seeds.contains(&c)) {
+            let distance = seeds
+                .iter()
+                .map(|&s| allele_fraction_distance(&counts.cells[c], &counts.cells[s]))
+                .fold(f64::INFINITY, f64::min);
+            match best {
+                Some((_, d)) if d >= distance => {}
+                _ => best = Some((c, distance)),
+            }
+        }
+        match best {
+            Some((c, _)) => seeds.push(c),
+            None => break,
+        }
+    }
+
+    let mut assignment = vec![None; counts.cells.len()];
+    for (donor, &c) in seeds.iter().enumerate() {
+        assignment[c] = Some(donor);
+    }
+    assignment
+}
+
+/// Cluster cells into `config.n_donors` donors and call singlets and doublets.
+///
+/// `n_snps` is the size of the SNP set the counts index into.
+pub fn demux_donors(
+    counts: &AlleleCounts,
+    n_snps: usize,
+    config: &DonorDemuxConfig,
+) -> Result<DonorDemux> {
+    let k = config.n_donors;
+    if k < 2 {
+        return Err(Error::Config(format!(
+            "donor demultiplexing needs at least 2 donors, got {}",
+            k
+        )));
+    }
+    let informative: Vec<usize> =
+        (0..counts.cells.len()).filter(|&c| counts.cells[c].len() >= config.min_snps).collect();
+    if informative.len() < k {
+        return Err(Error::Config(format!(
+            "only {} cells cover at least {} SNPs; cannot separate {} donors",
+            informative.len(),
+            config.min_snps,
+            k
+        )));
+    }
+
+    // Hard-assignment EM: genotypes from singlets, singlets from genotypes
+    let mut assignment = seed_donors(counts, &informative, k);
+    let mut genotypes = donor_genotypes(counts, &assignment, k, n_snps);
+    let mut iterations = 0;
+    while iterations < config.max_iter {
+        iterations += 1;
+        let mut next = vec![None; counts.cells.len()];
+        for &c in &informative {
+            if let DonorCall::Singlet(d) = score_cell(&counts.cells[c], &genotypes, config).best.0
+            {
+                next[c] = Some(d);
+            }
+        }
+        let converged = next == assignment;
+        assignment = next;
+        genotypes = donor_genotypes(counts, &assignment, k, n_snps);
+        if converged {
+            break;
+        }
+    }
+    log::info!("Donor clustering finished after {} iterations", iterations);
+
+    let cells = counts
+        .barcodes
+        .iter()
+        .zip(&counts.cells)
+        .map(|(barcode, cell)| {
+            let score = score_cell(cell, &genotypes, config);
+            let call = if cell.len() < config.min_snps {
+                DonorCall::Unassigned
+            } else if score.best.1 >= config.min_posterior {
+                score.best.0
+            } else if score.doublet_posterior >= config.min_posterior {
+                DonorCall::Doublet(score.best_pair.0, score.best_pair.1)
+            } else {
+                DonorCall::Unassigned
+            };
+            CellDonorCall {
+                barcode: barcode.clone(),
+                call,
+                best_donor: score.best_donor,
+                n_snps: cell.len(),
+                n_alleles: cell.iter().map(|&(_, r, a)| r + a).sum(),
+                posterior: score.best.1,
+                doublet_posterior: score.doublet_posterior,
+                singlet_llk: score.singlet_llk,
+                doublet_llk: score.doublet_llk,
+            }
+        })
+        .collect();
+
+    Ok(DonorDemux {
+        genotypes,
+        cells,
+        iterations,
+    })
+}
+
+/// Donor label used in output files
+pub fn donor_name(donor: usize) -> String {
+    format!("donor{}", donor)
+}
+
+impl DonorDemux {
+    /// Number of cells with a singlet call for each donor
+    pub fn singlets_per_donor(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.genotypes.len()];
+        for cell in &self.cells {
+            if let DonorCall::Singlet(d) = cell.call {
+                counts[d] += 1;
+            }
+        }
+        counts
+    }
+
+    /// Number of cells with the given status (`singlet`, `doublet`, `unassigned`)
+    pub fn count(&self, status: &str) -> usize {
+        self.cells.iter().filter(|c| c.call.status() == status).count()
+    }
+
+    /// Write per-cell calls as CSV (`donor` is `donorA+donorB` for doublets)
+    pub fn write_assignments<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut out = AtomicFile::create(path)?;
+        writeln!(
+            out,
+            "barcode,status,donor,best_donor,n_snps,n_alleles,posterior,doublet_posterior,\
+             singlet_llk,doublet_llk"
+        )?;
+        for cell in &self.cells {
+            let donor = match cell.call {
+                DonorCall::Singlet(d) => donor_name(d),
+                DonorCall::Doublet(i, j) => format!("{}+{}", donor_name(i), donor_name(j)),
+                DonorCall::Unassigned => String::new(),
+            };
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{:.4},{:.4},{:.3},{:.3}",
+                cell.barcode,
+                cell.call.status(),
+                donor,
+                donor_name(cell.best_donor),
+                cell.n_snps,
+                cell.n_alleles,
+                cell.posterior,
+                cell.doublet_posterior,
+                cell.singlet_llk,
+                cell.doublet_llk
+            )?;
+        }
+        out.commit()
+    }
+
+    /// Write each donor's estimated alternate allele fraction per SNP as TSV
+    /// (1-based positions, like the input VCF)
+    pub fn write_genotypes<P: AsRef<Path>>(&self, snps: &SnpSet, path: P) -> Result<()> {
+        let mut out = AtomicFile::create(path)?;
+        write!(out, "chrom\tpos\tref\talt")?;
+        for d in 0..self.genotypes.len() {
+            write!(out, "\t{}", donor_name(d))?;
+        }
+        writeln!(out)?;
+        for (s, snp) in snps.snps().iter().enumerate() {
+            write!(
+                out,
+                "{}\t{}\t{}\t{}",
+                snp.chrom,
+                snp.pos + 1,
+                snp.ref_base as char,
+                snp.alt_base as char
+            )?;
+            for genotype in &self.genotypes {
+                write!(out, "\t{:.3}", genotype[s])?;
+            }
+            writeln!(out)?;
+        }
+        out.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_htslib::bam::header::HeaderRecord;
+    use rust_htslib::bam::record::CigarString;
+
+    const VCF: &str = "\
+##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+chr1\t11\trs1\tA\tG\t.\tPASS\t.
+chr1\t15\trs2\tC\tT\t.\t.\t.
+chr1\t20\trs3\tAT\tA\t.\tPASS\t.
+chr1\t30\trs4\tG\tA,C\t.\tPASS\t.
+chr1\t40\trs5\tT\tC\t.\tLowQual\t.
+";
+
+    #[test]
+    fn test_vcf_keeps_biallelic_snps() {
+        let snps = SnpSet::from_reader(VCF.as_bytes()).unwrap();
+        assert_eq!(snps.len(), 2);
+        assert_eq!(snps.get(0).pos, 10);
+        assert_eq!((snps.get(1).ref_base, snps.get(1).alt_base), (b'C', b'T'));
+        assert_eq!(snps.in_range("chr1", 10, 15), &[0]);
+        assert!(snps.in_range("chr2", 0, 100).is_empty());
+        assert!(SnpSet::from_reader("chr1\tx\t.\tA\tG\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_query_offset() {
+        // 2S 5M 3D 5M starting at 100
+        let cigar = [Cigar::SoftClip(2), Cigar::Match(5), Cigar::Del(3), Cigar::Match(5)];
+        assert_eq!(query_offset(100, cigar.iter(), 100), Some(2));
+        assert_eq!(query_offset(100, cigar.iter(), 104), Some(6));
+        assert_eq!(query_offset(100, cigar.iter(), 106), None);
+        assert_eq!(query_offset(100, cigar.iter(), 108), Some(7));
+        assert_eq!(query_offset(100, cigar.iter(), 113), None);
+    }
+
+    #[test]
+    fn test_pileup_counts_alleles_per_cell() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.bam");
+        let mut header = bam::Header::new();
+        let mut sq = HeaderRecord::new(b"SQ");
+        sq.push_tag(b"SN", "chr1");
+        sq.push_tag(b"LN", 1000);
+        header.push_record(&sq);
+
+        let mut writer = bam::Writer::from_path(&path, &header, bam::Format::Bam).unwrap();
+        // Reads cover positions 8..18; SNPs sit at offsets 2 (rs1) and 6 (rs2)
+        let reads: [(&str, &str, &[u8]); 4] = [
+            ("AAAC", "U1", b"TTATTTCTTT"),
+            ("AAAC", "U1", b"TTGTTTTTTT"), // same UMI: not counted again
+            ("AAAC", "U2", b"TTGTTTTTTT"),
+            ("GGGT", "U1", b"TTATTTGTTT"), // rs2 base matches neither allele
+        ];
+        for (i, (cb, ub, seq)) in reads.iter().enumerate() {
+            let mut record = bam::Record::new();
+            let cigar = CigarString(vec![Cigar::Match(10)]);
+            record.set(format!("r{}", i).as_bytes(), Some(&cigar), seq, &[30; 10]);
+            record.set_tid(0);
+            record.set_pos(8);
+            record.set_mapq(60);
+            record.push_aux(b"CB", Aux::String(cb)).unwrap();
+            record.push_aux(b"UB", Aux::String(ub)).unwrap();
+            writer.write(&record).unwrap();
+        }
+        drop(writer);
+
+        let snps = SnpSet::from_reader(VCF.as_bytes()).unwrap();
+        let counts = pileup(&path, &snps, None, &PileupConfig::default()).unwrap();
+        assert_eq!(counts.barcodes, vec!["AAAC", "GGGT"]);
+        assert_eq!(counts.cells[0], vec![(0, 1, 1), (1, 1, 1)]);
+        assert_eq!(counts.cells[1], vec![(0, 1, 0)]);
+
+        let only = ["GGGT".to_string()];
+        let counts = pileup(&path, &snps, Some(&only), &PileupConfig::default()).unwrap();
+        assert_eq!(counts.cells, vec![vec![(0, 1, 0)]]);
+    }
+
+    /// Alternate allele fraction of donor `k` at SNP `s`: each donor pair
+    /// differs at most SNPs
+    fn genotype(k: usize, s: usize) -> u32 {
+        [s % 3, (s / 3) % 3, (s % 3 + s / 3) % 3][k] as u32
+    }
+
+    /// 10 singlets per donor, one doublet per donor pair, one low-coverage cell
+    fn simulated_counts() -> AlleleCounts {
+        let mut counts = AlleleCounts::default();
+        let mut add = |name: String, alt: &dyn Fn(usize) -> u32| {
+            let i = counts.cells.len();
+            // 4 reads per covered SNP; each cell covers two thirds of 36 SNPs
+            let cell = (0..36).filter(|s| (s + i) % 3 != 0).map(|s| (s, 4 - alt(s), alt(s)));
+            counts.barcodes.push(name);
+            counts.cells.push(cell.collect());
+        };
+        for k in 0..3 {
+            for i in 0..10 {
+                add(format!("S{}_{}", k, i), &|s| 2 * genotype(k, s));
+            }
+        }
+        for (i, j) in [(0, 1), (1, 2), (0, 2)] {
+            add(format!("D{}{}", i, j), &|s| genotype(i, s) + genotype(j, s));
+        }
+        counts.barcodes.push("LOW".to_string());
+        counts.cells.push(vec![(0, 2, 0), (1, 0, 2)]);
+        counts
+    }
+
+    #[test]
+    fn test_demux_singlets_and_doublets() {
+        let counts = simulated_counts();
+        let config = DonorDemuxConfig {
+            n_donors: 3,
+            ..Default::default()
+        };
+        let demux = demux_donors(&counts, 36, &config).unwrap();
+
+        // Learned donor labels are arbitrary; map them from the first singlet of each
+        let label: Vec<usize> = (0..3)
+            .map(|k| match demux.cells[k * 10].call {
+                DonorCall::Singlet(d) => d,
+                other => panic!("expected singlet, got {:?}", other),
+            })
+            .collect();
+        let mut distinct = label.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 3);
+
+        for k in 0..3 {
+            for i in 0..10 {
+                assert_eq!(demux.cells[k * 10 + i].call, DonorCall::Singlet(label[k]));
+            }
+        }
+        for (cell, (i, j)) in demux.cells[30..33].iter().zip([(0, 1), (1, 2), (0, 2)]) {
+            let (a, b) = (label[i].min(label[j]), label[i].max(label[j]));
+            assert_eq!(cell.call, DonorCall::Doublet(a, b), "{}", cell.barcode);
+        }
+        assert_eq!(demux.cells[33].call, DonorCall::Unassigned);
+        assert_eq!(demux.singlets_per_donor(), vec![10, 10, 10]);
+        assert_eq!(demux.count("doublet"), 3);
+    }
+
+    #[test]
+    fn test_demux_needs_two_donors() {
+        let counts = simulated_counts();
+        let config = DonorDemuxConfig {
+            n_donors: 1,
+            ..Default::default()
+        };
+        assert!(demux_donors(&counts, 36, &config).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! String interning for barcode, gene, and UMI identifiers
+//!
+//! Counting tables key on small integer IDs instead of owned Strings: each
+//! distinct name is stored once, lookups of a name already seen allocate
+//! nothing, and hashing a `(u32, u32)` key is far cheaper than hashing two
+//! strings.
+
+use ahash::AHashMap;
+use std::sync::Arc;
+
+/// Assigns dense `u32` IDs to strings in first-seen order
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    ids: AHashMap<Arc<str>, u32>,
+    /// Shares each allocation with `ids`
+    names: Vec<Arc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ID of `name`, assigning the next free ID if it is new
+    #[inline]
+    pub fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = u32::try_from(self.names.len()).expect("more than u32::MAX interned names");
+        let name: Arc<str> = name.into();
+        self.names.push(Arc::clone(&name));
+        self.ids.insert(name, id);
+        id
+    }
+
+    /// ID of `name` if it has been interned
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    /// Name for an ID returned by `intern`.
+    ///
+    /// Panics if the ID was not issued by this interner.
+    #[inline]
+    pub fn resolve(&self, id: u32) -> &str {
+        &self.names[id as usize]
+    }
+
+    /// Number of distinct names
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Names in ID order
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|n| &**n)
+    }
+
+    /// Names in ID order, consuming the interner
+    pub fn into_names(self) -> Vec<String> {
+        self.names.iter().map(|n| n.to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interner() {
+        let mut interner = Interner::new();
+        assert!(interner.is_empty());
+        let a = interner.intern("GENE_A");
+        let b = interner.intern("GENE_B");
+        assert_eq!((a, b), (0, 1));
+        assert_eq!(interner.intern("GENE_A"), a);
+        assert_eq!(interner.len(), 2);
+        assert_eq!(interner.get("GENE_B"), Some(b));
+        assert_eq!(interner.get("GENE_C"), None);
+        assert_eq!(interner.resolve(b), "GENE_B");
+        assert_eq!(interner.iter().collect::<Vec<_>>(), vec!["GENE_A", "GENE_B"]);
+        assert_eq!(interner.into_names(), vec!["GENE_A", "GENE_B"]);
+    }
+}
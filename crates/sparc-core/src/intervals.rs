@@ -0,0 +1,369 @@
+//! Genomic intervals: BED records, merging, and overlap queries
+//!
+//! Shared by gene annotation, ATAC peak counting, and region-level QC.
+//! Coordinates are 0-based and half-open throughout, as in BED.
+
+use crate::atomic::AtomicFile;
+use crate::{Error, Result};
+use ahash::AHashMap;
+use flate2::read::MultiGzDecoder;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Feature strand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Strand {
+    Forward,
+    Reverse,
+    Unknown,
+}
+
+impl Strand {
+    /// Parse a GTF/BED strand character (`+`, `-`, anything else unknown)
+    pub fn from_char(c: char) -> Self {
+        match c {
+            '+' => Strand::Forward,
+            '-' => Strand::Reverse,
+            _ => Strand::Unknown,
+        }
+    }
+
+    /// Strand character (`+`, `-`, or `.`)
+    pub fn as_char(&self) -> char {
+        match self {
+            Strand::Forward => '+',
+            Strand::Reverse => '-',
+            Strand::Unknown => '.',
+        }
+    }
+
+    /// The opposite strand; unknown stays unknown
+    pub fn flip(&self) -> Self {
+        match self {
+            Strand::Forward => Strand::Reverse,
+            Strand::Reverse => Strand::Forward,
+            Strand::Unknown => Strand::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Strand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+/// A genomic interval, e.g. one BED record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interval {
+    pub chrom: String,
+    pub start: i64,
+    pub end: i64,
+    /// BED name column, if present and not `.`
+    pub name: Option<String>,
+    pub strand: Strand,
+}
+
+impl Interval {
+    /// Unnamed, unstranded interval
+    pub fn new(chrom: &str, start: i64, end: i64) -> Self {
+        Self {
+            chrom: chrom.to_string(),
+            start,
+            end,
+            name: None,
+            strand: Strand::Unknown,
+        }
+    }
+
+    /// Length in bases
+    pub fn len(&self) -> i64 {
+        self.end - self.start
+    }
+
+    /// Whether the interval covers no bases
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// Whether the two intervals share at least one base
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        self.chrom == other.chrom && self.start < other.end && other.start < self.end
+    }
+
+    /// The 5' end: `start` on the forward (or unknown) strand, `end - 1` on the reverse
+    pub fn five_prime(&self) -> i64 {
+        match self.strand {
+            Strand::Reverse => self.end - 1,
+            _ => self.start,
+        }
+    }
+
+    /// `chrom:start-end`, the usual feature name for peaks
+    pub fn locus(&self) -> String {
+        format!("{}:{}-{}", self.chrom, self.start, self.end)
+    }
+}
+
+/// Read a BED file (plain or gzipped)
+pub fn read_bed<P: AsRef<Path>>(path: P) -> Result<Vec<Interval>> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        parse_bed(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        parse_bed(BufReader::new(file))
+    }
+}
+
+/// Parse BED text (BED3 or more; name and strand are read from columns 4 and 6)
+pub fn parse_bed<R: BufRead>(reader: R) -> Result<Vec<Interval>> {
+    let mut intervals = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let invalid =
+            || Error::Annotation(format!("BED line {}: invalid interval '{}'", i + 1, line));
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 || fields[0].is_empty() {
+            return Err(invalid());
+        }
+        let start: i64 = fields[1].trim().parse().map_err(|_| invalid())?;
+        let end: i64 = fields[2].trim().parse().map_err(|_| invalid())?;
+        if start < 0 || end <= start {
+            return Err(invalid());
+        }
+        intervals.push(Interval {
+            chrom: fields[0].to_string(),
+            start,
+            end,
+            name: fields
+                .get(3)
+                .map(|n| n.trim())
+                .filter(|n| !n.is_empty() && *n != ".")
+                .map(|n| n.to_string()),
+            strand: fields
+                .get(5)
+                .and_then(|s| s.trim().chars().next())
+                .map_or(Strand::Unknown, Strand::from_char),
+        });
+    }
+    Ok(intervals)
+}
+
+/// Write intervals as BED3, or BED6 when any has a name or strand
+pub fn write_bed<P: AsRef<Path>>(path: P, intervals: &[Interval]) -> Result<()> {
+    let bed6 = intervals.iter().any(|iv| iv.name.is_some() || iv.strand != Strand::Unknown);
+    let mut writer = AtomicFile::create(path)?;
+    for iv in intervals {
+        write!(writer, "{}\t{}\t{}", iv.chrom, iv.start, iv.end)?;
+        if bed6 {
+            let name = iv.name.as_deref().unwrap_or(".");
+            write!(writer, "\t{}\t0\t{}", name, iv.strand)?;
+        }
+        writeln!(writer)?;
+    }
+    writer.commit()
+}
+
+/// Sort and merge overlapping or touching intervals
+pub fn merge_intervals(mut intervals: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    intervals.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(intervals.len());
+    for (s, e) in intervals {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    merged
+}
+
+/// Static interval tree over half-open intervals of one chromosome.
+///
+/// Intervals are sorted by start and viewed as an implicit balanced binary
+/// tree (each range's midpoint is its root), augmented with the largest end
+/// in every subtree so queries skip subtrees that end before the query.
+#[derive(Debug, Clone, Default)]
+pub struct IntervalTree {
+    /// (start, end, value), sorted by start then value
+    intervals: Vec<(i64, i64, usize)>,
+    /// Largest end in the subtree rooted at each index
+    max_end: Vec<i64>,
+}
+
+impl IntervalTree {
+    /// Build from `(start, end, value)` intervals
+    pub fn new(mut intervals: Vec<(i64, i64, usize)>) -> Self {
+        intervals.sort_unstable_by_key(|&(start, _, value)| (start, value));
+        let mut tree = Self {
+            max_end: vec![i64::MIN; intervals.len()],
+            intervals,
+        };
+        tree.augment(0, tree.intervals.len());
+        tree
+    }
+
+    fn augment(&mut self, lo: usize, hi: usize) -> i64 {
+        if lo >= hi {
+            return i64::MIN;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let max_end = self.intervals[mid]
+            .1
+            .max(self.augment(lo, mid))
+            .max(self.augment(mid + 1, hi));
+        self.max_end[mid] = max_end;
+        max_end
+    }
+
+    /// Number of intervals
+    pub fn len(&self) -> usize {
+        self.intervals.len()
+    }
+
+    /// Whether the tree holds no intervals
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Append the values of intervals overlapping `[start, end)` to `out`,
+    /// ordered by interval start
+    pub fn query(&self, start: i64, end: i64, out: &mut Vec<usize>) {
+        self.query_range(0, self.intervals.len(), start, end, out);
+    }
+
+    fn query_range(&self, lo: usize, hi: usize, start: i64, end: i64, out: &mut Vec<usize>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        if self.max_end[mid] <= start {
+            return;
+        }
+        self.query_range(lo, mid, start, end, out);
+        let (s, e, value) = self.intervals[mid];
+        // Everything right of `mid` starts at or after `s`
+        if s < end {
+            if e > start {
+                out.push(value);
+            }
+            self.query_range(mid + 1, hi, start, end, out);
+        }
+    }
+}
+
+/// Interval trees for every chromosome, answering with the position of each
+/// interval in the input order
+#[derive(Debug, Clone, Default)]
+pub struct IntervalIndex {
+    trees: AHashMap<String, IntervalTree>,
+}
+
+impl IntervalIndex {
+    /// Index `(chrom, start, end)` intervals
+    pub fn new<'a, I>(intervals: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, i64, i64)>,
+    {
+        let mut by_chrom: AHashMap<String, Vec<(i64, i64, usize)>> = AHashMap::new();
+        for (i, (chrom, start, end)) in intervals.into_iter().enumerate() {
+            match by_chrom.get_mut(chrom) {
+                Some(list) => list.push((start, end, i)),
+                None => {
+                    by_chrom.insert(chrom.to_string(), vec![(start, end, i)]);
+                }
+            }
+        }
+        let trees = by_chrom
+            .into_iter()
+            .map(|(chrom, intervals)| (chrom, IntervalTree::new(intervals)))
+            .collect();
+        Self { trees }
+    }
+
+    /// Index BED-style intervals
+    pub fn from_intervals(intervals: &[Interval]) -> Self {
+        Self::new(intervals.iter().map(|iv| (iv.chrom.as_str(), iv.start, iv.end)))
+    }
+
+    /// Append positions of intervals overlapping `[start, end)` on `chrom` to `out`
+    pub fn query(&self, chrom: &str, start: i64, end: i64, out: &mut Vec<usize>) {
+        if let Some(tree) = self.trees.get(chrom) {
+            tree.query(start, end, out);
+        }
+    }
+
+    /// Positions of intervals overlapping `[start, end)` on `chrom`, by start
+    pub fn overlapping(&self, chrom: &str, start: i64, end: i64) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.query(chrom, start, end, &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_tree_matches_brute_force() {
+        let intervals: Vec<(i64, i64, usize)> = (0..200)
+            .map(|i| {
+                let start = (i * 37 % 1000) as i64;
+                (start, start + (i * 13 % 90) as i64 + 1, i)
+            })
+            .collect();
+        let tree = IntervalTree::new(intervals.clone());
+        for (start, end) in [(0, 1), (100, 150), (500, 501), (950, 2000), (-10, 0)] {
+            let mut found = Vec::new();
+            tree.query(start, end, &mut found);
+            found.sort_unstable();
+            let expected: Vec<usize> = intervals
+                .iter()
+                .filter(|&&(s, e, _)| s < end && e > start)
+                .map(|&(_, _, i)| i)
+                .collect();
+            assert_eq!(found, expected, "query {}-{}", start, end);
+        }
+    }
+
+    #[test]
+    fn test_parse_bed() {
+        let text = "track name=x\nchr1\t10\t20\nchr1\t5\t50\tgeneA\t0\t-\n#c\nchr2\t0\t5\t.\n";
+        let intervals = parse_bed(text.as_bytes()).unwrap();
+        assert_eq!(intervals.len(), 3);
+        assert_eq!(intervals[0], Interval::new("chr1", 10, 20));
+        assert_eq!(intervals[1].name.as_deref(), Some("geneA"));
+        assert_eq!(intervals[1].strand, Strand::Reverse);
+        assert_eq!(intervals[1].five_prime(), 49);
+        assert_eq!(intervals[2].name, None);
+
+        let err = parse_bed("chr1\t20\t10\n".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("BED line 1"));
+    }
+
+    #[test]
+    fn test_index_and_merge() {
+        let intervals = parse_bed("chr1\t100\t200\nchr2\t0\t10\nchr1\t50\t120\n".as_bytes())
+            .unwrap();
+        let index = IntervalIndex::from_intervals(&intervals);
+        // Ordered by start
+        assert_eq!(index.overlapping("chr1", 110, 111), vec![2, 0]);
+        assert_eq!(index.overlapping("chr1", 200, 300), Vec::<usize>::new());
+        assert_eq!(index.overlapping("chrX", 0, 10), Vec::<usize>::new());
+
+        assert_eq!(
+            merge_intervals(vec![(10, 20), (0, 5), (5, 8), (15, 30)]),
+            vec![(0, 8), (10, 30)]
+        );
+    }
+}
@@ -5,27 +5,58 @@
 //! This crate provides the core functionality for processing single-cell sequencing data,
 //! including FASTQ/BAM parsing, barcode detection, UMI deduplication, count matrix generation,
 //! alignment integration, streaming processing, and downstream analysis.
+//!
+//! BAM handling (and everything built on htslib) needs the default `native`
+//! feature. With `--no-default-features` the crate builds for `wasm32`, keeping
+//! FASTQ parsing, protocols, barcodes, and QC metrics.
 
+pub mod adt;
 pub mod aligner;
+pub mod ambient;
 pub mod analysis;
+pub mod annotation;
+pub mod atac;
+pub mod atomic;
+#[cfg(feature = "native")]
 pub mod bam;
 pub mod barcode;
+pub mod benchmark;
 pub mod count;
+pub mod crispr;
+pub mod demux;
 pub mod fastq;
+#[cfg(feature = "native")]
+pub mod genotype;
+pub mod intern;
+pub mod intervals;
+pub mod longread;
+pub mod metrics;
+pub mod perf;
 pub mod protocols;
+pub mod pseudoalign;
 pub mod qc;
+pub mod remote;
+pub mod resources;
+pub mod seed;
+pub mod seq_util;
+pub mod sim;
+pub mod spatial;
 pub mod streaming;
 pub mod umi;
 pub mod validation;
+#[cfg(feature = "native")]
+pub mod velocity;
 
 pub use aligner::{Aligner, AlignerConfig, AlignerType};
+pub use annotation::{GeneAnnotation, RegionType};
+#[cfg(feature = "native")]
 pub use bam::{BamParser, BamRecord, BamWriter};
 pub use barcode::{BarcodeCorrector, BarcodeMatcher, Whitelist};
 pub use count::{CountMatrix, CsrMatrix, GeneCounter};
 pub use fastq::{FastqParser, FastqRecord, FastqWriter};
 pub use protocols::{DropSeq, InDrop, Protocol, SciRNA, SmartSeq2, TenX3Prime, TenX5Prime};
 pub use qc::{QcMetrics, QcReport};
-pub use streaming::{StreamConfig, StreamStats, StreamingProcessor};
+pub use streaming::{ChunkPipeline, StreamConfig, StreamStats, StreamingProcessor};
 pub use umi::{UmiDeduplicator, UmiGraph};
 pub use validation::{ValidationReport, SyntheticConfig, SyntheticDataset, TruthSet};
 
@@ -38,9 +69,21 @@ pub enum Error {
     #[error("FASTQ parsing error: {0}")]
     FastqParse(String),
 
+    #[error("FASTQ parsing error: {context}: {message}")]
+    FastqRecord {
+        context: Box<RecordContext>,
+        message: String,
+    },
+
     #[error("BAM parsing error: {0}")]
     BamParse(String),
 
+    #[error("BAM parsing error: {context}: {message}")]
+    BamRecord {
+        context: Box<RecordContext>,
+        message: String,
+    },
+
     #[error("Barcode error: {0}")]
     Barcode(String),
 
@@ -52,10 +95,97 @@ pub enum Error {
 
     #[error("Invalid read structure: {0}")]
     ReadStructure(String),
+
+    #[error("Annotation error: {0}")]
+    Annotation(String),
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    #[error("Matrix error: {0}")]
+    Matrix(String),
+
+    #[error("Invalid count matrix: {0}")]
+    InvalidMatrix(#[from] count::MatrixIssue),
+}
+
+impl Error {
+    /// Where the offending record sits, for errors raised on a single record
+    pub fn record_context(&self) -> Option<&RecordContext> {
+        match self {
+            Error::FastqRecord { context, .. } | Error::BamRecord { context, .. } => {
+                Some(&**context)
+            }
+            _ => None,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Location of a malformed record in its input file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordContext {
+    /// File path, or the stream name given to the parser
+    pub path: String,
+    /// 1-based record number
+    pub record: u64,
+    /// Read name, when the record got far enough to have one
+    pub read_name: Option<String>,
+    /// Byte offset of the record: into the decompressed stream for FASTQ, of
+    /// the enclosing BGZF block in the file for BAM
+    pub byte_offset: Option<u64>,
+}
+
+impl RecordContext {
+    pub fn new(path: impl Into<String>, record: u64) -> Self {
+        Self {
+            path: path.into(),
+            record,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_read_name(mut self, read_name: impl Into<String>) -> Self {
+        self.read_name = Some(read_name.into());
+        self
+    }
+
+    pub fn with_byte_offset(mut self, byte_offset: u64) -> Self {
+        self.byte_offset = Some(byte_offset);
+        self
+    }
+
+    /// A FASTQ parse error at this record
+    pub fn fastq_error(self, message: impl Into<String>) -> Error {
+        Error::FastqRecord {
+            context: Box::new(self),
+            message: message.into(),
+        }
+    }
+
+    /// A BAM parse error at this record
+    pub fn bam_error(self, message: impl Into<String>) -> Error {
+        Error::BamRecord {
+            context: Box::new(self),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for RecordContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: record {}", self.path, self.record)?;
+        if let Some(name) = &self.read_name {
+            write!(f, " ('{}')", name)?;
+        }
+        if let Some(offset) = self.byte_offset {
+            write!(f, " at byte {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
 /// Read structure definition for parsing sequencing reads
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ReadStructure {
@@ -112,4 +242,119 @@ impl ReadStructure {
     pub fn scirna() -> Self {
         Self::new(0, 10, 10, 8, 0)
     }
+
+    /// Parse a read structure from the segment DSL
+    ///
+    /// A spec is a sequence of `<length><kind>` segments, where kind is `C`
+    /// (cell barcode), `U` (UMI), `X` (skipped bases), or `T` (cDNA through the
+    /// end of the read, so it must come last). `+` can stand in for the length
+    /// of the last segment. For example `16C12U` is 10x 3' v3 and `8X12C8U+T`
+    /// skips 8 bases and keeps the cDNA that follows the UMI on the same read.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = |msg: String| Error::ReadStructure(format!("'{}': {}", spec, msg));
+        let mut rs = Self::new(0, 0, 0, 0, 0);
+        let mut chars = spec.trim().chars().peekable();
+        if chars.peek().is_none() {
+            return Err(invalid("empty read structure".to_string()));
+        }
+
+        let mut pos = 0;
+        let mut seen = String::new();
+        let mut last = false;
+        while chars.peek().is_some() {
+            if last {
+                return Err(invalid("'T' and '+' segments must come last".to_string()));
+            }
+            let len = if chars.next_if_eq(&'+').is_some() {
+                last = true;
+                None
+            } else {
+                let mut digits = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                }
+                match digits.parse::<usize>() {
+                    Ok(0) => return Err(invalid("segment lengths must be positive".to_string())),
+                    Ok(len) => Some(len),
+                    Err(_) => return Err(invalid(format!("expected a length at offset {}", pos))),
+                }
+            };
+            let kind = chars
+                .next()
+                .ok_or_else(|| invalid("segment length without a kind".to_string()))?
+                .to_ascii_uppercase();
+            if kind != 'X' && seen.contains(kind) {
+                return Err(invalid(format!("more than one '{}' segment", kind)));
+            }
+            seen.push(kind);
+
+            match (kind, len) {
+                ('C', Some(len)) => {
+                    rs.barcode_start = pos;
+                    rs.barcode_len = len;
+                }
+                ('U', Some(len)) => {
+                    rs.umi_start = pos;
+                    rs.umi_len = len;
+                }
+                ('C' | 'U', None) => {
+                    return Err(invalid(format!("'{}' segments need a fixed length", kind)));
+                }
+                ('T', _) => {
+                    rs.cdna_start = pos;
+                    last = true;
+                }
+                ('X', _) => {}
+                (other, _) => {
+                    return Err(invalid(format!(
+                        "unknown segment kind '{}' (expected C, U, X, or T)",
+                        other
+                    )));
+                }
+            }
+            pos += len.unwrap_or(0);
+        }
+        Ok(rs)
+    }
+
+    /// Minimum R1 length that contains the barcode and UMI
+    pub fn min_len(&self) -> usize {
+        (self.barcode_start + self.barcode_len).max(self.umi_start + self.umi_len)
+    }
+}
+
+impl std::str::FromStr for ReadStructure {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl std::fmt::Display for ReadStructure {
+    /// Format in the segment DSL accepted by [`ReadStructure::parse`]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut segments = vec![
+            (self.barcode_start, self.barcode_len, 'C'),
+            (self.umi_start, self.umi_len, 'U'),
+        ];
+        segments.retain(|&(_, len, _)| len > 0);
+        segments.sort();
+
+        let mut pos = 0;
+        for (start, len, kind) in segments {
+            if start > pos {
+                write!(f, "{}X", start - pos)?;
+            }
+            write!(f, "{}{}", len, kind)?;
+            pos = start + len;
+        }
+        if self.cdna_start > 0 || pos == 0 {
+            if self.cdna_start > pos {
+                write!(f, "{}X", self.cdna_start - pos)?;
+            }
+            write!(f, "+T")?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,126 @@
+//! Approximate adapter search
+//!
+//! In long reads an adapter can sit anywhere and carries indels, so it is
+//! found by semi-global edit-distance alignment (Sellers' algorithm): the whole
+//! adapter must align, but it may start and end anywhere in the read.
+
+/// `read[start..end]` aligns to the adapter with `errors` edits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdapterHit {
+    pub start: usize,
+    pub end: usize,
+    pub errors: u32,
+}
+
+/// Occurrence of `adapter` in `read` with the fewest edits (the leftmost on
+/// ties), if any is within `max_errors`
+pub fn find_adapter(read: &[u8], adapter: &[u8], max_errors: u32) -> Option<AdapterHit> {
+    find_adapters(read, adapter, max_errors)
+        .into_iter()
+        .min_by_key(|hit| hit.errors)
+}
+
+/// Non-overlapping occurrences of `adapter` in `read` within `max_errors`
+/// edits, left to right
+pub fn find_adapters(read: &[u8], adapter: &[u8], max_errors: u32) -> Vec<AdapterHit> {
+    let mut hits: Vec<AdapterHit> = Vec::new();
+    if adapter.is_empty() {
+        return hits;
+    }
+    // Alignments ending at neighbouring read positions are one occurrence;
+    // keep the best of each run of ends within `max_errors`
+    let mut best: Option<AdapterHit> = None;
+    align_ends(read, adapter, |hit| {
+        if hit.errors > max_errors {
+            if let Some(done) = best.take() {
+                push(done, &mut hits);
+            }
+            return;
+        }
+        match best {
+            Some(current) if hit.start < current.end => {
+                if hit.errors < current.errors {
+                    best = Some(hit);
+                }
+            }
+            Some(current) => {
+                push(current, &mut hits);
+                best = Some(hit);
+            }
+            None => best = Some(hit),
+        }
+    });
+    if let Some(done) = best {
+        push(done, &mut hits);
+    }
+    hits
+}
+
+fn push(hit: AdapterHit, hits: &mut Vec<AdapterHit>) {
+    if hits.last().map_or(true, |last| hit.start >= last.end) {
+        hits.push(hit);
+    }
+}
+
+/// Best alignment of the whole adapter ending at each read position
+fn align_ends(read: &[u8], adapter: &[u8], mut f: impl FnMut(AdapterHit)) {
+    // (edits, read start) of the best alignment of each adapter prefix
+    let mut column: Vec<(u32, usize)> = (0..=adapter.len()).map(|i| (i as u32, 0)).collect();
+    for (j, &base) in read.iter().enumerate() {
+        let mut diagonal = column[0];
+        column[0] = (0, j + 1);
+        for i in 1..=adapter.len() {
+            let (up, left) = (column[i - 1], column[i]);
+            let mut cell = (diagonal.0 + (adapter[i - 1] != base) as u32, diagonal.1);
+            if up.0 + 1 < cell.0 {
+                cell = (up.0 + 1, up.1);
+            }
+            if left.0 + 1 < cell.0 {
+                cell = (left.0 + 1, left.1);
+            }
+            diagonal = left;
+            column[i] = cell;
+        }
+        let (errors, start) = column[adapter.len()];
+        f(AdapterHit {
+            start,
+            end: j + 1,
+            errors,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADAPTER: &[u8] = b"CTACACGACGCTCTTCCGATCT";
+
+    #[test]
+    fn test_find_adapter() {
+        let read = [b"GGGGG".as_slice(), ADAPTER, b"AAAACCCC".as_slice()].concat();
+        assert_eq!(
+            find_adapter(&read, ADAPTER, 2),
+            Some(AdapterHit { start: 5, end: 27, errors: 0 })
+        );
+
+        // One deleted and one substituted base
+        let mut damaged = ADAPTER.to_vec();
+        damaged.remove(8);
+        damaged[15] = b'A';
+        let read = [b"TTT".as_slice(), &damaged[..], b"GATTACA".as_slice()].concat();
+        let hit = find_adapter(&read, ADAPTER, 3).unwrap();
+        assert_eq!((hit.start, hit.end, hit.errors), (3, 24, 2));
+        assert_eq!(find_adapter(&read, ADAPTER, 1), None);
+    }
+
+    #[test]
+    fn test_find_adapters() {
+        let read = [ADAPTER, b"ACGTACGTAC".as_slice(), ADAPTER, b"TT".as_slice()].concat();
+        let hits = find_adapters(&read, ADAPTER, 2);
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].start, hits[0].end), (0, 22));
+        assert_eq!((hits[1].start, hits[1].end), (32, 54));
+        assert!(find_adapters(&read, b"", 2).is_empty());
+    }
+}
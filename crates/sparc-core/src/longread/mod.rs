@@ -0,0 +1,299 @@
+//! Long-read (PacBio, ONT) single-cell barcode extraction
+//!
+//! A long read holds a whole molecule in either orientation, and with MAS-Seq
+//! several molecules concatenated into one array. Instead of reading the
+//! barcode at a fixed offset, each molecule's 10x Read 1 adapter is located by
+//! edit-distance alignment ([`find_adapter`]); the barcode and UMI follow it,
+//! and the barcode is corrected allowing one insertion, deletion, or
+//! substitution ([`EditCorrector`]).
+
+mod anchor;
+mod segment;
+
+pub use anchor::{find_adapter, find_adapters, AdapterHit};
+pub use segment::segment_array;
+
+use crate::barcode::EditCorrector;
+use crate::fastq::{AnnotationStyle, FastqRecord};
+use crate::seq_util::reverse_complement;
+use serde::{Deserialize, Serialize};
+
+/// 10x Genomics Read 1 adapter, immediately 5' of the cell barcode
+pub const TENX_READ1: &[u8] = b"CTACACGACGCTCTTCCGATCT";
+
+/// Where the barcode and UMI sit in each molecule
+#[derive(Debug, Clone)]
+pub struct LongReadLayout {
+    /// Adapter immediately 5' of the barcode
+    pub adapter: Vec<u8>,
+    /// Edits allowed when locating `adapter`
+    pub max_adapter_errors: u32,
+    pub umi_len: usize,
+    /// MAS-Seq segmentation adapters; empty for one molecule per read
+    pub array_adapters: Vec<Vec<u8>>,
+    /// Edits allowed when locating an array adapter
+    pub max_array_errors: u32,
+    /// Shortest cDNA insert kept after the barcode, UMI, and polyT
+    pub min_insert_len: usize,
+}
+
+impl Default for LongReadLayout {
+    /// 10x Genomics 3' v3: Read 1, 16 bp barcode, 12 bp UMI, polyT
+    fn default() -> Self {
+        Self {
+            adapter: TENX_READ1.to_vec(),
+            max_adapter_errors: 4,
+            umi_len: 12,
+            array_adapters: Vec::new(),
+            max_array_errors: 2,
+            min_insert_len: 50,
+        }
+    }
+}
+
+/// Outcome counts of long-read extraction
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LongReadStats {
+    pub reads: u64,
+    /// Molecules after array segmentation
+    pub segments: u64,
+    /// Segments without the adapter in either orientation
+    pub no_adapter: u64,
+    /// Segments whose barcode is not within one edit of exactly one whitelist barcode
+    pub no_barcode: u64,
+    /// Segments too short for the UMI or a `min_insert_len` insert
+    pub too_short: u64,
+    /// Molecules extracted
+    pub extracted: u64,
+    /// Extracted molecules whose barcode needed an edit
+    pub corrected: u64,
+    /// Extracted molecules read reverse-complemented
+    pub reverse: u64,
+}
+
+impl LongReadStats {
+    pub fn merge(&mut self, other: &Self) {
+        self.reads += other.reads;
+        self.segments += other.segments;
+        self.no_adapter += other.no_adapter;
+        self.no_barcode += other.no_barcode;
+        self.too_short += other.too_short;
+        self.extracted += other.extracted;
+        self.corrected += other.corrected;
+        self.reverse += other.reverse;
+    }
+}
+
+/// Splits long reads into molecules and tags each with its barcode and UMI
+pub struct LongReadExtractor {
+    layout: LongReadLayout,
+    corrector: EditCorrector,
+}
+
+impl LongReadExtractor {
+    pub fn new(layout: LongReadLayout, corrector: EditCorrector) -> Self {
+        Self { layout, corrector }
+    }
+
+    /// The cDNA insert of each molecule in `read`, in transcript orientation,
+    /// annotated with its barcode and UMI. Molecules of an array are named
+    /// `<name>:<n>`, counting from 1.
+    pub fn extract(
+        &self,
+        read: &FastqRecord,
+        style: AnnotationStyle,
+        stats: &mut LongReadStats,
+    ) -> Vec<FastqRecord> {
+        stats.reads += 1;
+        let layout = &self.layout;
+        let spans = segment_array(
+            &read.seq,
+            &layout.array_adapters,
+            layout.max_array_errors,
+            layout.min_insert_len,
+        );
+        let numbered = spans.len() > 1;
+        let mut molecules = Vec::with_capacity(spans.len());
+        for (i, span) in spans.into_iter().enumerate() {
+            stats.segments += 1;
+            let id = if numbered {
+                format!("{}:{}", read.name(), i + 1)
+            } else {
+                read.name().to_string()
+            };
+            let (seq, qual) = (&read.seq[span.clone()], read.qual.get(span).unwrap_or(&[]));
+            if let Some(molecule) = self.extract_molecule(id, seq, qual, style, stats) {
+                molecules.push(molecule);
+            }
+        }
+        molecules
+    }
+
+    fn extract_molecule(
+        &self,
+        id: String,
+        seq: &[u8],
+        qual: &[u8],
+        style: AnnotationStyle,
+        stats: &mut LongReadStats,
+    ) -> Option<FastqRecord> {
+        let layout = &self.layout;
+        // Orient the molecule so the adapter reads forward, preferring the
+        // orientation where it matches with fewer edits
+        let forward = find_adapter(seq, &layout.adapter, layout.max_adapter_errors);
+        let flipped = reverse_complement(seq);
+        let reverse = find_adapter(&flipped, &layout.adapter, layout.max_adapter_errors);
+        let (seq, qual, hit, is_reverse) = match (forward, reverse) {
+            (Some(f), Some(r)) if r.errors < f.errors => {
+                (flipped, qual.iter().rev().copied().collect::<Vec<u8>>(), r, true)
+            }
+            (Some(f), _) => (seq.to_vec(), qual.to_vec(), f, false),
+            (None, Some(r)) => (flipped, qual.iter().rev().copied().collect::<Vec<u8>>(), r, true),
+            (None, None) => {
+                stats.no_adapter += 1;
+                return None;
+            }
+        };
+
+        let barcode_start = hit.end;
+        let window_end = (barcode_start + self.corrector.barcode_len() + 1).min(seq.len());
+        let Some(found) = self.corrector.correct(&seq[barcode_start..window_end]) else {
+            stats.no_barcode += 1;
+            return None;
+        };
+        let umi_start = barcode_start + found.len;
+        let umi_end = umi_start + layout.umi_len;
+        let Some(umi) = seq.get(umi_start..umi_end) else {
+            stats.too_short += 1;
+            return None;
+        };
+        // The insert follows the oligo-dT primer, antisense to the transcript
+        let insert_start = umi_end + seq[umi_end..].iter().take_while(|&&b| b == b'T').count();
+        if seq.len() - insert_start < layout.min_insert_len {
+            stats.too_short += 1;
+            return None;
+        }
+
+        let raw_barcode = String::from_utf8_lossy(&seq[barcode_start..umi_start]);
+        let umi = String::from_utf8_lossy(umi);
+        let insert_qual = qual.get(insert_start..).unwrap_or(&[]).iter().rev().copied();
+        let mut record = FastqRecord::new(
+            id,
+            reverse_complement(&seq[insert_start..]),
+            insert_qual.collect(),
+        );
+        record.annotate(&raw_barcode, &found.barcode, &umi, style);
+
+        stats.extracted += 1;
+        stats.corrected += (found.distance > 0) as u64;
+        stats.reverse += is_reverse as u64;
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::barcode::Whitelist;
+
+    const BARCODE: &[u8] = b"AAACCCAAGAAACACT";
+    const UMI: &[u8] = b"GATTACAGATTA";
+
+    fn extractor(layout: LongReadLayout) -> LongReadExtractor {
+        let whitelist = Whitelist::from_vec(vec![
+            String::from_utf8(BARCODE.to_vec()).unwrap(),
+            "TTTGTCATCAGCGTAC".to_string(),
+        ])
+        .unwrap();
+        LongReadExtractor::new(layout, EditCorrector::new(&whitelist).unwrap())
+    }
+
+    /// A 10x 3' molecule as sequenced from the Read 1 side
+    fn molecule(barcode: &[u8], transcript: &[u8]) -> Vec<u8> {
+        [
+            b"GGAT".as_slice(),
+            TENX_READ1,
+            barcode,
+            UMI,
+            [b'T'; 25].as_slice(),
+            reverse_complement(transcript).as_slice(),
+        ]
+        .concat()
+    }
+
+    fn transcript() -> Vec<u8> {
+        b"ATGGCCCTGTGGATGCGCCTCCTGCCCCTGCTGGCGCTGCTGGCCCTCTGGGGACCTGACCCAGCCGCAGCC".to_vec()
+    }
+
+    fn read(seq: Vec<u8>) -> FastqRecord {
+        let qual = vec![b'I'; seq.len()];
+        FastqRecord::new("m1/5/ccs".to_string(), seq, qual)
+    }
+
+    #[test]
+    fn test_extract_both_orientations() {
+        let extractor = extractor(LongReadLayout::default());
+        let mut stats = LongReadStats::default();
+
+        let forward = read(molecule(BARCODE, &transcript()));
+        let out = extractor.extract(&forward, AnnotationStyle::ReadName, &mut stats);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].id, "m1/5/ccs_AAACCCAAGAAACACT_GATTACAGATTA");
+        assert_eq!(out[0].seq, transcript());
+
+        let reverse = read(reverse_complement(&molecule(BARCODE, &transcript())));
+        let out = extractor.extract(&reverse, AnnotationStyle::ReadName, &mut stats);
+        assert_eq!(out[0].seq, transcript());
+        assert_eq!((stats.extracted, stats.reverse), (2, 1));
+    }
+
+    #[test]
+    fn test_extract_barcode_indel() {
+        let extractor = extractor(LongReadLayout::default());
+        let mut stats = LongReadStats::default();
+        // A deleted barcode base shifts the UMI, which is still read in full
+        let mut barcode = BARCODE.to_vec();
+        barcode.remove(5);
+        let out = extractor.extract(
+            &read(molecule(&barcode, &transcript())),
+            AnnotationStyle::ReadName,
+            &mut stats,
+        );
+        assert_eq!(out[0].barcode_umi(), Some(("AAACCCAAGAAACACT", "GATTACAGATTA")));
+        assert_eq!(stats.corrected, 1);
+
+        let out = extractor.extract(
+            &read(molecule(b"GGGGGGGGGGGGGGGG", &transcript())),
+            AnnotationStyle::ReadName,
+            &mut stats,
+        );
+        assert!(out.is_empty());
+        assert_eq!(stats.no_barcode, 1);
+    }
+
+    #[test]
+    fn test_extract_array() {
+        let adapter = b"AGCTTACTATCGCGTA".to_vec();
+        let extractor = extractor(LongReadLayout {
+            array_adapters: vec![adapter.clone()],
+            ..LongReadLayout::default()
+        });
+        let mut stats = LongReadStats::default();
+        let array = [
+            molecule(BARCODE, &transcript()),
+            adapter,
+            reverse_complement(&molecule(BARCODE, &transcript())),
+        ]
+        .concat();
+        let out = extractor.extract(&read(array), AnnotationStyle::ReadName, &mut stats);
+        let names: Vec<&str> = out.iter().map(|r| r.name()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "m1/5/ccs:1_AAACCCAAGAAACACT_GATTACAGATTA",
+                "m1/5/ccs:2_AAACCCAAGAAACACT_GATTACAGATTA"
+            ]
+        );
+        assert_eq!((stats.segments, stats.extracted), (2, 2));
+    }
+}
@@ -0,0 +1,69 @@
+//! MAS-Seq array segmentation
+//!
+//! MAS-Seq (PacBio Kinnex) concatenates several cDNA molecules into one read,
+//! joined by known segmentation adapters. Cutting the read at every adapter
+//! occurrence, in either orientation, recovers the molecules.
+
+use super::anchor::{find_adapters, AdapterHit};
+use crate::seq_util::reverse_complement;
+use std::ops::Range;
+
+/// Spans of `read` between occurrences of any of `adapters` (forward or
+/// reverse complement, within `max_errors` edits), without those shorter than
+/// `min_len`. With no adapters the whole read is one span.
+pub fn segment_array(
+    read: &[u8],
+    adapters: &[Vec<u8>],
+    max_errors: u32,
+    min_len: usize,
+) -> Vec<Range<usize>> {
+    let mut hits: Vec<AdapterHit> = adapters
+        .iter()
+        .flat_map(|adapter| {
+            let mut hits = find_adapters(read, adapter, max_errors);
+            hits.extend(find_adapters(read, &reverse_complement(adapter), max_errors));
+            hits
+        })
+        .collect();
+    hits.sort_unstable_by_key(|hit| (hit.start, hit.errors));
+
+    let mut spans = Vec::with_capacity(hits.len() + 1);
+    let mut from = 0;
+    for hit in hits {
+        // Overlaps an adapter already cut at
+        if hit.start < from {
+            continue;
+        }
+        spans.push(from..hit.start);
+        from = hit.end;
+    }
+    spans.push(from..read.len());
+    spans.retain(|span| span.len() >= min_len.max(1));
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_array() {
+        let a = b"AGCTTACTATCGCGTA".to_vec();
+        let b = b"CATGGTCAGGATCCAG".to_vec();
+        let molecule = |base: u8| vec![base; 30];
+        let read = [
+            molecule(b'A'),
+            a.clone(),
+            molecule(b'C'),
+            reverse_complement(&b),
+            molecule(b'G'),
+            a.clone(),
+            b"TT".to_vec(),
+        ]
+        .concat();
+
+        let spans = segment_array(&read, &[a, b], 2, 10);
+        assert_eq!(spans, vec![0..30, 46..76, 92..122]);
+        assert_eq!(segment_array(&read, &[], 2, 10), vec![0..read.len()]);
+    }
+}
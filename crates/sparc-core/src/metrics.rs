@@ -0,0 +1,168 @@
+//! Named run counters shared across stages
+//!
+//! A [`Metrics`] registry hands out atomic `u64` counters by `(stage, name)`,
+//! so worker threads can feed the same counter and a command's summary reads
+//! them back. Per-sample registries are merged into the process-wide
+//! [`global`] one, which the CLI logs and copies into `run_manifest.json`.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+static GLOBAL: OnceLock<Metrics> = OnceLock::new();
+
+/// A `u64` tally that can be bumped from any thread
+#[derive(Debug)]
+pub struct Counter {
+    stage: &'static str,
+    name: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    /// Add `n`
+    pub fn add(&self, n: u64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Add one
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Current value
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// Stage the counter belongs to
+    pub fn stage(&self) -> &'static str {
+        self.stage
+    }
+
+    /// Counter name within its stage
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// A set of counters, grouped by stage
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<Vec<Arc<Counter>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The counter `name` of `stage`, registered at zero on first use
+    pub fn counter(&self, stage: &'static str, name: &'static str) -> Arc<Counter> {
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(c) = counters.iter().find(|c| c.stage == stage && c.name == name) {
+            return Arc::clone(c);
+        }
+        let c = Arc::new(Counter {
+            stage,
+            name,
+            value: AtomicU64::new(0),
+        });
+        counters.push(Arc::clone(&c));
+        c
+    }
+
+    /// Add every counter of `other` to the same-named counter here
+    pub fn merge(&self, other: &Metrics) {
+        let theirs = other.counters.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for c in theirs {
+            self.counter(c.stage, c.name).add(c.get());
+        }
+    }
+
+    /// Current values of all counters
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stages: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+        for c in counters.iter() {
+            stages
+                .entry(c.stage.to_string())
+                .or_default()
+                .insert(c.name.to_string(), c.get());
+        }
+        MetricsSnapshot(stages)
+    }
+}
+
+/// The process-wide registry
+pub fn global() -> &'static Metrics {
+    GLOBAL.get_or_init(Metrics::new)
+}
+
+/// Every counter's value, grouped by stage
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct MetricsSnapshot(pub BTreeMap<String, BTreeMap<String, u64>>);
+
+impl MetricsSnapshot {
+    /// True when no counter was registered
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Value of one counter, if registered
+    pub fn get(&self, stage: &str, name: &str) -> Option<u64> {
+        self.0.get(stage)?.get(name).copied()
+    }
+
+    /// One `stage.name  value` line per counter
+    pub fn summary(&self) -> String {
+        let width = self
+            .0
+            .iter()
+            .flat_map(|(stage, names)| names.keys().map(move |n| stage.len() + n.len() + 1))
+            .max()
+            .unwrap_or(0);
+        let mut out = String::new();
+        for (stage, names) in &self.0 {
+            for (name, value) in names {
+                let key = format!("{}.{}", stage, name);
+                out.push_str(&format!("{:<width$}  {}\n", key, value, width = width));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_counters() {
+        let metrics = Metrics::new();
+        let reads = metrics.counter("extract", "reads");
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let reads = metrics.counter("extract", "reads");
+                    for _ in 0..1000 {
+                        reads.inc();
+                    }
+                });
+            }
+        });
+        reads.add(5);
+        metrics.counter("count", "assigned");
+
+        let total = Metrics::new();
+        total.merge(&metrics);
+        total.merge(&metrics);
+        let snapshot = total.snapshot();
+        assert_eq!(snapshot.get("extract", "reads"), Some(8010));
+        assert_eq!(snapshot.get("count", "assigned"), Some(0));
+        assert_eq!(snapshot.get("count", "missing"), None);
+        assert_eq!(snapshot.summary(), "count.assigned  0\nextract.reads   8010\n");
+    }
+}
@@ -0,0 +1,115 @@
+//! Per-stage wall time and peak memory
+//!
+//! Commands wrap their stages in [`stage`]. When the returned timer finishes
+//! (or is dropped) the stage's wall time and the process's peak resident set
+//! size so far are logged at debug level and kept in a process-wide registry,
+//! which the CLI copies into `run_manifest.json`.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Instant;
+
+static STAGES: Mutex<Vec<StageTiming>> = Mutex::new(Vec::new());
+
+/// A finished stage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageTiming {
+    pub name: String,
+    pub wall_time_secs: f64,
+    /// Process peak RSS when the stage ended (`None` where unsupported)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Times a stage until `finish` is called or it goes out of scope
+#[must_use = "the stage is recorded when the timer is dropped"]
+pub struct StageTimer {
+    name: String,
+    started: Instant,
+}
+
+/// Start timing a stage
+pub fn stage(name: impl Into<String>) -> StageTimer {
+    let name = name.into();
+    log::debug!("Stage {} started", name);
+    StageTimer {
+        name,
+        started: Instant::now(),
+    }
+}
+
+impl StageTimer {
+    /// End the stage now
+    pub fn finish(self) {}
+}
+
+impl Drop for StageTimer {
+    fn drop(&mut self) {
+        let timing = StageTiming {
+            name: std::mem::take(&mut self.name),
+            wall_time_secs: self.started.elapsed().as_secs_f64(),
+            peak_rss_bytes: peak_rss_bytes(),
+        };
+        log::debug!(
+            "Stage {} finished in {:.2}s (peak RSS {})",
+            timing.name,
+            timing.wall_time_secs,
+            timing.peak_rss_bytes.map_or("unknown".to_string(), format_bytes)
+        );
+        if let Ok(mut stages) = STAGES.lock() {
+            stages.push(timing);
+        }
+    }
+}
+
+/// Stages finished so far, in completion order
+pub fn timings() -> Vec<StageTiming> {
+    STAGES.lock().map(|s| s.clone()).unwrap_or_default()
+}
+
+/// Peak resident set size of this process (Linux `VmHWM`)
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_status_kb(&status, "VmHWM:").map(|kb| kb * 1024)
+}
+
+/// A `<field> <n> kB` value from `/proc/<pid>/status`
+fn parse_status_kb(status: &str, field: &str) -> Option<u64> {
+    let line = status.lines().find(|l| l.starts_with(field))?;
+    line[field.len()..].split_whitespace().next()?.parse().ok()
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+    let b = bytes as f64;
+    if b >= GB {
+        format!("{:.1} GB", b / GB)
+    } else {
+        format!("{:.1} MB", b / MB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_recorded() {
+        stage("perf-test-stage").finish();
+        let recorded = timings();
+        let timing = recorded.iter().find(|t| t.name == "perf-test-stage").unwrap();
+        assert!(timing.wall_time_secs >= 0.0);
+        if cfg!(target_os = "linux") {
+            assert!(timing.peak_rss_bytes.unwrap() > 0);
+        }
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let status = "Name:\tsparc\nVmPeak:\t  20000 kB\nVmHWM:\t    1234 kB\n";
+        assert_eq!(parse_status_kb(status, "VmHWM:"), Some(1234));
+        assert_eq!(parse_status_kb(status, "VmSwap:"), None);
+        assert_eq!(format_bytes(3 * 1024 * 1024 / 2), "1.5 MB");
+    }
+}
@@ -0,0 +1,104 @@
+//! Protocol defined by an arbitrary read structure
+
+use super::{Protocol, ReadComponents};
+use crate::{Error, ReadStructure, Result};
+
+/// Protocol for chemistries without a built-in implementation
+///
+/// Barcode and UMI are taken from the positions in the read structure. When
+/// the structure places cDNA on R1 (e.g. `16C12U+T`), the rest of the read
+/// from that offset is returned as cDNA.
+pub struct Custom {
+    read_structure: ReadStructure,
+}
+
+impl Custom {
+    pub fn new(read_structure: ReadStructure) -> Self {
+        Self { read_structure }
+    }
+
+    /// Create from a read structure spec such as `16C12U`
+    pub fn parse(spec: &str) -> Result<Self> {
+        Ok(Self::new(ReadStructure::parse(spec)?))
+    }
+}
+
+impl Protocol for Custom {
+    fn read_structure(&self) -> &ReadStructure {
+        &self.read_structure
+    }
+
+    fn extract_r1(&self, seq: &[u8], qual: &[u8]) -> Result<ReadComponents> {
+        let rs = &self.read_structure;
+        let min_len = rs.min_len().max(rs.cdna_start);
+
+        if seq.len() < min_len {
+            return Err(Error::Protocol(format!(
+                "R1 too short: {} < {} required",
+                seq.len(),
+                min_len
+            )));
+        }
+        if qual.len() != seq.len() {
+            return Err(Error::Protocol(format!(
+                "R1 quality length {} does not match sequence length {}",
+                qual.len(),
+                seq.len()
+            )));
+        }
+
+        let barcode_end = rs.barcode_start + rs.barcode_len;
+        let umi_end = rs.umi_start + rs.umi_len;
+        // A structure with no barcode or UMI (`+T`) is all cDNA
+        let cdna_start = if rs.cdna_start > 0 || rs.min_len() == 0 {
+            rs.cdna_start
+        } else {
+            seq.len()
+        };
+
+        Ok(ReadComponents {
+            barcode: seq[rs.barcode_start..barcode_end].to_vec(),
+            umi: seq[rs.umi_start..umi_end].to_vec(),
+            cdna: seq[cdna_start..].to_vec(),
+            barcode_qual: qual[rs.barcode_start..barcode_end].to_vec(),
+            umi_qual: qual[rs.umi_start..umi_end].to_vec(),
+            cdna_qual: qual[cdna_start..].to_vec(),
+        })
+    }
+
+    fn name(&self) -> &str {
+        "Custom"
+    }
+
+    fn version(&self) -> &str {
+        "custom"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_with_cdna_on_r1() {
+        let protocol = Custom::parse("4X6C4U+T").unwrap();
+        let seq = b"NNNNAAAAAAGGGGCCCCCCCC";
+        let qual = b"IIIIIIIIIIIIIIIIIIIIII";
+
+        let components = protocol.extract_r1(seq, qual).unwrap();
+        assert_eq!(components.barcode_str(), "AAAAAA");
+        assert_eq!(components.umi_str(), "GGGG");
+        assert_eq!(components.cdna, b"CCCCCCCC");
+        assert_eq!(components.cdna_qual.len(), 8);
+    }
+
+    #[test]
+    fn test_custom_without_cdna() {
+        let protocol = Custom::parse("6C4U").unwrap();
+        let components = protocol.extract_r1(b"AAAAAAGGGGTT", b"IIIIIIIIIIII").unwrap();
+        assert_eq!(components.umi_str(), "GGGG");
+        assert!(components.cdna.is_empty());
+
+        assert!(protocol.extract_r1(b"AAAAAA", b"IIIIII").is_err());
+    }
+}
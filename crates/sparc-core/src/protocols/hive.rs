@@ -0,0 +1,96 @@
+//! Honeycomb HIVE protocol implementation
+
+use super::{Protocol, ReadComponents};
+use crate::{Error, ReadStructure, Result};
+
+/// Honeycomb Biotechnologies HIVE scRNA-seq (picowell arrays)
+///
+/// Read structure:
+/// - R1: Barcode (14bp) + UMI (8bp) + polyT
+/// - R2: cDNA
+///
+/// Bead barcodes are not drawn from a fixed list, so a whitelist is optional:
+/// without one barcodes are kept as sequenced and cells are called at
+/// counting.
+pub struct Hive {
+    read_structure: ReadStructure,
+}
+
+impl Hive {
+    pub fn v1() -> Self {
+        Self {
+            read_structure: ReadStructure::new(0, 14, 14, 8, 0),
+        }
+    }
+
+    pub fn custom(read_structure: ReadStructure) -> Self {
+        Self { read_structure }
+    }
+}
+
+impl Default for Hive {
+    fn default() -> Self {
+        Self::v1()
+    }
+}
+
+impl Protocol for Hive {
+    fn read_structure(&self) -> &ReadStructure {
+        &self.read_structure
+    }
+
+    fn extract_r1(&self, seq: &[u8], qual: &[u8]) -> Result<ReadComponents> {
+        let rs = &self.read_structure;
+        let barcode_end = rs.barcode_start + rs.barcode_len;
+        let umi_end = rs.umi_start + rs.umi_len;
+        let min_len = barcode_end.max(umi_end);
+
+        if seq.len() < min_len {
+            return Err(Error::Protocol(format!(
+                "R1 too short: {} < {} required",
+                seq.len(),
+                min_len
+            )));
+        }
+
+        Ok(ReadComponents {
+            barcode: seq[rs.barcode_start..barcode_end].to_vec(),
+            umi: seq[rs.umi_start..umi_end].to_vec(),
+            cdna: Vec::new(),
+            barcode_qual: qual[rs.barcode_start..barcode_end].to_vec(),
+            umi_qual: qual[rs.umi_start..umi_end].to_vec(),
+            cdna_qual: Vec::new(),
+        })
+    }
+
+    fn requires_whitelist(&self) -> bool {
+        false
+    }
+
+    fn name(&self) -> &str {
+        "HIVE"
+    }
+
+    fn version(&self) -> &str {
+        "v1"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hive_extraction() {
+        let protocol = Hive::v1();
+        // 14bp barcode + 8bp UMI + polyT
+        let seq = b"AAAAGGGGCCCCTTACGTACGTTTTTTTTT";
+        let qual = vec![b'I'; seq.len()];
+
+        let components = protocol.extract_r1(seq, &qual).unwrap();
+        assert_eq!(components.barcode_str(), "AAAAGGGGCCCCTT");
+        assert_eq!(components.umi_str(), "ACGTACGT");
+        assert!(!protocol.requires_whitelist());
+        assert!(protocol.extract_r1(&seq[..20], &qual[..20]).is_err());
+    }
+}
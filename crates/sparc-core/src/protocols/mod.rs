@@ -1,20 +1,60 @@
 //! Protocol implementations for various single-cell sequencing kits
 
+mod custom;
 mod dropseq;
+mod hive;
 mod indrop;
+mod pipseq;
 mod scirna;
 mod smartseq;
 mod tenx_3prime;
 mod tenx_5prime;
 
+pub use custom::Custom;
 pub use dropseq::DropSeq;
+pub use hive::Hive;
 pub use indrop::InDrop;
+pub use pipseq::PipSeq;
 pub use scirna::SciRNA;
 pub use smartseq::SmartSeq2;
 pub use tenx_3prime::TenX3Prime;
 pub use tenx_5prime::TenX5Prime;
 
-use crate::{ReadStructure, Result};
+use crate::{Error, ReadStructure, Result};
+
+/// Names accepted by [`from_name`]
+pub const PROTOCOL_NAMES: [&str; 9] = [
+    "10x-3prime-v3",
+    "10x-3prime-v2",
+    "10x-5prime-v2",
+    "drop-seq",
+    "hive-v1",
+    "indrop",
+    "pipseq-v3",
+    "sci-rna-seq",
+    "smart-seq2",
+];
+
+/// Look up a built-in protocol by its CLI name (e.g. `10x-3prime-v3`).
+/// `pipseq-t2` and `pipseq-t20` name the PIPseq kits sharing the V3 layout.
+pub fn from_name(name: &str) -> Result<Box<dyn Protocol>> {
+    match name {
+        "10x-3prime-v3" => Ok(Box::new(TenX3Prime::v3())),
+        "10x-3prime-v2" => Ok(Box::new(TenX3Prime::v2())),
+        "10x-5prime-v2" => Ok(Box::new(TenX5Prime::v2())),
+        "drop-seq" => Ok(Box::new(DropSeq::new())),
+        "hive-v1" => Ok(Box::new(Hive::v1())),
+        "indrop" => Ok(Box::new(InDrop::new())),
+        "pipseq-v3" | "pipseq-t2" | "pipseq-t20" => Ok(Box::new(PipSeq::v3())),
+        "sci-rna-seq" => Ok(Box::new(SciRNA::new())),
+        "smart-seq2" => Ok(Box::new(SmartSeq2::new("sample".to_string()))),
+        _ => Err(Error::Protocol(format!(
+            "Unknown protocol: {} (expected one of: {})",
+            name,
+            PROTOCOL_NAMES.join(", ")
+        ))),
+    }
+}
 
 /// Extracted read components
 #[derive(Debug, Clone)]
@@ -73,6 +113,18 @@ pub trait Protocol: Send + Sync {
     /// Extract components from R1 read
     fn extract_r1(&self, seq: &[u8], qual: &[u8]) -> Result<ReadComponents>;
 
+    /// Lengths of the barcode's tiers when each is drawn from its own
+    /// whitelist and corrected separately (PIPseq); `None` for one whitelist
+    fn barcode_tiers(&self) -> Option<&[usize]> {
+        None
+    }
+
+    /// Whether barcodes come from a fixed list that `--whitelist` must supply;
+    /// when false and no whitelist is given, barcodes are kept as sequenced
+    fn requires_whitelist(&self) -> bool {
+        true
+    }
+
     /// Protocol name
     fn name(&self) -> &str;
 
@@ -0,0 +1,133 @@
+//! Fluent PIPseq protocol implementation
+
+use super::{Protocol, ReadComponents};
+use crate::{Error, ReadStructure, Result};
+
+/// Linker mismatches tolerated before a read is rejected
+const MAX_LINKER_MISMATCHES: usize = 1;
+
+/// (start, length) of the V3 barcode tiers in R1
+const V3_TIERS: [(usize, usize); 4] = [(0, 8), (11, 6), (20, 6), (31, 8)];
+
+/// (start, sequence) of the V3 linkers in R1
+const V3_LINKERS: [(usize, &[u8]); 3] = [(8, b"ATG"), (17, b"GAG"), (26, b"TCGAG")];
+
+/// Fluent BioSciences PIPseq V3 (T2 and T20 kits)
+///
+/// Read structure:
+/// - R1: BC1 (8bp) + `ATG` + BC2 (6bp) + `GAG` + BC3 (6bp) + `TCGAG` +
+///   BC4 (8bp) + UMI (12bp) + polyT
+/// - R2: cDNA
+/// - Combined barcode = BC1 + BC2 + BC3 + BC4 (28bp), corrected one tier at
+///   a time against per-tier whitelists (see `barcode::TieredCorrector`)
+pub struct PipSeq {
+    read_structure: ReadStructure,
+    /// (start, length) of each barcode tier in R1
+    tiers: &'static [(usize, usize)],
+    /// (start, sequence) of each linker in R1
+    linkers: &'static [(usize, &'static [u8])],
+    tier_lens: Vec<usize>,
+}
+
+impl PipSeq {
+    pub fn v3() -> Self {
+        let tier_lens: Vec<usize> = V3_TIERS.iter().map(|&(_, len)| len).collect();
+        Self {
+            read_structure: ReadStructure::new(0, tier_lens.iter().sum(), 39, 12, 0),
+            tiers: &V3_TIERS,
+            linkers: &V3_LINKERS,
+            tier_lens,
+        }
+    }
+
+    /// Whether every linker matches within [`MAX_LINKER_MISMATCHES`]
+    fn linkers_ok(&self, seq: &[u8]) -> bool {
+        self.linkers.iter().all(|&(start, linker)| {
+            let observed = &seq[start..start + linker.len()];
+            let mismatches = observed.iter().zip(linker).filter(|(a, b)| a != b).count();
+            mismatches <= MAX_LINKER_MISMATCHES
+        })
+    }
+}
+
+impl Default for PipSeq {
+    fn default() -> Self {
+        Self::v3()
+    }
+}
+
+impl Protocol for PipSeq {
+    fn read_structure(&self) -> &ReadStructure {
+        &self.read_structure
+    }
+
+    fn extract_r1(&self, seq: &[u8], qual: &[u8]) -> Result<ReadComponents> {
+        let rs = &self.read_structure;
+        let min_len = rs.umi_start + rs.umi_len;
+
+        if seq.len() < min_len {
+            return Err(Error::Protocol(format!(
+                "R1 too short: {} < {} required",
+                seq.len(),
+                min_len
+            )));
+        }
+        if !self.linkers_ok(seq) {
+            return Err(Error::Protocol("PIPseq linker not found in R1".to_string()));
+        }
+
+        let mut barcode = Vec::with_capacity(rs.barcode_len);
+        let mut barcode_qual = Vec::with_capacity(rs.barcode_len);
+        for &(start, len) in self.tiers {
+            barcode.extend_from_slice(&seq[start..start + len]);
+            barcode_qual.extend_from_slice(&qual[start..start + len]);
+        }
+        let umi_end = rs.umi_start + rs.umi_len;
+
+        Ok(ReadComponents {
+            barcode,
+            umi: seq[rs.umi_start..umi_end].to_vec(),
+            cdna: Vec::new(),
+            barcode_qual,
+            umi_qual: qual[rs.umi_start..umi_end].to_vec(),
+            cdna_qual: Vec::new(),
+        })
+    }
+
+    fn barcode_tiers(&self) -> Option<&[usize]> {
+        Some(&self.tier_lens)
+    }
+
+    fn name(&self) -> &str {
+        "PIPseq"
+    }
+
+    fn version(&self) -> &str {
+        "v3"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipseq_extraction() {
+        let protocol = PipSeq::v3();
+        let seq = b"AAAACCCCATGGGGTTTGAGACGTACTCGAGTTTTGGGGCCCCAAAATTTTTTTTTTT";
+        let qual = vec![b'I'; seq.len()];
+
+        let components = protocol.extract_r1(seq, &qual).unwrap();
+        assert_eq!(components.barcode_str(), "AAAACCCCGGGTTTACGTACTTTTGGGG");
+        assert_eq!(components.umi_str(), "CCCCAAAATTTT");
+        assert_eq!(protocol.barcode_tiers(), Some(&[8, 6, 6, 8][..]));
+
+        // One linker error is tolerated, two are not
+        let mut one_off = seq.to_vec();
+        one_off[8] = b'C';
+        assert!(protocol.extract_r1(&one_off, &qual).is_ok());
+        one_off[9] = b'C';
+        assert!(protocol.extract_r1(&one_off, &qual).is_err());
+        assert!(protocol.extract_r1(&seq[..50], &qual[..50]).is_err());
+    }
+}
@@ -0,0 +1,508 @@
+//! Alignment-free gene assignment by k-mer pseudoalignment
+//!
+//! A [`KmerIndex`] maps every canonical k-mer of a transcriptome to the set of
+//! genes containing it (an equivalence class). A read is assigned to the genes
+//! shared by all of its indexed k-mers, as in kallisto; k-mers missing from the
+//! index are ignored. Only gene-level assignments are made, which is what the
+//! count matrix needs.
+
+use crate::atomic::AtomicFile;
+use crate::{Error, Result};
+use ahash::AHashMap;
+use needletail::parse_fastx_file;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// Default k-mer length (kallisto's default)
+pub const DEFAULT_K: usize = 31;
+
+/// Longest k-mer that fits a 2-bit encoded `u64`
+pub const MAX_K: usize = 31;
+
+const MAGIC: &[u8; 8] = b"SPARCKI1";
+
+/// 2-bit base code, `None` for anything but A/C/G/T
+fn encode_base(base: u8) -> Option<u64> {
+    match base {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' | b'U' | b'u' => Some(3),
+        _ => None,
+    }
+}
+
+/// Canonical (strand-independent) k-mers of `seq`, skipping any with an N
+fn canonical_kmers(seq: &[u8], k: usize) -> impl Iterator<Item = u64> + '_ {
+    let mask = (1u64 << (2 * k)) - 1;
+    let shift = 2 * (k - 1);
+    let (mut fwd, mut rev, mut len) = (0u64, 0u64, 0usize);
+    seq.iter().filter_map(move |&base| match encode_base(base) {
+        Some(code) => {
+            fwd = ((fwd << 2) | code) & mask;
+            rev = (rev >> 2) | ((3 - code) << shift);
+            len += 1;
+            (len >= k).then(|| fwd.min(rev))
+        }
+        None => {
+            len = 0;
+            None
+        }
+    })
+}
+
+/// Result of pseudoaligning one read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PseudoHit {
+    /// All indexed k-mers agree on one gene
+    Unique(usize),
+    /// The read is compatible with several genes
+    MultiGene,
+    /// No indexed k-mers, or k-mers with no gene in common
+    Unmapped,
+}
+
+/// Transcriptome k-mer index
+#[derive(Debug, Clone)]
+pub struct KmerIndex {
+    k: usize,
+    gene_ids: Vec<String>,
+    gene_names: Vec<String>,
+    /// Equivalence classes: sorted gene indices
+    classes: Vec<Vec<u32>>,
+    /// Canonical k-mer -> equivalence class
+    kmers: AHashMap<u64, u32>,
+}
+
+impl KmerIndex {
+    /// Build an index from a transcriptome FASTA (plain or gzipped).
+    ///
+    /// Transcripts are mapped to genes through `t2g` (`transcript<TAB>gene_id
+    /// [<TAB>gene_name]`, as used by kallisto|bustools) when given, otherwise
+    /// from GENCODE (`|`-separated) or Ensembl (`gene:`/`gene_symbol:`) headers.
+    /// Transcripts with no known gene count as their own gene.
+    pub fn from_fasta<P: AsRef<Path>>(path: P, t2g: Option<&Path>, k: usize) -> Result<Self> {
+        let t2g = match t2g {
+            Some(t2g) => Some(read_t2g(t2g)?),
+            None => None,
+        };
+        let mut reader = parse_fastx_file(path.as_ref()).map_err(|e| {
+            Error::Annotation(format!("Failed to open {:?}: {}", path.as_ref(), e))
+        })?;
+
+        let mut builder = KmerIndexBuilder::new(k)?;
+        let mut orphans = 0usize;
+        while let Some(record) = reader.next() {
+            let record = record.map_err(|e| {
+                Error::Annotation(format!("Invalid FASTA {:?}: {}", path.as_ref(), e))
+            })?;
+            let header = String::from_utf8_lossy(record.id()).to_string();
+            let transcript = header.split_whitespace().next().unwrap_or("");
+            let transcript = transcript.split('|').next().unwrap_or("");
+            let gene = match &t2g {
+                Some(t2g) => t2g
+                    .get(transcript)
+                    .or_else(|| t2g.get(strip_version(transcript)))
+                    .cloned(),
+                None => gene_from_header(&header),
+            };
+            let (gene_id, gene_name) = gene.unwrap_or_else(|| {
+                orphans += 1;
+                (transcript.to_string(), transcript.to_string())
+            });
+            builder.add(&gene_id, &gene_name, &record.seq());
+        }
+        if orphans > 0 {
+            log::warn!("{} transcripts have no gene and are indexed as their own gene", orphans);
+        }
+        Ok(builder.finish())
+    }
+
+    /// K-mer length
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Number of genes
+    pub fn n_genes(&self) -> usize {
+        self.gene_ids.len()
+    }
+
+    /// Number of distinct k-mers
+    pub fn n_kmers(&self) -> usize {
+        self.kmers.len()
+    }
+
+    /// Number of equivalence classes
+    pub fn n_classes(&self) -> usize {
+        self.classes.len()
+    }
+
+    /// Gene ID of gene `i`
+    pub fn gene_id(&self, i: usize) -> &str {
+        &self.gene_ids[i]
+    }
+
+    /// Gene name (symbol) of gene `i`
+    pub fn gene_name(&self, i: usize) -> &str {
+        &self.gene_names[i]
+    }
+
+    /// Pseudoalign a read sequence
+    pub fn classify(&self, seq: &[u8]) -> PseudoHit {
+        match self.compatible_genes(seq).as_deref() {
+            None | Some([]) => PseudoHit::Unmapped,
+            Some([gene]) => PseudoHit::Unique(*gene as usize),
+            Some(_) => PseudoHit::MultiGene,
+        }
+    }
+
+    /// Sorted indices of the genes compatible with every indexed k-mer of the
+    /// read: `None` if no k-mer is indexed, empty if they share no gene
+    pub fn compatible_genes(&self, seq: &[u8]) -> Option<Vec<u32>> {
+        let mut genes: Option<Vec<u32>> = None;
+        let mut last_class = u32::MAX;
+        for kmer in canonical_kmers(seq, self.k) {
+            let Some(&class) = self.kmers.get(&kmer) else {
+                continue;
+            };
+            if class == last_class {
+                continue;
+            }
+            last_class = class;
+            let class_genes = &self.classes[class as usize];
+            if let Some(genes) = genes.as_mut() {
+                genes.retain(|g| class_genes.binary_search(g).is_ok());
+                if genes.is_empty() {
+                    return Some(Vec::new());
+                }
+            } else {
+                genes = Some(class_genes.clone());
+            }
+        }
+        genes
+    }
+
+    /// Write the index in SPARC's binary format. K-mers are sorted so the
+    /// same transcriptome always produces the same file.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = AtomicFile::create(path)?;
+        writer.write_all(MAGIC)?;
+        write_u32(&mut writer, self.k as u32)?;
+        write_u32(&mut writer, self.gene_ids.len() as u32)?;
+        for (id, name) in self.gene_ids.iter().zip(&self.gene_names) {
+            write_str(&mut writer, id)?;
+            write_str(&mut writer, name)?;
+        }
+        write_u32(&mut writer, self.classes.len() as u32)?;
+        for class in &self.classes {
+            write_u32(&mut writer, class.len() as u32)?;
+            for &gene in class {
+                write_u32(&mut writer, gene)?;
+            }
+        }
+        let mut kmers: Vec<(u64, u32)> = self.kmers.iter().map(|(&k, &c)| (k, c)).collect();
+        kmers.sort_unstable();
+        writer.write_all(&(kmers.len() as u64).to_le_bytes())?;
+        for (kmer, class) in kmers {
+            writer.write_all(&kmer.to_le_bytes())?;
+            write_u32(&mut writer, class)?;
+        }
+        writer.commit()
+    }
+
+    /// Load an index written by [`KmerIndex::write`]
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut reader = BufReader::new(File::open(path)?);
+        let invalid =
+            |what: &str| Error::Annotation(format!("Invalid k-mer index {:?}: {}", path, what));
+
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a SPARC k-mer index"));
+        }
+        let k = read_u32(&mut reader)? as usize;
+        if !(1..=MAX_K).contains(&k) {
+            return Err(invalid("bad k-mer length"));
+        }
+        let n_genes = read_u32(&mut reader)? as usize;
+        let mut gene_ids = Vec::with_capacity(n_genes);
+        let mut gene_names = Vec::with_capacity(n_genes);
+        for _ in 0..n_genes {
+            gene_ids.push(read_str(&mut reader).map_err(|_| invalid("bad gene ID"))?);
+            gene_names.push(read_str(&mut reader).map_err(|_| invalid("bad gene name"))?);
+        }
+        let n_classes = read_u32(&mut reader)? as usize;
+        let mut classes = Vec::with_capacity(n_classes);
+        for _ in 0..n_classes {
+            let len = read_u32(&mut reader)? as usize;
+            let class = (0..len).map(|_| read_u32(&mut reader)).collect::<Result<Vec<_>>>()?;
+            if class.iter().any(|&g| g as usize >= n_genes) {
+                return Err(invalid("gene index out of range"));
+            }
+            classes.push(class);
+        }
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf)?;
+        let n_kmers = u64::from_le_bytes(buf) as usize;
+        let mut kmers = AHashMap::with_capacity(n_kmers);
+        for _ in 0..n_kmers {
+            reader.read_exact(&mut buf)?;
+            let class = read_u32(&mut reader)?;
+            if class as usize >= n_classes {
+                return Err(invalid("class index out of range"));
+            }
+            kmers.insert(u64::from_le_bytes(buf), class);
+        }
+        log::info!("Loaded k-mer index {:?}: {} genes, {} k-mers", path, n_genes, n_kmers);
+        Ok(Self {
+            k,
+            gene_ids,
+            gene_names,
+            classes,
+            kmers,
+        })
+    }
+}
+
+/// Incrementally builds a [`KmerIndex`] from transcript sequences
+pub struct KmerIndexBuilder {
+    index: KmerIndex,
+    genes: AHashMap<String, u32>,
+    class_ids: AHashMap<Vec<u32>, u32>,
+}
+
+impl KmerIndexBuilder {
+    /// Start an empty index with k-mer length `k` (1 to [`MAX_K`])
+    pub fn new(k: usize) -> Result<Self> {
+        if !(1..=MAX_K).contains(&k) {
+            return Err(Error::Config(format!("k-mer length must be 1-{}, got {}", MAX_K, k)));
+        }
+        Ok(Self {
+            index: KmerIndex {
+                k,
+                gene_ids: Vec::new(),
+                gene_names: Vec::new(),
+                classes: Vec::new(),
+                kmers: AHashMap::new(),
+            },
+            genes: AHashMap::new(),
+            class_ids: AHashMap::new(),
+        })
+    }
+
+    /// Add the k-mers of one transcript of `gene_id`
+    pub fn add(&mut self, gene_id: &str, gene_name: &str, seq: &[u8]) {
+        let index = &mut self.index;
+        let gene = match self.genes.get(gene_id) {
+            Some(&gene) => gene,
+            None => {
+                let gene = index.gene_ids.len() as u32;
+                index.gene_ids.push(gene_id.to_string());
+                index.gene_names.push(gene_name.to_string());
+                self.genes.insert(gene_id.to_string(), gene);
+                gene
+            }
+        };
+        for kmer in canonical_kmers(seq, index.k) {
+            let genes = match index.kmers.get(&kmer) {
+                Some(&class) if index.classes[class as usize].contains(&gene) => continue,
+                Some(&class) => {
+                    let mut genes = index.classes[class as usize].clone();
+                    let pos = genes.partition_point(|&g| g < gene);
+                    genes.insert(pos, gene);
+                    genes
+                }
+                None => vec![gene],
+            };
+            let class = match self.class_ids.get(&genes) {
+                Some(&class) => class,
+                None => {
+                    let class = index.classes.len() as u32;
+                    index.classes.push(genes.clone());
+                    self.class_ids.insert(genes, class);
+                    class
+                }
+            };
+            index.kmers.insert(kmer, class);
+        }
+    }
+
+    /// Finish building
+    pub fn finish(self) -> KmerIndex {
+        log::info!(
+            "Built k-mer index: {} genes, {} k-mers, {} equivalence classes",
+            self.index.n_genes(),
+            self.index.n_kmers(),
+            self.index.n_classes()
+        );
+        self.index
+    }
+}
+
+/// Transcript ID without a trailing `.N` version
+fn strip_version(transcript: &str) -> &str {
+    transcript.split_once('.').map_or(transcript, |(id, _)| id)
+}
+
+/// Gene ID and name from a GENCODE or Ensembl transcriptome FASTA header
+fn gene_from_header(header: &str) -> Option<(String, String)> {
+    let id = header.split_whitespace().next()?;
+    if id.contains('|') {
+        // GENCODE: transcript|gene|havana_gene|havana_transcript|tx_name|gene_name|len|biotype|
+        let fields: Vec<&str> = id.split('|').collect();
+        let gene_id = fields.get(1).filter(|g| !g.is_empty())?;
+        let gene_name = fields.get(5).filter(|n| !n.is_empty()).unwrap_or(gene_id);
+        return Some((gene_id.to_string(), gene_name.to_string()));
+    }
+    // Ensembl: >ENST... cdna chromosome:... gene:ENSG... gene_symbol:NAME ...
+    let field = |key: &str| header.split_whitespace().find_map(|f| f.strip_prefix(key));
+    let gene_id = field("gene:")?;
+    let gene_name = field("gene_symbol:").unwrap_or(gene_id);
+    Some((gene_id.to_string(), gene_name.to_string()))
+}
+
+/// Transcript -> (gene ID, gene name) from a t2g TSV
+fn read_t2g(path: &Path) -> Result<AHashMap<String, (String, String)>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut t2g = AHashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 2 {
+            return Err(Error::Annotation(format!(
+                "{:?} line {}: expected transcript<TAB>gene[<TAB>name]",
+                path,
+                i + 1
+            )));
+        }
+        let name = fields.get(2).filter(|n| !n.is_empty()).unwrap_or(&fields[1]);
+        t2g.insert(fields[0].to_string(), (fields[1].to_string(), name.to_string()));
+    }
+    Ok(t2g)
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_str<W: Write>(writer: &mut W, value: &str) -> Result<()> {
+    write_u32(writer, value.len() as u32)?;
+    writer.write_all(value.as_bytes())?;
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_str<R: Read>(reader: &mut R) -> Result<String> {
+    let len = read_u32(reader)? as usize;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| Error::Annotation(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENE_A: &[u8] = b"ACGTTGCATGCCATGGACTTGACCAGTGATCCAGGTTACAGG";
+    const GENE_B: &[u8] = b"TTGACCCGGATAGGCTAACGTTAGCAGGCTTACGGATCATGC";
+
+    fn revcomp(seq: &[u8]) -> Vec<u8> {
+        seq.iter()
+            .rev()
+            .map(|&b| match b {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect()
+    }
+
+    fn index() -> KmerIndex {
+        let mut builder = KmerIndexBuilder::new(11).unwrap();
+        builder.add("G1", "Alpha", GENE_A);
+        builder.add("G2", "Beta", GENE_B);
+        // A second isoform of G2 sharing the start of G1
+        let mut isoform = GENE_A[..20].to_vec();
+        isoform.extend_from_slice(&GENE_B[20..]);
+        builder.add("G2", "Beta", &isoform);
+        builder.finish()
+    }
+
+    #[test]
+    fn test_canonical_kmers() {
+        let fwd: Vec<u64> = canonical_kmers(b"ACGTTGCA", 5).collect();
+        let mut rev: Vec<u64> = canonical_kmers(&revcomp(b"ACGTTGCA"), 5).collect();
+        rev.reverse();
+        assert_eq!(fwd, rev);
+        assert_eq!(canonical_kmers(b"ACGTNACGTA", 4).count(), 3);
+    }
+
+    #[test]
+    fn test_classify() {
+        let index = index();
+        assert_eq!(index.n_genes(), 2);
+        assert_eq!(index.classify(&GENE_A[22..]), PseudoHit::Unique(0));
+        assert_eq!(index.classify(&revcomp(&GENE_B[5..35])), PseudoHit::Unique(1));
+        // The shared prefix is compatible with both genes
+        assert_eq!(index.classify(&GENE_A[..18]), PseudoHit::MultiGene);
+        assert_eq!(index.classify(b"NNNNNNNNNNNNNNNN"), PseudoHit::Unmapped);
+        // Unique k-mers of different genes conflict
+        let mut chimera = GENE_A[25..].to_vec();
+        chimera.extend_from_slice(&GENE_B[..15]);
+        assert_eq!(index.classify(&chimera), PseudoHit::Unmapped);
+    }
+
+    #[test]
+    fn test_index_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.kidx");
+        let index = index();
+        index.write(&path).unwrap();
+        let read = KmerIndex::read(&path).unwrap();
+        assert_eq!((read.k(), read.n_kmers()), (11, index.n_kmers()));
+        assert_eq!(read.gene_name(1), "Beta");
+        assert_eq!(read.classify(&GENE_A[22..]), PseudoHit::Unique(0));
+
+        std::fs::write(&path, b"not an index").unwrap();
+        assert!(KmerIndex::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_from_fasta_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = dir.path().join("tx.fa");
+        let mut text = String::new();
+        text.push_str(">ENST01.1|ENSG01.1|-|-|ALPHA-201|ALPHA|42|protein_coding|\n");
+        text.push_str(std::str::from_utf8(GENE_A).unwrap());
+        text.push_str("\n>ENST02.1 cdna chromosome:GRCh38 gene:ENSG02.1 gene_symbol:BETA\n");
+        text.push_str(std::str::from_utf8(GENE_B).unwrap());
+        text.push_str("\n>ENST03\nACGTACGTACGTACGT\n");
+        std::fs::write(&fasta, text).unwrap();
+
+        let index = KmerIndex::from_fasta(&fasta, None, 15).unwrap();
+        assert_eq!(index.n_genes(), 3);
+        assert_eq!((index.gene_id(0), index.gene_name(0)), ("ENSG01.1", "ALPHA"));
+        assert_eq!((index.gene_id(1), index.gene_name(1)), ("ENSG02.1", "BETA"));
+        assert_eq!(index.gene_id(2), "ENST03");
+
+        let t2g = dir.path().join("t2g.tsv");
+        std::fs::write(&t2g, "ENST01\tG1\tAlpha\nENST02.1\tG1\n").unwrap();
+        let index = KmerIndex::from_fasta(&fasta, Some(&t2g), 15).unwrap();
+        assert_eq!(index.n_genes(), 2);
+        assert_eq!(index.classify(&GENE_B[3..30]), PseudoHit::Unique(0));
+    }
+}
@@ -0,0 +1,335 @@
+//! scATAC-seq quality metrics
+//!
+//! Computed from fragments, per cell and over all cells:
+//!
+//! - TSS enrichment: Tn5 cut sites per base within `tss_core` of a
+//!   transcription start site, over cut sites per base in the flanks
+//!   `tss_flank` away (the ENCODE/ArchR definition)
+//! - fraction of fragments in peaks (FRiP), when peaks are given
+//! - fragment-size distribution, and the nucleosome signal: mononucleosomal
+//!   (147-294 bp) over nucleosome-free (< 147 bp) fragments, as in Signac
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::annotation::GeneAnnotation;
+use crate::atac::{Fragment, PeakSet};
+use crate::intervals::{Interval, IntervalIndex, Strand};
+use crate::Result;
+
+/// Fragments shorter than this are nucleosome-free
+pub const NUCLEOSOME_FREE_MAX: i64 = 147;
+/// Fragments from `NUCLEOSOME_FREE_MAX` up to this span one nucleosome
+pub const MONONUCLEOSOME_MAX: i64 = 294;
+
+/// ATAC QC settings
+#[derive(Debug, Clone)]
+pub struct AtacQcConfig {
+    /// Distance from the TSS to the outer edge of the background flanks
+    pub tss_flank: i64,
+    /// Half-width of the window around the TSS counted as signal
+    pub tss_core: i64,
+    /// Width of each background flank, ending `tss_flank` from the TSS
+    pub tss_edge: i64,
+    /// Longer fragments fall in the last fragment-size bin
+    pub max_fragment_len: usize,
+}
+
+impl Default for AtacQcConfig {
+    fn default() -> Self {
+        Self {
+            tss_flank: 2000,
+            tss_core: 50,
+            tss_edge: 100,
+            max_fragment_len: 1000,
+        }
+    }
+}
+
+/// ATAC QC metrics of one cell
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AtacCellMetrics {
+    pub barcode: String,
+    /// Unique fragments
+    pub fragments: u64,
+    /// Fragments overlapping a peak
+    pub fragments_in_peaks: u64,
+    /// Fraction of fragments in peaks
+    pub frip: f64,
+    /// TSS enrichment score
+    pub tss_enrichment: f64,
+    /// Fragments shorter than 147 bp
+    pub nucleosome_free: u64,
+    /// Fragments of 147-294 bp
+    pub mononucleosomal: u64,
+    /// Mononucleosomal over nucleosome-free fragments
+    pub nucleosome_signal: f64,
+}
+
+/// ATAC QC metrics over all cells
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AtacMetrics {
+    pub cells: u64,
+    /// Fragments from the cells
+    pub fragments_in_cells: u64,
+    pub median_fragments_per_cell: f64,
+    /// Fraction of cell fragments in peaks; 0 without peaks
+    pub frip: f64,
+    /// TSS enrichment of all cell fragments pooled
+    pub tss_enrichment: f64,
+    pub median_tss_enrichment: f64,
+    /// Nucleosome signal of all cell fragments pooled
+    pub nucleosome_signal: f64,
+    pub median_nucleosome_signal: f64,
+    /// Cell fragments of each length in bases; the last bin counts every
+    /// longer fragment too
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fragment_sizes: Vec<u64>,
+}
+
+/// Transcription start sites of every gene, as stranded 1 bp intervals
+pub fn tss_from_annotation(annotation: &GeneAnnotation) -> Vec<Interval> {
+    annotation
+        .genes()
+        .iter()
+        .map(|gene| {
+            let tss = match gene.strand {
+                Strand::Reverse => gene.end - 1,
+                _ => gene.start,
+            };
+            Interval {
+                strand: gene.strand,
+                ..Interval::new(&gene.chrom, tss, tss + 1)
+            }
+        })
+        .collect()
+}
+
+/// Running counts of one cell
+#[derive(Debug, Clone, Default)]
+struct CellCounts {
+    fragments: u64,
+    in_peaks: u64,
+    tss_core: u64,
+    tss_edge: u64,
+    nucleosome_free: u64,
+    mononucleosomal: u64,
+}
+
+/// Core and flank cut counts around TSSs
+struct TssWindows<'a> {
+    tss: &'a [Interval],
+    index: IntervalIndex,
+    config: &'a AtacQcConfig,
+    hits: Vec<usize>,
+}
+
+impl<'a> TssWindows<'a> {
+    fn new(tss: &'a [Interval], config: &'a AtacQcConfig) -> Self {
+        let flank = config.tss_flank;
+        let index = IntervalIndex::new(tss.iter().map(|t| {
+            let site = t.five_prime();
+            (t.chrom.as_str(), site - flank, site + flank + 1)
+        }));
+        Self {
+            tss,
+            index,
+            config,
+            hits: Vec::new(),
+        }
+    }
+
+    /// Add a cut site to the core and flank counts of every TSS near it
+    fn count(&mut self, chrom: &str, site: i64, counts: &mut CellCounts) {
+        self.hits.clear();
+        self.index.query(chrom, site, site + 1, &mut self.hits);
+        for &i in &self.hits {
+            // Windows are symmetric, so strand does not matter here
+            let offset = (site - self.tss[i].five_prime()).abs();
+            if offset <= self.config.tss_core {
+                counts.tss_core += 1;
+            } else if offset > self.config.tss_flank - self.config.tss_edge {
+                counts.tss_edge += 1;
+            }
+        }
+    }
+
+    /// Signal per base over background per base; the background is at least
+    /// one cut so sparse cells get a finite score
+    fn enrichment(&self, core: u64, edge: u64) -> f64 {
+        let core_width = (2 * self.config.tss_core + 1) as f64;
+        let edge_width = (2 * self.config.tss_edge.max(1)) as f64;
+        (core as f64 / core_width) / (edge.max(1) as f64 / edge_width)
+    }
+}
+
+fn ratio(num: u64, den: u64) -> f64 {
+    num as f64 / den.max(1) as f64
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[values.len() / 2]
+}
+
+/// ATAC QC metrics of every cell and over all cells.
+///
+/// With `cells`, only those barcodes are measured, in the given order;
+/// otherwise every barcode is, sorted. `tss` gives the transcription start
+/// sites (see [`tss_from_annotation`]); FRiP is 0 without `peaks`.
+pub fn atac_qc<I>(
+    fragments: I,
+    tss: &[Interval],
+    peaks: Option<&PeakSet>,
+    cells: Option<&[String]>,
+    config: &AtacQcConfig,
+) -> Result<(AtacMetrics, Vec<AtacCellMetrics>)>
+where
+    I: IntoIterator<Item = Result<Fragment>>,
+{
+    let mut columns: AHashMap<String, usize> = cells
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (b.clone(), i))
+        .collect();
+    let mut counts = vec![CellCounts::default(); columns.len()];
+    let mut sizes = vec![0u64; config.max_fragment_len + 1];
+    let mut windows = TssWindows::new(tss, config);
+    let mut hits = Vec::new();
+
+    for fragment in fragments {
+        let fragment = fragment?;
+        let col = match columns.get(&fragment.barcode) {
+            Some(&col) => col,
+            None if cells.is_none() => {
+                columns.insert(fragment.barcode.clone(), counts.len());
+                counts.push(CellCounts::default());
+                counts.len() - 1
+            }
+            None => continue,
+        };
+        let cell = &mut counts[col];
+        cell.fragments += 1;
+
+        let len = fragment.end - fragment.start;
+        sizes[(len.max(0) as usize).min(config.max_fragment_len)] += 1;
+        if len < NUCLEOSOME_FREE_MAX {
+            cell.nucleosome_free += 1;
+        } else if len <= MONONUCLEOSOME_MAX {
+            cell.mononucleosomal += 1;
+        }
+
+        if let Some(peaks) = peaks {
+            hits.clear();
+            peaks.overlapping(&fragment.chrom, fragment.start, fragment.end, &mut hits);
+            cell.in_peaks += !hits.is_empty() as u64;
+        }
+        for site in [fragment.start, fragment.end - 1] {
+            windows.count(&fragment.chrom, site, cell);
+        }
+    }
+
+    let barcodes: Vec<String> = match cells {
+        Some(cells) => cells.to_vec(),
+        None => {
+            let mut barcodes: Vec<String> = columns.keys().cloned().collect();
+            barcodes.sort_unstable();
+            barcodes
+        }
+    };
+    let per_cell: Vec<AtacCellMetrics> = barcodes
+        .into_iter()
+        .map(|barcode| {
+            let c = &counts[columns[&barcode]];
+            AtacCellMetrics {
+                fragments: c.fragments,
+                fragments_in_peaks: c.in_peaks,
+                frip: ratio(c.in_peaks, c.fragments),
+                tss_enrichment: windows.enrichment(c.tss_core, c.tss_edge),
+                nucleosome_free: c.nucleosome_free,
+                mononucleosomal: c.mononucleosomal,
+                nucleosome_signal: ratio(c.mononucleosomal, c.nucleosome_free),
+                barcode,
+            }
+        })
+        .collect();
+
+    let total = counts.iter().fold(CellCounts::default(), |mut total, c| {
+        total.fragments += c.fragments;
+        total.in_peaks += c.in_peaks;
+        total.tss_core += c.tss_core;
+        total.tss_edge += c.tss_edge;
+        total.nucleosome_free += c.nucleosome_free;
+        total.mononucleosomal += c.mononucleosomal;
+        total
+    });
+    let metrics = AtacMetrics {
+        cells: per_cell.len() as u64,
+        fragments_in_cells: total.fragments,
+        median_fragments_per_cell: median(per_cell.iter().map(|c| c.fragments as f64).collect()),
+        frip: ratio(total.in_peaks, total.fragments),
+        tss_enrichment: windows.enrichment(total.tss_core, total.tss_edge),
+        median_tss_enrichment: median(per_cell.iter().map(|c| c.tss_enrichment).collect()),
+        nucleosome_signal: ratio(total.mononucleosomal, total.nucleosome_free),
+        median_nucleosome_signal: median(per_cell.iter().map(|c| c.nucleosome_signal).collect()),
+        fragment_sizes: sizes,
+    };
+    Ok((metrics, per_cell))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(start: i64, end: i64, barcode: &str) -> Result<Fragment> {
+        Ok(Fragment {
+            chrom: "chr1".to_string(),
+            start,
+            end,
+            barcode: barcode.to_string(),
+            count: 1,
+        })
+    }
+
+    #[test]
+    fn test_atac_qc() {
+        let tss = vec![Interval {
+            strand: Strand::Reverse,
+            ..Interval::new("chr1", 10_000, 10_001)
+        }];
+        let peaks = PeakSet::from_peaks(vec![Interval::new("chr1", 9_900, 10_100)]);
+        let fragments = vec![
+            // AAA: both cut sites at the TSS, nucleosome-free
+            fragment(9_980, 10_021, "AAA"),
+            fragment(9_990, 10_011, "AAA"),
+            // AAA: one cut site in the background flank, mononucleosomal
+            fragment(11_950, 12_150, "AAA"),
+            // BBB: far from the TSS and the peak
+            fragment(50_000, 50_100, "BBB"),
+            fragment(60_000, 62_000, "BBB"),
+        ];
+        let config = AtacQcConfig::default();
+        let (metrics, cells) = atac_qc(fragments, &tss, Some(&peaks), None, &config).unwrap();
+
+        assert_eq!(cells.len(), 2);
+        let a = &cells[0];
+        assert_eq!(a.barcode, "AAA");
+        assert_eq!((a.fragments, a.fragments_in_peaks), (3, 2));
+        assert_eq!((a.nucleosome_free, a.mononucleosomal), (2, 1));
+        assert!((a.nucleosome_signal - 0.5).abs() < 1e-9);
+        // 4 core cuts over 101 bp against 1 flank cut over 200 bp
+        assert!((a.tss_enrichment - (4.0 / 101.0) / (1.0 / 200.0)).abs() < 1e-9);
+        assert_eq!(cells[1].tss_enrichment, 0.0);
+
+        assert_eq!(metrics.cells, 2);
+        assert_eq!(metrics.fragments_in_cells, 5);
+        assert!((metrics.frip - 0.4).abs() < 1e-9);
+        assert_eq!(metrics.fragment_sizes[41], 1);
+        assert_eq!(metrics.fragment_sizes[1000], 1);
+        assert_eq!(metrics.fragment_sizes.iter().sum::<u64>(), 5);
+    }
+}
@@ -0,0 +1,231 @@
+//! Barnyard (species-mixing) analysis for mixed-species references
+//!
+//! Genes of a combined reference (e.g. GRCh38+mm10) carry a species prefix
+//! such as `GRCh38_ACTB` or `mm10___Actb`. Each cell is called for the species
+//! holding at least `min_fraction` of its UMIs, or as a multiplet otherwise.
+//! Only doublets of two different species are visible, so the total multiplet
+//! rate is inferred from the observed one and the species proportions.
+
+use serde::{Deserialize, Serialize};
+
+use crate::count::CountMatrix;
+use crate::{Error, Result};
+
+/// Fraction of genes a prefix must cover to be detected as a species
+const MIN_PREFIX_GENE_FRACTION: f64 = 0.05;
+
+/// Barnyard analysis settings
+#[derive(Debug, Clone)]
+pub struct BarnyardConfig {
+    /// Gene name prefixes of the two species; detected from gene names when `None`
+    pub species: Option<[String; 2]>,
+    /// Minimum fraction of UMIs from one species for a singlet call
+    pub min_fraction: f64,
+    /// Cells with fewer UMIs are ignored
+    pub min_umis: u64,
+    /// Keep per-cell points for a species scatter plot
+    pub scatter: bool,
+}
+
+impl Default for BarnyardConfig {
+    fn default() -> Self {
+        Self {
+            species: None,
+            min_fraction: 0.9,
+            min_umis: 1,
+            scatter: false,
+        }
+    }
+}
+
+/// One cell in the species scatter plot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BarnyardPoint {
+    pub barcode: String,
+    /// UMIs from each species
+    pub umis: [u64; 2],
+    /// Species name, or "multiplet"
+    pub call: String,
+}
+
+/// Species-mixing metrics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BarnyardMetrics {
+    /// Species prefixes
+    pub species: [String; 2],
+    /// Singlet cells called for each species
+    pub singlets: [u64; 2],
+    /// Cells with UMIs from both species
+    pub multiplets: u64,
+    /// Fraction of cells called as multiplets
+    pub observed_multiplet_rate: f64,
+    /// Total multiplet rate, counting same-species multiplets
+    pub inferred_multiplet_rate: f64,
+    /// Mean fraction of other-species UMIs in each species' singlets
+    pub cross_contamination: [f64; 2],
+    /// Per-cell points for a scatter plot, when requested
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub points: Vec<BarnyardPoint>,
+}
+
+/// Species index of a gene, from its `<prefix>_` name
+fn gene_species(gene: &str, species: &[String; 2]) -> Option<usize> {
+    species.iter().position(|prefix| {
+        gene.strip_prefix(prefix.as_str())
+            .is_some_and(|rest| rest.starts_with('_'))
+    })
+}
+
+/// The two most common gene name prefixes (text before the first `_`), if
+/// both cover a meaningful share of genes
+pub fn detect_species(genes: &[String]) -> Option<[String; 2]> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for gene in genes {
+        let Some((prefix, _)) = gene.split_once('_') else {
+            continue;
+        };
+        if prefix.is_empty() {
+            continue;
+        }
+        match counts.iter_mut().find(|(p, _)| p == prefix) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((prefix.to_string(), 1)),
+        }
+    }
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let min_genes = (genes.len() as f64 * MIN_PREFIX_GENE_FRACTION).ceil() as usize;
+    match counts.as_slice() {
+        [(a, na), (b, nb), ..] if *na >= min_genes && *nb >= min_genes => {
+            Some([a.clone(), b.clone()])
+        }
+        _ => None,
+    }
+}
+
+impl BarnyardMetrics {
+    /// Compute species calls and mixing rates for every cell in `matrix`
+    pub fn from_matrix(matrix: &CountMatrix, config: &BarnyardConfig) -> Result<Self> {
+        let species = match &config.species {
+            Some(species) => species.clone(),
+            None => detect_species(&matrix.genes).ok_or_else(|| {
+                Error::Matrix(
+                    "Could not detect two species prefixes in gene names \
+                     (expected e.g. GRCh38_ACTB and mm10___Actb)"
+                        .into(),
+                )
+            })?,
+        };
+        let species_of: Vec<Option<usize>> =
+            matrix.genes.iter().map(|g| gene_species(g, &species)).collect();
+        for (s, prefix) in species.iter().enumerate() {
+            if !species_of.contains(&Some(s)) {
+                return Err(Error::Matrix(format!("No genes with prefix {}_", prefix)));
+            }
+        }
+
+        let mut umis = vec![[0u64; 2]; matrix.n_cols];
+        for ((&row, &col), &value) in matrix.rows.iter().zip(&matrix.cols).zip(&matrix.values) {
+            if let Some(s) = species_of[row] {
+                umis[col][s] += value as u64;
+            }
+        }
+
+        let mut singlets = [0u64; 2];
+        let mut contamination = [0f64; 2];
+        let mut multiplets = 0u64;
+        let mut points = Vec::new();
+        for (barcode, counts) in matrix.barcodes.iter().zip(&umis) {
+            let total = counts[0] + counts[1];
+            if total < config.min_umis.max(1) {
+                continue;
+            }
+            let call = (0..2).find(|&s| counts[s] as f64 / total as f64 >= config.min_fraction);
+            match call {
+                Some(s) => {
+                    singlets[s] += 1;
+                    contamination[s] += counts[1 - s] as f64 / total as f64;
+                }
+                None => multiplets += 1,
+            }
+            if config.scatter {
+                points.push(BarnyardPoint {
+                    barcode: barcode.clone(),
+                    umis: *counts,
+                    call: call.map_or("multiplet", |s| species[s].as_str()).to_string(),
+                });
+            }
+        }
+
+        let called = singlets[0] + singlets[1];
+        let cells = called + multiplets;
+        let observed = multiplets as f64 / cells.max(1) as f64;
+        // A random doublet mixes species with probability 2p(1 - p)
+        let p = singlets[0] as f64 / called.max(1) as f64;
+        let mixing = 2.0 * p * (1.0 - p);
+        let inferred = if mixing > 0.0 { (observed / mixing).min(1.0) } else { observed };
+
+        Ok(Self {
+            cross_contamination: [0, 1].map(|s| contamination[s] / singlets[s].max(1) as f64),
+            species,
+            singlets,
+            multiplets,
+            observed_multiplet_rate: observed,
+            inferred_multiplet_rate: inferred,
+            points,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix() -> CountMatrix {
+        let genes = ["GRCh38_ACTB", "GRCh38_GAPDH", "mm10___Actb", "mm10___Gapdh", "ERCC_1"];
+        let barcodes = ["H1", "H2", "H3", "M1", "M2", "X1", "E1"];
+        // rows = genes, columns = cells
+        let data = vec![
+            vec![90, 50, 30, 1, 0, 40, 0],
+            vec![10, 49, 30, 0, 1, 10, 0],
+            vec![0, 1, 0, 60, 70, 30, 0],
+            vec![0, 0, 0, 39, 29, 20, 0],
+            vec![5, 5, 5, 5, 5, 5, 5],
+        ];
+        CountMatrix::from_dense(
+            barcodes.iter().map(|s| s.to_string()).collect(),
+            genes.iter().map(|s| s.to_string()).collect(),
+            data,
+        )
+    }
+
+    #[test]
+    fn test_detect_species() {
+        let matrix = matrix();
+        assert_eq!(
+            detect_species(&matrix.genes),
+            Some(["GRCh38".to_string(), "mm10".to_string()])
+        );
+        let single: Vec<String> = vec!["ACTB".into(), "GAPDH".into()];
+        assert_eq!(detect_species(&single), None);
+    }
+
+    #[test]
+    fn test_barnyard_calls() {
+        let config = BarnyardConfig {
+            scatter: true,
+            ..Default::default()
+        };
+        let metrics = BarnyardMetrics::from_matrix(&matrix(), &config).unwrap();
+        assert_eq!(metrics.singlets, [3, 2]);
+        assert_eq!(metrics.multiplets, 1);
+        // E1 has only ERCC counts and is skipped
+        assert_eq!(metrics.points.len(), 6);
+        assert_eq!(metrics.points[5].call, "multiplet");
+        assert_eq!(metrics.points[5].umis, [50, 50]);
+        assert!((metrics.observed_multiplet_rate - 1.0 / 6.0).abs() < 1e-9);
+        // p = 0.6, so 48% of doublets are mixed
+        assert!((metrics.inferred_multiplet_rate - (1.0 / 6.0) / 0.48).abs() < 1e-9);
+        // Mouse cells each carry 1% human UMIs
+        assert!((metrics.cross_contamination[1] - 0.01).abs() < 1e-9);
+    }
+}
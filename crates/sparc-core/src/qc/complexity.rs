@@ -0,0 +1,130 @@
+//! Homopolymer and low-entropy barcode/UMI detection
+//!
+//! Poly-T priming, bead synthesis failures, and empty clusters produce
+//! barcodes and UMIs like `TTTTTTTTTTTTTTTT` or `ACACACACACACACAC`. Flagging
+//! them keeps such artifacts from posing as cells or inflating molecule counts.
+
+use serde::{Deserialize, Serialize};
+
+/// Default minimum Shannon entropy, in bits per base
+pub const DEFAULT_MIN_ENTROPY: f64 = 1.0;
+
+/// Shannon entropy (bits) of the base composition of `seq`
+pub fn base_entropy(seq: &[u8]) -> f64 {
+    if seq.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for &b in seq {
+        counts[b.to_ascii_uppercase() as usize] += 1;
+    }
+    let len = seq.len() as f64;
+    counts
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Flags homopolymers and sequences below a minimum base entropy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplexityFilter {
+    /// Entropy (bits per base) below which a sequence is low-complexity; 0
+    /// flags homopolymers only
+    pub min_entropy: f64,
+}
+
+impl Default for ComplexityFilter {
+    fn default() -> Self {
+        Self {
+            min_entropy: DEFAULT_MIN_ENTROPY,
+        }
+    }
+}
+
+impl ComplexityFilter {
+    pub fn new(min_entropy: f64) -> Self {
+        Self { min_entropy }
+    }
+
+    /// Whether `seq` is a homopolymer or has less than `min_entropy`
+    pub fn is_low_complexity(&self, seq: &[u8]) -> bool {
+        let Some(&first) = seq.first() else {
+            return false;
+        };
+        seq.iter().all(|&b| b.eq_ignore_ascii_case(&first)) || base_entropy(seq) < self.min_entropy
+    }
+}
+
+/// Reads with low-complexity barcodes or UMIs, as written by `sparc extract`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComplexityStats {
+    /// Reads checked
+    pub total_reads: u64,
+    /// Reads whose barcode is low-complexity
+    pub low_complexity_barcodes: u64,
+    /// Reads whose UMI is low-complexity
+    pub low_complexity_umis: u64,
+    /// Reads dropped for either, when dropping was enabled
+    pub dropped: u64,
+}
+
+impl ComplexityStats {
+    /// Record one read's barcode and UMI flags
+    pub fn record(&mut self, low_barcode: bool, low_umi: bool) {
+        self.total_reads += 1;
+        self.low_complexity_barcodes += low_barcode as u64;
+        self.low_complexity_umis += low_umi as u64;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.total_reads += other.total_reads;
+        self.low_complexity_barcodes += other.low_complexity_barcodes;
+        self.low_complexity_umis += other.low_complexity_umis;
+        self.dropped += other.dropped;
+    }
+}
+
+/// Low-complexity fractions for the QC report
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ComplexityMetrics {
+    pub fraction_low_complexity_barcodes: f64,
+    pub fraction_low_complexity_umis: f64,
+    pub fraction_dropped: f64,
+}
+
+impl ComplexityMetrics {
+    pub fn from_stats(stats: &ComplexityStats) -> Self {
+        let total = stats.total_reads.max(1) as f64;
+        Self {
+            fraction_low_complexity_barcodes: stats.low_complexity_barcodes as f64 / total,
+            fraction_low_complexity_umis: stats.low_complexity_umis as f64 / total,
+            fraction_dropped: stats.dropped as f64 / total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_low_complexity() {
+        assert_eq!(base_entropy(b"AAAA"), 0.0);
+        assert!((base_entropy(b"ACGT") - 2.0).abs() < 1e-9);
+
+        let filter = ComplexityFilter::default();
+        assert!(filter.is_low_complexity(b"TTTTTTTTTTTT"));
+        assert!(filter.is_low_complexity(b"AAAAAAAAAAAC"));
+        assert!(!filter.is_low_complexity(b"ACACACACACAC"));
+        assert!(!filter.is_low_complexity(b"AACCGGTTAGCT"));
+        assert!(!filter.is_low_complexity(b""));
+
+        let homopolymers_only = ComplexityFilter::new(0.0);
+        assert!(homopolymers_only.is_low_complexity(b"GGGGGGGG"));
+        assert!(!homopolymers_only.is_low_complexity(b"GGGGGGGA"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Cell calling and QC filtering of a count matrix
+//!
+//! Barcodes are first called as cells from their UMI counts, then called
+//! cells failing the QC thresholds (too few UMIs, too few or too many genes,
+//! too high a mitochondrial fraction, or a known doublet) are removed. Every
+//! barcode gets the full list of reasons, so the removed ones can be traced
+//! instead of silently dropped.
+
+use crate::atomic::AtomicFile;
+use crate::count::CountMatrix;
+use crate::Result;
+use ahash::AHashSet;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+/// How barcodes are called as cells before the QC thresholds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CellCalling {
+    /// Every barcode is a cell (the matrix is already cell-filtered)
+    All,
+    /// Barcodes with at least this many UMIs
+    MinUmis(u64),
+    /// Cell Ranger's order-of-magnitude rule for about this many cells: a
+    /// tenth of the 99th percentile UMI count among the top barcodes
+    Expected(usize),
+    /// Exactly this many barcodes, those with the most UMIs
+    Forced(usize),
+}
+
+/// Whether each barcode (by UMI count) is called as a cell
+pub fn call_cells(umis: &[u64], calling: CellCalling) -> Vec<bool> {
+    match calling {
+        CellCalling::All => vec![true; umis.len()],
+        CellCalling::MinUmis(min) => umis.iter().map(|&u| u >= min).collect(),
+        CellCalling::Expected(expected) => {
+            let mut sorted = umis.to_vec();
+            sorted.sort_unstable_by(|a, b| b.cmp(a));
+            let top = &sorted[..expected.min(sorted.len())];
+            let Some(&anchor) = top.get(((top.len().max(1) - 1) as f64 * 0.01).round() as usize)
+            else {
+                return vec![false; umis.len()];
+            };
+            let threshold = (anchor as f64 / 10.0).max(1.0);
+            umis.iter().map(|&u| u as f64 >= threshold).collect()
+        }
+        CellCalling::Forced(n) => {
+            let mut order: Vec<usize> = (0..umis.len()).collect();
+            order.sort_by_key(|&i| std::cmp::Reverse(umis[i]));
+            let mut called = vec![false; umis.len()];
+            for &i in order.iter().take(n) {
+                called[i] = true;
+            }
+            called
+        }
+    }
+}
+
+/// Why a barcode was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterReason {
+    /// Not called as a cell
+    NotCalled,
+    /// Fewer UMIs than `min_umis`
+    LowCounts,
+    /// Fewer genes than `min_genes`
+    LowGenes,
+    /// More genes than `max_genes`
+    HighGenes,
+    /// Mitochondrial UMI percentage above `max_mito_percent`
+    HighMito,
+    /// Listed as a doublet (e.g. by `sparc genotype-demux`)
+    Doublet,
+}
+
+impl FilterReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterReason::NotCalled => "not_called",
+            FilterReason::LowCounts => "low_counts",
+            FilterReason::LowGenes => "low_genes",
+            FilterReason::HighGenes => "high_genes",
+            FilterReason::HighMito => "high_mito",
+            FilterReason::Doublet => "doublet",
+        }
+    }
+}
+
+/// Cell calling plus per-cell QC thresholds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CellFilter {
+    pub calling: CellCalling,
+    /// Minimum UMIs of a called cell (0 disables)
+    pub min_umis: u64,
+    pub min_genes: u64,
+    pub max_genes: u64,
+    pub max_mito_percent: f64,
+}
+
+impl Default for CellFilter {
+    /// The `sparc qc` defaults
+    fn default() -> Self {
+        Self {
+            calling: CellCalling::All,
+            min_umis: 0,
+            min_genes: 200,
+            max_genes: 10_000,
+            max_mito_percent: 20.0,
+        }
+    }
+}
+
+impl CellFilter {
+    /// Thresholds a called cell fails, in [`FilterReason`] order
+    pub fn check(
+        &self,
+        umis: u64,
+        genes: u64,
+        mito_percent: f64,
+        doublet: bool,
+    ) -> Vec<FilterReason> {
+        [
+            (umis < self.min_umis, FilterReason::LowCounts),
+            (genes < self.min_genes, FilterReason::LowGenes),
+            (genes > self.max_genes, FilterReason::HighGenes),
+            (mito_percent > self.max_mito_percent, FilterReason::HighMito),
+            (doublet, FilterReason::Doublet),
+        ]
+        .into_iter()
+        .filter_map(|(fails, reason)| fails.then_some(reason))
+        .collect()
+    }
+
+    /// Status of every barcode of `matrix`, with `doublets` failing as such
+    pub fn apply(&self, matrix: &CountMatrix, doublets: &[String]) -> CellFilterResult {
+        let umis = matrix.counts_per_cell();
+        let genes = matrix.genes_per_cell();
+        let mito_percent = mito_percent(matrix, &umis);
+        let called = call_cells(&umis, self.calling);
+        let doublets: AHashSet<&str> = doublets.iter().map(String::as_str).collect();
+        let reasons = (0..matrix.n_cols)
+            .map(|c| {
+                if !called[c] {
+                    return vec![FilterReason::NotCalled];
+                }
+                let doublet = doublets.contains(matrix.barcodes[c].as_str());
+                self.check(umis[c], genes[c], mito_percent[c], doublet)
+            })
+            .collect();
+        CellFilterResult {
+            umis,
+            genes,
+            mito_percent,
+            reasons,
+        }
+    }
+}
+
+/// Percentage of each cell's UMIs from mitochondrial genes (symbols starting
+/// with `MT-`, any case)
+pub fn mito_percent(matrix: &CountMatrix, umis: &[u64]) -> Vec<f64> {
+    let is_mito: Vec<bool> = (0..matrix.n_rows)
+        .map(|row| {
+            let name = matrix.gene_name(row).as_bytes();
+            name.len() > 3 && name[..3].eq_ignore_ascii_case(b"MT-")
+        })
+        .collect();
+    let mut mito = vec![0u64; matrix.n_cols];
+    for ((&r, &c), &v) in matrix.rows.iter().zip(&matrix.cols).zip(&matrix.values) {
+        if is_mito[r] {
+            mito[c] += v as u64;
+        }
+    }
+    mito.iter()
+        .zip(umis)
+        .map(|(&m, &total)| if total == 0 { 0.0 } else { m as f64 / total as f64 * 100.0 })
+        .collect()
+}
+
+/// Per-barcode metrics and status, in matrix column order
+#[derive(Debug, Clone)]
+pub struct CellFilterResult {
+    pub umis: Vec<u64>,
+    pub genes: Vec<u64>,
+    pub mito_percent: Vec<f64>,
+    /// Why each barcode was removed; empty if kept
+    pub reasons: Vec<Vec<FilterReason>>,
+}
+
+impl CellFilterResult {
+    /// Columns of the kept barcodes
+    pub fn kept(&self) -> Vec<usize> {
+        (0..self.reasons.len()).filter(|&c| self.reasons[c].is_empty()).collect()
+    }
+
+    /// Barcodes removed for `reason`, among others
+    pub fn count(&self, reason: FilterReason) -> usize {
+        self.reasons.iter().filter(|r| r.contains(&reason)).count()
+    }
+
+    /// Write `barcode<TAB>status<TAB>umis<TAB>genes<TAB>mito_pct` for every
+    /// barcode; status is `kept` or the comma-separated removal reasons
+    pub fn write_status<P: AsRef<Path>>(&self, matrix: &CountMatrix, path: P) -> Result<()> {
+        let mut out = AtomicFile::create(path)?;
+        writeln!(out, "barcode\tstatus\tumis\tgenes\tmito_pct")?;
+        for (c, barcode) in matrix.barcodes.iter().enumerate() {
+            let reasons: Vec<&str> = self.reasons[c].iter().map(|r| r.as_str()).collect();
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{:.2}",
+                barcode,
+                if reasons.is_empty() { "kept".to_string() } else { reasons.join(",") },
+                self.umis[c],
+                self.genes[c],
+                self.mito_percent[c]
+            )?;
+        }
+        out.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_cells() {
+        let umis = [5000, 4000, 3000, 350, 200, 10, 0];
+        assert_eq!(call_cells(&umis, CellCalling::All), vec![true; 7]);
+        assert_eq!(
+            call_cells(&umis, CellCalling::MinUmis(300)),
+            vec![true, true, true, true, false, false, false]
+        );
+        // Top 3 barcodes anchor at 5000 UMIs, so the threshold is 500
+        assert_eq!(
+            call_cells(&umis, CellCalling::Expected(3)),
+            vec![true, true, true, false, false, false, false]
+        );
+        assert_eq!(
+            call_cells(&[10, 30, 20], CellCalling::Forced(2)),
+            vec![false, true, true]
+        );
+        assert_eq!(call_cells(&[], CellCalling::Expected(3)), Vec::<bool>::new());
+    }
+
+    #[test]
+    fn test_cell_filter() {
+        let genes = vec!["MT-CO1".to_string(), "ACTB".to_string(), "GAPDH".to_string()];
+        let barcodes = ["A", "B", "C", "D"].map(String::from).to_vec();
+        // Cells as columns: A passes, B has too few genes, C is mostly
+        // mitochondrial, D is nearly empty
+        let matrix = CountMatrix::from_dense(
+            barcodes,
+            genes,
+            vec![vec![1, 0, 90, 0], vec![50, 100, 5, 1], vec![50, 0, 5, 0]],
+        );
+        let filter = CellFilter {
+            calling: CellCalling::MinUmis(10),
+            min_genes: 2,
+            ..CellFilter::default()
+        };
+        let result = filter.apply(&matrix, &[]);
+        assert_eq!(
+            result.reasons,
+            vec![
+                vec![],
+                vec![FilterReason::LowGenes],
+                vec![FilterReason::HighMito],
+                vec![FilterReason::NotCalled]
+            ]
+        );
+        assert_eq!(result.kept(), vec![0]);
+        assert!((result.mito_percent[2] - 90.0).abs() < 1e-9);
+        assert_eq!(result.count(FilterReason::HighMito), 1);
+
+        // Every failed threshold is reported, not just the first
+        let strict = CellFilter {
+            min_umis: 101,
+            ..filter
+        };
+        let result = strict.apply(&matrix, &["A".to_string(), "B".to_string()]);
+        assert_eq!(result.reasons[0], vec![FilterReason::Doublet]);
+        assert_eq!(
+            result.reasons[1],
+            vec![FilterReason::LowCounts, FilterReason::LowGenes, FilterReason::Doublet]
+        );
+    }
+}
@@ -1,9 +1,27 @@
 //! Quality control metrics calculation
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::adt::AdtMetrics;
+use crate::annotation::RegionMetrics;
+use crate::qc::{AtacCellMetrics, AtacMetrics, BarnyardMetrics, ComplexityMetrics, FilterReason};
+use crate::{Error, Result};
+
+/// Schema version of [`QcReport`] JSON written by this release
+///
+/// Version 1 is the unversioned schema; version 2 adds per-cell `pass_qc` and
+/// `qc_flags`. Bump it, and add a step to [`upgrade_report`], whenever a field
+/// is renamed or changes meaning.
+pub const QC_REPORT_VERSION: u32 = 2;
 
 /// Quality control metrics for a single-cell dataset
+///
+/// Metrics missing from older reports read as zero.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct QcMetrics {
     /// Total number of reads
     pub total_reads: u64,
@@ -35,6 +53,24 @@ pub struct QcMetrics {
     pub sequencing_saturation: f64,
     /// Fraction of reads in cells
     pub fraction_reads_in_cells: f64,
+    /// Antibody capture metrics, when an ADT matrix was supplied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adt: Option<AdtMetrics>,
+    /// Genomic region and splicing fractions, when annotation stats were supplied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regions: Option<RegionMetrics>,
+    /// Species-mixing calls and multiplet rates for mixed-species references
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub barnyard: Option<BarnyardMetrics>,
+    /// Fraction of UMIs per gene biotype, when an annotation was supplied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub biotypes: Option<BTreeMap<String, f64>>,
+    /// Low-complexity barcode and UMI fractions, when extraction stats were supplied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_complexity: Option<ComplexityMetrics>,
+    /// TSS enrichment, FRiP, and fragment sizes, when ATAC fragments were supplied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atac: Option<AtacMetrics>,
 }
 
 impl QcMetrics {
@@ -109,16 +145,25 @@ impl QcMetrics {
 /// QC report containing metrics and summary statistics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QcReport {
+    /// Schema version the report was written with
+    #[serde(default = "unversioned")]
+    pub schema_version: u32,
     /// Sample name
     pub sample_name: String,
     /// QC metrics
     pub metrics: QcMetrics,
     /// Per-cell metrics (cell_barcode -> (reads, genes, umis))
+    #[serde(default)]
     pub per_cell_metrics: Vec<CellMetrics>,
     /// Warnings
+    #[serde(default)]
     pub warnings: Vec<String>,
 }
 
+fn unversioned() -> u32 {
+    1
+}
+
 /// Metrics for a single cell
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CellMetrics {
@@ -132,11 +177,48 @@ pub struct CellMetrics {
     pub umis: u64,
     /// Mitochondrial gene percentage
     pub mito_percent: f64,
+    /// Whether the cell passes every QC threshold
+    #[serde(default = "passes_by_default")]
+    pub pass_qc: bool,
+    /// Thresholds the cell fails; empty when it passes
+    #[serde(default)]
+    pub qc_flags: Vec<FilterReason>,
+    /// ATAC metrics, when ATAC fragments were supplied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atac: Option<AtacCellMetrics>,
+}
+
+fn passes_by_default() -> bool {
+    true
+}
+
+impl CellMetrics {
+    /// Metrics of a cell not yet checked against QC thresholds
+    pub fn new(barcode: String, reads: u64, genes: u64, umis: u64, mito_percent: f64) -> Self {
+        Self {
+            barcode,
+            reads,
+            genes,
+            umis,
+            mito_percent,
+            pass_qc: true,
+            qc_flags: Vec::new(),
+            atac: None,
+        }
+    }
+
+    /// Record the thresholds the cell fails, as from
+    /// [`CellFilter::check`](crate::qc::CellFilter::check)
+    pub fn set_qc_flags(&mut self, flags: Vec<FilterReason>) {
+        self.pass_qc = flags.is_empty();
+        self.qc_flags = flags;
+    }
 }
 
 impl QcReport {
     pub fn new(sample_name: String) -> Self {
         Self {
+            schema_version: QC_REPORT_VERSION,
             sample_name,
             metrics: QcMetrics::new(),
             per_cell_metrics: Vec::new(),
@@ -144,6 +226,11 @@ impl QcReport {
         }
     }
 
+    /// Cells whose per-cell metrics pass QC
+    pub fn cells_passing(&self) -> usize {
+        self.per_cell_metrics.iter().filter(|c| c.pass_qc).count()
+    }
+
     /// Add a warning
     pub fn add_warning(&mut self, warning: String) {
         self.warnings.push(warning);
@@ -163,12 +250,95 @@ impl QcReport {
         if self.metrics.median_genes_per_cell < 200.0 {
             self.warnings.push("Low median genes per cell (<200)".to_string());
         }
+        if let Some(barnyard) = &self.metrics.barnyard {
+            if barnyard.inferred_multiplet_rate > 0.1 {
+                self.warnings.push(format!(
+                    "High inferred multiplet rate ({:.1}%) from species mixing",
+                    barnyard.inferred_multiplet_rate * 100.0
+                ));
+            }
+        }
+        if let Some(atac) = &self.metrics.atac {
+            if atac.tss_enrichment < 4.0 {
+                self.warnings.push(format!(
+                    "Low TSS enrichment ({:.1}, expected >= 4)",
+                    atac.tss_enrichment
+                ));
+            }
+        }
+        if let Some(adt) = &self.metrics.adt {
+            if adt.high_isotype_cell_fraction > 0.1 {
+                self.warnings.push(format!(
+                    "High isotype background in {:.1}% of cells",
+                    adt.high_isotype_cell_fraction * 100.0
+                ));
+            }
+        }
     }
 
     /// Export to JSON
     pub fn to_json(&self) -> serde_json::Result<String> {
         serde_json::to_string_pretty(self)
     }
+
+    /// Parse a report of any schema version up to [`QC_REPORT_VERSION`],
+    /// upgrading older ones to the current schema
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|e| Error::Config(format!("Invalid QC report: {}", e)))?;
+        let value = upgrade_report(value)?;
+        serde_json::from_value(value)
+            .map_err(|e| Error::Config(format!("Invalid QC report: {}", e)))
+    }
+
+    /// Load a `qc_report.json` of any supported schema version
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let json = std::fs::read_to_string(path.as_ref())?;
+        Self::from_json(&json).map_err(|e| match e {
+            Error::Config(msg) => Error::Config(format!("{:?}: {}", path.as_ref(), msg)),
+            e => e,
+        })
+    }
+}
+
+/// Upgrade a report's JSON one schema version at a time to
+/// [`QC_REPORT_VERSION`]
+pub fn upgrade_report(mut report: Value) -> Result<Value> {
+    let Some(fields) = report.as_object_mut() else {
+        return Err(Error::Config("QC report is not a JSON object".to_string()));
+    };
+    let mut version = match fields.get("schema_version") {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| Error::Config(format!("Invalid QC report schema_version {}", v)))?,
+    };
+    if version == 0 || version > QC_REPORT_VERSION {
+        return Err(Error::Config(format!(
+            "QC report schema version {} is not supported (this release reads 1 to {})",
+            version, QC_REPORT_VERSION
+        )));
+    }
+    while version < QC_REPORT_VERSION {
+        match version {
+            1 => upgrade_v1(fields),
+            _ => unreachable!("no upgrade from QC report schema version {}", version),
+        }
+        version += 1;
+    }
+    fields.insert("schema_version".to_string(), Value::from(version));
+    Ok(report)
+}
+
+/// Version 1 predates QC flags: every recorded cell passed
+fn upgrade_v1(fields: &mut serde_json::Map<String, Value>) {
+    if let Some(Value::Array(cells)) = fields.get_mut("per_cell_metrics") {
+        for cell in cells.iter_mut().filter_map(Value::as_object_mut) {
+            cell.entry("pass_qc").or_insert(Value::Bool(true));
+            cell.entry("qc_flags").or_insert_with(|| Value::Array(Vec::new()));
+        }
+    }
 }
 
 #[cfg(test)]
@@ -202,4 +372,52 @@ mod tests {
         assert!((metrics.mean_reads_per_cell - 300.0).abs() < 0.001);
         assert!((metrics.median_reads_per_cell - 300.0).abs() < 0.001);
     }
+
+    #[test]
+    fn test_cell_qc_flags() {
+        let mut report = QcReport::new("s1".to_string());
+        let mut failing = CellMetrics::new("AAAC".to_string(), 10, 5, 8, 40.0);
+        failing.set_qc_flags(vec![FilterReason::LowGenes, FilterReason::HighMito]);
+        report.per_cell_metrics.push(failing);
+        report.per_cell_metrics.push(CellMetrics::new("GGTT".to_string(), 900, 400, 800, 2.0));
+        assert_eq!(report.cells_passing(), 1);
+
+        let json = report.to_json().unwrap();
+        assert!(json.contains("\"high_mito\""));
+        let back: QcReport = serde_json::from_str(&json).unwrap();
+        assert!(!back.per_cell_metrics[0].pass_qc);
+        assert_eq!(back.per_cell_metrics[0].qc_flags.len(), 2);
+        // Reports written before the flags existed read back as passing
+        let old: CellMetrics = serde_json::from_str(
+            r#"{"barcode": "A", "reads": 1, "genes": 1, "umis": 1, "mito_percent": 0.0}"#,
+        )
+        .unwrap();
+        assert!(old.pass_qc && old.qc_flags.is_empty());
+    }
+
+    #[test]
+    fn test_report_schema_upgrade() {
+        let report = QcReport::new("s1".to_string());
+        let json = report.to_json().unwrap();
+        assert!(json.contains("\"schema_version\": 2"));
+        assert_eq!(QcReport::from_json(&json).unwrap().schema_version, QC_REPORT_VERSION);
+
+        // An unversioned report missing since-added metrics
+        let v1 = r#"{
+            "sample_name": "old",
+            "metrics": {"total_reads": 1000, "num_cells": 1},
+            "per_cell_metrics": [
+                {"barcode": "A", "reads": 1, "genes": 1, "umis": 1, "mito_percent": 0.0}
+            ],
+            "warnings": []
+        }"#;
+        let old = QcReport::from_json(v1).unwrap();
+        assert_eq!(old.schema_version, QC_REPORT_VERSION);
+        assert_eq!(old.metrics.total_reads, 1000);
+        assert_eq!(old.metrics.median_umi_per_cell, 0.0);
+        assert_eq!(old.cells_passing(), 1);
+
+        let future = r#"{"schema_version": 99, "sample_name": "new", "metrics": {}}"#;
+        assert!(QcReport::from_json(future).is_err());
+    }
 }
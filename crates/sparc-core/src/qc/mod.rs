@@ -1,5 +1,14 @@
 //! Quality control metrics module
 
+pub mod atac;
+pub mod barnyard;
+pub mod complexity;
+pub mod filter;
 mod metrics;
+pub mod stats;
 
-pub use metrics::{CellMetrics, QcMetrics, QcReport};
+pub use atac::{atac_qc, tss_from_annotation, AtacCellMetrics, AtacMetrics, AtacQcConfig};
+pub use barnyard::{BarnyardConfig, BarnyardMetrics};
+pub use complexity::{ComplexityFilter, ComplexityMetrics, ComplexityStats};
+pub use filter::{call_cells, CellCalling, CellFilter, CellFilterResult, FilterReason};
+pub use metrics::{upgrade_report, CellMetrics, QcMetrics, QcReport, QC_REPORT_VERSION};
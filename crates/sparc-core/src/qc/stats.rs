@@ -0,0 +1,496 @@
+//! Quick file statistics from a sample of records (`sparc stats`)
+
+#[cfg(feature = "native")]
+use crate::bam::{BamParser, BamRecord};
+use crate::fastq::{FastqParser, FastqRecord, FastqRecordRef, QualityEncoding};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::Error;
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// On-disk compression format, detected from magic bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    None,
+    Gzip,
+    /// Block gzip (BGZF), as used by BAM and `bgzip`
+    Bgzf,
+    Zstd,
+}
+
+impl Compression {
+    /// Detect the compression of a file from its first bytes
+    pub fn detect<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut magic = [0u8; 16];
+        let n = File::open(path.as_ref())?.read(&mut magic)?;
+        Ok(Self::from_magic(&magic[..n]))
+    }
+
+    fn from_magic(magic: &[u8]) -> Self {
+        match magic {
+            [0x1f, 0x8b, _, flags, ..]
+                if flags & 0x04 != 0 && magic.get(12..14) == Some(&b"BC"[..]) =>
+            {
+                Compression::Bgzf
+            }
+            [0x1f, 0x8b, ..] => Compression::Gzip,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Read length and quality summary from sampled FASTQ records
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FastqStats {
+    pub reads: u64,
+    pub bases: u64,
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Read length -> count
+    pub length_distribution: BTreeMap<usize, u64>,
+    pub q30_bases: u64,
+    pub gc_bases: u64,
+    pub n_bases: u64,
+    quality_sum: u64,
+}
+
+impl FastqStats {
+    /// Add one record (qualities must be Phred+33)
+    pub fn add(&mut self, record: &FastqRecord) {
+        self.add_ref(record.as_record_ref());
+    }
+
+    /// Add a borrowed record (see [`FastqParser::next_ref`])
+    pub fn add_ref(&mut self, record: FastqRecordRef<'_>) {
+        let len = record.seq.len();
+        if self.reads == 0 || len < self.min_length {
+            self.min_length = len;
+        }
+        self.max_length = self.max_length.max(len);
+        self.reads += 1;
+        self.bases += len as u64;
+        *self.length_distribution.entry(len).or_insert(0) += 1;
+
+        for &b in record.seq {
+            match b {
+                b'G' | b'C' | b'g' | b'c' => self.gc_bases += 1,
+                b'N' | b'n' => self.n_bases += 1,
+                _ => {}
+            }
+        }
+        for &q in record.qual {
+            let q = q.saturating_sub(33) as u64;
+            self.quality_sum += q;
+            if q >= 30 {
+                self.q30_bases += 1;
+            }
+        }
+    }
+
+    pub fn mean_length(&self) -> f64 {
+        self.bases as f64 / self.reads.max(1) as f64
+    }
+
+    pub fn mean_quality(&self) -> f64 {
+        self.quality_sum as f64 / self.bases.max(1) as f64
+    }
+
+    pub fn q30_fraction(&self) -> f64 {
+        self.q30_bases as f64 / self.bases.max(1) as f64
+    }
+
+    pub fn gc_fraction(&self) -> f64 {
+        self.gc_bases as f64 / self.bases.max(1) as f64
+    }
+}
+
+/// Alignment and tag summary from sampled BAM records
+#[cfg(feature = "native")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BamStats {
+    pub records: u64,
+    pub mapped: u64,
+    pub with_cb: u64,
+    pub with_ub: u64,
+    pub with_gene: u64,
+    /// `SO` field of the `@HD` header line, if present
+    pub header_sort_order: Option<String>,
+    /// Whether sampled records were in coordinate order
+    pub coordinate_sorted: bool,
+    #[serde(skip)]
+    last_pos: Option<(i32, i64)>,
+}
+
+#[cfg(feature = "native")]
+impl BamStats {
+    pub fn new(header_sort_order: Option<String>) -> Self {
+        Self {
+            header_sort_order,
+            coordinate_sorted: true,
+            ..Default::default()
+        }
+    }
+
+    /// Add one record
+    pub fn add(&mut self, record: &BamRecord) {
+        self.records += 1;
+        self.with_cb += record.cell_barcode.is_some() as u64;
+        self.with_ub += record.umi.is_some() as u64;
+        self.with_gene += record.is_assigned() as u64;
+        if !record.is_mapped {
+            return;
+        }
+        self.mapped += 1;
+        // Unmapped reads may appear anywhere; only mapped positions are checked
+        let pos = (record.tid, record.pos);
+        if let Some(last) = self.last_pos {
+            if pos < last {
+                self.coordinate_sorted = false;
+            }
+        }
+        self.last_pos = Some(pos);
+    }
+
+    /// Fraction of records carrying a tag count
+    pub fn fraction(&self, n: u64) -> f64 {
+        n as f64 / self.records.max(1) as f64
+    }
+}
+
+/// Content statistics of a sampled file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum FileContent {
+    Fastq {
+        encoding: QualityEncoding,
+        stats: FastqStats,
+    },
+    #[cfg(feature = "native")]
+    Bam(BamStats),
+}
+
+/// Statistics for one input file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileStats {
+    pub path: String,
+    pub file_size: u64,
+    pub compression: Compression,
+    /// Whether every record was read (false if sampling stopped early)
+    pub complete: bool,
+    /// Estimated total records, extrapolated from bytes consumed when sampling
+    pub estimated_records: u64,
+    pub content: FileContent,
+}
+
+/// Reader wrapper counting bytes consumed from the underlying file
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Record count extrapolated from a sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordEstimate {
+    /// Records actually read
+    pub sampled: u64,
+    /// Whether the whole file was read
+    pub complete: bool,
+    /// Estimated total records
+    pub estimated: u64,
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn skip<R: Read>(reader: &mut R, n: u64) -> std::io::Result<()> {
+    let skipped = std::io::copy(&mut reader.take(n), &mut std::io::sink())?;
+    if skipped < n {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Count raw BAM records without decoding them (up to `max_records`, 0 = all),
+/// extrapolating the total from the compressed bytes consumed
+pub fn estimate_bam_records<P: AsRef<Path>>(path: P, max_records: u64) -> Result<RecordEstimate> {
+    let path = path.as_ref();
+    let file_size = std::fs::metadata(path)?.len();
+    let limit = if max_records == 0 { u64::MAX } else { max_records };
+    let consumed = Arc::new(AtomicU64::new(0));
+    let reader = CountingReader {
+        inner: File::open(path)?,
+        count: Arc::clone(&consumed),
+    };
+    let mut bam = BufReader::new(MultiGzDecoder::new(reader));
+
+    let mut magic = [0u8; 4];
+    bam.read_exact(&mut magic)?;
+    if &magic != b"BAM\x01" {
+        return Err(Error::BamParse(format!("{}: not a BAM file", path.display())));
+    }
+    let l_text = read_u32(&mut bam)?;
+    skip(&mut bam, l_text as u64)?;
+    let n_ref = read_u32(&mut bam)?;
+    for _ in 0..n_ref {
+        let l_name = read_u32(&mut bam)?;
+        skip(&mut bam, l_name as u64 + 4)?;
+    }
+
+    let mut sampled = 0u64;
+    let mut complete = true;
+    loop {
+        if sampled >= limit {
+            complete = false;
+            break;
+        }
+        match read_u32(&mut bam) {
+            Ok(block_size) => skip(&mut bam, block_size as u64)?,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        sampled += 1;
+    }
+
+    let estimated = if complete {
+        sampled
+    } else {
+        extrapolate(sampled, file_size, consumed.load(Ordering::Relaxed))
+    };
+    Ok(RecordEstimate {
+        sampled,
+        complete,
+        estimated,
+    })
+}
+
+/// Estimate the records of a FASTQ or BAM file from its first `max_records`
+/// (0 = all), extrapolating from the (compressed) bytes they took up. Cheap
+/// enough to size a progress bar before a run.
+pub fn estimate_records<P: AsRef<Path>>(path: P, max_records: u64) -> Result<RecordEstimate> {
+    let path = path.as_ref();
+    if is_alignment_file(path) {
+        return estimate_bam_records(path, max_records);
+    }
+    let file_size = std::fs::metadata(path)?.len();
+    let limit = if max_records == 0 { u64::MAX } else { max_records };
+    let consumed = Arc::new(AtomicU64::new(0));
+    let reader = CountingReader {
+        inner: File::open(path)?,
+        count: Arc::clone(&consumed),
+    };
+    let mut parser = FastqParser::from_reader(reader, &path.display().to_string())?;
+    let mut sampled = 0u64;
+    let mut complete = true;
+    while let Some(record) = parser.next_ref() {
+        if sampled >= limit {
+            complete = false;
+            break;
+        }
+        record?;
+        sampled += 1;
+    }
+
+    let estimated = if complete {
+        sampled
+    } else {
+        extrapolate(sampled, file_size, consumed.load(Ordering::Relaxed))
+    };
+    Ok(RecordEstimate {
+        sampled,
+        complete,
+        estimated,
+    })
+}
+
+/// Records in a `file_size`-byte file, given `sampled` records in its first
+/// `consumed` bytes
+fn extrapolate(sampled: u64, file_size: u64, consumed: u64) -> u64 {
+    (sampled as f64 * file_size as f64 / consumed.max(1) as f64).round() as u64
+}
+
+/// Whether a path looks like an alignment file
+pub fn is_alignment_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| matches!(e, "bam" | "sam" | "cram"))
+}
+
+/// Statistics from the first `limit` records of an alignment file
+#[cfg(feature = "native")]
+fn bam_file_stats(
+    path: &Path,
+    file_size: u64,
+    compression: Compression,
+    limit: u64,
+) -> Result<FileStats> {
+    let mut parser = BamParser::open(path)?;
+    let sort_order = parser
+        .header()
+        .to_hashmap()
+        .get("HD")
+        .and_then(|hd| hd.first())
+        .and_then(|hd| hd.get("SO").cloned());
+    let mut stats = BamStats::new(sort_order);
+    let mut complete = true;
+    for record in &mut parser {
+        if stats.records >= limit {
+            complete = false;
+            break;
+        }
+        stats.add(&record?);
+    }
+    let estimated_records = if !complete && compression == Compression::Bgzf {
+        estimate_bam_records(path, stats.records)?.estimated
+    } else {
+        stats.records
+    };
+    Ok(FileStats {
+        path: path.display().to_string(),
+        file_size,
+        compression,
+        complete,
+        estimated_records,
+        content: FileContent::Bam(stats),
+    })
+}
+
+/// Statistics from the first `limit` records of an alignment file
+#[cfg(not(feature = "native"))]
+fn bam_file_stats(path: &Path, _: u64, _: Compression, _: u64) -> Result<FileStats> {
+    Err(Error::BamParse(format!(
+        "{}: alignment input requires SPARC to be built with the `native` feature",
+        path.display()
+    )))
+}
+
+/// Collect statistics from the first `max_records` records (0 = all)
+pub fn file_stats<P: AsRef<Path>>(path: P, max_records: u64) -> Result<FileStats> {
+    let path = path.as_ref();
+    let file_size = std::fs::metadata(path)?.len();
+    let compression = Compression::detect(path)?;
+    let limit = if max_records == 0 { u64::MAX } else { max_records };
+
+    if is_alignment_file(path) {
+        return bam_file_stats(path, file_size, compression, limit);
+    }
+
+    let consumed = Arc::new(AtomicU64::new(0));
+    let reader = CountingReader {
+        inner: File::open(path)?,
+        count: Arc::clone(&consumed),
+    };
+    let mut parser = FastqParser::from_reader(reader, &path.display().to_string())?;
+    let encoding = parser.detect_encoding();
+    let mut stats = FastqStats::default();
+    let mut complete = true;
+    while let Some(record) = parser.next_ref() {
+        if stats.reads >= limit {
+            complete = false;
+            break;
+        }
+        stats.add_ref(record?);
+    }
+
+    let estimated_records = if complete {
+        stats.reads
+    } else {
+        extrapolate(stats.reads, file_size, consumed.load(Ordering::Relaxed))
+    };
+
+    Ok(FileStats {
+        path: path.display().to_string(),
+        file_size,
+        compression,
+        complete,
+        estimated_records,
+        content: FileContent::Fastq { encoding, stats },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression_magic() {
+        assert_eq!(Compression::from_magic(b"@read1\nACGT"), Compression::None);
+        assert_eq!(Compression::from_magic(&[0x1f, 0x8b, 8, 0]), Compression::Gzip);
+        assert_eq!(Compression::from_magic(&[0x28, 0xb5, 0x2f, 0xfd]), Compression::Zstd);
+        let bgzf = [0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0];
+        assert_eq!(Compression::from_magic(&bgzf), Compression::Bgzf);
+    }
+
+    #[test]
+    fn test_estimate_bam_records() {
+        use flate2::{write::GzEncoder, Compression as Level};
+        use std::io::Write;
+
+        let mut raw = b"BAM\x01".to_vec();
+        raw.extend(0u32.to_le_bytes()); // l_text
+        raw.extend(1u32.to_le_bytes()); // n_ref
+        raw.extend(5u32.to_le_bytes());
+        raw.extend(b"chr1\0");
+        raw.extend(1000u32.to_le_bytes());
+        for size in [40u32, 52, 36] {
+            raw.extend(size.to_le_bytes());
+            raw.extend(vec![0u8; size as usize]);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raw.bam");
+        let mut gz = GzEncoder::new(File::create(&path).unwrap(), Level::default());
+        gz.write_all(&raw).unwrap();
+        gz.finish().unwrap();
+
+        let all = estimate_bam_records(&path, 0).unwrap();
+        assert_eq!((all.sampled, all.complete, all.estimated), (3, true, 3));
+        let sample = estimate_bam_records(&path, 2).unwrap();
+        assert_eq!((sample.sampled, sample.complete), (2, false));
+    }
+
+    #[test]
+    fn test_estimate_fastq_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.fastq");
+        std::fs::write(&path, "@r\nACGTACGT\n+\nIIIIIIII\n".repeat(50_000)).unwrap();
+
+        let all = estimate_records(&path, 0).unwrap();
+        assert_eq!((all.sampled, all.complete, all.estimated), (50_000, true, 50_000));
+        let sample = estimate_records(&path, 10_000).unwrap();
+        assert_eq!((sample.sampled, sample.complete), (10_000, false));
+        // The parser reads ahead of the sample, so the estimate runs low
+        assert!(sample.estimated > 30_000 && sample.estimated <= 50_000);
+        assert_eq!(extrapolate(10, 1000, 100), 100);
+    }
+
+    #[test]
+    fn test_fastq_stats() {
+        let mut stats = FastqStats::default();
+        stats.add(&FastqRecord::new("a".into(), b"ACGTN".to_vec(), b"IIII#".to_vec()));
+        stats.add(&FastqRecord::new("b".into(), b"GGG".to_vec(), b"III".to_vec()));
+
+        assert_eq!(stats.reads, 2);
+        assert_eq!((stats.min_length, stats.max_length), (3, 5));
+        assert_eq!(stats.length_distribution[&5], 1);
+        assert_eq!(stats.n_bases, 1);
+        assert!((stats.gc_fraction() - 5.0 / 8.0).abs() < 1e-9);
+        assert!((stats.q30_fraction() - 7.0 / 8.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,208 @@
+//! Process-wide resource limits: temporary directory, memory budget, and
+//! threads per stage
+//!
+//! The CLI sets these once from `--tmp-dir`, `--max-memory`, and the
+//! `--*-threads` options; the spilling gene counter, the external BAM sort
+//! (STAR, samtools), and the readers and writers read them so SPARC stays
+//! within node quotas.
+
+use crate::{Error, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+static GLOBAL: OnceLock<ResourceConfig> = OnceLock::new();
+static SPILL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Temporary directory, memory budget, and stage threads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceConfig {
+    /// Directory for spill files and sort temporaries
+    pub tmp_dir: PathBuf,
+    /// Approximate memory budget in bytes (None = unlimited)
+    pub max_memory: Option<u64>,
+    /// Threads for decompression, processing, and compression
+    pub threads: StageThreads,
+}
+
+impl Default for ResourceConfig {
+    fn default() -> Self {
+        Self {
+            tmp_dir: std::env::temp_dir(),
+            max_memory: None,
+            threads: StageThreads::default(),
+        }
+    }
+}
+
+/// Threads per kind of stage. Gzip-bound stages want decompression and
+/// compression threads, CPU-bound ones want workers; unset counts fall back
+/// to each call site's default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageThreads {
+    /// Threads decoding input: BGZF blocks of BAMs, or (any count above 0) a
+    /// read-ahead thread for gzip/zstd FASTQ
+    pub decompress: Option<usize>,
+    /// Threads processing records (the rayon pool and chunk workers)
+    pub workers: Option<usize>,
+    /// Threads compressing BAM and FASTQ output
+    pub compress: Option<usize>,
+}
+
+impl StageThreads {
+    /// Decompression threads, or `default` when unset
+    pub fn decompress_or(&self, default: usize) -> usize {
+        self.decompress.unwrap_or(default)
+    }
+
+    /// Worker threads, or the size of the rayon pool when unset
+    pub fn workers(&self) -> usize {
+        self.workers.unwrap_or_else(rayon::current_num_threads).max(1)
+    }
+
+    /// Compression threads, or `default` when unset
+    pub fn compress_or(&self, default: usize) -> usize {
+        self.compress.unwrap_or(default)
+    }
+}
+
+impl ResourceConfig {
+    /// A fresh path under `tmp_dir` that does not exist yet
+    pub fn unique_path(&self, prefix: &str) -> PathBuf {
+        let n = SPILL_COUNTER.fetch_add(1, Ordering::Relaxed);
+        self.tmp_dir
+            .join(format!("sparc-{}-{}-{}", prefix, std::process::id(), n))
+    }
+}
+
+/// Install the process-wide configuration (ignored if already set)
+pub fn set_global(config: ResourceConfig) {
+    let _ = GLOBAL.set(config);
+}
+
+/// The process-wide configuration (defaults if never set)
+pub fn global() -> &'static ResourceConfig {
+    GLOBAL.get_or_init(ResourceConfig::default)
+}
+
+/// Memory the system can still hand out without swapping (`MemAvailable`
+/// in `/proc/meminfo`); None where that is unknown
+pub fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    parse_meminfo_kb(&meminfo, "MemAvailable:").map(|kb| kb << 10)
+}
+
+/// Largest single allocation to attempt: the `--max-memory` budget when set,
+/// else the memory available to the system
+pub fn memory_limit() -> Option<u64> {
+    global().max_memory.or_else(available_memory)
+}
+
+/// A `<field> <n> kB` value from `/proc/meminfo`
+fn parse_meminfo_kb(meminfo: &str, field: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|l| l.starts_with(field))?;
+    line[field.len()..].split_whitespace().next()?.parse().ok()
+}
+
+/// Parse a memory size such as `512M`, `16G`, `1.5GB`, or a plain byte count
+pub fn parse_memory_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let upper = s.to_ascii_uppercase();
+    let digits = upper.trim_end_matches('B').trim_end_matches('I');
+    let (number, unit) = match digits.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&digits[..i], c),
+        _ => (digits, ' '),
+    };
+    let multiplier: u64 = match unit {
+        ' ' => 1,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        'T' => 1 << 40,
+        _ => {
+            return Err(Error::Config(format!(
+                "invalid memory size '{}' (unknown unit '{}')",
+                s, unit
+            )))
+        }
+    };
+    let value: f64 = number.trim().parse().map_err(|_| {
+        Error::Config(format!(
+            "invalid memory size '{}' (expected e.g. 512M, 16G)",
+            s
+        ))
+    })?;
+    if value <= 0.0 {
+        return Err(Error::Config(format!("memory size '{}' must be positive", s)));
+    }
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Temporary file under the configured `tmp_dir`, deleted on drop
+#[derive(Debug)]
+pub struct SpillFile {
+    path: PathBuf,
+}
+
+impl SpillFile {
+    /// Create a new empty spill file
+    pub fn create(config: &ResourceConfig, prefix: &str) -> Result<(Self, File)> {
+        let path = config.unique_path(prefix);
+        let file = File::create(&path).map_err(|e| {
+            Error::Io(std::io::Error::new(
+                e.kind(),
+                format!("cannot create spill file {:?}: {}", path, e),
+            ))
+        })?;
+        Ok((Self { path }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!(parse_memory_size("1024").unwrap(), 1024);
+        assert_eq!(parse_memory_size("512M").unwrap(), 512u64 << 20);
+        assert_eq!(parse_memory_size("16g").unwrap(), 16u64 << 30);
+        assert_eq!(parse_memory_size("2GiB").unwrap(), 2u64 << 30);
+        assert_eq!(parse_memory_size("1.5G").unwrap(), 3u64 << 29);
+        assert!(parse_memory_size("12X").is_err());
+        assert!(parse_memory_size("lots").is_err());
+    }
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "MemTotal:       32768000 kB\nMemFree:         1024000 kB\n\
+                       MemAvailable:   16384000 kB\n";
+        assert_eq!(parse_meminfo_kb(meminfo, "MemAvailable:"), Some(16_384_000));
+        assert_eq!(parse_meminfo_kb(meminfo, "SwapTotal:"), None);
+    }
+
+    #[test]
+    fn test_spill_file_removed_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ResourceConfig {
+            tmp_dir: dir.path().to_path_buf(),
+            ..ResourceConfig::default()
+        };
+        let (spill, _file) = SpillFile::create(&config, "test").unwrap();
+        let path = spill.path().to_path_buf();
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+    }
+}
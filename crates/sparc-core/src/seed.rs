@@ -0,0 +1,22 @@
+//! Process-wide random seed
+//!
+//! The CLI sets this once from `--seed`; every stochastic step (read
+//! subsampling, read simulation, synthetic validation data) draws from it so a
+//! run with the same seed and inputs gives identical outputs.
+
+use std::sync::OnceLock;
+
+/// Seed used when none is given
+pub const DEFAULT_SEED: u64 = 42;
+
+static GLOBAL: OnceLock<u64> = OnceLock::new();
+
+/// Install the process-wide seed (ignored if already set)
+pub fn set_global(seed: u64) {
+    let _ = GLOBAL.set(seed);
+}
+
+/// The process-wide seed ([`DEFAULT_SEED`] if never set)
+pub fn global() -> u64 {
+    *GLOBAL.get_or_init(|| DEFAULT_SEED)
+}
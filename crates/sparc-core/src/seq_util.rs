@@ -0,0 +1,200 @@
+//! Sequence comparison kernels
+//!
+//! Hamming distances shared by barcode correction, UMI clustering, and sample
+//! demultiplexing. Byte slices are compared eight bases per step (XOR of u64
+//! words, then a count of the nonzero bytes); sequences of up to 32 A/C/G/T
+//! bases can also be packed two bits per base and compared with one XOR and a
+//! popcount. Indel-prone long reads are compared by edit distance instead.
+
+const LOW7: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+const LOW_BITS: u64 = 0x5555_5555_5555_5555;
+
+/// Number of nonzero bytes in a word
+#[inline]
+fn nonzero_bytes(x: u64) -> u32 {
+    // Bit 7 of each byte ends up set iff the byte is nonzero
+    ((((x & LOW7) + LOW7) | x) & !LOW7).count_ones()
+}
+
+#[inline]
+fn word(bytes: &[u8]) -> u64 {
+    u64::from_ne_bytes(bytes.try_into().expect("8-byte chunk"))
+}
+
+/// Mismatching positions between two sequences, or `u32::MAX` if their
+/// lengths differ
+#[inline]
+pub fn hamming(a: &[u8], b: &[u8]) -> u32 {
+    if a.len() != b.len() {
+        return u32::MAX;
+    }
+    let mut a_words = a.chunks_exact(8);
+    let mut b_words = b.chunks_exact(8);
+    let mut dist: u32 = (&mut a_words)
+        .zip(&mut b_words)
+        .map(|(x, y)| nonzero_bytes(word(x) ^ word(y)))
+        .sum();
+    for (x, y) in a_words.remainder().iter().zip(b_words.remainder()) {
+        dist += (x != y) as u32;
+    }
+    dist
+}
+
+/// Hamming distance if it is at most `max_distance`, stopping as soon as it is
+/// exceeded; `None` for longer distances or different lengths
+#[inline]
+pub fn hamming_within(a: &[u8], b: &[u8], max_distance: u32) -> Option<u32> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let mut a_words = a.chunks_exact(8);
+    let mut b_words = b.chunks_exact(8);
+    let mut dist = 0u32;
+    for (x, y) in (&mut a_words).zip(&mut b_words) {
+        dist += nonzero_bytes(word(x) ^ word(y));
+        if dist > max_distance {
+            return None;
+        }
+    }
+    for (x, y) in a_words.remainder().iter().zip(b_words.remainder()) {
+        dist += (x != y) as u32;
+    }
+    (dist <= max_distance).then_some(dist)
+}
+
+/// Pack a sequence of at most 32 A/C/G/T bases two bits per base.
+///
+/// Returns `None` for longer sequences or any other base (including `N` and
+/// lowercase). Only sequences of equal length are comparable.
+pub fn pack_2bit(seq: &[u8]) -> Option<u64> {
+    if seq.len() > 32 {
+        return None;
+    }
+    seq.iter().try_fold(0u64, |packed, &base| {
+        let code = match base {
+            b'A' => 0,
+            b'C' => 1,
+            b'G' => 2,
+            b'T' => 3,
+            _ => return None,
+        };
+        Some((packed << 2) | code)
+    })
+}
+
+/// Unpack `len` bases packed by [`pack_2bit`]
+pub fn unpack_2bit(packed: u64, len: usize) -> String {
+    (0..len)
+        .map(|i| b"ACGT"[((packed >> (2 * (len - 1 - i))) & 3) as usize] as char)
+        .collect()
+}
+
+/// Mismatching bases between two packed sequences of the same length
+#[inline]
+pub fn hamming_2bit(a: u64, b: u64) -> u32 {
+    let x = a ^ b;
+    ((x | (x >> 1)) & LOW_BITS).count_ones()
+}
+
+/// Edit (Levenshtein) distance if it is at most `max_distance`, stopping once
+/// every alignment exceeds it; `None` for longer distances
+pub fn edit_distance_within(a: &[u8], b: &[u8], max_distance: u32) -> Option<u32> {
+    if a.len().abs_diff(b.len()) > max_distance as usize {
+        return None;
+    }
+    let mut prev: Vec<u32> = (0..=b.len() as u32).collect();
+    let mut cur = vec![0u32; b.len() + 1];
+    for (i, &x) in a.iter().enumerate() {
+        cur[0] = i as u32 + 1;
+        let mut row_min = cur[0];
+        for (j, &y) in b.iter().enumerate() {
+            cur[j + 1] = (prev[j] + (x != y) as u32)
+                .min(prev[j + 1] + 1)
+                .min(cur[j] + 1);
+            row_min = row_min.min(cur[j + 1]);
+        }
+        if row_min > max_distance {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    let dist = prev[b.len()];
+    (dist <= max_distance).then_some(dist)
+}
+
+/// Reverse complement; bases other than A/C/G/T are kept as they are
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|&base| match base {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            other => other,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(a: &[u8], b: &[u8]) -> u32 {
+        a.iter().zip(b).filter(|(x, y)| x != y).count() as u32
+    }
+
+    #[test]
+    fn test_hamming_matches_naive() {
+        let a = b"ACGTACGTNNACGTTTGCAACGTACGTACGTAC";
+        for len in 0..=a.len() {
+            let mut b = a[..len].to_vec();
+            for i in (0..len).step_by(3) {
+                b[i] = if b[i] == b'T' { b'A' } else { b'T' };
+                assert_eq!(hamming(&a[..len], &b), naive(&a[..len], &b), "len {}", len);
+            }
+        }
+        assert_eq!(hamming(b"ACGT", b"ACG"), u32::MAX);
+        // Bytes with the high bit set count as mismatches too
+        assert_eq!(hamming(&[0x80; 9], &[0x00; 9]), 9);
+    }
+
+    #[test]
+    fn test_hamming_within() {
+        let a = b"AAAACCCCGGGGTTTTAC";
+        let b = b"AAAACCCAGGGGTTTTCC";
+        assert_eq!(hamming_within(a, b, 2), Some(2));
+        assert_eq!(hamming_within(a, b, 1), None);
+        assert_eq!(hamming_within(a, a, 0), Some(0));
+        assert_eq!(hamming_within(a, b"AAAA", 5), None);
+    }
+
+    #[test]
+    fn test_packed_hamming() {
+        let a = pack_2bit(b"ACGTACGTACGT").unwrap();
+        let b = pack_2bit(b"ACGAACGTACTT").unwrap();
+        assert_eq!(hamming_2bit(a, b), 2);
+        assert_eq!(hamming_2bit(a, a), 0);
+        // Every pair of distinct bases differs in at least one bit
+        for (x, y) in [(b"A", b"C"), (b"A", b"G"), (b"A", b"T"), (b"C", b"G"), (b"C", b"T")] {
+            assert_eq!(hamming_2bit(pack_2bit(x).unwrap(), pack_2bit(y).unwrap()), 1);
+        }
+        assert_eq!(unpack_2bit(a, 12), "ACGTACGTACGT");
+        assert_eq!(pack_2bit(b"ACGN"), None);
+        assert_eq!(pack_2bit(&[b'A'; 33]), None);
+        assert!(pack_2bit(&[b'T'; 32]).is_some());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance_within(b"ACGTACGT", b"ACGTACGT", 0), Some(0));
+        // One deletion, one insertion, one substitution
+        assert_eq!(edit_distance_within(b"ACGTACGT", b"ACGACGT", 1), Some(1));
+        assert_eq!(edit_distance_within(b"ACGTACGT", b"ACGTTACGT", 1), Some(1));
+        assert_eq!(edit_distance_within(b"ACGTACGT", b"ACCTACGT", 1), Some(1));
+        assert_eq!(edit_distance_within(b"ACGTACGT", b"CGTACGTA", 2), Some(2));
+        assert_eq!(edit_distance_within(b"ACGTACGT", b"CGTACGTA", 1), None);
+        assert_eq!(edit_distance_within(b"ACGT", b"ACGTAA", 1), None);
+        assert_eq!(reverse_complement(b"AACGTN"), b"NACGTT");
+    }
+}
@@ -0,0 +1,517 @@
+//! Synthetic single-cell read simulator
+//!
+//! Generates paired reads (R1 = barcode + UMI, R2 = cDNA) from known cells,
+//! molecules, and transcripts, with sequencing errors, PCR duplicates, ambient
+//! RNA, and empty droplets, together with the ground-truth count matrices.
+//! Unlike [`validation::synthetic`](crate::validation::synthetic), R2 carries
+//! real transcript sequence, so the reads can run through extract, index,
+//! count, and QC end to end.
+
+use std::io::Write;
+use std::path::Path;
+
+use ahash::{AHashMap, AHashSet};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_distr::{LogNormal, Poisson};
+use serde::{Deserialize, Serialize};
+
+use crate::atomic::AtomicFile;
+use crate::count::CountMatrix;
+use crate::fastq::FastqRecord;
+use crate::{Error, Result};
+
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+/// Quality of correctly called bases (Q37)
+const QUAL_OK: u8 = b'F';
+/// Quality of substituted bases (Q12)
+const QUAL_ERROR: u8 = b'-';
+/// Marker genes per cell type
+const MARKERS_PER_TYPE: usize = 10;
+/// Expression fold change of marker genes
+const MARKER_FOLD: f64 = 8.0;
+
+/// Simulation settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimConfig {
+    /// Number of real cells
+    pub n_cells: usize,
+    /// Number of empty droplets holding only ambient RNA
+    pub n_empty: usize,
+    /// Number of genes (one transcript each)
+    pub n_genes: usize,
+    /// Number of cell types, each with its own marker genes
+    pub n_cell_types: usize,
+    /// Mean UMIs per cell
+    pub mean_umis: f64,
+    /// Log-normal sigma of UMIs per cell
+    pub umi_sigma: f64,
+    /// Mean UMIs per empty droplet
+    pub empty_umis: f64,
+    /// Fraction of each cell's molecules drawn from the ambient pool
+    pub ambient_fraction: f64,
+    /// Mean reads (PCR duplicates) per molecule, at least 1
+    pub reads_per_umi: f64,
+    /// Per-base substitution rate in barcodes
+    pub barcode_error_rate: f64,
+    /// Per-base substitution rate in UMIs
+    pub umi_error_rate: f64,
+    /// Per-base substitution rate in cDNA reads
+    pub cdna_error_rate: f64,
+    /// Barcode length
+    pub barcode_len: usize,
+    /// UMI length
+    pub umi_len: usize,
+    /// R2 (cDNA) read length
+    pub read_len: usize,
+    /// Transcript length
+    pub transcript_len: usize,
+    /// Whitelist barcodes that never appear in reads
+    pub whitelist_decoys: usize,
+    /// Random seed
+    pub seed: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            n_cells: 100,
+            n_empty: 500,
+            n_genes: 500,
+            n_cell_types: 3,
+            mean_umis: 1000.0,
+            umi_sigma: 0.5,
+            empty_umis: 5.0,
+            ambient_fraction: 0.05,
+            reads_per_umi: 2.0,
+            barcode_error_rate: 0.005,
+            umi_error_rate: 0.005,
+            cdna_error_rate: 0.002,
+            barcode_len: 16,
+            umi_len: 12,
+            read_len: 90,
+            transcript_len: 1000,
+            whitelist_decoys: 1000,
+            seed: crate::seed::DEFAULT_SEED,
+        }
+    }
+}
+
+impl SimConfig {
+    /// Check that the settings describe a possible simulation
+    pub fn validate(&self) -> Result<()> {
+        let fail = |msg: String| Err(Error::Config(msg));
+        if self.n_cells == 0 || self.n_genes == 0 || self.n_cell_types == 0 {
+            return fail("cells, genes, and cell types must all be positive".into());
+        }
+        if self.barcode_len == 0 || self.umi_len == 0 || self.read_len == 0 {
+            return fail("barcode, UMI, and read lengths must be positive".into());
+        }
+        if self.read_len > self.transcript_len {
+            return fail(format!(
+                "read length {} exceeds transcript length {}",
+                self.read_len, self.transcript_len
+            ));
+        }
+        for (name, rate) in [
+            ("ambient fraction", self.ambient_fraction),
+            ("barcode error rate", self.barcode_error_rate),
+            ("UMI error rate", self.umi_error_rate),
+            ("cDNA error rate", self.cdna_error_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                return fail(format!("{} must be in [0, 1], got {}", name, rate));
+            }
+        }
+        if self.mean_umis < 1.0 || self.umi_sigma < 0.0 || self.empty_umis < 0.0 {
+            return fail("UMI means must be at least 1 (0 for empty droplets)".into());
+        }
+        if self.reads_per_umi < 1.0 {
+            return fail(format!("reads per UMI must be at least 1, got {}", self.reads_per_umi));
+        }
+        Ok(())
+    }
+}
+
+/// Counts of what was simulated
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SimStats {
+    pub cells: usize,
+    pub empty_droplets: usize,
+    /// Distinct molecules, ambient included
+    pub molecules: u64,
+    /// Molecules from the ambient pool (in cells and empty droplets)
+    pub ambient_molecules: u64,
+    /// Read pairs written
+    pub reads: u64,
+    /// Read pairs with at least one barcode substitution
+    pub barcode_error_reads: u64,
+    /// Read pairs with at least one UMI substitution
+    pub umi_error_reads: u64,
+}
+
+/// Ground truth of a simulation
+#[derive(Debug, Clone)]
+pub struct SimTruth {
+    /// Settings used
+    pub config: SimConfig,
+    /// Barcode whitelist: every droplet barcode plus decoys, shuffled
+    pub whitelist: Vec<String>,
+    /// Barcodes of real cells
+    pub cells: Vec<String>,
+    /// Cell type of each real cell
+    pub cell_types: Vec<usize>,
+    /// Gene names
+    pub genes: Vec<String>,
+    /// Transcript sequence of each gene
+    pub transcripts: Vec<Vec<u8>>,
+    /// Molecules per gene and droplet (cells first, then empty droplets)
+    pub matrix: CountMatrix,
+    /// Molecules per gene and cell, excluding ambient RNA
+    pub endogenous: CountMatrix,
+    pub stats: SimStats,
+}
+
+/// Random DNA sequence
+fn random_seq(rng: &mut StdRng, len: usize) -> Vec<u8> {
+    (0..len).map(|_| BASES[rng.gen_range(0..4)]).collect()
+}
+
+/// Substitute each base with probability `rate`; returns the qualities and
+/// whether any base changed
+fn add_errors(rng: &mut StdRng, seq: &mut [u8], rate: f64) -> (Vec<u8>, bool) {
+    let mut qual = vec![QUAL_OK; seq.len()];
+    let mut changed = false;
+    if rate > 0.0 {
+        for (base, q) in seq.iter_mut().zip(qual.iter_mut()) {
+            if rng.gen_bool(rate) {
+                let offset = rng.gen_range(1..4);
+                let idx = BASES.iter().position(|b| b == base).unwrap_or(0);
+                *base = BASES[(idx + offset) % 4];
+                *q = QUAL_ERROR;
+                changed = true;
+            }
+        }
+    }
+    (qual, changed)
+}
+
+/// Sample from Poisson(`mean`), treating a zero mean as always 0
+fn poisson(rng: &mut StdRng, mean: f64) -> u64 {
+    match Poisson::new(mean) {
+        Ok(dist) => Distribution::<f64>::sample(&dist, rng) as u64,
+        Err(_) => 0,
+    }
+}
+
+/// Gene x droplet matrix from (droplet, gene) molecule counts
+fn to_matrix(
+    counts: AHashMap<(usize, usize), u32>,
+    barcodes: Vec<String>,
+    genes: &[String],
+) -> CountMatrix {
+    let mut entries: Vec<_> = counts.into_iter().collect();
+    entries.sort_unstable();
+    let mut matrix = CountMatrix {
+        n_rows: genes.len(),
+        n_cols: barcodes.len(),
+        barcodes,
+        genes: genes.to_vec(),
+        ..CountMatrix::new()
+    };
+    for ((droplet, gene), n) in entries {
+        matrix.rows.push(gene);
+        matrix.cols.push(droplet);
+        matrix.values.push(n);
+    }
+    matrix
+}
+
+/// Run a simulation, passing each (R1, R2) read pair to `emit`.
+///
+/// Read names are `sim<N>:<barcode>:<umi>:<gene>` with the true (error-free)
+/// barcode and UMI. Molecules within a droplet have distinct UMIs per gene,
+/// so perfect barcode correction and UMI deduplication recover
+/// [`SimTruth::matrix`] exactly.
+pub fn simulate<F>(config: &SimConfig, mut emit: F) -> Result<SimTruth>
+where
+    F: FnMut(FastqRecord, FastqRecord) -> Result<()>,
+{
+    config.validate()?;
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let n_droplets = config.n_cells + config.n_empty;
+
+    let mut seen = AHashSet::new();
+    let mut barcodes = Vec::with_capacity(n_droplets + config.whitelist_decoys);
+    while barcodes.len() < n_droplets + config.whitelist_decoys {
+        let barcode = String::from_utf8(random_seq(&mut rng, config.barcode_len))
+            .expect("bases are ASCII");
+        if seen.insert(barcode.clone()) {
+            barcodes.push(barcode);
+        }
+    }
+    let genes: Vec<String> = (1..=config.n_genes).map(|i| format!("GENE{:05}", i)).collect();
+    let transcripts: Vec<Vec<u8>> = (0..config.n_genes)
+        .map(|_| random_seq(&mut rng, config.transcript_len))
+        .collect();
+
+    // Log-normal gene abundances, with a block of marker genes per cell type
+    let abundance = LogNormal::new(0.0, 1.0).map_err(|e| Error::Config(e.to_string()))?;
+    let base: Vec<f64> = (0..config.n_genes).map(|_| abundance.sample(&mut rng)).collect();
+    let markers = MARKERS_PER_TYPE.min(config.n_genes / config.n_cell_types);
+    let profiles: Vec<Vec<f64>> = (0..config.n_cell_types)
+        .map(|t| {
+            let mut profile = base.clone();
+            for weight in &mut profile[t * markers..(t + 1) * markers] {
+                *weight *= MARKER_FOLD;
+            }
+            let total: f64 = profile.iter().sum();
+            profile.iter().map(|w| w / total).collect()
+        })
+        .collect();
+    let cell_types: Vec<usize> = (0..config.n_cells).map(|i| i % config.n_cell_types).collect();
+
+    // The ambient pool mixes the cell profiles in proportion to their cells
+    let mut ambient = vec![0.0; config.n_genes];
+    for &t in &cell_types {
+        for (a, p) in ambient.iter_mut().zip(&profiles[t]) {
+            *a += p;
+        }
+    }
+    let weighted = |weights: &[f64]| {
+        WeightedIndex::new(weights).map_err(|e| Error::Config(e.to_string()))
+    };
+    let samplers = profiles.iter().map(|p| weighted(p.as_slice())).collect::<Result<Vec<_>>>()?;
+    let ambient_sampler = weighted(&ambient)?;
+
+    let sigma = config.umi_sigma;
+    let cell_umis = LogNormal::new(config.mean_umis.ln() - sigma * sigma / 2.0, sigma)
+        .map_err(|e| Error::Config(e.to_string()))?;
+
+    let mut counts: AHashMap<(usize, usize), u32> = AHashMap::new();
+    let mut endogenous: AHashMap<(usize, usize), u32> = AHashMap::new();
+    let mut stats = SimStats {
+        cells: config.n_cells,
+        empty_droplets: config.n_empty,
+        ..Default::default()
+    };
+    for (droplet, barcode) in barcodes[..n_droplets].iter().enumerate() {
+        let is_cell = droplet < config.n_cells;
+        let n_umis = if is_cell {
+            (cell_umis.sample(&mut rng).round() as u64).max(1)
+        } else {
+            poisson(&mut rng, config.empty_umis)
+        };
+        let mut umis: AHashSet<(usize, Vec<u8>)> = AHashSet::new();
+        for _ in 0..n_umis {
+            let from_ambient = !is_cell || rng.gen_bool(config.ambient_fraction);
+            let gene = if from_ambient {
+                ambient_sampler.sample(&mut rng)
+            } else {
+                samplers[cell_types[droplet]].sample(&mut rng)
+            };
+            let umi = loop {
+                let umi = random_seq(&mut rng, config.umi_len);
+                if umis.insert((gene, umi.clone())) {
+                    break umi;
+                }
+            };
+
+            stats.molecules += 1;
+            *counts.entry((droplet, gene)).or_default() += 1;
+            if from_ambient {
+                stats.ambient_molecules += 1;
+            } else {
+                *endogenous.entry((droplet, gene)).or_default() += 1;
+            }
+
+            let start = rng.gen_range(0..=config.transcript_len - config.read_len);
+            let cdna = &transcripts[gene][start..start + config.read_len];
+            let n_reads = 1 + poisson(&mut rng, config.reads_per_umi - 1.0);
+            for _ in 0..n_reads {
+                let mut bc_seq = barcode.as_bytes().to_vec();
+                let (bc_qual, bc_error) =
+                    add_errors(&mut rng, &mut bc_seq, config.barcode_error_rate);
+                let mut umi_seq = umi.clone();
+                let (umi_qual, umi_error) =
+                    add_errors(&mut rng, &mut umi_seq, config.umi_error_rate);
+                let mut r2_seq = cdna.to_vec();
+                let (r2_qual, _) = add_errors(&mut rng, &mut r2_seq, config.cdna_error_rate);
+
+                let id = format!(
+                    "sim{}:{}:{}:{}",
+                    stats.reads,
+                    barcode,
+                    String::from_utf8_lossy(&umi),
+                    genes[gene]
+                );
+                bc_seq.extend_from_slice(&umi_seq);
+                let mut r1_qual = bc_qual;
+                r1_qual.extend_from_slice(&umi_qual);
+                emit(
+                    FastqRecord::new(id.clone(), bc_seq, r1_qual),
+                    FastqRecord::new(id, r2_seq, r2_qual),
+                )?;
+
+                stats.reads += 1;
+                stats.barcode_error_reads += bc_error as u64;
+                stats.umi_error_reads += umi_error as u64;
+            }
+        }
+    }
+
+    let cells = barcodes[..config.n_cells].to_vec();
+    let matrix = to_matrix(counts, barcodes[..n_droplets].to_vec(), &genes);
+    let endogenous = to_matrix(endogenous, cells.clone(), &genes);
+    // Shuffle so the whitelist order reveals nothing about which barcodes are cells
+    let mut whitelist = barcodes;
+    whitelist.shuffle(&mut rng);
+
+    Ok(SimTruth {
+        config: config.clone(),
+        whitelist,
+        cells,
+        cell_types,
+        genes,
+        transcripts,
+        matrix,
+        endogenous,
+        stats,
+    })
+}
+
+impl SimTruth {
+    /// Write the whitelist, transcriptome, and truth files to `dir`:
+    /// `whitelist.txt`, `transcripts.fa` (Ensembl-style headers for
+    /// `sparc index`), `truth/` (all molecules), `truth/endogenous/` (cells
+    /// without ambient RNA), `truth/cells.tsv`, and `simulation.json`
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let truth_dir = dir.join("truth");
+        let endogenous_dir = truth_dir.join("endogenous");
+        std::fs::create_dir_all(&endogenous_dir)?;
+
+        let mut writer = AtomicFile::create(dir.join("whitelist.txt"))?;
+        for barcode in &self.whitelist {
+            writeln!(writer, "{}", barcode)?;
+        }
+        writer.commit()?;
+
+        let mut writer = AtomicFile::create(dir.join("transcripts.fa"))?;
+        for (gene, seq) in self.genes.iter().zip(&self.transcripts) {
+            writeln!(writer, ">{}-T1 cdna gene:{} gene_symbol:{}", gene, gene, gene)?;
+            for line in seq.chunks(60) {
+                writer.write_all(line)?;
+                writeln!(writer)?;
+            }
+        }
+        writer.commit()?;
+
+        for (matrix, out) in [(&self.matrix, &truth_dir), (&self.endogenous, &endogenous_dir)] {
+            matrix.write_mtx(out.join("matrix.mtx"))?;
+            matrix.write_barcodes(out.join("barcodes.tsv"))?;
+            matrix.write_genes(out.join("genes.tsv"))?;
+        }
+
+        let mut writer = AtomicFile::create(truth_dir.join("cells.tsv"))?;
+        for (barcode, cell_type) in self.cells.iter().zip(&self.cell_types) {
+            writeln!(writer, "{}\ttype{}", barcode, cell_type)?;
+        }
+        writer.commit()?;
+
+        let summary = serde_json::json!({ "config": self.config, "stats": self.stats });
+        crate::atomic::write(dir.join("simulation.json"), serde_json::to_string_pretty(&summary)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_config() -> SimConfig {
+        SimConfig {
+            n_cells: 6,
+            n_empty: 10,
+            n_genes: 40,
+            mean_umis: 50.0,
+            whitelist_decoys: 20,
+            read_len: 30,
+            transcript_len: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_simulate_matches_truth() {
+        let config = small_config();
+        let mut pairs = Vec::new();
+        let truth = simulate(&config, |r1, r2| {
+            pairs.push((r1, r2));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(truth.whitelist.len(), 36);
+        assert_eq!(truth.matrix.n_cols, 16);
+        assert_eq!(truth.endogenous.n_cols, 6);
+        assert_eq!(pairs.len() as u64, truth.stats.reads);
+
+        // Distinct (barcode, UMI, gene) in read names reproduce the truth matrix
+        let mut molecules: AHashSet<(String, String, String)> = AHashSet::new();
+        for (r1, r2) in &pairs {
+            assert_eq!(r1.seq.len(), config.barcode_len + config.umi_len);
+            assert_eq!(r2.seq.len(), config.read_len);
+            let fields: Vec<&str> = r1.id.split(':').collect();
+            molecules.insert((fields[1].into(), fields[2].into(), fields[3].into()));
+        }
+        assert_eq!(molecules.len() as u64, truth.stats.molecules);
+        let total: u32 = truth.matrix.values.iter().sum();
+        assert_eq!(total as u64, truth.stats.molecules);
+        let endogenous: u32 = truth.endogenous.values.iter().sum();
+        assert_eq!((total - endogenous) as u64, truth.stats.ambient_molecules);
+    }
+
+    #[test]
+    fn test_simulate_deterministic_and_error_free() {
+        let config = SimConfig {
+            barcode_error_rate: 0.0,
+            umi_error_rate: 0.0,
+            cdna_error_rate: 0.0,
+            ..small_config()
+        };
+        let mut first = Vec::new();
+        let truth = simulate(&config, |r1, r2| {
+            first.push((r1.id, r1.seq, r2.seq));
+            Ok(())
+        })
+        .unwrap();
+        let mut second = Vec::new();
+        simulate(&config, |r1, r2| {
+            second.push((r1.id, r1.seq, r2.seq));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(truth.stats.barcode_error_reads, 0);
+
+        for (id, r1, r2) in &first {
+            let fields: Vec<&str> = id.split(':').collect();
+            assert_eq!(&r1[..16], fields[1].as_bytes());
+            assert_eq!(&r1[16..], fields[2].as_bytes());
+            let gene = truth.genes.iter().position(|g| g == fields[3]).unwrap();
+            let transcript = &truth.transcripts[gene];
+            assert!(transcript.windows(r2.len()).any(|w| w == r2.as_slice()));
+        }
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = SimConfig {
+            read_len: 2000,
+            ..Default::default()
+        };
+        assert!(simulate(&config, |_, _| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Visium spot coordinates and image metadata
+//!
+//! Reads spot whitelists with array (and optionally pixel) coordinates plus a
+//! `scalefactors_json.json`, matches spots to matrix barcodes, and writes a
+//! Space Ranger style `spatial/` folder that Squidpy (`read_visium`) and Seurat
+//! (`Load10X_Spatial`) can load next to the count matrix.
+
+use crate::atomic::AtomicFile;
+use crate::{Error, Result};
+use ahash::AHashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Visium spot centre-to-centre distance over spot diameter (100 µm / 55 µm)
+const SPOT_PITCH_PER_DIAMETER: f64 = 100.0 / 55.0;
+
+/// Image scale factors (Space Ranger `scalefactors_json.json`)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ScaleFactors {
+    /// Spot diameter in full-resolution pixels
+    pub spot_diameter_fullres: f64,
+    /// Full-resolution to high-resolution image scale
+    pub tissue_hires_scalef: f64,
+    /// Full-resolution to low-resolution image scale
+    pub tissue_lowres_scalef: f64,
+    /// Fiducial diameter in full-resolution pixels
+    #[serde(default)]
+    pub fiducial_diameter_fullres: f64,
+}
+
+impl ScaleFactors {
+    /// Load from a `scalefactors_json.json` file
+    pub fn from_json<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+            Error::Config(format!("Invalid scale factors {:?}: {}", path.as_ref(), e))
+        })
+    }
+
+    /// Write as `scalefactors_json.json`
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::Config(format!("Failed to serialize scale factors: {}", e)))?;
+        crate::atomic::write(path, json)
+    }
+}
+
+/// One capture spot
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Spot {
+    /// Spot barcode as listed in the whitelist
+    pub barcode: String,
+    /// Whether the spot lies under tissue, when the whitelist says
+    pub in_tissue: Option<bool>,
+    /// Array row (0-based)
+    pub array_row: u32,
+    /// Array column (0-based)
+    pub array_col: u32,
+    /// Full-resolution pixel row and column of the spot centre, when known
+    pub pixel: Option<(f64, f64)>,
+}
+
+impl Spot {
+    /// Pixel row and column, falling back to the ideal hexagonal Visium grid
+    /// (columns are interleaved, so neighbouring spots in a row are 2 apart)
+    pub fn pixel_or_grid(&self, scale: &ScaleFactors) -> (f64, f64) {
+        self.pixel.unwrap_or_else(|| {
+            let pitch = scale.spot_diameter_fullres * SPOT_PITCH_PER_DIAMETER;
+            (
+                self.array_row as f64 * pitch * 3f64.sqrt() / 2.0,
+                self.array_col as f64 * pitch / 2.0,
+            )
+        })
+    }
+}
+
+/// Barcode without a trailing `-<number>` GEM group suffix
+fn core_barcode(barcode: &str) -> &str {
+    match barcode.rsplit_once('-') {
+        Some((core, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => core,
+        _ => barcode,
+    }
+}
+
+/// Spot whitelist indexed by barcode
+#[derive(Debug, Clone, Default)]
+pub struct SpotTable {
+    spots: Vec<Spot>,
+    /// Core barcode -> spot index
+    index: AHashMap<String, usize>,
+}
+
+impl SpotTable {
+    /// Load a spot whitelist.
+    ///
+    /// Accepts Space Ranger `tissue_positions.csv` / `tissue_positions_list.csv`
+    /// (`barcode,in_tissue,array_row,array_col,pxl_row_in_fullres,pxl_col_in_fullres`,
+    /// header optional) or a Visium coordinates whitelist (`barcode col row`,
+    /// whitespace-separated, 1-based).
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let table = Self::from_reader(BufReader::new(File::open(path.as_ref())?))?;
+        log::info!("Loaded {} spots from {:?}", table.len(), path.as_ref());
+        Ok(table)
+    }
+
+    /// Parse a spot whitelist; see [`SpotTable::from_file`]
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self> {
+        let mut table = Self::default();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("barcode") {
+                continue;
+            }
+            let fields: Vec<&str> = if line.contains(',') {
+                line.split(',').map(str::trim).collect()
+            } else {
+                line.split_whitespace().collect()
+            };
+            let invalid = |what: &str| {
+                Error::Barcode(format!("Spot whitelist line {}: invalid {}", i + 1, what))
+            };
+            let int = |s: &str, what: &str| s.parse::<u32>().map_err(|_| invalid(what));
+            let float = |s: &str, what: &str| s.parse::<f64>().map_err(|_| invalid(what));
+
+            let spot = match fields[..] {
+                [barcode, in_tissue, row, col, pxl_row, pxl_col] => Spot {
+                    barcode: barcode.to_string(),
+                    in_tissue: Some(int(in_tissue, "in_tissue")? != 0),
+                    array_row: int(row, "array_row")?,
+                    array_col: int(col, "array_col")?,
+                    pixel: Some((float(pxl_row, "pixel row")?, float(pxl_col, "pixel column")?)),
+                },
+                [barcode, col, row] => Spot {
+                    barcode: barcode.to_string(),
+                    in_tissue: None,
+                    array_row: int(row, "row")?.checked_sub(1).ok_or_else(|| invalid("row"))?,
+                    array_col: int(col, "col")?.checked_sub(1).ok_or_else(|| invalid("col"))?,
+                    pixel: None,
+                },
+                _ => {
+                    return Err(Error::Barcode(format!(
+                        "Spot whitelist line {}: expected 6 comma-separated or 3 \
+                         whitespace-separated columns, got {}",
+                        i + 1,
+                        fields.len()
+                    )))
+                }
+            };
+            let core = core_barcode(&spot.barcode).to_string();
+            if table.index.insert(core, table.spots.len()).is_some() {
+                return Err(Error::Barcode(format!(
+                    "Spot whitelist line {}: duplicate barcode {}",
+                    i + 1,
+                    spot.barcode
+                )));
+            }
+            table.spots.push(spot);
+        }
+        Ok(table)
+    }
+
+    /// Number of spots
+    pub fn len(&self) -> usize {
+        self.spots.len()
+    }
+
+    /// Whether the whitelist is empty
+    pub fn is_empty(&self) -> bool {
+        self.spots.is_empty()
+    }
+
+    /// All spots, in whitelist order
+    pub fn spots(&self) -> &[Spot] {
+        &self.spots
+    }
+
+    /// Spot for a barcode, ignoring any `-1` style suffix on either side
+    pub fn get(&self, barcode: &str) -> Option<&Spot> {
+        self.index.get(core_barcode(barcode)).map(|&i| &self.spots[i])
+    }
+
+    /// Spot index of each matrix barcode (`None` for barcodes not on the slide)
+    pub fn attach(&self, barcodes: &[String]) -> Vec<Option<usize>> {
+        barcodes
+            .iter()
+            .map(|b| self.index.get(core_barcode(b)).copied())
+            .collect()
+    }
+
+    /// Write a `spatial/` folder for the matrix with `barcodes`:
+    /// `tissue_positions.csv` (with header), `tissue_positions_list.csv` (legacy,
+    /// no header), and `scalefactors_json.json`.
+    ///
+    /// Every whitelist spot is listed. Spots in the matrix use the matrix
+    /// barcode (keeping its suffix) so the files join on `barcodes.tsv`. When
+    /// the whitelist has no tissue flags, spots present in the matrix count as
+    /// in tissue. Returns the number of matrix barcodes placed on the slide.
+    pub fn write_spatial_dir<P: AsRef<Path>>(
+        &self,
+        dir: P,
+        barcodes: &[String],
+        scale: &ScaleFactors,
+    ) -> Result<usize> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut matrix_barcode: Vec<Option<&str>> = vec![None; self.spots.len()];
+        for (barcode, spot) in barcodes.iter().zip(self.attach(barcodes)) {
+            if let Some(i) = spot {
+                matrix_barcode[i] = Some(barcode);
+            }
+        }
+
+        let mut positions = AtomicFile::create(dir.join("tissue_positions.csv"))?;
+        let mut legacy = AtomicFile::create(dir.join("tissue_positions_list.csv"))?;
+        writeln!(
+            positions,
+            "barcode,in_tissue,array_row,array_col,pxl_row_in_fullres,pxl_col_in_fullres"
+        )?;
+        for (spot, in_matrix) in self.spots.iter().zip(&matrix_barcode) {
+            let (pxl_row, pxl_col) = spot.pixel_or_grid(scale);
+            let line = format!(
+                "{},{},{},{},{},{}",
+                in_matrix.unwrap_or(&spot.barcode),
+                spot.in_tissue.unwrap_or(in_matrix.is_some()) as u8,
+                spot.array_row,
+                spot.array_col,
+                pxl_row.round() as i64,
+                pxl_col.round() as i64
+            );
+            writeln!(positions, "{}", line)?;
+            writeln!(legacy, "{}", line)?;
+        }
+        positions.commit()?;
+        legacy.commit()?;
+
+        scale.write_json(dir.join("scalefactors_json.json"))?;
+        Ok(matrix_barcode.iter().filter(|b| b.is_some()).count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scale() -> ScaleFactors {
+        ScaleFactors {
+            spot_diameter_fullres: 55.0,
+            tissue_hires_scalef: 0.2,
+            tissue_lowres_scalef: 0.05,
+            fiducial_diameter_fullres: 85.0,
+        }
+    }
+
+    #[test]
+    fn test_parse_tissue_positions() {
+        let csv = "barcode,in_tissue,array_row,array_col,pxl_row_in_fullres,pxl_col_in_fullres\n\
+                   AAAC-1,1,0,16,1200,3400\n\
+                   AAAG-1,0,1,17,1290.5,3450\n";
+        let table = SpotTable::from_reader(csv.as_bytes()).unwrap();
+        assert_eq!(table.len(), 2);
+        let spot = table.get("AAAC").unwrap();
+        assert_eq!((spot.in_tissue, spot.array_col), (Some(true), 16));
+        assert_eq!(table.get("AAAG-1").unwrap().pixel, Some((1290.5, 3450.0)));
+        assert!(table.get("TTTT-1").is_none());
+    }
+
+    #[test]
+    fn test_coordinates_whitelist_uses_grid() {
+        let table = SpotTable::from_reader("AAAC 17 1\nAAAG 18 2\n".as_bytes()).unwrap();
+        let spot = table.get("AAAG-1").unwrap();
+        assert_eq!((spot.array_row, spot.array_col), (1, 17));
+        // 100 µm pitch = 100 px at 55 px per spot: rows 86.6 px apart, columns 50
+        let (row, col) = spot.pixel_or_grid(&scale());
+        assert!((row - 86.6025).abs() < 1e-3);
+        assert!((col - 850.0).abs() < 1e-9);
+        assert!(SpotTable::from_reader("AAAC 0 1\n".as_bytes()).is_err());
+        assert!(SpotTable::from_reader("AAAC 1 1\nAAAC 2 2\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_write_spatial_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = SpotTable::from_reader("AAAC 1 1\nAAAG 3 1\nAAAT 2 2\n".as_bytes()).unwrap();
+        let barcodes = vec!["AAAG-1".to_string(), "CCCC-1".to_string()];
+        let placed = table.write_spatial_dir(dir.path(), &barcodes, &scale()).unwrap();
+        assert_eq!(placed, 1);
+
+        let positions = std::fs::read_to_string(dir.path().join("tissue_positions.csv")).unwrap();
+        let lines: Vec<&str> = positions.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "AAAC,0,0,0,0,0");
+        assert_eq!(lines[2], "AAAG-1,1,0,2,0,100");
+        let legacy =
+            std::fs::read_to_string(dir.path().join("tissue_positions_list.csv")).unwrap();
+        assert_eq!(legacy.lines().count(), 3);
+
+        let read = ScaleFactors::from_json(dir.path().join("scalefactors_json.json")).unwrap();
+        assert_eq!(read, scale());
+    }
+}
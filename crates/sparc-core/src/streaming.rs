@@ -2,7 +2,10 @@
 
 use crate::fastq::{FastqParser, FastqRecord};
 use crate::Result;
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::sync::mpsc::sync_channel;
+use std::sync::{Arc, Mutex};
 
 /// Configuration for streaming processing
 pub struct StreamConfig {
@@ -96,3 +99,225 @@ impl StreamingProcessor {
         Ok(results)
     }
 }
+
+/// Bounded-memory, order-preserving parallel pipeline over a record stream
+///
+/// A reader thread cuts the input into chunks, worker threads process them
+/// with per-worker state, and the caller consumes results in input order. At
+/// most `max_in_flight` chunks are alive at once (queued, being processed, or
+/// waiting to be consumed), so memory does not grow with input size.
+#[derive(Debug, Clone)]
+pub struct ChunkPipeline {
+    /// Records per chunk
+    pub chunk_size: usize,
+    /// Worker threads
+    pub threads: usize,
+    /// Maximum chunks alive at once
+    pub max_in_flight: usize,
+}
+
+impl ChunkPipeline {
+    /// Pipeline with `threads` workers, 4096-record chunks, and four chunks in
+    /// flight per worker
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        Self {
+            chunk_size: 4096,
+            threads,
+            max_in_flight: threads * 4,
+        }
+    }
+
+    /// Set the number of records per chunk
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Run `work` over chunks of `items`, passing each chunk's result to `sink`
+    /// in input order.
+    ///
+    /// Every worker starts from `init()`; the final worker states are returned
+    /// so per-worker statistics can be merged. The first error, from the input,
+    /// a worker, or the sink, stops the pipeline and is returned.
+    pub fn run<T, R, S, I, F, W, K>(
+        &self,
+        items: I,
+        init: F,
+        work: W,
+        mut sink: K,
+    ) -> Result<(StreamStats, Vec<S>)>
+    where
+        I: Iterator<Item = Result<T>> + Send,
+        T: Send,
+        R: Send,
+        S: Send,
+        F: Fn() -> S + Sync,
+        W: Fn(&mut S, Vec<T>) -> Result<R> + Sync,
+        K: FnMut(R) -> Result<()>,
+    {
+        let threads = self.threads.max(1);
+        let chunk_size = self.chunk_size.max(1);
+        let in_flight = self.max_in_flight.max(threads);
+
+        // The reader takes a token per chunk; the sink returns it once the
+        // chunk's result has been consumed
+        let (token_tx, token_rx) = sync_channel::<()>(in_flight);
+        for _ in 0..in_flight {
+            token_tx.send(()).expect("token channel has capacity");
+        }
+        let (chunk_tx, chunk_rx) = sync_channel::<(u64, Result<Vec<T>>)>(threads);
+        let chunk_rx = Arc::new(Mutex::new(chunk_rx));
+        let (result_tx, result_rx) = sync_channel::<(u64, Result<R>)>(in_flight);
+
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(move || {
+                let mut items = items;
+                let mut records = 0u64;
+                let mut index = 0u64;
+                while token_rx.recv().is_ok() {
+                    let chunk: Result<Vec<T>> = items.by_ref().take(chunk_size).collect();
+                    let last = chunk.as_ref().map_or(true, |c| c.len() < chunk_size);
+                    match &chunk {
+                        Ok(c) if c.is_empty() => break,
+                        Ok(c) => records += c.len() as u64,
+                        Err(_) => {}
+                    }
+                    if chunk_tx.send((index, chunk)).is_err() {
+                        break;
+                    }
+                    index += 1;
+                    if last {
+                        break;
+                    }
+                }
+                records
+            });
+
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    let chunk_rx = Arc::clone(&chunk_rx);
+                    let result_tx = result_tx.clone();
+                    let (init, work) = (&init, &work);
+                    scope.spawn(move || {
+                        let mut state = init();
+                        loop {
+                            let next = chunk_rx.lock().expect("chunk queue poisoned").recv();
+                            let Ok((index, chunk)) = next else { break };
+                            let result = chunk.and_then(|c| work(&mut state, c));
+                            if result_tx.send((index, result)).is_err() {
+                                break;
+                            }
+                        }
+                        state
+                    })
+                })
+                .collect();
+            // Workers hold the only remaining handles, so both channels close
+            // once the workers exit
+            drop(chunk_rx);
+            drop(result_tx);
+
+            let mut chunks = 0u64;
+            let drained = (|| -> Result<()> {
+                let mut pending = BTreeMap::new();
+                for (index, result) in result_rx {
+                    pending.insert(index, result);
+                    while let Some(result) = pending.remove(&chunks) {
+                        sink(result?)?;
+                        chunks += 1;
+                        let _ = token_tx.send(());
+                    }
+                }
+                Ok(())
+            })();
+            // Unblocks the reader if the sink stopped early
+            drop(token_tx);
+
+            let total_records = reader.join().expect("pipeline reader panicked");
+            let states = workers
+                .into_iter()
+                .map(|w| w.join().expect("pipeline worker panicked"))
+                .collect();
+            drained.map(|()| {
+                let stats = StreamStats {
+                    total_records,
+                    chunks_processed: chunks,
+                };
+                (stats, states)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_chunk_pipeline_preserves_order() {
+        let items = (0..10_000u64).map(Ok);
+        let mut seen = Vec::new();
+        let (stats, states) = ChunkPipeline::new(4)
+            .chunk_size(7)
+            .run(
+                items,
+                || 0u64,
+                |sum, chunk| {
+                    *sum += chunk.iter().sum::<u64>();
+                    Ok(chunk.iter().map(|x| x * 2).collect::<Vec<_>>())
+                },
+                |doubled| {
+                    seen.extend(doubled);
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        assert_eq!(stats.total_records, 10_000);
+        assert_eq!(stats.chunks_processed, 10_000u64.div_ceil(7));
+        assert_eq!(states.len(), 4);
+        assert_eq!(states.iter().sum::<u64>(), (0..10_000u64).sum::<u64>());
+        assert!(seen.iter().copied().eq((0..10_000u64).map(|x| x * 2)));
+    }
+
+    #[test]
+    fn test_chunk_pipeline_errors() {
+        // An input error surfaces after the chunks before it
+        let items = (0..100u64).map(|i| {
+            if i == 50 {
+                Err(Error::FastqParse("bad record".to_string()))
+            } else {
+                Ok(i)
+            }
+        });
+        let mut consumed = 0;
+        let err = ChunkPipeline::new(3)
+            .chunk_size(10)
+            .run(
+                items,
+                || (),
+                |_, chunk| Ok(chunk.len()),
+                |n| {
+                    consumed += n;
+                    Ok(())
+                },
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("bad record"));
+        assert_eq!(consumed, 50);
+
+        // A failing sink stops the pipeline without deadlocking
+        let err = ChunkPipeline::new(2)
+            .chunk_size(1)
+            .run(
+                (0..1_000_000u64).map(Ok),
+                || (),
+                |_, chunk| Ok(chunk),
+                |_| Err(Error::Config("stop".to_string())),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("stop"));
+    }
+}
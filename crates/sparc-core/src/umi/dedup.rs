@@ -1,81 +1,95 @@
 //! UMI deduplication using directional adjacency method
 
 use super::{Umi, UmiGroup};
-use ahash::{AHashMap, AHashSet};
+use crate::seq_util::{hamming, hamming_2bit, pack_2bit};
+use crate::intern::Interner;
+use ahash::AHashMap;
 
 /// UMI graph for clustering
 pub struct UmiGraph {
-    /// Nodes: UMI -> count
-    nodes: AHashMap<String, u32>,
-    /// Edges: UMI -> connected UMIs
-    edges: AHashMap<String, Vec<String>>,
+    /// Node IDs by UMI sequence
+    umis: Interner,
+    /// Read count per node
+    counts: Vec<u32>,
+    /// Edges: node -> connected nodes
+    edges: Vec<Vec<u32>>,
 }
 
 impl UmiGraph {
     pub fn new() -> Self {
         Self {
-            nodes: AHashMap::new(),
-            edges: AHashMap::new(),
+            umis: Interner::new(),
+            counts: Vec::new(),
+            edges: Vec::new(),
         }
     }
 
     /// Add a UMI to the graph
     pub fn add_umi(&mut self, umi: &str, count: u32) {
-        *self.nodes.entry(umi.to_string()).or_insert(0) += count;
+        let id = self.umis.intern(umi) as usize;
+        if id == self.counts.len() {
+            self.counts.push(0);
+        }
+        self.counts[id] += count;
     }
 
     /// Build edges based on Hamming distance
+    ///
+    /// UMIs of one length made only of A/C/G/T (the usual case) are compared
+    /// 2-bit packed; anything else falls back to byte comparison. For
+    /// distances up to 2 in large groups, each UMI's neighbours are enumerated
+    /// and looked up instead of comparing every pair.
     pub fn build_edges(&mut self, max_distance: u32) {
-        let umis: Vec<String> = self.nodes.keys().cloned().collect();
-
-        for i in 0..umis.len() {
-            for j in (i + 1)..umis.len() {
-                if Self::hamming_distance(&umis[i], &umis[j]) <= max_distance {
-                    self.edges
-                        .entry(umis[i].clone())
-                        .or_default()
-                        .push(umis[j].clone());
-                    self.edges
-                        .entry(umis[j].clone())
-                        .or_default()
-                        .push(umis[i].clone());
-                }
+        let umis: Vec<&str> = self.umis.iter().collect();
+        let len = umis.first().map_or(0, |u| u.len());
+        let packed: Option<Vec<u64>> = if umis.iter().all(|u| u.len() == len) {
+            umis.iter().map(|u| pack_2bit(u.as_bytes())).collect()
+        } else {
+            None
+        };
+
+        let mut edges = vec![Vec::new(); umis.len()];
+        match packed {
+            Some(packed) if prefer_lookup(umis.len(), len, max_distance) => {
+                link_neighbors(&mut edges, &packed, len, max_distance)
             }
+            Some(packed) => link_pairs(
+                &mut edges,
+                |i, j| hamming_2bit(packed[i], packed[j]),
+                max_distance,
+            ),
+            None => link_pairs(
+                &mut edges,
+                |i, j| hamming(umis[i].as_bytes(), umis[j].as_bytes()),
+                max_distance,
+            ),
         }
-    }
-
-    fn hamming_distance(a: &str, b: &str) -> u32 {
-        if a.len() != b.len() {
-            return u32::MAX;
-        }
-        a.chars().zip(b.chars()).filter(|(a, b)| a != b).count() as u32
+        self.edges = edges;
     }
 
     /// Get connected components
     pub fn connected_components(&self) -> Vec<Vec<String>> {
-        let mut visited: AHashSet<String> = AHashSet::new();
+        let mut visited = vec![false; self.umis.len()];
         let mut components = Vec::new();
 
-        for umi in self.nodes.keys() {
-            if !visited.contains(umi) {
-                let mut component = Vec::new();
-                let mut stack = vec![umi.clone()];
-
-                while let Some(current) = stack.pop() {
-                    if visited.insert(current.clone()) {
-                        component.push(current.clone());
-                        if let Some(neighbors) = self.edges.get(&current) {
-                            for neighbor in neighbors {
-                                if !visited.contains(neighbor) {
-                                    stack.push(neighbor.clone());
-                                }
-                            }
-                        }
-                    }
-                }
+        for start in 0..self.umis.len() {
+            if visited[start] {
+                continue;
+            }
+            let mut component = Vec::new();
+            let mut stack = vec![start as u32];
 
-                components.push(component);
+            while let Some(current) = stack.pop() {
+                if std::mem::replace(&mut visited[current as usize], true) {
+                    continue;
+                }
+                component.push(self.umis.resolve(current).to_string());
+                if let Some(neighbors) = self.edges.get(current as usize) {
+                    stack.extend(neighbors.iter().filter(|&&n| !visited[n as usize]));
+                }
             }
+
+            components.push(component);
         }
 
         components
@@ -83,7 +97,7 @@ impl UmiGraph {
 
     /// Get node count
     pub fn get_count(&self, umi: &str) -> u32 {
-        *self.nodes.get(umi).unwrap_or(&0)
+        self.umis.get(umi).map_or(0, |id| self.counts[id as usize])
     }
 }
 
@@ -93,6 +107,74 @@ impl Default for UmiGraph {
     }
 }
 
+/// Link every pair of nodes within `max_distance`
+fn link_pairs<F>(edges: &mut [Vec<u32>], distance: F, max_distance: u32)
+where
+    F: Fn(usize, usize) -> u32,
+{
+    for i in 0..edges.len() {
+        for j in (i + 1)..edges.len() {
+            if distance(i, j) <= max_distance {
+                edges[i].push(j as u32);
+                edges[j].push(i as u32);
+            }
+        }
+    }
+}
+
+/// Link nodes by looking up every sequence within `max_distance` (at most 2)
+/// of each packed UMI
+fn link_neighbors(edges: &mut [Vec<u32>], packed: &[u64], len: usize, max_distance: u32) {
+    let index: AHashMap<u64, u32> =
+        packed.iter().enumerate().map(|(i, &p)| (p, i as u32)).collect();
+    for (i, &umi) in packed.iter().enumerate() {
+        for_each_neighbor(umi, len, max_distance, |neighbor| {
+            if let Some(&j) = index.get(&neighbor) {
+                if j as usize > i {
+                    edges[i].push(j);
+                    edges[j as usize].push(i as u32);
+                }
+            }
+        });
+    }
+}
+
+/// Sequences within `max_distance` of one `len`-base UMI
+fn neighbor_count(len: usize, max_distance: u32) -> usize {
+    let one = 3 * len;
+    match max_distance {
+        0 => 0,
+        1 => one,
+        _ => one + 9 * len * len.saturating_sub(1) / 2,
+    }
+}
+
+/// Whether enumerating neighbours beats comparing all pairs of `n` UMIs; a
+/// hash lookup costs several packed comparisons
+fn prefer_lookup(n: usize, len: usize, max_distance: u32) -> bool {
+    (1..=2).contains(&max_distance) && neighbor_count(len, max_distance) * 8 < n
+}
+
+/// Call `f` with every packed sequence 1 to `max_distance` (at most 2)
+/// substitutions from `umi`
+fn for_each_neighbor<F: FnMut(u64)>(umi: u64, len: usize, max_distance: u32, mut f: F) {
+    for p1 in 0..len {
+        for s1 in 1..4u64 {
+            // XOR with a non-zero 2-bit code always changes the base
+            let one = umi ^ (s1 << (2 * p1));
+            f(one);
+            if max_distance < 2 {
+                continue;
+            }
+            for p2 in (p1 + 1)..len {
+                for s2 in 1..4u64 {
+                    f(one ^ (s2 << (2 * p2)));
+                }
+            }
+        }
+    }
+}
+
 /// UMI deduplicator using directional adjacency method
 pub struct UmiDeduplicator {
     /// Maximum edit distance for UMI clustering
@@ -194,6 +276,57 @@ mod tests {
         assert_eq!(a_group.unwrap().members.len(), 2);
     }
 
+    #[test]
+    fn test_dedup_with_ambiguous_bases() {
+        // An N (or mixed lengths) disables packed comparison
+        let umis = vec![
+            Umi::with_count("AAAAAAAAAAAA".to_string(), 10),
+            Umi::with_count("AAAAAAAAAAAN".to_string(), 1),
+            Umi::with_count("CCCCCCCCCC".to_string(), 5),
+        ];
+
+        let groups = UmiDeduplicator::new(1).deduplicate(&umis);
+        assert_eq!(groups.len(), 2);
+    }
+
+    #[test]
+    fn test_neighbor_lookup_matches_pairs() {
+        // Clusters of close variants around random centres
+        let mut state = 12345u64;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            state >> 33
+        };
+        let mut umis = Vec::new();
+        for _ in 0..40 {
+            let centre: Vec<u8> = (0..8).map(|_| b"ACGT"[(next() % 4) as usize]).collect();
+            for _ in 0..5 {
+                let mut umi = centre.clone();
+                for _ in 0..(next() % 3) {
+                    umi[(next() % 8) as usize] = b"ACGT"[(next() % 4) as usize];
+                }
+                umis.push(String::from_utf8(umi).unwrap());
+            }
+        }
+
+        umis.sort();
+        umis.dedup();
+        let packed: Vec<u64> = umis.iter().map(|u| pack_2bit(u.as_bytes()).unwrap()).collect();
+        for max_distance in [1, 2] {
+            let mut pairs = vec![Vec::new(); packed.len()];
+            link_pairs(&mut pairs, |i, j| hamming_2bit(packed[i], packed[j]), max_distance);
+            let mut lookup = vec![Vec::new(); packed.len()];
+            link_neighbors(&mut lookup, &packed, 8, max_distance);
+            for edges in pairs.iter_mut().chain(lookup.iter_mut()) {
+                edges.sort_unstable();
+            }
+            assert_eq!(pairs, lookup, "max_distance {}", max_distance);
+        }
+        assert_eq!(neighbor_count(12, 2), 36 + 594);
+        assert!(!prefer_lookup(100, 12, 2));
+        assert!(prefer_lookup(10_000, 12, 2));
+    }
+
     #[test]
     fn test_exact_dedup() {
         let umis = vec![
@@ -4,6 +4,51 @@ mod dedup;
 
 pub use dedup::{UmiDeduplicator, UmiGraph};
 
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+/// What to do with reads whose UMI contains an `N`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UmiNPolicy {
+    /// Count the UMI as read; `N` is just another mismatch
+    #[default]
+    Keep,
+    /// Skip the read
+    Drop,
+    /// Resolve a single `N` to the most-read UMI of the same cell and gene that
+    /// matches at every other position; skip the read if none does
+    Correct,
+}
+
+impl FromStr for UmiNPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "keep" => Ok(UmiNPolicy::Keep),
+            "drop" => Ok(UmiNPolicy::Drop),
+            "correct" => Ok(UmiNPolicy::Correct),
+            _ => Err(Error::Config(format!(
+                "unknown UMI N policy '{}' (expected keep, drop, or correct)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for UmiNPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UmiNPolicy::Keep => "keep",
+            UmiNPolicy::Drop => "drop",
+            UmiNPolicy::Correct => "correct",
+        })
+    }
+}
+
 /// A UMI with associated data
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Umi {
@@ -0,0 +1,404 @@
+//! Pre-flight input integrity checks
+//!
+//! Cheap checks run before a long pipeline: R1/R2 pairing, whitelist vs
+//! protocol barcode length, GTF parsability, BAM tag presence, and quality
+//! encoding. Each check carries an actionable message.
+
+use crate::annotation::GeneAnnotation;
+#[cfg(feature = "native")]
+use crate::bam::BamParser;
+use crate::barcode::{TieredCorrector, Whitelist};
+use crate::fastq::{FastqParser, QualityEncoding};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// A named check result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputCheck {
+    /// Check name (e.g. "fastq_pairing")
+    pub name: String,
+    pub status: CheckStatus,
+    /// What was found and, on failure, what to do about it
+    pub message: String,
+}
+
+impl InputCheck {
+    fn new(name: &str, status: CheckStatus, message: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// All input checks for a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InputReport {
+    pub checks: Vec<InputCheck>,
+}
+
+impl InputReport {
+    /// Whether no check failed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+
+    /// Number of checks with the given status
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+}
+
+/// Read name with any `/1` or `/2` mate suffix removed
+fn mate_name(name: &str) -> &str {
+    name.strip_suffix("/1")
+        .or_else(|| name.strip_suffix("/2"))
+        .unwrap_or(name)
+}
+
+fn truncated(shorter: &str, longer: &str, pairs: u64) -> InputCheck {
+    InputCheck::new(
+        "fastq_pairing",
+        CheckStatus::Fail,
+        format!(
+            "{} ends after {} records but {} continues; \
+             re-download or re-demultiplex the truncated file",
+            shorter, pairs, longer
+        ),
+    )
+}
+
+/// Check R1/R2 record counts, read-name pairing, R1 length, and quality encoding.
+///
+/// `max_records` limits how many pairs are read (0 = whole files);
+/// `min_r1_len` is the barcode+UMI length required by the protocol.
+pub fn check_fastq_pair<P: AsRef<Path>>(
+    r1: P,
+    r2: P,
+    max_records: u64,
+    min_r1_len: usize,
+) -> Vec<InputCheck> {
+    let mut checks = Vec::new();
+    let (mut p1, mut p2) = match (FastqParser::open(r1.as_ref()), FastqParser::open(r2.as_ref())) {
+        (Ok(p1), Ok(p2)) => (p1, p2),
+        (Err(e), _) | (_, Err(e)) => {
+            checks.push(InputCheck::new(
+                "fastq_open",
+                CheckStatus::Fail,
+                format!("{} (check the path and that the file is FASTQ, optionally .gz/.zst)", e),
+            ));
+            return checks;
+        }
+    };
+
+    for (label, parser) in [("R1", &mut p1), ("R2", &mut p2)] {
+        if parser.detect_encoding() == QualityEncoding::Phred64 {
+            checks.push(InputCheck::new(
+                "quality_encoding",
+                CheckStatus::Warn,
+                format!(
+                    "{} uses legacy Phred+64 qualities; they will be converted to Phred+33",
+                    label
+                ),
+            ));
+        }
+    }
+    if !checks.iter().any(|c| c.name == "quality_encoding") {
+        checks.push(InputCheck::new(
+            "quality_encoding",
+            CheckStatus::Pass,
+            "R1 and R2 use Phred+33 qualities",
+        ));
+    }
+
+    let mut pairs = 0u64;
+    let mut short_r1 = 0u64;
+    let pairing = loop {
+        if max_records > 0 && pairs >= max_records {
+            break InputCheck::new(
+                "fastq_pairing",
+                CheckStatus::Pass,
+                format!("First {} records are paired (R1/R2 names match)", pairs),
+            );
+        }
+        let rec = match (p1.next(), p2.next()) {
+            (None, None) => {
+                break InputCheck::new(
+                    "fastq_pairing",
+                    CheckStatus::Pass,
+                    format!("{} read pairs, R1/R2 names match", pairs),
+                )
+            }
+            (None, Some(_)) => break truncated("R1", "R2", pairs),
+            (Some(_), None) => break truncated("R2", "R1", pairs),
+            (Some(a), Some(b)) => (a, b),
+        };
+        let (a, b) = match rec {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(e), _) | (_, Err(e)) => {
+                break InputCheck::new("fastq_pairing", CheckStatus::Fail, e.to_string());
+            }
+        };
+        pairs += 1;
+        if mate_name(a.name()) != mate_name(b.name()) {
+            break InputCheck::new(
+                "fastq_pairing",
+                CheckStatus::Fail,
+                format!(
+                    "Read names differ at record {}: '{}' (R1) vs '{}' (R2); \
+                     the files are out of order or from different runs",
+                    pairs,
+                    a.name(),
+                    b.name()
+                ),
+            );
+        }
+        if a.seq.len() < min_r1_len {
+            short_r1 += 1;
+        }
+    };
+    checks.push(pairing);
+
+    if min_r1_len > 0 {
+        checks.push(if short_r1 == 0 {
+            InputCheck::new(
+                "r1_length",
+                CheckStatus::Pass,
+                format!("All R1 reads cover barcode+UMI ({} bp)", min_r1_len),
+            )
+        } else {
+            InputCheck::new(
+                "r1_length",
+                CheckStatus::Fail,
+                format!(
+                    "{} of {} R1 reads are shorter than barcode+UMI ({} bp); \
+                     check --protocol or whether R1 and R2 are swapped",
+                    short_r1, pairs, min_r1_len
+                ),
+            )
+        });
+    }
+
+    checks
+}
+
+/// Check that a whitelist loads and matches the protocol barcode length (0 = skip)
+pub fn check_whitelist<P: AsRef<Path>>(path: P, expected_len: usize) -> InputCheck {
+    let whitelist = match Whitelist::from_file(path.as_ref()) {
+        Ok(w) => w,
+        Err(e) => {
+            return InputCheck::new(
+                "whitelist",
+                CheckStatus::Fail,
+                format!("{:?}: {}", path.as_ref(), e),
+            )
+        }
+    };
+    if whitelist.is_empty() {
+        return InputCheck::new(
+            "whitelist",
+            CheckStatus::Fail,
+            format!("{:?} contains no barcodes", path.as_ref()),
+        );
+    }
+    if expected_len > 0 && whitelist.barcode_len() != expected_len {
+        return InputCheck::new(
+            "whitelist",
+            CheckStatus::Fail,
+            format!(
+                "Whitelist barcodes are {} bp but the protocol expects {} bp; \
+                 check --protocol or use the whitelist for this chemistry",
+                whitelist.barcode_len(),
+                expected_len
+            ),
+        );
+    }
+    InputCheck::new(
+        "whitelist",
+        CheckStatus::Pass,
+        format!("{} barcodes of {} bp", whitelist.len(), whitelist.barcode_len()),
+    )
+}
+
+/// Check that a per-tier whitelist loads and matches the protocol tier lengths
+pub fn check_tiered_whitelist<P: AsRef<Path>>(path: P, expected: &[usize]) -> InputCheck {
+    let tiers = match TieredCorrector::from_file(path.as_ref(), 0) {
+        Ok(t) => t,
+        Err(e) => {
+            return InputCheck::new(
+                "whitelist",
+                CheckStatus::Fail,
+                format!("{:?}: {}", path.as_ref(), e),
+            )
+        }
+    };
+    if tiers.tier_lens() != expected {
+        return InputCheck::new(
+            "whitelist",
+            CheckStatus::Fail,
+            format!(
+                "Whitelist tiers are {:?} bp but the protocol expects {:?} bp; \
+                 check --protocol or use the whitelist for this chemistry",
+                tiers.tier_lens(),
+                expected
+            ),
+        );
+    }
+    InputCheck::new(
+        "whitelist",
+        CheckStatus::Pass,
+        format!("{} tiers of {:?} bp", expected.len(), expected),
+    )
+}
+
+/// Check that a GTF parses and defines at least one gene
+pub fn check_gtf<P: AsRef<Path>>(path: P) -> InputCheck {
+    match GeneAnnotation::from_gtf(path.as_ref()) {
+        Ok(ann) if ann.is_empty() => InputCheck::new(
+            "gtf",
+            CheckStatus::Fail,
+            "GTF contains no gene or exon features with a gene_id",
+        ),
+        Ok(ann) => InputCheck::new("gtf", CheckStatus::Pass, format!("{} genes", ann.len())),
+        Err(e) => InputCheck::new("gtf", CheckStatus::Fail, e.to_string()),
+    }
+}
+
+/// Check that the first `sample` mapped BAM records carry CB/UB and gene tags
+#[cfg(feature = "native")]
+pub fn check_bam_tags<P: AsRef<Path>>(path: P, sample: usize) -> Vec<InputCheck> {
+    let parser = match BamParser::open(path.as_ref()) {
+        Ok(p) => p,
+        Err(e) => return vec![InputCheck::new("bam_open", CheckStatus::Fail, e.to_string())],
+    };
+
+    let (mut seen, mut cb, mut ub, mut gene) = (0usize, 0usize, 0usize, 0usize);
+    for record in parser {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => return vec![InputCheck::new("bam_read", CheckStatus::Fail, e.to_string())],
+        };
+        if !record.is_mapped {
+            continue;
+        }
+        seen += 1;
+        cb += record.cell_barcode.is_some() as usize;
+        ub += record.umi.is_some() as usize;
+        gene += record.is_assigned() as usize;
+        if seen >= sample {
+            break;
+        }
+    }
+
+    if seen == 0 {
+        return vec![InputCheck::new(
+            "bam_tags",
+            CheckStatus::Fail,
+            "BAM has no mapped records",
+        )];
+    }
+
+    let tag_check = |name: &str, tag: &str, n: usize, missing_status: CheckStatus, hint: &str| {
+        if n == 0 {
+            InputCheck::new(
+                name,
+                missing_status,
+                format!("No {} tags in the first {} mapped reads; {}", tag, seen, hint),
+            )
+        } else {
+            InputCheck::new(
+                name,
+                CheckStatus::Pass,
+                format!(
+                    "{} present on {:.1}% of the first {} mapped reads",
+                    tag,
+                    n as f64 / seen as f64 * 100.0,
+                    seen
+                ),
+            )
+        }
+    };
+
+    vec![
+        tag_check(
+            "bam_cb",
+            "CB",
+            cb,
+            CheckStatus::Fail,
+            "align reads extracted with `sparc extract --annotate sam-tags`",
+        ),
+        tag_check(
+            "bam_ub",
+            "UB",
+            ub,
+            CheckStatus::Fail,
+            "align reads extracted with `sparc extract --annotate sam-tags`",
+        ),
+        tag_check(
+            "bam_gene",
+            "GX/GN",
+            gene,
+            CheckStatus::Warn,
+            "run `sparc annotate` with a GTF before counting",
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_fastq_pair_name_mismatch() {
+        let dir = tempdir().unwrap();
+        let r1 = dir.path().join("R1.fastq");
+        let r2 = dir.path().join("R2.fastq");
+        std::fs::write(&r1, "@a/1\nACGTACGT\n+\nIIIIIIII\n@b/1\nACGTACGT\n+\nIIIIIIII\n").unwrap();
+        std::fs::write(&r2, "@a/2\nACGT\n+\nIIII\n@c/2\nACGT\n+\nIIII\n").unwrap();
+
+        let checks = check_fastq_pair(&r1, &r2, 0, 8);
+        let pairing = checks.iter().find(|c| c.name == "fastq_pairing").unwrap();
+        assert_eq!(pairing.status, CheckStatus::Fail);
+        assert!(pairing.message.contains("record 2"), "{}", pairing.message);
+    }
+
+    #[test]
+    fn test_fastq_pair_short_r1() {
+        let dir = tempdir().unwrap();
+        let r1 = dir.path().join("R1.fastq");
+        let r2 = dir.path().join("R2.fastq");
+        std::fs::write(&r1, "@a\nACGT\n+\nIIII\n").unwrap();
+        std::fs::write(&r2, "@a\nACGT\n+\nIIII\n").unwrap();
+
+        let report = InputReport {
+            checks: check_fastq_pair(&r1, &r2, 0, 28),
+        };
+        assert!(!report.passed());
+        assert_eq!(report.count(CheckStatus::Fail), 1);
+    }
+
+    #[test]
+    fn test_whitelist_length() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wl.txt");
+        std::fs::write(&path, "ACGTACGTACGT\nTTTTGGGGCCCC\n").unwrap();
+
+        assert_eq!(check_whitelist(&path, 12).status, CheckStatus::Pass);
+        assert_eq!(check_whitelist(&path, 16).status, CheckStatus::Fail);
+
+        let tiered = dir.path().join("tiers.txt");
+        std::fs::write(&tiered, "1 ACGT\n1 TTTT\n2 GGCC\n").unwrap();
+        assert_eq!(check_tiered_whitelist(&tiered, &[4, 4]).status, CheckStatus::Pass);
+        assert_eq!(check_tiered_whitelist(&tiered, &[8, 6]).status, CheckStatus::Fail);
+    }
+}
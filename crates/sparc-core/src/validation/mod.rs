@@ -2,12 +2,15 @@
 //!
 //! Generates synthetic ground-truth datasets and validates each pipeline stage
 //! (extract, count, analysis) against expected results with accuracy metrics.
+//! The `inputs` submodule holds pre-flight integrity checks for real inputs.
 
+pub mod inputs;
 pub mod metrics;
 pub mod report;
 pub mod stages;
 pub mod synthetic;
 
+pub use inputs::{CheckStatus, InputCheck, InputReport};
 pub use metrics::*;
 pub use report::ValidationReport;
 pub use synthetic::{SyntheticConfig, SyntheticDataset, TruthSet};
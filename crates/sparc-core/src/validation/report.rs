@@ -1,8 +1,6 @@
 //! Validation report generation and serialization
 
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::BufWriter;
 use std::path::Path;
 
 use super::stages::{AnalysisValidationResult, CountValidationResult, ExtractValidationResult};
@@ -128,11 +126,9 @@ impl ValidationReport {
     }
 
     /// Write JSON report to file
-    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
-        let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-        serde_json::to_writer_pretty(writer, self)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+        let json = self.to_json().map_err(std::io::Error::from)?;
+        crate::atomic::write(path, json)
     }
 
     /// Format a human-readable summary
@@ -57,7 +57,7 @@ impl Default for SyntheticConfig {
             invalid_barcode_rate: 0.05,
             barcode_len: 16,
             umi_len: 12,
-            seed: 42,
+            seed: crate::seed::DEFAULT_SEED,
             protocol: "10x-3prime-v3".to_string(),
         }
     }
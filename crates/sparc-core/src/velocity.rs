@@ -0,0 +1,228 @@
+//! Spliced, unspliced, and ambiguous count matrices for RNA velocity
+//!
+//! Reads are assigned to genes with introns included and labelled with a
+//! [`SpliceClass`]. Reads sharing a cell, gene, and UMI form one molecule,
+//! which keeps its class when all of its reads agree and is ambiguous
+//! otherwise. Reads without a UMI are counted individually.
+
+use crate::annotation::{aligned_blocks, AnnotateStats, GeneAssigner, SpliceClass};
+use crate::count::CountMatrix;
+use crate::{Error, Result};
+use ahash::AHashMap;
+use rust_htslib::bam::{self, record::Aux, Read};
+use std::path::Path;
+
+/// Velocity count matrices sharing one set of genes and barcodes
+#[derive(Debug, Clone)]
+pub struct VelocityMatrices {
+    pub spliced: CountMatrix,
+    pub unspliced: CountMatrix,
+    pub ambiguous: CountMatrix,
+}
+
+impl VelocityMatrices {
+    /// Write `spliced.mtx`, `unspliced.mtx`, `ambiguous.mtx`, `barcodes.tsv`,
+    /// and `genes.tsv` into `dir` (the STARsolo Velocyto layout)
+    pub fn write<P: AsRef<Path>>(&self, dir: P) -> Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        self.spliced.write_mtx(dir.join("spliced.mtx"))?;
+        self.unspliced.write_mtx(dir.join("unspliced.mtx"))?;
+        self.ambiguous.write_mtx(dir.join("ambiguous.mtx"))?;
+        self.spliced.write_barcodes(dir.join("barcodes.tsv"))?;
+        self.spliced.write_genes(dir.join("genes.tsv"))?;
+        Ok(())
+    }
+
+    /// Total spliced, unspliced, and ambiguous counts
+    pub fn totals(&self) -> [u64; 3] {
+        let sum = |m: &CountMatrix| m.values.iter().map(|&v| v as u64).sum::<u64>();
+        [sum(&self.spliced), sum(&self.unspliced), sum(&self.ambiguous)]
+    }
+}
+
+/// Count molecules by splicing state from a BAM with CB (and UB) tags.
+///
+/// Introns are always included in gene assignment; the overlap mode and
+/// strandedness come from `assigner`. Reads below `min_mapq`, secondary, and
+/// supplementary alignments are skipped. The returned stats cover every mapped
+/// primary read, with or without a cell barcode.
+pub fn count_velocity<P: AsRef<Path>>(
+    bam_path: P,
+    assigner: &GeneAssigner,
+    min_mapq: u8,
+) -> Result<(VelocityMatrices, AnnotateStats)> {
+    let assigner = assigner.clone().include_introns(true);
+    let genes = assigner.annotation().genes();
+    let gene_index: AHashMap<&str, usize> =
+        genes.iter().enumerate().map(|(i, g)| (g.id.as_str(), i)).collect();
+
+    let mut reader = bam::Reader::from_path(bam_path.as_ref())
+        .map_err(|e| Error::BamParse(format!("Failed to open BAM: {}", e)))?;
+    let chrom_names: Vec<String> = reader
+        .header()
+        .target_names()
+        .iter()
+        .map(|n| String::from_utf8_lossy(n).to_string())
+        .collect();
+
+    let mut stats = AnnotateStats::default();
+    let mut cells: AHashMap<String, usize> = AHashMap::new();
+    let mut molecules: AHashMap<(usize, usize, Vec<u8>), SpliceClass> = AHashMap::new();
+    let mut counts: AHashMap<(usize, usize), [u32; 3]> = AHashMap::new();
+
+    let mut record = bam::Record::new();
+    while let Some(result) = reader.read(&mut record) {
+        result.map_err(|e| Error::BamParse(e.to_string()))?;
+        stats.total_reads += 1;
+        let chrom = usize::try_from(record.tid()).ok().and_then(|t| chrom_names.get(t));
+        let chrom = match chrom {
+            Some(c) if !record.is_unmapped() => c,
+            _ => {
+                stats.unmapped_reads += 1;
+                continue;
+            }
+        };
+        if record.is_secondary() || record.is_supplementary() || record.mapq() < min_mapq {
+            continue;
+        }
+
+        let blocks = aligned_blocks(record.pos(), record.cigar().iter());
+        let ann = assigner.assign_blocks(chrom, &blocks, record.is_reverse());
+        stats.record(&ann);
+        let (Some(gene), Some(class)) = (ann.gene, ann.splice) else {
+            continue;
+        };
+        let Ok(Aux::String(barcode)) = record.aux(b"CB") else {
+            continue;
+        };
+        let next = cells.len();
+        let cell = *cells.entry(barcode.to_string()).or_insert(next);
+        let gene = gene_index[gene.id.as_str()];
+
+        match record.aux(b"UB") {
+            Ok(Aux::String(umi)) => {
+                molecules
+                    .entry((cell, gene, umi.as_bytes().to_vec()))
+                    .and_modify(|c| *c = c.merge(class))
+                    .or_insert(class);
+            }
+            _ => counts.entry((cell, gene)).or_default()[class.index()] += 1,
+        }
+    }
+
+    for ((cell, gene, _), class) in molecules {
+        counts.entry((cell, gene)).or_default()[class.index()] += 1;
+    }
+
+    // Barcodes in sorted order, entries sorted by cell then gene
+    let mut barcodes: Vec<(String, usize)> = cells.into_iter().collect();
+    barcodes.sort_unstable();
+    let mut column = vec![0; barcodes.len()];
+    for (col, (_, cell)) in barcodes.iter().enumerate() {
+        column[*cell] = col;
+    }
+    let mut entries: Vec<((usize, usize), [u32; 3])> =
+        counts.into_iter().map(|((cell, gene), c)| ((column[cell], gene), c)).collect();
+    entries.sort_unstable_by_key(|&(key, _)| key);
+
+    let empty = CountMatrix {
+        barcodes: barcodes.into_iter().map(|(b, _)| b).collect(),
+        genes: genes.iter().map(|g| g.id.clone()).collect(),
+        gene_names: genes.iter().map(|g| g.name.clone()).collect(),
+        n_rows: genes.len(),
+        ..CountMatrix::new()
+    };
+    let mut matrices = [empty.clone(), empty.clone(), empty];
+    for ((col, gene), class_counts) in entries {
+        for (matrix, &count) in matrices.iter_mut().zip(&class_counts) {
+            if count > 0 {
+                matrix.rows.push(gene);
+                matrix.cols.push(col);
+                matrix.values.push(count);
+            }
+        }
+    }
+    for matrix in matrices.iter_mut() {
+        matrix.n_cols = matrix.barcodes.len();
+    }
+    let [spliced, unspliced, ambiguous] = matrices;
+    Ok((
+        VelocityMatrices {
+            spliced,
+            unspliced,
+            ambiguous,
+        },
+        stats,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::GeneAnnotation;
+    use rust_htslib::bam::header::HeaderRecord;
+    use rust_htslib::bam::record::{Cigar, CigarString};
+
+    const GTF: &str = "\
+chr1\tsrc\texon\t101\t200\t.\t+\t.\tgene_id \"G1\"; gene_name \"Alpha\";
+chr1\tsrc\texon\t401\t500\t.\t+\t.\tgene_id \"G1\"; gene_name \"Alpha\";
+";
+
+    #[test]
+    fn test_count_velocity() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reads.bam");
+        let mut header = bam::Header::new();
+        let mut sq = HeaderRecord::new(b"SQ");
+        sq.push_tag(b"SN", "chr1");
+        sq.push_tag(b"LN", 1000);
+        header.push_record(&sq);
+
+        let spliced = vec![Cigar::Match(20), Cigar::RefSkip(220), Cigar::Match(20)];
+        let exonic = vec![Cigar::Match(40)];
+        // (cell, umi, pos, cigar)
+        let reads = [
+            ("AAAC", "U1", 160, &spliced),
+            ("AAAC", "U1", 120, &exonic), // same molecule, still spliced
+            ("AAAC", "U2", 300, &exonic), // intronic: unspliced
+            ("AAAC", "U3", 120, &exonic), // U3 seen spliced and unspliced:
+            ("AAAC", "U3", 300, &exonic), // ambiguous
+            ("GGGT", "U1", 310, &exonic), // unspliced in a second cell
+            ("GGGT", "U9", 700, &exonic), // intergenic
+        ];
+        let mut writer = bam::Writer::from_path(&path, &header, bam::Format::Bam).unwrap();
+        for (i, (cb, ub, pos, cigar)) in reads.iter().enumerate() {
+            let mut record = bam::Record::new();
+            let cigar = CigarString((*cigar).clone());
+            record.set(format!("r{}", i).as_bytes(), Some(&cigar), &[b'A'; 40], &[30; 40]);
+            record.set_tid(0);
+            record.set_pos(*pos);
+            record.set_mapq(60);
+            record.push_aux(b"CB", Aux::String(cb)).unwrap();
+            record.push_aux(b"UB", Aux::String(ub)).unwrap();
+            writer.write(&record).unwrap();
+        }
+        drop(writer);
+
+        let annotation = GeneAnnotation::from_reader(GTF.as_bytes()).unwrap();
+        let (matrices, stats) =
+            count_velocity(&path, &GeneAssigner::new(&annotation), 30).unwrap();
+
+        assert_eq!(matrices.spliced.barcodes, vec!["AAAC", "GGGT"]);
+        assert_eq!(matrices.spliced.genes, vec!["G1"]);
+        assert_eq!(matrices.spliced.gene_name(0), "Alpha");
+        assert_eq!(matrices.spliced.get(0, 0), 1);
+        assert_eq!(matrices.unspliced.get(0, 0), 1);
+        assert_eq!(matrices.ambiguous.get(0, 0), 1);
+        assert_eq!(matrices.unspliced.get(0, 1), 1);
+        assert_eq!(matrices.totals(), [1, 2, 1]);
+
+        assert_eq!(stats.total_reads, 7);
+        assert_eq!((stats.exonic_reads, stats.intronic_reads, stats.intergenic_reads), (3, 3, 1));
+        assert_eq!((stats.spliced_reads, stats.unspliced_reads), (3, 3));
+
+        matrices.write(dir.path().join("velocity")).unwrap();
+        assert!(dir.path().join("velocity/unspliced.mtx").exists());
+    }
+}
@@ -415,6 +415,35 @@ fn test_read_structure() {
     assert_eq!(rs.umi_len, 12);
 }
 
+#[test]
+fn test_read_structure_dsl() {
+    let rs = ReadStructure::parse("16C12U").unwrap();
+    assert_eq!((rs.barcode_start, rs.barcode_len), (0, 16));
+    assert_eq!((rs.umi_start, rs.umi_len), (16, 12));
+    assert_eq!(rs.cdna_start, 0);
+    assert_eq!(rs.min_len(), 28);
+
+    let rs: ReadStructure = "8x12c8u+t".parse().unwrap();
+    assert_eq!((rs.barcode_start, rs.umi_start, rs.cdna_start), (8, 20, 28));
+    assert_eq!(rs.to_string(), "8X12C8U+T");
+
+    // UMI before barcode round-trips
+    assert_eq!(ReadStructure::parse("10U16C").unwrap().to_string(), "10U16C");
+    assert_eq!(ReadStructure::tenx_3prime_v3().to_string(), "16C12U");
+
+    for bad in ["", "16", "C16", "16C12Q", "16C16C", "16C+U", "+T16C", "0C"] {
+        assert!(ReadStructure::parse(bad).is_err(), "{:?} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_protocol_from_name() {
+    for name in sparc_core::protocols::PROTOCOL_NAMES {
+        assert!(sparc_core::protocols::from_name(name).is_ok(), "{}", name);
+    }
+    assert!(sparc_core::protocols::from_name("10x-9prime").is_err());
+}
+
 // ===== MTX Write/Read Roundtrip =====
 
 #[test]
@@ -0,0 +1,141 @@
+/*
+ * sparc.h - C interface to SPARC single-cell processing
+ *
+ * Link against libsparc (cdylib or staticlib from crates/sparc-ffi).
+ *
+ * Conventions:
+ *   - Functions return SPARC_OK, an informational code (> 0), or an error (< 0),
+ *     and write results through out-pointers.
+ *   - After an error, sparc_last_error() describes it. The message belongs to
+ *     the calling thread and stays valid until its next failing call.
+ *   - Handles are opaque. Free each with its *_free function; passing NULL to a
+ *     *_free function is a no-op. Handles may be used from any thread, but not
+ *     from two threads at once.
+ *   - Strings are NUL-terminated UTF-8. Strings returned by the library are
+ *     owned by it and valid for the lifetime of the handle they came from.
+ */
+
+#ifndef SPARC_H
+#define SPARC_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes */
+#define SPARC_OK 0
+#define SPARC_END 1             /* no more records */
+#define SPARC_NO_MATCH 2        /* barcode not in, or correctable to, the whitelist */
+#define SPARC_ERR_NULL (-1)     /* a required pointer was NULL */
+#define SPARC_ERR_ARGUMENT (-2) /* invalid argument (bad UTF-8, buffer too small) */
+#define SPARC_ERR_IO (-3)       /* file system error */
+#define SPARC_ERR_CORE (-4)     /* parsing/format error from the SPARC core */
+#define SPARC_ERR_PANIC (-5)    /* internal error */
+
+/* Message for the last error on this thread, or NULL */
+const char *sparc_last_error(void);
+
+/* Library version, e.g. "0.1.0" */
+const char *sparc_version(void);
+
+/* ---- FASTQ ------------------------------------------------------------ */
+
+typedef struct SparcFastqReader SparcFastqReader;
+
+/* Current record; pointers are valid until the next call on the reader */
+typedef struct {
+    const char *id;      /* header without '@' */
+    const uint8_t *seq;  /* len bases, not NUL-terminated */
+    const uint8_t *qual; /* len Phred+33 qualities, not NUL-terminated */
+    size_t len;
+} SparcFastqRecord;
+
+/* Open a FASTQ file (plain or gzipped) */
+int sparc_fastq_open(const char *path, SparcFastqReader **out);
+
+/* Read the next record: SPARC_OK, SPARC_END at end of file, or an error */
+int sparc_fastq_next(SparcFastqReader *reader, SparcFastqRecord *record);
+
+void sparc_fastq_free(SparcFastqReader *reader);
+
+/* ---- Barcodes --------------------------------------------------------- */
+
+typedef struct SparcWhitelist SparcWhitelist;
+typedef struct SparcCorrector SparcCorrector;
+
+/* Load a whitelist, one barcode per line */
+int sparc_whitelist_load(const char *path, SparcWhitelist **out);
+
+/* Build a whitelist from n barcodes */
+int sparc_whitelist_from_array(const char *const *barcodes, size_t n, SparcWhitelist **out);
+
+/* Number of barcodes and (if barcode_len is not NULL) their length */
+int sparc_whitelist_len(const SparcWhitelist *whitelist, size_t *len, size_t *barcode_len);
+
+void sparc_whitelist_free(SparcWhitelist *whitelist);
+
+/* Corrector matching barcodes within max_distance mismatches; copies the
+ * whitelist, which may be freed afterwards */
+int sparc_corrector_new(const SparcWhitelist *whitelist, uint32_t max_distance,
+                        SparcCorrector **out);
+
+/* Correct one barcode into out (out_len bytes, including the NUL).
+ * Returns SPARC_OK with the whitelist barcode and (if distance is not NULL)
+ * its mismatch count, or SPARC_NO_MATCH leaving out untouched. */
+int sparc_corrector_correct(const SparcCorrector *corrector, const char *barcode, char *out,
+                            size_t out_len, uint32_t *distance);
+
+void sparc_corrector_free(SparcCorrector *corrector);
+
+/* ---- UMIs ------------------------------------------------------------- */
+
+/* Deduplicate the UMIs of one (cell, gene) group with directional clustering.
+ * counts holds the reads per UMI (NULL = 1 each); repeated sequences are
+ * summed. Writes the number of molecules and, if molecule_of is not NULL
+ * (n entries), the molecule index in [0, *n_molecules) of each input UMI. */
+int sparc_umi_dedup(const char *const *umis, const uint32_t *counts, size_t n,
+                    uint32_t max_distance, size_t *n_molecules, size_t *molecule_of);
+
+/* ---- Count matrices --------------------------------------------------- */
+
+typedef struct SparcCounter SparcCounter;
+typedef struct SparcMatrix SparcMatrix;
+
+int sparc_counter_new(SparcCounter **out);
+
+/* Add count molecules for a (barcode, gene) pair */
+int sparc_counter_add(SparcCounter *counter, const char *barcode, const char *gene,
+                      uint32_t count);
+
+/* Build the gene x cell matrix. Consumes the counter, even on error. */
+int sparc_counter_build(SparcCounter *counter, SparcMatrix **out);
+
+void sparc_counter_free(SparcCounter *counter);
+
+/* Read a 10x-style directory (matrix.mtx, barcodes.tsv, features/genes.tsv) */
+int sparc_matrix_read_mtx(const char *dir, SparcMatrix **out);
+
+/* Write matrix.mtx, barcodes.tsv, and genes.tsv to dir (created if missing) */
+int sparc_matrix_write_mtx(const SparcMatrix *matrix, const char *dir);
+
+int sparc_matrix_shape(const SparcMatrix *matrix, size_t *n_genes, size_t *n_cells,
+                       size_t *nnz);
+
+/* Copy the nonzero entries (0-based gene row, cell column, count) into
+ * caller-allocated arrays of nnz elements each */
+int sparc_matrix_coo(const SparcMatrix *matrix, size_t *rows, size_t *cols, uint32_t *values);
+
+/* Gene or barcode name by index, or NULL if out of range */
+const char *sparc_matrix_gene(const SparcMatrix *matrix, size_t idx);
+const char *sparc_matrix_barcode(const SparcMatrix *matrix, size_t idx);
+
+void sparc_matrix_free(SparcMatrix *matrix);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SPARC_H */
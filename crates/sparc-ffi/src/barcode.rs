@@ -0,0 +1,123 @@
+//! Whitelists and barcode correction
+
+use crate::{
+    guard, ptr_arg, ref_arg, str_arg, str_array, FfiError, SPARC_ERR_ARGUMENT, SPARC_NO_MATCH,
+    SPARC_OK,
+};
+use sparc_core::barcode::{BarcodeCorrector, BarcodeMatch, Whitelist};
+use std::ffi::{c_char, c_int};
+
+/// Barcode whitelist
+pub struct SparcWhitelist(Whitelist);
+
+/// Barcode corrector holding its own copy of a whitelist
+pub struct SparcCorrector(BarcodeCorrector);
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_whitelist_load(
+    path: *const c_char,
+    out: *mut *mut SparcWhitelist,
+) -> c_int {
+    guard(|| {
+        let out = ptr_arg(out, "out")?;
+        let whitelist = Whitelist::from_file(str_arg(path, "path")?)?;
+        *out = Box::into_raw(Box::new(SparcWhitelist(whitelist)));
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_whitelist_from_array(
+    barcodes: *const *const c_char,
+    n: usize,
+    out: *mut *mut SparcWhitelist,
+) -> c_int {
+    guard(|| {
+        let out = ptr_arg(out, "out")?;
+        let barcodes = str_array(barcodes, n, "barcodes")?;
+        let whitelist = Whitelist::from_vec(barcodes.into_iter().map(String::from).collect())?;
+        *out = Box::into_raw(Box::new(SparcWhitelist(whitelist)));
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_whitelist_len(
+    whitelist: *const SparcWhitelist,
+    len: *mut usize,
+    barcode_len: *mut usize,
+) -> c_int {
+    guard(|| {
+        let whitelist = &ref_arg(whitelist, "whitelist")?.0;
+        *ptr_arg(len, "len")? = whitelist.len();
+        if let Some(barcode_len) = barcode_len.as_mut() {
+            *barcode_len = whitelist.barcode_len();
+        }
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_whitelist_free(whitelist: *mut SparcWhitelist) {
+    if !whitelist.is_null() {
+        drop(Box::from_raw(whitelist));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_corrector_new(
+    whitelist: *const SparcWhitelist,
+    max_distance: u32,
+    out: *mut *mut SparcCorrector,
+) -> c_int {
+    guard(|| {
+        let out = ptr_arg(out, "out")?;
+        let whitelist = ref_arg(whitelist, "whitelist")?.0.clone();
+        *out = Box::into_raw(Box::new(SparcCorrector(BarcodeCorrector::new(
+            whitelist,
+            max_distance,
+        ))));
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_corrector_correct(
+    corrector: *const SparcCorrector,
+    barcode: *const c_char,
+    out: *mut c_char,
+    out_len: usize,
+    distance: *mut u32,
+) -> c_int {
+    guard(|| {
+        let corrector = &ref_arg(corrector, "corrector")?.0;
+        let barcode = str_arg(barcode, "barcode")?;
+        if out.is_null() {
+            return Err(FfiError::null("out"));
+        }
+        let (corrected, dist) = match corrector.match_barcode(barcode) {
+            BarcodeMatch::Exact(bc) => (bc, 0),
+            BarcodeMatch::Corrected(_, bc, dist) => (bc, dist),
+            BarcodeMatch::NoMatch(_) => return Ok(SPARC_NO_MATCH),
+        };
+        if corrected.len() >= out_len {
+            return Err(FfiError::new(
+                SPARC_ERR_ARGUMENT,
+                format!("out_len {} too small for a {}-base barcode", out_len, corrected.len()),
+            ));
+        }
+        std::ptr::copy_nonoverlapping(corrected.as_ptr() as *const c_char, out, corrected.len());
+        *out.add(corrected.len()) = 0;
+        if let Some(distance) = distance.as_mut() {
+            *distance = dist;
+        }
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_corrector_free(corrector: *mut SparcCorrector) {
+    if !corrector.is_null() {
+        drop(Box::from_raw(corrector));
+    }
+}
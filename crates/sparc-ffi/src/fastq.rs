@@ -0,0 +1,79 @@
+//! FASTQ reading
+
+use crate::{guard, ptr_arg, str_arg, to_cstring, FfiError, SPARC_END, SPARC_OK};
+use sparc_core::fastq::{FastqParser, FastqRecord};
+use std::ffi::{c_char, c_int, CString};
+
+/// Open FASTQ reader (plain or gzipped)
+pub struct SparcFastqReader {
+    parser: FastqParser,
+    /// Current record, borrowed by the last `SparcFastqRecord`
+    record: Option<FastqRecord>,
+    id: CString,
+}
+
+/// View of the current record; valid until the next call on the reader
+#[repr(C)]
+pub struct SparcFastqRecord {
+    /// Read header without the leading `@`
+    pub id: *const c_char,
+    pub seq: *const u8,
+    pub qual: *const u8,
+    /// Length of `seq` and `qual`
+    pub len: usize,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_fastq_open(
+    path: *const c_char,
+    out: *mut *mut SparcFastqReader,
+) -> c_int {
+    guard(|| {
+        let out = ptr_arg(out, "out")?;
+        let parser = FastqParser::open(str_arg(path, "path")?)?;
+        *out = Box::into_raw(Box::new(SparcFastqReader {
+            parser,
+            record: None,
+            id: CString::default(),
+        }));
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_fastq_next(
+    reader: *mut SparcFastqReader,
+    record: *mut SparcFastqRecord,
+) -> c_int {
+    guard(|| {
+        let reader = ptr_arg(reader, "reader")?;
+        let out = ptr_arg(record, "record")?;
+        let Some(next) = reader.parser.next() else {
+            reader.record = None;
+            return Ok(SPARC_END);
+        };
+        let next = next?;
+        if next.qual.len() != next.seq.len() {
+            return Err(FfiError::new(
+                crate::SPARC_ERR_CORE,
+                format!("record {}: sequence and quality lengths differ", next.id),
+            ));
+        }
+        reader.id = to_cstring(&next.id);
+        let current = reader.record.insert(next);
+        *out = SparcFastqRecord {
+            id: reader.id.as_ptr(),
+            seq: current.seq.as_ptr(),
+            qual: current.qual.as_ptr(),
+            len: current.seq.len(),
+        };
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_fastq_free(reader: *mut SparcFastqReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
@@ -0,0 +1,295 @@
+//! C ABI for SPARC
+//!
+//! Exposes FASTQ parsing, barcode correction, UMI deduplication, and count
+//! matrix building through opaque handles and integer status codes, so SPARC
+//! can back bindings in R, Julia, or existing C++ pipelines. The matching
+//! declarations are in `include/sparc.h`.
+//!
+//! Every function returns `SPARC_OK`, an informational code (`SPARC_END`,
+//! `SPARC_NO_MATCH`), or a negative error, and writes results through
+//! out-pointers. After an error, `sparc_last_error` describes it. Panics never
+//! cross the boundary; they are reported as `SPARC_ERR_PANIC`.
+
+// Pointer contracts are documented per function in include/sparc.h
+#![allow(clippy::missing_safety_doc)]
+
+pub mod barcode;
+pub mod fastq;
+pub mod matrix;
+pub mod umi;
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Success
+pub const SPARC_OK: c_int = 0;
+/// No more records
+pub const SPARC_END: c_int = 1;
+/// Barcode is neither in nor correctable to the whitelist
+pub const SPARC_NO_MATCH: c_int = 2;
+/// A required pointer argument was NULL
+pub const SPARC_ERR_NULL: c_int = -1;
+/// An argument was invalid (bad UTF-8, buffer too small, ...)
+pub const SPARC_ERR_ARGUMENT: c_int = -2;
+/// File system error
+pub const SPARC_ERR_IO: c_int = -3;
+/// Error reported by the SPARC core library (parsing, format, ...)
+pub const SPARC_ERR_CORE: c_int = -4;
+/// Internal panic
+pub const SPARC_ERR_PANIC: c_int = -5;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Error carried back across the boundary as a status code and message
+pub(crate) struct FfiError {
+    code: c_int,
+    message: String,
+}
+
+impl FfiError {
+    pub(crate) fn new(code: c_int, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn null(name: &str) -> Self {
+        Self::new(SPARC_ERR_NULL, format!("{} must not be NULL", name))
+    }
+}
+
+impl From<sparc_core::Error> for FfiError {
+    fn from(e: sparc_core::Error) -> Self {
+        let code = match e {
+            sparc_core::Error::Io(_) => SPARC_ERR_IO,
+            _ => SPARC_ERR_CORE,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<std::io::Error> for FfiError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(SPARC_ERR_IO, e.to_string())
+    }
+}
+
+pub(crate) type FfiResult = Result<c_int, FfiError>;
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Run an FFI body, turning errors and panics into status codes
+pub(crate) fn guard<F: FnOnce() -> FfiResult>(f: F) -> c_int {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(code)) => code,
+        Ok(Err(e)) => {
+            set_last_error(&e.message);
+            e.code
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(&format!("panic: {}", message));
+            SPARC_ERR_PANIC
+        }
+    }
+}
+
+/// Borrow a NUL-terminated UTF-8 string argument
+pub(crate) unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::null(name));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| FfiError::new(SPARC_ERR_ARGUMENT, format!("{} is not valid UTF-8", name)))
+}
+
+/// Borrow a pointer argument (handle or out-pointer)
+pub(crate) unsafe fn ptr_arg<'a, T>(ptr: *mut T, name: &str) -> Result<&'a mut T, FfiError> {
+    ptr.as_mut().ok_or_else(|| FfiError::null(name))
+}
+
+/// Borrow a read-only handle argument
+pub(crate) unsafe fn ref_arg<'a, T>(ptr: *const T, name: &str) -> Result<&'a T, FfiError> {
+    ptr.as_ref().ok_or_else(|| FfiError::null(name))
+}
+
+/// Borrow an array of `n` C strings
+pub(crate) unsafe fn str_array<'a>(
+    ptr: *const *const c_char,
+    n: usize,
+    name: &str,
+) -> Result<Vec<&'a str>, FfiError> {
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+    if ptr.is_null() {
+        return Err(FfiError::null(name));
+    }
+    std::slice::from_raw_parts(ptr, n)
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| {
+            str_arg(s, name)
+                .map_err(|e| FfiError::new(e.code, format!("{} (element {})", e.message, i)))
+        })
+        .collect()
+}
+
+/// C string for a Rust string, dropping interior NULs
+pub(crate) fn to_cstring(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
+
+/// Message for the last error on this thread, or NULL if none.
+/// Valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn sparc_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(std::ptr::null(), |s| s.as_ptr()))
+}
+
+/// Library version (static string)
+#[no_mangle]
+pub extern "C" fn sparc_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::barcode::*;
+    use super::matrix::*;
+    use super::umi::*;
+    use super::*;
+    use std::ptr;
+
+    fn cstrings(values: &[&str]) -> Vec<CString> {
+        values.iter().map(|s| CString::new(*s).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_barcode_correction() {
+        let barcodes = cstrings(&["AAAACCCC", "GGGGTTTT"]);
+        let ptrs: Vec<*const c_char> = barcodes.iter().map(|s| s.as_ptr()).collect();
+        unsafe {
+            let mut whitelist = ptr::null_mut();
+            assert_eq!(sparc_whitelist_from_array(ptrs.as_ptr(), 2, &mut whitelist), SPARC_OK);
+            let mut corrector = ptr::null_mut();
+            assert_eq!(sparc_corrector_new(whitelist, 1, &mut corrector), SPARC_OK);
+            sparc_whitelist_free(whitelist);
+
+            let mut buf = [0 as c_char; 9];
+            let mut distance = 0u32;
+            let query = CString::new("AAAACCCG").unwrap();
+            let out = buf.as_mut_ptr();
+            let status = sparc_corrector_correct(corrector, query.as_ptr(), out, 9, &mut distance);
+            assert_eq!(status, SPARC_OK);
+            assert_eq!(CStr::from_ptr(buf.as_ptr()).to_str().unwrap(), "AAAACCCC");
+            assert_eq!(distance, 1);
+
+            let far = CString::new("ACGTACGT").unwrap();
+            let status = sparc_corrector_correct(corrector, far.as_ptr(), out, 9, ptr::null_mut());
+            assert_eq!(status, SPARC_NO_MATCH);
+
+            // Buffer too small for the barcode and its NUL
+            let status =
+                sparc_corrector_correct(corrector, query.as_ptr(), out, 8, ptr::null_mut());
+            assert_eq!(status, SPARC_ERR_ARGUMENT);
+            assert!(!sparc_last_error().is_null());
+            sparc_corrector_free(corrector);
+        }
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        unsafe {
+            let mut whitelist = ptr::null_mut();
+            assert_eq!(sparc_whitelist_load(ptr::null(), &mut whitelist), SPARC_ERR_NULL);
+            let message = CStr::from_ptr(sparc_last_error()).to_str().unwrap();
+            assert!(message.contains("path"));
+            let path = CString::new("/nonexistent/whitelist.txt").unwrap();
+            assert_eq!(sparc_whitelist_load(path.as_ptr(), &mut whitelist), SPARC_ERR_IO);
+            sparc_whitelist_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_umi_dedup() {
+        let umis = cstrings(&["AAAA", "AAAT", "GGGG", "AAAA"]);
+        let ptrs: Vec<*const c_char> = umis.iter().map(|s| s.as_ptr()).collect();
+        let counts = [10u32, 1, 5, 2];
+        let mut n_molecules = 0usize;
+        let mut molecule_of = [0usize; 4];
+        unsafe {
+            let status = sparc_umi_dedup(
+                ptrs.as_ptr(),
+                counts.as_ptr(),
+                4,
+                1,
+                &mut n_molecules,
+                molecule_of.as_mut_ptr(),
+            );
+            assert_eq!(status, SPARC_OK);
+        }
+        assert_eq!(n_molecules, 2);
+        assert_eq!(molecule_of[0], molecule_of[1]);
+        assert_eq!(molecule_of[0], molecule_of[3]);
+        assert_ne!(molecule_of[0], molecule_of[2]);
+    }
+
+    #[test]
+    fn test_counter_to_matrix() {
+        let names = cstrings(&["CELL1", "CELL2", "GENE1", "GENE2"]);
+        let dir = tempfile::tempdir().unwrap();
+        let dir_c = CString::new(dir.path().to_str().unwrap()).unwrap();
+        unsafe {
+            let mut counter = ptr::null_mut();
+            assert_eq!(sparc_counter_new(&mut counter), SPARC_OK);
+            for (cell, gene, n) in [(0, 2, 3), (1, 2, 1), (1, 3, 4), (0, 2, 1)] {
+                let status =
+                    sparc_counter_add(counter, names[cell].as_ptr(), names[gene].as_ptr(), n);
+                assert_eq!(status, SPARC_OK);
+            }
+            let mut matrix = ptr::null_mut();
+            assert_eq!(sparc_counter_build(counter, &mut matrix), SPARC_OK);
+
+            let (mut n_genes, mut n_cells, mut nnz) = (0, 0, 0);
+            assert_eq!(sparc_matrix_shape(matrix, &mut n_genes, &mut n_cells, &mut nnz), SPARC_OK);
+            assert_eq!((n_genes, n_cells, nnz), (2, 2, 3));
+            let mut rows = vec![0usize; nnz];
+            let mut cols = vec![0usize; nnz];
+            let mut values = vec![0u32; nnz];
+            let status =
+                sparc_matrix_coo(matrix, rows.as_mut_ptr(), cols.as_mut_ptr(), values.as_mut_ptr());
+            assert_eq!(status, SPARC_OK);
+            let gene1_cell1 = (0..nnz)
+                .find(|&i| {
+                    CStr::from_ptr(sparc_matrix_gene(matrix, rows[i])).to_str() == Ok("GENE1")
+                        && CStr::from_ptr(sparc_matrix_barcode(matrix, cols[i])).to_str()
+                            == Ok("CELL1")
+                })
+                .unwrap();
+            assert_eq!(values[gene1_cell1], 4);
+            assert!(sparc_matrix_gene(matrix, 5).is_null());
+
+            assert_eq!(sparc_matrix_write_mtx(matrix, dir_c.as_ptr()), SPARC_OK);
+            sparc_matrix_free(matrix);
+
+            let mut reread = ptr::null_mut();
+            assert_eq!(sparc_matrix_read_mtx(dir_c.as_ptr(), &mut reread), SPARC_OK);
+            assert_eq!(sparc_matrix_shape(reread, &mut n_genes, &mut n_cells, &mut nnz), SPARC_OK);
+            assert_eq!((n_genes, n_cells, nnz), (2, 2, 3));
+            sparc_matrix_free(reread);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Count matrix building and access
+
+use crate::{guard, ptr_arg, ref_arg, str_arg, to_cstring, FfiError, SPARC_OK};
+use sparc_core::count::{CountMatrix, GeneCounter};
+use std::ffi::{c_char, c_int, CString};
+
+/// Accumulates (barcode, gene) counts
+pub struct SparcCounter(GeneCounter);
+
+/// Gene x cell count matrix (COO), with C copies of its names
+pub struct SparcMatrix {
+    matrix: CountMatrix,
+    genes: Vec<CString>,
+    barcodes: Vec<CString>,
+}
+
+impl SparcMatrix {
+    fn new(matrix: CountMatrix) -> Self {
+        Self {
+            genes: matrix.genes.iter().map(|g| to_cstring(g.as_str())).collect(),
+            barcodes: matrix.barcodes.iter().map(|b| to_cstring(b.as_str())).collect(),
+            matrix,
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_counter_new(out: *mut *mut SparcCounter) -> c_int {
+    guard(|| {
+        *ptr_arg(out, "out")? = Box::into_raw(Box::new(SparcCounter(GeneCounter::new())));
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_counter_add(
+    counter: *mut SparcCounter,
+    barcode: *const c_char,
+    gene: *const c_char,
+    count: u32,
+) -> c_int {
+    guard(|| {
+        let counter = ptr_arg(counter, "counter")?;
+        counter.0.add_count(str_arg(barcode, "barcode")?, str_arg(gene, "gene")?, count);
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_counter_build(
+    counter: *mut SparcCounter,
+    out: *mut *mut SparcMatrix,
+) -> c_int {
+    guard(|| {
+        if counter.is_null() {
+            return Err(FfiError::null("counter"));
+        }
+        let counter = Box::from_raw(counter);
+        let out = ptr_arg(out, "out")?;
+        let matrix = counter.0.try_build()?;
+        *out = Box::into_raw(Box::new(SparcMatrix::new(matrix)));
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_counter_free(counter: *mut SparcCounter) {
+    if !counter.is_null() {
+        drop(Box::from_raw(counter));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_matrix_read_mtx(
+    dir: *const c_char,
+    out: *mut *mut SparcMatrix,
+) -> c_int {
+    guard(|| {
+        let out = ptr_arg(out, "out")?;
+        let matrix = CountMatrix::read_mtx(str_arg(dir, "dir")?)?;
+        *out = Box::into_raw(Box::new(SparcMatrix::new(matrix)));
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_matrix_write_mtx(
+    matrix: *const SparcMatrix,
+    dir: *const c_char,
+) -> c_int {
+    guard(|| {
+        let matrix = &ref_arg(matrix, "matrix")?.matrix;
+        let dir = std::path::Path::new(str_arg(dir, "dir")?);
+        std::fs::create_dir_all(dir)?;
+        matrix.write_mtx(dir.join("matrix.mtx"))?;
+        matrix.write_barcodes(dir.join("barcodes.tsv"))?;
+        matrix.write_genes(dir.join("genes.tsv"))?;
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_matrix_shape(
+    matrix: *const SparcMatrix,
+    n_genes: *mut usize,
+    n_cells: *mut usize,
+    nnz: *mut usize,
+) -> c_int {
+    guard(|| {
+        let matrix = &ref_arg(matrix, "matrix")?.matrix;
+        *ptr_arg(n_genes, "n_genes")? = matrix.n_rows;
+        *ptr_arg(n_cells, "n_cells")? = matrix.n_cols;
+        *ptr_arg(nnz, "nnz")? = matrix.values.len();
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_matrix_coo(
+    matrix: *const SparcMatrix,
+    rows: *mut usize,
+    cols: *mut usize,
+    values: *mut u32,
+) -> c_int {
+    guard(|| {
+        let matrix = &ref_arg(matrix, "matrix")?.matrix;
+        let nnz = matrix.values.len();
+        if nnz == 0 {
+            return Ok(SPARC_OK);
+        }
+        if rows.is_null() || cols.is_null() || values.is_null() {
+            return Err(FfiError::null("rows, cols, and values"));
+        }
+        std::slice::from_raw_parts_mut(rows, nnz).copy_from_slice(&matrix.rows);
+        std::slice::from_raw_parts_mut(cols, nnz).copy_from_slice(&matrix.cols);
+        std::slice::from_raw_parts_mut(values, nnz).copy_from_slice(&matrix.values);
+        Ok(SPARC_OK)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_matrix_gene(
+    matrix: *const SparcMatrix,
+    idx: usize,
+) -> *const c_char {
+    matrix
+        .as_ref()
+        .and_then(|m| m.genes.get(idx))
+        .map_or(std::ptr::null(), |s| s.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_matrix_barcode(
+    matrix: *const SparcMatrix,
+    idx: usize,
+) -> *const c_char {
+    matrix
+        .as_ref()
+        .and_then(|m| m.barcodes.get(idx))
+        .map_or(std::ptr::null(), |s| s.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_matrix_free(matrix: *mut SparcMatrix) {
+    if !matrix.is_null() {
+        drop(Box::from_raw(matrix));
+    }
+}
@@ -0,0 +1,54 @@
+//! UMI deduplication
+
+use crate::{guard, ptr_arg, str_array, SPARC_OK};
+use sparc_core::umi::{Umi, UmiDeduplicator};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int};
+
+#[no_mangle]
+pub unsafe extern "C" fn sparc_umi_dedup(
+    umis: *const *const c_char,
+    counts: *const u32,
+    n: usize,
+    max_distance: u32,
+    n_molecules: *mut usize,
+    molecule_of: *mut usize,
+) -> c_int {
+    guard(|| {
+        let n_molecules = ptr_arg(n_molecules, "n_molecules")?;
+        let umis = str_array(umis, n, "umis")?;
+        let counts = if counts.is_null() || n == 0 {
+            None
+        } else {
+            Some(std::slice::from_raw_parts(counts, n))
+        };
+
+        // Collapse repeated sequences, keeping first-seen order
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        let mut unique: Vec<Umi> = Vec::new();
+        for (i, &umi) in umis.iter().enumerate() {
+            let count = counts.map_or(1, |c| c[i]);
+            let idx = *index.entry(umi).or_insert_with(|| {
+                unique.push(Umi::with_count(umi.to_string(), 0));
+                unique.len() - 1
+            });
+            unique[idx].count += count;
+        }
+
+        let groups = UmiDeduplicator::new(max_distance).deduplicate(&unique);
+        *n_molecules = groups.len();
+        if !molecule_of.is_null() && n > 0 {
+            let mut group_of: HashMap<&str, usize> = HashMap::new();
+            for (g, group) in groups.iter().enumerate() {
+                for member in &group.members {
+                    group_of.insert(member.sequence.as_str(), g);
+                }
+            }
+            let out = std::slice::from_raw_parts_mut(molecule_of, n);
+            for (slot, umi) in out.iter_mut().zip(&umis) {
+                *slot = group_of[umi];
+            }
+        }
+        Ok(SPARC_OK)
+    })
+}
@@ -15,8 +15,10 @@ use sparc_core::analysis::{
 #[pyo3(signature = (data, target_sum = 10000.0))]
 pub fn py_normalize_total<'py>(py: Python<'py>, data: Vec<Vec<f64>>, target_sum: f64) -> &'py PyArray2<f64> {
     let mut d = data;
-    normalize_total(&mut d, target_sum);
-    log1p_transform(&mut d);
+    py.allow_threads(|| {
+        normalize_total(&mut d, target_sum);
+        log1p_transform(&mut d);
+    });
     vec_to_array2(py, &d)
 }
 
@@ -25,37 +27,37 @@ pub fn py_normalize_total<'py>(py: Python<'py>, data: Vec<Vec<f64>>, target_sum:
 #[pyo3(signature = (data, max_value = None))]
 pub fn py_scale<'py>(py: Python<'py>, data: Vec<Vec<f64>>, max_value: Option<f64>) -> &'py PyArray2<f64> {
     let mut d = data;
-    scale(&mut d, max_value);
+    py.allow_threads(|| scale(&mut d, max_value));
     vec_to_array2(py, &d)
 }
 
 /// Find highly variable genes. Returns list of gene indices.
 #[pyfunction]
 #[pyo3(signature = (data, min_mean = 0.0125, max_mean = 3.0, min_disp = 0.5))]
-pub fn py_highly_variable_genes(data: Vec<Vec<f64>>, min_mean: f64, max_mean: f64, min_disp: f64) -> Vec<usize> {
-    highly_variable_genes(&data, min_mean, max_mean, min_disp)
+pub fn py_highly_variable_genes(py: Python<'_>, data: Vec<Vec<f64>>, min_mean: f64, max_mean: f64, min_disp: f64) -> Vec<usize> {
+    py.allow_threads(|| highly_variable_genes(&data, min_mean, max_mean, min_disp))
 }
 
 /// Run PCA. Returns (scores: n_cells x n_pcs, variances: n_pcs).
 #[pyfunction]
 #[pyo3(signature = (data, n_components = 50))]
 pub fn py_pca<'py>(py: Python<'py>, data: Vec<Vec<f64>>, n_components: usize) -> (&'py PyArray2<f64>, &'py PyArray1<f64>) {
-    let (scores, variances) = pca(&data, n_components);
+    let (scores, variances) = py.allow_threads(|| pca(&data, n_components));
     (vec_to_array2(py, &scores), variances.to_pyarray(py))
 }
 
 /// Build KNN graph. Returns list of (neighbor_index, distance) per cell.
 #[pyfunction]
 #[pyo3(signature = (coords, k = 15))]
-pub fn py_build_knn_graph(coords: Vec<Vec<f64>>, k: usize) -> Vec<Vec<(usize, f64)>> {
-    build_knn_graph(&coords, k)
+pub fn py_build_knn_graph(py: Python<'_>, coords: Vec<Vec<f64>>, k: usize) -> Vec<Vec<(usize, f64)>> {
+    py.allow_threads(|| build_knn_graph(&coords, k))
 }
 
 /// Run label propagation clustering. Returns cluster labels.
 #[pyfunction]
 #[pyo3(signature = (graph, resolution = 1.0, max_iterations = 100))]
-pub fn py_label_propagation(graph: Vec<Vec<(usize, f64)>>, resolution: f64, max_iterations: usize) -> Vec<usize> {
-    label_propagation(&graph, resolution, max_iterations)
+pub fn py_label_propagation(py: Python<'_>, graph: Vec<Vec<(usize, f64)>>, resolution: f64, max_iterations: usize) -> Vec<usize> {
+    py.allow_threads(|| label_propagation(&graph, resolution, max_iterations))
 }
 
 /// Run full analysis pipeline: normalize -> HVG -> scale -> PCA -> KNN -> cluster.
@@ -69,6 +71,17 @@ pub fn py_run_analysis<'py>(
     n_neighbors: usize,
     resolution: f64,
 ) -> (&'py PyArray2<f64>, Vec<usize>, Vec<usize>) {
+    let (pca_coords, labels, hvg) = py.allow_threads(|| run_analysis(data, n_pcs, n_neighbors, resolution));
+    (vec_to_array2(py, &pca_coords), labels, hvg)
+}
+
+/// Analysis steps behind `py_run_analysis`, run without the GIL
+fn run_analysis(
+    data: Vec<Vec<f64>>,
+    n_pcs: usize,
+    n_neighbors: usize,
+    resolution: f64,
+) -> (Vec<Vec<f64>>, Vec<usize>, Vec<usize>) {
     let mut d = data;
     let n_cells = d.len();
 
@@ -91,7 +104,7 @@ pub fn py_run_analysis<'py>(
     let graph = build_knn_graph(&pca_coords, k);
     let labels = label_propagation(&graph, resolution, 100);
 
-    (vec_to_array2(py, &pca_coords), labels, hvg)
+    (pca_coords, labels, hvg)
 }
 
 fn vec_to_array2<'py>(py: Python<'py>, data: &[Vec<f64>]) -> &'py PyArray2<f64> {
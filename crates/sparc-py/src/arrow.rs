@@ -0,0 +1,194 @@
+//! Arrow record batches for the `to_arrow_batches` methods
+//!
+//! Columns are assembled in Rust as Arrow buffers (offsets, data, validity)
+//! and handed to pyarrow without a per-value copy or Python object.
+
+use numpy::IntoPyArray;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyModule};
+
+use crate::bam::PyBamParser;
+use crate::fastq::{PyFastqParser, PyPairedFastqParser};
+
+/// Import pyarrow, or explain how to install it
+pub(crate) fn import_pyarrow<'py>(py: Python<'py>, method: &str) -> PyResult<&'py PyModule> {
+    py.import("pyarrow").map_err(|_| {
+        PyErr::new::<pyo3::exceptions::PyImportError, _>(format!(
+            "{}() requires pyarrow (pip install sparc-sc[arrow])",
+            method
+        ))
+    })
+}
+
+/// Columns of one record batch, in order
+pub(crate) struct BatchBuilder<'py> {
+    py: Python<'py>,
+    pyarrow: &'py PyModule,
+    names: Vec<&'static str>,
+    arrays: Vec<PyObject>,
+}
+
+impl<'py> BatchBuilder<'py> {
+    pub(crate) fn new(py: Python<'py>, pyarrow: &'py PyModule) -> Self {
+        Self {
+            py,
+            pyarrow,
+            names: Vec::new(),
+            arrays: Vec::new(),
+        }
+    }
+
+    /// Add a `large_binary` column
+    pub(crate) fn binary<'a>(
+        &mut self,
+        name: &'static str,
+        values: impl ExactSizeIterator<Item = &'a [u8]>,
+    ) -> PyResult<()> {
+        let array = self.variable("large_binary", values.map(Some))?;
+        self.push(name, array)
+    }
+
+    /// Add a `large_string` column; `None` values are null
+    pub(crate) fn string<'a>(
+        &mut self,
+        name: &'static str,
+        values: impl ExactSizeIterator<Item = Option<&'a str>>,
+    ) -> PyResult<()> {
+        let array = self.variable("large_string", values.map(|v| v.map(str::as_bytes)))?;
+        self.push(name, array)
+    }
+
+    /// Add a numeric or boolean column from a numpy-compatible vector
+    pub(crate) fn primitive<T: numpy::Element>(
+        &mut self,
+        name: &'static str,
+        values: Vec<T>,
+    ) -> PyResult<()> {
+        let array = self.pyarrow.call_method1("array", (values.into_pyarray(self.py),))?;
+        self.push(name, array.into())
+    }
+
+    /// Finish as a `pyarrow.RecordBatch`
+    pub(crate) fn finish(self) -> PyResult<PyObject> {
+        let kwargs = PyDict::new(self.py);
+        kwargs.set_item("names", self.names)?;
+        let batch = self
+            .pyarrow
+            .getattr("RecordBatch")?
+            .call_method("from_arrays", (self.arrays,), Some(kwargs))?;
+        Ok(batch.into())
+    }
+
+    fn push(&mut self, name: &'static str, array: PyObject) -> PyResult<()> {
+        self.names.push(name);
+        self.arrays.push(array);
+        Ok(())
+    }
+
+    /// Build a variable-length array of type `pyarrow.<type_name>()` from
+    /// 64-bit offsets, the concatenated values, and a validity bitmap (only
+    /// when a value is missing)
+    fn variable<'a>(
+        &self,
+        type_name: &str,
+        values: impl ExactSizeIterator<Item = Option<&'a [u8]>>,
+    ) -> PyResult<PyObject> {
+        let len = values.len();
+        let mut offsets = Vec::with_capacity(len + 1);
+        let mut data = Vec::new();
+        let mut validity = vec![0u8; (len + 7) / 8];
+        let mut null_count = 0usize;
+        offsets.push(0i64);
+        for (i, value) in values.enumerate() {
+            match value {
+                Some(value) => {
+                    data.extend_from_slice(value);
+                    validity[i / 8] |= 1 << (i % 8);
+                }
+                None => null_count += 1,
+            }
+            offsets.push(data.len() as i64);
+        }
+
+        let buffer = |bytes: Vec<u8>| -> PyResult<PyObject> {
+            Ok(self.pyarrow.call_method1("py_buffer", (bytes.into_pyarray(self.py),))?.into())
+        };
+        let validity = if null_count > 0 { buffer(validity)? } else { self.py.None() };
+        let offsets = self
+            .pyarrow
+            .call_method1("py_buffer", (offsets.into_pyarray(self.py),))?
+            .into();
+        let buffers: Vec<PyObject> = vec![validity, offsets, buffer(data)?];
+
+        let data_type = self.pyarrow.call_method0(type_name)?;
+        let kwargs = PyDict::new(self.py);
+        kwargs.set_item("null_count", null_count)?;
+        let array = self.pyarrow.getattr("Array")?.call_method(
+            "from_buffers",
+            (data_type, len, buffers),
+            Some(kwargs),
+        )?;
+        Ok(array.into())
+    }
+}
+
+/// The parser an `ArrowBatches` iterator reads from
+enum Source {
+    Fastq(Py<PyFastqParser>),
+    PairedFastq(Py<PyPairedFastqParser>),
+    Bam(Py<PyBamParser>),
+}
+
+/// Iterator of `pyarrow.RecordBatch`es of up to `batch_size` records, as
+/// returned by the parsers' `to_arrow_batches`
+#[pyclass(name = "ArrowBatches")]
+pub struct PyArrowBatches {
+    source: Source,
+    batch_size: usize,
+}
+
+impl PyArrowBatches {
+    fn new(py: Python<'_>, source: Source, batch_size: usize) -> PyResult<Self> {
+        import_pyarrow(py, "to_arrow_batches")?;
+        if batch_size == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "batch_size must be at least 1",
+            ));
+        }
+        Ok(Self { source, batch_size })
+    }
+
+    pub(crate) fn fastq(py: Python<'_>, parser: Py<PyFastqParser>, n: usize) -> PyResult<Self> {
+        Self::new(py, Source::Fastq(parser), n)
+    }
+
+    pub(crate) fn paired_fastq(
+        py: Python<'_>,
+        parser: Py<PyPairedFastqParser>,
+        n: usize,
+    ) -> PyResult<Self> {
+        Self::new(py, Source::PairedFastq(parser), n)
+    }
+
+    pub(crate) fn bam(py: Python<'_>, parser: Py<PyBamParser>, n: usize) -> PyResult<Self> {
+        Self::new(py, Source::Bam(parser), n)
+    }
+}
+
+#[pymethods]
+impl PyArrowBatches {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(text_signature = "($self) -> pa.RecordBatch")]
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let pyarrow = import_pyarrow(py, "to_arrow_batches")?;
+        let n = self.batch_size;
+        match &self.source {
+            Source::Fastq(parser) => parser.try_borrow_mut(py)?.arrow_batch(py, pyarrow, n),
+            Source::PairedFastq(parser) => parser.try_borrow_mut(py)?.arrow_batch(py, pyarrow, n),
+            Source::Bam(parser) => parser.try_borrow_mut(py)?.arrow_batch(py, pyarrow, n),
+        }
+    }
+}
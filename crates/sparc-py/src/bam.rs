@@ -1,8 +1,13 @@
 //! BAM Python bindings
 
+use numpy::IntoPyArray;
 use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyModule};
 use sparc_core::bam::{BamParser, BamRecord};
 
+use crate::arrow::{BatchBuilder, PyArrowBatches};
+use crate::columns::bytes_column;
+
 /// Python wrapper for BamRecord
 #[pyclass(name = "BamRecord")]
 pub struct PyBamRecord {
@@ -114,6 +119,33 @@ impl PyBamParser {
         self.inner.reference_names()
     }
 
+    /// Get reference lengths from header, in the same order as `reference_names()`
+    fn reference_lengths(&self) -> Vec<u64> {
+        self.inner.reference_lengths()
+    }
+
+    /// Full SAM header text
+    #[getter]
+    fn header(&self) -> String {
+        String::from_utf8_lossy(&self.inner.header().to_bytes()).to_string()
+    }
+
+    /// Records overlapping `chrom:start-end` (0-based, half-open). Requires a
+    /// `.bai`/`.csi` index and does not move the iteration position.
+    fn fetch(
+        &mut self,
+        py: Python<'_>,
+        chrom: &str,
+        start: i64,
+        end: i64,
+    ) -> PyResult<Vec<PyBamRecord>> {
+        let parser = &mut self.inner;
+        let records = py
+            .allow_threads(|| parser.fetch(chrom, start, end))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        Ok(records.into_iter().map(|r| PyBamRecord { inner: r }).collect())
+    }
+
     fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
         slf
     }
@@ -126,21 +158,107 @@ impl PyBamParser {
         }
     }
 
-    /// Read all records into a list
-    fn read_all(&mut self) -> PyResult<Vec<PyBamRecord>> {
-        let records = self
-            .inner
-            .read_all()
+    /// Read all records into a list (parsing runs without the GIL)
+    fn read_all(&mut self, py: Python<'_>) -> PyResult<Vec<PyBamRecord>> {
+        let parser = &mut self.inner;
+        let records = py
+            .allow_threads(|| parser.read_all())
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
         Ok(records.into_iter().map(|r| PyBamRecord { inner: r }).collect())
     }
 
+    /// Read up to `n` records as columns: `name`, `seq`, `qual`, `cigar`, and
+    /// the `CB`/`UB`/`GN`/`GX` tags as numpy fixed-width bytes arrays (empty
+    /// when a tag is missing), plus numeric `mapq`, `tid`, `pos`,
+    /// `is_mapped`, and `is_reverse`. An empty batch marks the end of the file.
+    #[pyo3(text_signature = "($self, n: int) -> dict[str, npt.NDArray[Any]]")]
+    fn read_chunk(&mut self, py: Python<'_>, n: usize) -> PyResult<PyObject> {
+        let records = self.take(py, n)?;
+        let tag = |f: fn(&BamRecord) -> &Option<String>| {
+            records.iter().map(move |r| f(r).as_deref().unwrap_or("").as_bytes())
+        };
+        let batch = PyDict::new(py);
+        batch.set_item("name", bytes_column(py, records.iter().map(|r| r.name.as_bytes()))?)?;
+        batch.set_item("seq", bytes_column(py, records.iter().map(|r| r.seq.as_slice()))?)?;
+        batch.set_item("qual", bytes_column(py, records.iter().map(|r| r.qual.as_slice()))?)?;
+        batch.set_item("cigar", bytes_column(py, records.iter().map(|r| r.cigar.as_bytes()))?)?;
+        batch.set_item("CB", bytes_column(py, tag(|r| &r.cell_barcode))?)?;
+        batch.set_item("UB", bytes_column(py, tag(|r| &r.umi))?)?;
+        batch.set_item("GN", bytes_column(py, tag(|r| &r.gene_name))?)?;
+        batch.set_item("GX", bytes_column(py, tag(|r| &r.gene_id))?)?;
+        batch.set_item("mapq", records.iter().map(|r| r.mapq).collect::<Vec<u8>>().into_pyarray(py))?;
+        batch.set_item("tid", records.iter().map(|r| r.tid).collect::<Vec<i32>>().into_pyarray(py))?;
+        batch.set_item("pos", records.iter().map(|r| r.pos).collect::<Vec<i64>>().into_pyarray(py))?;
+        batch.set_item(
+            "is_mapped",
+            records.iter().map(|r| r.is_mapped).collect::<Vec<bool>>().into_pyarray(py),
+        )?;
+        batch.set_item(
+            "is_reverse",
+            records.iter().map(|r| r.is_reverse).collect::<Vec<bool>>().into_pyarray(py),
+        )?;
+        Ok(batch.into())
+    }
+
     /// Filter records by mapping quality
-    fn filter_by_mapq(&mut self, min_mapq: u8) -> PyResult<Vec<PyBamRecord>> {
-        let records = self
-            .inner
-            .filter_by_mapq(min_mapq)
+    fn filter_by_mapq(&mut self, py: Python<'_>, min_mapq: u8) -> PyResult<Vec<PyBamRecord>> {
+        let parser = &mut self.inner;
+        let records = py
+            .allow_threads(|| parser.filter_by_mapq(min_mapq))
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
         Ok(records.into_iter().map(|r| PyBamRecord { inner: r }).collect())
     }
+
+    /// Iterate over `pyarrow.RecordBatch`es of up to `batch_size` records
+    /// with columns `id` (read name), `cigar`, and the `CB`/`UB`/`GN`/`GX`
+    /// tags as strings (null when a tag is missing), `seq` and `qual` as
+    /// binary, and numeric `mapq`, `tid`, `pos`, `is_mapped`, and `is_reverse`
+    #[pyo3(signature = (batch_size = 100_000))]
+    fn to_arrow_batches(
+        slf: Py<Self>,
+        py: Python<'_>,
+        batch_size: usize,
+    ) -> PyResult<PyArrowBatches> {
+        PyArrowBatches::bam(py, slf, batch_size)
+    }
+}
+
+impl PyBamParser {
+    /// Parse up to `n` records without the GIL
+    fn take(&mut self, py: Python<'_>, n: usize) -> PyResult<Vec<BamRecord>> {
+        let parser = &mut self.inner;
+        py.allow_threads(|| parser.by_ref().take(n).collect::<sparc_core::Result<Vec<_>>>())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+    }
+
+    /// The next `to_arrow_batches` batch, or `None` at the end of the file
+    pub(crate) fn arrow_batch(
+        &mut self,
+        py: Python<'_>,
+        pyarrow: &PyModule,
+        n: usize,
+    ) -> PyResult<Option<PyObject>> {
+        let records = self.take(py, n)?;
+        if records.is_empty() {
+            return Ok(None);
+        }
+        let tag = |f: fn(&BamRecord) -> &Option<String>| {
+            records.iter().map(move |r| f(r).as_deref())
+        };
+        let mut batch = BatchBuilder::new(py, pyarrow);
+        batch.string("id", records.iter().map(|r| Some(r.name.as_str())))?;
+        batch.binary("seq", records.iter().map(|r| r.seq.as_slice()))?;
+        batch.binary("qual", records.iter().map(|r| r.qual.as_slice()))?;
+        batch.string("cigar", records.iter().map(|r| Some(r.cigar.as_str())))?;
+        batch.string("CB", tag(|r| &r.cell_barcode))?;
+        batch.string("UB", tag(|r| &r.umi))?;
+        batch.string("GN", tag(|r| &r.gene_name))?;
+        batch.string("GX", tag(|r| &r.gene_id))?;
+        batch.primitive("mapq", records.iter().map(|r| r.mapq).collect::<Vec<u8>>())?;
+        batch.primitive("tid", records.iter().map(|r| r.tid).collect::<Vec<i32>>())?;
+        batch.primitive("pos", records.iter().map(|r| r.pos).collect::<Vec<i64>>())?;
+        batch.primitive("is_mapped", records.iter().map(|r| r.is_mapped).collect::<Vec<_>>())?;
+        batch.primitive("is_reverse", records.iter().map(|r| r.is_reverse).collect::<Vec<_>>())?;
+        batch.finish().map(Some)
+    }
 }
@@ -1,6 +1,8 @@
 //! Barcode Python bindings
 
 use pyo3::prelude::*;
+use pyo3::types::PyList;
+use rayon::prelude::*;
 use sparc_core::barcode::{BarcodeCorrector, BarcodeMatch, Whitelist};
 
 /// Python wrapper for Whitelist
@@ -27,11 +29,85 @@ impl PyWhitelist {
         Ok(Self { inner })
     }
 
+    /// Create whitelist from a numpy bytes array (dtype `S<n>`), e.g. a
+    /// `read_chunk` column or `np.loadtxt(path, dtype="S16")`. Empty values
+    /// (such as missing tags) are skipped.
+    #[staticmethod]
+    #[pyo3(text_signature = "(array: npt.NDArray[np.bytes_]) -> Whitelist")]
+    fn from_numpy(py: Python<'_>, array: &PyAny) -> PyResult<Self> {
+        let array = py.import("numpy")?.call_method1("ascontiguousarray", (array,))?;
+        let dtype = array.getattr("dtype")?;
+        if dtype.getattr("kind")?.extract::<&str>()? != "S" {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                "from_numpy expects a bytes array (dtype 'S'); use from_list for str values",
+            ));
+        }
+        let width: usize = dtype.getattr("itemsize")?.extract()?;
+        let raw: &[u8] = array.call_method0("tobytes")?.extract()?;
+        let barcodes = raw
+            .chunks(width.max(1))
+            .map(|item| {
+                let end = item.iter().position(|&b| b == 0).unwrap_or(item.len());
+                String::from_utf8_lossy(&item[..end]).into_owned()
+            })
+            .filter(|barcode| !barcode.is_empty())
+            .collect();
+        let inner = Whitelist::from_vec(barcodes)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
     /// Check if barcode is in whitelist
     fn contains(&self, barcode: &str) -> bool {
         self.inner.contains(barcode)
     }
 
+    fn __contains__(&self, barcode: &str) -> bool {
+        self.inner.contains(barcode)
+    }
+
+    /// Iterate over barcodes in sorted order
+    #[pyo3(text_signature = "($self) -> Iterator[str]")]
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let list = PyList::new(py, self.sorted());
+        Ok(list.call_method0("__iter__")?.into())
+    }
+
+    /// Barcodes in both whitelists
+    fn intersection(&self, other: &PyWhitelist) -> Self {
+        Self {
+            inner: self.inner.intersection(&other.inner),
+        }
+    }
+
+    /// Barcodes in either whitelist
+    fn union(&self, other: &PyWhitelist) -> PyResult<Self> {
+        let inner = self
+            .inner
+            .union(&other.inner)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    /// Barcodes in this whitelist but not in `other`
+    fn difference(&self, other: &PyWhitelist) -> Self {
+        Self {
+            inner: self.inner.difference(&other.inner),
+        }
+    }
+
+    fn __and__(&self, other: &PyWhitelist) -> Self {
+        self.intersection(other)
+    }
+
+    fn __or__(&self, other: &PyWhitelist) -> PyResult<Self> {
+        self.union(other)
+    }
+
+    fn __sub__(&self, other: &PyWhitelist) -> Self {
+        self.difference(other)
+    }
+
     /// Get number of barcodes
     fn __len__(&self) -> usize {
         self.inner.len()
@@ -52,6 +128,14 @@ impl PyWhitelist {
     }
 }
 
+impl PyWhitelist {
+    fn sorted(&self) -> Vec<&str> {
+        let mut barcodes: Vec<&str> = self.inner.iter().map(String::as_str).collect();
+        barcodes.sort_unstable();
+        barcodes
+    }
+}
+
 /// Python wrapper for BarcodeCorrector
 #[pyclass(name = "BarcodeCorrector")]
 pub struct PyBarcodeCorrector {
@@ -87,11 +171,14 @@ impl PyBarcodeCorrector {
         self.inner.match_barcode(barcode).barcode().map(|s| s.to_string())
     }
 
-    /// Batch correct barcodes
-    fn correct_batch(&self, barcodes: Vec<String>) -> Vec<Option<String>> {
-        barcodes
-            .iter()
-            .map(|bc| self.inner.match_barcode(bc).barcode().map(|s| s.to_string()))
-            .collect()
+    /// Batch correct barcodes in parallel, without holding the GIL
+    fn correct_batch(&self, py: Python<'_>, barcodes: Vec<String>) -> Vec<Option<String>> {
+        let corrector = &self.inner;
+        py.allow_threads(|| {
+            barcodes
+                .par_iter()
+                .map(|bc| corrector.match_barcode(bc).barcode().map(|s| s.to_string()))
+                .collect()
+        })
     }
 }
@@ -0,0 +1,19 @@
+//! Columnar record batches for the `read_chunk` methods
+
+use numpy::IntoPyArray;
+use pyo3::prelude::*;
+
+/// Pack byte strings into a numpy fixed-width bytes array (`S<max_len>`).
+/// Shorter values are NUL-padded, which numpy strips on access.
+pub(crate) fn bytes_column<'a>(
+    py: Python<'_>,
+    values: impl ExactSizeIterator<Item = &'a [u8]> + Clone,
+) -> PyResult<PyObject> {
+    let width = values.clone().map(<[u8]>::len).max().unwrap_or(0).max(1);
+    let mut buf = vec![0u8; values.len() * width];
+    for (slot, value) in buf.chunks_exact_mut(width).zip(values) {
+        slot[..value.len()].copy_from_slice(value);
+    }
+    let array = buf.into_pyarray(py);
+    Ok(array.call_method1("view", (format!("S{}", width),))?.into())
+}
@@ -1,7 +1,15 @@
 //! FASTQ Python bindings
 
+use numpy::IntoPyArray;
 use pyo3::prelude::*;
-use sparc_core::fastq::{FastqParser, FastqRecord, FastqWriter};
+use pyo3::types::{PyDict, PyModule};
+use sparc_core::fastq::{
+    FastqCompression, FastqParser, FastqRecord, FastqWriter, PairedFastqParser, WriterOptions,
+};
+use std::path::Path;
+
+use crate::arrow::{BatchBuilder, PyArrowBatches};
+use crate::columns::bytes_column;
 
 /// Python wrapper for FastqRecord
 #[pyclass(name = "FastqRecord")]
@@ -89,19 +97,169 @@ impl PyFastqParser {
         }
     }
 
-    /// Read all records into a list
-    fn read_all(&mut self) -> PyResult<Vec<PyFastqRecord>> {
-        let mut records = Vec::new();
-        while let Some(result) = self.inner.next() {
-            let record = result
-                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-            records.push(PyFastqRecord { inner: record });
+    /// Read all records into a list (parsing runs without the GIL)
+    fn read_all(&mut self, py: Python<'_>) -> PyResult<Vec<PyFastqRecord>> {
+        let parser = &mut self.inner;
+        let records = py
+            .allow_threads(|| parser.read_all())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        Ok(records.into_iter().map(|inner| PyFastqRecord { inner }).collect())
+    }
+
+    /// Read up to `n` records as columns: `id`, `seq`, and `qual` as numpy
+    /// fixed-width bytes arrays plus `length` (uint32). An empty batch
+    /// (`len(batch["seq"]) == 0`) marks the end of the file.
+    #[pyo3(text_signature = "($self, n: int) -> dict[str, npt.NDArray[Any]]")]
+    fn read_chunk(&mut self, py: Python<'_>, n: usize) -> PyResult<PyObject> {
+        let records = self.take(py, n)?;
+        let batch = PyDict::new(py);
+        batch.set_item("id", bytes_column(py, records.iter().map(|r| r.id.as_bytes()))?)?;
+        batch.set_item("seq", bytes_column(py, records.iter().map(|r| r.seq.as_slice()))?)?;
+        batch.set_item("qual", bytes_column(py, records.iter().map(|r| r.qual.as_slice()))?)?;
+        let lengths: Vec<u32> = records.iter().map(|r| r.seq.len() as u32).collect();
+        batch.set_item("length", lengths.into_pyarray(py))?;
+        Ok(batch.into())
+    }
+
+    /// Iterate over `pyarrow.RecordBatch`es of up to `batch_size` records
+    /// with columns `id` (string), `seq` and `qual` (binary), and `length`
+    /// (uint32), e.g. for `polars.from_arrow` or `.to_pandas()`
+    #[pyo3(signature = (batch_size = 100_000))]
+    fn to_arrow_batches(
+        slf: Py<Self>,
+        py: Python<'_>,
+        batch_size: usize,
+    ) -> PyResult<PyArrowBatches> {
+        PyArrowBatches::fastq(py, slf, batch_size)
+    }
+}
+
+impl PyFastqParser {
+    /// Parse up to `n` records without the GIL
+    fn take(&mut self, py: Python<'_>, n: usize) -> PyResult<Vec<FastqRecord>> {
+        let parser = &mut self.inner;
+        py.allow_threads(|| parser.by_ref().take(n).collect::<sparc_core::Result<Vec<_>>>())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+    }
+
+    /// The next `to_arrow_batches` batch, or `None` at the end of the file
+    pub(crate) fn arrow_batch(
+        &mut self,
+        py: Python<'_>,
+        pyarrow: &PyModule,
+        n: usize,
+    ) -> PyResult<Option<PyObject>> {
+        let records = self.take(py, n)?;
+        if records.is_empty() {
+            return Ok(None);
         }
-        Ok(records)
+        let mut batch = BatchBuilder::new(py, pyarrow);
+        batch.string("id", records.iter().map(|r| Some(r.id.as_str())))?;
+        batch.binary("seq", records.iter().map(|r| r.seq.as_slice()))?;
+        batch.binary("qual", records.iter().map(|r| r.qual.as_slice()))?;
+        batch.primitive("length", records.iter().map(|r| r.seq.len() as u32).collect())?;
+        batch.finish().map(Some)
+    }
+}
+
+/// Python wrapper for PairedFastqParser
+#[pyclass(name = "PairedFastqParser")]
+pub struct PyPairedFastqParser {
+    inner: PairedFastqParser,
+}
+
+#[pymethods]
+impl PyPairedFastqParser {
+    /// Open R1 and R2 together. With `check_names` (the default), a pair whose
+    /// read names differ raises instead of silently drifting out of sync.
+    #[new]
+    #[pyo3(signature = (r1, r2, check_names = true))]
+    fn new(r1: &str, r2: &str, check_names: bool) -> PyResult<Self> {
+        let inner = PairedFastqParser::open(r1, r2)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?
+            .check_names(check_names);
+        Ok(Self { inner })
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<(PyFastqRecord, PyFastqRecord)>> {
+        match self.inner.next() {
+            Some(Ok((r1, r2))) => {
+                Ok(Some((PyFastqRecord { inner: r1 }, PyFastqRecord { inner: r2 })))
+            }
+            Some(Err(e)) => Err(PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Read up to `n` pairs as columns: `id` (from R1), `r1_seq`, `r1_qual`,
+    /// `r2_seq`, and `r2_qual` as numpy fixed-width bytes arrays. An empty
+    /// batch marks the end of the files.
+    #[pyo3(text_signature = "($self, n: int) -> dict[str, npt.NDArray[Any]]")]
+    fn read_chunk(&mut self, py: Python<'_>, n: usize) -> PyResult<PyObject> {
+        let pairs = self.take(py, n)?;
+        let r1s = || pairs.iter().map(|(r1, _)| r1);
+        let r2s = || pairs.iter().map(|(_, r2)| r2);
+        let batch = PyDict::new(py);
+        batch.set_item("id", bytes_column(py, r1s().map(|r| r.id.as_bytes()))?)?;
+        batch.set_item("r1_seq", bytes_column(py, r1s().map(|r| r.seq.as_slice()))?)?;
+        batch.set_item("r1_qual", bytes_column(py, r1s().map(|r| r.qual.as_slice()))?)?;
+        batch.set_item("r2_seq", bytes_column(py, r2s().map(|r| r.seq.as_slice()))?)?;
+        batch.set_item("r2_qual", bytes_column(py, r2s().map(|r| r.qual.as_slice()))?)?;
+        Ok(batch.into())
+    }
+
+    /// Iterate over `pyarrow.RecordBatch`es of up to `batch_size` pairs with
+    /// columns `id` (string, from R1) and `r1_seq`, `r1_qual`, `r2_seq`, and
+    /// `r2_qual` (binary)
+    #[pyo3(signature = (batch_size = 100_000))]
+    fn to_arrow_batches(
+        slf: Py<Self>,
+        py: Python<'_>,
+        batch_size: usize,
+    ) -> PyResult<PyArrowBatches> {
+        PyArrowBatches::paired_fastq(py, slf, batch_size)
+    }
+}
+
+impl PyPairedFastqParser {
+    /// Parse up to `n` pairs without the GIL
+    fn take(&mut self, py: Python<'_>, n: usize) -> PyResult<Vec<(FastqRecord, FastqRecord)>> {
+        let parser = &mut self.inner;
+        py.allow_threads(|| parser.by_ref().take(n).collect::<sparc_core::Result<Vec<_>>>())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+    }
+
+    /// The next `to_arrow_batches` batch, or `None` at the end of the files
+    pub(crate) fn arrow_batch(
+        &mut self,
+        py: Python<'_>,
+        pyarrow: &PyModule,
+        n: usize,
+    ) -> PyResult<Option<PyObject>> {
+        let pairs = self.take(py, n)?;
+        if pairs.is_empty() {
+            return Ok(None);
+        }
+        let r1s = || pairs.iter().map(|(r1, _)| r1);
+        let r2s = || pairs.iter().map(|(_, r2)| r2);
+        let mut batch = BatchBuilder::new(py, pyarrow);
+        batch.string("id", r1s().map(|r| Some(r.id.as_str())))?;
+        batch.binary("r1_seq", r1s().map(|r| r.seq.as_slice()))?;
+        batch.binary("r1_qual", r1s().map(|r| r.qual.as_slice()))?;
+        batch.binary("r2_seq", r2s().map(|r| r.seq.as_slice()))?;
+        batch.binary("r2_qual", r2s().map(|r| r.qual.as_slice()))?;
+        batch.finish().map(Some)
     }
 }
 
 /// Python wrapper for FastqWriter
+///
+/// The file appears at its path on `close()` (or a clean `with` exit); a
+/// writer discarded without closing leaves no partial file behind.
 #[pyclass(name = "FastqWriter", unsendable)]
 pub struct PyFastqWriter {
     inner: Option<FastqWriter>,
@@ -109,10 +267,30 @@ pub struct PyFastqWriter {
 
 #[pymethods]
 impl PyFastqWriter {
+    /// Open `path` for writing. `compression` is "plain", "gzip", or "zstd"
+    /// (default: from the extension, `.gz` or `.zst`); `level` defaults to 6
+    /// for gzip and 3 for zstd; `threads` > 1 compresses blocks in parallel.
     #[new]
-    fn new(path: &str) -> PyResult<Self> {
-        let inner = FastqWriter::new(path)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+    #[pyo3(signature = (path, compression = None, level = None, threads = 1))]
+    fn new(
+        path: &str,
+        compression: Option<&str>,
+        level: Option<i32>,
+        threads: usize,
+    ) -> PyResult<Self> {
+        let value_error = |e: sparc_core::Error| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string())
+        };
+        let mut options = WriterOptions::for_path(Path::new(path));
+        if let Some(compression) = compression {
+            options.compression = compression.parse::<FastqCompression>().map_err(value_error)?;
+        }
+        options.level = level;
+        options.threads = threads.max(1);
+        let inner = FastqWriter::with_options(path, options).map_err(|e| match e {
+            sparc_core::Error::Config(_) => value_error(e),
+            e => PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()),
+        })?;
         Ok(Self { inner: Some(inner) })
     }
 
@@ -129,11 +307,11 @@ impl PyFastqWriter {
         }
     }
 
-    /// Flush and close the writer
+    /// Finish the file, move it into place, and close the writer
     fn close(&mut self) -> PyResult<()> {
-        if let Some(mut writer) = self.inner.take() {
+        if let Some(writer) = self.inner.take() {
             writer
-                .flush()
+                .finish()
                 .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
         } else {
             Ok(())
@@ -144,13 +322,23 @@ impl PyFastqWriter {
         slf
     }
 
+    /// Close on a clean exit; discard the partial file if the block raised
+    #[pyo3(
+        text_signature = "($self, exc_type: type[BaseException] | None, \
+                          _exc_value: BaseException | None, \
+                          _traceback: types.TracebackType | None) -> bool"
+    )]
     fn __exit__(
         &mut self,
-        _exc_type: Option<PyObject>,
+        exc_type: Option<PyObject>,
         _exc_value: Option<PyObject>,
         _traceback: Option<PyObject>,
     ) -> PyResult<bool> {
-        self.close()?;
+        if exc_type.is_some() {
+            self.inner = None;
+        } else {
+            self.close()?;
+        }
         Ok(false)
     }
 }
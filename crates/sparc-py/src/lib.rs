@@ -1,11 +1,16 @@
 //! Python bindings for SPARC
 
 mod analysis;
+mod arrow;
 mod bam;
 mod barcode;
+mod columns;
 mod fastq;
 mod matrix;
+mod pipeline;
+mod protocol;
 mod qc;
+mod umi;
 mod validation_py;
 
 use pyo3::prelude::*;
@@ -16,22 +21,32 @@ fn sparc_py(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     // Core I/O classes
     m.add_class::<fastq::PyFastqParser>()?;
     m.add_class::<fastq::PyFastqRecord>()?;
+    m.add_class::<fastq::PyPairedFastqParser>()?;
     m.add_class::<fastq::PyFastqWriter>()?;
     m.add_class::<bam::PyBamParser>()?;
     m.add_class::<bam::PyBamRecord>()?;
+    m.add_class::<arrow::PyArrowBatches>()?;
 
     // Barcode classes
     m.add_class::<barcode::PyWhitelist>()?;
     m.add_class::<barcode::PyBarcodeCorrector>()?;
 
+    // Protocol classes
+    m.add_class::<protocol::PyReadStructure>()?;
+    m.add_class::<protocol::PyProtocol>()?;
+
     // Count matrix classes
     m.add_class::<matrix::PyCountMatrix>()?;
     m.add_class::<matrix::PyGeneCounter>()?;
+    m.add_class::<matrix::PyDenseChunks>()?;
 
     // QC classes
     m.add_class::<qc::PyQcMetrics>()?;
     m.add_class::<qc::PyQcReport>()?;
 
+    // UMI functions
+    m.add_function(wrap_pyfunction!(umi::py_deduplicate_umis, m)?)?;
+
     // Analysis functions
     m.add_function(wrap_pyfunction!(analysis::py_normalize_total, m)?)?;
     m.add_function(wrap_pyfunction!(analysis::py_scale, m)?)?;
@@ -41,6 +56,9 @@ fn sparc_py(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(analysis::py_label_propagation, m)?)?;
     m.add_function(wrap_pyfunction!(analysis::py_run_analysis, m)?)?;
 
+    // Pipeline
+    m.add_function(wrap_pyfunction!(pipeline::run, m)?)?;
+
     // Validation functions
     m.add_function(wrap_pyfunction!(validation_py::adjusted_rand_index, m)?)?;
     m.add_function(wrap_pyfunction!(validation_py::normalized_mutual_info, m)?)?;
@@ -1,13 +1,22 @@
 //! Count matrix Python bindings
 
-use numpy::{PyArray1, PyArray2, ToPyArray};
+use numpy::{IntoPyArray, PyArray1, PyArray2, PyReadonlyArray1, ToPyArray};
 use pyo3::prelude::*;
-use sparc_core::count::{CountMatrix, GeneCounter};
+use pyo3::exceptions::{PyIndexError, PyKeyError, PyMemoryError, PyTypeError, PyValueError};
+use pyo3::types::{PyBool, PyDict, PySlice, PyString, PyTuple, PyType};
+use rayon::prelude::*;
+use sparc_core::annotation::GeneAnnotation;
+use sparc_core::count::{CountMatrix, GeneCounter, Metadata, MetadataColumn};
+use sparc_core::qc::CellMetrics;
+use sparc_core::resources;
+use std::collections::HashMap;
+
+use crate::qc::PyQcReport;
 
 /// Python wrapper for CountMatrix
 #[pyclass(name = "CountMatrix")]
 pub struct PyCountMatrix {
-    inner: CountMatrix,
+    pub(crate) inner: CountMatrix,
 }
 
 #[pymethods]
@@ -20,6 +29,35 @@ impl PyCountMatrix {
         }
     }
 
+    /// Read a Matrix Market directory (`matrix.mtx`, `barcodes.tsv`,
+    /// `genes.tsv`/`features.tsv`, optionally gzipped) or a `matrix.mtx` path
+    #[classmethod]
+    fn read_mtx(_cls: &PyType, py: Python<'_>, path: &str) -> PyResult<Self> {
+        let inner = py
+            .allow_threads(|| CountMatrix::read_mtx(path))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    /// Read raw counts from an AnnData `.h5ad` file (cells as obs, genes as var)
+    #[classmethod]
+    fn read_h5ad(_cls: &PyType, py: Python<'_>, path: &str) -> PyResult<Self> {
+        #[cfg(feature = "h5ad")]
+        {
+            let inner = py
+                .allow_threads(|| CountMatrix::read_h5ad(path))
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+            Ok(Self { inner })
+        }
+        #[cfg(not(feature = "h5ad"))]
+        {
+            let _ = (py, path);
+            Err(PyErr::new::<pyo3::exceptions::PyNotImplementedError, _>(
+                "sparc was built without h5ad support (enable the `h5ad` feature)",
+            ))
+        }
+    }
+
     /// Get barcodes (column names)
     #[getter]
     fn barcodes(&self) -> Vec<String> {
@@ -32,6 +70,56 @@ impl PyCountMatrix {
         self.inner.genes.clone()
     }
 
+    /// Per-gene metadata: column name -> list of str or numpy array
+    #[pyo3(text_signature = "($self) -> dict[str, list[str] | np.ndarray]")]
+    fn gene_metadata<'py>(&self, py: Python<'py>) -> &'py PyDict {
+        metadata_dict(py, &self.inner.gene_metadata)
+    }
+
+    /// Per-cell metadata: column name -> list of str or numpy array
+    #[pyo3(text_signature = "($self) -> dict[str, list[str] | np.ndarray]")]
+    fn cell_metadata<'py>(&self, py: Python<'py>) -> &'py PyDict {
+        metadata_dict(py, &self.inner.cell_metadata)
+    }
+
+    /// Add or replace a gene metadata column of str, int, or float values,
+    /// one per gene
+    #[pyo3(text_signature = "($self, name: str, values: Sequence[str | int | float]) -> None")]
+    fn set_gene_metadata(&mut self, name: &str, values: &PyAny) -> PyResult<()> {
+        let column = metadata_column(values)?;
+        self.inner
+            .set_gene_metadata(name, column)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Add or replace a cell metadata column of str, int, or float values,
+    /// one per cell
+    #[pyo3(text_signature = "($self, name: str, values: Sequence[str | int | float]) -> None")]
+    fn set_cell_metadata(&mut self, name: &str, values: &PyAny) -> PyResult<()> {
+        let column = metadata_column(values)?;
+        self.inner
+            .set_cell_metadata(name, column)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Fill the chromosome, biotype, and length gene metadata from a GTF
+    /// (.gz supported); returns the number of genes found in it
+    fn annotate_genes(&mut self, py: Python<'_>, gtf_path: &str) -> PyResult<usize> {
+        let annotation = py
+            .allow_threads(|| GeneAnnotation::from_gtf(gtf_path))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        Ok(self.inner.annotate_genes(&annotation))
+    }
+
+    /// Sum transcript rows into one row per gene of the `gene_id` gene metadata
+    /// column (set it with `set_gene_metadata` for matrices read from disk)
+    fn rollup_to_genes(&self, py: Python<'_>) -> PyResult<PyCountMatrix> {
+        let inner = py
+            .allow_threads(|| self.inner.rollup_to_genes())
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyCountMatrix { inner })
+    }
+
     /// Get number of rows (genes)
     #[getter]
     fn n_rows(&self) -> usize {
@@ -71,6 +159,28 @@ impl PyCountMatrix {
         self.inner.values.to_pyarray(py)
     }
 
+    /// Non-zero (cell indices, counts) of a gene by ID or symbol, or None
+    fn gene_vector<'py>(
+        &self,
+        py: Python<'py>,
+        gene: &str,
+    ) -> Option<(&'py PyArray1<usize>, &'py PyArray1<u32>)> {
+        let entries = self.inner.gene_vector(gene)?;
+        let (cells, counts): (Vec<usize>, Vec<u32>) = entries.into_iter().unzip();
+        Some((cells.into_pyarray(py), counts.into_pyarray(py)))
+    }
+
+    /// Non-zero (gene indices, counts) of a cell barcode, or None
+    fn cell_vector<'py>(
+        &self,
+        py: Python<'py>,
+        barcode: &str,
+    ) -> Option<(&'py PyArray1<usize>, &'py PyArray1<u32>)> {
+        let entries = self.inner.cell_vector(barcode)?;
+        let (genes, counts): (Vec<usize>, Vec<u32>) = entries.into_iter().unzip();
+        Some((genes.into_pyarray(py), counts.into_pyarray(py)))
+    }
+
     /// Get total counts per cell as numpy array
     fn counts_per_cell<'py>(&self, py: Python<'py>) -> &'py PyArray1<u64> {
         self.inner.counts_per_cell().to_pyarray(py)
@@ -91,29 +201,139 @@ impl PyCountMatrix {
         self.inner.cells_per_gene().to_pyarray(py)
     }
 
-    /// Convert to dense numpy array (for small matrices)
-    fn to_dense<'py>(&self, py: Python<'py>) -> &'py PyArray2<u32> {
-        let mut dense = vec![vec![0u32; self.inner.n_cols]; self.inner.n_rows];
-        for ((&r, &c), &v) in self
-            .inner
-            .rows
-            .iter()
-            .zip(self.inner.cols.iter())
-            .zip(self.inner.values.iter())
-        {
-            dense[r][c] = v;
+    /// Convert to a dense numpy array, genes x cells. Raises MemoryError when
+    /// the array would not fit in memory (the `--max-memory` budget, else the
+    /// memory available); use `to_dense_chunk` or `to_scipy` for large ones.
+    fn to_dense<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArray2<u32>> {
+        let m = &self.inner;
+        let array = dense_zeros(py, m.n_rows, m.n_cols)?;
+        let mut dense = array.readwrite();
+        let dense = dense.as_slice_mut()?;
+        py.allow_threads(|| {
+            for ((&r, &c), &v) in m.rows.iter().zip(&m.cols).zip(&m.values) {
+                dense[r * m.n_cols + c] = v;
+            }
+        });
+        Ok(array)
+    }
+
+    /// Iterate over the dense matrix `rows` genes at a time, as numpy arrays
+    /// of `rows` x cells (the last may be shorter), so a matrix too large for
+    /// `to_dense` can be processed in slices
+    fn to_dense_chunk(&self, py: Python<'_>, rows: usize) -> PyResult<PyDenseChunks> {
+        if rows == 0 {
+            return Err(PyValueError::new_err("rows must be at least 1"));
         }
+        let (indptr, indices, data, (n_rows, n_cols)) =
+            py.allow_threads(|| self.csr_parts(false));
+        Ok(PyDenseChunks {
+            indptr,
+            indices,
+            data,
+            n_rows,
+            n_cols,
+            rows,
+            next_row: 0,
+        })
+    }
 
-        let flat: Vec<u32> = dense.into_iter().flatten().collect();
-        PyArray1::from_vec(py, flat)
-            .reshape((self.inner.n_rows, self.inner.n_cols))
-            .expect("reshape dimensions match n_rows * n_cols")
+    /// Convert to a `scipy.sparse.csr_matrix`, genes x cells (or cells x genes
+    /// with `cells_as_rows=True`). The CSR buffers are built once and handed to
+    /// numpy without copying; int64 indices keep scipy from copying them again.
+    #[pyo3(signature = (cells_as_rows = false))]
+    #[pyo3(text_signature = "($self, cells_as_rows: bool = False) -> scipy.sparse.csr_matrix")]
+    fn to_scipy(&self, py: Python<'_>, cells_as_rows: bool) -> PyResult<PyObject> {
+        let (indptr, indices, data, shape) = py.allow_threads(|| self.csr_parts(cells_as_rows));
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("shape", shape)?;
+        kwargs.set_item("copy", false)?;
+        let csr = py.import("scipy.sparse")?.getattr("csr_matrix")?.call(
+            ((
+                data.into_pyarray(py),
+                indices.into_pyarray(py),
+                indptr.into_pyarray(py),
+            ),),
+            Some(kwargs),
+        )?;
+        Ok(csr.into())
+    }
+
+    /// Convert to an `anndata.AnnData` (cells x genes) with barcodes as `obs`
+    /// and genes as `var`, plus per-cell and per-gene count QC columns. When a
+    /// `QcReport` is given, its per-cell metrics are joined by barcode and its
+    /// summary metrics stored in `uns["qc"]`.
+    #[pyo3(signature = (qc = None))]
+    #[pyo3(text_signature = "($self, qc: QcReport | None = None) -> anndata.AnnData")]
+    fn to_anndata(&self, py: Python<'_>, qc: Option<PyRef<'_, PyQcReport>>) -> PyResult<PyObject> {
+        let pandas = py.import("pandas")?;
+        let x = self
+            .to_scipy(py, true)?
+            .call_method1(py, "astype", ("float32",))?;
+
+        let obs_columns = PyDict::new(py);
+        obs_columns.set_item("total_counts", self.inner.counts_per_cell().into_pyarray(py))?;
+        obs_columns.set_item("n_genes_by_counts", self.inner.genes_per_cell().into_pyarray(py))?;
+        if let Some(report) = &qc {
+            let by_barcode: HashMap<&str, &CellMetrics> = report
+                .inner
+                .per_cell_metrics
+                .iter()
+                .map(|c| (c.barcode.as_str(), c))
+                .collect();
+            if !by_barcode.is_empty() {
+                let column = |f: fn(&CellMetrics) -> f64| -> Vec<f64> {
+                    self.inner
+                        .barcodes
+                        .iter()
+                        .map(|b| by_barcode.get(b.as_str()).map_or(f64::NAN, |c| f(c)))
+                        .collect()
+                };
+                obs_columns.set_item("reads", column(|c| c.reads as f64).into_pyarray(py))?;
+                obs_columns.set_item("umis", column(|c| c.umis as f64).into_pyarray(py))?;
+                obs_columns.set_item("mito_percent", column(|c| c.mito_percent).into_pyarray(py))?;
+            }
+        }
+        for (name, column) in self.inner.cell_metadata.iter() {
+            obs_columns.set_item(name, column_object(py, column))?;
+        }
+        let obs_kwargs = PyDict::new(py);
+        obs_kwargs.set_item("index", self.inner.barcodes.clone())?;
+        let obs = pandas
+            .getattr("DataFrame")?
+            .call((obs_columns,), Some(obs_kwargs))?;
+
+        let var_columns = PyDict::new(py);
+        var_columns.set_item("total_counts", self.inner.counts_per_gene().into_pyarray(py))?;
+        var_columns.set_item("n_cells_by_counts", self.inner.cells_per_gene().into_pyarray(py))?;
+        for (name, column) in self.inner.gene_metadata.iter() {
+            var_columns.set_item(name, column_object(py, column))?;
+        }
+        let var_kwargs = PyDict::new(py);
+        var_kwargs.set_item("index", self.inner.genes.clone())?;
+        let var = pandas
+            .getattr("DataFrame")?
+            .call((var_columns,), Some(var_kwargs))?;
+
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("X", x)?;
+        kwargs.set_item("obs", obs)?;
+        kwargs.set_item("var", var)?;
+        let adata = py.import("anndata")?.getattr("AnnData")?.call((), Some(kwargs))?;
+
+        if let Some(report) = &qc {
+            let metrics = serde_json::to_string(&report.inner.metrics)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+            let uns = adata.getattr("uns")?;
+            uns.set_item("qc", py.import("json")?.call_method1("loads", (metrics,))?)?;
+            uns.set_item("qc_warnings", report.inner.warnings.clone())?;
+        }
+        Ok(adata.into())
     }
 
     /// Write to Matrix Market format
-    fn write_mtx(&self, path: &str) -> PyResult<()> {
-        self.inner
-            .write_mtx(path)
+    fn write_mtx(&self, py: Python<'_>, path: &str) -> PyResult<()> {
+        let matrix = &self.inner;
+        py.allow_threads(|| matrix.write_mtx(path))
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
     }
 
@@ -131,6 +351,46 @@ impl PyCountMatrix {
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
     }
 
+    /// `matrix[genes]` or `matrix[genes, cells]`, genes first as in `shape`.
+    ///
+    /// Each axis takes an int, a slice, a gene ID/symbol or barcode, or a
+    /// list/array of ints, names, or booleans (a mask over the axis). Two
+    /// ints give the count; anything else gives a new CountMatrix.
+    #[pyo3(text_signature = "($self, key: Any) -> CountMatrix | int")]
+    fn __getitem__(&self, py: Python<'_>, key: &PyAny) -> PyResult<PyObject> {
+        let (gene_key, cell_key) = match key.downcast::<PyTuple>() {
+            Ok(tuple) if tuple.len() == 2 => (tuple.get_item(0)?, Some(tuple.get_item(1)?)),
+            Ok(tuple) => {
+                return Err(PyIndexError::new_err(format!(
+                    "CountMatrix is 2-dimensional, but {} indices were given",
+                    tuple.len()
+                )))
+            }
+            Err(_) => (key, None),
+        };
+        let genes = self.axis_indices(gene_key, Axis::Genes)?;
+        let cells = match cell_key {
+            Some(cell_key) => Some(self.axis_indices(cell_key, Axis::Cells)?),
+            None => None,
+        };
+
+        if let (Selection::Scalar(gene), Some(Selection::Scalar(cell))) = (&genes, &cells) {
+            return Ok(self.inner.get(*gene, *cell).into_py(py));
+        }
+        let mut subset = match genes {
+            Selection::All => self.inner.clone(),
+            genes => self.inner.subset_rows(&genes.into_indices()),
+        };
+        if let Some(cells) = cells.filter(|c| !matches!(c, Selection::All)) {
+            subset = subset.subset_cols(&cells.into_indices());
+        }
+        Ok(Self { inner: subset }.into_py(py))
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.n_rows
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "CountMatrix(genes={}, cells={}, nnz={})",
@@ -141,6 +401,314 @@ impl PyCountMatrix {
     }
 }
 
+/// An axis of the genes x cells matrix
+#[derive(Clone, Copy)]
+enum Axis {
+    Genes,
+    Cells,
+}
+
+/// Positions selected along one axis
+enum Selection {
+    /// The whole axis (`:`)
+    All,
+    /// One position from an int or a name
+    Scalar(usize),
+    Indices(Vec<usize>),
+}
+
+impl Selection {
+    fn into_indices(self) -> Vec<usize> {
+        match self {
+            Selection::All => unreachable!("whole axes are not subset"),
+            Selection::Scalar(i) => vec![i],
+            Selection::Indices(indices) => indices,
+        }
+    }
+}
+
+impl PyCountMatrix {
+    /// Positions along `axis` selected by one index of `__getitem__`
+    fn axis_indices(&self, key: &PyAny, axis: Axis) -> PyResult<Selection> {
+        let m = &self.inner;
+        let (len, what) = match axis {
+            Axis::Genes => (m.n_rows, "gene"),
+            Axis::Cells => (m.n_cols, "cell"),
+        };
+        let position = |i: i64| -> PyResult<usize> {
+            let pos = if i < 0 { i + len as i64 } else { i };
+            if pos < 0 || pos >= len as i64 {
+                return Err(PyIndexError::new_err(format!(
+                    "{} index {} is out of range for {} {}s",
+                    what, i, len, what
+                )));
+            }
+            Ok(pos as usize)
+        };
+
+        if let Ok(slice) = key.downcast::<PySlice>() {
+            let range = slice.indices(len as std::os::raw::c_long)?;
+            if (range.start, range.step, range.slicelength) == (0, 1, len as isize) {
+                return Ok(Selection::All);
+            }
+            let indices = (0..range.slicelength)
+                .map(|k| (range.start + k * range.step) as usize)
+                .collect();
+            return Ok(Selection::Indices(indices));
+        }
+        if key.is_instance_of::<PyBool>() {
+            return Err(PyTypeError::new_err("use a boolean mask, not a single bool"));
+        }
+        if let Ok(name) = key.downcast::<PyString>() {
+            return self.lookup(&[name.to_str()?], axis).map(|i| Selection::Scalar(i[0]));
+        }
+        if let Ok(i) = key.extract::<i64>() {
+            return position(i).map(Selection::Scalar);
+        }
+
+        let indices: Vec<usize> = if let Ok(mask) = key.extract::<PyReadonlyArray1<bool>>() {
+            mask_indices(mask.as_slice()?, len, what)?
+        } else if let Ok(mask) = key.extract::<Vec<bool>>() {
+            mask_indices(&mask, len, what)?
+        } else if let Ok(indices) = key.extract::<Vec<i64>>() {
+            indices.into_iter().map(position).collect::<PyResult<_>>()?
+        } else if let Ok(names) = key.extract::<Vec<&str>>() {
+            self.lookup(&names, axis)?
+        } else {
+            return Err(PyTypeError::new_err(format!(
+                "{} index must be an int, slice, name, or a list/array of ints, names, or bools",
+                what
+            )));
+        };
+        let mut seen = vec![false; len];
+        for &i in &indices {
+            if std::mem::replace(&mut seen[i], true) {
+                return Err(PyIndexError::new_err(format!("{} {} selected twice", what, i)));
+            }
+        }
+        Ok(Selection::Indices(indices))
+    }
+
+    /// Positions of gene IDs/symbols or barcodes
+    fn lookup(&self, names: &[&str], axis: Axis) -> PyResult<Vec<usize>> {
+        let m = &self.inner;
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        match axis {
+            // IDs take precedence over symbols, as in `gene_vector`
+            Axis::Genes => {
+                for (i, name) in m.gene_names.iter().enumerate().rev() {
+                    index.insert(name, i);
+                }
+                for (i, id) in m.genes.iter().enumerate().rev() {
+                    index.insert(id, i);
+                }
+            }
+            Axis::Cells => {
+                for (i, barcode) in m.barcodes.iter().enumerate().rev() {
+                    index.insert(barcode, i);
+                }
+            }
+        }
+        names
+            .iter()
+            .map(|name| {
+                index
+                    .get(name)
+                    .copied()
+                    .ok_or_else(|| PyKeyError::new_err(name.to_string()))
+            })
+            .collect()
+    }
+
+    /// Canonical CSR (indptr, indices, data, shape) with genes or cells as
+    /// rows: column indices sorted within each row and duplicate entries summed
+    fn csr_parts(&self, cells_as_rows: bool) -> (Vec<i64>, Vec<i64>, Vec<u32>, (usize, usize)) {
+        let m = &self.inner;
+        let (rows, cols, shape) = if cells_as_rows {
+            (&m.cols, &m.rows, (m.n_cols, m.n_rows))
+        } else {
+            (&m.rows, &m.cols, (m.n_rows, m.n_cols))
+        };
+
+        let mut indptr = vec![0i64; shape.0 + 1];
+        for &r in rows {
+            indptr[r + 1] += 1;
+        }
+        let mut total = 0;
+        for p in indptr.iter_mut() {
+            total += *p;
+            *p = total;
+        }
+
+        let mut next = indptr[..shape.0].to_vec();
+        let mut indices = vec![0i64; m.values.len()];
+        let mut data = vec![0u32; m.values.len()];
+        for ((&r, &c), &v) in rows.iter().zip(cols).zip(&m.values) {
+            let pos = next[r] as usize;
+            indices[pos] = c as i64;
+            data[pos] = v;
+            next[r] += 1;
+        }
+
+        // Sort each row and merge repeated (row, col) entries, compacting in
+        // place; a row never moves right, so `out` trails the row being read
+        let mut out = 0usize;
+        let mut start = 0usize;
+        let mut entries: Vec<(i64, u32)> = Vec::new();
+        for r in 0..shape.0 {
+            let end = indptr[r + 1] as usize;
+            entries.clear();
+            entries.extend(
+                indices[start..end]
+                    .iter()
+                    .copied()
+                    .zip(data[start..end].iter().copied()),
+            );
+            entries.sort_unstable_by_key(|&(c, _)| c);
+            let row_start = out;
+            for &(c, v) in &entries {
+                if out > row_start && indices[out - 1] == c {
+                    data[out - 1] += v;
+                } else {
+                    indices[out] = c;
+                    data[out] = v;
+                    out += 1;
+                }
+            }
+            start = end;
+            indptr[r + 1] = out as i64;
+        }
+        indices.truncate(out);
+        data.truncate(out);
+        (indptr, indices, data, shape)
+    }
+}
+
+/// Iterator of dense row blocks, as returned by `CountMatrix.to_dense_chunk`
+#[pyclass(name = "DenseChunks")]
+pub struct PyDenseChunks {
+    indptr: Vec<i64>,
+    indices: Vec<i64>,
+    data: Vec<u32>,
+    n_rows: usize,
+    n_cols: usize,
+    rows: usize,
+    next_row: usize,
+}
+
+#[pymethods]
+impl PyDenseChunks {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<&'py PyArray2<u32>>> {
+        if self.next_row >= self.n_rows {
+            return Ok(None);
+        }
+        let start = self.next_row;
+        let end = (start + self.rows).min(self.n_rows);
+        let array = dense_zeros(py, end - start, self.n_cols)?;
+        let mut block = array.readwrite();
+        let block = block.as_slice_mut()?;
+        let (indptr, indices, data) = (&self.indptr, &self.indices, &self.data);
+        let n_cols = self.n_cols;
+        py.allow_threads(|| {
+            for (i, row) in block.chunks_exact_mut(n_cols.max(1)).enumerate() {
+                let span = indptr[start + i] as usize..indptr[start + i + 1] as usize;
+                for (&c, &v) in indices[span.clone()].iter().zip(&data[span]) {
+                    row[c as usize] = v;
+                }
+            }
+        });
+        self.next_row = end;
+        Ok(Some(array))
+    }
+
+    /// Number of blocks left
+    fn __len__(&self) -> usize {
+        (self.n_rows - self.next_row + self.rows - 1) / self.rows
+    }
+}
+
+/// A zeroed `rows` x `cols` uint32 array, or MemoryError when it would not
+/// fit in the memory limit. Allocated through numpy, so a failed allocation
+/// also raises instead of aborting.
+fn dense_zeros(py: Python<'_>, rows: usize, cols: usize) -> PyResult<&PyArray2<u32>> {
+    let bytes = rows
+        .checked_mul(cols)
+        .and_then(|n| n.checked_mul(std::mem::size_of::<u32>()))
+        .map(|b| b as u64);
+    let gb = |b: u64| b as f64 / (1u64 << 30) as f64;
+    match (bytes, resources::memory_limit()) {
+        (None, _) => {
+            return Err(PyMemoryError::new_err(format!(
+                "a dense {} x {} matrix is too large to address",
+                rows, cols
+            )))
+        }
+        (Some(bytes), Some(limit)) if bytes > limit => {
+            return Err(PyMemoryError::new_err(format!(
+                "a dense {} x {} matrix needs {:.1} GB but only {:.1} GB is available; \
+                 use to_dense_chunk(rows) or to_scipy()",
+                rows,
+                cols,
+                gb(bytes),
+                gb(limit)
+            )))
+        }
+        _ => {}
+    }
+    let array = py
+        .import("numpy")?
+        .call_method1("zeros", ((rows, cols), "uint32"))?;
+    Ok(array.downcast()?)
+}
+
+/// Positions set in a boolean mask over an axis of `len`
+fn mask_indices(mask: &[bool], len: usize, what: &str) -> PyResult<Vec<usize>> {
+    if mask.len() != len {
+        return Err(PyIndexError::new_err(format!(
+            "boolean mask of length {} does not match {} {}s",
+            mask.len(),
+            len,
+            what
+        )));
+    }
+    Ok((0..len).filter(|&i| mask[i]).collect())
+}
+
+/// Metadata columns as a dict of name -> list of str or numpy array
+fn metadata_dict<'py>(py: Python<'py>, metadata: &Metadata) -> &'py PyDict {
+    let dict = PyDict::new(py);
+    for (name, column) in metadata.iter() {
+        dict.set_item(name, column_object(py, column)).expect("str keys are hashable");
+    }
+    dict
+}
+
+fn column_object(py: Python<'_>, column: &MetadataColumn) -> PyObject {
+    match column {
+        MetadataColumn::Text(v) => v.to_object(py),
+        MetadataColumn::Int(v) => v.to_pyarray(py).into(),
+        MetadataColumn::Float(v) => v.to_pyarray(py).into(),
+    }
+}
+
+/// A metadata column from a sequence of str, int, or float values
+fn metadata_column(values: &PyAny) -> PyResult<MetadataColumn> {
+    if let Ok(v) = values.extract::<Vec<String>>() {
+        return Ok(MetadataColumn::Text(v));
+    }
+    if let Ok(v) = values.extract::<Vec<i64>>() {
+        return Ok(MetadataColumn::Int(v));
+    }
+    if let Ok(v) = values.extract::<Vec<f64>>() {
+        return Ok(MetadataColumn::Float(v));
+    }
+    Err(PyTypeError::new_err("metadata values must be a sequence of str, int, or float"))
+}
+
 /// Python wrapper for GeneCounter
 #[pyclass(name = "GeneCounter")]
 pub struct PyGeneCounter {
@@ -161,6 +729,71 @@ impl PyGeneCounter {
         self.inner.add_count(barcode, gene, count);
     }
 
+    /// Add many barcode-gene counts in one call. `counts` is any array-like of
+    /// non-negative integers (all 1 when omitted). Pairs are aggregated in
+    /// parallel without the GIL, keeping first-seen order for barcodes and genes.
+    #[pyo3(
+        signature = (barcodes, genes, counts = None),
+        text_signature = "($self, barcodes: Sequence[str], genes: Sequence[str], \
+                          counts: npt.ArrayLike | None = None) -> None"
+    )]
+    fn add_counts(
+        &mut self,
+        py: Python<'_>,
+        barcodes: Vec<String>,
+        genes: Vec<String>,
+        counts: Option<&PyAny>,
+    ) -> PyResult<()> {
+        let counts: Option<PyReadonlyArray1<u32>> = counts
+            .map(|c| {
+                py.import("numpy")?
+                    .call_method1("asarray", (c, "uint32"))?
+                    .extract()
+            })
+            .transpose()?;
+        let counts = counts.as_ref().map(|c| c.as_slice()).transpose()?;
+        let n = barcodes.len();
+        if genes.len() != n || counts.map_or(false, |c| c.len() != n) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "barcodes, genes, and counts must have the same length ({}, {}, {})",
+                n,
+                genes.len(),
+                counts.map_or(n, |c| c.len())
+            )));
+        }
+
+        let counter = &mut self.inner;
+        py.allow_threads(|| {
+            // (barcode, gene) -> (first index, total)
+            type Totals<'a> = HashMap<(&'a str, &'a str), (usize, u64)>;
+            let totals = (0..n)
+                .into_par_iter()
+                .fold(Totals::new, |mut acc, i| {
+                    let count = counts.map_or(1, |c| c[i] as u64);
+                    let entry = acc
+                        .entry((barcodes[i].as_str(), genes[i].as_str()))
+                        .or_insert((i, 0));
+                    entry.1 += count;
+                    acc
+                })
+                .reduce(Totals::new, |mut a, b| {
+                    for (key, (first, total)) in b {
+                        let entry = a.entry(key).or_insert((first, 0));
+                        entry.0 = entry.0.min(first);
+                        entry.1 += total;
+                    }
+                    a
+                });
+
+            let mut totals: Vec<_> = totals.into_iter().collect();
+            totals.sort_unstable_by_key(|&(_, (first, _))| first);
+            for ((barcode, gene), (_, total)) in totals {
+                counter.add_count(barcode, gene, total.min(u32::MAX as u64) as u32);
+            }
+        });
+        Ok(())
+    }
+
     /// Increment count by 1
     fn increment(&mut self, barcode: &str, gene: &str) {
         self.inner.increment(barcode, gene);
@@ -176,12 +809,13 @@ impl PyGeneCounter {
         self.inner.num_genes()
     }
 
-    /// Build the count matrix
-    fn build(&mut self) -> PyCountMatrix {
+    /// Build the count matrix (merging any spilled runs without the GIL)
+    fn build(&mut self, py: Python<'_>) -> PyResult<PyCountMatrix> {
         let counter = std::mem::take(&mut self.inner);
-        PyCountMatrix {
-            inner: counter.build(),
-        }
+        let inner = py
+            .allow_threads(|| counter.try_build())
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        Ok(PyCountMatrix { inner })
     }
 
     fn __repr__(&self) -> String {
@@ -0,0 +1,292 @@
+//! High-level pipeline entry point: extract, align, count, and QC in one call
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::{Deserialize, Serialize};
+use sparc_core::aligner::AlignerConfig;
+use sparc_core::bam::BamParser;
+use sparc_core::barcode::{BarcodeCorrector, BarcodeMatch, TieredCorrector, Whitelist};
+use sparc_core::count::{CountMatrix, GeneCounter};
+use sparc_core::fastq::FastqParser;
+use sparc_core::protocols::Protocol;
+use sparc_core::qc::{CellCalling, CellFilter, CellMetrics, QcMetrics, QcReport};
+use std::path::{Path, PathBuf};
+
+use crate::matrix::PyCountMatrix;
+use crate::qc::{with_overrides, PyQcReport};
+
+/// Reads between progress callbacks
+const PROGRESS_INTERVAL: u64 = 100_000;
+
+/// `run()` settings; the keys and defaults follow `sparc pipeline`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RunConfig {
+    r1: Option<PathBuf>,
+    r2: Option<PathBuf>,
+    /// Reference genome directory (STAR) or FASTA (minimap2)
+    reference: Option<PathBuf>,
+    /// Output directory; nothing is written without it
+    output: Option<PathBuf>,
+    whitelist: Option<PathBuf>,
+    protocol: String,
+    sample: String,
+    aligner: String,
+    align_retries: usize,
+    max_mismatch: u32,
+    min_barcode_qual: u8,
+    min_mapq: u8,
+    /// Count this BAM instead of aligning
+    bam: Option<PathBuf>,
+    min_genes: u64,
+    max_genes: u64,
+    max_mito: f64,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            r1: None,
+            r2: None,
+            reference: None,
+            output: None,
+            whitelist: None,
+            protocol: "10x-3prime-v3".to_string(),
+            sample: "sample".to_string(),
+            aligner: "star".to_string(),
+            align_retries: 1,
+            max_mismatch: 1,
+            min_barcode_qual: 10,
+            min_mapq: 30,
+            bam: None,
+            min_genes: 200,
+            max_genes: 10_000,
+            max_mito: 20.0,
+        }
+    }
+}
+
+fn io_error(e: impl ToString) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string())
+}
+
+fn value_error(e: impl ToString) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string())
+}
+
+/// Barcode matching for the protocol, as `sparc extract` loads it
+enum Corrector {
+    Whole(BarcodeCorrector),
+    Tiered(TieredCorrector),
+    /// No whitelist: any barcode without an `N` is accepted
+    Observed,
+}
+
+impl Corrector {
+    fn load(
+        protocol: &dyn Protocol,
+        whitelist: Option<&Path>,
+        max_mismatch: u32,
+    ) -> PyResult<Self> {
+        let Some(path) = whitelist else {
+            if protocol.requires_whitelist() {
+                return Err(value_error(format!("{} needs a 'whitelist'", protocol.name())));
+            }
+            return Ok(Corrector::Observed);
+        };
+        if protocol.barcode_tiers().is_some() {
+            return TieredCorrector::from_file(path, max_mismatch)
+                .map(Corrector::Tiered)
+                .map_err(io_error);
+        }
+        let whitelist = Whitelist::from_file(path).map_err(io_error)?;
+        Ok(Corrector::Whole(BarcodeCorrector::new(whitelist, max_mismatch)))
+    }
+
+    /// Whether the barcode matches the whitelist, after correction
+    fn is_valid(&self, barcode: &str) -> bool {
+        let matched = match self {
+            Corrector::Whole(corrector) => corrector.match_barcode(barcode),
+            Corrector::Tiered(corrector) => corrector.match_barcode(barcode),
+            Corrector::Observed => {
+                return barcode.bytes().all(|b| matches!(b, b'A' | b'C' | b'G' | b'T'))
+            }
+        };
+        !matches!(matched, BarcodeMatch::NoMatch(_))
+    }
+}
+
+/// Reports progress to an optional Python callable `progress(stage, reads)`
+struct Progress(Option<PyObject>);
+
+impl Progress {
+    fn report(&self, stage: &str, reads: u64) -> PyResult<()> {
+        match &self.0 {
+            Some(callback) => Python::with_gil(|py| callback.call1(py, (stage, reads)).map(drop)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Run extraction, alignment, counting, and QC, returning
+/// `(CountMatrix, QcReport)`.
+///
+/// `config` takes the `sparc pipeline` options by name (`r1`, `r2`,
+/// `reference`, `whitelist`, `protocol`, `output`, `bam`, `min_genes`, ...).
+/// With `bam`, alignment is skipped and R1/R2 are optional. With `output`,
+/// the matrix and `qc_report.json` are written as by `sparc pipeline`.
+/// `progress` is called as `progress(stage, reads)` when each stage starts
+/// and every 100,000 reads; an exception it raises stops the run.
+#[pyfunction]
+#[pyo3(
+    signature = (config, progress = None),
+    text_signature = "(config: dict[str, Any], \
+                      progress: Callable[[str, int], object] | None = None) \
+                      -> tuple[CountMatrix, QcReport]"
+)]
+pub fn run(
+    py: Python<'_>,
+    config: &PyDict,
+    progress: Option<PyObject>,
+) -> PyResult<(PyCountMatrix, PyQcReport)> {
+    let config = with_overrides(py, RunConfig::default(), Some(config))?;
+    let progress = Progress(progress);
+    let (matrix, report) = py.allow_threads(|| run_pipeline(&config, &progress))?;
+    Ok((PyCountMatrix { inner: matrix }, PyQcReport { inner: report }))
+}
+
+/// Pipeline steps behind `run`, run without the GIL
+fn run_pipeline(config: &RunConfig, progress: &Progress) -> PyResult<(CountMatrix, QcReport)> {
+    let mut qc_metrics = QcMetrics::new();
+
+    // Extract: count reads with a valid barcode
+    if let Some(r1) = &config.r1 {
+        progress.report("extract", 0)?;
+        let protocol = sparc_core::protocols::from_name(&config.protocol).map_err(value_error)?;
+        let corrector =
+            Corrector::load(&*protocol, config.whitelist.as_deref(), config.max_mismatch)?;
+        for record in FastqParser::open(r1).map_err(io_error)? {
+            let record = record.map_err(io_error)?;
+            qc_metrics.total_reads += 1;
+            if qc_metrics.total_reads % PROGRESS_INTERVAL == 0 {
+                progress.report("extract", qc_metrics.total_reads)?;
+            }
+            let Ok(components) = protocol.extract_r1(&record.seq, &record.qual) else {
+                continue;
+            };
+            if !components.barcode_quality_ok(config.min_barcode_qual) {
+                continue;
+            }
+            if corrector.is_valid(&components.barcode_str()) {
+                qc_metrics.valid_barcode_reads += 1;
+            }
+        }
+    }
+
+    // Align, unless a BAM was given
+    let bam = match &config.bam {
+        Some(bam) => bam.clone(),
+        None => {
+            progress.report("align", 0)?;
+            let (Some(r1), Some(r2)) = (&config.r1, &config.r2) else {
+                return Err(value_error("config needs 'r1' and 'r2', or a 'bam'"));
+            };
+            let (Some(reference), Some(output)) = (&config.reference, &config.output) else {
+                return Err(value_error("aligning needs 'reference' and 'output'"));
+            };
+            let threads = rayon::current_num_threads();
+            let aligner = match config.aligner.as_str() {
+                "star" => AlignerConfig::star(reference.clone(), threads),
+                "minimap2" => AlignerConfig::minimap2(reference.clone(), threads),
+                other => return Err(value_error(format!("Unknown aligner: {}", other))),
+            }
+            .retries(config.align_retries)
+            .build();
+            if !aligner.is_available() {
+                return Err(io_error(format!("{} not found in PATH", aligner.binary_name())));
+            }
+            let align_dir = output.join("alignment");
+            std::fs::create_dir_all(&align_dir).map_err(io_error)?;
+            aligner.align(r2, Some(r1.as_path()), &align_dir).map_err(io_error)?
+        }
+    };
+
+    // Count reads per cell and gene from the CB and GX/GN tags
+    progress.report("count", 0)?;
+    let mut counter = GeneCounter::with_resources(sparc_core::resources::global());
+    for record in BamParser::open(&bam).map_err(io_error)? {
+        let record = record.map_err(io_error)?;
+        qc_metrics.mapped_reads += 1;
+        if qc_metrics.mapped_reads % PROGRESS_INTERVAL == 0 {
+            progress.report("count", qc_metrics.mapped_reads)?;
+        }
+        if !record.is_mapped || record.mapq < config.min_mapq {
+            continue;
+        }
+        let (Some(barcode), Some(gene)) =
+            (&record.cell_barcode, record.gene_id.as_ref().or(record.gene_name.as_ref()))
+        else {
+            continue;
+        };
+        if let (Some(gx), Some(gn)) = (&record.gene_id, &record.gene_name) {
+            counter.set_gene_name(gx, gn);
+        }
+        counter.increment(barcode, gene);
+        qc_metrics.assigned_reads += 1;
+    }
+    let matrix = counter.try_build().map_err(value_error)?;
+
+    // QC
+    progress.report("qc", qc_metrics.mapped_reads)?;
+    let counts_per_cell = matrix.counts_per_cell();
+    let genes_per_cell = matrix.genes_per_cell();
+    qc_metrics.num_cells = matrix.n_cols as u64;
+    qc_metrics.total_genes = matrix.n_rows as u64;
+    qc_metrics.update_from_cells(&counts_per_cell, &genes_per_cell, &counts_per_cell);
+
+    let mut report = QcReport::new(config.sample.clone());
+    report.metrics = qc_metrics;
+    let filter = CellFilter {
+        calling: CellCalling::All,
+        min_umis: 0,
+        min_genes: config.min_genes,
+        max_genes: config.max_genes,
+        max_mito_percent: config.max_mito,
+    };
+    let status = filter.apply(&matrix, &[]);
+    for (i, barcode) in matrix.barcodes.iter().enumerate() {
+        let mut cell_metrics = CellMetrics::new(
+            barcode.clone(),
+            counts_per_cell[i],
+            genes_per_cell[i],
+            counts_per_cell[i],
+            status.mito_percent[i],
+        );
+        cell_metrics.set_qc_flags(status.reasons[i].clone());
+        report.per_cell_metrics.push(cell_metrics);
+    }
+    report.generate_warnings();
+
+    if let Some(output) = &config.output {
+        write_outputs(output, &matrix, &report).map_err(io_error)?;
+    }
+    Ok((matrix, report))
+}
+
+/// Write `counts/` and `qc/qc_report.json` under `output`
+fn write_outputs(
+    output: &Path,
+    matrix: &CountMatrix,
+    report: &QcReport,
+) -> sparc_core::Result<()> {
+    let count_dir = output.join("counts");
+    let qc_dir = output.join("qc");
+    std::fs::create_dir_all(&count_dir)?;
+    std::fs::create_dir_all(&qc_dir)?;
+    matrix.write_mtx(count_dir.join("matrix.mtx"))?;
+    matrix.write_barcodes(count_dir.join("barcodes.tsv"))?;
+    matrix.write_genes(count_dir.join("genes.tsv"))?;
+    let json = report
+        .to_json()
+        .map_err(|e| sparc_core::Error::Config(format!("Failed to serialize QC report: {}", e)))?;
+    sparc_core::atomic::write(qc_dir.join("qc_report.json"), json)
+}
@@ -0,0 +1,153 @@
+//! Python bindings for protocols and read structures
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use sparc_core::protocols::{self, Custom, Protocol};
+use sparc_core::ReadStructure;
+
+fn value_error(e: impl ToString) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string())
+}
+
+/// Python wrapper for ReadStructure
+#[pyclass(name = "ReadStructure")]
+#[derive(Clone)]
+pub struct PyReadStructure {
+    inner: ReadStructure,
+}
+
+#[pymethods]
+impl PyReadStructure {
+    #[new]
+    #[pyo3(signature = (barcode_start, barcode_len, umi_start, umi_len, cdna_start = 0))]
+    fn new(barcode_start: usize, barcode_len: usize, umi_start: usize, umi_len: usize, cdna_start: usize) -> Self {
+        Self {
+            inner: ReadStructure::new(barcode_start, barcode_len, umi_start, umi_len, cdna_start),
+        }
+    }
+
+    /// Parse a spec such as `16C12U` or `8X12C8U+T`
+    /// (C = barcode, U = UMI, X = skip, T = cDNA to the end of the read)
+    #[staticmethod]
+    fn parse(spec: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: ReadStructure::parse(spec).map_err(value_error)?,
+        })
+    }
+
+    #[getter]
+    fn barcode_start(&self) -> usize { self.inner.barcode_start }
+
+    #[getter]
+    fn barcode_len(&self) -> usize { self.inner.barcode_len }
+
+    #[getter]
+    fn umi_start(&self) -> usize { self.inner.umi_start }
+
+    #[getter]
+    fn umi_len(&self) -> usize { self.inner.umi_len }
+
+    #[getter]
+    fn cdna_start(&self) -> usize { self.inner.cdna_start }
+
+    /// Minimum R1 length that contains the barcode and UMI
+    fn min_len(&self) -> usize { self.inner.min_len() }
+
+    fn __str__(&self) -> String {
+        self.inner.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ReadStructure('{}')", self.inner)
+    }
+}
+
+/// Python wrapper for a single-cell protocol
+#[pyclass(name = "Protocol")]
+pub struct PyProtocol {
+    inner: Box<dyn Protocol>,
+}
+
+#[pymethods]
+impl PyProtocol {
+    /// Look up a built-in protocol by name (e.g. `10x-3prime-v3`)
+    #[new]
+    fn new(name: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: protocols::from_name(name).map_err(value_error)?,
+        })
+    }
+
+    /// Protocol for a custom chemistry, from a ReadStructure or a spec string
+    #[staticmethod]
+    #[pyo3(text_signature = "(read_structure: ReadStructure | str) -> Protocol")]
+    fn from_read_structure(read_structure: &PyAny) -> PyResult<Self> {
+        let rs = match read_structure.extract::<PyReadStructure>() {
+            Ok(rs) => rs.inner,
+            Err(_) => ReadStructure::parse(read_structure.extract::<&str>()?).map_err(value_error)?,
+        };
+        Ok(Self {
+            inner: Box::new(Custom::new(rs)),
+        })
+    }
+
+    /// Names accepted by the constructor
+    #[staticmethod]
+    fn available() -> Vec<&'static str> {
+        protocols::PROTOCOL_NAMES.to_vec()
+    }
+
+    #[getter]
+    fn name(&self) -> &str { self.inner.name() }
+
+    #[getter]
+    fn version(&self) -> &str { self.inner.version() }
+
+    #[getter]
+    fn read_structure(&self) -> PyReadStructure {
+        PyReadStructure {
+            inner: self.inner.read_structure().clone(),
+        }
+    }
+
+    /// Split an R1 read into barcode, UMI, and cDNA
+    ///
+    /// Returns a dict with `barcode`, `umi`, `cdna` and matching `*_qual`
+    /// strings. Quality defaults to all `I` when omitted.
+    #[pyo3(signature = (seq, qual = None))]
+    #[pyo3(text_signature = "($self, seq: str, qual: str | None = None) -> dict[str, str]")]
+    fn extract_r1(&self, py: Python<'_>, seq: &str, qual: Option<&str>) -> PyResult<PyObject> {
+        let default_qual = "I".repeat(seq.len());
+        let qual = qual.unwrap_or(&default_qual);
+        if qual.len() != seq.len() {
+            return Err(value_error(format!(
+                "quality length {} does not match sequence length {}",
+                qual.len(),
+                seq.len()
+            )));
+        }
+        let components = self
+            .inner
+            .extract_r1(seq.as_bytes(), qual.as_bytes())
+            .map_err(value_error)?;
+
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+        let dict = PyDict::new(py);
+        dict.set_item("barcode", text(&components.barcode))?;
+        dict.set_item("umi", text(&components.umi))?;
+        dict.set_item("cdna", text(&components.cdna))?;
+        dict.set_item("barcode_qual", text(&components.barcode_qual))?;
+        dict.set_item("umi_qual", text(&components.umi_qual))?;
+        dict.set_item("cdna_qual", text(&components.cdna_qual))?;
+        Ok(dict.into())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Protocol('{}', version='{}', read_structure='{}')",
+            self.inner.name(),
+            self.inner.version(),
+            self.inner.read_structure()
+        )
+    }
+}
@@ -1,19 +1,86 @@
 //! Python bindings for QC metrics
 
+use numpy::IntoPyArray;
 use pyo3::prelude::*;
-use sparc_core::qc::{QcMetrics, QcReport};
+use pyo3::types::PyDict;
+use serde::{de::DeserializeOwned, Serialize};
+use sparc_core::qc::{CellMetrics, QcMetrics, QcReport};
+
+use crate::arrow::import_pyarrow;
+
+fn value_error(e: impl ToString) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string())
+}
+
+/// Serialize to a Python dict via JSON
+fn to_py_dict<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(value_error)?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.into())
+}
+
+/// Overlay Python keyword values on `base`, rejecting unknown keys
+pub(crate) fn with_overrides<T: Serialize + DeserializeOwned>(
+    py: Python<'_>,
+    base: T,
+    overrides: Option<&PyDict>,
+) -> PyResult<T> {
+    let Some(overrides) = overrides else {
+        return Ok(base);
+    };
+    let mut value = serde_json::to_value(&base).map_err(value_error)?;
+    let json: String = py
+        .import("json")?
+        .call_method1("dumps", (overrides,))?
+        .extract()?;
+    let updates: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&json).map_err(value_error)?;
+    let fields = value.as_object_mut().expect("struct serializes to an object");
+    for (key, v) in updates {
+        if !fields.contains_key(&key) {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                "unexpected keyword argument '{}'",
+                key
+            )));
+        }
+        fields.insert(key, v);
+    }
+    serde_json::from_value(value).map_err(value_error)
+}
 
 /// Python wrapper for QcMetrics
 #[pyclass(name = "QcMetrics")]
+#[derive(Clone)]
 pub struct PyQcMetrics {
     inner: QcMetrics,
 }
 
 #[pymethods]
 impl PyQcMetrics {
+    /// Create metrics; any field can be given as a keyword (e.g. `total_reads=1000`)
     #[new]
-    fn new() -> Self {
-        Self { inner: QcMetrics::new() }
+    #[pyo3(signature = (**kwargs), text_signature = "(**kwargs: Any)")]
+    fn new(py: Python<'_>, kwargs: Option<&PyDict>) -> PyResult<Self> {
+        Ok(Self {
+            inner: with_overrides(py, QcMetrics::new(), kwargs)?,
+        })
+    }
+
+    /// Parse metrics from JSON
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: serde_json::from_str(json).map_err(value_error)?,
+        })
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string_pretty(&self.inner).map_err(value_error)
+    }
+
+    /// All fields as a dict
+    #[pyo3(text_signature = "($self) -> dict[str, Any]")]
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py_dict(py, &self.inner)
     }
 
     #[getter]
@@ -22,6 +89,9 @@ impl PyQcMetrics {
     #[getter]
     fn valid_barcode_reads(&self) -> u64 { self.inner.valid_barcode_reads }
 
+    #[getter]
+    fn valid_umi_reads(&self) -> u64 { self.inner.valid_umi_reads }
+
     #[getter]
     fn mapped_reads(&self) -> u64 { self.inner.mapped_reads }
 
@@ -46,18 +116,29 @@ impl PyQcMetrics {
     #[getter]
     fn median_genes_per_cell(&self) -> f64 { self.inner.median_genes_per_cell }
 
+    #[getter]
+    fn mean_umi_per_cell(&self) -> f64 { self.inner.mean_umi_per_cell }
+
     #[getter]
     fn median_umi_per_cell(&self) -> f64 { self.inner.median_umi_per_cell }
 
     #[getter]
     fn sequencing_saturation(&self) -> f64 { self.inner.sequencing_saturation }
 
+    #[getter]
+    fn fraction_reads_in_cells(&self) -> f64 { self.inner.fraction_reads_in_cells }
+
     fn barcode_validity_rate(&self) -> f64 { self.inner.barcode_validity_rate() }
 
     fn mapping_rate(&self) -> f64 { self.inner.mapping_rate() }
 
     fn assignment_rate(&self) -> f64 { self.inner.assignment_rate() }
 
+    /// Fill cell count, mean, and median fields from per-cell reads, genes, and UMIs
+    fn update_from_cells(&mut self, reads: Vec<u64>, genes: Vec<u64>, umis: Vec<u64>) {
+        self.inner.update_from_cells(&reads, &genes, &umis);
+    }
+
     fn __repr__(&self) -> String {
         format!(
             "QcMetrics(cells={}, genes={}, reads={})",
@@ -69,29 +150,109 @@ impl PyQcMetrics {
 /// Python wrapper for QcReport
 #[pyclass(name = "QcReport")]
 pub struct PyQcReport {
-    inner: QcReport,
+    pub(crate) inner: QcReport,
 }
 
 #[pymethods]
 impl PyQcReport {
     #[new]
-    fn new(sample_name: String) -> Self {
-        Self { inner: QcReport::new(sample_name) }
+    #[pyo3(signature = (sample_name, metrics = None))]
+    fn new(sample_name: String, metrics: Option<PyQcMetrics>) -> Self {
+        let mut inner = QcReport::new(sample_name);
+        if let Some(metrics) = metrics {
+            inner.metrics = metrics.inner;
+        }
+        Self { inner }
+    }
+
+    /// Parse a report from JSON (e.g. `qc_report.json` written by `sparc pipeline`),
+    /// upgrading reports written by older releases
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        Ok(Self {
+            inner: QcReport::from_json(json).map_err(value_error)?,
+        })
     }
 
+    /// Schema version of the report JSON
+    #[getter]
+    fn schema_version(&self) -> u32 { self.inner.schema_version }
+
     #[getter]
     fn sample_name(&self) -> &str { &self.inner.sample_name }
 
     #[getter]
     fn warnings(&self) -> Vec<String> { self.inner.warnings.clone() }
 
+    #[getter]
+    fn metrics(&self) -> PyQcMetrics {
+        PyQcMetrics {
+            inner: self.inner.metrics.clone(),
+        }
+    }
+
+    #[setter]
+    fn set_metrics(&mut self, metrics: PyQcMetrics) {
+        self.inner.metrics = metrics.inner;
+    }
+
+    /// Number of cells with per-cell metrics
+    #[getter]
+    fn num_cell_metrics(&self) -> usize { self.inner.per_cell_metrics.len() }
+
+    /// Record metrics for one cell
+    #[pyo3(signature = (barcode, reads, genes, umis, mito_percent = 0.0))]
+    fn add_cell_metrics(&mut self, barcode: String, reads: u64, genes: u64, umis: u64, mito_percent: f64) {
+        self.inner
+            .per_cell_metrics
+            .push(CellMetrics::new(barcode, reads, genes, umis, mito_percent));
+    }
+
+    /// Per-cell metrics as a `pyarrow.Table` with columns barcode, reads,
+    /// genes, umis, mito_percent, pass_qc, and qc_flags (call `.to_pandas()`
+    /// for a DataFrame)
+    #[pyo3(text_signature = "($self) -> pa.Table")]
+    fn per_cell_dataframe(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let pyarrow = import_pyarrow(py, "per_cell_dataframe")?;
+        let cells = &self.inner.per_cell_metrics;
+        let column = |f: fn(&CellMetrics) -> u64| -> Vec<u64> { cells.iter().map(f).collect() };
+
+        let columns = PyDict::new(py);
+        columns.set_item("barcode", cells.iter().map(|c| c.barcode.as_str()).collect::<Vec<_>>())?;
+        columns.set_item("reads", column(|c| c.reads).into_pyarray(py))?;
+        columns.set_item("genes", column(|c| c.genes).into_pyarray(py))?;
+        columns.set_item("umis", column(|c| c.umis).into_pyarray(py))?;
+        columns.set_item(
+            "mito_percent",
+            cells.iter().map(|c| c.mito_percent).collect::<Vec<f64>>().into_pyarray(py),
+        )?;
+        columns.set_item("pass_qc", cells.iter().map(|c| c.pass_qc).collect::<Vec<bool>>())?;
+        columns.set_item(
+            "qc_flags",
+            cells
+                .iter()
+                .map(|c| c.qc_flags.iter().map(|f| f.as_str()).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+        )?;
+        Ok(pyarrow.call_method1("table", (columns,))?.into())
+    }
+
+    fn add_warning(&mut self, warning: String) {
+        self.inner.add_warning(warning);
+    }
+
     fn generate_warnings(&mut self) {
         self.inner.generate_warnings();
     }
 
     fn to_json(&self) -> PyResult<String> {
-        self.inner.to_json()
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+        self.inner.to_json().map_err(value_error)
+    }
+
+    /// The whole report (metrics, per-cell metrics, warnings) as a dict
+    #[pyo3(text_signature = "($self) -> dict[str, Any]")]
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py_dict(py, &self.inner)
     }
 
     fn __repr__(&self) -> String {
@@ -0,0 +1,73 @@
+//! UMI deduplication Python bindings
+
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use sparc_core::umi::{Umi, UmiDeduplicator};
+use std::collections::HashMap;
+
+/// Deduplicate UMIs within each (barcode, gene) group using directional
+/// clustering. Groups are processed in parallel without holding the GIL.
+/// Returns (barcodes, genes, representative_umis, counts), one entry per group
+/// in order of first appearance, where counts is the number of molecules.
+#[pyfunction]
+#[pyo3(signature = (barcodes, umis, genes, max_distance = 1))]
+pub fn py_deduplicate_umis(
+    py: Python<'_>,
+    barcodes: Vec<String>,
+    umis: Vec<String>,
+    genes: Vec<String>,
+    max_distance: u32,
+) -> PyResult<(Vec<String>, Vec<String>, Vec<String>, Vec<usize>)> {
+    if umis.len() != barcodes.len() || genes.len() != barcodes.len() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "barcodes, umis, and genes must have the same length ({}, {}, {})",
+            barcodes.len(),
+            umis.len(),
+            genes.len()
+        )));
+    }
+
+    Ok(py.allow_threads(|| {
+        let mut index: HashMap<(&str, &str), usize> = HashMap::new();
+        let mut groups: Vec<((&str, &str), HashMap<&str, u32>)> = Vec::new();
+        for ((bc, umi), gene) in barcodes.iter().zip(&umis).zip(&genes) {
+            let key = (bc.as_str(), gene.as_str());
+            let i = *index.entry(key).or_insert_with(|| {
+                groups.push((key, HashMap::new()));
+                groups.len() - 1
+            });
+            *groups[i].1.entry(umi.as_str()).or_insert(0) += 1;
+        }
+
+        let dedup = UmiDeduplicator::new(max_distance);
+        let results: Vec<(String, usize)> = groups
+            .par_iter()
+            .map(|(_, counts)| {
+                let umis: Vec<Umi> = counts
+                    .iter()
+                    .map(|(seq, &count)| Umi::with_count(seq.to_string(), count))
+                    .collect();
+                let molecules = dedup.deduplicate(&umis);
+                let representative = molecules
+                    .iter()
+                    .max_by(|a, b| {
+                        a.total_count
+                            .cmp(&b.total_count)
+                            .then_with(|| b.representative.cmp(&a.representative))
+                    })
+                    .map(|g| g.representative.clone())
+                    .unwrap_or_default();
+                (representative, molecules.len())
+            })
+            .collect();
+
+        let mut out = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for (((bc, gene), _), (representative, count)) in groups.iter().zip(results) {
+            out.0.push(bc.to_string());
+            out.1.push(gene.to_string());
+            out.2.push(representative);
+            out.3.push(count);
+        }
+        out
+    }))
+}
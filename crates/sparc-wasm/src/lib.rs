@@ -0,0 +1,194 @@
+//! WebAssembly bindings for SPARC read checks
+//!
+//! Runs SPARC's own FASTQ parser, protocol extraction, and QC statistics over
+//! reads held in memory, so a browser-based read checker reports exactly what
+//! the pipeline would see. Build with
+//! `wasm-pack build crates/sparc-wasm --target web`.
+//!
+//! The JavaScript entry points take FASTQ bytes (plain or gzipped) and return
+//! JSON strings.
+
+use serde::Serialize;
+use sparc_core::barcode::Whitelist;
+use sparc_core::fastq::{FastqParser, QualityEncoding};
+use sparc_core::protocols::{self, PROTOCOL_NAMES};
+use sparc_core::qc::stats::FastqStats;
+use sparc_core::Result;
+use std::io::Cursor;
+use wasm_bindgen::prelude::*;
+
+/// An R1 sample checked against one protocol
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadCheck {
+    /// Protocol name (as accepted by `sparc extract --protocol`)
+    pub protocol: String,
+    /// R1 bases the protocol needs for barcode and UMI
+    pub required_length: usize,
+    pub encoding: QualityEncoding,
+    pub mean_length: f64,
+    pub mean_quality: f64,
+    pub q30_fraction: f64,
+    pub gc_fraction: f64,
+    /// Reads from which barcode and UMI could be extracted
+    pub extracted: u64,
+    pub extracted_fraction: f64,
+    /// Extracted barcodes found in the whitelist, if one was given
+    pub whitelist_matches: Option<u64>,
+    pub whitelist_fraction: Option<f64>,
+    pub stats: FastqStats,
+}
+
+/// Check the first `max_reads` R1 records (0 = all) against a protocol
+pub fn check_reads(
+    r1: &[u8],
+    protocol: &str,
+    whitelist: Option<&Whitelist>,
+    max_reads: u64,
+) -> Result<ReadCheck> {
+    let chemistry = protocols::from_name(protocol)?;
+    let rs = chemistry.read_structure();
+    let required_length = (rs.barcode_start + rs.barcode_len).max(rs.umi_start + rs.umi_len);
+
+    let mut parser = FastqParser::from_reader(Cursor::new(r1.to_vec()), "R1")?;
+    let encoding = parser.detect_encoding();
+    let limit = if max_reads == 0 { u64::MAX } else { max_reads };
+    let mut stats = FastqStats::default();
+    let (mut extracted, mut matches) = (0u64, 0u64);
+    while let Some(record) = parser.next_ref() {
+        if stats.reads >= limit {
+            break;
+        }
+        let record = record?;
+        stats.add_ref(record);
+        if let Ok(components) = chemistry.extract_r1(record.seq, record.qual) {
+            extracted += 1;
+            if whitelist.is_some_and(|w| w.contains(&components.barcode_str())) {
+                matches += 1;
+            }
+        }
+    }
+
+    let reads = stats.reads.max(1) as f64;
+    Ok(ReadCheck {
+        protocol: protocol.to_string(),
+        required_length,
+        encoding,
+        mean_length: stats.mean_length(),
+        mean_quality: stats.mean_quality(),
+        q30_fraction: stats.q30_fraction(),
+        gc_fraction: stats.gc_fraction(),
+        extracted,
+        extracted_fraction: extracted as f64 / reads,
+        whitelist_matches: whitelist.map(|_| matches),
+        whitelist_fraction: whitelist.map(|_| matches as f64 / reads),
+        stats,
+    })
+}
+
+/// Check an R1 sample against every built-in protocol, best match first.
+///
+/// Protocols are ranked by whitelist hit rate, then by the fraction of reads
+/// long enough to extract.
+pub fn rank_protocols(
+    r1: &[u8],
+    whitelist: Option<&Whitelist>,
+    max_reads: u64,
+) -> Result<Vec<ReadCheck>> {
+    let mut checks = PROTOCOL_NAMES
+        .iter()
+        .map(|name| check_reads(r1, name, whitelist, max_reads))
+        .collect::<Result<Vec<_>>>()?;
+    checks.sort_by(|a, b| {
+        let score = |c: &ReadCheck| (c.whitelist_fraction.unwrap_or(0.0), c.extracted_fraction);
+        let (a, b) = (score(a), score(b));
+        b.0.total_cmp(&a.0).then(b.1.total_cmp(&a.1))
+    });
+    Ok(checks)
+}
+
+fn parse_whitelist(text: Option<String>) -> Result<Option<Whitelist>> {
+    text.map(|t| Whitelist::from_reader(t.as_bytes())).transpose()
+}
+
+/// Check an R1 sample against a protocol. `whitelist` is newline-separated
+/// barcodes; returns a JSON-encoded `ReadCheck`.
+#[wasm_bindgen(js_name = checkReads)]
+pub fn check_reads_js(
+    r1: &[u8],
+    protocol: &str,
+    whitelist: Option<String>,
+    max_reads: u32,
+) -> std::result::Result<String, JsError> {
+    let whitelist = parse_whitelist(whitelist)?;
+    let check = check_reads(r1, protocol, whitelist.as_ref(), max_reads as u64)?;
+    Ok(serde_json::to_string(&check)?)
+}
+
+/// Check an R1 sample against every protocol; returns a JSON array of
+/// `ReadCheck`, best match first
+#[wasm_bindgen(js_name = rankProtocols)]
+pub fn rank_protocols_js(
+    r1: &[u8],
+    whitelist: Option<String>,
+    max_reads: u32,
+) -> std::result::Result<String, JsError> {
+    let whitelist = parse_whitelist(whitelist)?;
+    let checks = rank_protocols(r1, whitelist.as_ref(), max_reads as u64)?;
+    Ok(serde_json::to_string(&checks)?)
+}
+
+/// Built-in protocol names as a JSON array
+#[wasm_bindgen(js_name = protocolNames)]
+pub fn protocol_names_js() -> String {
+    serde_json::to_string(&PROTOCOL_NAMES).unwrap_or_default()
+}
+
+/// SPARC version
+#[wasm_bindgen]
+pub fn version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r1_fastq(barcodes: &[&str], umi_len: usize) -> Vec<u8> {
+        let mut fastq = String::new();
+        for (i, bc) in barcodes.iter().enumerate() {
+            let seq = format!("{}{}", bc, "A".repeat(umi_len));
+            fastq.push_str(&format!("@read{}\n{}\n+\n{}\n", i, seq, "I".repeat(seq.len())));
+        }
+        fastq.into_bytes()
+    }
+
+    #[test]
+    fn test_check_reads() {
+        let whitelist =
+            Whitelist::from_reader("AAAACCCCGGGGTTTT\nACGTACGTACGTACGT\n".as_bytes()).unwrap();
+        let r1 = r1_fastq(&["AAAACCCCGGGGTTTT", "ACGTACGTACGTACGT", "TTTTTTTTTTTTTTTT"], 12);
+        let check = check_reads(&r1, "10x-3prime-v3", Some(&whitelist), 0).unwrap();
+        assert_eq!(check.stats.reads, 3);
+        assert_eq!(check.required_length, 28);
+        assert_eq!(check.extracted, 3);
+        assert_eq!(check.whitelist_matches, Some(2));
+        assert!((check.q30_fraction - 1.0).abs() < 1e-9);
+
+        let limited = check_reads(&r1, "10x-3prime-v3", None, 1).unwrap();
+        assert_eq!(limited.stats.reads, 1);
+        assert_eq!(limited.whitelist_fraction, None);
+        assert!(check_reads(&r1, "no-such-kit", None, 0).is_err());
+    }
+
+    #[test]
+    fn test_rank_protocols() {
+        // 26 bp reads fit 10x v2 (16 + 10) but are too short for v3 (16 + 12)
+        let whitelist = Whitelist::from_reader("AAAACCCCGGGGTTTT\n".as_bytes()).unwrap();
+        let r1 = r1_fastq(&["AAAACCCCGGGGTTTT"; 4], 10);
+        let checks = rank_protocols(&r1, Some(&whitelist), 0).unwrap();
+        assert_eq!(checks.len(), PROTOCOL_NAMES.len());
+        assert_eq!(checks[0].whitelist_fraction, Some(1.0));
+        let v3 = checks.iter().find(|c| c.protocol == "10x-3prime-v3").unwrap();
+        assert_eq!(v3.extracted, 0);
+    }
+}
@@ -13,6 +13,7 @@ __version__ = "0.1.0"
 try:
     from sparc._sparc_py import (
         FastqParser,
+        PairedFastqParser,
         FastqRecord,
         FastqWriter,
         BamParser,
@@ -27,12 +28,15 @@ try:
     from sparc._sparc_py import (
         QcMetrics,
         QcReport,
+        Protocol,
+        ReadStructure,
         py_normalize_total as rust_normalize_total,
         py_pca as rust_pca,
         py_run_analysis as rust_run_analysis,
         py_label_propagation as rust_label_propagation,
         adjusted_rand_index as rust_adjusted_rand_index,
         normalized_mutual_info as rust_normalized_mutual_info,
+        run,
     )
 
     _RUST_AVAILABLE = True
@@ -50,6 +54,7 @@ from sparc import validation
 if TYPE_CHECKING:
     from sparc._sparc_py import (
         FastqParser,
+        PairedFastqParser,
         FastqRecord,
         FastqWriter,
         BamParser,
@@ -65,6 +70,7 @@ __all__ = [
     "__version__",
     # Rust classes
     "FastqParser",
+    "PairedFastqParser",
     "FastqRecord",
     "FastqWriter",
     "BamParser",
@@ -84,6 +90,8 @@ __all__ = [
     "extract_barcodes",
     "correct_barcodes",
     "deduplicate_umis",
+    # Pipeline
+    "run",
     # Analysis
     "to_anndata",
     "from_anndata",
@@ -0,0 +1,672 @@
+# Generated by scripts/generate_stubs.py from crates/sparc-py/src; do not edit.
+
+from __future__ import annotations
+
+import os
+import types
+from typing import Any, Callable, Iterator, Sequence
+
+import anndata
+import numpy as np
+import numpy.typing as npt
+import pyarrow as pa
+import scipy.sparse
+
+__version__: str
+
+
+class FastqParser:
+    """Python wrapper for FastqParser"""
+    def __init__(self, path: str) -> None: ...
+    def __iter__(self) -> FastqParser: ...
+    def __next__(self) -> FastqRecord: ...
+    def read_all(self) -> list[FastqRecord]:
+        """Read all records into a list (parsing runs without the GIL)"""
+        ...
+    def read_chunk(self, n: int) -> dict[str, npt.NDArray[Any]]:
+        """Read up to `n` records as columns: `id`, `seq`, and `qual` as numpy
+        fixed-width bytes arrays plus `length` (uint32). An empty batch
+        (`len(batch["seq"]) == 0`) marks the end of the file.
+        """
+        ...
+    def to_arrow_batches(self, batch_size: int = 100_000) -> ArrowBatches:
+        """Iterate over `pyarrow.RecordBatch`es of up to `batch_size` records
+        with columns `id` (string), `seq` and `qual` (binary), and `length`
+        (uint32), e.g. for `polars.from_arrow` or `.to_pandas()`
+        """
+        ...
+
+
+class FastqRecord:
+    """Python wrapper for FastqRecord"""
+    def __init__(self, id: str, seq: bytes, qual: bytes) -> None: ...
+    @property
+    def id(self) -> str: ...
+    @property
+    def seq(self) -> bytes: ...
+    @property
+    def qual(self) -> bytes: ...
+    def seq_str(self) -> str:
+        """Get sequence as string"""
+        ...
+    def qual_str(self) -> str:
+        """Get quality as string"""
+        ...
+    def mean_quality(self) -> float:
+        """Get mean quality score"""
+        ...
+    def subsequence(self, start: int, len: int) -> list[int] | None:
+        """Get subsequence"""
+        ...
+    def __repr__(self) -> str: ...
+
+
+class PairedFastqParser:
+    """Python wrapper for PairedFastqParser"""
+    def __init__(self, r1: str, r2: str, check_names: bool = True) -> None:
+        """Open R1 and R2 together. With `check_names` (the default), a pair whose
+        read names differ raises instead of silently drifting out of sync.
+        """
+        ...
+    def __iter__(self) -> PairedFastqParser: ...
+    def __next__(self) -> tuple[FastqRecord, FastqRecord]: ...
+    def read_chunk(self, n: int) -> dict[str, npt.NDArray[Any]]:
+        """Read up to `n` pairs as columns: `id` (from R1), `r1_seq`, `r1_qual`,
+        `r2_seq`, and `r2_qual` as numpy fixed-width bytes arrays. An empty
+        batch marks the end of the files.
+        """
+        ...
+    def to_arrow_batches(self, batch_size: int = 100_000) -> ArrowBatches:
+        """Iterate over `pyarrow.RecordBatch`es of up to `batch_size` pairs with
+        columns `id` (string, from R1) and `r1_seq`, `r1_qual`, `r2_seq`, and
+        `r2_qual` (binary)
+        """
+        ...
+
+
+class FastqWriter:
+    """Python wrapper for FastqWriter
+
+    The file appears at its path on `close()` (or a clean `with` exit); a
+    writer discarded without closing leaves no partial file behind.
+    """
+    def __init__(self, path: str, compression: str | None = None, level: int | None = None, threads: int = 1) -> None:
+        """Open `path` for writing. `compression` is "plain", "gzip", or "zstd"
+        (default: from the extension, `.gz` or `.zst`); `level` defaults to 6
+        for gzip and 3 for zstd; `threads` > 1 compresses blocks in parallel.
+        """
+        ...
+    def write(self, record: FastqRecord) -> None:
+        """Write a record"""
+        ...
+    def close(self) -> None:
+        """Finish the file, move it into place, and close the writer"""
+        ...
+    def __enter__(self) -> FastqWriter: ...
+    def __exit__(self, exc_type: type[BaseException] | None, _exc_value: BaseException | None, _traceback: types.TracebackType | None) -> bool:
+        """Close on a clean exit; discard the partial file if the block raised"""
+        ...
+
+
+class BamParser:
+    """Python wrapper for BamParser"""
+    def __init__(self, path: str) -> None: ...
+    def reference_names(self) -> list[str]:
+        """Get reference names from header"""
+        ...
+    def reference_lengths(self) -> list[int]:
+        """Get reference lengths from header, in the same order as `reference_names()`"""
+        ...
+    @property
+    def header(self) -> str:
+        """Full SAM header text"""
+        ...
+    def fetch(self, chrom: str, start: int, end: int) -> list[BamRecord]:
+        """Records overlapping `chrom:start-end` (0-based, half-open). Requires a
+        `.bai`/`.csi` index and does not move the iteration position.
+        """
+        ...
+    def __iter__(self) -> BamParser: ...
+    def __next__(self) -> BamRecord: ...
+    def read_all(self) -> list[BamRecord]:
+        """Read all records into a list (parsing runs without the GIL)"""
+        ...
+    def read_chunk(self, n: int) -> dict[str, npt.NDArray[Any]]:
+        """Read up to `n` records as columns: `name`, `seq`, `qual`, `cigar`, and
+        the `CB`/`UB`/`GN`/`GX` tags as numpy fixed-width bytes arrays (empty
+        when a tag is missing), plus numeric `mapq`, `tid`, `pos`,
+        `is_mapped`, and `is_reverse`. An empty batch marks the end of the file.
+        """
+        ...
+    def filter_by_mapq(self, min_mapq: int) -> list[BamRecord]:
+        """Filter records by mapping quality"""
+        ...
+    def to_arrow_batches(self, batch_size: int = 100_000) -> ArrowBatches:
+        """Iterate over `pyarrow.RecordBatch`es of up to `batch_size` records
+        with columns `id` (read name), `cigar`, and the `CB`/`UB`/`GN`/`GX`
+        tags as strings (null when a tag is missing), `seq` and `qual` as
+        binary, and numeric `mapq`, `tid`, `pos`, `is_mapped`, and `is_reverse`
+        """
+        ...
+
+
+class BamRecord:
+    """Python wrapper for BamRecord"""
+    @property
+    def name(self) -> str: ...
+    @property
+    def seq(self) -> bytes: ...
+    @property
+    def qual(self) -> bytes: ...
+    @property
+    def mapq(self) -> int: ...
+    @property
+    def tid(self) -> int: ...
+    @property
+    def pos(self) -> int: ...
+    @property
+    def cigar(self) -> str: ...
+    @property
+    def cell_barcode(self) -> str | None: ...
+    @property
+    def umi(self) -> str | None: ...
+    @property
+    def gene_name(self) -> str | None: ...
+    @property
+    def gene_id(self) -> str | None: ...
+    @property
+    def is_mapped(self) -> bool: ...
+    @property
+    def is_reverse(self) -> bool: ...
+    def has_valid_tags(self) -> bool:
+        """Check if record has valid cell barcode and UMI"""
+        ...
+    def is_assigned(self) -> bool:
+        """Check if record is assigned to a gene"""
+        ...
+    def __repr__(self) -> str: ...
+
+
+class ArrowBatches:
+    """Iterator of `pyarrow.RecordBatch`es of up to `batch_size` records, as
+    returned by the parsers' `to_arrow_batches`
+    """
+    def __iter__(self) -> ArrowBatches: ...
+    def __next__(self) -> pa.RecordBatch: ...
+
+
+class Whitelist:
+    """Python wrapper for Whitelist"""
+    def __init__(self, path: str) -> None:
+        """Create whitelist from file"""
+        ...
+    @staticmethod
+    def from_list(barcodes: Sequence[str]) -> Whitelist:
+        """Create whitelist from list of barcodes"""
+        ...
+    @staticmethod
+    def from_numpy(array: npt.NDArray[np.bytes_]) -> Whitelist:
+        """Create whitelist from a numpy bytes array (dtype `S<n>`), e.g. a
+        `read_chunk` column or `np.loadtxt(path, dtype="S16")`. Empty values
+        (such as missing tags) are skipped.
+        """
+        ...
+    def contains(self, barcode: str) -> bool:
+        """Check if barcode is in whitelist"""
+        ...
+    def __contains__(self, barcode: str) -> bool: ...
+    def __iter__(self) -> Iterator[str]:
+        """Iterate over barcodes in sorted order"""
+        ...
+    def intersection(self, other: Whitelist) -> Whitelist:
+        """Barcodes in both whitelists"""
+        ...
+    def union(self, other: Whitelist) -> Whitelist:
+        """Barcodes in either whitelist"""
+        ...
+    def difference(self, other: Whitelist) -> Whitelist:
+        """Barcodes in this whitelist but not in `other`"""
+        ...
+    def __and__(self, other: Whitelist) -> Whitelist: ...
+    def __or__(self, other: Whitelist) -> Whitelist: ...
+    def __sub__(self, other: Whitelist) -> Whitelist: ...
+    def __len__(self) -> int:
+        """Get number of barcodes"""
+        ...
+    def barcode_len(self) -> int:
+        """Get expected barcode length"""
+        ...
+    def to_list(self) -> list[str]:
+        """Get all barcodes as list"""
+        ...
+    def __repr__(self) -> str: ...
+
+
+class BarcodeCorrector:
+    """Python wrapper for BarcodeCorrector"""
+    def __init__(self, whitelist: Whitelist, max_distance: int) -> None:
+        """Create a barcode corrector"""
+        ...
+    def match_barcode(self, barcode: str) -> tuple[str, str | None, int]:
+        """Match a barcode, returning (status, corrected_barcode, distance)
+        status: "exact", "corrected", or "no_match"
+        """
+        ...
+    def is_valid(self, barcode: str) -> bool:
+        """Check if barcode is valid (exact or correctable)"""
+        ...
+    def correct(self, barcode: str) -> str | None:
+        """Get corrected barcode or None"""
+        ...
+    def correct_batch(self, barcodes: Sequence[str]) -> list[str | None]:
+        """Batch correct barcodes in parallel, without holding the GIL"""
+        ...
+
+
+class ReadStructure:
+    """Python wrapper for ReadStructure"""
+    def __init__(self, barcode_start: int, barcode_len: int, umi_start: int, umi_len: int, cdna_start: int = 0) -> None: ...
+    @staticmethod
+    def parse(spec: str) -> ReadStructure:
+        """Parse a spec such as `16C12U` or `8X12C8U+T`
+        (C = barcode, U = UMI, X = skip, T = cDNA to the end of the read)
+        """
+        ...
+    @property
+    def barcode_start(self) -> int: ...
+    @property
+    def barcode_len(self) -> int: ...
+    @property
+    def umi_start(self) -> int: ...
+    @property
+    def umi_len(self) -> int: ...
+    @property
+    def cdna_start(self) -> int: ...
+    def min_len(self) -> int:
+        """Minimum R1 length that contains the barcode and UMI"""
+        ...
+    def __str__(self) -> str: ...
+    def __repr__(self) -> str: ...
+
+
+class Protocol:
+    """Python wrapper for a single-cell protocol"""
+    def __init__(self, name: str) -> None:
+        """Look up a built-in protocol by name (e.g. `10x-3prime-v3`)"""
+        ...
+    @staticmethod
+    def from_read_structure(read_structure: ReadStructure | str) -> Protocol:
+        """Protocol for a custom chemistry, from a ReadStructure or a spec string"""
+        ...
+    @staticmethod
+    def available() -> list[str]:
+        """Names accepted by the constructor"""
+        ...
+    @property
+    def name(self) -> str: ...
+    @property
+    def version(self) -> str: ...
+    @property
+    def read_structure(self) -> ReadStructure: ...
+    def extract_r1(self, seq: str, qual: str | None = None) -> dict[str, str]:
+        """Split an R1 read into barcode, UMI, and cDNA
+
+        Returns a dict with `barcode`, `umi`, `cdna` and matching `*_qual`
+        strings. Quality defaults to all `I` when omitted.
+        """
+        ...
+    def __repr__(self) -> str: ...
+
+
+class CountMatrix:
+    """Python wrapper for CountMatrix"""
+    def __init__(self) -> None:
+        """Create empty count matrix"""
+        ...
+    @classmethod
+    def read_mtx(cls, path: str) -> CountMatrix:
+        """Read a Matrix Market directory (`matrix.mtx`, `barcodes.tsv`,
+        `genes.tsv`/`features.tsv`, optionally gzipped) or a `matrix.mtx` path
+        """
+        ...
+    @classmethod
+    def read_h5ad(cls, path: str) -> CountMatrix:
+        """Read raw counts from an AnnData `.h5ad` file (cells as obs, genes as var)"""
+        ...
+    @property
+    def barcodes(self) -> list[str]:
+        """Get barcodes (column names)"""
+        ...
+    @property
+    def genes(self) -> list[str]:
+        """Get genes (row names)"""
+        ...
+    def gene_metadata(self) -> dict[str, list[str] | np.ndarray]:
+        """Per-gene metadata: column name -> list of str or numpy array"""
+        ...
+    def cell_metadata(self) -> dict[str, list[str] | np.ndarray]:
+        """Per-cell metadata: column name -> list of str or numpy array"""
+        ...
+    def set_gene_metadata(self, name: str, values: Sequence[str | int | float]) -> None:
+        """Add or replace a gene metadata column of str, int, or float values,
+        one per gene
+        """
+        ...
+    def set_cell_metadata(self, name: str, values: Sequence[str | int | float]) -> None:
+        """Add or replace a cell metadata column of str, int, or float values,
+        one per cell
+        """
+        ...
+    def annotate_genes(self, gtf_path: str) -> int:
+        """Fill the chromosome, biotype, and length gene metadata from a GTF
+        (.gz supported); returns the number of genes found in it
+        """
+        ...
+    def rollup_to_genes(self) -> CountMatrix:
+        """Sum transcript rows into one row per gene of the `gene_id` gene metadata
+        column (set it with `set_gene_metadata` for matrices read from disk)
+        """
+        ...
+    @property
+    def n_rows(self) -> int:
+        """Get number of rows (genes)"""
+        ...
+    @property
+    def n_cols(self) -> int:
+        """Get number of columns (cells)"""
+        ...
+    @property
+    def nnz(self) -> int:
+        """Get number of non-zero entries"""
+        ...
+    @property
+    def shape(self) -> tuple[int, int]:
+        """Get shape as tuple"""
+        ...
+    def row_indices(self) -> npt.NDArray[np.uintp]:
+        """Get row indices as numpy array"""
+        ...
+    def col_indices(self) -> npt.NDArray[np.uintp]:
+        """Get column indices as numpy array"""
+        ...
+    def values(self) -> npt.NDArray[np.uint32]:
+        """Get values as numpy array"""
+        ...
+    def gene_vector(self, gene: str) -> tuple[npt.NDArray[np.uintp], npt.NDArray[np.uint32]] | None:
+        """Non-zero (cell indices, counts) of a gene by ID or symbol, or None"""
+        ...
+    def cell_vector(self, barcode: str) -> tuple[npt.NDArray[np.uintp], npt.NDArray[np.uint32]] | None:
+        """Non-zero (gene indices, counts) of a cell barcode, or None"""
+        ...
+    def counts_per_cell(self) -> npt.NDArray[np.uint64]:
+        """Get total counts per cell as numpy array"""
+        ...
+    def counts_per_gene(self) -> npt.NDArray[np.uint64]:
+        """Get total counts per gene as numpy array"""
+        ...
+    def genes_per_cell(self) -> npt.NDArray[np.uint64]:
+        """Get number of genes detected per cell"""
+        ...
+    def cells_per_gene(self) -> npt.NDArray[np.uint64]:
+        """Get number of cells expressing each gene"""
+        ...
+    def to_dense(self) -> npt.NDArray[np.uint32]:
+        """Convert to a dense numpy array, genes x cells. Raises MemoryError when
+        the array would not fit in memory (the `--max-memory` budget, else the
+        memory available); use `to_dense_chunk` or `to_scipy` for large ones.
+        """
+        ...
+    def to_dense_chunk(self, rows: int) -> DenseChunks:
+        """Iterate over the dense matrix `rows` genes at a time, as numpy arrays
+        of `rows` x cells (the last may be shorter), so a matrix too large for
+        `to_dense` can be processed in slices
+        """
+        ...
+    def to_scipy(self, cells_as_rows: bool = False) -> scipy.sparse.csr_matrix:
+        """Convert to a `scipy.sparse.csr_matrix`, genes x cells (or cells x genes
+        with `cells_as_rows=True`). The CSR buffers are built once and handed to
+        numpy without copying; int64 indices keep scipy from copying them again.
+        """
+        ...
+    def to_anndata(self, qc: QcReport | None = None) -> anndata.AnnData:
+        """Convert to an `anndata.AnnData` (cells x genes) with barcodes as `obs`
+        and genes as `var`, plus per-cell and per-gene count QC columns. When a
+        `QcReport` is given, its per-cell metrics are joined by barcode and its
+        summary metrics stored in `uns["qc"]`.
+        """
+        ...
+    def write_mtx(self, path: str) -> None:
+        """Write to Matrix Market format"""
+        ...
+    def write_barcodes(self, path: str) -> None:
+        """Write barcodes to file"""
+        ...
+    def write_genes(self, path: str) -> None:
+        """Write genes to file"""
+        ...
+    def __getitem__(self, key: Any) -> CountMatrix | int:
+        """`matrix[genes]` or `matrix[genes, cells]`, genes first as in `shape`.
+
+        Each axis takes an int, a slice, a gene ID/symbol or barcode, or a
+        list/array of ints, names, or booleans (a mask over the axis). Two
+        ints give the count; anything else gives a new CountMatrix.
+        """
+        ...
+    def __len__(self) -> int: ...
+    def __repr__(self) -> str: ...
+
+
+class GeneCounter:
+    """Python wrapper for GeneCounter"""
+    def __init__(self) -> None: ...
+    def add_count(self, barcode: str, gene: str, count: int) -> None:
+        """Add a count for a barcode-gene pair"""
+        ...
+    def add_counts(self, barcodes: Sequence[str], genes: Sequence[str], counts: npt.ArrayLike | None = None) -> None:
+        """Add many barcode-gene counts in one call. `counts` is any array-like of
+        non-negative integers (all 1 when omitted). Pairs are aggregated in
+        parallel without the GIL, keeping first-seen order for barcodes and genes.
+        """
+        ...
+    def increment(self, barcode: str, gene: str) -> None:
+        """Increment count by 1"""
+        ...
+    def num_cells(self) -> int:
+        """Get number of cells"""
+        ...
+    def num_genes(self) -> int:
+        """Get number of genes"""
+        ...
+    def build(self) -> CountMatrix:
+        """Build the count matrix (merging any spilled runs without the GIL)"""
+        ...
+    def __repr__(self) -> str: ...
+
+
+class DenseChunks:
+    """Iterator of dense row blocks, as returned by `CountMatrix.to_dense_chunk`"""
+    def __iter__(self) -> DenseChunks: ...
+    def __next__(self) -> npt.NDArray[np.uint32]: ...
+    def __len__(self) -> int:
+        """Number of blocks left"""
+        ...
+
+
+class QcMetrics:
+    """Python wrapper for QcMetrics"""
+    def __init__(self, **kwargs: Any) -> None:
+        """Create metrics; any field can be given as a keyword (e.g. `total_reads=1000`)"""
+        ...
+    @staticmethod
+    def from_json(json: str) -> QcMetrics:
+        """Parse metrics from JSON"""
+        ...
+    def to_json(self) -> str: ...
+    def to_dict(self) -> dict[str, Any]:
+        """All fields as a dict"""
+        ...
+    @property
+    def total_reads(self) -> int: ...
+    @property
+    def valid_barcode_reads(self) -> int: ...
+    @property
+    def valid_umi_reads(self) -> int: ...
+    @property
+    def mapped_reads(self) -> int: ...
+    @property
+    def assigned_reads(self) -> int: ...
+    @property
+    def num_cells(self) -> int: ...
+    @property
+    def total_genes(self) -> int: ...
+    @property
+    def mean_reads_per_cell(self) -> float: ...
+    @property
+    def median_reads_per_cell(self) -> float: ...
+    @property
+    def mean_genes_per_cell(self) -> float: ...
+    @property
+    def median_genes_per_cell(self) -> float: ...
+    @property
+    def mean_umi_per_cell(self) -> float: ...
+    @property
+    def median_umi_per_cell(self) -> float: ...
+    @property
+    def sequencing_saturation(self) -> float: ...
+    @property
+    def fraction_reads_in_cells(self) -> float: ...
+    def barcode_validity_rate(self) -> float: ...
+    def mapping_rate(self) -> float: ...
+    def assignment_rate(self) -> float: ...
+    def update_from_cells(self, reads: Sequence[int], genes: Sequence[int], umis: Sequence[int]) -> None:
+        """Fill cell count, mean, and median fields from per-cell reads, genes, and UMIs"""
+        ...
+    def __repr__(self) -> str: ...
+
+
+class QcReport:
+    """Python wrapper for QcReport"""
+    def __init__(self, sample_name: str, metrics: QcMetrics | None = None) -> None: ...
+    @staticmethod
+    def from_json(json: str) -> QcReport:
+        """Parse a report from JSON (e.g. `qc_report.json` written by `sparc pipeline`),
+        upgrading reports written by older releases
+        """
+        ...
+    @property
+    def schema_version(self) -> int:
+        """Schema version of the report JSON"""
+        ...
+    @property
+    def sample_name(self) -> str: ...
+    @property
+    def warnings(self) -> list[str]: ...
+    @property
+    def metrics(self) -> QcMetrics: ...
+    @metrics.setter
+    def metrics(self, metrics: QcMetrics) -> None: ...
+    @property
+    def num_cell_metrics(self) -> int:
+        """Number of cells with per-cell metrics"""
+        ...
+    def add_cell_metrics(self, barcode: str, reads: int, genes: int, umis: int, mito_percent: float = 0.0) -> None:
+        """Record metrics for one cell"""
+        ...
+    def per_cell_dataframe(self) -> pa.Table:
+        """Per-cell metrics as a `pyarrow.Table` with columns barcode, reads,
+        genes, umis, mito_percent, pass_qc, and qc_flags (call `.to_pandas()`
+        for a DataFrame)
+        """
+        ...
+    def add_warning(self, warning: str) -> None: ...
+    def generate_warnings(self) -> None: ...
+    def to_json(self) -> str: ...
+    def to_dict(self) -> dict[str, Any]:
+        """The whole report (metrics, per-cell metrics, warnings) as a dict"""
+        ...
+    def __repr__(self) -> str: ...
+
+
+def py_deduplicate_umis(barcodes: Sequence[str], umis: Sequence[str], genes: Sequence[str], max_distance: int = 1) -> tuple[list[str], list[str], list[str], list[int]]:
+    """Deduplicate UMIs within each (barcode, gene) group using directional
+    clustering. Groups are processed in parallel without holding the GIL.
+    Returns (barcodes, genes, representative_umis, counts), one entry per group
+    in order of first appearance, where counts is the number of molecules.
+    """
+    ...
+
+
+def py_normalize_total(data: Sequence[Sequence[float]], target_sum: float = 10000.0) -> npt.NDArray[np.float64]:
+    """Normalize cells to a target sum, apply log1p, and return processed matrix."""
+    ...
+
+
+def py_scale(data: Sequence[Sequence[float]], max_value: float | None = None) -> npt.NDArray[np.float64]:
+    """Scale data to zero mean and unit variance per gene."""
+    ...
+
+
+def py_highly_variable_genes(data: Sequence[Sequence[float]], min_mean: float = 0.0125, max_mean: float = 3.0, min_disp: float = 0.5) -> list[int]:
+    """Find highly variable genes. Returns list of gene indices."""
+    ...
+
+
+def py_pca(data: Sequence[Sequence[float]], n_components: int = 50) -> tuple[npt.NDArray[np.float64], npt.NDArray[np.float64]]:
+    """Run PCA. Returns (scores: n_cells x n_pcs, variances: n_pcs)."""
+    ...
+
+
+def py_build_knn_graph(coords: Sequence[Sequence[float]], k: int = 15) -> list[list[tuple[int, float]]]:
+    """Build KNN graph. Returns list of (neighbor_index, distance) per cell."""
+    ...
+
+
+def py_label_propagation(graph: Sequence[Sequence[tuple[int, float]]], resolution: float = 1.0, max_iterations: int = 100) -> list[int]:
+    """Run label propagation clustering. Returns cluster labels."""
+    ...
+
+
+def py_run_analysis(data: Sequence[Sequence[float]], n_pcs: int = 50, n_neighbors: int = 15, resolution: float = 1.0) -> tuple[npt.NDArray[np.float64], list[int], list[int]]:
+    """Run full analysis pipeline: normalize -> HVG -> scale -> PCA -> KNN -> cluster.
+    Returns (pca_scores, cluster_labels, hvg_indices).
+    """
+    ...
+
+
+def run(config: dict[str, Any], progress: Callable[[str, int], object] | None = None) -> tuple[CountMatrix, QcReport]:
+    """Run extraction, alignment, counting, and QC, returning
+    `(CountMatrix, QcReport)`.
+
+    `config` takes the `sparc pipeline` options by name (`r1`, `r2`,
+    `reference`, `whitelist`, `protocol`, `output`, `bam`, `min_genes`, ...).
+    With `bam`, alignment is skipped and R1/R2 are optional. With `output`,
+    the matrix and `qc_report.json` are written as by `sparc pipeline`.
+    `progress` is called as `progress(stage, reads)` when each stage starts
+    and every 100,000 reads; an exception it raises stops the run.
+    """
+    ...
+
+
+def adjusted_rand_index(labels_true: Sequence[int], labels_pred: Sequence[int]) -> float:
+    """Compute Adjusted Rand Index between two label vectors."""
+    ...
+
+
+def normalized_mutual_info(labels_true: Sequence[int], labels_pred: Sequence[int]) -> float:
+    """Compute Normalized Mutual Information between two label vectors."""
+    ...
+
+
+def pearson_correlation(x: Sequence[float], y: Sequence[float]) -> float:
+    """Compute Pearson correlation between two vectors."""
+    ...
+
+
+def spearman_correlation(x: Sequence[float], y: Sequence[float]) -> float:
+    """Compute Spearman rank correlation between two vectors."""
+    ...
+
+
+def f1_score(precision: float, recall: float) -> float:
+    """Compute F1 score from precision and recall."""
+    ...
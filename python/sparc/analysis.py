@@ -53,15 +53,7 @@ def to_anndata(
         barcodes = matrix.barcodes
         genes = matrix.genes
 
-        # Build sparse matrix
-        data = np.array(matrix.values(), dtype=np.float32)
-        row = np.array(matrix.row_indices())
-        col = np.array(matrix.col_indices())
-
-        sparse_mat = sp.csr_matrix(
-            (data, (col, row)),  # cells x genes
-            shape=(matrix.n_cols, matrix.n_rows),
-        )
+        sparse_mat = matrix.to_scipy(cells_as_rows=True).astype(np.float32)
     elif sp.issparse(matrix):
         sparse_mat = matrix
         if barcodes is None or genes is None:
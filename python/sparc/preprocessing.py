@@ -14,6 +14,7 @@ try:
         Whitelist,
         BarcodeCorrector,
         GeneCounter,
+        py_deduplicate_umis as _rust_deduplicate_umis,
     )
     _RUST_AVAILABLE = True
 except ImportError:
@@ -181,6 +182,9 @@ def deduplicate_umis(
     tuple
         (unique_barcodes, unique_genes, representative_umis, counts)
     """
+    if _RUST_AVAILABLE:
+        return _rust_deduplicate_umis(barcodes, umis, genes, max_distance)
+
     from collections import defaultdict
 
     # Group by barcode-gene
@@ -194,8 +198,7 @@ def deduplicate_umis(
     counts = []
 
     for (bc, gene), umi_list in groups.items():
-        # Simple deduplication: count unique UMIs
-        # For proper clustering, use the Rust implementation
+        # Fallback without the Rust bindings: count exact unique UMIs
         unique_umis = set(umi_list)
         unique_barcodes.append(bc)
         unique_genes.append(gene)
@@ -0,0 +1,478 @@
+"""
+Generate ``python/sparc/_sparc_py.pyi`` from the pyo3 bindings in ``crates/sparc-py/src``.
+
+Parameter names and defaults come from ``#[pyo3(signature = ...)]`` (or the Rust parameter
+list), types from the Rust parameter and return types, and docstrings from the ``///``
+comments. Where the Rust type does not pin down the Python type (``PyObject``, ``&PyAny``,
+``&PyDict``, ...), the method must carry ``#[pyo3(text_signature = "(...) -> T")]`` with
+annotated parameters; generation fails otherwise, so the stubs stay complete.
+
+Usage::
+
+    python scripts/generate_stubs.py           # rewrite the stub file
+    python scripts/generate_stubs.py --check   # exit 1 if it is out of date
+"""
+
+import argparse
+import re
+import sys
+from dataclasses import dataclass, field
+from pathlib import Path
+from typing import Dict, List, Optional, Tuple
+
+ROOT = Path(__file__).resolve().parent.parent
+SRC = ROOT / "crates" / "sparc-py" / "src"
+STUB = ROOT / "python" / "sparc" / "_sparc_py.pyi"
+
+HEADER = '''\
+# Generated by scripts/generate_stubs.py from crates/sparc-py/src; do not edit.
+
+from __future__ import annotations
+
+import os
+import types
+from typing import Any, Callable, Iterator, Sequence
+
+import anndata
+import numpy as np
+import numpy.typing as npt
+import pyarrow as pa
+import scipy.sparse
+
+__version__: str
+'''
+
+INTS = {"u8", "u16", "u32", "u64", "usize", "i8", "i16", "i32", "i64", "isize"}
+NUMPY_DTYPES = {
+    "u8": "np.uint8",
+    "u16": "np.uint16",
+    "u32": "np.uint32",
+    "u64": "np.uint64",
+    "usize": "np.uintp",
+    "i32": "np.int32",
+    "i64": "np.int64",
+    "f32": "np.float32",
+    "f64": "np.float64",
+    "bool": "np.bool_",
+}
+# Types whose Python shape the Rust type does not describe
+OPAQUE = {"PyObject", "PyAny", "PyDict", "PyList", "PyTuple", "PyType", "PyModule"}
+
+
+class StubError(Exception):
+    pass
+
+
+@dataclass
+class Function:
+    rust_name: str
+    doc: List[str]
+    attrs: List[str]
+    params: List[Tuple[str, str]]  # (name, Rust type), without self/py
+    ret: str  # Rust return type, "" for none
+    receiver: Optional[str]  # "self", "cls" or None
+
+    def pyo3_option(self, key: str) -> Optional[str]:
+        """Value of `key = ...` inside a `#[pyo3(...)]` attribute"""
+        for attr in self.attrs:
+            if not attr.startswith("pyo3("):
+                continue
+            for part in split_top(attr[len("pyo3(") : -1]):
+                name, _, value = part.partition("=")
+                if name.strip() == key:
+                    # Rust string continuation: backslash-newline skips the indentation
+                    return re.sub(r"\\\s+", "", value.strip())
+        return None
+
+    def has_attr(self, name: str) -> bool:
+        return any(a == name or a.startswith(name + "(") for a in self.attrs)
+
+
+@dataclass
+class Class:
+    rust_name: str
+    py_name: str
+    doc: List[str]
+    methods: List[Function] = field(default_factory=list)
+
+
+def split_top(text: str, sep: str = ",") -> List[str]:
+    """Split on `sep` outside brackets and string literals"""
+    parts, depth, current, in_str = [], 0, [], False
+    for i, ch in enumerate(text):
+        if ch == '"' and (i == 0 or text[i - 1] != "\\"):
+            in_str = not in_str
+        elif not in_str and ch in "<([{":
+            depth += 1
+        elif not in_str and ch in ">)]}" and not (ch == ">" and text[i - 1] == "-"):
+            depth -= 1
+        elif not in_str and ch == sep and depth == 0:
+            parts.append("".join(current).strip())
+            current = []
+            continue
+        current.append(ch)
+    if "".join(current).strip():
+        parts.append("".join(current).strip())
+    return parts
+
+
+def strip_rust(ty: str) -> str:
+    """Drop references, lifetimes, and `mut` from a Rust type"""
+    ty = re.sub(r"'\w+\s*,?\s*", "", ty)
+    ty = ty.replace("&mut ", "").replace("&", "").replace("mut ", "")
+    return re.sub(r"<\s*>", "", ty).strip()
+
+
+def generic(ty: str) -> Tuple[str, List[str]]:
+    """Split `Outer<A, B>` into ("Outer", ["A", "B"])"""
+    m = re.fullmatch(r"([\w:]+)\s*<(.*)>", ty, re.S)
+    if not m:
+        return ty, []
+    return m.group(1).split("::")[-1], split_top(m.group(2))
+
+
+class TypeMapper:
+    def __init__(self, classes: Dict[str, str]):
+        self.classes = classes  # Rust struct name -> Python class name
+
+    def map(self, ty: str, arg: bool, owner: str) -> str:
+        ty = strip_rust(ty)
+        if ty.startswith("(") and ty.endswith(")"):
+            items = split_top(ty[1:-1])
+            if not items:
+                return "None"
+            return "tuple[" + ", ".join(self.map(t, arg, owner) for t in items) + "]"
+        if ty.startswith("[") and ty.endswith("]"):
+            inner = ty[1:-1].strip()
+            if inner == "u8":
+                return "bytes"
+            return ("Sequence[" if arg else "list[") + self.map(inner, arg, owner) + "]"
+        name, args = generic(ty)
+        if name in ("PyResult", "Result"):
+            return self.map(args[0], arg, owner)
+        if name == "Option":
+            return self.map(args[0], arg, owner) + " | None"
+        if name == "Vec" and arg and args == ["u8"]:
+            return "bytes"
+        if name == "Vec":
+            return ("Sequence[" if arg else "list[") + self.map(args[0], arg, owner) + "]"
+        if name in ("HashMap", "BTreeMap", "AHashMap"):
+            key, value = (self.map(a, arg, owner) for a in args)
+            return f"dict[{key}, {value}]"
+        if name in ("PyArray1", "PyArray2", "PyReadonlyArray1", "PyReadonlyArray2"):
+            return f"npt.NDArray[{NUMPY_DTYPES.get(args[0], 'Any')}]"
+        if name in ("PyRef", "PyRefMut", "Py"):
+            return self.map(args[-1], arg, owner)
+        if name == "Self":
+            return owner
+        if name in self.classes:
+            return self.classes[name]
+        if name in ("String", "str"):
+            return "str"
+        if name in INTS:
+            return "int"
+        if name in ("f32", "f64"):
+            return "float"
+        if name == "bool":
+            return "bool"
+        if name in ("PathBuf", "Path"):
+            return "str | os.PathLike[str]"
+        if name in ("Cow",) and args and args[-1] == "[u8]":
+            return "bytes"
+        if name in OPAQUE:
+            raise StubError(f"{name} needs a text_signature")
+        raise StubError(f"no Python type for Rust type {ty!r}")
+
+
+def read_attr(lines: List[str], i: int) -> Tuple[str, int]:
+    """The attribute starting at line `i` (possibly multi-line) and the next line index"""
+    text = lines[i].strip()
+    while text.count("[") > text.count("]"):
+        i += 1
+        text += " " + lines[i].strip()
+    return text[2:-1].strip(), i + 1
+
+
+def read_signature(lines: List[str], i: int) -> Tuple[str, int]:
+    """The `fn` signature starting at line `i`, up to its body, and the next line index"""
+    text = ""
+    while True:
+        text += " " + lines[i].strip()
+        i += 1
+        if ("{" in text or text.rstrip().endswith(";")) and ")" in text:
+            break
+    return text.strip(), i
+
+
+def parse_fn(signature: str, doc: List[str], attrs: List[str]) -> Function:
+    m = re.match(r"(?:pub(?:\([\w:]+\))?\s+)?fn\s+(\w+)\s*(<[^(]*>)?\s*\(", signature)
+    if not m:
+        raise StubError(f"cannot parse {signature!r}")
+    start = m.end()
+    depth, end = 1, start
+    while depth:
+        depth += {"(": 1, ")": -1}.get(signature[end], 0)
+        end += 1
+    params_text = signature[start : end - 1]
+    rest = signature[end:]
+    ret = ""
+    if "->" in rest:
+        ret = rest.split("->", 1)[1]
+        ret = re.split(r"\s*(?:\{|where\b)", ret, 1)[0].strip()
+
+    params, receiver = [], None
+    for param in split_top(params_text):
+        if re.fullmatch(r"&?(?:'\w+\s+)?(?:mut\s+)?self", param):
+            receiver = "self"
+            continue
+        name, _, ty = param.partition(":")
+        name, ty = name.strip().removeprefix("mut "), ty.strip()
+        if name == "slf":
+            receiver = "self"
+        elif name.lstrip("_") == "cls":
+            receiver = "cls"
+        elif not strip_rust(ty).startswith("Python"):
+            params.append((name, ty))
+    return Function(m.group(1), doc, attrs, params, ret, receiver)
+
+
+def brace_change(line: str) -> int:
+    """Opened minus closed braces, ignoring string and char literals and comments"""
+    code = re.sub(r'"(?:\\.|[^"\\])*"|\'(?:\\.|[^\'\\])\'', "", line).split("//")[0]
+    return code.count("{") - code.count("}")
+
+
+def parse_file(path: Path, classes: Dict[str, Class], functions: Dict[str, Function]) -> None:
+    lines = path.read_text().splitlines()
+    doc: List[str] = []
+    attrs: List[str] = []
+    impl_for: Optional[str] = None
+    depth = 0
+    i = 0
+    while i < len(lines):
+        stripped = lines[i].strip()
+        if stripped.startswith("///"):
+            doc.append(stripped[3:].removeprefix(" "))
+            i += 1
+            continue
+        if stripped.startswith("#["):
+            attr, i = read_attr(lines, i)
+            attrs.append(attr)
+            continue
+        m = re.match(r"(?:pub(?:\([\w:]+\))?\s+)?struct\s+(\w+)", stripped)
+        if m and depth == 0:
+            for attr in attrs:
+                name = re.match(r'pyclass\(.*name\s*=\s*"(\w+)"', attr)
+                if name:
+                    classes[m.group(1)] = Class(m.group(1), name.group(1), doc)
+        m = re.match(r"impl\s+(\w+)\s*\{", stripped)
+        if m and depth == 0 and "pymethods" in attrs:
+            impl_for = m.group(1)
+        if re.match(r"(?:pub(?:\([\w:]+\))?\s+)?fn\s", stripped) and (
+            (depth == 1 and impl_for) or (depth == 0 and "pyfunction" in attrs)
+        ):
+            signature, j = read_signature(lines, i)
+            function = parse_fn(signature, doc, attrs)
+            if depth == 0:
+                functions[function.rust_name] = function
+            else:
+                classes[impl_for].methods.append(function)
+            depth += sum(brace_change(line) for line in lines[i:j])
+            doc, attrs, i = [], [], j
+            continue
+        depth += brace_change(stripped)
+        if depth == 0:
+            impl_for = None
+        if stripped and not stripped.startswith("//"):
+            doc, attrs = [], []
+        i += 1
+
+
+def python_default(value: str) -> str:
+    """A Rust default from `signature = (...)` as a Python literal"""
+    literal = {"true": "True", "false": "False", "None": "None"}.get(value, value)
+    if re.fullmatch(r"-?[\d_]+(\.[\d_]*)?|None|True|False|\"[^\"]*\"", literal):
+        return literal
+    return "..."
+
+
+def docstring(doc: List[str], indent: str) -> List[str]:
+    text = [line.rstrip() for line in doc]
+    while text and not text[-1]:
+        text.pop()
+    if not text:
+        return []
+    text = [t.replace("\\", "\\\\").replace('"""', '\\"\\"\\"') for t in text]
+    if len(text) == 1:
+        return [f'{indent}"""{text[0]}"""']
+    return [f'{indent}"""{text[0]}'] + [f"{indent}{t}" if t else "" for t in text[1:]] + [
+        f'{indent}"""'
+    ]
+
+
+def render_function(
+    function: Function, mapper: TypeMapper, owner: Optional[str], indent: str
+) -> List[str]:
+    """Stub lines for one function or method"""
+    name = function.pyo3_option("name")
+    name = name.strip('"') if name else function.rust_name
+    decorators = []
+    is_getter = function.has_attr("getter")
+    is_setter = function.has_attr("setter")
+    if function.has_attr("new"):
+        name = "__init__"
+    elif is_getter or is_setter:
+        prefix = "get_" if is_getter else "set_"
+        custom = next(
+            (a for a in function.attrs if a.startswith(("getter(", "setter("))), None
+        )
+        if custom:
+            name = custom[custom.index("(") + 1 : -1].strip()
+        else:
+            name = name.removeprefix(prefix)
+        decorators.append("@property" if is_getter else f"@{name}.setter")
+    elif function.has_attr("staticmethod"):
+        decorators.append("@staticmethod")
+    elif function.has_attr("classmethod"):
+        decorators.append("@classmethod")
+
+    receiver = []
+    if owner is not None and not function.has_attr("staticmethod"):
+        receiver = ["cls" if function.has_attr("classmethod") else "self"]
+
+    text_signature = function.pyo3_option("text_signature")
+    if text_signature:
+        sig = text_signature.strip('"').replace("$self", "self").replace("$cls", "cls")
+        params_text, _, ret = sig.partition("->")
+        params = split_top(params_text.strip()[1:-1])
+        params = [p for p in params if p not in ("self", "cls")]
+        ret = ret.strip() or "None"
+        if function.has_attr("new"):
+            ret = "None"
+    else:
+        params = render_params(function, mapper, owner)
+        ret = render_return(function, mapper, owner)
+
+    lines = [indent + d for d in decorators]
+    lines.append(f"{indent}def {name}({', '.join(receiver + params)}) -> {ret}:")
+    body = docstring(function.doc, indent + "    ")
+    if body:
+        lines.extend(body)
+        lines.append(f"{indent}    ...")
+    else:
+        lines[-1] += " ..."
+    return lines
+
+
+def render_params(function: Function, mapper: TypeMapper, owner: Optional[str]) -> List[str]:
+    types = {name: ty for name, ty in function.params}
+    signature = function.pyo3_option("signature")
+    if signature is None:
+        entries = [name for name, _ in function.params]
+    else:
+        entries = split_top(signature.strip()[1:-1])
+
+    def annotated(name: str) -> str:
+        if name not in types:
+            raise StubError(f"{function.rust_name}: no parameter {name!r}")
+        try:
+            return f"{name}: {mapper.map(types[name], True, owner or '')}"
+        except StubError as e:
+            raise StubError(f"{function.rust_name}: {e}") from None
+
+    params = []
+    for entry in entries:
+        name, _, default = (s.strip() for s in entry.partition("="))
+        if name == "*":
+            params.append("*")
+        elif name.startswith("**"):
+            raise StubError(f"{function.rust_name}: **{name[2:]} needs a text_signature")
+        elif name.startswith("*"):
+            raise StubError(f"{function.rust_name}: *{name[1:]} needs a text_signature")
+        elif default:
+            params.append(f"{annotated(name)} = {python_default(default)}")
+        else:
+            params.append(annotated(name))
+    return params
+
+
+def render_return(function: Function, mapper: TypeMapper, owner: Optional[str]) -> str:
+    if function.has_attr("new") or function.has_attr("setter") or not function.ret:
+        return "None"
+    ret = strip_rust(function.ret)
+    # `__next__` signals the end by returning None (raising StopIteration)
+    if function.rust_name == "__next__":
+        name, args = generic(ret)
+        if name == "PyResult":
+            ret = args[0]
+        name, args = generic(ret)
+        if name == "Option":
+            ret = args[0]
+    try:
+        return mapper.map(ret, False, owner or "")
+    except StubError as e:
+        raise StubError(f"{function.rust_name}: {e}") from None
+
+
+def exported(lib: str) -> Tuple[List[str], List[Tuple[str, str]]]:
+    """Registered classes (Rust names) and functions (module, Rust name), in order"""
+    classes = re.findall(r"add_class::<\w+::(\w+)>", lib)
+    functions = re.findall(r"wrap_pyfunction!\((\w+)::(\w+)", lib)
+    return classes, functions
+
+
+def generate() -> str:
+    classes: Dict[str, Class] = {}
+    functions: Dict[str, Function] = {}
+    for path in sorted(SRC.glob("*.rs")):
+        parse_file(path, classes, functions)
+    mapper = TypeMapper({c.rust_name: c.py_name for c in classes.values()})
+
+    class_names, function_names = exported((SRC / "lib.rs").read_text())
+    out = [HEADER]
+    errors = []
+    for rust_name in class_names:
+        cls = classes[rust_name]
+        lines = ["", f"class {cls.py_name}:"]
+        lines.extend(docstring(cls.doc, "    "))
+        for method in cls.methods:
+            try:
+                lines.extend(render_function(method, mapper, cls.py_name, "    "))
+            except StubError as e:
+                errors.append(f"{cls.py_name}.{e}")
+        if len(lines) == 2:
+            lines.append("    ...")
+        out.append("\n".join(lines) + "\n")
+    for _, rust_name in function_names:
+        try:
+            lines = render_function(functions[rust_name], mapper, None, "")
+        except StubError as e:
+            errors.append(str(e))
+            continue
+        out.append("\n" + "\n".join(lines) + "\n")
+    if errors:
+        raise StubError("\n".join(errors))
+    return "\n".join(out)
+
+
+def main() -> int:
+    parser = argparse.ArgumentParser(description=__doc__.split("\n\n")[0])
+    parser.add_argument("--check", action="store_true", help="fail if the stubs are stale")
+    args = parser.parse_args()
+    try:
+        stubs = generate()
+    except StubError as e:
+        print(f"error: incomplete signature metadata:\n{e}", file=sys.stderr)
+        return 1
+    if args.check:
+        if not STUB.exists() or STUB.read_text() != stubs:
+            print(f"{STUB.relative_to(ROOT)} is out of date; run {sys.argv[0]}", file=sys.stderr)
+            return 1
+        return 0
+    STUB.write_text(stubs)
+    print(f"wrote {STUB.relative_to(ROOT)}")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
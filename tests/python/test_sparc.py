@@ -137,6 +137,349 @@ class TestAnalysis:
         assert adata.uns.get("rank_genes_groups") is not None
 
 
+class TestCountMatrixBindings:
+    """Tests for the Rust CountMatrix bindings."""
+
+    @pytest.fixture
+    def count_matrix(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        counter = sparc_py.GeneCounter()
+        counter.add_count("CELL1", "GENE1", 10)
+        counter.add_count("CELL1", "GENE2", 3)
+        counter.add_count("CELL2", "GENE1", 5)
+        return counter.build()
+
+    def test_add_counts(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        counter = sparc_py.GeneCounter()
+
+        counter.add_counts(
+            ["CELL1", "CELL1", "CELL2", "CELL1"],
+            ["GENE1", "GENE2", "GENE1", "GENE1"],
+            np.array([10, 3, 5, 2]),
+        )
+        counter.add_counts(["CELL3"], ["GENE2"])
+        matrix = counter.build()
+
+        assert matrix.barcodes == ["CELL1", "CELL2", "CELL3"]
+        assert matrix.genes == ["GENE1", "GENE2"]
+        csr = matrix.to_scipy()
+        assert csr[0, 0] == 12
+        assert csr.sum() == 21
+        with pytest.raises(ValueError):
+            counter.add_counts(["CELL1"], ["GENE1", "GENE2"])
+
+    def test_read_mtx_roundtrip(self, count_matrix, tmp_dir):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        count_matrix.write_mtx(str(tmp_dir / "matrix.mtx"))
+        count_matrix.write_barcodes(str(tmp_dir / "barcodes.tsv"))
+        count_matrix.write_genes(str(tmp_dir / "genes.tsv"))
+
+        loaded = sparc_py.CountMatrix.read_mtx(str(tmp_dir))
+
+        assert loaded.barcodes == count_matrix.barcodes
+        assert loaded.genes == count_matrix.genes
+        assert (loaded.to_scipy() != count_matrix.to_scipy()).nnz == 0
+
+    def test_read_h5ad(self, tmp_dir, sample_matrix):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        pytest.importorskip("anndata")
+        from sparc.io import write_h5ad
+
+        matrix, barcodes, genes = sample_matrix
+        path = tmp_dir / "counts.h5ad"
+        write_h5ad(matrix, barcodes, genes, path)
+
+        try:
+            loaded = sparc_py.CountMatrix.read_h5ad(str(path))
+        except NotImplementedError:
+            pytest.skip("built without h5ad support")
+
+        assert loaded.barcodes == list(barcodes)
+        assert loaded.genes == list(genes)
+        assert loaded.to_scipy(cells_as_rows=True).sum() == matrix.sum()
+
+    def test_to_scipy(self, count_matrix):
+        csr = count_matrix.to_scipy()
+
+        assert isinstance(csr, sp.csr_matrix)
+        assert csr.shape == (2, 2)  # genes x cells
+        assert csr.nnz == 3
+        assert csr.sum() == 18
+        genes, barcodes = count_matrix.genes, count_matrix.barcodes
+        assert csr[genes.index("GENE1"), barcodes.index("CELL2")] == 5
+
+    def test_to_scipy_canonical(self, tmp_dir):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        (tmp_dir / "barcodes.tsv").write_text("CELL1\nCELL2\nCELL3\n")
+        (tmp_dir / "genes.tsv").write_text("GENE1\nGENE2\n")
+        # Entries out of order within both genes and cells
+        (tmp_dir / "matrix.mtx").write_text(
+            "%%MatrixMarket matrix coordinate integer general\n"
+            "2 3 5\n2 3 1\n1 3 4\n2 2 7\n1 1 2\n1 2 3\n"
+        )
+        matrix = sparc_py.CountMatrix.read_mtx(str(tmp_dir))
+
+        for cells_as_rows in (False, True):
+            csr = matrix.to_scipy(cells_as_rows=cells_as_rows)
+            assert csr.has_canonical_format
+            assert csr.nnz == 5
+        csr = matrix.to_scipy()
+        assert list(csr.indices[: csr.indptr[1]]) == [0, 1, 2]
+        assert csr[0, 2] == 4
+
+    def test_to_scipy_cells_as_rows(self, count_matrix):
+        csr = count_matrix.to_scipy(cells_as_rows=True)
+
+        assert csr.shape == (2, 2)
+        cell1 = count_matrix.barcodes.index("CELL1")
+        assert csr[cell1].sum() == 13
+
+    def test_to_anndata(self, count_matrix):
+        pytest.importorskip("anndata")
+
+        adata = count_matrix.to_anndata()
+
+        assert adata.n_obs == 2
+        assert adata.n_vars == 2
+        assert adata.obs.loc["CELL1", "total_counts"] == 13
+        assert adata.obs.loc["CELL1", "n_genes_by_counts"] == 2
+        assert adata.var.loc["GENE1", "n_cells_by_counts"] == 2
+
+
+class TestQcBindings:
+    """Tests for the Rust QcMetrics/QcReport bindings."""
+
+    def test_metrics_kwargs_and_rates(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+
+        metrics = sparc_py.QcMetrics(total_reads=1000, valid_barcode_reads=900, mapped_reads=800)
+
+        assert metrics.total_reads == 1000
+        assert metrics.barcode_validity_rate() == pytest.approx(0.9)
+        with pytest.raises(TypeError):
+            sparc_py.QcMetrics(not_a_field=1)
+
+    def test_metrics_json_round_trip(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+
+        metrics = sparc_py.QcMetrics(total_reads=42, num_cells=3)
+        restored = sparc_py.QcMetrics.from_json(metrics.to_json())
+
+        assert restored.to_dict() == metrics.to_dict()
+        assert restored.to_dict()["num_cells"] == 3
+
+    def test_report_warnings_and_dict(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+
+        metrics = sparc_py.QcMetrics(total_reads=1000, valid_barcode_reads=100, num_cells=50)
+        report = sparc_py.QcReport("sample1", metrics)
+        report.add_cell_metrics("CELL1", reads=100, genes=20, umis=30, mito_percent=2.5)
+        report.generate_warnings()
+
+        assert report.warnings
+        data = report.to_dict()
+        assert data["sample_name"] == "sample1"
+        assert data["metrics"]["total_reads"] == 1000
+        assert data["per_cell_metrics"][0]["barcode"] == "CELL1"
+
+        restored = sparc_py.QcReport.from_json(report.to_json())
+        assert restored.to_dict() == data
+
+    def test_per_cell_dataframe(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        pytest.importorskip("pyarrow")
+
+        report = sparc_py.QcReport("sample1")
+        report.add_cell_metrics("CELL1", reads=100, genes=20, umis=30, mito_percent=2.5)
+        report.add_cell_metrics("CELL2", reads=50, genes=10, umis=12)
+
+        table = report.per_cell_dataframe()
+
+        assert table.num_rows == 2
+        assert table.column_names == ["barcode", "reads", "genes", "umis", "mito_percent"]
+        df = table.to_pandas()
+        assert df.loc[df.barcode == "CELL2", "umis"].item() == 12
+        assert df["mito_percent"].tolist() == [2.5, 0.0]
+
+
+class TestProtocolBindings:
+    """Tests for the Rust Protocol/ReadStructure bindings."""
+
+    def test_read_structure_parse(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+
+        rs = sparc_py.ReadStructure.parse("8X12C8U+T")
+
+        assert (rs.barcode_start, rs.barcode_len) == (8, 12)
+        assert (rs.umi_start, rs.umi_len) == (20, 8)
+        assert rs.cdna_start == 28
+        assert str(rs) == "8X12C8U+T"
+        with pytest.raises(ValueError):
+            sparc_py.ReadStructure.parse("16C12Q")
+
+    def test_builtin_extract_r1(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+
+        protocol = sparc_py.Protocol("10x-3prime-v3")
+        parts = protocol.extract_r1("AAACCCAAGAAACACT" + "GGGGTTTTAAAA")
+
+        assert parts["barcode"] == "AAACCCAAGAAACACT"
+        assert parts["umi"] == "GGGGTTTTAAAA"
+        assert str(protocol.read_structure) == "16C12U"
+        assert "drop-seq" in sparc_py.Protocol.available()
+        with pytest.raises(ValueError):
+            protocol.extract_r1("ACGT")
+
+    def test_custom_protocol(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+
+        protocol = sparc_py.Protocol.from_read_structure("4X6C4U+T")
+        parts = protocol.extract_r1("NNNNAAAAAAGGGGCCCCCCCC", "#" * 22)
+
+        assert parts["barcode"] == "AAAAAA"
+        assert parts["umi"] == "GGGG"
+        assert parts["cdna"] == "CCCCCCCC"
+        assert parts["cdna_qual"] == "#" * 8
+
+
+class TestThreadedBindings:
+    """Tests for bindings that run without the GIL."""
+
+    def test_deduplicate_umis_clusters_neighbours(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+
+        barcodes = ["C1", "C1", "C1", "C1", "C2"]
+        umis = ["AAAA", "AAAA", "AAAT", "GGGG", "AAAA"]
+        genes = ["G1", "G1", "G1", "G1", "G1"]
+
+        bcs, gs, reps, counts = sparc_py.py_deduplicate_umis(barcodes, umis, genes)
+
+        assert bcs == ["C1", "C2"]
+        assert gs == ["G1", "G1"]
+        assert counts == [2, 1]  # AAAT folds into AAAA
+        assert reps[0] == "AAAA"
+
+    def test_correct_batch_from_threads(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        from concurrent.futures import ThreadPoolExecutor
+
+        whitelist = sparc_py.Whitelist.from_list(["AAAAAAAA", "CCCCCCCC"])
+        corrector = sparc_py.BarcodeCorrector(whitelist, 1)
+        batch = ["AAAAAAAT", "CCCCCCCC", "GGGGGGGG"] * 1000
+
+        with ThreadPoolExecutor(max_workers=4) as pool:
+            results = list(pool.map(corrector.correct_batch, [batch] * 4))
+
+        for result in results:
+            assert result[:3] == ["AAAAAAAA", "CCCCCCCC", None]
+
+
+class TestChunkedReading:
+    """Tests for columnar read_chunk batches."""
+
+    def test_bam_fetch(self, tmp_dir):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        pysam = pytest.importorskip("pysam")
+        path = str(tmp_dir / "sorted.bam")
+        header = {"HD": {"VN": "1.6", "SO": "coordinate"},
+                  "SQ": [{"SN": "chr1", "LN": 1000}, {"SN": "chr2", "LN": 500}]}
+        with pysam.AlignmentFile(path, "wb", header=header) as out:
+            for i, (tid, pos) in enumerate([(0, 10), (0, 400), (1, 50)]):
+                read = pysam.AlignedSegment(out.header)
+                read.query_name = f"r{i}"
+                read.query_sequence = "ACGT"
+                read.query_qualities = pysam.qualitystring_to_array("IIII")
+                read.reference_id, read.reference_start = tid, pos
+                read.cigarstring = "4M"
+                out.write(read)
+        pysam.index(path)
+
+        bam = sparc_py.BamParser(path)
+        assert bam.reference_names() == ["chr1", "chr2"]
+        assert bam.reference_lengths() == [1000, 500]
+        assert "@SQ\tSN:chr2" in bam.header
+        assert [r.name for r in bam.fetch("chr1", 0, 100)] == ["r0"]
+        assert [r.name for r in bam.fetch("chr1", 0, 1000)] == ["r0", "r1"]
+        with pytest.raises(IOError):
+            bam.fetch("chrX", 0, 10)
+
+    def test_fastq_read_chunk(self, tmp_dir):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        fastq = tmp_dir / "reads.fastq"
+        fastq.write_text(
+            "@r1\nACGTACGT\n+\nIIIIIIII\n"
+            "@r2\nACG\n+\nIII\n"
+            "@r3\nTTTT\n+\n####\n"
+        )
+
+        parser = sparc_py.FastqParser(str(fastq))
+        first = parser.read_chunk(2)
+        rest = parser.read_chunk(2)
+        done = parser.read_chunk(2)
+
+        assert first["id"].tolist() == [b"r1", b"r2"]
+        assert first["seq"].tolist() == [b"ACGTACGT", b"ACG"]
+        assert first["length"].tolist() == [8, 3]
+        assert rest["qual"].tolist() == [b"####"]
+        assert len(done["seq"]) == 0
+
+    def test_paired_fastq(self, tmp_dir):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        r1 = tmp_dir / "R1.fastq"
+        r2 = tmp_dir / "R2.fastq"
+        r1.write_text("@a/1\nAAAA\n+\nIIII\n@b/1\nCCCC\n+\nIIII\n")
+        r2.write_text("@a/2\nTTTTTT\n+\nIIIIII\n@b/2\nGGGGGG\n+\nIIIIII\n")
+
+        pairs = list(sparc_py.PairedFastqParser(str(r1), str(r2)))
+        assert [(a.seq_str(), b.seq_str()) for a, b in pairs] == [
+            ("AAAA", "TTTTTT"),
+            ("CCCC", "GGGGGG"),
+        ]
+
+        batch = sparc_py.PairedFastqParser(str(r1), str(r2)).read_chunk(10)
+        assert batch["r1_seq"].tolist() == [b"AAAA", b"CCCC"]
+        assert batch["r2_seq"].tolist() == [b"TTTTTT", b"GGGGGG"]
+
+    def test_paired_fastq_out_of_sync(self, tmp_dir):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        r1 = tmp_dir / "R1.fastq"
+        r2 = tmp_dir / "R2.fastq"
+        r1.write_text("@a\nAAAA\n+\nIIII\n")
+        r2.write_text("@z\nTTTT\n+\nIIII\n")
+
+        with pytest.raises(IOError):
+            list(sparc_py.PairedFastqParser(str(r1), str(r2)))
+        assert len(list(sparc_py.PairedFastqParser(str(r1), str(r2), check_names=False))) == 1
+
+
+class TestWhitelistBindings:
+    """Tests for Whitelist set operations."""
+
+    def test_set_operations(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+        a = sparc_py.Whitelist.from_list(["AAAA", "CCCC"])
+        b = sparc_py.Whitelist.from_list(["CCCC", "GGGG"])
+
+        assert "AAAA" in a
+        assert "GGGG" not in a
+        assert list(a.union(b)) == ["AAAA", "CCCC", "GGGG"]
+        assert list(a.intersection(b)) == ["CCCC"]
+        assert list(a - b) == ["AAAA"]
+        with pytest.raises(ValueError):
+            a | sparc_py.Whitelist.from_list(["AAA"])
+
+    def test_from_numpy(self):
+        sparc_py = pytest.importorskip("sparc._sparc_py")
+
+        whitelist = sparc_py.Whitelist.from_numpy(np.array([b"AAAA", b"CCCC", b"", b"AAAA"]))
+
+        assert len(whitelist) == 2
+        assert whitelist.barcode_len() == 4
+        with pytest.raises(TypeError):
+            sparc_py.Whitelist.from_numpy(np.array(["AAAA"]))
+
+
 class TestStreaming:
     """Tests for sparc.streaming module."""
 